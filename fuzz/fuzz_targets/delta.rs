@@ -0,0 +1,8 @@
+#![no_main]
+
+use codecrafters_git::git::fuzz;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = fuzz::delta(data);
+});
@@ -0,0 +1,365 @@
+// am: apply a series of patch mails, committing each one
+//
+// am [-k] [<mbox>...]
+// am --continue   commit the patch that failed, once applied by hand
+// am --skip       drop the patch that failed and carry on
+// am --abort      return the branch and working tree to where they were
+//
+// The mails, as written by `format-patch`, are split out of the mboxes (or
+// standard input) and applied in order to the working tree and the index.
+// Each becomes a commit on HEAD with the mail's author, date, subject and
+// message; -k keeps the subject's "[PATCH]" style tags. A patch that doesn't
+// apply stops the series with nothing changed: fix the files up and run
+// --continue, which stages the files the patch touches as they are in the
+// working tree. A patch naming a path outside the work tree or inside .git
+// is refused as apply refuses it, --continue included.
+//
+// Progress lives in .git/rebase-apply/:
+//   applying    marks the directory as am's rather than rebase's
+//   0001, ...   the mails still to apply
+//   next, last  number of the current mail and of the last one
+//   orig-head   where HEAD was before the first patch
+//   keep        "t" with -k
+//   patch       the diff of the mail that stopped
+
+use crate::commands::apply::{self, Options};
+use crate::git::index::{Entry, Index};
+use crate::git::mailbox::{self, MailInfo};
+use crate::git::refs::{self, Head};
+use crate::git::{commit, date, ident, object, patch, tree, worktree};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+const STATE_DIR: &str = ".git/rebase-apply";
+
+const USAGE: &str = "Usage: am [-k] [<mbox>...] | --continue | --skip | --abort";
+
+struct State {
+    next: usize,
+    last: usize,
+    /// Empty when the series started on an unborn branch
+    orig_head: String,
+    keep_subject: bool,
+}
+
+impl State {
+    fn load() -> io::Result<State> {
+        let dir = Path::new(STATE_DIR);
+        if !dir.join("applying").exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Resolve operation not in progress, we are not resuming.",
+            ));
+        }
+        let read = |name: &str| -> io::Result<String> {
+            Ok(fs::read_to_string(dir.join(name))?.trim().to_string())
+        };
+        let number = |name: &str| -> io::Result<usize> {
+            read(name)?.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("corrupt {}/{}", STATE_DIR, name),
+                )
+            })
+        };
+        Ok(State {
+            next: number("next")?,
+            last: number("last")?,
+            orig_head: read("orig-head")?,
+            keep_subject: read("keep")? == "t",
+        })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let dir = Path::new(STATE_DIR);
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("applying"), "")?;
+        fs::write(dir.join("next"), format!("{}\n", self.next))?;
+        fs::write(dir.join("last"), format!("{}\n", self.last))?;
+        fs::write(dir.join("orig-head"), format!("{}\n", self.orig_head))?;
+        let keep = if self.keep_subject { "t" } else { "f" };
+        fs::write(dir.join("keep"), format!("{}\n", keep))
+    }
+
+    fn mail_path(&self) -> PathBuf {
+        Path::new(STATE_DIR).join(format!("{:04}", self.next))
+    }
+
+    /// The mail the series is at
+    fn current(&self) -> io::Result<MailInfo> {
+        Ok(mailbox::parse(
+            &fs::read(self.mail_path())?,
+            self.keep_subject,
+        ))
+    }
+
+    /// Move past the current mail
+    fn advance(&mut self) -> io::Result<()> {
+        fs::remove_file(self.mail_path())?;
+        self.next += 1;
+        self.save()
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    match args {
+        [flag] if flag == "--continue" => continue_am(),
+        [flag] if flag == "--skip" => skip(),
+        [flag] if flag == "--abort" => abort(),
+        _ => {
+            let mut keep_subject = false;
+            let mut inputs = Vec::new();
+            for arg in args {
+                match arg.as_str() {
+                    "-k" | "--keep" => keep_subject = true,
+                    arg if arg.starts_with('-') && arg != "-" => {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+                    }
+                    arg => inputs.push(arg),
+                }
+            }
+            start(&inputs, keep_subject)
+        }
+    }
+}
+
+fn start(inputs: &[&str], keep_subject: bool) -> io::Result<()> {
+    let dir = Path::new(STATE_DIR);
+    if dir.join("applying").exists() {
+        return Err(io::Error::other(
+            "previous rebase directory .git/rebase-apply still exists but mbox given.\nUse 'am --continue', '--skip' or '--abort'.",
+        ));
+    }
+    if dir.exists() {
+        return Err(io::Error::other(
+            "It looks like 'rebase' is in progress. Cannot am.",
+        ));
+    }
+
+    let mut messages = Vec::new();
+    let inputs = if inputs.is_empty() {
+        &["-"][..]
+    } else {
+        inputs
+    };
+    for input in inputs {
+        let mbox = if *input == "-" {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)?;
+            data
+        } else {
+            fs::read(input)?
+        };
+        messages.extend(mailbox::split(&mbox));
+    }
+    if messages.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Patch format detection failed.",
+        ));
+    }
+
+    let orig_head = refs::head_commit()?.unwrap_or_default();
+    let state = State {
+        next: 1,
+        last: messages.len(),
+        orig_head: orig_head.clone(),
+        keep_subject,
+    };
+    state.save()?;
+    for (i, message) in messages.iter().enumerate() {
+        fs::write(dir.join(format!("{:04}", i + 1)), message)?;
+    }
+    if !orig_head.is_empty() {
+        refs::write_ref("ORIG_HEAD", &orig_head)?;
+    }
+    apply_series(state)
+}
+
+fn continue_am() -> io::Result<()> {
+    let mut state = State::load()?;
+    if state.next <= state.last {
+        let info = state.current()?;
+        // Whatever the patch would have touched, as fixed up by hand
+        let mut index = Index::load()?;
+        for path in touched_paths(&info)? {
+            match worktree_file(&path)? {
+                Some(file) => index.add(Entry::checked_out(&path, &file)?),
+                None => {
+                    index.remove(&path);
+                }
+            }
+        }
+        index.write()?;
+
+        let tree_sha = tree::write_snapshot(&index.snapshot())?;
+        let head = refs::head_commit()?;
+        let unchanged = match &head {
+            Some(head) => commit::read(head)?.tree == tree_sha,
+            None => index.entries().is_empty(),
+        };
+        if unchanged {
+            return Err(io::Error::other(format!(
+                "Applying: {}\nNo changes - did you forget to apply the patch by hand?\nIf there is nothing left to apply, run 'am --skip' to drop it.",
+                info.subject
+            )));
+        }
+        println!("Applying: {}", info.subject);
+        commit_patch(&info, &tree_sha, head)?;
+        state.advance()?;
+    }
+    apply_series(state)
+}
+
+fn skip() -> io::Result<()> {
+    let mut state = State::load()?;
+    if state.next <= state.last {
+        restore_touched(&state.current()?)?;
+        state.advance()?;
+    }
+    apply_series(state)
+}
+
+fn abort() -> io::Result<()> {
+    let state = State::load()?;
+    if state.next <= state.last {
+        restore_touched(&state.current()?)?;
+    }
+
+    let head = refs::head_commit()?;
+    let orig_head = Some(state.orig_head.as_str()).filter(|sha| !sha.is_empty());
+    let current = worktree::snapshot_of_commit(head.as_deref())?;
+    let original = worktree::snapshot_of_commit(orig_head)?;
+    worktree::update(&current, &original, true)?;
+
+    match (refs::read_head()?, orig_head) {
+        (_, Some(sha)) => refs::update_head(sha, "am --abort")?,
+        (Head::Branch(name), None) => refs::delete_ref(&name)?,
+        (Head::Detached(_), None) => {}
+    }
+    fs::remove_dir_all(STATE_DIR)
+}
+
+/// Apply and commit the remaining mails one by one, stopping at the first
+/// patch that doesn't apply
+fn apply_series(mut state: State) -> io::Result<()> {
+    while state.next <= state.last {
+        let info = state.current()?;
+        println!("Applying: {}", info.subject);
+
+        let options = Options {
+            index: true,
+            ..Options::default()
+        };
+        if let Err(e) = apply::apply(&info.patch, &options) {
+            fs::write(Path::new(STATE_DIR).join("patch"), &info.patch)?;
+            return Err(io::Error::new(
+                e.kind(),
+                format!(
+                    "{}\nPatch failed at {:04} {}\nThe copy of the patch that failed is found in: {}/patch\nWhen you have resolved this problem, run 'am --continue'.\nIf you prefer to skip this patch, run 'am --skip' instead.\nTo restore the original branch and stop patching, run 'am --abort'.",
+                    e, state.next, info.subject, STATE_DIR
+                ),
+            ));
+        }
+
+        let tree_sha = tree::write_snapshot(&Index::load()?.snapshot())?;
+        commit_patch(&info, &tree_sha, refs::head_commit()?)?;
+        state.advance()?;
+    }
+    fs::remove_dir_all(STATE_DIR)
+}
+
+/// Commit `tree_sha` on top of `head` as the mail's author, and move HEAD
+/// (or the branch it is on) to the new commit
+fn commit_patch(info: &MailInfo, tree_sha: &str, head: Option<String>) -> io::Result<()> {
+    let mut message = format!("{}\n", info.subject);
+    if !info.message.is_empty() {
+        message.push('\n');
+        message.push_str(&info.message);
+    }
+    let parents: Vec<String> = head.into_iter().collect();
+    let sha = commit::create_with_author(tree_sha, &parents, &author(info), &message)?;
+
+    refs::update_head(&sha, &format!("am: {}", info.subject))
+}
+
+/// Author line from the mail's From: and Date:, dated now if the date
+/// doesn't parse
+fn author(info: &MailInfo) -> String {
+    let name = if info.author.is_empty() {
+        &info.email
+    } else {
+        &info.author
+    };
+    let date = date::parse(&info.date).unwrap_or_else(ident::format_current_timestamp);
+    format!("{} <{}> {}", name, info.email, date)
+}
+
+/// Every path the mail's patch reads or writes
+fn touched_paths(info: &MailInfo) -> io::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for file_patch in patch::parse(&info.patch, 1)? {
+        for path in [file_patch.old_path, file_patch.new_path]
+            .into_iter()
+            .flatten()
+        {
+            worktree::validate_path(&path)?;
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Put the paths a mail's patch touches back as HEAD has them, in the
+/// working tree and the index
+fn restore_touched(info: &MailInfo) -> io::Result<()> {
+    let head = worktree::snapshot_of_commit(refs::head_commit()?.as_deref())?;
+    let mut index = Index::load()?;
+    for path in touched_paths(info)? {
+        match head.get(&path) {
+            Some(file) => {
+                worktree::checkout_entry(&path, file)?;
+                index.add(Entry::checked_out(&path, file)?);
+            }
+            None => {
+                worktree::remove_file(&path)?;
+                index.remove(&path);
+            }
+        }
+    }
+    index.write()
+}
+
+/// The file on disk at `path` as a tree entry, its blob written
+fn worktree_file(path: &str) -> io::Result<Option<tree::FileEntry>> {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_file() || meta.file_type().is_symlink() => meta,
+        Ok(_) => return Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let (mode, content) = if meta.file_type().is_symlink() {
+        let target = fs::read_link(path)?.to_string_lossy().into_owned();
+        (tree::MODE_SYMLINK, target.into_bytes())
+    } else {
+        (file_mode(&meta), fs::read(path)?)
+    };
+    Ok(Some(tree::FileEntry {
+        mode: mode.to_string(),
+        sha: object::write("blob", &content)?,
+    }))
+}
+
+fn file_mode(meta: &fs::Metadata) -> &'static str {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.permissions().mode() & 0o111 != 0 {
+            return tree::MODE_EXECUTABLE;
+        }
+    }
+    tree::MODE_FILE
+}
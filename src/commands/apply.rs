@@ -0,0 +1,367 @@
+// apply: apply patches to the working tree or the index
+//
+// apply [--check] [--cached | --index] [-p<n>] [-C<n>] [--unidiff-zero] [<patch>...]
+//
+// Reads unified diffs, as written by `diff` or `diff -u`, from the patch
+// files (standard input if none) and applies each file's hunks. A hunk
+// whose lines have moved is looked for near the line its header names, and
+// with -C<n> it may also apply with only <n> lines of its surrounding
+// context matching; --unidiff-zero accepts the context-free hunks of
+// `diff -U0`, which are otherwise refused. -p<n> strips <n> leading path
+// components (1 by default). Nothing is written unless every patch
+// applies: --cached patches the index and leaves the working tree alone,
+// --index patches both (and refuses files whose working tree copy differs
+// from the index), --check only verifies that the patches apply.
+//
+// The paths a patch names are checked as a checkout checks a tree's: one
+// that would leave the work tree or reach into the repository refuses the
+// whole patch, as does one beyond a symlinked directory in the working
+// tree.
+
+use crate::git::index::{Entry, Index};
+use crate::git::patch::{self, FilePatch};
+use crate::git::tree::{self, FileEntry};
+use crate::git::{object, worktree};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+const USAGE: &str =
+    "Usage: apply [--check] [--cached | --index] [-p<n>] [-C<n>] [--unidiff-zero] [<patch>...]";
+
+/// How `apply` treats a patch
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Patch the index instead of the working tree
+    pub cached: bool,
+    /// Patch the working tree and stage the result
+    pub index: bool,
+    /// Only verify that the patch applies
+    pub check: bool,
+    /// Leading path components stripped from the patch's file names
+    pub strip: usize,
+    /// Context lines that must still match around a fuzzed hunk; `None`
+    /// requires all of them
+    pub min_context: Option<usize>,
+    /// Accept hunks without context away from the ends of the file
+    pub unidiff_zero: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            cached: false,
+            index: false,
+            check: false,
+            strip: 1,
+            min_context: None,
+            unidiff_zero: false,
+        }
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = Options::default();
+    let mut files = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--check" => options.check = true,
+            "--cached" => options.cached = true,
+            "--index" => options.index = true,
+            "--unidiff-zero" => options.unidiff_zero = true,
+            arg if arg.starts_with("-p") => options.strip = parse_number(&arg[2..])?,
+            arg if arg.starts_with("-C") => options.min_context = Some(parse_number(&arg[2..])?),
+            "-" => files.push("-"),
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => files.push(arg),
+        }
+    }
+
+    let mut data = Vec::new();
+    if files.is_empty() {
+        io::stdin().read_to_end(&mut data)?;
+    }
+    for file in files {
+        if file == "-" {
+            io::stdin().read_to_end(&mut data)?;
+        } else {
+            data.extend(fs::read(file)?);
+        }
+    }
+    apply(&data, &options)
+}
+
+fn parse_number(value: &str) -> io::Result<usize> {
+    value
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+}
+
+/// A file as the patches see it
+#[derive(Debug, Clone)]
+struct FileState {
+    mode: String,
+    content: Vec<u8>,
+}
+
+/// Apply every file patch in `data`, or none of them if any fails
+pub fn apply(data: &[u8], options: &Options) -> io::Result<()> {
+    let patches = patch::parse(data, options.strip)?;
+    if patches.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No valid patches in input",
+        ));
+    }
+    for file_patch in &patches {
+        for path in file_patch.old_path.iter().chain(&file_patch.new_path) {
+            worktree::validate_path(path)?;
+            if !options.cached {
+                check_leading_dirs(path)?;
+            }
+        }
+    }
+    let index = if options.cached || options.index {
+        Some(Index::load()?)
+    } else {
+        None
+    };
+
+    // Patched files by path, `None` once deleted; a later patch in the
+    // input sees the result of an earlier one
+    let mut result: BTreeMap<String, Option<FileState>> = BTreeMap::new();
+    for file_patch in &patches {
+        apply_file(file_patch, index.as_ref(), options, &mut result)?;
+    }
+    if options.check {
+        return Ok(());
+    }
+
+    match index {
+        Some(mut index) if options.cached => {
+            for (path, state) in &result {
+                match state {
+                    Some(state) => {
+                        let sha = object::write("blob", &state.content)?;
+                        let file = FileEntry {
+                            mode: state.mode.clone(),
+                            sha,
+                        };
+                        index.add(Entry::new(path, &file));
+                    }
+                    None => {
+                        index.remove(path);
+                    }
+                }
+            }
+            index.write()
+        }
+        index => {
+            // Removals first, so a file may give way to a directory
+            for (path, _) in result.iter().filter(|(_, state)| state.is_none()) {
+                worktree::remove_file(path)?;
+            }
+            for (path, state) in &result {
+                if let Some(state) = state {
+                    write_file(path, state)?;
+                }
+            }
+            let Some(mut index) = index else {
+                return Ok(());
+            };
+            for (path, state) in &result {
+                match state {
+                    Some(state) => {
+                        let file = FileEntry {
+                            mode: state.mode.clone(),
+                            sha: object::write("blob", &state.content)?,
+                        };
+                        index.add(Entry::checked_out(path, &file)?);
+                    }
+                    None => {
+                        index.remove(path);
+                    }
+                }
+            }
+            index.write()
+        }
+    }
+}
+
+fn apply_file(
+    file_patch: &FilePatch,
+    index: Option<&Index>,
+    options: &Options,
+    result: &mut BTreeMap<String, Option<FileState>>,
+) -> io::Result<()> {
+    let location = if options.cached {
+        "index"
+    } else {
+        "working tree"
+    };
+    let name = file_patch
+        .new_path
+        .as_deref()
+        .or(file_patch.old_path.as_deref())
+        .unwrap_or_default();
+    let current = |path: &str, result: &BTreeMap<String, Option<FileState>>| match result.get(path)
+    {
+        Some(state) => Ok(state.clone()),
+        None => read_file(path, index, options.cached),
+    };
+
+    if file_patch.binary {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("cannot apply binary patch to '{}'", name),
+        ));
+    }
+
+    let old = match &file_patch.old_path {
+        Some(path) => Some(current(path, result)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{}: does not exist in {}", path, location),
+            )
+        })?),
+        None => None,
+    };
+    if let Some(path) = &file_patch.new_path {
+        if file_patch.old_path.as_ref() != Some(path) && current(path, result)?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{}: already exists in {}", path, location),
+            ));
+        }
+    }
+
+    let old_content = old.as_ref().map_or(&[][..], |state| &state.content[..]);
+    let content = patch::apply(
+        old_content,
+        &file_patch.hunks,
+        options.min_context,
+        options.unidiff_zero,
+    )
+    .map_err(|line| {
+        io::Error::other(format!(
+            "patch failed: {}:{}\nerror: {}: patch does not apply",
+            name, line, name
+        ))
+    })?;
+
+    if let Some(path) = &file_patch.old_path {
+        result.insert(path.clone(), None);
+    }
+    match &file_patch.new_path {
+        Some(path) => {
+            let mode = file_patch
+                .new_mode
+                .clone()
+                .or_else(|| old.map(|state| state.mode))
+                .unwrap_or_else(|| tree::MODE_FILE.to_string());
+            result.insert(path.clone(), Some(FileState { mode, content }));
+        }
+        None if !content.is_empty() => {
+            return Err(io::Error::other(format!(
+                "removal patch leaves file contents\nerror: {}: patch does not apply",
+                name
+            )));
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// The staged (with `cached`) or checked-out file at `path`, if there is
+/// one. A checked-out file must match `index` when given.
+fn read_file(path: &str, index: Option<&Index>, cached: bool) -> io::Result<Option<FileState>> {
+    if let (Some(index), true) = (index, cached) {
+        return match index.get(path) {
+            Some(entry) if entry.stage == 0 => Ok(Some(FileState {
+                mode: entry.file().mode,
+                content: object::read_expecting(&entry.sha, "blob")?,
+            })),
+            Some(_) => Err(io::Error::other(format!("{}: needs merge", path))),
+            None => Ok(None),
+        };
+    }
+
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !meta.is_file() && !meta.file_type().is_symlink() {
+        return Ok(None);
+    }
+    if let Some(index) = index {
+        let matches = match index.get(path) {
+            Some(entry) => worktree::matches_worktree(path, &entry.file())?,
+            None => false,
+        };
+        if !matches {
+            return Err(io::Error::other(format!("{}: does not match index", path)));
+        }
+    }
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        return Ok(Some(FileState {
+            mode: tree::MODE_SYMLINK.to_string(),
+            content: target.to_string_lossy().into_owned().into_bytes(),
+        }));
+    }
+    Ok(Some(FileState {
+        mode: file_mode(&meta).to_string(),
+        content: fs::read(path)?,
+    }))
+}
+
+fn file_mode(meta: &fs::Metadata) -> &'static str {
+    #[cfg(unix)]
+    {
+        if meta.permissions().mode() & 0o111 != 0 {
+            return tree::MODE_EXECUTABLE;
+        }
+    }
+    tree::MODE_FILE
+}
+
+/// Fail if a directory above `path` in the working tree is a symlink,
+/// which reading, removing or writing the file would go through
+fn check_leading_dirs(path: &str) -> io::Result<()> {
+    let mut dir = PathBuf::new();
+    let names: Vec<&str> = path.split('/').collect();
+    for name in &names[..names.len() - 1] {
+        dir.push(name);
+        match fs::symlink_metadata(&dir) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("affected file '{}' is beyond a symbolic link", path),
+                ))
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+fn write_file(path: &str, state: &FileState) -> io::Result<()> {
+    worktree::create_leading_dirs(path)?;
+    let path = &worktree::long_path(path.as_ref())?;
+    match fs::symlink_metadata(path) {
+        Ok(_) => fs::remove_file(path)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    worktree::write_content(path, &state.mode, &state.content)
+}
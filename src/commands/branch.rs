@@ -0,0 +1,132 @@
+// branch: list branches
+//
+// branch [--list] [-a | -r] [--contains [<commit>]] [<pattern>...]
+//
+// Lists the local branches by name, marking the one checked out with "* "
+// and listing a detached HEAD first; -r lists the remote-tracking branches
+// instead and -a both, those prefixed "remotes/". A branch that is a
+// symbolic ref shows what it points to ("origin/HEAD -> origin/main").
+// <pattern>s, shell globs matched against the names, limit the listing;
+// they need --list or --contains, as a bare name would be one to create,
+// which is left to checkout -b and switch -c. --contains lists only the
+// branches whose commits reach <commit> (HEAD when it comes last with none;
+// see git/reach.rs).
+
+use crate::git::reach::Reachability;
+use crate::git::refs::{self, Head};
+use crate::git::{revision, tag};
+use std::io::{self, BufWriter, Write};
+
+const USAGE: &str = "Usage: branch [--list] [-a | -r] [--contains <commit>] [<pattern>...]";
+
+/// Which branches are listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Local,
+    Remote,
+    All,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut kind = Kind::Local;
+    let mut list = false;
+    let mut contains: Option<String> = None;
+    let mut patterns = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-l" | "--list" => list = true,
+            "-a" | "--all" => kind = Kind::All,
+            "-r" | "--remotes" => kind = Kind::Remote,
+            "--contains" => {
+                // Like git, the commit may only be left out at the end
+                let name = args.next().map_or("HEAD", String::as_str);
+                contains = Some(resolve_commit(name)?);
+            }
+            arg if arg.starts_with("--contains=") => {
+                contains = Some(resolve_commit(&arg["--contains=".len()..])?)
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => patterns.push(arg),
+        }
+    }
+    if !patterns.is_empty() && !list && contains.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "creating branches is not supported; use checkout -b or switch -c",
+        ));
+    }
+
+    let mut reach = match contains {
+        Some(_) => Some(Reachability::new()?),
+        None => None,
+    };
+    let mut reaches = |sha: &str| -> io::Result<bool> {
+        match (contains.as_deref(), reach.as_mut()) {
+            (Some(target), Some(reach)) => reach.is_ancestor(target, sha),
+            _ => Ok(true),
+        }
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let head = refs::read_head()?;
+    if let Head::Detached(sha) = &head {
+        if kind != Kind::Remote && patterns.is_empty() && reaches(sha)? {
+            writeln!(out, "* (HEAD detached at {})", &sha[..7])?;
+        }
+    }
+
+    let mut prefixes = Vec::new();
+    if kind != Kind::Remote {
+        prefixes.push(("refs/heads/", ""));
+    }
+    if kind != Kind::Local {
+        prefixes.push((
+            "refs/remotes/",
+            if kind == Kind::All { "remotes/" } else { "" },
+        ));
+    }
+    for (prefix, shown_prefix) in prefixes {
+        for (refname, sha) in refs::list_refs(prefix.trim_end_matches('/'))? {
+            let name = &refname[prefix.len()..];
+            if !patterns.is_empty() && !patterns.iter().any(|p| refs::glob_match(p, name)) {
+                continue;
+            }
+            if !reaches(&sha)? {
+                continue;
+            }
+            let marker = match &head {
+                Head::Branch(current) if *current == refname => "* ",
+                _ => "  ",
+            };
+            match refs::read_symref(&refname)? {
+                Some(target) => {
+                    let target = target.strip_prefix(prefix).unwrap_or(&target);
+                    writeln!(out, "{}{}{} -> {}", marker, shown_prefix, name, target)?
+                }
+                None => writeln!(out, "{}{}{}", marker, shown_prefix, name)?,
+            }
+        }
+    }
+    out.flush()
+}
+
+fn resolve_commit(name: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("malformed object name {}", name),
+        )
+    })?;
+    let (sha, kind) = tag::peel(&sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a commit", name, kind),
+        ));
+    }
+    Ok(sha)
+}
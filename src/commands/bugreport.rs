@@ -0,0 +1,261 @@
+// bugreport: collect what helps diagnose a problem into a file
+//
+// bugreport [-o <path>] [-s <format>]
+//
+// Writes git-bugreport-<suffix>.txt into the directory <path> (by default
+// the current one), the suffix being the local time in the strftime
+// <format>, by default %Y-%m-%d-%H%M. An existing report is never
+// overwritten. The report starts with questions for the reporter, followed
+// by:
+//
+// - the version of this program, the features it was built with and the
+//   system it runs on
+// - the configuration, file by file, with anything that looks like a
+//   secret (passwords in URLs, tokens, extra HTTP headers) redacted
+// - inside a repository, its loose objects, packs, refs and enabled hooks,
+//   and the last entries of the HEAD reflog
+// - the end of the trace log, when GIT_TRACE names a file
+//
+// Nothing is sent anywhere; the file is there to read over and attach.
+
+use crate::git::config::Config;
+use crate::git::{hooks, object, pack, reflog, refs, repository, url};
+use chrono::format::{Item, StrftimeItems};
+use chrono::Local;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: bugreport [-o <path>] [-s <format>]";
+
+/// HEAD reflog entries shown, newest first
+const REFLOG_ENTRIES: usize = 10;
+
+/// Lines shown from the end of the trace log
+const TRACE_LINES: usize = 50;
+
+/// Parts of config keys whose values are kept out of the report
+const SECRET_KEYS: [&str; 5] = ["password", "token", "secret", "cookie", "extraheader"];
+
+const QUESTIONS: &str = "\
+Thank you for filling out a bug report!
+Please answer the following questions to help us understand your issue.
+
+What did you do before the bug happened? (Steps to reproduce your issue)
+
+What did you expect to happen? (Expected behavior)
+
+What happened instead? (Actual behavior)
+
+What's different between what you expected and what actually happened?
+
+Anything else you want to add:
+
+Please review the rest of the bug report below.
+You can delete any lines you don't wish to share.
+";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut dir = PathBuf::from(".");
+    let mut suffix = "%Y-%m-%d-%H%M".to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output-directory" => dir = PathBuf::from(args.next().ok_or_else(usage)?),
+            "-s" | "--suffix" => suffix = args.next().ok_or_else(usage)?.clone(),
+            arg if arg.starts_with("--output-directory=") => {
+                dir = PathBuf::from(&arg["--output-directory=".len()..])
+            }
+            arg if arg.starts_with("--suffix=") => suffix = arg["--suffix=".len()..].to_string(),
+            _ => return Err(usage()),
+        }
+    }
+
+    let mut report = String::from(QUESTIONS);
+    report.push('\n');
+    system_info(&mut report);
+    configuration(&mut report)?;
+    if repository::git_dir().is_dir() {
+        repository_info(&mut report)?;
+    }
+    trace_log(&mut report)?;
+
+    if StrftimeItems::new(&suffix).any(|item| item == Item::Error) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid suffix format '{}'", suffix),
+        ));
+    }
+    let name = format!("git-bugreport-{}.txt", Local::now().format(&suffix));
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(name);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("couldn't create a new file at '{}': {}", path.display(), e),
+            )
+        })?;
+    file.write_all(report.as_bytes())?;
+    eprintln!("Created new report at '{}'.", path.display());
+    Ok(())
+}
+
+fn system_info(report: &mut String) {
+    let mut features = Vec::new();
+    if cfg!(feature = "strict") {
+        features.push("strict");
+    }
+    report.push_str("[System Info]\n");
+    report.push_str(&format!(
+        "version: {} {}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    ));
+    report.push_str(&format!(
+        "features: {}\n",
+        if features.is_empty() {
+            "(none)".to_string()
+        } else {
+            features.join(" ")
+        }
+    ));
+    report.push_str(&format!(
+        "system: {} {} ({})\n",
+        env::consts::OS,
+        env::consts::ARCH,
+        env::consts::FAMILY
+    ));
+    report.push_str(&format!(
+        "$SHELL (typically, interactive shell): {}\n",
+        env::var("SHELL").unwrap_or_else(|_| "<unset>".to_string())
+    ));
+    report.push('\n');
+}
+
+/// Every config entry under the file it came from, secrets redacted
+fn configuration(report: &mut String) -> io::Result<()> {
+    let config = Config::load()?;
+    report.push_str("[Configuration]\n");
+    let mut origin = None;
+    for entry in config.entries() {
+        if origin != Some(&entry.origin) {
+            origin = Some(&entry.origin);
+            report.push_str(&format!("{}:\n", entry.origin.display()));
+        }
+        let key = entry.key();
+        match &entry.value {
+            None => report.push_str(&format!("  {}\n", key)),
+            Some(value) => report.push_str(&format!("  {}={}\n", key, redact(&key, value))),
+        }
+    }
+    if origin.is_none() {
+        report.push_str("(no configuration)\n");
+    }
+    report.push('\n');
+    Ok(())
+}
+
+fn redact(key: &str, value: &str) -> String {
+    let name = key.to_ascii_lowercase();
+    if SECRET_KEYS.iter().any(|secret| name.contains(secret)) {
+        return "(redacted)".to_string();
+    }
+    match url::parse(value) {
+        Ok(parsed) if parsed.password.is_some() => parsed.to_string(),
+        _ => value.to_string(),
+    }
+}
+
+fn repository_info(report: &mut String) -> io::Result<()> {
+    let loose = object::loose_statistics()?;
+    report.push_str("[Repository]\n");
+    report.push_str(&format!(
+        "loose objects: {} ({} KiB)\n",
+        loose.count,
+        loose.disk_size / 1024
+    ));
+    let packs = pack::packs()?;
+    let mut packed = 0;
+    let mut pack_size = 0;
+    for pack in &packs {
+        packed += pack.entries().len();
+        pack_size += fs::metadata(&pack.path).map(|meta| meta.len()).unwrap_or(0);
+    }
+    report.push_str(&format!(
+        "packs: {} holding {} objects ({} KiB)\n",
+        packs.len(),
+        packed,
+        pack_size / 1024
+    ));
+    for (kind, prefix) in [
+        ("branches", "refs/heads/"),
+        ("tags", "refs/tags/"),
+        ("remote-tracking branches", "refs/remotes/"),
+    ] {
+        report.push_str(&format!("{}: {}\n", kind, refs::list_refs(prefix)?.len()));
+    }
+    let head = match refs::read_head()? {
+        refs::Head::Branch(branch) => branch,
+        refs::Head::Detached(sha) => format!("detached at {}", sha),
+    };
+    report.push_str(&format!("HEAD: {}\n", head));
+    report.push('\n');
+
+    report.push_str("[Enabled Hooks]\n");
+    for hook in hooks::enabled()? {
+        report.push_str(&format!("{}\n", hook));
+    }
+    report.push('\n');
+
+    report.push_str("[Recent HEAD Reflog]\n");
+    let entries = if reflog::exists("HEAD") {
+        reflog::read("HEAD")?
+    } else {
+        Vec::new()
+    };
+    for entry in entries.iter().rev().take(REFLOG_ENTRIES) {
+        // The identity is left out: the time it ends with is what helps
+        let time = entry
+            .identity
+            .rsplit_once("> ")
+            .map_or("", |(_, time)| time);
+        let new = entry.new.get(..7).unwrap_or(&entry.new);
+        report.push_str(&format!("{} {} {}\n", new, time, entry.message));
+    }
+    report.push('\n');
+    Ok(())
+}
+
+/// The last lines of the file GIT_TRACE names; a GIT_TRACE of "1" or
+/// "true" traces to standard error, which leaves nothing to show
+fn trace_log(report: &mut String) -> io::Result<()> {
+    let Some(trace) = env::var_os("GIT_TRACE").map(PathBuf::from) else {
+        return Ok(());
+    };
+    if !trace.is_absolute() {
+        return Ok(());
+    }
+    report.push_str(&format!("[Trace Log] {}\n", trace.display()));
+    match fs::read(&trace) {
+        Ok(data) => {
+            let text = String::from_utf8_lossy(&data);
+            let lines: Vec<&str> = text.lines().collect();
+            for line in &lines[lines.len().saturating_sub(TRACE_LINES)..] {
+                report.push_str(line);
+                report.push('\n');
+            }
+        }
+        Err(e) => report.push_str(&format!("(unreadable: {})\n", e)),
+    }
+    report.push('\n');
+    Ok(())
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -1,23 +1,254 @@
-use crate::git::object;
-use std::io::{self, Write};
+// cat-file: show objects in the database
+//
+// cat-file (-t | -s | -p | -e) <object>
+// cat-file (--batch[=<format>] | --batch-check[=<format>])
+//          [--batch-all-objects [--unordered]]
+//
+// -t, -s and -p print the type, size or content of the object <object>
+// names (any revision expression, see git/revision.rs); -e prints nothing
+// and exits with status 1 unless the object exists. The batch forms read
+// object names from standard input, one per line, and print a line in
+// <format> for each, "%(objectname) %(objecttype) %(objectsize)" unless
+// given; --batch follows it with the object's content and a newline. A name
+// that doesn't resolve prints "<name> missing". The format may use
+// %(objectname), %(objecttype), %(objectsize), %(objectsize:disk) (bytes
+// used in storage), %(deltabase) (the object a packed delta is against, or
+// the null id) and %(rest) (whatever follows the name on the input line).
+//
+// --batch-all-objects reports every object, loose or packed, instead of
+// reading names: sorted by id, or with --unordered in the order they are
+// stored.
+//
+// In a partial clone an object that was left out is fetched from the
+// promisor remote (see git/promisor.rs) when it is asked for by name, as it
+// is wherever an object is read.
 
-// Runs the cat-file command with -p flag.
-pub fn run(object_id: &str, flag: &str) -> io::Result<()> {
-    let (content_type, size, content) = object::read_blob(object_id)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+use crate::git::object::{self, Location, StoredObject};
+use crate::git::revision;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+const USAGE: &str = "Usage: cat-file (-t | -s | -p | -e) <object>\n   or: cat-file (--batch[=<format>] | --batch-check[=<format>]) [--batch-all-objects [--unordered]]";
+
+const DEFAULT_FORMAT: &str = "%(objectname) %(objecttype) %(objectsize)";
+const NULL_ID: &str = "0000000000000000000000000000000000000000";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    if let [flag, object_id] = args {
+        if matches!(flag.as_str(), "-t" | "-s" | "-p" | "-e") {
+            return show_one(object_id, flag);
+        }
+    }
+
+    let mut batch = None;
+    let mut all = false;
+    let mut unordered = false;
+    for arg in args {
+        match arg.as_str() {
+            "--batch-all-objects" => all = true,
+            "--unordered" => unordered = true,
+            arg => {
+                let (option, format) = match arg.split_once('=') {
+                    Some((option, format)) => (option, format),
+                    None => (arg, DEFAULT_FORMAT),
+                };
+                let with_content = match option {
+                    "--batch" => true,
+                    "--batch-check" => false,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+                };
+                batch = Some(Batch {
+                    format: parse_format(format)?,
+                    with_content,
+                });
+            }
+        }
+    }
+    let batch = batch.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+    if unordered && !all {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+    }
+
+    let mut stdout = io::stdout().lock();
+    if all {
+        for stored in all_objects(unordered)? {
+            batch.show(&mut stdout, &stored, "")?;
+        }
+        return stdout.flush();
+    }
+
+    let uses_rest = batch.format.iter().any(|part| matches!(part, Part::Rest));
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        // The name is the whole line unless the format wants the rest of it
+        let (name, rest) = match line.split_once(char::is_whitespace) {
+            Some((name, rest)) if uses_rest => (name, rest.trim_start()),
+            _ => (line.as_str(), ""),
+        };
+        // A name that doesn't resolve to one object is answered as missing
+        // What the promisor of a partial clone can't hand over is missing too
+        let location = match revision::resolve(name) {
+            Ok(Some(sha)) => object::find(&sha)
+                .ok()
+                .flatten()
+                .map(|location| (sha, location)),
+            _ => None,
+        };
+        match location {
+            Some((id, location)) => {
+                batch.show(&mut stdout, &StoredObject { id, location }, rest)?
+            }
+            None => writeln!(stdout, "{} missing", name)?,
+        }
+        // Answer each name as it comes, for callers talking over a pipe
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn show_one(name: &str, flag: &str) -> io::Result<()> {
+    if flag == "-e" {
+        // A name that doesn't resolve names no object either
+        let Ok(Some(sha)) = revision::resolve(name) else {
+            process::exit(1);
+        };
+        if !matches!(object::find(&sha), Ok(Some(_))) {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Not a valid object name {}", name),
+        )
+    })?;
+
+    let (content_type, content) = object::read(&sha)?;
 
     let mut stdout = io::stdout();
 
     if flag == "-t" {
         stdout.write_all(content_type.as_bytes())?;
     } else if flag == "-s" {
-        stdout.write_all(size.to_string().as_bytes())?;
-    } else if flag == "-p" {
-        stdout.write_all(&content)?;
+        stdout.write_all(content.len().to_string().as_bytes())?;
     } else {
-        return Err(io::Error::new(io::ErrorKind::Other, "Invalid flag"));
+        stdout.write_all(&content)?;
     }
 
     stdout.flush()?;
     Ok(())
 }
+
+/// Every stored object once, sorted by id unless `unordered`
+fn all_objects(unordered: bool) -> io::Result<Vec<StoredObject>> {
+    let mut objects: Vec<StoredObject> = object::all_objects()?.collect();
+    if unordered {
+        let mut seen = HashSet::new();
+        objects.retain(|stored| seen.insert(stored.id.clone()));
+    } else {
+        // Stable, so a loose copy wins over a packed one as when reading
+        objects.sort_by(|a, b| a.id.cmp(&b.id));
+        objects.dedup_by(|a, b| a.id == b.id);
+    }
+    Ok(objects)
+}
+
+enum Part {
+    Literal(String),
+    Name,
+    Type,
+    Size,
+    DiskSize,
+    DeltaBase,
+    Rest,
+}
+
+fn parse_format(format: &str) -> io::Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut rest = format;
+    while let Some(start) = rest.find("%(") {
+        if start > 0 {
+            parts.push(Part::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find(')')
+            .map(|end| start + end)
+            .ok_or_else(|| unknown_atom(&rest[start..]))?;
+        parts.push(match &rest[start + 2..end] {
+            "objectname" => Part::Name,
+            "objecttype" => Part::Type,
+            "objectsize" => Part::Size,
+            "objectsize:disk" => Part::DiskSize,
+            "deltabase" => Part::DeltaBase,
+            "rest" => Part::Rest,
+            _ => return Err(unknown_atom(&rest[start..=end])),
+        });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+fn unknown_atom(atom: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unknown format element: {}", atom),
+    )
+}
+
+struct Batch {
+    format: Vec<Part>,
+    /// Print each object's content after its line (--batch)
+    with_content: bool,
+}
+
+impl Batch {
+    fn show(&self, out: &mut impl Write, stored: &StoredObject, rest: &str) -> io::Result<()> {
+        let (kind, content) = match &stored.location {
+            Location::Loose(_) => object::read(&stored.id)?,
+            Location::Packed { pack, offset } => pack.read_at(*offset)?,
+        };
+        let needs_entry = self
+            .format
+            .iter()
+            .any(|part| matches!(part, Part::DiskSize | Part::DeltaBase));
+        let entry = match &stored.location {
+            Location::Packed { pack, offset } if needs_entry => Some(pack.entry(*offset)?),
+            _ => None,
+        };
+
+        let mut line = String::new();
+        for part in &self.format {
+            match part {
+                Part::Literal(text) => line.push_str(text),
+                Part::Name => line.push_str(&stored.id),
+                Part::Type => line.push_str(&kind),
+                Part::Size => line.push_str(&content.len().to_string()),
+                Part::DiskSize => {
+                    let size = match (&stored.location, &entry) {
+                        (Location::Loose(path), _) => fs::metadata(path)?.len(),
+                        (_, Some(entry)) => entry.disk_size,
+                        _ => 0,
+                    };
+                    line.push_str(&size.to_string());
+                }
+                Part::DeltaBase => {
+                    let base = entry.as_ref().and_then(|entry| entry.delta_base.as_deref());
+                    line.push_str(base.unwrap_or(NULL_ID));
+                }
+                Part::Rest => line.push_str(rest),
+            }
+        }
+        writeln!(out, "{}", line)?;
+        if self.with_content {
+            out.write_all(&content)?;
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
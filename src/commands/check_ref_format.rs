@@ -0,0 +1,93 @@
+// check-ref-format: test whether a name is acceptable as a ref name
+//
+// check-ref-format [--normalize] [--[no-]allow-onelevel] [--refspec-pattern] <refname>
+// check-ref-format --branch <branch>
+//
+// Exits with status 0 if <refname> is well-formed and 1 otherwise, printing
+// nothing: the rules are those every ref this tool creates is held to (see
+// refs::is_valid_name), and unless --allow-onelevel is given the name also
+// needs at least two components, like refs/heads or heads/main.
+// --refspec-pattern accepts a single "*", and --normalize removes a leading
+// "/" and repeated slashes first and prints the result.
+//
+// --branch checks <branch> as the name of a local branch and prints it.
+
+use crate::git::refs;
+use std::io;
+use std::process;
+
+const USAGE: &str = "Usage: check-ref-format [--normalize] [--[no-]allow-onelevel] \
+                     [--refspec-pattern] <refname> | check-ref-format --branch <branch>";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut normalize = false;
+    let mut allow_onelevel = false;
+    let mut refspec_pattern = false;
+    let mut branch = false;
+    let mut name = None;
+    for arg in args {
+        match arg.as_str() {
+            "--normalize" | "--print" => normalize = true,
+            "--allow-onelevel" => allow_onelevel = true,
+            "--no-allow-onelevel" => allow_onelevel = false,
+            "--refspec-pattern" => refspec_pattern = true,
+            "--branch" => branch = true,
+            arg if arg.starts_with("--") => return Err(usage()),
+            arg if name.is_none() => name = Some(arg),
+            _ => return Err(usage()),
+        }
+    }
+    let name = name.ok_or_else(usage)?;
+
+    if branch {
+        if normalize || allow_onelevel || refspec_pattern {
+            return Err(usage());
+        }
+        if !refs::is_valid_branch_name(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a valid branch name", name),
+            ));
+        }
+        println!("{}", name);
+        return Ok(());
+    }
+
+    let name = if normalize {
+        collapse_slashes(name)
+    } else {
+        name.to_string()
+    };
+    let well_formed = if refspec_pattern {
+        refs::is_valid_pattern(&name)
+    } else {
+        refs::is_valid_name(&name)
+    };
+    if !well_formed || (!allow_onelevel && !name.contains('/')) {
+        process::exit(1);
+    }
+    if normalize {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// `name` without a leading slash or runs of slashes
+fn collapse_slashes(name: &str) -> String {
+    let mut collapsed = String::new();
+    for component in name.split('/').filter(|component| !component.is_empty()) {
+        if !collapsed.is_empty() {
+            collapsed.push('/');
+        }
+        collapsed.push_str(component);
+    }
+    // A trailing slash stays, so that the name is still rejected
+    if name.ends_with('/') && !collapsed.is_empty() {
+        collapsed.push('/');
+    }
+    collapsed
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -0,0 +1,333 @@
+// checkout: switch branches (or detach HEAD at a commit), updating the
+// working tree to match the target tree
+//
+// checkout [-f] <branch> | <commit>
+// checkout [-f] (-b | -B) <new-branch> [--track | --no-track] [<start-point>]
+// checkout [-f] --track <remote>/<branch>
+//
+// -b creates <new-branch> at <start-point> (HEAD by default) and switches
+// to it; -B does the same but resets the branch if it already exists. A
+// branch started from a remote-tracking branch tracks it as its upstream
+// (branch.<name>.remote and branch.<name>.merge), as does one started from
+// a local branch with --track; --no-track sets up nothing. --track without
+// -b names the new branch after the remote one, and so does checking out a
+// <branch> that doesn't exist but is a remote-tracking branch of exactly
+// one remote.
+//
+// In a partial clone the blobs of the target tree that aren't here are
+// fetched from the promisor remote (see git/promisor.rs) before anything
+// is written.
+
+use crate::git::config::{self, Config};
+use crate::git::reflog::ZERO_SHA;
+use crate::git::refs::{self, Head};
+use crate::git::worktree;
+use crate::git::{commit, revision, tag};
+use std::io;
+use std::path::Path;
+
+const USAGE: &str = "Usage: checkout [-f] <branch>|<commit> | \
+                     checkout [-f] (-b|-B) <new-branch> [--track|--no-track] [<start-point>]";
+
+/// Whether a new branch gets an upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Track {
+    /// Only when started from a remote-tracking branch
+    Auto,
+    Always,
+    Never,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut force = false;
+    let mut new_branch = None;
+    let mut reset = false;
+    let mut track = Track::Auto;
+    let mut target = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--force" => force = true,
+            "-b" | "-B" => {
+                reset = arg == "-B";
+                new_branch = Some(args.next().ok_or_else(usage)?.as_str());
+            }
+            "-t" | "--track" => track = Track::Always,
+            "--no-track" => track = Track::Never,
+            arg if arg.starts_with('-') => return Err(usage()),
+            _ if target.is_none() => target = Some(arg.as_str()),
+            _ => return Err(usage()),
+        }
+    }
+
+    if let Some(branch) = new_branch {
+        return create_branch(branch, target, reset, track, force);
+    }
+    let target = target.ok_or_else(usage)?;
+    if track == Track::Always {
+        let branch = implied_branch_name(target)?;
+        return create_branch(&branch, Some(target), false, track, force);
+    }
+
+    // A local branch name wins; anything else that resolves detaches HEAD
+    if refs::read_ref(&refs::branch_ref(target))?.is_some() {
+        return switch_branch(target, force);
+    }
+
+    let Some(sha) = revision::resolve(target)? else {
+        if let Some(remote_branch) = guess_remote_branch(target)? {
+            return create_branch(target, Some(&remote_branch), false, track, force);
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("pathspec '{}' did not match any branch or commit", target),
+        ));
+    };
+    detach(target, &sha, force)
+}
+
+/// Create `branch` at `start` (HEAD if `None`) and switch to it; with
+/// `reset` an existing branch is moved there instead
+fn create_branch(
+    branch: &str,
+    start: Option<&str>,
+    reset: bool,
+    track: Track,
+    force: bool,
+) -> io::Result<()> {
+    if !refs::is_valid_branch_name(branch) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a valid branch name", branch),
+        ));
+    }
+    let refname = refs::branch_ref(branch);
+    let existing = refs::read_ref(&refname)?;
+    if existing.is_some() && !reset {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("a branch named '{}' already exists", branch),
+        ));
+    }
+
+    let start_name = start.unwrap_or("HEAD");
+    let sha = match start {
+        Some(start) => Some(revision::resolve(start)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "'{}' is not a commit and a branch '{}' cannot be created from it",
+                    start, branch
+                ),
+            )
+        })?),
+        None => refs::head_commit()?,
+    };
+    let Some(sha) = sha else {
+        // Nothing to start from on an unborn branch; the new one is unborn too
+        refs::write_head_symbolic(&refname)?;
+        println!("Switched to a new branch '{}'", branch);
+        return Ok(());
+    };
+    let sha = revision::peel(&sha, "commit")?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "'{}' is not a commit and a branch '{}' cannot be created from it",
+                start_name, branch
+            ),
+        )
+    })?;
+    let upstream = upstream_of(start_name, track)?;
+
+    let current = refs::read_head()? == Head::Branch(refname.clone());
+    if !current {
+        update_worktree(&sha, force)?;
+    }
+    let message = match existing {
+        Some(_) => format!("branch: Reset to {}", start_name),
+        None => format!("branch: Created from {}", start_name),
+    };
+    refs::update_ref_checked(
+        &refname,
+        &sha,
+        Some(existing.as_deref().unwrap_or(ZERO_SHA)),
+        &message,
+    )?;
+    if let Some((remote, merge)) = &upstream {
+        let config_path = Path::new(".git/config");
+        config::set_value(config_path, &format!("branch.{}.remote", branch), remote)?;
+        config::set_value(config_path, &format!("branch.{}.merge", branch), merge)?;
+        let short = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+        if remote == "." {
+            println!("branch '{}' set up to track '{}'.", branch, short);
+        } else {
+            println!(
+                "branch '{}' set up to track '{}/{}'.",
+                branch, remote, short
+            );
+        }
+    }
+    if current {
+        println!("Reset branch '{}'", branch);
+        return Ok(());
+    }
+    refs::update_head_symbolic(&refname, &moving_message(branch)?)?;
+    if existing.is_some() {
+        println!("Switched to and reset branch '{}'", branch);
+    } else {
+        println!("Switched to a new branch '{}'", branch);
+    }
+    Ok(())
+}
+
+/// The `(remote, merge ref)` a branch started at `start` should track:
+/// a remote-tracking branch's remote and branch there, or with
+/// `Track::Always` a local branch in this repository (remote ".")
+fn upstream_of(start: &str, track: Track) -> io::Result<Option<(String, String)>> {
+    if track == Track::Never {
+        return Ok(None);
+    }
+    let full = revision::full_ref_name(start)?;
+    if let Some(rest) = full
+        .as_deref()
+        .and_then(|name| name.strip_prefix("refs/remotes/"))
+    {
+        // The longest configured remote name the ref is under
+        let config = Config::load()?;
+        let remote = remote_names(&config)
+            .into_iter()
+            .filter(|remote| rest.starts_with(&format!("{}/", remote)))
+            .max_by_key(String::len);
+        if let Some(remote) = remote {
+            let branch = &rest[remote.len() + 1..];
+            if branch != "HEAD" {
+                return Ok(Some((remote, refs::branch_ref(branch))));
+            }
+        }
+    }
+    match full {
+        Some(full) if track == Track::Always && full.starts_with("refs/heads/") => {
+            Ok(Some((".".to_string(), full)))
+        }
+        _ if track == Track::Always => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "cannot set up tracking information; starting point '{}' is not a branch",
+                start
+            ),
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// The names of the configured remotes
+fn remote_names(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for entry in config.entries() {
+        if let (true, Some(name)) = (entry.section == "remote", &entry.subsection) {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+    }
+    names
+}
+
+/// The branch `checkout --track <start>` creates: the ref name without
+/// refs/, remotes/ and the remote's name
+fn implied_branch_name(start: &str) -> io::Result<String> {
+    let full = revision::full_ref_name(start)?.unwrap_or_else(|| start.to_string());
+    let name = full.strip_prefix("refs/").unwrap_or(&full);
+    let name = name.strip_prefix("remotes/").unwrap_or(name);
+    match name.split_once('/') {
+        Some((_, branch)) if !branch.is_empty() => Ok(branch.to_string()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "missing branch name; try -b (cannot guess it from '{}')",
+                start
+            ),
+        )),
+    }
+}
+
+/// `<remote>/<branch>` if exactly one remote has `branch`
+fn guess_remote_branch(branch: &str) -> io::Result<Option<String>> {
+    if !refs::is_valid_branch_name(branch) {
+        return Ok(None);
+    }
+    let mut found = Vec::new();
+    for remote in remote_names(&Config::load()?) {
+        if refs::read_ref(&format!("refs/remotes/{}/{}", remote, branch))?.is_some() {
+            found.push(format!("{}/{}", remote, branch));
+        }
+    }
+    Ok(match found.as_slice() {
+        [only] => Some(only.clone()),
+        _ => None,
+    })
+}
+
+/// Switch HEAD to an existing local branch and update the working tree
+pub fn switch_branch(branch: &str, force: bool) -> io::Result<()> {
+    let refname = refs::branch_ref(branch);
+    let target_sha = refs::read_ref(&refname)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("invalid reference: {}", branch),
+        )
+    })?;
+
+    if refs::read_head()? == Head::Branch(refname.clone()) {
+        println!("Already on '{}'", branch);
+        return Ok(());
+    }
+
+    update_worktree(&target_sha, force)?;
+    refs::update_head_symbolic(&refname, &moving_message(branch)?)?;
+    println!("Switched to branch '{}'", branch);
+    Ok(())
+}
+
+/// Detach HEAD at `sha` (peeling tags), which the user called `name`, and
+/// update the working tree
+pub fn detach(name: &str, sha: &str, force: bool) -> io::Result<()> {
+    let (sha, kind) = tag::peel(sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("reference is not a commit: {} is a {}", sha, kind),
+        ));
+    }
+    let target = commit::read(&sha)?;
+
+    update_worktree(&sha, force)?;
+    refs::update_head_detached(&sha, &moving_message(name)?)?;
+    println!("HEAD is now at {} {}", &sha[..7], target.summary());
+    Ok(())
+}
+
+/// The reflog message for moving HEAD from where it is now to `target`
+fn moving_message(target: &str) -> io::Result<String> {
+    let from = match refs::read_head()? {
+        Head::Branch(name) => name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&name)
+            .to_string(),
+        Head::Detached(sha) => sha,
+    };
+    Ok(format!("checkout: moving from {} to {}", from, target))
+}
+
+fn update_worktree(target_sha: &str, force: bool) -> io::Result<()> {
+    let current_sha = refs::head_commit()?;
+    let current = worktree::snapshot_of_commit(current_sha.as_deref())?;
+    let target = worktree::snapshot_of_commit(Some(target_sha))?;
+    worktree::update(&current, &target, force)
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
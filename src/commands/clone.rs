@@ -1,23 +1,56 @@
 // Git clone command implementation
 // This module handles the complete Git clone process including:
-// - Reference discovery
-// - Pack file fetching and unpacking
-// - Side-band protocol handling
-// - Delta compression (REF_DELTA and OFS_DELTA)
+// - Reference discovery (smart HTTP, ssh, git://, or a repository on this
+//   machine), in protocol v2 when the remote speaks it, and from an HTTP
+//   server without the smart protocol by reading its files (dumb HTTP)
+// - Pack file fetching, each pack kept as it arrives (`pack::store`),
+//   submodules' included
+// - Side-band protocol handling: progress on band 2 is shown through the
+//   `Progress` callback, and asked not to be sent at all (no-progress)
+//   when there is none. Empty packets, which a remote sends as keepalives
+//   while it prepares the pack, carry nothing and are passed over.
 // - File checkout
-
-use flate2::read::ZlibDecoder;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use reqwest;
-use sha1_smol::Sha1;
+// - Submodule cloning (--recurse-submodules)
+// - Bare and mirror clones (--bare, --mirror), which leave out the working
+//   tree and keep the repository in the target directory itself
+// - Local clones: a repository named by its path has its objects
+//   hard-linked (copied with --no-hardlinks, or across filesystems) rather
+//   than packed and sent; a file:// URL, or --no-local, goes through the
+//   pack protocol as any other remote does
+// - Borrowing objects (--reference <repo>): the clone's object directory
+//   lists the reference repository's in objects/info/alternates, so what
+//   that already has is neither fetched nor copied, and the remote is told
+//   the reference's ref tips as haves. --dissociate copies the borrowed
+//   objects in once the clone is done and drops the alternates file.
+// - Shallow clones (--depth <n>): only the last n commits of each branch
+//   fetched, the commits at the edge listed in .git/shallow (see
+//   git/shallow.rs). --depth implies --single-branch unless
+//   --no-single-branch is given, and is ignored by a local clone, whose
+//   objects are copied as they are.
+// - Partial clones (--filter=<spec>, such as blob:none): the remote leaves
+//   out what the filter excludes and is recorded as the promisor remote
+//   (see git/promisor.rs), and the blobs checkout needs are fetched from it
+//   in one request before the files are written. A local clone ignores
+//   --filter as it does --depth.
+
+use crate::commands::fetch::{self, FetchOptions, TagMode};
+use crate::commands::remote;
+use crate::git::advertise::{self, HiddenRefs};
+use crate::git::config::{self, Config};
+use crate::git::http::{self, HttpClient};
+use crate::git::negotiate::{self, Negotiator};
+use crate::git::pktline::{self, Packet};
+use crate::git::progress::{CancellationToken, Progress, ProgressCallback};
+use crate::git::promisor;
+use crate::git::shallow::{self, Deepen};
+use crate::git::url::{self, RemoteUrl, Scheme};
+use crate::git::worktree::{self, Snapshot};
+use crate::git::{commit, interrupt, object, pack, refs, submodule, tag, tree};
+use crate::git::{daemon, dumb_http, ssh};
 use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Read, Write};
-use std::path::Path;
-
-#[cfg(unix)]
-use std::os::unix::fs::PermissionsExt;
+use std::path::{self, Path, PathBuf};
 
 // ============================================================================
 // PUBLIC API
@@ -25,1078 +58,1797 @@ use std::os::unix::fs::PermissionsExt;
 
 /// Main entry point for the clone command
 pub fn run(args: &[String]) -> io::Result<()> {
-    if args.len() != 2 {
+    let mut recurse_submodules = false;
+    let mut branch = None;
+    let mut single_branch = None;
+    let mut depth = None;
+    let mut filter = None;
+    let mut bare = false;
+    let mut mirror = false;
+    let mut no_local = false;
+    let mut no_hardlinks = false;
+    let mut references = Vec::new();
+    let mut dissociate = false;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--recurse-submodules" | "--recursive" => recurse_submodules = true,
+            "-b" | "--branch" => {
+                branch = Some(
+                    args.next()
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "--branch needs a branch name",
+                            )
+                        })?
+                        .as_str(),
+                )
+            }
+            arg if arg.starts_with("--branch=") => branch = Some(&arg["--branch=".len()..]),
+            "--single-branch" => single_branch = Some(true),
+            "--no-single-branch" => single_branch = Some(false),
+            "--depth" => depth = Some(args.next().map(String::as_str).unwrap_or_default()),
+            arg if arg.starts_with("--depth=") => depth = Some(&arg["--depth=".len()..]),
+            "--filter" => filter = Some(args.next().map(String::as_str).unwrap_or_default()),
+            arg if arg.starts_with("--filter=") => filter = Some(&arg["--filter=".len()..]),
+            "--bare" => bare = true,
+            "--mirror" => mirror = true,
+            "-l" | "--local" => no_local = false,
+            "--no-local" => no_local = true,
+            "--no-hardlinks" => no_hardlinks = true,
+            "--reference" => references.push(
+                args.next()
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "--reference needs a repository",
+                        )
+                    })?
+                    .as_str(),
+            ),
+            arg if arg.starts_with("--reference=") => references.push(&arg["--reference=".len()..]),
+            "--dissociate" => dissociate = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.len() != 2 {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
             "Invalid number of arguments",
         ));
     }
 
-    let repo_url = &args[0];
-    let target_dir = &args[1];
+    let repo_url = positional[0];
+    let target_dir = positional[1];
+    let depth = match depth {
+        Some(value) => Some(
+            value
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("depth {} is not a positive number", value),
+                    )
+                })?,
+        ),
+        None => None,
+    };
 
     println!("Cloning repository {} into {}...", repo_url, target_dir);
 
-    // Create target directory and change into it
-    fs::create_dir_all(target_dir)?;
-    let _current_dir = std::env::current_dir()?;
-    std::env::set_current_dir(target_dir)?;
-
-    // Initialize git repository structure
-    init_git_repo()?;
-
-    // Clone the repository
-    clone_repository(repo_url)?;
+    let mut builder = CloneOptions::builder()
+        .recurse_submodules(recurse_submodules)
+        .single_branch(single_branch.unwrap_or(depth.is_some()))
+        .bare(bare)
+        .mirror(mirror)
+        .no_local(no_local)
+        .no_hardlinks(no_hardlinks)
+        .dissociate(dissociate)
+        .progress(|line| println!("{}", line));
+    for reference in references {
+        builder = builder.reference(reference);
+    }
+    if let Some(branch) = branch {
+        builder = builder.branch(branch);
+    }
+    if let Some(depth) = depth {
+        builder = builder.depth(depth);
+    }
+    if let Some(filter) = filter {
+        builder = builder.filter(filter);
+    }
+    let options = builder.build();
+    clone(repo_url, Path::new(target_dir), options)?;
 
     Ok(())
 }
 
-// ============================================================================
-// CORE CLONE LOGIC
-// ============================================================================
+/// Username and password (or token) sent with every HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
 
-/// Initialize basic Git repository structure
-fn init_git_repo() -> io::Result<()> {
-    fs::create_dir_all(".git/objects")?;
-    fs::create_dir_all(".git/refs/heads")?;
-    fs::create_dir_all(".git/refs/remotes/origin")?;
+/// How to clone a repository; build one with [`CloneOptions::builder`]
+#[derive(Default)]
+pub struct CloneOptions {
+    /// Fetch only this many commits of history from each tip, making a
+    /// shallow clone
+    pub depth: Option<u32>,
+    /// Branch or tag to check out instead of the remote's HEAD
+    pub branch: Option<String>,
+    /// Fetch and track only the branch (or tag) checked out, with just the
+    /// tags in its history
+    pub single_branch: bool,
+    /// Make a bare repository: objects and refs go straight into the target
+    /// directory, each branch of the remote becomes a branch, and nothing is
+    /// checked out
+    pub bare: bool,
+    /// A bare clone of every ref of the remote as it is, with origin set up
+    /// to fetch them that way again
+    pub mirror: bool,
+    /// Fetch from a repository named by its path as from any other remote,
+    /// instead of taking its objects straight from its directory
+    pub no_local: bool,
+    /// Copy the objects of a local clone instead of hard-linking them
+    pub no_hardlinks: bool,
+    /// Local repositories to borrow objects from rather than fetch them
+    pub references: Vec<PathBuf>,
+    /// Copy the borrowed objects in after all, once the clone is done
+    pub dissociate: bool,
+    /// Partial clone filter spec such as `blob:none`
+    pub filter: Option<String>,
+    /// Also clone the submodules of the checked-out commit
+    pub recurse_submodules: bool,
+    /// Where progress messages go; a clone without one is silent
+    pub progress_callback: Option<ProgressCallback>,
+    /// Stops the clone when cancelled, removing what it had created
+    pub cancellation: Option<CancellationToken>,
+    /// Credentials for an HTTP remote, in place of any in the URL
+    pub auth: Option<Credentials>,
+}
 
-    // Write initial HEAD file
-    fs::write(".git/HEAD", "ref: refs/heads/master\n")?;
+impl CloneOptions {
+    pub fn builder() -> CloneBuilder {
+        CloneBuilder::default()
+    }
+}
 
-    Ok(())
+/// Chained setters for [`CloneOptions`]
+#[derive(Default)]
+pub struct CloneBuilder {
+    options: CloneOptions,
 }
 
-/// Main clone orchestration function
-fn clone_repository(repo_url: &str) -> io::Result<()> {
-    // Step 1: Discover references
-    let (_, (head_ref, head_sha)) = discover_refs(repo_url)?;
-    println!("Received head ref: {} and sha: {}", head_ref, head_sha);
+impl CloneBuilder {
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.options.depth = Some(depth);
+        self
+    }
 
-    // Update HEAD and create reference
-    println!("Updating HEAD to {}", head_ref);
-    fs::write(".git/HEAD", format!("ref: {}\n", head_ref))?;
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.options.branch = Some(branch.into());
+        self
+    }
 
-    let ref_path = Path::new(".git").join(&head_ref);
-    fs::create_dir_all(ref_path.parent().unwrap())?;
-    println!("Creating reference {}", head_ref);
-    fs::write(&ref_path, format!("{}\n", head_sha))?;
+    pub fn single_branch(mut self, single_branch: bool) -> Self {
+        self.options.single_branch = single_branch;
+        self
+    }
 
-    // Step 2: Fetch packfile
-    let pack_data = fetch_packfile(repo_url, &head_sha)?;
-    println!("Received packfile of size {}", pack_data.len());
+    pub fn bare(mut self, bare: bool) -> Self {
+        self.options.bare = bare;
+        self
+    }
 
-    // Step 3: Unpack packfile
-    println!("Unpacking packfile...");
-    unpack_packfile(&pack_data)?;
+    pub fn mirror(mut self, mirror: bool) -> Self {
+        self.options.mirror = mirror;
+        self
+    }
 
-    // Step 4: Checkout files
-    println!("Checking out files...");
-    checkout_files(&head_sha)?;
+    pub fn no_local(mut self, no_local: bool) -> Self {
+        self.options.no_local = no_local;
+        self
+    }
 
-    Ok(())
-}
+    pub fn no_hardlinks(mut self, no_hardlinks: bool) -> Self {
+        self.options.no_hardlinks = no_hardlinks;
+        self
+    }
 
-// ============================================================================
-// REFERENCE DISCOVERY
-// ============================================================================
+    pub fn reference(mut self, repository: impl Into<PathBuf>) -> Self {
+        self.options.references.push(repository.into());
+        self
+    }
 
-/// Discover references from the remote repository
-fn discover_refs(repo_url: &str) -> io::Result<(Vec<(String, String)>, (String, String))> {
-    let refs_url = if repo_url.ends_with(".git") {
-        format!("{}/info/refs?service=git-upload-pack", repo_url)
-    } else {
-        format!("{}.git/info/refs?service=git-upload-pack", repo_url)
-    };
+    pub fn dissociate(mut self, dissociate: bool) -> Self {
+        self.options.dissociate = dissociate;
+        self
+    }
 
-    println!("Discovering references from {}", refs_url);
-
-    let client = reqwest::blocking::Client::new();
-    let response = client
-        .get(&refs_url)
-        .header("User-Agent", "git/2.0")
-        .send()
-        .map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Error fetching refs: {:?}", e),
-            )
-        })?;
+    pub fn filter(mut self, filter: impl Into<String>) -> Self {
+        self.options.filter = Some(filter.into());
+        self
+    }
 
-    if !response.status().is_success() {
+    pub fn recurse_submodules(mut self, recurse: bool) -> Self {
+        self.options.recurse_submodules = recurse;
+        self
+    }
+
+    pub fn progress(mut self, callback: impl FnMut(&str) + 'static) -> Self {
+        self.options.progress_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.options.cancellation = Some(token);
+        self
+    }
+
+    pub fn auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.options.auth = Some(Credentials {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    pub fn build(self) -> CloneOptions {
+        self.options
+    }
+}
+
+/// Clone `repo_url` into `target_dir`, which is created if needed, and
+/// return the commit checked out (for a bare clone, the one HEAD is at).
+/// The process's working directory is
+/// changed for the duration of the clone and restored afterwards. A clone
+/// that fails or is cancelled leaves `target_dir` as it found it.
+pub fn clone(
+    repo_url: &str,
+    target_dir: &Path,
+    mut options: CloneOptions,
+) -> io::Result<Option<String>> {
+    if options.filter.as_deref() == Some("") {
         return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to fetch refs: {}", response.status()),
+            io::ErrorKind::InvalidInput,
+            "expected 'tree:<depth>', 'blob:none' or another filter spec",
         ));
     }
 
-    let body = response.text().map_err(|e| {
-        io::Error::new(io::ErrorKind::Other, format!("Error parsing refs: {:?}", e))
-    })?;
+    let mut progress = match options.progress_callback.take() {
+        Some(callback) => Progress::new(callback),
+        None => Progress::silent(),
+    };
+    if let Some(token) = options.cancellation.take() {
+        progress = progress.with_cancellation(token);
+    }
 
-    parse_refs_response(&body)
-}
+    // A path still has to lead to the repository from inside the clone
+    let remote = url::parse(repo_url)?;
+    let repo_url = match is_local_path(repo_url, &remote) {
+        true => path::absolute(&remote.path)?.to_string_lossy().into_owned(),
+        false => repo_url.to_string(),
+    };
+    let repo_url = repo_url.as_str();
 
-/// Parse the refs response from git-upload-pack
-fn parse_refs_response(body: &str) -> io::Result<(Vec<(String, String)>, (String, String))> {
-    let mut refs = Vec::new();
-    let mut head_ref = String::new();
-    let mut head_sha = String::new();
+    let existed = target_dir.exists();
+    if existed && fs::read_dir(target_dir)?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "destination path '{}' already exists and is not an empty directory.",
+                target_dir.display()
+            ),
+        ));
+    }
 
-    for line in body.lines() {
-        if line.is_empty() || line.len() < 4 {
-            continue;
-        }
+    // A second Ctrl-C exits without waiting for the clone to unwind, so the
+    // directory is also registered for removal
+    let _cancellable = interrupt::cancellable();
+    let _cleanup = if existed {
+        interrupt::empty_on_interrupt(target_dir)
+    } else {
+        interrupt::remove_on_interrupt(target_dir)
+    };
 
-        if line.starts_with("001e# service=git-upload-pack") || line == "0000" {
-            continue; // Skip service announcement and flush packets
-        }
+    // Create target directory and change into it
+    fs::create_dir_all(target_dir)?;
+    let original_dir = std::env::current_dir()?;
+    std::env::set_current_dir(target_dir)?;
 
-        let content = &line[4..];
-        let fields: Vec<&str> = content.split_whitespace().collect();
+    let result = (|| {
+        let bare = options.bare || options.mirror;
+        // Initialize git repository structure
+        init_git_repo(bare)?;
 
-        if fields.len() < 2 {
-            continue;
+        for reference in &options.references {
+            add_alternate(reference)?;
         }
 
-        if fields.len() == 2 {
-            let sha = fields[0];
-            let ref_name = fields[1];
-            if sha.len() != 40 {
-                println!("Skipping invalid SHA: {} for {}", sha, ref_name);
-                continue;
-            }
-            if ref_name == head_ref {
-                head_sha = sha.to_string();
-            }
-            refs.push((ref_name.to_string(), sha.to_string()));
-            continue;
+        // Clone the repository
+        let head_sha = clone_repository(repo_url, &options, &mut progress)?;
+        if options.dissociate {
+            dissociate(&mut progress)?;
         }
 
-        // Look for symref=HEAD:refs/heads/master
-        for field in fields {
-            if field.starts_with("symref=HEAD") {
-                head_ref = field.split(':').collect::<Vec<&str>>()[1].to_string();
-            }
+        if bare {
+            flatten_git_dir()?;
+        } else if let (true, Some(head_sha)) = (options.recurse_submodules, &head_sha) {
+            clone_submodules(repo_url, head_sha, &mut progress)?;
         }
-    }
+        Ok(head_sha)
+    })();
 
-    if head_sha.is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "No HEAD reference found",
-        ));
+    std::env::set_current_dir(original_dir)?;
+    if result.is_err() {
+        // Remove the directory if the clone created it, or empty it again
+        let _ = interrupt::discard(target_dir, existed);
     }
-
-    Ok((refs, (head_ref, head_sha)))
+    result
 }
 
 // ============================================================================
-// PACK FILE FETCHING
+// CORE CLONE LOGIC
 // ============================================================================
 
-/// Fetch packfile from the remote repository
-fn fetch_packfile(repo_url: &str, head_sha: &str) -> io::Result<Vec<u8>> {
-    let pack_url = if repo_url.ends_with(".git") {
-        format!("{}/git-upload-pack", repo_url)
+/// Initialize basic Git repository structure. A bare repository is made
+/// in .git all the same, and moved into place once the clone is done.
+fn init_git_repo(bare: bool) -> io::Result<()> {
+    fs::create_dir_all(".git/objects")?;
+    fs::create_dir_all(".git/refs/heads")?;
+    if bare {
+        config::set_value(Path::new(".git/config"), "core.bare", "true")?;
     } else {
-        format!("{}.git/git-upload-pack", repo_url)
-    };
+        fs::create_dir_all(".git/refs/remotes/origin")?;
+    }
 
-    println!("Requesting pack from: {}", pack_url);
+    // HEAD stays on this unborn branch if the remote is empty
+    refs::write_head_symbolic(&refs::branch_ref(&refs::default_branch()?))?;
+    worktree::configure_symlinks()
+}
 
-    let want_line = format!(
-        "want {} multi_ack_detailed side-band-64k thin-pack ofs-delta\n",
-        head_sha
-    );
-    let want_pkt = encode_pkt_line(&want_line);
-    let done_pkt = encode_pkt_line("done\n");
+/// Move everything in .git up into the directory holding it, which then is
+/// the bare repository
+fn flatten_git_dir() -> io::Result<()> {
+    for entry in fs::read_dir(".git")? {
+        let entry = entry?;
+        fs::rename(entry.path(), entry.file_name())?;
+    }
+    fs::remove_dir(".git")
+}
 
-    let mut request_body = Vec::new();
-    request_body.extend_from_slice(&want_pkt);
-    request_body.extend_from_slice(b"0000"); // flush packet
-    request_body.extend_from_slice(&done_pkt);
+/// Main clone orchestration function, returning the commit checked out;
+/// `None` when the remote has nothing to check out
+fn clone_repository(
+    repo_url: &str,
+    options: &CloneOptions,
+    progress: &mut Progress,
+) -> io::Result<Option<String>> {
+    let branch = options.branch.as_deref();
+    let auth = options.auth.as_ref();
+    let bare = options.bare || options.mirror;
+    // A mirror has every ref, whichever branch it was asked for
+    let single_branch = options.single_branch && !options.mirror;
+    let remote = url::parse(repo_url)?;
+    let mut deepen = options.depth.map(Deepen::Depth);
+    let mut filter = options.filter.as_deref();
+    if is_local_path(repo_url, &remote) && !options.no_local {
+        if deepen.take().is_some() {
+            progress.report("warning: --depth is ignored in local clones.");
+        }
+        if filter.take().is_some() {
+            progress.report("warning: --filter is ignored in local clones.");
+        }
+        copy_objects(Path::new(&remote.path), !options.no_hardlinks, progress)?;
+    }
 
-    let client = reqwest::blocking::Client::new();
-    let resp = client
-        .post(&pack_url)
-        .header("User-Agent", "git/2.0")
-        .header("Content-Type", "application/x-git-upload-pack-request")
-        .body(request_body)
-        .send()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    // Step 1: Fetch every branch and tag, or for a single branch just that
+    // one with the tags pointing into its history. A non-bare clone keeps
+    // the branches as remote-tracking branches; a bare clone fetches them
+    // as branches once, leaving origin with nothing to fetch; a mirror
+    // fetches every ref as itself and keeps doing so.
+    let single = if single_branch {
+        single_branch_ref(repo_url, branch, auth, progress)?
+    } else {
+        None
+    };
+    let config_path = Path::new(".git/config");
+    let mut refspecs = Vec::new();
+    if options.mirror {
+        config::set_value(config_path, "remote.origin.url", repo_url)?;
+        config::add_value(config_path, "remote.origin.fetch", "+refs/*:refs/*")?;
+        config::set_value(config_path, "remote.origin.mirror", "true")?;
+    } else if bare {
+        config::set_value(config_path, "remote.origin.url", repo_url)?;
+        refspecs.push(match &single {
+            Some(refname) => format!("+{}:{}", refname, refname),
+            None => "+refs/heads/*:refs/heads/*".to_string(),
+        });
+    } else {
+        match single.as_deref() {
+            None => remote::add("origin", repo_url, &[])?,
+            Some(refname) => match refname.strip_prefix("refs/heads/") {
+                Some(branch) => remote::add("origin", repo_url, &[branch])?,
+                // A tag is fetched as itself
+                None => {
+                    config::set_value(config_path, "remote.origin.url", repo_url)?;
+                    config::add_value(
+                        config_path,
+                        "remote.origin.fetch",
+                        &format!("+{}:{}", refname, refname),
+                    )?;
+                }
+            },
+        }
+    }
+    if let Some(filter) = filter {
+        promisor::record("origin", filter)?;
+    }
+    let message = format!("clone: from {}", url::parse(repo_url)?);
+    let fetch_options = FetchOptions {
+        tags: if options.mirror {
+            TagMode::None
+        } else if single_branch {
+            TagMode::Follow
+        } else {
+            TagMode::All
+        },
+        auth: auth.cloned(),
+        reflog_message: Some(message.clone()),
+        write_fetch_head: false,
+        deepen,
+    };
+    let refspecs: Vec<&str> = refspecs.iter().map(String::as_str).collect();
+    let outcome = fetch::fetch(Some("origin"), &refspecs, &fetch_options, progress)?;
+    let default_branch = outcome.head.as_ref().map(|(name, _)| name.clone());
+    let (head_ref, want, head_sha) = match (branch, outcome.head) {
+        (Some(branch), _) => select_branch(&outcome.advertised, branch)?,
+        (None, Some((name, sha))) => (name, sha.clone(), sha),
+        // HEAD stays on the unborn branch init made
+        (None, None) if outcome.advertised.is_empty() => {
+            progress.report("warning: You appear to have cloned an empty repository.");
+            return Ok(None);
+        }
+        (None, None) => {
+            progress.report("warning: remote HEAD refers to nonexistent ref, unable to checkout");
+            return Ok(None);
+        }
+    };
+    progress.report(&format!(
+        "Received head ref: {} and sha: {}",
+        head_ref, head_sha
+    ));
+
+    // A bare clone has its branches already: HEAD only has to name one
+    if bare {
+        if head_ref.starts_with("refs/heads/") {
+            refs::write_head_symbolic(&head_ref)?;
+        } else {
+            refs::update_head_detached(&head_sha, &message)?;
+        }
+        return Ok(Some(head_sha));
+    }
 
-    if !resp.status().is_success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to fetch packfile: {}", resp.status()),
-        ));
+    // Step 2: Update HEAD; a tag is checked out detached, a branch is
+    // created to track its remote counterpart
+    if let Some(branch) = head_ref.strip_prefix("refs/heads/") {
+        progress.report(&format!("Updating HEAD to {}", head_ref));
+        refs::write_head_symbolic(&head_ref)?;
+        progress.report(&format!("Creating reference {}", head_ref));
+        refs::update_ref(&head_ref, &want, &message)?;
+        let config_path = Path::new(".git/config");
+        config::set_value(config_path, &format!("branch.{}.remote", branch), "origin")?;
+        config::set_value(config_path, &format!("branch.{}.merge", branch), &head_ref)?;
+    } else {
+        progress.report(&format!("Detaching HEAD at {}", head_ref));
+        refs::update_head_detached(&head_sha, &message)?;
+    }
+    // origin/HEAD names the remote's default branch, once it is tracked
+    if let Some(default) = default_branch
+        .as_deref()
+        .and_then(|name| name.strip_prefix("refs/heads/"))
+    {
+        let tracking = format!("refs/remotes/origin/{}", default);
+        if refs::read_ref(&tracking)?.is_some() {
+            refs::write_symref("refs/remotes/origin/HEAD", &tracking)?;
+        }
     }
 
-    let pack_data = resp
-        .bytes()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
-        .to_vec();
+    // Step 3: Checkout files
+    progress.check()?;
+    progress.report("Checking out files...");
+    checkout_files(&head_sha, progress)?;
 
-    Ok(pack_data)
+    Ok(Some(head_sha))
 }
 
-/// Encode a line in Git's pkt-line format
-/// Format: 4-byte hex length (including the 4 bytes) + data
-fn encode_pkt_line(line: &str) -> Vec<u8> {
-    let len = line.len() + 4;
-    format!("{:04x}{}", len, line).into_bytes()
+/// Whether `repo_url` names a repository by its path, rather than by a URL
+fn is_local_path(repo_url: &str, remote: &RemoteUrl) -> bool {
+    remote.scheme == Scheme::File && !repo_url.contains("://")
 }
 
-// ============================================================================
-// SIDE-BAND PROTOCOL HANDLING
-// ============================================================================
-
-/// Find where the actual pack data starts
-fn find_pack_start(data: &[u8]) -> io::Result<usize> {
-    // Look for "PACK" signature
-    for i in 0..data.len().saturating_sub(4) {
-        if &data[i..i + 4] == b"PACK" {
-            return Ok(i);
-        }
+/// Give the new repository every object of the one at `source`, its
+/// working tree or its .git directory, by hard-linking the files that hold
+/// them or, where a link can't be made or `hardlink` is off, copying them.
+/// Fetching from it then finds nothing missing.
+fn copy_objects(source: &Path, hardlink: bool, progress: &mut Progress) -> io::Result<()> {
+    let git_dir = match source.join(".git") {
+        dir if dir.is_dir() => dir,
+        _ => source.to_path_buf(),
+    };
+    let objects = git_dir.join("objects");
+    if !objects.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "'{}' does not appear to be a git repository",
+                source.display()
+            ),
+        ));
     }
-    Err(io::Error::new(
-        io::ErrorKind::InvalidData,
-        "Could not find PACK signature",
-    ))
+    progress.report(&format!(
+        "{} objects from {}",
+        if hardlink { "Linking" } else { "Copying" },
+        objects.display()
+    ));
+    copy_dir(&objects, Path::new(".git/objects"), hardlink)
 }
 
-/// Decode side-band data from the pack response
-/// Git uses side-band protocol to interleave pack data with progress messages
-fn decode_sideband_data(data: &[u8]) -> io::Result<Vec<u8>> {
-    let mut decoded = Vec::new();
-    let mut offset = 0;
-
-    // Find the PACK start first
-    let pack_start = find_pack_start(data)?;
-
-    // If PACK is at the beginning, no side-band encoding
-    if pack_start == 0 {
-        return Ok(data.to_vec());
-    }
-
-    // Process pkt-line formatted data
-    while offset < data.len() {
-        // Check for pkt-line format (4-byte hex length prefix)
-        if offset + 4 <= data.len() {
-            let length_str = String::from_utf8_lossy(&data[offset..offset + 4]);
-            if let Ok(length) = u32::from_str_radix(&length_str, 16) {
-                if length == 0 {
-                    // Flush packet, skip it
-                    offset += 4;
-                    continue;
-                } else if length >= 4 && length <= 65520 && offset + length as usize <= data.len() {
-                    let pkt_data_start = offset + 4;
-                    let pkt_data_end = offset + length as usize;
-
-                    // Check for side-band prefix
-                    if pkt_data_start < pkt_data_end {
-                        let side_band = data[pkt_data_start];
-                        match side_band {
-                            1 => {
-                                // Side-band 1: pack data
-                                let actual_data = &data[pkt_data_start + 1..pkt_data_end];
-                                decoded.extend_from_slice(actual_data);
-                            }
-                            2 | 3 => {
-                                // Side-band 2/3: progress messages
-                                let message = String::from_utf8_lossy(
-                                    &data[pkt_data_start + 1..pkt_data_end],
-                                );
-                                println!("Git progress: {}", message.trim());
-                            }
-                            _ => {
-                                // Unknown side-band, treat as raw data
-                                let actual_data = &data[pkt_data_start..pkt_data_end];
-                                decoded.extend_from_slice(actual_data);
-                            }
-                        }
-                    }
-
-                    offset = pkt_data_end;
-                    continue;
-                }
+/// Borrow the objects of the repository at `reference`, its working tree or
+/// its .git directory
+fn add_alternate(reference: &Path) -> io::Result<()> {
+    let git_dir = match reference.join(".git") {
+        dir if dir.is_dir() => dir,
+        _ => reference.to_path_buf(),
+    };
+    let objects = fs::canonicalize(git_dir.join("objects")).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "reference repository '{}' is not a local repository.",
+                reference.display()
+            ),
+        )
+    })?;
+    let list = Path::new(".git/objects/info/alternates");
+    fs::create_dir_all(".git/objects/info")?;
+    let mut alternates = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(list)?;
+    writeln!(alternates, "{}", objects.display())
+}
+
+/// Copy in every object borrowed from other repositories, which the clone
+/// then no longer needs
+fn dissociate(progress: &mut Progress) -> io::Result<()> {
+    for dir in object::alternates()? {
+        progress.report(&format!("Copying objects from {}", dir.display()));
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            // Its info/alternates is not ours to take
+            if entry.file_name() != "info" && entry.file_type()?.is_dir() {
+                let target = Path::new(".git/objects").join(entry.file_name());
+                copy_dir(&entry.path(), &target, false)?;
             }
         }
-
-        // If we can't parse as pkt-line, copy raw data
-        decoded.push(data[offset]);
-        offset += 1;
     }
-
-    Ok(decoded)
+    match fs::remove_file(".git/objects/info/alternates") {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
 }
 
-// ============================================================================
-// PACK FILE UNPACKING
-// ============================================================================
+fn copy_dir(from: &Path, to: &Path, hardlink: bool) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        if entry.file_type()?.is_dir() {
+            copy_dir(&source, &target, hardlink)?;
+        } else if !hardlink || fs::hard_link(&source, &target).is_err() {
+            fs::copy(&source, &target)?;
+        }
+    }
+    Ok(())
+}
 
-/// Pack object type enumeration
-#[derive(Debug)]
-enum PackObjectType {
-    Commit,
-    Tree,
-    Blob,
-    OfsDelta(#[allow(dead_code)] usize),
-    RefDelta(String),
+/// The one ref a single-branch clone fetches: `branch`, or the branch the
+/// remote's HEAD is on. Which ref that is takes asking the remote for its
+/// refs first. `None` when there is nothing to single out yet, the remote
+/// being empty or its HEAD dangling.
+fn single_branch_ref(
+    repo_url: &str,
+    branch: Option<&str>,
+    auth: Option<&Credentials>,
+    progress: &mut Progress,
+) -> io::Result<Option<String>> {
+    let (session, advertisement) = connect(repo_url, auth, progress)?;
+    session.close()?;
+    let (advertised, head) = advertisement.into_discovery();
+    Ok(match (branch, head) {
+        (Some(branch), _) => Some(select_branch(&advertised, branch)?.0),
+        (None, head) => head.map(|(name, _)| name),
+    })
 }
 
-impl PackObjectType {
-    fn as_str(&self) -> &str {
-        match self {
-            PackObjectType::Commit => "commit",
-            PackObjectType::Tree => "tree",
-            PackObjectType::Blob => "blob",
-            _ => "unknown",
+/// The advertised branch or tag called `name` as `(ref, sha to fetch,
+/// commit to check out)`; for an annotated tag the last is its peeled value
+fn select_branch(
+    advertised: &[(String, String)],
+    name: &str,
+) -> io::Result<(String, String, String)> {
+    let lookup = |refname: &str| {
+        advertised
+            .iter()
+            .find(|(advertised, _)| advertised == refname)
+            .map(|(_, sha)| sha.clone())
+    };
+    for refname in [refs::branch_ref(name), refs::tag_ref(name)] {
+        if let Some(sha) = lookup(&refname) {
+            let commit = lookup(&format!("{}^{{}}", refname)).unwrap_or_else(|| sha.clone());
+            return Ok((refname, sha, commit));
         }
     }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Remote branch {} not found in upstream origin", name),
+    ))
 }
 
-/// Unpack the pack file and extract all objects
-fn unpack_packfile(pack_data: &[u8]) -> io::Result<()> {
-    // Decode side-band data to get clean pack file
-    let decoded_data = decode_sideband_data(pack_data)?;
-    let pack_start = find_pack_start(&decoded_data)?;
-    let pack_data = &decoded_data[pack_start..];
+/// An upload-pack conversation, in protocol v2 if both sides speak it
+pub struct Session {
+    transport: Transport,
+    v2: bool,
+    /// What the remote's upload-pack offers: its v0 capabilities, or in v2
+    /// the features of the fetch command
+    features: Vec<String>,
+}
 
-    // Validate pack header
-    if pack_data.len() < 12 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Pack file too small",
-        ));
-    }
+/// How a session reaches upload-pack. Over ssh the process (and over git://
+/// the connection to the daemon) stays open between the ref advertisement
+/// and the pack request; smart HTTP is stateless. A repository on this
+/// machine is read in place, as upload-pack would read it, without a
+/// process in between, and a dumb HTTP server is read file by file.
+enum Transport {
+    Http {
+        client: HttpClient,
+        remote: RemoteUrl,
+    },
+    DumbHttp {
+        client: HttpClient,
+        remote: RemoteUrl,
+    },
+    Ssh(ssh::Connection),
+    Daemon(daemon::Connection),
+    Local(PathBuf),
+}
 
-    // Check PACK signature
-    if &pack_data[0..4] != b"PACK" {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("Invalid pack signature: {:?}", &pack_data[0..4]),
-        ));
+impl Session {
+    /// Ask for `wants`, after negotiating with the remote which commits
+    /// from the local ref tips `haves` it has too, so it can leave out what
+    /// they reach, and return the pack it sends, to be read as it arrives,
+    /// with the changes to the shallow edge it announced. The remote is told
+    /// the edge of a shallow repository, and asked to cut history short or
+    /// deepen it by `deepen`, and to leave out the objects `filter` excludes
+    /// (see git/promisor.rs); a remote that can't filter sends everything,
+    /// with a warning. Its progress messages go to `progress` on the way.
+    pub fn fetch<'p>(
+        mut self,
+        wants: &[String],
+        haves: &[String],
+        deepen: Option<Deepen>,
+        filter: Option<&str>,
+        progress: &'p mut Progress,
+    ) -> io::Result<(Box<dyn Read + 'p>, shallow::Update)> {
+        let no_progress = progress.is_silent();
+        let filter = match filter {
+            Some(_) if !self.transport.negotiates() || !self.offers("filter") => {
+                progress.report("warning: filtering not recognized by server, ignoring");
+                None
+            }
+            filter => filter,
+        };
+        let shallow = ShallowRequest {
+            edge: shallow::read()?,
+            deepen,
+        };
+        if deepen.is_some() && !self.transport.negotiates() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "shallow fetches are not supported over this transport",
+            ));
+        }
+        if !shallow.is_empty() && !self.supports_shallow(deepen) {
+            return Err(io::Error::other("Server does not support shallow clients"));
+        }
+        // v0 over a connection that stays open keeps what was said in
+        // earlier rounds; otherwise each round repeats the wants and the
+        // haves acknowledged so far
+        let stateless = self.v2 || matches!(self.transport, Transport::Http { .. });
+        let mut negotiator = Negotiator::new(match self.transport.negotiates() {
+            true => haves,
+            false => &[],
+        })?;
+        let mut update = shallow::Update::default();
+        let mut count = negotiate::INITIAL_FLUSH;
+        let mut started = false;
+        let mut sending = None;
+        loop {
+            progress.check()?;
+            let batch = negotiator.next_batch(count)?;
+            if batch.is_empty() {
+                break;
+            }
+            let offered = [negotiator.acked(), &batch].concat();
+            let request = match (self.v2, !stateless && started) {
+                (true, _) => fetch_command(wants, &shallow, filter, &offered, false, no_progress),
+                (false, true) => have_lines(&batch, false),
+                (false, false) => {
+                    upload_pack_request(wants, &shallow, filter, &offered, false, no_progress)
+                }
+            };
+            started = true;
+            let round =
+                self.transport
+                    .round(request, self.v2, &mut negotiator, &mut update, progress)?;
+            match round {
+                Round::Continue => count = negotiate::next_flush(stateless, count),
+                Round::Ready => break,
+                Round::Sending(response) => {
+                    sending = Some(response);
+                    break;
+                }
+            }
+        }
+
+        let acked = negotiator.acked();
+        let request = match (self.v2, !stateless && started) {
+            _ if sending.is_some() => Vec::new(),
+            (true, _) => fetch_command(wants, &shallow, filter, acked, true, no_progress),
+            (false, true) => have_lines(&[], true),
+            (false, false) => {
+                upload_pack_request(wants, &shallow, filter, acked, true, no_progress)
+            }
+        };
+        let response: Box<dyn Read> = match self.transport {
+            Transport::Http { client, remote } => match sending {
+                Some(Some(response)) => Box::new(response),
+                _ => Box::new(fetch_packfile(
+                    &client, &remote, request, self.v2, progress,
+                )?),
+            },
+            Transport::Ssh(connection) => Box::new(connection.respond(request.as_slice())?),
+            Transport::Daemon(connection) => Box::new(connection.respond(request.as_slice())?),
+            Transport::Local(dir) => {
+                let pack =
+                    in_repository(&dir, || pack::write(&pack::objects_to_send(wants, haves)?))?;
+                return Ok((Box::new(io::Cursor::new(pack)), update));
+            }
+            // The objects come in as they are on the server, leaving an
+            // empty pack for the caller
+            Transport::DumbHttp { client, remote } => {
+                dumb_http::fetch(&client, &remote, wants, progress)?;
+                return Ok((Box::new(io::Cursor::new(pack::write(&[])?)), update));
+            }
+        };
+        let mut reader = PackReader::new(response, self.v2, update, progress);
+        reader.read_preamble()?;
+        let update = std::mem::take(&mut reader.shallow);
+        Ok((Box::new(reader), update))
     }
 
-    // Parse version (big-endian uint32)
-    let version = u32::from_be_bytes([pack_data[4], pack_data[5], pack_data[6], pack_data[7]]);
-    if version != 2 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("Unsupported pack version: {}", version),
-        ));
+    /// Whether the remote takes a shallow request: in v0 it says so with
+    /// the shallow capability (and deepen-relative, for deepening from the
+    /// edge), in v2 with the shallow feature of fetch
+    fn supports_shallow(&self, deepen: Option<Deepen>) -> bool {
+        let relative = matches!(deepen, Some(Deepen::Relative(_)));
+        self.offers("shallow") && (self.v2 || !relative || self.offers("deepen-relative"))
     }
 
-    // Parse object count (big-endian uint32)
-    let object_count =
-        u32::from_be_bytes([pack_data[8], pack_data[9], pack_data[10], pack_data[11]]);
-    println!("Pack contains {} objects", object_count);
+    /// Whether upload-pack offers `feature`
+    fn offers(&self, feature: &str) -> bool {
+        self.features.iter().any(|offered| offered == feature)
+    }
 
-    // Process all objects
-    process_pack_objects(pack_data, object_count)?;
+    /// End the conversation without asking for anything
+    pub fn close(self) -> io::Result<()> {
+        match self.transport {
+            Transport::Http { .. } | Transport::DumbHttp { .. } => Ok(()),
+            Transport::Ssh(connection) => connection.request(pktline::FLUSH).map(drop),
+            Transport::Daemon(connection) => connection.request(pktline::FLUSH).map(drop),
+            Transport::Local(_) => Ok(()),
+        }
+    }
+}
 
-    println!("Successfully unpacked {} objects", object_count);
-    Ok(())
+/// What the remote said to a round of haves
+enum Round {
+    /// It wants to hear more
+    Continue,
+    /// It has enough to go on, and waits for "done"
+    Ready,
+    /// It has enough and went ahead with the pack (in protocol v2), which
+    /// follows on the connection or, over HTTP, in this response
+    Sending(Option<reqwest::blocking::Response>),
 }
 
-/// Process all objects in the pack file
-fn process_pack_objects(pack_data: &[u8], object_count: u32) -> io::Result<()> {
-    let mut offset = 12; // Skip pack header
-    let mut objects = HashMap::new(); // SHA -> full object (with header)
-    let mut objects_by_offset = HashMap::new(); // pack offset -> raw content
-    let mut ref_delta_objects = Vec::new();
-    let mut ofs_delta_objects = Vec::new();
-
-    // First pass: process regular objects and collect deltas
-    for i in 0..object_count {
-        println!("Processing object {}/{}", i + 1, object_count);
-        let pack_offset = offset;
-
-        // Check if we're near the end of the pack (leave space for checksum)
-        let remaining_bytes = pack_data.len().saturating_sub(offset);
-        if remaining_bytes <= 20 {
-            println!(
-                "Reached end of pack file at offset {} with {} bytes remaining",
-                offset, remaining_bytes
-            );
-            break;
-        }
+impl Transport {
+    /// Whether the remote is asked for a pack, after negotiating, rather
+    /// than read directly
+    fn negotiates(&self) -> bool {
+        matches!(
+            self,
+            Transport::Http { .. } | Transport::Ssh(_) | Transport::Daemon(_)
+        )
+    }
 
-        let (obj_type, obj_data, bytes_consumed) = match parse_pack_object(&pack_data[offset..]) {
-            Ok(result) => result,
-            Err(e) => {
-                println!(
-                    "Error parsing object {} at pack offset {}: {}",
-                    i + 1,
-                    offset,
-                    e
-                );
-                println!("Remaining pack data: {} bytes", pack_data.len() - offset);
-
-                // Try error recovery
-                if let Some((recovered_offset, recovered_obj)) =
-                    attempt_error_recovery(pack_data, offset)?
-                {
-                    offset = recovered_offset;
-                    recovered_obj
-                } else {
-                    println!("Could not recover, stopping at object {}", i + 1);
-                    break;
-                }
+    /// Send one round of a negotiation and take in the acknowledgements,
+    /// and any changes to the shallow edge announced before them
+    fn round(
+        &mut self,
+        request: Vec<u8>,
+        v2: bool,
+        negotiator: &mut Negotiator,
+        update: &mut shallow::Update,
+        progress: &mut Progress,
+    ) -> io::Result<Round> {
+        let (ready, response) = match self {
+            Transport::Http { client, remote } => {
+                let mut response = fetch_packfile(client, remote, request, v2, progress)?;
+                (
+                    read_acks(&mut response, v2, negotiator, update)?,
+                    Some(response),
+                )
             }
-        };
-
-        // Store object based on type
-        match obj_type {
-            PackObjectType::Commit | PackObjectType::Tree | PackObjectType::Blob => {
-                let sha = store_object(&obj_type, &obj_data)?;
-
-                // Store full object with header for delta base lookup
-                let header = format!("{} {}\0", obj_type.as_str(), obj_data.len());
-                let mut full_object = Vec::new();
-                full_object.extend_from_slice(header.as_bytes());
-                full_object.extend_from_slice(&obj_data);
-                objects.insert(sha.clone(), full_object);
-
-                // Store raw content for OFS_DELTA
-                objects_by_offset.insert(pack_offset, obj_data.clone());
-                println!("  Stored {} as {}", obj_type.as_str(), sha);
+            Transport::Ssh(connection) => {
+                connection.send(&request)?;
+                (read_acks(connection, v2, negotiator, update)?, None)
             }
-            PackObjectType::RefDelta(base_sha) => {
-                println!("  Found REF_DELTA referencing {}", base_sha);
-                ref_delta_objects.push((base_sha, obj_data));
+            Transport::Daemon(connection) => {
+                connection.send(&request)?;
+                (read_acks(connection, v2, negotiator, update)?, None)
             }
-            PackObjectType::OfsDelta(ofs) => {
-                println!("  Found OFS_DELTA with offset {}", ofs);
-                ofs_delta_objects.push((pack_offset, ofs, obj_data));
+            Transport::Local(_) | Transport::DumbHttp { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "this transport doesn't negotiate",
+                ))
             }
-        }
+        };
+        Ok(match (ready, v2) {
+            (false, _) => Round::Continue,
+            (true, false) => Round::Ready,
+            (true, true) => Round::Sending(response),
+        })
+    }
 
-        offset += bytes_consumed;
+    /// Send a protocol v2 command and return the response to it
+    fn command(&mut self, request: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Transport::Http { client, remote } => {
+                let request = client
+                    .post_rpc(
+                        &remote.http_endpoint("git-upload-pack"),
+                        "git-upload-pack",
+                        request,
+                    )?
+                    .header("Git-Protocol", "version=2");
+                let response = client.send(request).map_err(io::Error::other)?;
+                if !response.status().is_success() {
+                    return Err(io::Error::other(format!(
+                        "Failed to list refs: {}",
+                        response.status()
+                    )));
+                }
+                Ok(response.bytes().map_err(io::Error::other)?.to_vec())
+            }
+            Transport::Ssh(connection) => connection.command(&request),
+            Transport::Daemon(connection) => connection.command(&request),
+            Transport::Local(_) | Transport::DumbHttp { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "this transport has no protocol commands",
+            )),
+        }
     }
+}
 
-    // Second pass: process delta objects
-    process_ref_deltas(ref_delta_objects, &mut objects)?;
-    process_ofs_deltas(ofs_delta_objects, &mut objects, &mut objects_by_offset)?;
+/// Connect to the remote and read its ref advertisement. `auth` replaces
+/// any credentials given in the URL.
+pub fn open_session(
+    repo_url: &str,
+    auth: Option<&Credentials>,
+    progress: &mut Progress,
+) -> io::Result<(Session, RefDiscovery)> {
+    let (session, advertisement) = connect(repo_url, auth, progress)?;
+    Ok((session, advertisement.into_discovery()))
+}
 
-    Ok(())
+/// Connect to the remote's upload-pack and read everything it advertises
+pub fn connect(
+    repo_url: &str,
+    auth: Option<&Credentials>,
+    progress: &mut Progress,
+) -> io::Result<(Session, Advertisement)> {
+    connect_with_prefixes(repo_url, auth, &[], progress)
 }
 
-/// Attempt to recover from parsing errors by finding the next valid object
-fn attempt_error_recovery(
-    pack_data: &[u8],
-    offset: usize,
-) -> io::Result<Option<(usize, (PackObjectType, Vec<u8>, usize))>> {
-    println!("Attempting to recover by finding next valid object...");
-
-    let mut recovery_offset = 1;
-
-    // Look ahead up to 1000 bytes for the next valid object
-    while recovery_offset < 1000 && offset + recovery_offset < pack_data.len() - 20 {
-        if let Ok((next_obj_type, next_obj_data, next_bytes_consumed)) =
-            parse_pack_object(&pack_data[offset + recovery_offset..])
-        {
-            println!(
-                "Found valid object at offset {}, skipping {} bytes",
-                offset + recovery_offset,
-                recovery_offset
-            );
-            return Ok(Some((
-                offset + recovery_offset,
-                (next_obj_type, next_obj_data, next_bytes_consumed),
+/// Connect to the remote's upload-pack and read the refs it has under
+/// `ref_prefixes`, or all of them without any. Only a protocol v2 remote
+/// can leave out the rest; the others advertise everything regardless.
+pub fn connect_with_prefixes(
+    repo_url: &str,
+    auth: Option<&Credentials>,
+    ref_prefixes: &[String],
+    progress: &mut Progress,
+) -> io::Result<(Session, Advertisement)> {
+    let mut remote = url::parse(repo_url)?;
+    if let Some(auth) = auth {
+        remote.user = Some(auth.username.clone());
+        remote.password = Some(auth.password.clone());
+    }
+    let protocol = match protocol_version()? {
+        0 => None,
+        version => Some(format!("version={}", version)),
+    };
+    let protocol = protocol.as_deref();
+    let (mut transport, response) = match remote.scheme {
+        Scheme::Http | Scheme::Https => {
+            let mut client = http::client(repo_url)?;
+            let (response, smart) = discover_refs(&mut client, &remote, protocol, progress)?;
+            if !smart {
+                let refs = dumb_advertisement(&client, &remote, &response)?;
+                let transport = Transport::DumbHttp { client, remote };
+                return Ok((
+                    Session {
+                        transport,
+                        v2: false,
+                        features: Vec::new(),
+                    },
+                    refs,
+                ));
+            }
+            (Transport::Http { client, remote }, response)
+        }
+        Scheme::Ssh => {
+            progress.report(&format!("Connecting to {}", remote));
+            let mut connection = ssh::connect(&remote, "git-upload-pack", protocol)?;
+            let response = connection.read_advertisement()?;
+            (Transport::Ssh(connection), response)
+        }
+        Scheme::Git => {
+            let mut connection = daemon::connect(&remote, "git-upload-pack", protocol)?;
+            let response = connection.read_advertisement()?;
+            (Transport::Daemon(connection), response)
+        }
+        Scheme::File => {
+            let dir = path::absolute(&remote.path)?;
+            let refs = in_repository(&dir, local_advertisement)?;
+            let transport = Transport::Local(dir);
+            return Ok((
+                Session {
+                    transport,
+                    v2: false,
+                    features: Vec::new(),
+                },
+                refs,
+            ));
+        }
+    };
+
+    let Some(capabilities) = v2_capabilities(&response)? else {
+        let refs = parse_refs_response(&response, progress)?;
+        return Ok((
+            Session {
+                transport,
+                v2: false,
+                features: refs.capabilities.clone(),
+            },
+            refs,
+        ));
+    };
+    for command in ["ls-refs", "fetch"] {
+        let offered = capabilities
+            .iter()
+            .any(|c| c == command || c.starts_with(&format!("{}=", command)));
+        if !offered {
+            return Err(io::Error::other(format!(
+                "the server does not support the '{}' command",
+                command
             )));
         }
-        recovery_offset += 1;
     }
+    let listing = transport.command(ls_refs_command(ref_prefixes))?;
+    let refs = parse_ls_refs(&listing)?;
+    let features = capabilities
+        .iter()
+        .find_map(|c| c.strip_prefix("fetch="))
+        .map(|features| features.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    Ok((
+        Session {
+            transport,
+            v2: true,
+            features,
+        },
+        refs,
+    ))
+}
 
-    Ok(None)
+/// The protocol version to ask upload-pack for: protocol.version, 2 unless
+/// configured otherwise. A remote that doesn't know it answers in v0.
+fn protocol_version() -> io::Result<u8> {
+    match Config::load()?.get("protocol.version") {
+        None => Ok(2),
+        Some(value) => match value.parse() {
+            Ok(version) if version <= 2 => Ok(version),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown value for config 'protocol.version': {}", value),
+            )),
+        },
+    }
 }
 
-/// Process REF_DELTA objects
-fn process_ref_deltas(
-    ref_delta_objects: Vec<(String, Vec<u8>)>,
-    objects: &mut HashMap<String, Vec<u8>>,
-) -> io::Result<()> {
-    println!("Processing {} REF_DELTA objects", ref_delta_objects.len());
+/// Run `f` in the repository at `dir`, coming back to the current directory
+/// however it goes
+pub fn in_repository<T>(dir: &Path, f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    let original_dir = std::env::current_dir()?;
+    let result = advertise::enter_repository(&dir.to_string_lossy()).and_then(|()| f());
+    std::env::set_current_dir(original_dir)?;
+    result
+}
 
-    for (base_sha, delta_data) in ref_delta_objects {
-        let base_object_full = if let Some(obj) = objects.get(&base_sha) {
-            obj.clone()
-        } else {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("REF_DELTA base object {} not found in memory", base_sha),
-            ));
+/// What upload-pack would advertise for the repository in the current
+/// directory
+fn local_advertisement() -> io::Result<Advertisement> {
+    let hidden = HiddenRefs::load(&Config::load()?, "uploadpack");
+    let visible = advertise::visible_refs(true, &hidden)?;
+    let mut advertisement = Advertisement::default();
+    if let refs::Head::Branch(branch) = refs::read_head()? {
+        if visible.iter().any(|(name, _)| *name == branch) {
+            advertisement.symrefs.push(("HEAD".to_string(), branch));
+        }
+    }
+    for (name, sha) in visible {
+        let peeled = match name.starts_with("refs/tags/") {
+            true => Some(tag::peel(&sha)?.0).filter(|target| *target != sha),
+            false => None,
         };
+        advertisement.refs.push((name.clone(), sha));
+        if let Some(peeled) = peeled {
+            advertisement.refs.push((format!("{}^{{}}", name), peeled));
+        }
+    }
+    Ok(advertisement)
+}
 
-        // Extract raw content from full object (skip header)
-        let null_pos = base_object_full
-            .iter()
-            .position(|&b| b == 0)
-            .ok_or_else(|| {
-                io::Error::new(io::ErrorKind::InvalidData, "Invalid base object format")
-            })?;
-        let base_content = &base_object_full[null_pos + 1..];
+// ============================================================================
+// SUBMODULES
+// ============================================================================
 
-        let result_content = apply_delta(base_content, &delta_data)?;
-        let sha = store_raw_object(&result_content)?;
-        objects.insert(sha.clone(), result_content);
-        println!("  Applied REF_DELTA and stored as {}", sha);
+/// Clone every submodule that has a gitlink in the checked-out commit and
+/// check it out, detached, at the recorded commit. Nested submodules are
+/// cloned the same way.
+fn clone_submodules(
+    superproject_url: &str,
+    head_sha: &str,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let commit = commit::read(head_sha)?;
+    let gitlinks: HashMap<String, String> = tree::flatten(&commit.tree)?
+        .into_iter()
+        .filter(|(_, entry)| entry.mode == tree::MODE_GITLINK)
+        .map(|(path, entry)| (path, entry.sha))
+        .collect();
+    if gitlinks.is_empty() {
+        return Ok(());
     }
 
+    let config = Config::load()?;
+    for module in submodule::read_gitmodules()? {
+        progress.check()?;
+        let sha = match gitlinks.get(&module.path) {
+            Some(sha) => sha,
+            None => continue,
+        };
+        let url = submodule::resolve_url(&module, &config, superproject_url)?;
+        config::set_value(
+            Path::new(".git/config"),
+            &format!("submodule.{}.url", module.name),
+            &url,
+        )?;
+        progress.report(&format!(
+            "Submodule '{}' ({}) registered for path '{}'",
+            module.name, url, module.path
+        ));
+        progress.report(&format!("Cloning into '{}'...", module.path));
+
+        let superproject_dir = std::env::current_dir()?;
+        fs::create_dir_all(&module.path)?;
+        std::env::set_current_dir(&module.path)?;
+        let result = clone_submodule(&url, sha, progress);
+        std::env::set_current_dir(superproject_dir)?;
+        result?;
+
+        progress.report(&format!(
+            "Submodule path '{}': checked out '{}'",
+            module.path, sha
+        ));
+    }
     Ok(())
 }
 
-/// Process OFS_DELTA objects
-fn process_ofs_deltas(
-    ofs_delta_objects: Vec<(usize, usize, Vec<u8>)>,
-    objects: &mut HashMap<String, Vec<u8>>,
-    objects_by_offset: &mut HashMap<usize, Vec<u8>>,
-) -> io::Result<()> {
-    println!("Processing {} OFS_DELTA objects", ofs_delta_objects.len());
-
-    for (pack_offset, ofs, delta_data) in ofs_delta_objects {
-        let base_offset = pack_offset - ofs;
-        println!(
-            "  OFS_DELTA at offset {} references base at offset {}",
-            pack_offset, base_offset
-        );
-
-        let base_object = match objects_by_offset.get(&base_offset) {
-            Some(obj) => obj,
-            None => {
-                println!(
-                    "  Warning: OFS_DELTA base object not found at offset {}, skipping",
-                    base_offset
-                );
-                continue;
-            }
-        };
-
-        println!("  Base object size: {} bytes", base_object.len());
-        println!("  Delta data size: {} bytes", delta_data.len());
+/// Clone `url` into the current directory with HEAD detached at `sha`
+fn clone_submodule(url: &str, sha: &str, progress: &mut Progress) -> io::Result<()> {
+    init_git_repo(false)?;
+    config::set_value(Path::new(".git/config"), "remote.origin.url", url)?;
+    let (session, (advertised, _)) = open_session(url, None, progress)?;
 
-        let result_content = apply_delta(base_object, &delta_data)?;
-        println!("  Result content size: {} bytes", result_content.len());
+    // The pinned commit need not be a branch tip, so ask for every tip and
+    // expect it to be reachable from one of them
+    let mut wants: Vec<String> = advertised
+        .iter()
+        .filter(|(name, _)| !name.ends_with("^{}"))
+        .map(|(_, tip)| tip.clone())
+        .collect();
+    wants.sort();
+    wants.dedup();
+    if wants.is_empty() {
+        wants.push(sha.to_string());
+    }
 
-        // Create full object with blob header (most common for deltas)
-        let header = format!("blob {}\0", result_content.len());
-        let mut full_object = Vec::new();
-        full_object.extend_from_slice(header.as_bytes());
-        full_object.extend_from_slice(&result_content);
+    let (pack, _) = session.fetch(&wants, &[], None, None, progress)?;
+    pack::store(pack, &pack::Limits::from_config(&Config::load()?)?)?;
 
-        let sha = store_raw_object(&full_object)?;
-        objects.insert(sha.clone(), full_object.clone());
-        objects_by_offset.insert(pack_offset, result_content);
-        println!("  Applied OFS_DELTA and stored as {}", sha);
+    if object::locate(sha)?.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Fetched in submodule, but it did not contain {}", sha),
+        ));
     }
 
-    Ok(())
+    refs::update_head_detached(sha, &format!("clone: from {}", url::parse(url)?))?;
+    checkout_files(sha, progress)?;
+    clone_submodules(url, sha, progress)
 }
 
 // ============================================================================
-// PACK OBJECT PARSING
+// REFERENCE DISCOVERY
 // ============================================================================
 
-/// Parse a single object from the pack file
-fn parse_pack_object(data: &[u8]) -> io::Result<(PackObjectType, Vec<u8>, usize)> {
-    if data.is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "No data to parse",
-        ));
-    }
-
-    let mut offset = 0;
-    let first_byte = data[offset];
-    offset += 1;
+/// Advertised refs as `(name, sha)` pairs, plus the `(ref, sha)` HEAD points
+/// at; `None` when the remote has no HEAD, as an empty repository doesn't
+pub type RefDiscovery = (Vec<(String, String)>, Option<(String, String)>);
+
+/// A remote's ref advertisement
+#[derive(Debug, Clone, Default)]
+pub struct Advertisement {
+    /// Every ref as `(name, sha)` in the order sent: HEAD first when the
+    /// remote has one, and peeled tags as `<tag>^{}`
+    pub refs: Vec<(String, String)>,
+    /// `(name, target)` for each symbolic ref, as the symref capability
+    /// reports them
+    pub symrefs: Vec<(String, String)>,
+    /// The capabilities on the first line of a v0 advertisement
+    pub capabilities: Vec<String>,
+}
 
-    // Extract object type (bits 6-4) and initial size (bits 3-0)
-    let obj_type_num = (first_byte >> 4) & 0x07;
-    let mut size = (first_byte & 0x0F) as usize;
-    let mut shift = 4;
+impl Advertisement {
+    /// The refs other than HEAD, and the branch HEAD points at. Without a
+    /// symref capability to say which, HEAD is taken to be on a branch at the
+    /// same commit, and a remote that doesn't advertise HEAD at all on any
+    /// branch; either way the one init.defaultBranch names is preferred, then
+    /// main, then master, then the first.
+    pub fn into_discovery(self) -> RefDiscovery {
+        let lookup = |refname: &str| {
+            self.refs
+                .iter()
+                .find(|(name, _)| name == refname)
+                .map(|(_, sha)| sha.clone())
+        };
+        let symref = self
+            .symrefs
+            .iter()
+            .find(|(name, _)| name == "HEAD")
+            .and_then(|(_, target)| Some((target.clone(), lookup(target)?)));
+        let head = symref.or_else(|| {
+            let sha = lookup("HEAD");
+            let branches: Vec<&(String, String)> = self
+                .refs
+                .iter()
+                .filter(|(name, tip)| {
+                    name.starts_with("refs/heads/") && sha.as_ref().map_or(true, |sha| tip == sha)
+                })
+                .collect();
+            let configured = Config::load()
+                .ok()
+                .and_then(|config| config.get("init.defaultbranch").map(refs::branch_ref));
+            let mut preferred = configured.into_iter().chain([
+                "refs/heads/main".to_string(),
+                "refs/heads/master".to_string(),
+            ]);
+            let branch = preferred
+                .find_map(|refname| branches.iter().find(|(name, _)| *name == refname))
+                .or_else(|| branches.first())?;
+            let (name, sha) = branch;
+            Some((name.clone(), sha.clone()))
+        });
+        let refs = self
+            .refs
+            .into_iter()
+            .filter(|(name, _)| name != "HEAD")
+            .collect();
+        (refs, head)
+    }
+}
 
-    // Continue reading size if MSB is set (variable-length encoding)
-    let mut current_byte = first_byte;
-    while current_byte & 0x80 != 0 {
-        if offset >= data.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Incomplete size encoding",
-            ));
-        }
+/// Fetch the remote's `info/refs`: the ref advertisement, or in protocol v2
+/// the capability advertisement, and whether the server speaks smart HTTP
+/// at all; a dumb server sends its info/refs file instead
+fn discover_refs(
+    client: &mut HttpClient,
+    remote: &RemoteUrl,
+    protocol: Option<&str>,
+    progress: &mut Progress,
+) -> io::Result<(Vec<u8>, bool)> {
+    let refs_url = remote.http_endpoint("info/refs?service=git-upload-pack");
+
+    progress.report(&format!("Discovering references from {}", refs_url));
+
+    let headers: Vec<(&str, &str)> = protocol
+        .map(|protocol| ("Git-Protocol", protocol))
+        .into_iter()
+        .collect();
+    let response = client.get_authenticated(remote, &refs_url, &headers)?;
 
-        current_byte = data[offset];
-        offset += 1;
+    if !response.status().is_success() {
+        return Err(io::Error::other(format!(
+            "Failed to fetch refs: {}",
+            response.status()
+        )));
+    }
 
-        // Prevent shift overflow
-        if shift >= 64 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Size encoding too large",
-            ));
-        }
+    let smart = response
+        .headers()
+        .get("Content-Type")
+        .is_some_and(|value| value == "application/x-git-upload-pack-advertisement");
+    let body = response
+        .bytes()
+        .map_err(|e| io::Error::other(format!("Error reading refs: {:?}", e)))?;
+    Ok((body.to_vec(), smart))
+}
 
-        size |= ((current_byte & 0x7F) as usize) << shift;
-        shift += 7;
+/// The refs of a dumb HTTP server, from its `info/refs` and `HEAD` files
+fn dumb_advertisement(
+    client: &HttpClient,
+    remote: &RemoteUrl,
+    info_refs: &[u8],
+) -> io::Result<Advertisement> {
+    let mut advertisement = Advertisement {
+        refs: dumb_http::parse_info_refs(info_refs),
+        ..Advertisement::default()
+    };
+    let head = match dumb_http::read_head(client, remote)? {
+        Some(head) => match head.strip_prefix("ref: ") {
+            Some(target) => {
+                advertisement
+                    .symrefs
+                    .push(("HEAD".to_string(), target.to_string()));
+                advertisement
+                    .refs
+                    .iter()
+                    .find(|(name, _)| name == target)
+                    .map(|(_, sha)| sha.clone())
+            }
+            None => Some(head),
+        },
+        None => None,
+    };
+    if let Some(sha) = head {
+        advertisement.refs.insert(0, ("HEAD".to_string(), sha));
     }
+    Ok(advertisement)
+}
 
-    // Parse object type and handle special cases
-    let obj_type = match obj_type_num {
-        1 => PackObjectType::Commit,
-        2 => PackObjectType::Tree,
-        3 => PackObjectType::Blob,
-        4 => {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "TAG objects not supported",
-            ))
-        }
-        6 => {
-            // OFS_DELTA - read negative offset using Git's encoding
-            let (ofs_offset, new_offset) = read_ofs_delta_offset(data, offset)?;
-            offset = new_offset;
-            PackObjectType::OfsDelta(ofs_offset)
+/// Parse the refs response from git-upload-pack. Capabilities follow a NUL
+/// on the first line, symref=HEAD:refs/heads/main among them.
+fn parse_refs_response(body: &[u8], progress: &mut Progress) -> io::Result<Advertisement> {
+    let mut advertisement = Advertisement::default();
+    for packet in pktline::split(body)? {
+        let line = match packet {
+            Packet::Data(line) => String::from_utf8_lossy(line).into_owned(),
+            Packet::Flush | Packet::Delim => continue,
+        };
+        // Smart HTTP starts with "# service=git-upload-pack", and a remote
+        // asked for protocol v1 with "version 1"
+        if line.starts_with('#') || line == "version 1\n" {
+            continue;
         }
-        7 => {
-            // REF_DELTA - read 20-byte SHA1
-            if offset + 20 > data.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Incomplete REF_DELTA SHA",
-                ));
+        let (line, capabilities) = line.split_once('\0').unwrap_or((&line, ""));
+        for capability in capabilities.split_whitespace() {
+            advertisement.capabilities.push(capability.to_string());
+            if let Some((name, target)) = capability
+                .strip_prefix("symref=")
+                .and_then(|symref| symref.split_once(':'))
+            {
+                advertisement
+                    .symrefs
+                    .push((name.to_string(), target.to_string()));
             }
-            let sha_bytes = &data[offset..offset + 20];
-            offset += 20;
-            let sha = hex::encode(sha_bytes);
-            PackObjectType::RefDelta(sha)
-        }
-        _ => {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Unknown object type: {}", obj_type_num),
-            ))
         }
-    };
-
-    // Decompress object data
-    let compressed_data = &data[offset..];
-    let mut decoder = ZlibDecoder::new(compressed_data);
-    let mut decompressed = Vec::new();
 
-    decoder.read_to_end(&mut decompressed).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!(
-                "Failed to decompress object at header offset {}: {}",
-                offset, e
-            ),
-        )
-    })?;
-
-    // Verify the decompressed size matches expected (with tolerance for minor differences)
-    if decompressed.len() != size {
-        println!(
-            "Warning: Size mismatch for object - expected {}, got {}",
-            size,
-            decompressed.len()
-        );
-        if (decompressed.len() as i64 - size as i64).abs() > 1000 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Significant size mismatch: expected {}, got {}",
-                    size,
-                    decompressed.len()
-                ),
-            ));
+        let Some((sha, ref_name)) = line.trim_end().split_once(' ') else {
+            continue;
+        };
+        // An empty repository advertises only "capabilities^{}"
+        if ref_name == "capabilities^{}" {
+            continue;
+        }
+        if sha.len() != 40 {
+            progress.report(&format!("Skipping invalid SHA: {} for {}", sha, ref_name));
+            continue;
         }
+        advertisement
+            .refs
+            .push((ref_name.to_string(), sha.to_string()));
     }
-
-    // Calculate how many compressed bytes were consumed
-    let total_in = decoder.total_in() as usize;
-
-    Ok((obj_type, decompressed, offset + total_in))
+    Ok(advertisement)
 }
 
-/// Read OFS_DELTA offset using Git's variable-length encoding
-fn read_ofs_delta_offset(data: &[u8], mut offset: usize) -> io::Result<(usize, usize)> {
-    if offset >= data.len() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "No data for OFS_DELTA offset",
-        ));
+/// The capabilities of a protocol v2 capability advertisement; `None` when
+/// `response` is a v0 ref advertisement instead
+fn v2_capabilities(response: &[u8]) -> io::Result<Option<Vec<String>>> {
+    let mut lines = pktline::split(response)?
+        .into_iter()
+        .filter_map(|packet| match packet {
+            Packet::Data(line) if !line.starts_with(b"#") => {
+                Some(String::from_utf8_lossy(line).trim_end().to_string())
+            }
+            _ => None,
+        });
+    match lines.next().as_deref() {
+        Some("version 2") => Ok(Some(lines.collect())),
+        _ => Ok(None),
     }
+}
 
-    let mut c = data[offset];
-    offset += 1;
-    let mut ofs = (c & 0x7F) as usize;
+/// A v2 ls-refs command for the refs under `ref_prefixes` (all of them
+/// without any), with symbolic refs' targets and tags' peeled values
+fn ls_refs_command(ref_prefixes: &[String]) -> Vec<u8> {
+    let mut request = encode_pkt_line("command=ls-refs\n");
+    request.extend_from_slice(&encode_pkt_line(&format!("agent={}\n", http::USER_AGENT)));
+    request.extend_from_slice(pktline::DELIM);
+    request.extend_from_slice(&encode_pkt_line("symrefs\n"));
+    request.extend_from_slice(&encode_pkt_line("peel\n"));
+    for prefix in ref_prefixes {
+        request.extend_from_slice(&encode_pkt_line(&format!("ref-prefix {}\n", prefix)));
+    }
+    request.extend_from_slice(pktline::FLUSH);
+    request
+}
 
-    while c & 0x80 != 0 {
-        if offset >= data.len() {
+/// The refs an ls-refs lists, one a line: "<sha> <name>", then
+/// "symref-target:<ref>" for a symbolic ref and "peeled:<sha>" for a tag
+fn parse_ls_refs(response: &[u8]) -> io::Result<Advertisement> {
+    let mut advertisement = Advertisement::default();
+    for packet in pktline::split(response)? {
+        let Packet::Data(line) = packet else {
+            continue;
+        };
+        let line = String::from_utf8_lossy(line);
+        let mut fields = line.trim_end().split(' ');
+        let (Some(sha), Some(name)) = (fields.next(), fields.next()) else {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
-                "Incomplete OFS_DELTA offset",
+                format!("invalid ls-refs response: {}", line.trim_end()),
             ));
+        };
+        advertisement.refs.push((name.to_string(), sha.to_string()));
+        for attribute in fields {
+            if let Some(target) = attribute.strip_prefix("symref-target:") {
+                advertisement
+                    .symrefs
+                    .push((name.to_string(), target.to_string()));
+            } else if let Some(peeled) = attribute.strip_prefix("peeled:") {
+                advertisement
+                    .refs
+                    .push((format!("{}^{{}}", name), peeled.to_string()));
+            }
         }
-
-        c = data[offset];
-        offset += 1;
-        ofs = ((ofs + 1) << 7) + (c & 0x7F) as usize;
     }
-
-    Ok((ofs, offset))
+    Ok(advertisement)
 }
 
 // ============================================================================
-// DELTA COMPRESSION
+// PACK FILE FETCHING
 // ============================================================================
 
-/// Apply a delta to a base object to reconstruct the target object
-fn apply_delta(base: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
-    let mut offset = 0;
-
-    // Read base object size (variable length encoding)
-    let mut _base_size = 0usize;
-    let mut shift = 0;
-    loop {
-        if offset >= delta.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Incomplete base size in delta",
-            ));
-        }
-        let byte = delta[offset];
-        offset += 1;
-
-        // Prevent shift overflow
-        if shift >= 64 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Base size encoding too large",
-            ));
-        }
-
-        _base_size |= ((byte & 0x7F) as usize) << shift;
-        shift += 7;
-        if byte & 0x80 == 0 {
-            break;
-        }
+/// Fetch packfile from the remote repository
+fn fetch_packfile(
+    client: &HttpClient,
+    remote: &RemoteUrl,
+    request_body: Vec<u8>,
+    v2: bool,
+    progress: &mut Progress,
+) -> io::Result<reqwest::blocking::Response> {
+    let pack_url = remote.http_endpoint("git-upload-pack");
+
+    progress.report(&format!("Requesting pack from: {}", pack_url));
+
+    let mut request = client.post_rpc(&pack_url, "git-upload-pack", request_body)?;
+    if v2 {
+        request = request.header("Git-Protocol", "version=2");
     }
+    let resp = client.send(request).map_err(io::Error::other)?;
 
-    // Read result object size
-    let mut result_size = 0usize;
-    shift = 0;
-    loop {
-        if offset >= delta.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Incomplete result size in delta",
-            ));
-        }
-        let byte = delta[offset];
-        offset += 1;
-
-        // Prevent shift overflow
-        if shift >= 64 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Result size encoding too large",
-            ));
-        }
-
-        result_size |= ((byte & 0x7F) as usize) << shift;
-        shift += 7;
-        if byte & 0x80 == 0 {
-            break;
-        }
+    if !resp.status().is_success() {
+        return Err(io::Error::other(format!(
+            "Failed to fetch packfile: {}",
+            resp.status()
+        )));
     }
+    Ok(resp)
+}
 
-    let mut result = Vec::with_capacity(result_size);
-
-    // Apply delta instructions
-    while offset < delta.len() {
-        let cmd = delta[offset];
-        offset += 1;
-
-        if cmd & 0x80 != 0 {
-            // Copy command: copy bytes from base object
-            let mut copy_offset = 0usize;
-            let mut copy_size = 0usize;
-
-            // Read offset (up to 4 bytes)
-            if cmd & 0x01 != 0 {
-                copy_offset |= delta[offset] as usize;
-                offset += 1;
-            }
-            if cmd & 0x02 != 0 {
-                copy_offset |= (delta[offset] as usize) << 8;
-                offset += 1;
-            }
-            if cmd & 0x04 != 0 {
-                copy_offset |= (delta[offset] as usize) << 16;
-                offset += 1;
-            }
-            if cmd & 0x08 != 0 {
-                copy_offset |= (delta[offset] as usize) << 24;
-                offset += 1;
-            }
-
-            // Read size (up to 3 bytes)
-            if cmd & 0x10 != 0 {
-                copy_size |= delta[offset] as usize;
-                offset += 1;
-            }
-            if cmd & 0x20 != 0 {
-                copy_size |= (delta[offset] as usize) << 8;
-                offset += 1;
-            }
-            if cmd & 0x40 != 0 {
-                copy_size |= (delta[offset] as usize) << 16;
-                offset += 1;
-            }
-
-            if copy_size == 0 {
-                copy_size = 0x10000; // Default size when not specified
-            }
-
-            // Validate and copy from base
-            if copy_offset + copy_size > base.len() {
+/// Read the acknowledgements to a round of haves, passing the haves the
+/// remote has too to `negotiator`; true if it said it is ready. In v0 they
+/// end with a NAK, in v2 with the acknowledgments section. The shallow
+/// lines a v0 remote sends first go to `update`.
+fn read_acks(
+    response: &mut impl Read,
+    v2: bool,
+    negotiator: &mut Negotiator,
+    update: &mut shallow::Update,
+) -> io::Result<bool> {
+    let mut ready = false;
+    loop {
+        let line = match pktline::read(response) {
+            Ok(Some(line)) => line,
+            Ok(None) if v2 => return Ok(ready),
+            Ok(None) => continue,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
                 return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!(
-                        "Delta copy out of bounds: offset={}, size={}, base_len={}",
-                        copy_offset,
-                        copy_size,
-                        base.len()
-                    ),
-                ));
+                    io::ErrorKind::UnexpectedEof,
+                    "the remote end hung up unexpectedly",
+                ))
             }
-            result.extend_from_slice(&base[copy_offset..copy_offset + copy_size]);
-        } else if cmd != 0 {
-            // Insert command: insert new data from delta
-            let insert_size = cmd as usize;
-            if offset + insert_size > delta.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Delta insert out of bounds",
-                ));
+            Err(e) => return Err(e),
+        };
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end();
+        if let Some(message) = line.strip_prefix("ERR ") {
+            return Err(io::Error::other(format!("remote error: {}", message)));
+        }
+        if update.parse_line(line) {
+            continue;
+        }
+        match line.strip_prefix("ACK ").map(|ack| ack.split_once(' ')) {
+            Some(Some((sha, status))) => {
+                negotiator.ack(sha)?;
+                ready |= status == "ready";
             }
-            result.extend_from_slice(&delta[offset..offset + insert_size]);
-            offset += insert_size;
-        } else {
-            // cmd == 0 is invalid
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid delta command: 0",
-            ));
+            Some(None) => negotiator.ack(&line[4..])?,
+            // In v2 the packfile section follows straight away
+            None if v2 && line == "ready" => return Ok(true),
+            None if !v2 && line == "NAK" => return Ok(ready),
+            None => {}
         }
     }
+}
 
-    // Verify result size
-    if result.len() != result_size {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
+/// Body of an upload-pack request; capabilities ride on the first want.
+/// No thin-pack: the pack has to stand on its own to be kept as it is.
+/// The haves end with "done" once negotiating is over, and with a flush
+/// for the remote to acknowledge them before that.
+fn upload_pack_request(
+    wants: &[String],
+    shallow: &ShallowRequest,
+    filter: Option<&str>,
+    haves: &[String],
+    done: bool,
+    no_progress: bool,
+) -> Vec<u8> {
+    let mut request_body = Vec::new();
+    for (i, sha) in wants.iter().enumerate() {
+        let want_line = if i == 0 {
+            let no_progress = if no_progress { " no-progress" } else { "" };
+            let filter_capability = if filter.is_some() { " filter" } else { "" };
             format!(
-                "Delta result size mismatch: expected {}, got {}",
-                result_size,
-                result.len()
-            ),
-        ));
+                "want {} multi_ack_detailed side-band-64k ofs-delta include-tag{}{}{}\n",
+                sha,
+                shallow.capabilities(),
+                filter_capability,
+                no_progress
+            )
+        } else {
+            format!("want {}\n", sha)
+        };
+        request_body.extend_from_slice(&encode_pkt_line(&want_line));
     }
-
-    Ok(result)
+    request_body.extend_from_slice(&shallow.lines(false));
+    request_body.extend_from_slice(&filter_line(filter));
+    request_body.extend_from_slice(b"0000"); // flush packet
+    request_body.extend_from_slice(&have_lines(haves, done));
+    request_body
 }
 
-// ============================================================================
-// OBJECT STORAGE
-// ============================================================================
-
-/// Store an object in the Git object database
-fn store_object(obj_type: &PackObjectType, data: &[u8]) -> io::Result<String> {
-    let header = format!("{} {}\0", obj_type.as_str(), data.len());
-    let mut full_object = Vec::new();
-    full_object.extend_from_slice(header.as_bytes());
-    full_object.extend_from_slice(data);
-
-    store_raw_object(&full_object)
+/// The shallow part of a fetch request: the edge of a shallow repository,
+/// and how far to deepen it
+struct ShallowRequest {
+    edge: Vec<String>,
+    deepen: Option<Deepen>,
 }
 
-/// Store raw object data (with header) in the Git object database
-fn store_raw_object(data: &[u8]) -> io::Result<String> {
-    // Calculate SHA1 hash
-    let mut hasher = Sha1::new();
-    hasher.update(data);
-    let sha = hasher.digest().to_string();
-
-    // Create object directory and file path
-    let dir = format!(".git/objects/{}", &sha[..2]);
-    fs::create_dir_all(&dir)?;
-    let path = format!("{}/{}", dir, &sha[2..]);
-
-    // Compress and write object
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(data)?;
-    let compressed = encoder.finish()?;
+impl ShallowRequest {
+    fn is_empty(&self) -> bool {
+        self.edge.is_empty() && self.deepen.is_none()
+    }
 
-    fs::write(&path, compressed)?;
+    /// What goes on the first want line of a v0 request
+    fn capabilities(&self) -> &'static str {
+        match self.deepen {
+            _ if self.is_empty() => "",
+            Some(Deepen::Relative(_)) => " shallow deepen-relative",
+            _ => " shallow",
+        }
+    }
 
-    Ok(sha)
+    /// The lines after the wants; a v2 request has --deepen's
+    /// deepen-relative among them rather than among the capabilities
+    fn lines(&self, v2: bool) -> Vec<u8> {
+        let mut lines = Vec::new();
+        for sha in &self.edge {
+            lines.extend_from_slice(&encode_pkt_line(&format!("shallow {}\n", sha)));
+        }
+        if let Some(Deepen::Depth(depth) | Deepen::Relative(depth)) = self.deepen {
+            lines.extend_from_slice(&encode_pkt_line(&format!("deepen {}\n", depth)));
+        }
+        if v2 && matches!(self.deepen, Some(Deepen::Relative(_))) {
+            lines.extend_from_slice(&encode_pkt_line("deepen-relative\n"));
+        }
+        lines
+    }
 }
 
-/// Read an object from the Git object database (content only, no header)
-fn read_object_raw(sha: &str) -> io::Result<Vec<u8>> {
-    let path = format!(".git/objects/{}/{}", &sha[..2], &sha[2..]);
-    let compressed = fs::read(&path)?;
-
-    let mut decoder = ZlibDecoder::new(&compressed[..]);
-    let mut decompressed = Vec::new();
-    decoder.read_to_end(&mut decompressed)?;
-
-    // Find null byte to skip header
-    let null_pos = decompressed
-        .iter()
-        .position(|&b| b == 0)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid object format"))?;
-
-    Ok(decompressed[null_pos + 1..].to_vec())
+/// The filter line of a request that filters, after the shallow lines
+fn filter_line(filter: Option<&str>) -> Vec<u8> {
+    match filter {
+        Some(spec) => encode_pkt_line(&format!("filter {}\n", spec)),
+        None => Vec::new(),
+    }
 }
 
-/// Read a complete Git object by SHA (including header)
-fn read_git_object(sha: &str) -> io::Result<Vec<u8>> {
-    let path = format!(".git/objects/{}/{}", &sha[..2], &sha[2..]);
-    let compressed = fs::read(&path)?;
+/// `haves` as have lines, then "done" or a flush
+fn have_lines(haves: &[String], done: bool) -> Vec<u8> {
+    let mut lines = Vec::new();
+    for sha in haves {
+        lines.extend_from_slice(&encode_pkt_line(&format!("have {}\n", sha)));
+    }
+    match done {
+        true => lines.extend_from_slice(&encode_pkt_line("done\n")),
+        false => lines.extend_from_slice(pktline::FLUSH),
+    }
+    lines
+}
 
-    let mut decoder = ZlibDecoder::new(&compressed[..]);
-    let mut decompressed = Vec::new();
-    decoder.read_to_end(&mut decompressed)?;
+/// A v2 fetch command for `wants`, which like `upload_pack_request` says
+/// "done" after `haves` once negotiating is over
+fn fetch_command(
+    wants: &[String],
+    shallow: &ShallowRequest,
+    filter: Option<&str>,
+    haves: &[String],
+    done: bool,
+    no_progress: bool,
+) -> Vec<u8> {
+    let mut request = encode_pkt_line("command=fetch\n");
+    request.extend_from_slice(&encode_pkt_line(&format!("agent={}\n", http::USER_AGENT)));
+    request.extend_from_slice(pktline::DELIM);
+    request.extend_from_slice(&encode_pkt_line("ofs-delta\n"));
+    request.extend_from_slice(&encode_pkt_line("include-tag\n"));
+    if no_progress {
+        request.extend_from_slice(&encode_pkt_line("no-progress\n"));
+    }
+    for sha in wants {
+        request.extend_from_slice(&encode_pkt_line(&format!("want {}\n", sha)));
+    }
+    request.extend_from_slice(&shallow.lines(true));
+    request.extend_from_slice(&filter_line(filter));
+    for sha in haves {
+        request.extend_from_slice(&encode_pkt_line(&format!("have {}\n", sha)));
+    }
+    if done {
+        request.extend_from_slice(&encode_pkt_line("done\n"));
+    }
+    request.extend_from_slice(pktline::FLUSH);
+    request
+}
 
-    Ok(decompressed)
+/// Encode a line in Git's pkt-line format
+/// Format: 4-byte hex length (including the 4 bytes) + data
+fn encode_pkt_line(line: &str) -> Vec<u8> {
+    let len = line.len() + 4;
+    format!("{:04x}{}", len, line).into_bytes()
 }
 
 // ============================================================================
-// FILE CHECKOUT
+// SIDE-BAND PROTOCOL HANDLING
 // ============================================================================
 
-/// Checkout files from the repository
-fn checkout_files(head_sha: &str) -> io::Result<()> {
-    // Read the commit object
-    let commit_data = read_git_object(head_sha)?;
-
-    // Parse commit to find tree SHA
-    let tree_sha = parse_commit_tree(&commit_data)?;
-    println!("Checking out tree {}", tree_sha);
-
-    // Recursively checkout the tree
-    checkout_tree(&tree_sha, Path::new("."))?;
-
-    Ok(())
+/// The pack in an upload-pack response, read packet by packet as it
+/// arrives: the acknowledgements before it (or in v2, the sections before
+/// the packfile section) are skipped, and the side-band it comes over is
+/// taken apart on the fly, band 1 being the pack, band 2 progress messages
+/// and band 3 an error. A v0 server without side-band sends the pack bare
+/// after its acknowledgements. Shallow lines before the pack (in v2, the
+/// shallow-info section) are kept in `shallow`. Reading stops, as an
+/// error, once the operation is cancelled.
+struct PackReader<'p, R> {
+    inner: R,
+    v2: bool,
+    progress: &'p mut Progress,
+    shallow: shallow::Update,
+    framing: Framing,
+    /// Pack data taken from a packet and not yet read
+    pending: Vec<u8>,
+    pos: usize,
 }
 
-/// Parse commit object to extract tree SHA
-fn parse_commit_tree(commit_data: &[u8]) -> io::Result<String> {
-    let commit_str = String::from_utf8_lossy(commit_data);
-
-    // Find the null byte that separates header from content
-    let content_start = commit_data
-        .iter()
-        .position(|&b| b == 0)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid commit format"))?;
-
-    let content = &commit_str[content_start + 1..];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// Still before the pack
+    Preamble,
+    SideBand,
+    Bare,
+    Done,
+}
 
-    // Find tree line
-    for line in content.lines() {
-        if line.starts_with("tree ") {
-            return Ok(line[5..].trim().to_string());
+impl<'p, R: Read> PackReader<'p, R> {
+    fn new(inner: R, v2: bool, shallow: shallow::Update, progress: &'p mut Progress) -> Self {
+        PackReader {
+            inner,
+            v2,
+            progress,
+            shallow,
+            framing: Framing::Preamble,
+            pending: Vec::new(),
+            pos: 0,
         }
     }
 
-    Err(io::Error::new(
-        io::ErrorKind::InvalidData,
-        "No tree found in commit",
-    ))
-}
-
-/// Recursively checkout a tree
-fn checkout_tree(tree_sha: &str, base_path: &Path) -> io::Result<()> {
-    let tree_data = read_git_object(tree_sha)?;
-
-    // Find the null byte that separates header from content
-    let content_start = tree_data
-        .iter()
-        .position(|&b| b == 0)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid tree format"))?;
-
-    let mut offset = content_start + 1;
-
-    while offset < tree_data.len() {
-        // Parse mode and name
-        let space_pos = tree_data[offset..]
-            .iter()
-            .position(|&b| b == b' ')
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid tree entry"))?;
-
-        let mode = String::from_utf8_lossy(&tree_data[offset..offset + space_pos]);
-        offset += space_pos + 1;
-
-        let null_pos = tree_data[offset..]
-            .iter()
-            .position(|&b| b == 0)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid tree entry name"))?;
-
-        let name = String::from_utf8_lossy(&tree_data[offset..offset + null_pos]);
-        offset += null_pos + 1;
-
-        // Read 20-byte SHA
-        if offset + 20 > tree_data.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid tree entry SHA",
-            ));
+    /// Read up to where the pack starts
+    fn read_preamble(&mut self) -> io::Result<()> {
+        while self.framing == Framing::Preamble {
+            self.next_packet()?;
         }
+        Ok(())
+    }
 
-        let sha = hex::encode(&tree_data[offset..offset + 20]);
-        offset += 20;
-
-        let entry_path = base_path.join(name.as_ref());
-
-        if mode == "40000" {
-            // Directory
-            fs::create_dir_all(&entry_path)?;
-            checkout_tree(&sha, &entry_path)?;
-        } else {
-            // File
-            let blob_data = read_git_object(&sha)?;
-
-            // Find the null byte that separates header from content
-            let blob_content_start = blob_data
-                .iter()
-                .position(|&b| b == 0)
-                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid blob format"))?;
-
-            let content = &blob_data[blob_content_start + 1..];
-
-            // Ensure parent directory exists
-            if let Some(parent) = entry_path.parent() {
-                fs::create_dir_all(parent)?;
+    /// Read the next packet into `pending`, or what it says about the
+    /// conversation into `framing`
+    fn next_packet(&mut self) -> io::Result<()> {
+        let mut header = [0u8; 4];
+        if let Err(e) = self.inner.read_exact(&mut header) {
+            return match (e.kind(), self.framing) {
+                (io::ErrorKind::UnexpectedEof, Framing::SideBand) => {
+                    self.framing = Framing::Done;
+                    Ok(())
+                }
+                (io::ErrorKind::UnexpectedEof, _) if self.v2 => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected a packfile section in the fetch response",
+                )),
+                (io::ErrorKind::UnexpectedEof, _) => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the remote end hung up unexpectedly",
+                )),
+                _ => Err(e),
+            };
+        }
+        if self.framing == Framing::Preamble && !self.v2 && &header == b"PACK" {
+            self.framing = Framing::Bare;
+            self.pending = header.to_vec();
+            self.pos = 0;
+            return Ok(());
+        }
+        let len = std::str::from_utf8(&header)
+            .ok()
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+            .filter(|&len| len <= 1 || (4..=65520).contains(&len))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "protocol error: bad line length {}",
+                        String::from_utf8_lossy(&header)
+                    ),
+                )
+            })?;
+        if len <= 1 {
+            // A flush ends the pack; before it, flushes and delimiters
+            // only end sections being skipped
+            if self.framing == Framing::SideBand {
+                self.framing = Framing::Done;
             }
+            return Ok(());
+        }
+        let mut payload = vec![0u8; len - 4];
+        self.inner.read_exact(&mut payload)?;
+        if self.framing == Framing::Preamble {
+            if self.v2 && payload == b"packfile\n" {
+                self.framing = Framing::SideBand;
+                return Ok(());
+            }
+            // Acknowledgements and shallow lines are text; the side-band
+            // starts with a band number
+            if self.v2 || !matches!(payload.first(), Some(1..=3)) {
+                let line = String::from_utf8_lossy(&payload);
+                self.shallow.parse_line(line.trim_end());
+                return Ok(());
+            }
+            self.framing = Framing::SideBand;
+        }
+        // A keepalive is a band 1 packet with no data, or no band at all
+        match payload.split_first() {
+            Some((&pktline::BAND_DATA, _)) => {
+                payload.remove(0);
+                self.pending = payload;
+                self.pos = 0;
+            }
+            Some((2, message)) => {
+                self.progress.report(&format!(
+                    "Git progress: {}",
+                    String::from_utf8_lossy(message).trim()
+                ));
+            }
+            Some((3, message)) => {
+                return Err(io::Error::other(format!(
+                    "remote error: {}",
+                    String::from_utf8_lossy(message).trim_end()
+                )))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
 
-            fs::write(&entry_path, content)?;
-
-            // Set executable permission if needed (Unix-like systems only)
-            #[cfg(unix)]
-            {
-                if mode == "100755" {
-                    let mut perms = fs::metadata(&entry_path)?.permissions();
-                    perms.set_mode(0o755);
-                    fs::set_permissions(&entry_path, perms)?;
-                }
+impl<R: Read> Read for PackReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.pending.len() {
+            self.progress.check()?;
+            match self.framing {
+                Framing::Done => return Ok(0),
+                Framing::Bare => return self.inner.read(buf),
+                Framing::Preamble | Framing::SideBand => self.next_packet()?,
             }
         }
+        let n = (self.pending.len() - self.pos).min(buf.len());
+        buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
     }
+}
+// ============================================================================
+// FILE CHECKOUT
+// ============================================================================
 
-    Ok(())
+/// Checkout files from the repository
+fn checkout_files(head_sha: &str, progress: &mut Progress) -> io::Result<()> {
+    let tree_sha = commit::read(head_sha)?.tree;
+    progress.report(&format!("Checking out tree {}", tree_sha));
+
+    // The update checks every path before the first file is written, so a
+    // tree reaching outside the clone or into .git fails it cleanly, and
+    // stages the files so the clone starts out clean
+    let files = tree::flatten(&tree_sha)?;
+    worktree::update_with_progress(&Snapshot::new(), &files, true, progress)
 }
@@ -0,0 +1,184 @@
+// commit: record the index as a new commit on the current branch
+//
+// commit [--no-verify] [--allow-empty] [--dry-run] [--date <date>] -m <msg>
+// commit [--no-verify] [--allow-empty] [--dry-run] [--date <date>]
+//        (--fixup | --squash) <commit> [-m <msg>]
+//
+// The commit's tree is the index and its parent HEAD, if HEAD has a commit
+// yet. --fixup <commit> makes a commit whose message is "fixup! <subject of
+// commit>", and --squash <commit> one whose message is "squash! <subject>"
+// followed by the -m message; -m after --fixup adds a body the same way.
+// `rebase --autosquash` folds such commits into the commit they name. The
+// commit-msg hook vets the message unless --no-verify is given.
+// --dry-run lists the changes that would be committed, without running the
+// hook or committing. --date sets the author date, in any form
+// GIT_AUTHOR_DATE takes (see git/date.rs), and wins over it.
+
+use crate::git::commit;
+use crate::git::ident::{self, Role};
+use crate::git::index::Index;
+use crate::git::refs::{self, Head};
+use crate::git::{date, dry_run, hooks, revision, tag, tree};
+use std::io;
+use std::path::Path;
+
+const USAGE: &str = "Usage: commit [--no-verify] [--allow-empty] [--dry-run] [--date <date>] \
+                     [--fixup <commit> | --squash <commit>] [-m <msg>]";
+
+/// What a --fixup or --squash commit is for
+enum Target {
+    Fixup(String),
+    Squash(String),
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut message = None;
+    let mut target = None;
+    let mut verify = true;
+    let mut allow_empty = false;
+    let mut author_date = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" | "--message" => message = Some(args.next().ok_or_else(usage)?.clone()),
+            "--fixup" => target = Some(Target::Fixup(args.next().ok_or_else(usage)?.clone())),
+            "--squash" => target = Some(Target::Squash(args.next().ok_or_else(usage)?.clone())),
+            "--no-verify" | "-n" => verify = false,
+            "--allow-empty" => allow_empty = true,
+            "--dry-run" => dry_run::enable(),
+            "--date" => author_date = Some(parse_date(args.next().ok_or_else(usage)?)?),
+            arg if arg.starts_with("--date=") => {
+                author_date = Some(parse_date(&arg["--date=".len()..])?)
+            }
+            arg if arg.starts_with("--fixup=") => {
+                target = Some(Target::Fixup(arg["--fixup=".len()..].to_string()))
+            }
+            arg if arg.starts_with("--squash=") => {
+                target = Some(Target::Squash(arg["--squash=".len()..].to_string()))
+            }
+            _ => return Err(usage()),
+        }
+    }
+    // Unresolved conflicts are reported first, as git does
+    let index = Index::load()?;
+    if index.entries().iter().any(|entry| entry.stage != 0) {
+        return Err(io::Error::other(
+            "Committing is not possible because you have unmerged files.",
+        ));
+    }
+    if Path::new(".git/MERGE_HEAD").exists() {
+        return Err(io::Error::other(
+            "You have not concluded your merge (MERGE_HEAD exists).\nUse 'merge --continue' to commit it.",
+        ));
+    }
+
+    let message = match (&target, message) {
+        (None, Some(message)) => message,
+        (None, None) => return Err(usage()),
+        (Some(Target::Fixup(name)), body) => with_body(format!("fixup! {}", subject(name)?), body),
+        (Some(Target::Squash(name)), body) => {
+            with_body(format!("squash! {}", subject(name)?), body)
+        }
+    };
+
+    let tree_sha = tree::write_snapshot(&index.snapshot())?;
+    let parent = refs::head_commit()?;
+    let unchanged = match &parent {
+        Some(parent) => commit::read(parent)?.tree == tree_sha,
+        None => index.entries().is_empty(),
+    };
+    if unchanged && !allow_empty {
+        return Err(io::Error::other("nothing to commit"));
+    }
+    if dry_run::is_enabled() {
+        let parent_tree = match &parent {
+            Some(parent) => Some(commit::read(parent)?.tree),
+            None => None,
+        };
+        println!("Changes to be committed:");
+        for change in tree::diff(parent_tree.as_deref(), Some(&tree_sha), true)? {
+            let label = match (&change.old, &change.new) {
+                (None, _) => "new file:",
+                (_, None) => "deleted:",
+                _ => "modified:",
+            };
+            println!("\t{:<12}{}", label, change.path);
+        }
+        return Ok(());
+    }
+
+    let message = if verify {
+        hooks::check_commit_message(&message)?
+    } else {
+        message
+    };
+    let parents: Vec<String> = parent.iter().cloned().collect();
+    let sha = match author_date {
+        Some(date) => {
+            let author = format!("{} {}", ident::identity(Role::Author)?, date);
+            commit::create_with_author(&tree_sha, &parents, &author, &message)?
+        }
+        None => commit::create(&tree_sha, &parents, &message)?,
+    };
+    let summary = message.lines().next().unwrap_or("");
+    let action = if parents.is_empty() {
+        "commit (initial)"
+    } else {
+        "commit"
+    };
+    refs::update_head(&sha, &format!("{}: {}", action, summary))?;
+
+    let branch = match refs::read_head()? {
+        Head::Branch(name) => name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&name)
+            .to_string(),
+        Head::Detached(_) => "detached HEAD".to_string(),
+    };
+    let root = if parents.is_empty() {
+        " (root-commit)"
+    } else {
+        ""
+    };
+    println!("[{}{} {}] {}", branch, root, &sha[..7], summary);
+    Ok(())
+}
+
+/// The subject line of the commit `name` resolves to
+fn subject(name: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("could not lookup commit '{}'", name),
+        )
+    })?;
+    let (sha, kind) = tag::peel(&sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a commit", name, kind),
+        ));
+    }
+    Ok(commit::read(&sha)?.summary().to_string())
+}
+
+fn with_body(header: String, body: Option<String>) -> String {
+    match body {
+        Some(body) => format!("{}\n\n{}", header, body),
+        None => header,
+    }
+}
+
+/// A --date value as `<epoch> <+hhmm>`
+fn parse_date(text: &str) -> io::Result<String> {
+    date::parse(text).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid date format: {}", text),
+        )
+    })
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
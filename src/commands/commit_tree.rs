@@ -1,8 +1,8 @@
-use chrono::Local;
-use sha1_smol::Sha1;
 use std::io::{self, Write};
 
-use crate::git::object;
+use crate::git::commit;
+use crate::git::object::{self, Error};
+use crate::git::revision;
 
 // ./your_program.sh commit-tree <tree_sha> -p <commit_sha> -m <message>
 //
@@ -18,66 +18,26 @@ use crate::git::object;
 // parent 20d0f3ed7f014a71c4fa2f9303c90f109def9e06
 // author Muhammad Sultan Altamash Ali <altamashattari786@gmail.com> 1756208876 +0530
 // committer Muhammad Sultan Altamash Ali <altamashattari786@gmail.com> 1756208876 +0530
+//
+// The author and committer are the configured identity (see git::ident).
+// The tree and the parent may be given as any revision expression, such as
+// HEAD^{tree} and HEAD, but must name a tree and a commit.
 
 // fix mode for directory
 //
-const DEFAULT_USERNAME: &str = "Muhammad Sultan Altamash Ali";
-const DEFAULT_EMAIL: &str = "altamashattari786@gmail.com";
 
 pub fn run(args: &[String]) -> io::Result<()> {
-    let (tree_sha, parent_commit, commit_message) = parse_args(args)?;
-
-    let mut content = Vec::new();
-
-    // Add the tree SHA to the content
-    content.extend_from_slice(format!("tree {}", tree_sha).as_bytes());
-    content.push(b'\n');
-
-    // Add the parent commit SHA to the content
-    if let Some(parent) = parent_commit {
-        content.extend_from_slice(format!("parent {}", parent).as_bytes());
-        content.push(b'\n');
-    } else {
-        // do nothing
-    }
-
-    let current_timestamp = format_current_timestamp();
-
-    // Add author info
-    let formatted_author = format!(
-        "author {} <{}> {}",
-        DEFAULT_USERNAME, DEFAULT_EMAIL, current_timestamp
-    );
-    content.extend_from_slice(formatted_author.as_bytes());
-    content.push(b'\n');
-
-    // Add committer info
-    let formatted_committer = format!(
-        "committer {} <{}> {}",
-        DEFAULT_USERNAME, DEFAULT_EMAIL, current_timestamp
-    );
-    content.extend_from_slice(formatted_committer.as_bytes());
-    content.push(b'\n');
-
-    // Add commit message
-    content.push(b'\n');
-    content.extend_from_slice(commit_message.as_bytes());
-    content.push(b'\n');
-
-    let header = format!("commit {}\0", content.len());
-    let header_bytes = header.as_bytes();
-
-    let mut store = Vec::with_capacity(header_bytes.len() + content.len());
-    store.extend_from_slice(header_bytes);
-    store.extend_from_slice(&content);
-
-    // Compute SHA-1 hash
-    let mut hasher = Sha1::new();
-    hasher.update(&store);
-    let commit_hash = hasher.digest().to_string();
-
-    object::write_blob(&store, &commit_hash).map_err(|e| {
-        io::Error::new(io::ErrorKind::Other, format!("Error writing tree: {:?}", e))
+    let (tree, parent_commit, commit_message) = parse_args(args)?;
+
+    let tree_sha = resolve(tree, "tree")?;
+    let parents = parent_commit
+        .map(|parent| resolve(parent, "commit"))
+        .into_iter()
+        .collect::<io::Result<Vec<String>>>()?;
+    // A missing identity is reported as it is; anything else failed writing
+    let commit_hash = commit::create(&tree_sha, &parents, commit_message).map_err(|e| match e {
+        Error::Io(e) => e,
+        e => io::Error::other(format!("Error writing commit: {:?}", e)),
     })?;
 
     io::stdout().write_all(commit_hash.as_bytes())?;
@@ -86,6 +46,24 @@ pub fn run(args: &[String]) -> io::Result<()> {
     Ok(())
 }
 
+/// The object `name` resolves to, which must be a `kind`
+fn resolve(name: &str, kind: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Not a valid object name {}", name),
+        )
+    })?;
+    let (found, _) = object::read(&sha)?;
+    if found != kind {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a valid '{}' object", sha, kind),
+        ));
+    }
+    Ok(sha)
+}
+
 fn parse_args(args: &[String]) -> io::Result<(&str, Option<&str>, &str)> {
     if args.len() < 3 || args.len() > 5 {
         return Err(io::Error::new(
@@ -96,7 +74,6 @@ fn parse_args(args: &[String]) -> io::Result<(&str, Option<&str>, &str)> {
 
     let tree_sha = &args[0];
     let mut parent_commit = None;
-    let mut commit_message = None;
 
     let mut i = 1;
     if i < args.len() && args[i] == "-p" {
@@ -117,18 +94,12 @@ fn parse_args(args: &[String]) -> io::Result<(&str, Option<&str>, &str)> {
                 "Missing commit message after -m",
             ));
         }
-        commit_message = Some(args[i + 1].as_str());
+        let commit_message = args[i + 1].as_str();
+        Ok((tree_sha, parent_commit, commit_message))
     } else {
-        return Err(io::Error::new(
+        Err(io::Error::new(
             io::ErrorKind::InvalidInput,
             "Missing -m flag or commit message",
-        ));
+        ))
     }
-
-    Ok((tree_sha, parent_commit, commit_message.unwrap()))
-}
-
-fn format_current_timestamp() -> String {
-    let now_local = Local::now();
-    format!("{} {:+05}", now_local.timestamp(), now_local.offset())
 }
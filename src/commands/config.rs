@@ -0,0 +1,295 @@
+// config: read and change configuration values
+//
+// config [<scope>] [--show-origin] (-l | --list)
+// config [<scope>] [--show-origin] [--type=<type>] [--get | --get-all] <key>
+// config [<scope>] [--type=<type>] [--add] <key> <value>
+// config [<scope>] (--unset | --unset-all) <key>
+//
+// <scope> is --local (.git/config), --global (~/.gitconfig, or the XDG
+// file $XDG_CONFIG_HOME/git/config if only that exists) or -f <file>.
+// Reading without a scope sees every file, later ones winning; writing
+// without one changes .git/config.
+//
+// With just <key> the value is printed, the last one if there are several;
+// --get-all prints them all. A key that isn't set exits with status 1.
+// <key> <value> replaces the key's value (the only one: use --add for
+// another value of a multi-valued key). --unset removes a key that has a
+// single value and --unset-all every value; removing a key that isn't set
+// exits with status 5. --list prints every "key=value", with
+// --show-origin giving "file:<path>" and a tab before each.
+//
+// --type=<type> (bool, int, path or color; --bool, --int and --path for
+// short) reads values as that type: getting prints them the way git
+// settles on, "true" or "false", the number with its k/m/g suffix worked
+// out, the path with `~/` expanded or the color's escape sequence, and a
+// value that isn't of the type is an error. Setting checks the value and
+// writes a boolean or an integer in that settled form.
+
+use crate::git::config::{self, Config, Entry};
+use crate::git::repository;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "Usage: config [--local | --global | -f <file>] [--show-origin] \
+                     [--type=<bool|int|path|color>] \
+                     ([--get | --get-all] <key> | [--add] <key> <value> | \
+                     --unset[-all] <key> | -l)";
+
+/// What `--type` reads values as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Bool,
+    Int,
+    Path,
+    Color,
+}
+
+impl Type {
+    fn parse(name: &str) -> io::Result<Type> {
+        match name {
+            "bool" => Ok(Type::Bool),
+            "int" => Ok(Type::Int),
+            "path" => Ok(Type::Path),
+            "color" => Ok(Type::Color),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unrecognized --type argument, {}", name),
+            )),
+        }
+    }
+
+    /// `value` of `key` as it is printed
+    fn get(self, key: &str, value: &str) -> io::Result<String> {
+        match self {
+            Type::Bool => config::parse_bool(key, value).map(|b| b.to_string()),
+            Type::Int => config::parse_int(key, value).map(|n| n.to_string()),
+            Type::Path => Ok(config::expand_path(value).display().to_string()),
+            Type::Color => config::parse_color(key, value),
+        }
+    }
+
+    /// `value` of `key` as it is written
+    fn set(self, key: &str, value: &str) -> io::Result<String> {
+        match self {
+            Type::Path => Ok(value.to_string()),
+            Type::Color => config::parse_color(key, value).map(|_| value.to_string()),
+            Type::Bool | Type::Int => self.get(key, value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Get,
+    GetAll,
+    Set,
+    Add,
+    Unset,
+    UnsetAll,
+    List,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut file: Option<PathBuf> = None;
+    let mut show_origin = false;
+    let mut value_type = None;
+    let mut action = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let chosen = match arg.as_str() {
+            "--local" => {
+                file = Some(repository::path("config"));
+                None
+            }
+            "--global" => {
+                file =
+                    Some(config::global_path().ok_or_else(|| io::Error::other("$HOME not set"))?);
+                None
+            }
+            "-f" | "--file" => {
+                file = Some(PathBuf::from(args.next().ok_or_else(usage)?));
+                None
+            }
+            "--show-origin" => {
+                show_origin = true;
+                None
+            }
+            "--type" => {
+                value_type = Some(Type::parse(args.next().ok_or_else(usage)?)?);
+                None
+            }
+            arg if arg.starts_with("--type=") => {
+                value_type = Some(Type::parse(&arg["--type=".len()..])?);
+                None
+            }
+            "--bool" => {
+                value_type = Some(Type::Bool);
+                None
+            }
+            "--int" => {
+                value_type = Some(Type::Int);
+                None
+            }
+            "--path" => {
+                value_type = Some(Type::Path);
+                None
+            }
+            "--get" => Some(Action::Get),
+            "--get-all" => Some(Action::GetAll),
+            "--add" => Some(Action::Add),
+            "--unset" => Some(Action::Unset),
+            "--unset-all" => Some(Action::UnsetAll),
+            "-l" | "--list" => Some(Action::List),
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => {
+                positional.push(arg);
+                None
+            }
+        };
+        if let Some(chosen) = chosen {
+            if action.is_some_and(|action| action != chosen) {
+                return Err(usage());
+            }
+            action = Some(chosen);
+        }
+    }
+    let action = match action {
+        Some(action) => action,
+        None if positional.len() == 2 => Action::Set,
+        None => Action::Get,
+    };
+
+    match (action, positional.as_slice()) {
+        (Action::List, []) => list(&load(file.as_deref())?, show_origin),
+        (Action::Get, [key]) | (Action::GetAll, [key]) => {
+            let config = load(file.as_deref())?;
+            let key = check_key(key)?;
+            let mut values: Vec<&Entry> = config
+                .entries()
+                .iter()
+                .filter(|entry| entry.key() == key)
+                .collect();
+            if values.is_empty() {
+                process::exit(1);
+            }
+            if action == Action::Get {
+                values.drain(..values.len() - 1);
+            }
+            let mut out = BufWriter::new(io::stdout().lock());
+            for entry in values {
+                if show_origin {
+                    write!(out, "{}\t", origin(entry))?;
+                }
+                let value = entry.value.as_deref().unwrap_or("true");
+                match value_type {
+                    Some(value_type) => writeln!(out, "{}", value_type.get(&key, value)?)?,
+                    None => writeln!(out, "{}", value)?,
+                }
+            }
+            out.flush()
+        }
+        (Action::Set, [key, value]) | (Action::Add, [key, value]) => {
+            check_key(key)?;
+            let value = &match value_type {
+                Some(value_type) => value_type.set(key, value)?,
+                None => value.to_string(),
+            };
+            let path = writable(file)?;
+            if action == Action::Add {
+                config::add_value(&path, key, value)
+            } else {
+                let existing = load(Some(&path))?.get_all(key).len();
+                if existing > 1 {
+                    eprintln!("warning: {} has multiple values", key);
+                    return Err(io::Error::other(format!(
+                        "cannot overwrite multiple values with a single value\n       Use --add or --unset-all to change {}.",
+                        key
+                    )));
+                }
+                config::set_value(&path, key, value)
+            }
+        }
+        (Action::Unset, [key]) | (Action::UnsetAll, [key]) => {
+            check_key(key)?;
+            let path = writable(file)?;
+            match config::unset_value(&path, key, action == Action::UnsetAll)? {
+                0 => process::exit(5),
+                1 => Ok(()),
+                _ if action == Action::UnsetAll => Ok(()),
+                _ => {
+                    eprintln!("warning: {} has multiple values", key);
+                    process::exit(5)
+                }
+            }
+        }
+        _ => Err(usage()),
+    }
+}
+
+/// Every config file, or only `file`
+fn load(file: Option<&Path>) -> io::Result<Config> {
+    match file {
+        Some(file) => {
+            let mut config = Config::default();
+            config.read_file(file)?;
+            Ok(config)
+        }
+        None => Config::load(),
+    }
+}
+
+/// The file a change goes to, the repository's config unless a scope was
+/// given
+fn writable(file: Option<PathBuf>) -> io::Result<PathBuf> {
+    if let Some(file) = file {
+        return Ok(file);
+    }
+    if !repository::git_dir().is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "not in a git directory",
+        ));
+    }
+    Ok(repository::path("config"))
+}
+
+/// `key` as entries store it, if it has a section and a name
+fn check_key(key: &str) -> io::Result<String> {
+    let Some(first) = key.find('.') else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("key does not contain a section: {}", key),
+        ));
+    };
+    if first == 0 || key.ends_with('.') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid key: {}", key),
+        ));
+    }
+    Ok(config::normalize_key(key))
+}
+
+fn list(config: &Config, show_origin: bool) -> io::Result<()> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    for entry in config.entries() {
+        if show_origin {
+            write!(out, "{}\t", origin(entry))?;
+        }
+        match &entry.value {
+            Some(value) => writeln!(out, "{}={}", entry.key(), value)?,
+            None => writeln!(out, "{}", entry.key())?,
+        }
+    }
+    out.flush()
+}
+
+fn origin(entry: &Entry) -> String {
+    format!("file:{}", entry.origin.display())
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
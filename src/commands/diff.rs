@@ -0,0 +1,403 @@
+// diff: show changes between the working tree, the index and commits
+//
+// diff [<options>] [--] [<path>...]                 working tree against the index
+// diff [<options>] --cached [<commit>] [--] [<path>...]
+//                                                   index against <commit> (HEAD)
+// diff [<options>] <commit> [--] [<path>...]        working tree against <commit>
+//
+// Options: -U<n> / --unified=<n> sets the lines of context (3 by default),
+// --name-only and --name-status list the changed paths instead. Output is
+// git's: a "diff --git" header per file, mode and index lines, then the
+// unified hunks. Untracked files are not shown.
+
+use crate::commands::submodule as submodule_cmd;
+use crate::git::index::Index;
+use crate::git::tree::{self, FileEntry};
+use crate::git::worktree::{self, Snapshot};
+use crate::git::{diff, object, refs, revision, tag};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+const USAGE: &str =
+    "Usage: diff [-U<n>] [--name-only | --name-status] [--cached] [<commit>] [--] [<path>...]";
+
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Patch,
+    NameOnly,
+    NameStatus,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut context = 3;
+    let mut format = Format::Patch;
+    let mut cached = false;
+    let mut positional = Vec::new();
+    let mut pathspecs = Vec::new();
+
+    let mut options_done = false;
+    for arg in args {
+        match arg.as_str() {
+            arg if options_done => pathspecs.push(normalize(arg)),
+            "--" => options_done = true,
+            "--cached" | "--staged" => cached = true,
+            "--name-only" => format = Format::NameOnly,
+            "--name-status" => format = Format::NameStatus,
+            arg if arg.starts_with("-U") || arg.starts_with("--unified=") => {
+                let value = arg.strip_prefix("--unified=").unwrap_or_else(|| &arg[2..]);
+                context = value
+                    .parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => positional.push(arg),
+        }
+    }
+
+    // A leading revision names the commit; anything else is a path
+    let mut commit = None;
+    if let Some(first) = positional.first() {
+        if let Some(sha) = revision::resolve(first)? {
+            commit = Some(resolve_commit(first, &sha)?);
+            positional.remove(0);
+        }
+    }
+    if !positional.is_empty() && !options_done {
+        if let Some(missing) = positional.iter().find(|p| fs::symlink_metadata(p).is_err()) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "ambiguous argument '{}': unknown revision or path not in the working tree.",
+                    missing
+                ),
+            ));
+        }
+    }
+    pathspecs.extend(positional.iter().map(|p| normalize(p)));
+
+    let index = Index::load()?;
+    let unmerged: BTreeSet<String> = index
+        .entries()
+        .iter()
+        .filter(|e| e.stage != 0)
+        .map(|e| e.path.clone())
+        .collect();
+    let staged: Snapshot = index
+        .entries()
+        .iter()
+        .filter(|e| e.stage == 0)
+        .map(|e| (e.path.clone(), e.file()))
+        .collect();
+
+    let pairs = if cached {
+        let commit = match commit {
+            Some(commit) => Some(commit),
+            None => refs::head_commit()?,
+        };
+        let old = worktree::snapshot_of_commit(commit.as_deref())?;
+        compare(&old, &staged, &unmerged, &pathspecs, |_, file| {
+            Side::from_object(file)
+        })?
+    } else {
+        // Against a commit, paths dropped from the index count as deleted
+        let old = match &commit {
+            Some(commit) => worktree::snapshot_of_commit(Some(commit))?,
+            None => staged.clone(),
+        };
+        compare(&old, &staged, &unmerged, &pathspecs, |path, file| {
+            Side::from_worktree(path, file)
+        })?
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for pair in &pairs {
+        match format {
+            Format::NameOnly => writeln!(out, "{}", pair.path)?,
+            Format::NameStatus => writeln!(out, "{}\t{}", pair.status(), pair.path)?,
+            Format::Patch => out.write_all(&pair.patch(context)?)?,
+        }
+    }
+    Ok(())
+}
+
+/// The patch taking tree `old` (`None` for an empty tree) to tree `new`,
+/// as `diff` prints it between two commits, for the paths matching
+/// `pathspecs` (all of them, for none)
+pub fn tree_patch(
+    old: Option<&str>,
+    new: &str,
+    context: usize,
+    pathspecs: &[String],
+) -> io::Result<Vec<u8>> {
+    let old = match old {
+        Some(sha) => tree::flatten(sha)?,
+        None => Snapshot::new(),
+    };
+    let new = tree::flatten(new)?;
+    let pairs = compare(&old, &new, &BTreeSet::new(), pathspecs, |_, file| {
+        Side::from_object(file)
+    })?;
+    let mut out = Vec::new();
+    for pair in &pairs {
+        out.extend(pair.patch(context)?);
+    }
+    Ok(out)
+}
+
+/// One side of a changed path: mode and id, the id computed from the file
+/// when the side is the working tree
+struct Side {
+    mode: String,
+    sha: String,
+    content: Option<Vec<u8>>,
+}
+
+impl Side {
+    fn from_object(file: &FileEntry) -> io::Result<Option<Side>> {
+        Ok(Some(Side {
+            mode: file.mode.clone(),
+            sha: file.sha.clone(),
+            content: None,
+        }))
+    }
+
+    /// The file on disk at `path`, tracked as `staged`; `None` if it is gone
+    fn from_worktree(path: &str, staged: &FileEntry) -> io::Result<Option<Side>> {
+        let meta = match fs::symlink_metadata(path) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        if staged.mode == tree::MODE_GITLINK && meta.is_dir() {
+            // A submodule that isn't checked out is taken as unchanged
+            let sha =
+                submodule_cmd::checked_out_commit(path)?.unwrap_or_else(|| staged.sha.clone());
+            return Side::from_object(&FileEntry {
+                mode: staged.mode.clone(),
+                sha,
+            });
+        }
+
+        let (mode, content) = if meta.file_type().is_symlink() {
+            let target = fs::read_link(path)?;
+            (
+                tree::MODE_SYMLINK.to_string(),
+                target.to_string_lossy().into_owned().into_bytes(),
+            )
+        } else if meta.is_file()
+            && staged.mode == tree::MODE_SYMLINK
+            && !worktree::symlinks_enabled()?
+        {
+            // A symlink checked out as a plain file holding its target
+            (tree::MODE_SYMLINK.to_string(), fs::read(path)?)
+        } else if meta.is_file() {
+            (file_mode(&meta).to_string(), fs::read(path)?)
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(Side {
+            sha: object::hash("blob", &content),
+            mode,
+            content: Some(content),
+        }))
+    }
+
+    fn content(&self) -> io::Result<Vec<u8>> {
+        if let Some(content) = &self.content {
+            return Ok(content.clone());
+        }
+        if self.mode == tree::MODE_GITLINK {
+            return Ok(format!("Subproject commit {}\n", self.sha).into_bytes());
+        }
+        Ok(object::read_expecting(&self.sha, "blob")?)
+    }
+}
+
+fn file_mode(meta: &fs::Metadata) -> &'static str {
+    #[cfg(unix)]
+    {
+        if meta.permissions().mode() & 0o111 != 0 {
+            return tree::MODE_EXECUTABLE;
+        }
+    }
+    tree::MODE_FILE
+}
+
+struct FilePair {
+    path: String,
+    old: Option<Side>,
+    new: Option<Side>,
+    unmerged: bool,
+}
+
+/// Pair up every path of `old` and `staged` matching `pathspecs`, reading
+/// the new side of each with `new_side`, and keep the ones that differ
+fn compare(
+    old: &Snapshot,
+    staged: &Snapshot,
+    unmerged: &BTreeSet<String>,
+    pathspecs: &[String],
+    new_side: impl Fn(&str, &FileEntry) -> io::Result<Option<Side>>,
+) -> io::Result<Vec<FilePair>> {
+    let paths: BTreeSet<&String> = old.keys().chain(staged.keys()).chain(unmerged).collect();
+
+    let mut pairs = Vec::new();
+    for path in paths {
+        if !matches_pathspecs(pathspecs, path) {
+            continue;
+        }
+        if unmerged.contains(path) {
+            pairs.push(FilePair {
+                path: path.clone(),
+                old: None,
+                new: None,
+                unmerged: true,
+            });
+            continue;
+        }
+        let old = match old.get(path) {
+            Some(file) => Side::from_object(file)?,
+            None => None,
+        };
+        let new = match staged.get(path) {
+            Some(file) => new_side(path, file)?,
+            None => None,
+        };
+        let same = match (&old, &new) {
+            (Some(a), Some(b)) => a.mode == b.mode && a.sha == b.sha,
+            (None, None) => true,
+            _ => false,
+        };
+        if !same {
+            pairs.push(FilePair {
+                path: path.clone(),
+                old,
+                new,
+                unmerged: false,
+            });
+        }
+    }
+    Ok(pairs)
+}
+
+impl FilePair {
+    fn status(&self) -> char {
+        match (&self.old, &self.new) {
+            _ if self.unmerged => 'U',
+            (None, _) => 'A',
+            (_, None) => 'D',
+            (Some(a), Some(b)) if !same_type(&a.mode, &b.mode) => 'T',
+            _ => 'M',
+        }
+    }
+
+    fn patch(&self, context: usize) -> io::Result<Vec<u8>> {
+        if self.unmerged {
+            return Ok(format!("* Unmerged path {}\n", self.path).into_bytes());
+        }
+        match (&self.old, &self.new) {
+            // A change of type (file to symlink, ...) is shown as a
+            // deletion followed by an addition
+            (Some(a), Some(b)) if !same_type(&a.mode, &b.mode) => {
+                let mut out = self.render(Some(a), None, context)?;
+                out.extend(self.render(None, Some(b), context)?);
+                Ok(out)
+            }
+            (old, new) => self.render(old.as_ref(), new.as_ref(), context),
+        }
+    }
+
+    fn render(
+        &self,
+        old: Option<&Side>,
+        new: Option<&Side>,
+        context: usize,
+    ) -> io::Result<Vec<u8>> {
+        let path = &self.path;
+        let mut header = format!("diff --git a/{} b/{}\n", path, path);
+        let old_sha = old.map_or(NULL_SHA, |side| side.sha.as_str());
+        let new_sha = new.map_or(NULL_SHA, |side| side.sha.as_str());
+        let mut index_line = format!("index {}..{}", &old_sha[..7], &new_sha[..7]);
+        match (old, new) {
+            (Some(a), Some(b)) if a.mode != b.mode => {
+                header.push_str(&format!("old mode {}\nnew mode {}\n", a.mode, b.mode));
+            }
+            (Some(a), Some(_)) => index_line.push_str(&format!(" {}", a.mode)),
+            (None, Some(b)) => header.push_str(&format!("new file mode {}\n", b.mode)),
+            (Some(a), None) => header.push_str(&format!("deleted file mode {}\n", a.mode)),
+            (None, None) => {}
+        }
+        if old_sha == new_sha {
+            return Ok(header.into_bytes());
+        }
+        header.push_str(&index_line);
+        header.push('\n');
+
+        let old_content = old.map(Side::content).transpose()?.unwrap_or_default();
+        let new_content = new.map(Side::content).transpose()?.unwrap_or_default();
+        let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{}", path));
+        let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{}", path));
+
+        let mut out = header.into_bytes();
+        if diff::is_binary(&old_content) || diff::is_binary(&new_content) {
+            out.extend(format!("Binary files {} and {} differ\n", old_name, new_name).into_bytes());
+            return Ok(out);
+        }
+        let hunks = diff::unified(&old_content, &new_content, context);
+        if !hunks.is_empty() {
+            out.extend(format!("--- {}\n+++ {}\n", old_name, new_name).into_bytes());
+            out.extend(hunks);
+        }
+        Ok(out)
+    }
+}
+
+/// Regular files of either mode are the same type; symlinks and gitlinks
+/// are each their own
+fn same_type(a: &str, b: &str) -> bool {
+    let is_file = |mode: &str| mode == tree::MODE_FILE || mode == tree::MODE_EXECUTABLE;
+    a == b || (is_file(a) && is_file(b))
+}
+
+fn resolve_commit(name: &str, sha: &str) -> io::Result<String> {
+    let (sha, kind) = tag::peel(sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a commit", name, kind),
+        ));
+    }
+    Ok(sha)
+}
+
+/// A path matches an exact pathspec, a directory above it, or a glob;
+/// no pathspecs match everything
+fn matches_pathspecs(pathspecs: &[String], path: &str) -> bool {
+    pathspecs.is_empty()
+        || pathspecs.iter().any(|spec| {
+            spec.is_empty()
+                || path == spec
+                || path.starts_with(&format!("{}/", spec))
+                || refs::glob_match(spec, path)
+        })
+}
+
+/// Pathspecs are relative to the top of the repository
+fn normalize(spec: &str) -> String {
+    let spec = spec.trim_start_matches("./").trim_end_matches('/');
+    if spec == "." {
+        String::new()
+    } else {
+        spec.to_string()
+    }
+}
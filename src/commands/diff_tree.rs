@@ -0,0 +1,172 @@
+// diff-tree: compare the content and mode of blobs found via two tree objects
+//
+// diff-tree [-r] [--name-only | --name-status] [--root] <tree-ish> <tree-ish> [<path>...]
+// diff-tree [-r] [--name-only | --name-status] [--root] <commit> [<path>...]
+//
+// Prints one line per changed entry in git's raw format:
+//
+//   :<old mode> <new mode> <old sha> <new sha> <status>\t<path>
+//
+// with status A (added), D (deleted), M (modified) or T (type changed), and
+// zeros for the missing side. Without -r only the top level is compared and
+// a changed subtree is listed as a single 040000 entry. Given one commit,
+// it is compared with its parent and its id is printed first; a root commit
+// shows nothing unless --root is given.
+
+use crate::git::tree::{self, Change, FileEntry};
+use crate::git::{commit, revision, tag};
+use std::io;
+
+const USAGE: &str = "Usage: diff-tree [-r] [--name-only | --name-status] [--root] <tree-ish> [<tree-ish>] [<path>...]";
+
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Raw,
+    NameOnly,
+    NameStatus,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut recursive = false;
+    let mut root = false;
+    let mut format = Format::Raw;
+    let mut positional = Vec::new();
+    let mut pathspecs = Vec::new();
+
+    let mut options_done = false;
+    for arg in args {
+        match arg.as_str() {
+            arg if options_done => pathspecs.push(arg.trim_end_matches('/').to_string()),
+            "--" => options_done = true,
+            "-r" => recursive = true,
+            "--root" => root = true,
+            "--name-only" => format = Format::NameOnly,
+            "--name-status" => format = Format::NameStatus,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => positional.push(arg),
+        }
+    }
+    if positional.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+    }
+
+    // The first one or two arguments name objects, the rest are paths
+    let first = resolve(positional[0])?;
+    let second = match positional.get(1) {
+        Some(name) => revision::resolve(name)?,
+        None => None,
+    };
+    let objects = if second.is_some() { 2 } else { 1 };
+    pathspecs.extend(
+        positional[objects..]
+            .iter()
+            .map(|p| p.trim_end_matches('/').to_string()),
+    );
+
+    let (old_tree, new_tree) = match second {
+        Some(second) => (
+            Some(tree_of(positional[0], &first)?),
+            tree_of(positional[1], &second)?,
+        ),
+        None => {
+            // A single commit is compared with its parent
+            let (sha, kind) = tag::peel(&first)?;
+            if kind != "commit" {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+            }
+            let commit = commit::read(&sha)?;
+            let parent_tree = match commit.parents.as_slice() {
+                [] if !root => return Ok(()),
+                [] => None,
+                [parent] => Some(commit::read(parent)?.tree),
+                // Merges need -m or -c, which aren't supported
+                _ => return Ok(()),
+            };
+            println!("{}", sha);
+            (parent_tree, commit.tree)
+        }
+    };
+
+    for change in tree::diff(old_tree.as_deref(), Some(&new_tree), recursive)? {
+        if !matches_pathspecs(&pathspecs, &change) {
+            continue;
+        }
+        match format {
+            Format::Raw => println!(
+                ":{} {} {} {} {}\t{}",
+                raw_mode(change.old.as_ref()),
+                raw_mode(change.new.as_ref()),
+                change.old.as_ref().map_or(NULL_SHA, |e| &e.sha),
+                change.new.as_ref().map_or(NULL_SHA, |e| &e.sha),
+                status(&change),
+                change.path
+            ),
+            Format::NameOnly => println!("{}", change.path),
+            Format::NameStatus => println!("{}\t{}", status(&change), change.path),
+        }
+    }
+    Ok(())
+}
+
+fn resolve(name: &str) -> io::Result<String> {
+    revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("ambiguous argument '{}': unknown revision", name),
+        )
+    })
+}
+
+/// The tree a tree-ish names, peeling tags and commits
+fn tree_of(name: &str, sha: &str) -> io::Result<String> {
+    let (sha, kind) = tag::peel(sha)?;
+    match kind.as_str() {
+        "tree" => Ok(sha),
+        "commit" => Ok(commit::read(&sha)?.tree),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a tree", name, kind),
+        )),
+    }
+}
+
+fn status(change: &Change) -> char {
+    match (&change.old, &change.new) {
+        (None, _) => 'A',
+        (_, None) => 'D',
+        (Some(a), Some(b)) if file_type(&a.mode) != file_type(&b.mode) => 'T',
+        _ => 'M',
+    }
+}
+
+/// Regular files of either mode are the same type
+fn file_type(mode: &str) -> &str {
+    match mode {
+        tree::MODE_EXECUTABLE => tree::MODE_FILE,
+        mode => mode,
+    }
+}
+
+/// Modes are printed six digits wide, "000000" for a missing side
+fn raw_mode(entry: Option<&FileEntry>) -> String {
+    format!("{:0>6}", entry.map_or("0", |e| e.mode.as_str()))
+}
+
+/// A change is shown if its path is named, lies under a named directory,
+/// or (for a subtree) leads to a named path
+fn matches_pathspecs(pathspecs: &[String], change: &Change) -> bool {
+    let path = &change.path;
+    let is_tree = [&change.old, &change.new]
+        .iter()
+        .any(|e| e.as_ref().is_some_and(|e| e.mode == tree::MODE_TREE));
+    pathspecs.is_empty()
+        || pathspecs.iter().any(|spec| {
+            path == spec
+                || path.starts_with(&format!("{}/", spec))
+                || (is_tree && spec.starts_with(&format!("{}/", path)))
+        })
+}
@@ -0,0 +1,670 @@
+// fetch: download objects and refs from another repository
+//
+// fetch [-q | -v] [--dry-run] [--tags | --no-tags]
+//       [--depth <n> | --deepen <n> | --unshallow] [<remote> [<refspec>...]]
+//
+// <remote> is a configured remote or a URL, by default the current branch's
+// branch.<name>.remote, or else origin. Which refs are fetched and where
+// they are stored is up to the <refspec>s, or else remote.<remote>.fetch, or
+// else (for a URL) just HEAD. The remote is told what our refs, and those
+// of any repository we borrow objects from, point at so it sends only what
+// we are missing, and the pack it sends is kept as it is, with an index.
+// A stored ref only moves to a descendant of its commit unless its refspec
+// starts with "+", and a tag that exists is never moved without "+". Tags pointing at what we have come along (annotated ones by
+// the include-tag capability); --tags fetches every tag, --no-tags none.
+//
+// Every ref fetched is listed in .git/FETCH_HEAD, merge candidates first:
+// those of the <refspec>s given, or else the current branch's
+// branch.<name>.merge, or else the first configured refspec if it names a
+// single ref. The outcome for each ref goes to stderr as git reports it,
+// and the exit status is 1 if any update was rejected. --dry-run reports
+// the same outcome but leaves the refs and FETCH_HEAD alone; the objects
+// fetched are kept.
+//
+// --depth cuts the history fetched <n> commits below each ref's tip, which
+// makes the repository shallow (see git/shallow.rs) if it wasn't; --deepen
+// goes <n> commits further below the edge of a shallow repository, and
+// --unshallow fetches all the history it is missing. These fetch the refs'
+// commits even when they are already here.
+//
+// In a partial clone, fetches from the promisor remote leave out what its
+// filter does, as the clone did (see git/promisor.rs).
+
+use crate::commands::clone::{self, Credentials};
+use crate::git::config::Config;
+use crate::git::progress::Progress;
+use crate::git::promisor::{self, Promisor};
+use crate::git::refspec::Refspec;
+use crate::git::shallow::{self, Deepen};
+use crate::git::url::Scheme;
+use crate::git::{dry_run, http, interrupt, merge, object, pack, refs, repository, url};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+
+const USAGE: &str = "Usage: fetch [-q | -v] [--dry-run] [--tags | --no-tags] \
+    [--depth <n> | --deepen <n> | --unshallow] [<remote> [<refspec>...]]";
+const FETCH_HEAD: &str = "FETCH_HEAD";
+
+/// Width git pads the "old..new" column to
+const SUMMARY_WIDTH: usize = 17;
+/// Narrowest the remote ref column is
+const REF_WIDTH: usize = 10;
+
+/// Which tags to fetch besides those the refspecs name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TagMode {
+    /// New tags pointing at objects we end up having
+    #[default]
+    Follow,
+    /// Every tag, as if "refs/tags/*:refs/tags/*" were given
+    All,
+    None,
+}
+
+/// How to fetch
+pub struct FetchOptions {
+    pub tags: TagMode,
+    /// Credentials for an HTTP remote, in place of any in the URL
+    pub auth: Option<Credentials>,
+    /// Reflog message for every ref updated, in place of
+    /// "fetch <remote>: <what happened>"
+    pub reflog_message: Option<String>,
+    pub write_fetch_head: bool,
+    /// How to cut the history fetched short, or deepen a shallow one
+    pub deepen: Option<Deepen>,
+}
+
+impl Default for FetchOptions {
+    fn default() -> FetchOptions {
+        FetchOptions {
+            tags: TagMode::default(),
+            auth: None,
+            reflog_message: None,
+            write_fetch_head: true,
+            deepen: None,
+        }
+    }
+}
+
+/// What became of one remote ref
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    New,
+    UpToDate,
+    FastForward,
+    Forced,
+    /// Left as it was, for the reason given
+    Rejected(&'static str),
+    /// Fetched without a <dst>, so only listed in FETCH_HEAD
+    NotStored,
+}
+
+/// One remote ref fetched
+#[derive(Debug, Clone)]
+pub struct RefUpdate {
+    /// The remote ref
+    pub src: String,
+    /// The local ref it is stored as
+    pub dst: Option<String>,
+    pub old: Option<String>,
+    pub new: String,
+    pub status: Status,
+    pub for_merge: bool,
+}
+
+/// The result of a fetch
+#[derive(Debug, Clone)]
+pub struct FetchOutcome {
+    /// The URL fetched from as git shows it: without any password or
+    /// ".git" at the end
+    pub url: String,
+    /// Refs the remote advertised other than HEAD, as `(name, sha)` with
+    /// peeled tags as `<tag>^{}`
+    pub advertised: Vec<(String, String)>,
+    /// The `(ref, sha)` the remote's HEAD points at
+    pub head: Option<(String, String)>,
+    /// A ref fetched by more than one refspec appears once for each
+    pub updates: Vec<RefUpdate>,
+}
+
+impl FetchOutcome {
+    pub fn rejected(&self) -> bool {
+        self.updates
+            .iter()
+            .any(|update| matches!(update.status, Status::Rejected(_)))
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut quiet = false;
+    let mut verbose = false;
+    let mut options = FetchOptions::default();
+    let mut depth = None;
+    let mut deepen = None;
+    let mut unshallow = false;
+    let mut positional = Vec::new();
+    let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            "-v" | "--verbose" => verbose = true,
+            "--dry-run" => dry_run::enable(),
+            "-t" | "--tags" => options.tags = TagMode::All,
+            "-n" | "--no-tags" => options.tags = TagMode::None,
+            "--depth" => depth = Some(args.next().ok_or_else(usage)?.as_str()),
+            "--deepen" => deepen = Some(args.next().ok_or_else(usage)?.as_str()),
+            "--unshallow" => unshallow = true,
+            arg if arg.starts_with("--depth=") => depth = Some(&arg["--depth=".len()..]),
+            arg if arg.starts_with("--deepen=") => deepen = Some(&arg["--deepen=".len()..]),
+            arg if arg.starts_with('-') && arg.len() > 1 => return Err(usage()),
+            arg => positional.push(arg),
+        }
+    }
+    let positive = |value: &str| {
+        value.parse::<u32>().ok().filter(|&n| n > 0).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("depth {} is not a positive number", value),
+            )
+        })
+    };
+    options.deepen = match (depth, deepen, unshallow) {
+        (Some(_), Some(_), _) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "options '--deepen' and '--depth' cannot be used together",
+            ))
+        }
+        (Some(_), _, true) | (_, Some(_), true) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "options '--depth' and '--unshallow' cannot be used together",
+            ))
+        }
+        (None, None, true) if shallow::read()?.is_empty() => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--unshallow on a complete repository does not make sense",
+            ))
+        }
+        (None, None, true) => Some(Deepen::Depth(shallow::INFINITE_DEPTH)),
+        (Some(depth), None, false) => Some(Deepen::Depth(positive(depth)?)),
+        (None, Some(deepen), false) => Some(Deepen::Relative(positive(deepen)?)),
+        (None, None, false) => None,
+    };
+    let (remote, refspecs) = match positional.split_first() {
+        Some((remote, refspecs)) => (Some(*remote), refspecs),
+        None => (None, &[][..]),
+    };
+
+    let mut progress = if verbose {
+        Progress::new(|line| eprintln!("{}", line))
+    } else {
+        Progress::silent()
+    };
+    let outcome = fetch(remote, refspecs, &options, &mut progress)?;
+    if !quiet {
+        report(&outcome, verbose)?;
+    }
+    if outcome.rejected() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// A ref the refspecs ask for
+struct Wanted {
+    src: String,
+    sha: String,
+    dst: Option<String>,
+    force: bool,
+    for_merge: bool,
+}
+
+/// Fetch from `remote` (see the top of this file for the default) the refs
+/// `refspecs` name, or those its configuration names when there are none,
+/// and store them
+pub fn fetch(
+    remote: Option<&str>,
+    refspecs: &[&str],
+    options: &FetchOptions,
+    progress: &mut Progress,
+) -> io::Result<FetchOutcome> {
+    let _cancellable = interrupt::cancellable();
+    let config = Config::load()?;
+    let current = match refs::read_head()? {
+        refs::Head::Branch(branch) => Some(branch),
+        refs::Head::Detached(_) => None,
+    };
+    let branch_config = |key: &str| {
+        let branch = current.as_deref()?.strip_prefix("refs/heads/")?;
+        config.get(&format!("branch.{}.{}", branch, key))
+    };
+    let name = match remote {
+        Some(remote) => remote,
+        None => branch_config("remote").unwrap_or("origin"),
+    };
+    let promisor = Promisor::load()?.filter(|promisor| promisor.remote == name);
+    let filter = promisor
+        .as_ref()
+        .and_then(|promisor| promisor.filter.as_deref());
+
+    let configured_url = config.get(&format!("remote.{}.url", name));
+    let repo_url = match configured_url {
+        Some(configured) => configured,
+        None if name.contains(':') || Path::new(name).exists() => name,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("'{}' does not appear to be a git repository", name),
+            ))
+        }
+    };
+    let configured = config
+        .get_all(&format!("remote.{}.fetch", name))
+        .into_iter()
+        .map(Refspec::parse)
+        .collect::<io::Result<Vec<_>>>()?;
+    let from_command_line = !refspecs.is_empty();
+    let specs = if from_command_line {
+        refspecs
+            .iter()
+            .map(|spec| Refspec::parse(spec))
+            .collect::<io::Result<Vec<_>>>()?
+    } else if configured_url.is_some() {
+        configured.clone()
+    } else {
+        vec![Refspec::parse("HEAD")?]
+    };
+    let merge_src = match branch_config("remote") {
+        Some(remote) if remote == name && !from_command_line => branch_config("merge"),
+        _ => None,
+    };
+
+    // HEAD tells a clone which branch to check out
+    let mut ref_prefixes = vec!["HEAD".to_string()];
+    for spec in &specs {
+        ref_prefixes.extend(spec.ref_prefixes());
+    }
+    if options.tags != TagMode::None {
+        ref_prefixes.push("refs/tags/".to_string());
+    }
+    let (session, advertisement) =
+        clone::connect_with_prefixes(repo_url, options.auth.as_ref(), &ref_prefixes, progress)?;
+    let names: Vec<&str> = advertisement
+        .refs
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| !name.ends_with("^{}"))
+        .collect();
+    let sha_of = |refname: &str| {
+        advertisement
+            .refs
+            .iter()
+            .find(|(name, _)| name == refname)
+            .map(|(_, sha)| sha.clone())
+    };
+
+    let mut wanted: Vec<Wanted> = Vec::new();
+    let mut add = |candidate: Wanted| {
+        let duplicate = wanted
+            .iter()
+            .any(|known| known.src == candidate.src && known.dst == candidate.dst);
+        if !duplicate {
+            wanted.push(candidate);
+        }
+    };
+    for (i, spec) in specs.iter().enumerate() {
+        let selected = spec.select(&names);
+        if selected.is_empty() && from_command_line && !spec.is_pattern() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("couldn't find remote ref {}", spec.src),
+            ));
+        }
+        for (src, dst) in selected {
+            let for_merge = from_command_line
+                || merge_src == Some(src)
+                || (merge_src.is_none() && i == 0 && !spec.is_pattern());
+            let sha = sha_of(src).unwrap_or_default();
+            add(Wanted {
+                src: src.to_string(),
+                sha: sha.clone(),
+                dst,
+                force: spec.force,
+                for_merge,
+            });
+            // A ref fetched by name also updates its remote-tracking ref
+            if from_command_line {
+                for spec in &configured {
+                    for (_, dst) in spec.select(&[src]) {
+                        let Some(dst) = dst else { continue };
+                        add(Wanted {
+                            src: src.to_string(),
+                            sha: sha.clone(),
+                            dst: Some(dst),
+                            force: spec.force,
+                            for_merge: false,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    if options.tags == TagMode::All {
+        for (src, dst) in Refspec::parse("refs/tags/*:refs/tags/*")?.select(&names) {
+            add(Wanted {
+                src: src.to_string(),
+                sha: sha_of(src).unwrap_or_default(),
+                dst,
+                force: false,
+                for_merge: false,
+            });
+        }
+    }
+
+    // Fetching into the checked-out branch would leave the index and
+    // working tree behind it; a bare repository has neither
+    let bare = config.get_bool("core.bare")? == Some(true);
+    if let (Some(current), false) = (&current, bare) {
+        if wanted.iter().any(|w| w.dst.as_ref() == Some(current))
+            && refs::read_ref(current)?.is_some()
+        {
+            return Err(io::Error::other(format!(
+                "refusing to fetch into branch '{}' checked out at '{}'",
+                current,
+                std::env::current_dir()?.display()
+            )));
+        }
+    }
+
+    // Deepening wants the history below commits that may be here already
+    let mut wants = Vec::new();
+    for sha in wanted.iter().map(|w| &w.sha) {
+        let missing = options.deepen.is_some() || object::locate(sha)?.is_none();
+        if missing && !wants.contains(sha) {
+            wants.push(sha.clone());
+        }
+    }
+    if wants.is_empty() {
+        session.close()?;
+    } else {
+        let mut haves = Vec::new();
+        for sha in refs::list_refs("refs")?
+            .into_iter()
+            .map(|(_, sha)| sha)
+            .chain(alternate_tips()?)
+        {
+            if !haves.contains(&sha) && object::locate(&sha)?.is_some() {
+                haves.push(sha);
+            }
+        }
+        progress.check()?;
+        let limits = pack::Limits::from_config(&config)?;
+        // A pack cut off on its way over HTTP is asked for again, in a new
+        // negotiation
+        let retries = match url::parse(repo_url)?.scheme {
+            Scheme::Http | Scheme::Https => http::max_retries(&config)?,
+            _ => 0,
+        };
+        let mut session = Some(session);
+        let mut attempt = 0;
+        let objects = loop {
+            let session = match session.take() {
+                Some(session) => session,
+                None => {
+                    let auth = options.auth.as_ref();
+                    clone::connect_with_prefixes(repo_url, auth, &ref_prefixes, progress)?.0
+                }
+            };
+            let (pack, update) = session.fetch(&wants, &haves, options.deepen, filter, progress)?;
+            match pack::store(pack, &limits) {
+                Ok(objects) => {
+                    update.apply()?;
+                    if promisor.is_some() {
+                        promisor::mark(&objects)?;
+                    }
+                    break objects;
+                }
+                Err(e) if attempt < retries && http::is_transient(&e) => {
+                    progress.report(&format!("warning: pack download failed: {}; retrying", e));
+                    thread::sleep(http::backoff(attempt));
+                    progress.check()?;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        progress.report(&format!("Received pack of {} objects", objects.len()));
+    }
+
+    if options.tags == TagMode::Follow {
+        for name in names.iter().filter(|name| name.starts_with("refs/tags/")) {
+            let sha = sha_of(name).unwrap_or_default();
+            let peeled = sha_of(&format!("{}^{{}}", name)).unwrap_or_else(|| sha.clone());
+            if wanted.iter().all(|w| w.src != *name)
+                && refs::read_ref(name)?.is_none()
+                && object::locate(&sha)?.is_some()
+                && object::locate(&peeled)?.is_some()
+            {
+                wanted.push(Wanted {
+                    src: name.to_string(),
+                    sha,
+                    dst: Some(name.to_string()),
+                    force: false,
+                    for_merge: false,
+                });
+            }
+        }
+    }
+
+    let action = match remote {
+        Some(remote) => format!(
+            "fetch {}",
+            [remote]
+                .iter()
+                .chain(refspecs)
+                .copied()
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        None => "fetch".to_string(),
+    };
+    // Merge candidates come first, in what is reported and in FETCH_HEAD
+    wanted.sort_by_key(|wanted| !wanted.for_merge);
+    let mut updates = Vec::with_capacity(wanted.len());
+    for wanted in wanted {
+        let Some(dst) = wanted.dst.clone() else {
+            updates.push(update(wanted, None, Status::NotStored));
+            continue;
+        };
+        let old = refs::read_ref(&dst)?;
+        let status = match &old {
+            None => Status::New,
+            Some(old) if *old == wanted.sha => Status::UpToDate,
+            Some(_) if dst.starts_with("refs/tags/") && !wanted.force => {
+                Status::Rejected("would clobber existing tag")
+            }
+            Some(old)
+                if !dst.starts_with("refs/tags/")
+                    && object::locate(old)?.is_some()
+                    && merge::is_ancestor(old, &wanted.sha)? =>
+            {
+                Status::FastForward
+            }
+            Some(_) if wanted.force => Status::Forced,
+            Some(_) => Status::Rejected("non-fast-forward"),
+        };
+        let detail = match status {
+            Status::New if dst.starts_with("refs/tags/") => "storing tag",
+            Status::New if wanted.src.starts_with("refs/heads/") => "storing head",
+            Status::New => "storing ref",
+            Status::FastForward => "fast-forward",
+            Status::Forced => "forced-update",
+            _ => "",
+        };
+        if !detail.is_empty() {
+            let message = match &options.reflog_message {
+                Some(message) => message.clone(),
+                None => format!("{}: {}", action, detail),
+            };
+            refs::update_ref(&dst, &wanted.sha, &message)?;
+        }
+        updates.push(update(wanted, old, status));
+    }
+
+    // git leaves a trailing "/" and ".git" out of the URL it shows
+    let url = url::parse(repo_url)?.to_string();
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url).to_string();
+    if options.write_fetch_head {
+        write_fetch_head(&updates, &url)?;
+    }
+    let (advertised, head) = advertisement.into_discovery();
+    Ok(FetchOutcome {
+        url,
+        advertised,
+        head,
+        updates,
+    })
+}
+
+/// What the refs point at in the repositories whose objects this one
+/// borrows
+fn alternate_tips() -> io::Result<Vec<String>> {
+    let mut tips = Vec::new();
+    for dir in object::alternates()? {
+        let Some(repo) = dir.parent() else {
+            continue;
+        };
+        let refs = clone::in_repository(repo, || refs::list_refs("refs"))?;
+        tips.extend(refs.into_iter().map(|(_, sha)| sha));
+    }
+    Ok(tips)
+}
+
+fn update(wanted: Wanted, old: Option<String>, status: Status) -> RefUpdate {
+    RefUpdate {
+        src: wanted.src,
+        dst: wanted.dst,
+        old,
+        new: wanted.sha,
+        status,
+        for_merge: wanted.for_merge,
+    }
+}
+
+/// List every ref fetched in FETCH_HEAD, once each, as
+/// `<sha> TAB [not-for-merge] TAB <what> of <url>`
+fn write_fetch_head(updates: &[RefUpdate], url: &str) -> io::Result<()> {
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+    let mut listed: Vec<&str> = Vec::new();
+    let mut content = String::new();
+    for update in updates {
+        if listed.contains(&update.src.as_str()) {
+            continue;
+        }
+        listed.push(&update.src);
+        let what = describe(&update.src).map_or(String::new(), |what| what + " of ");
+        let merge = if update.for_merge {
+            ""
+        } else {
+            "not-for-merge"
+        };
+        content.push_str(&format!("{}\t{}\t{}{}\n", update.new, merge, what, url));
+    }
+    fs::write(repository::path(FETCH_HEAD), content)
+}
+
+/// A remote ref as FETCH_HEAD and merge messages name it, "branch 'main'"
+/// or "tag 'v1.0'"; `None` for HEAD, which goes by the URL alone
+pub fn describe(src: &str) -> Option<String> {
+    if src == "HEAD" {
+        None
+    } else if let Some(branch) = src.strip_prefix("refs/heads/") {
+        Some(format!("branch '{}'", branch))
+    } else if let Some(tag) = src.strip_prefix("refs/tags/") {
+        Some(format!("tag '{}'", tag))
+    } else if let Some(branch) = src.strip_prefix("refs/remotes/") {
+        Some(format!("remote-tracking branch '{}'", branch))
+    } else {
+        Some(format!("'{}'", src))
+    }
+}
+
+/// Print a line for each ref fetched, after "From <url>", as git does; up
+/// to date ones only when `verbose`
+pub fn report(outcome: &FetchOutcome, verbose: bool) -> io::Result<()> {
+    let shown: Vec<&RefUpdate> = outcome
+        .updates
+        .iter()
+        .filter(|update| verbose || update.status != Status::UpToDate)
+        .collect();
+    if shown.is_empty() {
+        return Ok(());
+    }
+    let width = shown
+        .iter()
+        .map(|update| short_name(&update.src).len())
+        .max()
+        .unwrap_or(0)
+        .max(REF_WIDTH);
+
+    let mut out = BufWriter::new(io::stderr().lock());
+    writeln!(out, "From {}", outcome.url)?;
+    for update in shown {
+        let abbrev = |sha: &Option<String>| sha.as_deref().map_or("", |sha| &sha[..7]).to_string();
+        let kind = if update.src.starts_with("refs/tags/") {
+            "tag"
+        } else if update.src.starts_with("refs/heads/") || update.src == "HEAD" {
+            "branch"
+        } else {
+            "ref"
+        };
+        let (flag, summary, reason) = match update.status {
+            Status::New => ('*', format!("[new {}]", kind), None),
+            Status::UpToDate => ('=', "[up to date]".to_string(), None),
+            Status::FastForward => (
+                ' ',
+                format!("{}..{}", abbrev(&update.old), &update.new[..7]),
+                None,
+            ),
+            Status::Forced => (
+                '+',
+                format!("{}...{}", abbrev(&update.old), &update.new[..7]),
+                Some("forced update"),
+            ),
+            Status::Rejected(reason) => ('!', "[rejected]".to_string(), Some(reason)),
+            Status::NotStored => ('*', kind.to_string(), None),
+        };
+        let dst = update.dst.as_deref().map_or("FETCH_HEAD", short_name);
+        write!(
+            out,
+            " {} {:<summary_width$} {:<width$} -> {}",
+            flag,
+            summary,
+            short_name(&update.src),
+            dst,
+            summary_width = SUMMARY_WIDTH,
+            width = width
+        )?;
+        match reason {
+            Some(reason) => writeln!(out, "  ({})", reason)?,
+            None => writeln!(out)?,
+        }
+    }
+    out.flush()
+}
+
+fn short_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
@@ -0,0 +1,99 @@
+// fetch-pack: download objects for some remote refs, without updating any
+//
+// fetch-pack [--all] <repository> [<ref>...]
+//
+// Runs the upload-pack negotiation the way clone does, stores the objects
+// it receives, and prints "<sha> <refname>" for each ref fetched. A ref may
+// be named in full or by its tail ("main" for refs/heads/main). Nothing is
+// requested when every wanted commit is already present.
+
+use crate::commands::clone;
+use crate::git::config::Config;
+use crate::git::interrupt;
+use crate::git::object;
+use crate::git::pack;
+use crate::git::progress::Progress;
+use std::io;
+
+const USAGE: &str = "Usage: fetch-pack [--all] <repository> [<ref>...]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut all = false;
+    let mut positional = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--all" => all = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => positional.push(arg),
+        }
+    }
+    let (repository, names) = match positional.split_first() {
+        Some((repository, names)) if all || !names.is_empty() => (*repository, names),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    };
+
+    // Progress goes to stderr so that stdout carries only the refs
+    let mut progress = Progress::new(|line| eprintln!("{}", line));
+    for (refname, sha) in fetch(repository, names, all, &mut progress)? {
+        println!("{} {}", sha, refname);
+    }
+    Ok(())
+}
+
+/// Download the objects for the remote refs `names` (every ref with `all`)
+/// and return the refs fetched as `(name, sha)`. A cancelled or failed
+/// fetch leaves no new objects behind.
+pub fn fetch(
+    repository: &str,
+    names: &[&str],
+    all: bool,
+    progress: &mut Progress,
+) -> io::Result<Vec<(String, String)>> {
+    let _cancellable = interrupt::cancellable();
+    let (session, (advertised, head)) = clone::open_session(repository, None, progress)?;
+    let advertised: Vec<(String, String)> = advertised
+        .into_iter()
+        .filter(|(name, _)| !name.ends_with("^{}"))
+        .collect();
+
+    let mut selected: Vec<(String, String)> = Vec::new();
+    if all {
+        selected.extend(advertised.iter().cloned());
+    }
+    for name in names {
+        let found = if *name == "HEAD" {
+            head.as_ref()
+                .map(|(_, sha)| ("HEAD".to_string(), sha.clone()))
+        } else {
+            advertised
+                .iter()
+                .find(|(refname, _)| refname == name || refname.ends_with(&format!("/{}", name)))
+                .cloned()
+        };
+        match found {
+            Some(found) if !selected.contains(&found) => selected.push(found),
+            Some(_) => {}
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no such remote ref {}", name),
+                ))
+            }
+        }
+    }
+
+    let mut wants: Vec<String> = selected
+        .iter()
+        .map(|(_, sha)| sha.clone())
+        .filter(|sha| object::read(sha).is_err())
+        .collect();
+    wants.sort();
+    wants.dedup();
+    if !wants.is_empty() {
+        let (pack, _) = session.fetch(&wants, &[], None, None, progress)?;
+        pack::store(pack, &pack::Limits::from_config(&Config::load()?)?)?;
+    }
+    Ok(selected)
+}
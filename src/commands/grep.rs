@@ -0,0 +1,315 @@
+// grep: print lines matching a pattern in tracked files
+//
+// grep [-i] [-v] [-w] [-l | -c] [-E | -F | -G] [-e <pattern>]... [<pattern>]
+//      [--cached | --untracked | <tree-ish>...] [--] [<path>...]
+//
+// Searches the tracked files of the working tree, the staged content with
+// --cached, or the files of each <tree-ish> (a commit, tag or tree), and
+// prints each matching line as <path>:<line number>:<line>; matches in a
+// tree-ish are prefixed "<tree-ish>:". --untracked also searches untracked
+// files not excluded by .gitignore. Patterns are POSIX basic regular
+// expressions, extended with -E or plain strings with -F; several -e
+// patterns match a line if any of them does. -i ignores case, -w only
+// matches whole words and -v selects the lines that don't match. -l prints
+// only the names of matching files, -c how many lines match in each. Files
+// with a NUL byte are binary: "Binary file <path> matches" stands in for
+// their lines. Paths limit the search to those files or directories, which
+// may be globs. Like git, exits with status 1 when nothing matches.
+
+use crate::git::index::Index;
+use crate::git::object::{self, Error};
+use crate::git::regex::{Regex, Syntax};
+use crate::git::tree::{self, MODE_GITLINK};
+use crate::git::{commit, ignore, refs, revision, tag};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "Usage: grep [-i] [-v] [-w] [-l | -c] [-E | -F | -G] [-e <pattern>]... [<pattern>] [--cached | --untracked | <tree-ish>...] [--] [<path>...]";
+
+/// How far binary detection looks, as git does
+const BINARY_CHECK: usize = 8000;
+
+#[derive(PartialEq, Eq)]
+enum Output {
+    Lines,
+    FileNames,
+    Counts,
+}
+
+enum Source {
+    Worktree { untracked: bool },
+    Index,
+    Trees(Vec<(String, String)>),
+}
+
+struct Search {
+    patterns: Vec<Regex>,
+    invert: bool,
+    output: Output,
+    paths: Vec<String>,
+    matched: bool,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut syntax = Syntax::Basic;
+    let mut ignore_case = false;
+    let mut words = false;
+    let mut invert = false;
+    let mut output = Output::Lines;
+    let mut cached = false;
+    let mut untracked = false;
+    let mut patterns: Vec<String> = Vec::new();
+    let mut operands: Vec<&str> = Vec::new();
+    let mut paths: Vec<String> = Vec::new();
+    let mut separated = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-i" | "--ignore-case" => ignore_case = true,
+            "-w" | "--word-regexp" => words = true,
+            "-v" | "--invert-match" => invert = true,
+            "-l" | "--files-with-matches" | "--name-only" => output = Output::FileNames,
+            "-c" | "--count" => output = Output::Counts,
+            "-E" | "--extended-regexp" => syntax = Syntax::Extended,
+            "-F" | "--fixed-strings" => syntax = Syntax::Fixed,
+            "-G" | "--basic-regexp" => syntax = Syntax::Basic,
+            "-n" | "--line-number" => {}
+            "--cached" => cached = true,
+            "--untracked" => untracked = true,
+            "-e" => patterns.push(
+                args.next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?
+                    .clone(),
+            ),
+            "--" => {
+                separated = true;
+                paths.extend(args.by_ref().cloned());
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => operands.push(arg),
+        }
+    }
+
+    let mut operands = operands.into_iter();
+    if patterns.is_empty() {
+        let pattern = operands
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no pattern given"))?;
+        patterns.push(pattern.to_string());
+    }
+    // Revisions come first; without "--", the first operand that isn't one
+    // starts the paths
+    let mut trees = Vec::new();
+    let mut operand_paths = Vec::new();
+    for operand in operands {
+        match resolve_tree(operand)? {
+            Some(sha) if operand_paths.is_empty() => trees.push((operand.to_string(), sha)),
+            None if separated => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("unable to resolve revision: {}", operand),
+                ))
+            }
+            _ => operand_paths.push(operand.to_string()),
+        }
+    }
+    paths.splice(0..0, operand_paths);
+
+    let source = match (cached, untracked, trees.is_empty()) {
+        (false, _, true) => Source::Worktree { untracked },
+        (true, false, true) => Source::Index,
+        (false, false, false) => Source::Trees(trees),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--cached, --untracked and trees can't be combined",
+            ))
+        }
+    };
+
+    let patterns = patterns
+        .iter()
+        .map(|pattern| {
+            Regex::with_words(pattern, syntax, ignore_case, words).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("command line, '{}': {}", pattern, e),
+                )
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let paths = paths
+        .into_iter()
+        .map(|path| path.trim_end_matches('/').to_string())
+        .collect();
+
+    let mut search = Search {
+        patterns,
+        invert,
+        output,
+        paths,
+        matched: false,
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    match source {
+        Source::Worktree { untracked } => search.worktree(&mut out, untracked)?,
+        Source::Index => search.index(&mut out)?,
+        Source::Trees(trees) => {
+            for (name, sha) in trees {
+                search.tree(&mut out, &name, &sha)?;
+            }
+        }
+    }
+    out.flush()?;
+    if !search.matched {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// The tree `name` stands for, if it names a commit, tag or tree
+fn resolve_tree(name: &str) -> io::Result<Option<String>> {
+    let Some(sha) = revision::resolve(name)? else {
+        return Ok(None);
+    };
+    let (sha, kind) = match tag::peel(&sha) {
+        Ok(peeled) => peeled,
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match kind.as_str() {
+        "commit" => Ok(Some(commit::read(&sha)?.tree)),
+        "tree" => Ok(Some(sha)),
+        _ => Ok(None),
+    }
+}
+
+impl Search {
+    fn wanted(&self, path: &str) -> bool {
+        self.paths.is_empty()
+            || self.paths.iter().any(|spec| {
+                spec.is_empty()
+                    || spec == "."
+                    || path == spec
+                    || path
+                        .strip_prefix(spec.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+                    || refs::glob_match(spec, path)
+            })
+    }
+
+    fn worktree(&mut self, out: &mut impl Write, untracked: bool) -> io::Result<()> {
+        let index = Index::load()?;
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+        for entry in index.entries() {
+            if entry.file().mode != MODE_GITLINK && seen.insert(entry.path.clone()) {
+                files.push(entry.path.clone());
+            }
+        }
+        if untracked {
+            files.extend(ignore::untracked_files(&seen)?);
+            files.sort();
+        }
+
+        for path in files {
+            if !self.wanted(&path) {
+                continue;
+            }
+            let content = match fs::symlink_metadata(&path) {
+                Ok(meta) if meta.file_type().is_symlink() => fs::read_link(&path)?
+                    .to_string_lossy()
+                    .into_owned()
+                    .into_bytes(),
+                Ok(meta) if meta.is_file() => fs::read(&path)?,
+                // Deleted or replaced by a directory since it was staged
+                Ok(_) => continue,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            self.file(out, &path, &content)?;
+        }
+        Ok(())
+    }
+
+    fn index(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let index = Index::load()?;
+        let mut last = None;
+        for entry in index.entries() {
+            // One stage of a conflicted path is enough
+            if last == Some(&entry.path) || entry.file().mode == MODE_GITLINK {
+                continue;
+            }
+            last = Some(&entry.path);
+            if self.wanted(&entry.path) {
+                let content = object::read_expecting(&entry.sha, "blob")?;
+                self.file(out, &entry.path, &content)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn tree(&mut self, out: &mut impl Write, name: &str, tree_sha: &str) -> io::Result<()> {
+        let mut visit = |path: &str, entry: &tree::TreeEntry| -> Result<(), Error> {
+            if entry.mode == MODE_GITLINK || !self.wanted(path) {
+                return Ok(());
+            }
+            // Each blob is read when its turn comes and dropped after
+            let content = object::read_expecting(&entry.sha, "blob")?;
+            let label = format!("{}:{}", name, path);
+            self.file(out, &label, &content)?;
+            Ok(())
+        };
+        tree::walk(tree_sha, &mut visit)?;
+        Ok(())
+    }
+
+    fn selects(&self, line: &[u8]) -> bool {
+        self.patterns.iter().any(|regex| regex.is_match(line)) != self.invert
+    }
+
+    /// Report the lines of one file's `content`, labelled `label`
+    fn file(&mut self, out: &mut impl Write, label: &str, content: &[u8]) -> io::Result<()> {
+        let binary = content[..content.len().min(BINARY_CHECK)].contains(&0);
+        let mut count = 0;
+        let mut lines = content.split(|&b| b == b'\n').enumerate().peekable();
+        while let Some((number, line)) = lines.next() {
+            // The split after a final newline is no line
+            if line.is_empty() && lines.peek().is_none() {
+                break;
+            }
+            if !self.selects(line) {
+                continue;
+            }
+            count += 1;
+            if self.output != Output::Lines {
+                if self.output == Output::FileNames {
+                    break;
+                }
+                continue;
+            }
+            if binary {
+                writeln!(out, "Binary file {} matches", label)?;
+                break;
+            }
+            write!(out, "{}:{}:", label, number + 1)?;
+            out.write_all(line)?;
+            out.write_all(b"\n")?;
+        }
+
+        if count > 0 {
+            self.matched = true;
+            match self.output {
+                Output::Lines => {}
+                Output::FileNames => writeln!(out, "{}", label)?,
+                Output::Counts => writeln!(out, "{}:{}", label, count)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,110 @@
-use std::io::{self, Write};
+// hash-object: compute the id an object would have, and optionally store it
+//
+// hash-object [-w] [-t <type>] [--path=<path> | --no-filters] [--stdin] [--stdin-paths] [<file>...]
+//
+// Prints the id of every <file>, of standard input with --stdin, and of
+// every file named on standard input, one per line, with --stdin-paths; -w
+// also writes the objects. Blobs are hashed in their clean form, the way
+// the file would be stored with the filters and line-ending conversion its
+// attributes ask for. The attributes are those of the file itself, or of
+// --path when given, which is how content from --stdin gets any;
+// --no-filters hashes the bytes as they are.
 
-use crate::git::object;
+use crate::git::{convert, object};
+use std::fs;
+use std::io::{self, BufRead, BufWriter, Read, Write};
 
-pub fn run(file_path: &str, should_write: bool) -> io::Result<()> {
-    let hash = object::create_file_hash(file_path, should_write)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+const USAGE: &str = "Usage: hash-object [-w] [-t <type>] [--path=<path> | --no-filters] \
+                     [--stdin] [--stdin-paths] [<file>...]";
 
-    io::stdout().write_all(hash.as_bytes())?;
-    io::stdout().flush()?;
-    Ok(())
+struct Options<'a> {
+    kind: &'a str,
+    write: bool,
+    path: Option<&'a str>,
+    no_filters: bool,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = Options {
+        kind: "blob",
+        write: false,
+        path: None,
+        no_filters: false,
+    };
+    let mut stdin = false;
+    let mut stdin_paths = false;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-w" => options.write = true,
+            "-t" => options.kind = args.next().ok_or_else(usage)?,
+            "--stdin" => stdin = true,
+            "--stdin-paths" => stdin_paths = true,
+            "--no-filters" => options.no_filters = true,
+            "--path" => options.path = Some(args.next().ok_or_else(usage)?),
+            arg if arg.starts_with("--path=") => options.path = Some(&arg["--path=".len()..]),
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => files.push(arg),
+        }
+    }
+    if !matches!(options.kind, "blob" | "tree" | "commit" | "tag") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid object type \"{}\"", options.kind),
+        ));
+    }
+    if options.path.is_some() && options.no_filters {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Can't use --path with --no-filters",
+        ));
+    }
+    if stdin_paths && (stdin || !files.is_empty()) {
+        return Err(usage());
+    }
+    if !stdin && !stdin_paths && files.is_empty() {
+        return Err(usage());
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    if stdin {
+        let mut content = Vec::new();
+        io::stdin().lock().read_to_end(&mut content)?;
+        writeln!(out, "{}", hash(content, options.path, &options)?)?;
+    }
+    for file in files {
+        writeln!(out, "{}", hash_file(file, &options)?)?;
+    }
+    if stdin_paths {
+        for line in io::stdin().lock().lines() {
+            writeln!(out, "{}", hash_file(&line?, &options)?)?;
+        }
+    }
+    out.flush()
+}
+
+fn hash_file(file: &str, options: &Options) -> io::Result<String> {
+    let content = fs::read(file)
+        .map_err(|e| io::Error::new(e.kind(), format!("could not open '{}': {}", file, e)))?;
+    hash(content, Some(options.path.unwrap_or(file)), options)
+}
+
+/// Hash `content`, converted for the attributes of `path` if there is one
+fn hash(content: Vec<u8>, path: Option<&str>, options: &Options) -> io::Result<String> {
+    let content = match path {
+        Some(path) if options.kind == "blob" && !options.no_filters => {
+            convert::to_git(path.strip_prefix("./").unwrap_or(path), content)?
+        }
+        _ => content,
+    };
+    if options.write {
+        Ok(object::write(options.kind, &content)?)
+    } else {
+        Ok(object::hash(options.kind, &content))
+    }
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
 }
@@ -1,10 +1,18 @@
+// init: create an empty repository in the current directory
+//
+// HEAD starts out on the branch init.defaultBranch names, or main.
+
+use crate::git::{hooks, refs, worktree};
 use std::fs;
 
 pub fn run() -> std::io::Result<()> {
+    let branch = refs::default_branch()?;
     fs::create_dir(".git")?;
     fs::create_dir(".git/objects")?;
     fs::create_dir(".git/refs")?;
-    fs::write(".git/HEAD", "ref: refs/heads/main\n").unwrap();
+    refs::write_head_symbolic(&refs::branch_ref(&branch))?;
+    worktree::configure_symlinks()?;
+    hooks::install_samples()?;
     println!("Initialized git directory");
     Ok(())
 }
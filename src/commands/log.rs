@@ -0,0 +1,125 @@
+// log: show commit history
+//
+// log [--max-count=<n> | -n <n> | -<n>] [--oneline] [-p | --patch] [-U<n>]
+//     [--date=<mode>] [-S <string> | -G <regex>] [--pickaxe-all]
+//     [-i | --regexp-ignore-case] [<rev>...] [^<rev>...] [--not <rev>...]
+//
+// Shows the commits reachable from the given revisions (HEAD by default),
+// newest first, each as show shows a commit, with a blank line between
+// them; -p adds each commit's patch and --oneline cuts each down to its
+// abbreviated id and subject. Revisions are the ones rev-list accepts.
+//
+// -S and -G (see git/pickaxe.rs) keep only the commits whose changes add
+// or remove <string>, or have an added or removed line matching <regex>;
+// -i makes either ignore case. The patch of such a commit is limited to
+// the files that matched, or with --pickaxe-all covers all of them.
+// --max-count counts the commits shown, after the others are left out.
+
+use crate::commands::rev_list;
+use crate::commands::show::{self, Options};
+use crate::git::pickaxe::Pickaxe;
+use crate::git::revwalk::RevWalk;
+use crate::git::{commit, date::Mode, encoding, refs};
+use std::io::{self, BufWriter, Write};
+
+const USAGE: &str = "Usage: log [-n <n>] [--oneline] [-p] [-U<n>] [--date=<mode>] \
+    [-S <string> | -G <regex>] [--pickaxe-all] [-i] [<rev>...] [--not <rev>...]";
+
+/// What -S or -G was given, to be compiled once the other options are in
+enum Search {
+    String(String),
+    Regex(String),
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = Options::from_config()?;
+    options.patch = false;
+    let mut max_count: Option<usize> = None;
+    let mut oneline = false;
+    let mut search: Option<Search> = None;
+    let mut pickaxe_all = false;
+    let mut ignore_case = false;
+    let mut negated = false;
+    let mut walk = RevWalk::new();
+    let mut any = false;
+
+    let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+    let parse_count = |value: &str| value.parse::<usize>().map_err(|_| usage());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--not" => negated = !negated,
+            "--oneline" => oneline = true,
+            "-p" | "--patch" => options.patch = true,
+            "-s" | "--no-patch" => options.patch = false,
+            "--pickaxe-all" => pickaxe_all = true,
+            "-i" | "--regexp-ignore-case" => ignore_case = true,
+            "-n" => max_count = Some(parse_count(args.next().ok_or_else(usage)?)?),
+            "-S" => search = Some(Search::String(args.next().ok_or_else(usage)?.clone())),
+            "-G" => search = Some(Search::Regex(args.next().ok_or_else(usage)?.clone())),
+            arg if arg.starts_with("--max-count=") => {
+                max_count = Some(parse_count(&arg["--max-count=".len()..])?)
+            }
+            arg if arg.len() > 1
+                && arg.starts_with('-')
+                && arg[1..].bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                max_count = Some(parse_count(&arg[1..])?)
+            }
+            arg if arg.starts_with("-S") => search = Some(Search::String(arg[2..].to_string())),
+            arg if arg.starts_with("-G") => search = Some(Search::Regex(arg[2..].to_string())),
+            arg if arg.starts_with("-U") || arg.starts_with("--unified=") => {
+                let value = arg.strip_prefix("--unified=").unwrap_or_else(|| &arg[2..]);
+                options.context = parse_count(value)?;
+                options.patch = true;
+            }
+            arg if arg.starts_with("--date=") => options.date = Mode::parse(&arg[7..])?,
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => {
+                any = true;
+                rev_list::add_revision(&mut walk, arg, negated)?;
+            }
+        }
+    }
+    if !any {
+        refs::require_head_commit()?;
+        rev_list::add_revision(&mut walk, "HEAD", false)?;
+    }
+    let pickaxe = match search {
+        Some(Search::String(needle)) => Some(Pickaxe::string(&needle, ignore_case)),
+        Some(Search::Regex(pattern)) => Some(Pickaxe::regex(&pattern, ignore_case)?),
+        None => None,
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut shown = 0;
+    for sha in walk {
+        if max_count.is_some_and(|max| shown >= max) {
+            break;
+        }
+        let sha = sha?;
+        let commit = commit::read(&sha)?;
+        if let Some(pickaxe) = &pickaxe {
+            // Finding one file is enough unless the patch wants them all
+            let all = options.patch && !pickaxe_all;
+            let paths = pickaxe.matching_paths(&commit, all)?;
+            if paths.is_empty() {
+                continue;
+            }
+            if !pickaxe_all {
+                options.paths = paths;
+            }
+        }
+        if oneline {
+            let line = format!("{} {}\n", &sha[..7], commit.summary());
+            out.write_all(&encoding::for_output(&line)?)?;
+        } else {
+            if shown > 0 {
+                out.write_all(b"\n")?;
+            }
+            show::show_commit(&mut out, &sha, &options)?;
+        }
+        shown += 1;
+    }
+    out.flush()
+}
@@ -0,0 +1,110 @@
+// ls-remote: list the refs of a remote repository
+//
+// ls-remote [--heads] [--tags] [--refs] [--symref] [--exit-code]
+//           [<repository> [<pattern>...]]
+//
+// Prints "<sha>\t<refname>" for each ref the remote advertises, HEAD
+// first. <repository> is a URL or the name of a configured remote (origin
+// by default). Patterns select refs whose name matches, either whole or
+// from a "/" on ("main" matches refs/heads/main), and may be globs such as
+// 'refs/tags/*'. --heads and --tags limit the list to branches and tags,
+// --refs leaves out HEAD and peeled tags. --symref also prints what each
+// symbolic ref points at, before the ref itself:
+//
+// ref: refs/heads/main	HEAD
+//
+// --exit-code exits with status 2 when no ref matches.
+
+use crate::commands::clone;
+use crate::git::config::Config;
+use crate::git::progress::Progress;
+use crate::git::refs;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "Usage: ls-remote [--heads] [--tags] [--refs] [--symref] [--exit-code] [<repository> [<pattern>...]]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut heads = false;
+    let mut tags = false;
+    let mut refs_only = false;
+    let mut symref = false;
+    let mut exit_code = false;
+    let mut positional = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--heads" | "--branches" => heads = true,
+            "-t" | "--tags" => tags = true,
+            "--refs" => refs_only = true,
+            "--symref" => symref = true,
+            "--exit-code" => exit_code = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => positional.push(arg),
+        }
+    }
+    let (repository, patterns) = match positional.split_first() {
+        Some((repository, patterns)) => (*repository, patterns),
+        None => ("origin", &[][..]),
+    };
+    let url = repository_url(repository)?;
+
+    let mut ref_prefixes = Vec::new();
+    if heads {
+        ref_prefixes.push("refs/heads/".to_string());
+    }
+    if tags {
+        ref_prefixes.push("refs/tags/".to_string());
+    }
+    let (_, advertisement) =
+        clone::connect_with_prefixes(&url, None, &ref_prefixes, &mut Progress::silent())?;
+
+    let wanted = |name: &str| {
+        let kind_ok = match (heads, tags) {
+            (false, false) => true,
+            _ => {
+                (heads && name.starts_with("refs/heads/"))
+                    || (tags && name.starts_with("refs/tags/"))
+            }
+        };
+        let plain = name.starts_with("refs/") && !name.ends_with("^{}");
+        kind_ok
+            && (!refs_only || plain)
+            && (patterns.is_empty()
+                || patterns.iter().any(|pattern| {
+                    refs::glob_match(pattern, name)
+                        || refs::glob_match(&format!("*/{}", pattern), name)
+                }))
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut matched = false;
+    for (name, sha) in &advertisement.refs {
+        if !wanted(name) {
+            continue;
+        }
+        matched = true;
+        if symref {
+            for (_, target) in advertisement.symrefs.iter().filter(|(s, _)| s == name) {
+                writeln!(out, "ref: {}\t{}", target, name)?;
+            }
+        }
+        writeln!(out, "{}\t{}", sha, name)?;
+    }
+    out.flush()?;
+    if exit_code && !matched {
+        process::exit(2);
+    }
+    Ok(())
+}
+
+/// The URL of the remote called `name`, or `name` itself when no remote
+/// has that name
+fn repository_url(name: &str) -> io::Result<String> {
+    let config = Config::load()?;
+    Ok(config
+        .get(&format!("remote.{}.url", name))
+        .unwrap_or(name)
+        .to_string())
+}
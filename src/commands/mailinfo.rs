@@ -0,0 +1,52 @@
+// mailinfo: extract the commit information from one patch mail
+//
+// mailinfo [-k] <msg> <patch> < mail
+//
+// The commit message body goes to <msg> and the patch to <patch>; author,
+// email, subject and date are printed:
+//
+// Author: A U Thor
+// Email: author@example.com
+// Subject: Fix the frotz
+// Date: Thu, 7 Apr 2005 15:13:13 -0700
+//
+// -k keeps the subject as it is instead of removing "[PATCH]" style tags.
+
+use crate::git::mailbox;
+use std::fs;
+use std::io::{self, Read};
+
+const USAGE: &str = "Usage: mailinfo [-k] <msg> <patch>";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut keep_subject = false;
+    let mut files = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "-k" => keep_subject = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => files.push(arg),
+        }
+    }
+    let (msg_path, patch_path) = match files.as_slice() {
+        [msg, patch] => (msg, patch),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    };
+
+    let mut mail = Vec::new();
+    io::stdin().read_to_end(&mut mail)?;
+    let info = mailbox::parse(&mail, keep_subject);
+
+    fs::write(msg_path, &info.message)?;
+    fs::write(patch_path, &info.patch)?;
+
+    println!("Author: {}", info.author);
+    println!("Email: {}", info.email);
+    println!("Subject: {}", info.subject);
+    println!("Date: {}", info.date);
+    println!();
+    Ok(())
+}
@@ -0,0 +1,60 @@
+// mailsplit: split an mbox into one file per message
+//
+// mailsplit -o<directory> [-d<prec>] [<mbox>...]
+//
+// Messages are written to <directory>/0001, 0002, ... (with -d, <prec>
+// digits) and the number of messages is printed. Reads stdin when no mbox
+// is given.
+
+use crate::git::mailbox;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: mailsplit -o<directory> [-d<prec>] [<mbox>...]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut dir = None;
+    let mut precision = 4;
+    let mut inputs = Vec::new();
+
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("-o") {
+            dir = Some(PathBuf::from(path));
+        } else if let Some(digits) = arg.strip_prefix("-d") {
+            precision = digits
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0 && n < 10)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+        } else if arg.starts_with('-') && arg != "-" {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+        } else {
+            inputs.push(arg.clone());
+        }
+    }
+    let dir = dir.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+
+    let mut messages = Vec::new();
+    if inputs.is_empty() {
+        inputs.push("-".to_string());
+    }
+    for input in &inputs {
+        let mbox = if input == "-" {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)?;
+            data
+        } else {
+            fs::read(input)?
+        };
+        messages.extend(mailbox::split(&mbox));
+    }
+
+    fs::create_dir_all(&dir)?;
+    for (i, message) in messages.iter().enumerate() {
+        let name = format!("{:0width$}", i + 1, width = precision);
+        fs::write(dir.join(name), message)?;
+    }
+    println!("{}", messages.len());
+    Ok(())
+}
@@ -0,0 +1,444 @@
+// merge: join another commit's history into the current branch
+//
+// merge [--no-ff | --ff-only] [--no-verify] [--[no-]verify-signatures] [-m <msg>] <commit>
+// merge --continue    conclude a conflicted merge once files are resolved
+// merge --abort       return to the pre-merge state
+//
+// A conflicted merge leaves MERGE_HEAD, MERGE_MSG and ORIG_HEAD behind; the
+// merge result is recomputed from them when the merge is continued or aborted.
+//
+// With --verify-signatures (or merge.verifySignatures), the commit being
+// merged must carry a good signature by a key trusted at least as far as
+// gpg.minTrustLevel, marginal by default; otherwise nothing is merged.
+
+use crate::git::commit;
+use crate::git::config::Config;
+use crate::git::index::{Entry, Index};
+use crate::git::merge::{self, ConflictKind, ConflictStyle, Labels, TreeMerge};
+use crate::git::refs;
+use crate::git::signature::{self, Status, Trust};
+use crate::git::tree::{self, FileEntry};
+use crate::git::worktree::{self, Snapshot};
+use crate::git::{hooks, object, revision, tag};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MERGE_HEAD: &str = ".git/MERGE_HEAD";
+const MERGE_MSG: &str = ".git/MERGE_MSG";
+const ORIG_HEAD: &str = ".git/ORIG_HEAD";
+
+const USAGE: &str =
+    "Usage: merge [--no-ff | --ff-only] [--no-verify] [--[no-]verify-signatures] [-m <msg>] <commit> | --continue | --abort";
+
+/// Whether a merge may, must or must not just move the branch forward
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FastForward {
+    Allow,
+    Never,
+    Only,
+}
+
+/// How to merge a commit into the current branch
+pub struct MergeOptions {
+    pub ff: FastForward,
+    /// Run the commit-msg hook on the merge commit's message
+    pub verify: bool,
+    /// Require a good signature on the commit merged
+    pub verify_signatures: bool,
+    /// What the reflog messages start with, "merge <commit>" for a merge
+    pub reflog_action: String,
+    /// Say nothing of how it went unless there are conflicts
+    pub quiet: bool,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut ff = FastForward::Allow;
+    let mut message = None;
+    let mut target = None;
+    let mut verify = true;
+    let mut verify_signatures = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--continue" => return continue_merge(),
+            "--abort" => return abort_merge(),
+            "--no-ff" => ff = FastForward::Never,
+            "--ff-only" => ff = FastForward::Only,
+            "--ff" => ff = FastForward::Allow,
+            "--no-verify" => verify = false,
+            "--verify-signatures" => verify_signatures = Some(true),
+            "--no-verify-signatures" => verify_signatures = Some(false),
+            "-m" => {
+                i += 1;
+                message = Some(args.get(i).cloned().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "Missing message after -m")
+                })?);
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg if target.is_none() => target = Some(arg.to_string()),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        }
+        i += 1;
+    }
+
+    let target = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+    let verify_signatures = match verify_signatures {
+        Some(verify_signatures) => verify_signatures,
+        None => Config::load()?
+            .get_bool("merge.verifySignatures")?
+            .unwrap_or(false),
+    };
+    require_no_merge_in_progress()?;
+    let theirs = resolve_commit(&target)?;
+    let options = MergeOptions {
+        ff,
+        verify,
+        verify_signatures,
+        reflog_action: format!("merge {}", target),
+        quiet: false,
+    };
+    merge_commit(&theirs, &target, message, &options)
+}
+
+/// Refuse to start a merge while a conflicted one waits to be concluded
+pub fn require_no_merge_in_progress() -> io::Result<()> {
+    if Path::new(MERGE_HEAD).exists() {
+        return Err(io::Error::other(
+            "You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge.",
+        ));
+    }
+    Ok(())
+}
+
+/// Merge commit `theirs`, called `target` in messages, into the current
+/// branch: fast-forward to it, or make a merge commit with `message` (by
+/// default "Merge branch '<target>'"), or leave the conflicts to resolve
+pub fn merge_commit(
+    theirs: &str,
+    target: &str,
+    message: Option<String>,
+    options: &MergeOptions,
+) -> io::Result<()> {
+    let (ff, verify) = (options.ff, options.verify);
+    let theirs = theirs.to_string();
+    if options.verify_signatures {
+        check_signature(&theirs)?;
+    }
+    let ours = match refs::head_commit()? {
+        Some(sha) => sha,
+        // Merging into an unborn branch just adopts the other history
+        None => return fast_forward(None, &theirs, options),
+    };
+
+    let bases = merge::merge_bases(&ours, &theirs)?;
+    if bases.contains(&theirs) {
+        if !options.quiet {
+            println!("Already up to date.");
+        }
+        return Ok(());
+    }
+    if bases.contains(&ours) && ff != FastForward::Never {
+        return fast_forward(Some(&ours), &theirs, options);
+    }
+    if ff == FastForward::Only {
+        return Err(io::Error::other("Not possible to fast-forward, aborting."));
+    }
+
+    let message = match message {
+        Some(message) => message,
+        None => default_message(target)?,
+    };
+    let result = compute_merge(&ours, &theirs, &bases, target)?;
+
+    // A rejected message must stop the merge before the worktree changes
+    let message = match result.conflicts.is_empty() && verify {
+        true => hooks::check_commit_message(&message)?,
+        false => message,
+    };
+
+    // Refuses (before writing anything) if local changes would be clobbered
+    let current = worktree::snapshot_of_commit(Some(&ours))?;
+    worktree::update(&current, &result.files, false)?;
+
+    if result.conflicts.is_empty() {
+        let tree_sha = tree::write_snapshot(&result.files)?;
+        let merge_sha = commit::create(&tree_sha, &[ours.clone(), theirs], &message)?;
+        refs::write_ref("ORIG_HEAD", &ours)?;
+        refs::update_head(
+            &merge_sha,
+            &format!(
+                "{}: Merge made by the 'ort' strategy.",
+                options.reflog_action
+            ),
+        )?;
+        if !options.quiet {
+            println!("Merge made by the 'ort' strategy.");
+        }
+        return Ok(());
+    }
+
+    result.stage_conflicts()?;
+    refs::write_ref("ORIG_HEAD", &ours)?;
+    refs::write_ref("MERGE_HEAD", &theirs)?;
+    let mut merge_msg = format!("{}\n\n# Conflicts:\n", message.trim_end());
+    for conflict in &result.conflicts {
+        merge_msg.push_str(&format!("#\t{}\n", conflict.path));
+        println!(
+            "{}",
+            describe_conflict(&conflict.path, conflict.kind, target)
+        );
+    }
+    fs::write(MERGE_MSG, merge_msg)?;
+
+    Err(io::Error::other(
+        "Automatic merge failed; fix conflicts and then run 'merge --continue'.",
+    ))
+}
+
+/// Conclude a conflicted merge once its files are resolved
+fn continue_merge() -> io::Result<()> {
+    let (ours, theirs) = merge_state()?;
+    let bases = merge::merge_bases(&ours, &theirs)?;
+    let result = compute_merge(&ours, &theirs, &bases, &theirs)?;
+
+    let files = resolved_files(result)?;
+
+    let message = strip_comments(&fs::read_to_string(MERGE_MSG)?);
+    let message = hooks::check_commit_message(message.trim_end())?;
+
+    let tree_sha = tree::write_snapshot(&files)?;
+    let merge_sha = commit::create(&tree_sha, &[ours, theirs], message.trim_end())?;
+    refs::update_head(
+        &merge_sha,
+        &format!("commit (merge): {}", message.lines().next().unwrap_or("")),
+    )?;
+    clear_merge_state()?;
+    println!(
+        "[{}] {}",
+        &merge_sha[..7],
+        message.lines().next().unwrap_or("")
+    );
+    Ok(())
+}
+
+/// Put the working tree back to ORIG_HEAD and forget the merge
+fn abort_merge() -> io::Result<()> {
+    let (ours, theirs) = merge_state()?;
+    let bases = merge::merge_bases(&ours, &theirs)?;
+    let result = compute_merge(&ours, &theirs, &bases, &theirs)?;
+
+    // Files written by the merge are overwritten unconditionally
+    let original = worktree::snapshot_of_commit(Some(&ours))?;
+    worktree::update(&result.files, &original, true)?;
+    result.unstage_conflicts(&original)?;
+    clear_merge_state()?;
+    Ok(())
+}
+
+fn compute_merge(
+    ours: &str,
+    theirs: &str,
+    bases: &[String],
+    their_label: &str,
+) -> io::Result<TreeMerge> {
+    // With several best bases (criss-cross), the first one is used
+    let base = bases.first().map(String::as_str);
+    let labels = Labels {
+        base: base.map(|b| &b[..7]).unwrap_or("empty tree"),
+        ours: "HEAD",
+        theirs: their_label,
+    };
+    merge::merge_commits(base, ours, theirs, &labels, conflict_style()?)
+}
+
+/// `merge.conflictStyle` from config
+pub fn conflict_style() -> io::Result<ConflictStyle> {
+    let config = Config::load()?;
+    Ok(ConflictStyle::from_config(
+        config.get("merge.conflictStyle"),
+    ))
+}
+
+/// Final content of a merge whose conflicts the user has resolved: conflicted
+/// paths are taken from the working tree (a deleted file resolves as a
+/// deletion), everything else from the merge result. Fails while any
+/// conflicted file still has conflict markers.
+pub fn resolved_files(result: TreeMerge) -> io::Result<Snapshot> {
+    let mut files = result.files;
+    let mut unresolved = Vec::new();
+    let mut index = Index::load()?;
+    for conflict in &result.conflicts {
+        let path = &conflict.path;
+        let mode = files
+            .get(path)
+            .map(|entry| entry.mode.clone())
+            .unwrap_or_else(|| "100644".to_string());
+        match fs::read(path) {
+            Ok(content) => {
+                if merge::has_conflict_markers(&content) {
+                    unresolved.push(path.as_str());
+                    continue;
+                }
+                let sha = object::write("blob", &content)?;
+                let file = FileEntry { mode, sha };
+                index.add(Entry::checked_out(path, &file)?);
+                files.insert(path.clone(), file);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                index.remove(path);
+                files.remove(path);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if !unresolved.is_empty() {
+        return Err(io::Error::other(format!(
+            "Committing is not possible because you have unmerged files:\n\t{}",
+            unresolved.join("\n\t")
+        )));
+    }
+    index.write()?;
+
+    Ok(files)
+}
+
+/// A message file without its `#` comment lines
+pub fn strip_comments(message: &str) -> String {
+    message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn fast_forward(ours: Option<&str>, theirs: &str, options: &MergeOptions) -> io::Result<()> {
+    let current = worktree::snapshot_of_commit(ours)?;
+    let target = worktree::snapshot_of_commit(Some(theirs))?;
+    worktree::update(&current, &target, false)?;
+
+    if let Some(ours) = ours {
+        if !options.quiet {
+            println!("Updating {}..{}", &ours[..7], &theirs[..7]);
+        }
+        refs::write_ref("ORIG_HEAD", ours)?;
+    }
+    refs::update_head(theirs, &format!("{}: Fast-forward", options.reflog_action))?;
+    if !options.quiet {
+        println!("Fast-forward");
+    }
+    Ok(())
+}
+
+/// Refuse to merge commit `sha` unless it has a good signature by a
+/// sufficiently trusted key
+pub fn check_signature(sha: &str) -> io::Result<()> {
+    let abbrev = &sha[..7];
+    let min_trust = signature::min_trust_level(&Config::load()?, Trust::Marginal)?;
+    let refused = |message: String| Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+    match signature::verify_commit(sha)? {
+        Some(check) if check.is_trusted(min_trust) => {
+            println!(
+                "Commit {} has a good GPG signature by {}",
+                abbrev, check.signer
+            );
+            Ok(())
+        }
+        Some(check) if check.status == Status::Good => refused(format!(
+            "Commit {} has an untrusted GPG signature, allegedly by {}.",
+            abbrev, check.signer
+        )),
+        Some(check) if check.status == Status::Bad => refused(format!(
+            "Commit {} has a bad GPG signature allegedly by {}.",
+            abbrev, check.signer
+        )),
+        _ => refused(format!("Commit {} does not have a GPG signature.", abbrev)),
+    }
+}
+
+fn resolve_commit(name: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} - not something we can merge", name),
+        )
+    })?;
+    let (sha, kind) = tag::peel(&sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} - not something we can merge", name),
+        ));
+    }
+    Ok(sha)
+}
+
+fn default_message(target: &str) -> io::Result<String> {
+    if refs::read_ref(&refs::branch_ref(target))?.is_some() {
+        Ok(format!("Merge branch '{}'", target))
+    } else if refs::read_ref(&refs::tag_ref(target))?.is_some() {
+        Ok(format!("Merge tag '{}'", target))
+    } else {
+        Ok(format!("Merge commit '{}'", target))
+    }
+}
+
+/// The `CONFLICT (...)` line git prints for a conflicted path
+pub fn describe_conflict(path: &str, kind: ConflictKind, theirs: &str) -> String {
+    match kind {
+        ConflictKind::Content => format!("CONFLICT (content): Merge conflict in {}", path),
+        ConflictKind::AddAdd => format!("CONFLICT (add/add): Merge conflict in {}", path),
+        ConflictKind::Binary => format!(
+            "warning: Cannot merge binary files: {} (HEAD vs. {})\nCONFLICT (content): Merge conflict in {}",
+            path, theirs, path
+        ),
+        ConflictKind::ModifyDelete { deleted_by_ours } => {
+            let (deleter, modifier) = if deleted_by_ours {
+                ("HEAD", theirs)
+            } else {
+                (theirs, "HEAD")
+            };
+            format!(
+                "CONFLICT (modify/delete): {} deleted in {} and modified in {}. Version {} of {} left in tree.",
+                path, deleter, modifier, modifier, path
+            )
+        }
+        ConflictKind::FileDirectory { file_in_ours } => {
+            let side = if file_in_ours { "HEAD" } else { theirs };
+            // The conflict is recorded at the path the file was moved to
+            let original = path
+                .strip_suffix(&format!("~{}", side.replace('/', "_")))
+                .or_else(|| path.rfind('~').map(|tilde| &path[..tilde]))
+                .unwrap_or(path);
+            format!(
+                "CONFLICT (file/directory): directory in the way of {} from {}; moving it to {} instead.",
+                original, side, path
+            )
+        }
+    }
+}
+
+fn merge_state() -> io::Result<(String, String)> {
+    let theirs = fs::read_to_string(MERGE_HEAD).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "There is no merge in progress (MERGE_HEAD missing).",
+        )
+    })?;
+    let ours = fs::read_to_string(ORIG_HEAD)?;
+    Ok((ours.trim().to_string(), theirs.trim().to_string()))
+}
+
+fn clear_merge_state() -> io::Result<()> {
+    for path in [MERGE_HEAD, MERGE_MSG] {
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
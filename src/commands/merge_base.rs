@@ -0,0 +1,74 @@
+// merge-base: print the best common ancestor of two commits
+//
+// merge-base [--all] <commit> <commit>
+// merge-base --is-ancestor <commit> <commit>
+//
+// Criss-cross histories can have several equally good bases; --all prints
+// every one of them instead of just the first. Like git, exits with status 1
+// and no output when the commits share no history.
+//
+// --is-ancestor prints nothing, exiting with status 0 if the first commit
+// is an ancestor of the second and 1 if not (see git/reach.rs).
+
+use crate::git::reach::Reachability;
+use crate::git::{merge, revision, tag};
+use std::io::{self, Write};
+use std::process;
+
+const USAGE: &str = "Usage: merge-base [--all | --is-ancestor] <commit> <commit>";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut all = false;
+    let mut is_ancestor = false;
+    let mut commits = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "-a" | "--all" => all = true,
+            "--is-ancestor" => is_ancestor = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => commits.push(resolve_commit(arg)?),
+        }
+    }
+
+    let (a, b) = match commits.as_slice() {
+        [a, b] => (a, b),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    };
+
+    if is_ancestor {
+        let found = Reachability::new()?.is_ancestor(a, b)?;
+        process::exit(if found { 0 } else { 1 });
+    }
+
+    let bases = merge::merge_bases(a, b)?;
+    if bases.is_empty() {
+        process::exit(1);
+    }
+
+    let mut stdout = io::stdout();
+    let shown = if all { &bases[..] } else { &bases[..1] };
+    for base in shown {
+        writeln!(stdout, "{}", base)?;
+    }
+    stdout.flush()
+}
+
+fn resolve_commit(name: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Not a valid object name {}", name),
+        )
+    })?;
+    let (sha, kind) = tag::peel(&sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a commit", name, kind),
+        ));
+    }
+    Ok(sha)
+}
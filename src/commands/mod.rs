@@ -1,7 +1,54 @@
+pub mod am;
+pub mod apply;
+pub mod branch;
+pub mod bugreport;
 pub mod cat_file;
+pub mod check_ref_format;
+pub mod checkout;
 pub mod clone;
+pub mod commit;
 pub mod commit_tree;
+pub mod config;
+pub mod diff;
+pub mod diff_tree;
+pub mod fetch;
+pub mod fetch_pack;
+pub mod grep;
 pub mod hash_object;
 pub mod init;
+pub mod log;
+pub mod ls_remote;
 pub mod ls_tree;
+pub mod mailinfo;
+pub mod mailsplit;
+pub mod merge;
+pub mod merge_base;
+pub mod mv;
+pub mod pack_objects;
+pub mod pack_refs;
+pub mod pull;
+pub mod push;
+pub mod rebase;
+pub mod receive_pack;
+pub mod reflog;
+pub mod remote;
+pub mod reset;
+pub mod rev_list;
+pub mod rev_parse;
+pub mod revert;
+pub mod rm;
+pub mod send_pack;
+pub mod shortlog;
+pub mod show;
+pub mod show_ref;
+pub mod stash;
+pub mod status;
+pub mod submodule;
+pub mod switch;
+pub mod symbolic_ref;
+pub mod tag;
+pub mod update_index;
+pub mod update_ref;
+pub mod upload_pack;
+pub mod worktree;
 pub mod write_tree;
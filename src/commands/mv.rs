@@ -0,0 +1,163 @@
+// mv: move or rename a tracked file or directory
+//
+// mv [-f] [-k] [-n] [-v] <source> <destination>
+// mv [-f] [-k] [-n] [-v] <source>... <destination-directory>
+//
+// The file is renamed on disk and its index entry follows it, keeping the
+// staged content. Moving onto an existing file needs -f; -k skips sources
+// that can't be moved instead of failing, and -n only shows what would be
+// done.
+
+use crate::git::index::Index;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const USAGE: &str = "Usage: mv [-f] [-k] [-n] [-v] <source>... <destination>";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut force = false;
+    let mut skip_errors = false;
+    let mut dry_run = false;
+    let mut verbose = false;
+    let mut paths = Vec::new();
+
+    let mut options_done = false;
+    for arg in args {
+        match arg.as_str() {
+            arg if options_done => paths.push(normalize(arg)),
+            "--" => options_done = true,
+            "-f" | "--force" => force = true,
+            "-k" => skip_errors = true,
+            "-n" | "--dry-run" => dry_run = true,
+            "-v" | "--verbose" => verbose = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => paths.push(normalize(arg)),
+        }
+    }
+    let (destination, sources) = match paths.split_last() {
+        Some((destination, sources)) if !sources.is_empty() => (destination, sources),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    };
+
+    // Several sources, or an existing directory, mean "move into"
+    let into_dir = Path::new(destination).is_dir();
+    if sources.len() > 1 && !into_dir {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("destination '{}' is not a directory", destination),
+        ));
+    }
+
+    let mut index = Index::load()?;
+    let mut moves = Vec::new();
+    for source in sources {
+        let target = if into_dir {
+            let name = source.rsplit('/').next().unwrap_or(source);
+            join(destination, name)
+        } else {
+            destination.clone()
+        };
+        match check_move(&index, source, &target, force) {
+            Ok(()) => moves.push((source.clone(), target)),
+            Err(_) if skip_errors => {}
+            Err(reason) => {
+                return Err(io::Error::other(format!(
+                    "{}, source={}, destination={}",
+                    reason, source, target
+                )))
+            }
+        }
+    }
+
+    for (source, target) in &moves {
+        if dry_run {
+            println!("Checking rename of '{}' to '{}'", source, target);
+        }
+        if dry_run || verbose {
+            println!("Renaming {} to {}", source, target);
+        }
+        if dry_run {
+            continue;
+        }
+        fs::rename(source, target)?;
+        rename_entries(&mut index, source, target);
+    }
+    if dry_run {
+        return Ok(());
+    }
+    index.write()
+}
+
+/// Why `source` can't be moved to `target`, in git's words
+fn check_move(index: &Index, source: &str, target: &str, force: bool) -> Result<(), &'static str> {
+    let meta = fs::symlink_metadata(source).map_err(|_| "bad source")?;
+    let is_dir = meta.is_dir() && index.get(source).is_none();
+
+    if is_dir {
+        if !has_entries_under(index, source) {
+            return Err("source directory is empty");
+        }
+        if target == source || target.starts_with(&format!("{}/", source)) {
+            return Err("can not move directory into itself");
+        }
+    } else if index.get(source).is_none() {
+        return Err("not under version control");
+    }
+
+    if let Ok(existing) = fs::symlink_metadata(target) {
+        // Only a file may replace a file, and only when forced
+        if is_dir || existing.is_dir() || !force {
+            return Err("destination exists");
+        }
+    }
+    let parent = Path::new(target)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty());
+    if parent.is_some_and(|dir| !dir.is_dir()) {
+        return Err("destination directory does not exist");
+    }
+    Ok(())
+}
+
+/// Re-stage what was at `source` (a file, or every file of a directory)
+/// under `target`
+fn rename_entries(index: &mut Index, source: &str, target: &str) {
+    let prefix = format!("{}/", source);
+    let moved: Vec<_> = index
+        .entries()
+        .iter()
+        .filter(|e| e.path == source || e.path.starts_with(&prefix))
+        .cloned()
+        .collect();
+
+    index.remove(target);
+    for mut entry in moved {
+        index.remove(&entry.path);
+        entry.path = format!("{}{}", target, &entry.path[source.len()..]);
+        index.add(entry);
+    }
+}
+
+fn has_entries_under(index: &Index, dir: &str) -> bool {
+    let prefix = format!("{}/", dir);
+    index.entries().iter().any(|e| e.path.starts_with(&prefix))
+}
+
+/// Paths are relative to the top of the repository
+fn normalize(path: &str) -> String {
+    match path.trim_start_matches("./").trim_end_matches('/') {
+        "" => ".".to_string(),
+        path => path.to_string(),
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() || dir == "." {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
@@ -0,0 +1,93 @@
+// pack-objects: write a pack of the objects listed on standard input
+//
+// pack-objects --stdout [--revs] [--all] [--honor-pack-keep] < <list>
+//
+// Without --revs every input line names one object to pack (anything after
+// the id, such as the path rev-list --objects prints, is ignored). With
+// --revs the lines are revisions instead, and the pack holds everything
+// reachable from them: a revision prefixed with "^", or given after a
+// "--not" line, excludes everything reachable from it. --all adds every ref
+// and HEAD to the revisions. --honor-pack-keep leaves out objects already
+// stored in a local pack marked with a .keep file. The pack is written to
+// standard output; writing a pack and index into the repository isn't
+// supported.
+
+use crate::git::{pack, refs, revision};
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufWriter, Write};
+
+const USAGE: &str = "Usage: pack-objects --stdout [--revs] [--all] [--honor-pack-keep] < <list>";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut stdout = false;
+    let mut revs = false;
+    let mut all = false;
+    let mut honor_pack_keep = false;
+    for arg in args {
+        match arg.as_str() {
+            "--stdout" => stdout = true,
+            "--revs" => revs = true,
+            "--all" => all = true,
+            "--honor-pack-keep" => honor_pack_keep = true,
+            _ => return Err(usage()),
+        }
+    }
+    if !stdout || (all && !revs) {
+        return Err(usage());
+    }
+
+    let mut objects = Vec::new();
+    let mut tips = Vec::new();
+    let mut known = Vec::new();
+    let mut negated = false;
+    if all {
+        tips.extend(refs::head_commit()?);
+        tips.extend(refs::list_refs("refs/")?.into_iter().map(|(_, sha)| sha));
+    }
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if !revs {
+            let id = line.split_whitespace().next().unwrap_or(line);
+            objects.push(object_id(id)?);
+            continue;
+        }
+        match line.strip_prefix('^') {
+            _ if line == "--not" => negated = !negated,
+            Some(rev) => known.push(object_id(rev)?),
+            None if negated => known.push(object_id(line)?),
+            None => tips.push(object_id(line)?),
+        }
+    }
+    if revs {
+        objects = pack::objects_to_send(&tips, &known)?;
+    } else {
+        let mut seen = HashSet::new();
+        objects.retain(|sha| seen.insert(sha.clone()));
+    }
+    if honor_pack_keep {
+        let kept = pack::kept_objects()?;
+        objects.retain(|sha| !kept.contains(sha));
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    pack::write_to(&objects, &mut out)?;
+    out.flush()
+}
+
+/// The id `rev` names
+fn object_id(rev: &str) -> io::Result<String> {
+    revision::resolve(rev)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("bad revision '{}'", rev),
+        )
+    })
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
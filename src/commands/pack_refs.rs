@@ -0,0 +1,29 @@
+// pack-refs: collect loose refs into .git/packed-refs
+//
+// pack-refs [--all] [--no-prune]
+//
+// Moves the tags, or with --all every ref under refs/ that isn't symbolic,
+// into .git/packed-refs, recording the object each annotated tag points at
+// on a "^<sha>" line after it. The loose files are removed afterwards
+// unless --no-prune is given. Branches are left loose by default since
+// they keep moving, and a ref that is updated later is written loose again
+// and shadows its packed value.
+
+use crate::git::refs;
+use std::io;
+
+const USAGE: &str = "Usage: pack-refs [--all] [--no-prune]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut all = false;
+    let mut prune = true;
+    for arg in args {
+        match arg.as_str() {
+            "--all" => all = true,
+            "--no-prune" => prune = false,
+            "--prune" => prune = true,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+    refs::pack_refs(all, prune)
+}
@@ -0,0 +1,138 @@
+// pull: fetch from another repository and merge what was fetched
+//
+// pull [-q | -v] [--ff | --no-ff | --ff-only] [--no-verify] [--[no-]verify-signatures]
+//      [<remote> [<refspec>...]]
+//
+// Fetches as `fetch` does, then merges the merge candidate it found into
+// the current branch: the ref of the <refspec> given, or else the branch
+// the current branch tracks (branch.<name>.remote and branch.<name>.merge).
+// The branch is fast-forwarded when it can be, unless --no-ff (or pull.ff
+// false) asks for a merge commit every time or --ff-only (pull.ff only)
+// refuses anything else. The merge commit is "Merge branch '<branch>' of
+// <url>", with "into <current>" unless that is master or main. As with
+// merge, --verify-signatures (or merge.verifySignatures, which the option
+// overrides) refuses to merge a commit without a trusted signature. Rebasing
+// instead of merging isn't supported.
+
+use crate::commands::fetch::{self, FetchOptions};
+use crate::commands::merge::{self, FastForward, MergeOptions};
+use crate::git::config::Config;
+use crate::git::progress::Progress;
+use crate::git::refs;
+use std::io;
+use std::process;
+
+const USAGE: &str = "Usage: pull [-q | -v] [--ff | --no-ff | --ff-only] [--no-verify] [--[no-]verify-signatures] [<remote> [<refspec>...]]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut quiet = false;
+    let mut verbose = false;
+    let mut ff = None;
+    let mut verify = true;
+    let mut verify_signatures = None;
+    let mut positional = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            "-v" | "--verbose" => verbose = true,
+            "--ff" => ff = Some(FastForward::Allow),
+            "--no-ff" => ff = Some(FastForward::Never),
+            "--ff-only" => ff = Some(FastForward::Only),
+            "--no-verify" => verify = false,
+            "--verify-signatures" => verify_signatures = Some(true),
+            "--no-verify-signatures" => verify_signatures = Some(false),
+            "-r" | "--rebase" => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "pull --rebase is not supported yet",
+                ))
+            }
+            arg if arg.starts_with('-') && arg.len() > 1 => return Err(usage()),
+            arg => positional.push(arg),
+        }
+    }
+    let (remote, refspecs) = match positional.split_first() {
+        Some((remote, refspecs)) => (Some(*remote), refspecs),
+        None => (None, &[][..]),
+    };
+
+    let config = Config::load()?;
+    let ff = match ff {
+        Some(ff) => ff,
+        None => match config.get("pull.ff") {
+            Some("only") => FastForward::Only,
+            _ if config.get_bool("pull.ff")? == Some(false) => FastForward::Never,
+            _ => FastForward::Allow,
+        },
+    };
+    let verify_signatures = match verify_signatures {
+        Some(verify_signatures) => verify_signatures,
+        None => config.get_bool("merge.verifySignatures")?.unwrap_or(false),
+    };
+    merge::require_no_merge_in_progress()?;
+
+    let mut progress = if verbose {
+        Progress::new(|line| eprintln!("{}", line))
+    } else {
+        Progress::silent()
+    };
+    let outcome = fetch::fetch(remote, refspecs, &FetchOptions::default(), &mut progress)?;
+    if !quiet {
+        fetch::report(&outcome, verbose)?;
+    }
+    if outcome.rejected() {
+        process::exit(1);
+    }
+
+    let mut candidates: Vec<&fetch::RefUpdate> = Vec::new();
+    for update in outcome.updates.iter().filter(|update| update.for_merge) {
+        if candidates.iter().all(|known| known.src != update.src) {
+            candidates.push(update);
+        }
+    }
+    let candidate = match candidates.as_slice() {
+        [candidate] => candidate,
+        [] => {
+            return Err(io::Error::other(
+                "There is no tracking information for the current branch.\n\
+                 Please specify which branch you want to merge with.",
+            ))
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Merging more than one branch is not supported",
+            ))
+        }
+    };
+
+    let mut message = match fetch::describe(&candidate.src) {
+        Some(what) => format!("Merge {} of {}", what, outcome.url),
+        None => format!("Merge {}", outcome.url),
+    };
+    if let refs::Head::Branch(current) = refs::read_head()? {
+        let branch = current.strip_prefix("refs/heads/").unwrap_or(&current);
+        if branch != "master" && branch != "main" {
+            message.push_str(&format!(" into {}", branch));
+        }
+    }
+
+    let reflog_action = ["pull"]
+        .into_iter()
+        .chain(args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let options = MergeOptions {
+        ff,
+        verify,
+        verify_signatures,
+        reflog_action,
+        quiet,
+    };
+    let target = candidate.src.clone();
+    merge::merge_commit(&candidate.new, &target, Some(message), &options)
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -0,0 +1,358 @@
+// push: update remote refs along with the objects they need
+//
+// push [-n] [-f] [-q] [-v] [-u] [--all] [--tags] [--follow-tags] [--atomic]
+//      [--signed[=(true|false|if-asked)]] [-o <option>]...
+//      [<repository> [<refspec>...]]
+// push [-n] [-q] [-v] (-d | --delete) <repository> <ref>...
+//
+// <repository> is a remote or a URL, by default branch.<name>.pushRemote,
+// remote.pushDefault, branch.<name>.remote or origin, and a remote is
+// pushed to at remote.<name>.pushurl if it has one, else at its url. The
+// refspecs are those of send-pack, and may have a "*" to push every ref it
+// matches. Without any, --all pushes every branch and otherwise
+// remote.<name>.push names what to push, or failing that push.default:
+//
+//   simple (the default)  the current branch to its upstream, which must
+//                         have the same name; to the branch of that name
+//                         on a remote it doesn't track
+//   current               the current branch to the branch of that name
+//   upstream              the current branch to its upstream
+//   matching              every branch that exists on both sides
+//   nothing               nothing, which is an error
+//
+// A tag is pushed by naming it, "push origin v1.0", and only replaces a
+// remote tag with --force. --tags pushes every tag as well, and
+// --follow-tags (or push.followTags) the annotated tags missing from the
+// remote that point at commits being pushed. --delete deletes each <ref>
+// on the remote, as a refspec ":<ref>" does; a remote that doesn't
+// advertise delete-refs refuses deletions.
+//
+// -o (--push-option) hands a string to the remote's hooks, push.pushOption
+// giving the options when there are none on the command line. --signed
+// (or push.gpgSign) sends a gpg-signed push certificate; "if-asked" signs
+// only when the remote supports it. --atomic has the remote update every
+// ref or none of them, as send-pack --atomic does.
+//
+// After a push the remote-tracking refs for what was updated are moved as
+// the next fetch would move them, and -u makes each branch pushed track the
+// branch it was pushed to.
+
+use crate::commands::send_pack::{self, SendOptions, Signed, Status};
+use crate::git::config::{self, Config};
+use crate::git::refspec::Refspec;
+use crate::git::{dry_run, refs, repository};
+use std::io::{self, Write};
+use std::path::Path;
+
+const USAGE: &str = "Usage: push [-n] [-f] [-q] [-v] [-u] [--all] [--tags] [--follow-tags] [--atomic] [-d] [--signed[=<when>]] [-o <option>]... [<repository> [<refspec>...]]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = SendOptions::default();
+    let mut quiet = false;
+    let mut verbose = false;
+    let mut set_upstream = false;
+    let mut tags = false;
+    let mut follow_tags = None;
+    let mut delete = false;
+    let mut signed = None;
+    let mut push_options = Vec::new();
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" | "--dry-run" => {
+                options.dry_run = true;
+                dry_run::enable();
+            }
+            "-f" | "--force" => options.force = true,
+            "-q" | "--quiet" => {
+                quiet = true;
+                options.quiet = true;
+            }
+            "-v" | "--verbose" => verbose = true,
+            "-u" | "--set-upstream" => set_upstream = true,
+            "--all" => options.all = true,
+            "--tags" => tags = true,
+            "--follow-tags" => follow_tags = Some(true),
+            "--no-follow-tags" => follow_tags = Some(false),
+            "-d" | "--delete" => delete = true,
+            "--atomic" => options.atomic = true,
+            "--no-atomic" => options.atomic = false,
+            "--signed" => signed = Some(Signed::Yes),
+            "--no-signed" => signed = Some(Signed::No),
+            "-o" | "--push-option" => push_options.push(args.next().ok_or_else(usage)?.clone()),
+            arg if arg.starts_with("--push-option=") => {
+                push_options.push(arg["--push-option=".len()..].to_string())
+            }
+            arg if arg.starts_with("--signed=") => {
+                signed = Some(Signed::parse(&arg["--signed=".len()..]).ok_or_else(usage)?)
+            }
+            arg if arg.starts_with('-') && arg.len() > 1 => return Err(usage()),
+            arg => positional.push(arg),
+        }
+    }
+    if options.all && positional.len() > 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--all can't be combined with refspecs",
+        ));
+    }
+
+    if delete {
+        if options.all || tags || positional.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--delete doesn't make sense without any refs",
+            ));
+        }
+        if positional[1..].iter().any(|name| name.contains(':')) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--delete only accepts plain target ref names",
+            ));
+        }
+    }
+
+    let config = Config::load()?;
+    options.follow_tags = match follow_tags {
+        Some(follow) => follow,
+        None => config.get_bool("push.followTags")?.unwrap_or(false),
+    };
+    options.signed = match (signed, config.get("push.gpgSign")) {
+        (Some(signed), _) => signed,
+        (None, Some(value)) => Signed::parse(value).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad numeric config value '{}' for 'push.gpgsign'", value),
+            )
+        })?,
+        (None, None) => Signed::No,
+    };
+    // An empty push.pushOption clears the ones before it
+    options.push_options = match push_options.is_empty() {
+        true => {
+            config
+                .get_all("push.pushOption")
+                .into_iter()
+                .fold(Vec::new(), |mut all, option| {
+                    match option.is_empty() {
+                        true => all.clear(),
+                        false => all.push(option.to_string()),
+                    }
+                    all
+                })
+        }
+        false => push_options,
+    };
+    let current = match refs::read_head()? {
+        refs::Head::Branch(branch) => Some(branch),
+        refs::Head::Detached(_) => None,
+    };
+    let branch_config = |key: &str| {
+        let branch = current.as_deref()?.strip_prefix("refs/heads/")?;
+        config.get(&format!("branch.{}.{}", branch, key))
+    };
+    let name = match positional.first() {
+        Some(name) => *name,
+        None => branch_config("pushRemote")
+            .or_else(|| config.get("remote.pushDefault"))
+            .or_else(|| branch_config("remote"))
+            .unwrap_or("origin"),
+    };
+    let repo_url = match config
+        .get(&format!("remote.{}.pushurl", name))
+        .or_else(|| config.get(&format!("remote.{}.url", name)))
+    {
+        Some(url) => url,
+        None if name.contains(':') || Path::new(name).exists() => name,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("'{}' does not appear to be a git repository", name),
+            ))
+        }
+    };
+
+    let local_refs: Vec<String> = refs::list_refs("refs/")?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    let requested: Vec<&str> = if positional.len() > 1 {
+        positional[1..].to_vec()
+    } else {
+        config.get_all(&format!("remote.{}.push", name))
+    };
+    let mut specs = Vec::new();
+    for spec in requested {
+        match delete {
+            true => specs.push(format!(":{}", spec)),
+            false => specs.extend(expand(spec, current.as_deref(), &local_refs)?),
+        }
+    }
+    if specs.is_empty() && !options.all && !tags {
+        specs = default_refspecs(&config, name, current.as_deref())?;
+    }
+    if tags {
+        specs.extend(
+            local_refs
+                .iter()
+                .filter(|name| name.starts_with("refs/tags/"))
+                .cloned(),
+        );
+    }
+
+    let outcome = send_pack::send(repo_url, &specs, &options)?;
+    if !quiet || outcome.rejected() {
+        send_pack::report(&outcome, verbose, &mut io::stderr().lock())?;
+    }
+    if options.dry_run {
+        return send_pack::finish(outcome.rejected(), repo_url);
+    }
+
+    let fetch_specs = config
+        .get_all(&format!("remote.{}.fetch", name))
+        .into_iter()
+        .map(Refspec::parse)
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut out = io::stdout().lock();
+    for update in &outcome.updates {
+        if !matches!(update.status, Status::Ok | Status::UpToDate) {
+            continue;
+        }
+        for spec in &fetch_specs {
+            for (_, tracking) in spec.select(&[update.dst.as_str()]) {
+                let Some(tracking) = tracking else {
+                    continue;
+                };
+                let local = refs::read_ref(&tracking)?;
+                match &update.new {
+                    Some(new) if local.as_ref() != Some(new) => {
+                        refs::update_ref(&tracking, new, "update by push")?
+                    }
+                    None if local.is_some() => refs::delete_ref(&tracking)?,
+                    _ => {}
+                }
+            }
+        }
+
+        let branch = update
+            .src_ref
+            .as_deref()
+            .and_then(|src| src.strip_prefix("refs/heads/"));
+        let merge = update.dst.strip_prefix("refs/heads/");
+        if let (true, Some(branch), Some(merge), Some(_)) =
+            (set_upstream, branch, merge, &update.new)
+        {
+            let path = &repository::path("config");
+            config::set_value(path, &format!("branch.{}.remote", branch), name)?;
+            config::set_value(path, &format!("branch.{}.merge", branch), &update.dst)?;
+            if !quiet {
+                writeln!(
+                    out,
+                    "branch '{}' set up to track '{}/{}'.",
+                    branch, name, merge
+                )?;
+            }
+        }
+    }
+    send_pack::finish(outcome.rejected(), repo_url)
+}
+
+/// `spec` as send-pack takes it: "HEAD" standing for the current branch,
+/// and a pattern made into a refspec for each local ref it matches
+fn expand(spec: &str, current: Option<&str>, local_refs: &[String]) -> io::Result<Vec<String>> {
+    let (force, rest) = match spec.strip_prefix('+') {
+        Some(rest) => ("+", rest),
+        None => ("", spec),
+    };
+    let (src, dst) = match rest.split_once(':') {
+        Some((src, dst)) => (src, Some(dst)),
+        None => (rest, None),
+    };
+    if src == "HEAD" {
+        if let Some(current) = current {
+            let dst = dst.unwrap_or(current);
+            return Ok(vec![format!("{}{}:{}", force, current, dst)]);
+        }
+    }
+    if !src.contains('*') {
+        return Ok(vec![spec.to_string()]);
+    }
+    let names: Vec<&str> = local_refs.iter().map(String::as_str).collect();
+    let pattern = Refspec::parse(spec)?;
+    Ok(pattern
+        .select(&names)
+        .into_iter()
+        .filter_map(|(src, dst)| Some(format!("{}{}:{}", force, src, dst?)))
+        .collect())
+}
+
+/// What push.default says to push to the remote `remote` when nothing is
+/// named; empty for "matching", which send-pack does without refspecs
+fn default_refspecs(
+    config: &Config,
+    remote: &str,
+    current: Option<&str>,
+) -> io::Result<Vec<String>> {
+    let mode = config.get("push.default").unwrap_or("simple");
+    let not_on_branch = || {
+        io::Error::other(format!(
+            "You are not currently on a branch.\n\
+             To push the history leading to the current (detached HEAD)\n\
+             state now, use\n\n    push {} HEAD:<name-of-remote-branch>\n",
+            remote
+        ))
+    };
+    match mode {
+        "nothing" => {
+            return Err(io::Error::other(
+                "You didn't specify any refspecs to push, and push.default is \"nothing\".",
+            ))
+        }
+        "matching" => return Ok(Vec::new()),
+        "current" | "simple" | "upstream" | "tracking" => {}
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("malformed value for push.default: {}", mode),
+            ))
+        }
+    }
+    let current = current.ok_or_else(not_on_branch)?;
+    let branch = current.strip_prefix("refs/heads/").unwrap_or(current);
+    // A branch without a remote of its own pulls from origin
+    let upstream_remote = config
+        .get(&format!("branch.{}.remote", branch))
+        .unwrap_or("origin");
+    let triangular = upstream_remote != remote;
+    if mode == "current" || (mode == "simple" && triangular) {
+        return Ok(vec![format!("{}:{}", current, current)]);
+    }
+    if triangular {
+        return Err(io::Error::other(format!(
+            "You are pushing to remote '{}', which is not the upstream of\n\
+             your current branch '{}', without telling me what to push\n\
+             to update which remote branch.",
+            remote, branch
+        )));
+    }
+    let Some(merge) = config.get(&format!("branch.{}.merge", branch)) else {
+        return Err(io::Error::other(format!(
+            "The current branch {} has no upstream branch.\n\
+             To push the current branch and set the remote as upstream, use\n\n    \
+             push --set-upstream {} {}\n",
+            branch, remote, branch
+        )));
+    };
+    if mode == "simple" && merge != current {
+        return Err(io::Error::other(
+            "The upstream branch of your current branch does not match\n\
+             the name of your current branch.",
+        ));
+    }
+    Ok(vec![format!("{}:{}", current, merge)])
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
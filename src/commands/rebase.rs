@@ -0,0 +1,526 @@
+// rebase: replay the current branch's own commits on top of another commit
+//
+// rebase [--[no-]autosquash] <upstream>
+// rebase --continue   commit the resolved conflict and carry on
+// rebase --skip       drop the commit that stopped and carry on
+// rebase --abort      return the branch and working tree to where they were
+//
+// The commits reachable from HEAD but not from <upstream> are cherry-picked
+// oldest first onto a detached HEAD at <upstream>; merge commits are
+// dropped, as are commits whose changes are already upstream. When every
+// commit has been applied the branch is moved to the result.
+//
+// With --autosquash (or rebase.autoSquash), a commit whose subject is
+// "fixup! <x>" or "squash! <x>" is moved to just after the first commit
+// that <x> names, by its subject, the start of its subject or its id, and
+// melded into it: a fixup keeps that commit's message, a squash adds its
+// own message (less the "squash!" line) to it.
+//
+// Progress lives in .git/rebase-apply/:
+//   head-name   branch being rebased (or "detached HEAD")
+//   orig-head   where HEAD was before the rebase
+//   onto        the new base
+//   todo        steps still to take, the one that stopped first, each
+//               "pick", "fixup" or "squash" and a commit
+
+use crate::commands::merge as merge_cmd;
+use crate::git::commit::{self, Commit};
+use crate::git::config::Config;
+use crate::git::merge::{self, Labels, TreeMerge};
+use crate::git::refs::{self, Head};
+use crate::git::worktree::{self, Snapshot};
+use crate::git::{revision, tag, tree};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const STATE_DIR: &str = ".git/rebase-apply";
+const DETACHED: &str = "detached HEAD";
+
+const USAGE: &str = "Usage: rebase [--[no-]autosquash] <upstream> | --continue | --skip | --abort";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Pick,
+    /// Meld into the commit before, keeping its message
+    Fixup,
+    /// Meld into the commit before, adding to its message
+    Squash,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Action::Pick => "pick",
+            Action::Fixup => "fixup",
+            Action::Squash => "squash",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    action: Action,
+    sha: String,
+}
+
+impl Step {
+    fn pick(sha: String) -> Step {
+        Step {
+            action: Action::Pick,
+            sha,
+        }
+    }
+
+    fn parse(line: &str) -> io::Result<Step> {
+        let (action, sha) = line.split_once(' ').unwrap_or(("pick", line));
+        let action = match action {
+            "pick" => Action::Pick,
+            "fixup" => Action::Fixup,
+            "squash" => Action::Squash,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid line in {}/todo: {}", STATE_DIR, line),
+                ))
+            }
+        };
+        Ok(Step {
+            action,
+            sha: sha.to_string(),
+        })
+    }
+}
+
+struct State {
+    head_name: String,
+    orig_head: String,
+    onto: String,
+    todo: Vec<Step>,
+}
+
+impl State {
+    fn load() -> io::Result<State> {
+        let dir = Path::new(STATE_DIR);
+        if !dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No rebase in progress?",
+            ));
+        }
+        if dir.join("applying").exists() {
+            return Err(io::Error::other(
+                "It looks like 'am' is in progress. Cannot rebase.",
+            ));
+        }
+        let read = |name: &str| -> io::Result<String> {
+            Ok(fs::read_to_string(dir.join(name))?.trim().to_string())
+        };
+        Ok(State {
+            head_name: read("head-name")?,
+            orig_head: read("orig-head")?,
+            onto: read("onto")?,
+            todo: read("todo")?
+                .lines()
+                .map(Step::parse)
+                .collect::<io::Result<_>>()?,
+        })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let dir = Path::new(STATE_DIR);
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("head-name"), format!("{}\n", self.head_name))?;
+        fs::write(dir.join("orig-head"), format!("{}\n", self.orig_head))?;
+        fs::write(dir.join("onto"), format!("{}\n", self.onto))?;
+        let todo: String = self
+            .todo
+            .iter()
+            .map(|step| format!("{} {}\n", step.action.name(), step.sha))
+            .collect();
+        fs::write(dir.join("todo"), todo)
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    match args {
+        [flag] if flag == "--continue" => continue_rebase(),
+        [flag] if flag == "--skip" => skip(),
+        [flag] if flag == "--abort" => abort(),
+        _ => {
+            let mut autosquash = None;
+            let mut upstream = None;
+            for arg in args {
+                match arg.as_str() {
+                    "--autosquash" => autosquash = Some(true),
+                    "--no-autosquash" => autosquash = Some(false),
+                    arg if !arg.starts_with('-') && upstream.is_none() => upstream = Some(arg),
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+                }
+            }
+            let upstream =
+                upstream.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+            let autosquash = match autosquash {
+                Some(autosquash) => autosquash,
+                None => Config::load()?
+                    .get_bool("rebase.autoSquash")?
+                    .unwrap_or(false),
+            };
+            start(upstream, autosquash)
+        }
+    }
+}
+
+fn start(upstream: &str, autosquash: bool) -> io::Result<()> {
+    if Path::new(STATE_DIR).exists() {
+        return Err(io::Error::other(
+            "It seems that there is already a rebase-apply directory.\nUse 'rebase --continue', '--skip' or '--abort'.",
+        ));
+    }
+    if Path::new(".git/MERGE_HEAD").exists() {
+        return Err(io::Error::other(
+            "You have not concluded your merge (MERGE_HEAD exists).",
+        ));
+    }
+
+    let onto = resolve_commit(upstream)?;
+    let orig_head = refs::head_commit()?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cannot rebase an unborn branch"))?;
+    let head_name = match refs::read_head()? {
+        Head::Branch(name) => name,
+        Head::Detached(_) => DETACHED.to_string(),
+    };
+    let branch = head_name.strip_prefix("refs/heads/").unwrap_or(&head_name);
+
+    let commits = commits_to_replay(&orig_head, &onto)?;
+    let todo = if autosquash {
+        squash_order(commits)?
+    } else {
+        commits.into_iter().map(Step::pick).collect()
+    };
+    // Already on top of upstream, unless there is something to squash
+    let melds = todo.iter().any(|step| step.action != Action::Pick);
+    if !melds && merge::is_ancestor(&onto, &orig_head)? {
+        println!("Current branch {} is up to date.", branch);
+        return Ok(());
+    }
+    let state = State {
+        head_name: head_name.clone(),
+        orig_head: orig_head.clone(),
+        onto: onto.clone(),
+        todo,
+    };
+
+    // Move to the new base; this refuses if local changes would be lost
+    let current = worktree::snapshot_of_commit(Some(&orig_head))?;
+    let target = worktree::snapshot_of_commit(Some(&onto))?;
+    worktree::update(&current, &target, false)?;
+    refs::update_head_detached(&onto, &format!("rebase (start): checkout {}", upstream))?;
+    refs::write_ref("ORIG_HEAD", &orig_head)?;
+
+    if state.todo.is_empty() {
+        // Nothing of our own: the branch simply catches up with upstream
+        finish(&state)?;
+        println!("Fast-forwarded {} to {}.", branch, upstream);
+        return Ok(());
+    }
+
+    state.save()?;
+    replay(state)
+}
+
+fn continue_rebase() -> io::Result<()> {
+    let mut state = State::load()?;
+    if let Some(step) = state.todo.first().cloned() {
+        let head = current_head()?;
+        let original = commit::read(&step.sha)?;
+        let result = compute_pick(&head, &step.sha, &original)?;
+        let files = merge_cmd::resolved_files(result)?;
+        commit_step(&head, &step, &original, &files, "continue")?;
+        state.todo.remove(0);
+        state.save()?;
+    }
+    replay(state)
+}
+
+fn skip() -> io::Result<()> {
+    let mut state = State::load()?;
+    if let Some(step) = state.todo.first().cloned() {
+        let head = current_head()?;
+        let original = commit::read(&step.sha)?;
+        let result = compute_pick(&head, &step.sha, &original)?;
+        let head_files = worktree::snapshot_of_commit(Some(&head))?;
+        worktree::update(&result.files, &head_files, true)?;
+        result.unstage_conflicts(&head_files)?;
+        state.todo.remove(0);
+        state.save()?;
+    }
+    replay(state)
+}
+
+fn abort() -> io::Result<()> {
+    let state = State::load()?;
+    let head = current_head()?;
+
+    // The working tree holds either HEAD or a conflicted pick on top of it
+    let pick = match state.todo.first() {
+        Some(step) => Some(compute_pick(&head, &step.sha, &commit::read(&step.sha)?)?),
+        None => None,
+    };
+    let in_tree = match &pick {
+        Some(pick) => pick.files.clone(),
+        None => worktree::snapshot_of_commit(Some(&head))?,
+    };
+    let original = worktree::snapshot_of_commit(Some(&state.orig_head))?;
+    worktree::update(&in_tree, &original, true)?;
+    if let Some(pick) = &pick {
+        pick.unstage_conflicts(&original)?;
+    }
+
+    let message = format!("rebase (abort): returning to {}", state.head_name);
+    if state.head_name == DETACHED {
+        refs::update_head_detached(&state.orig_head, &message)?;
+    } else {
+        refs::update_head_symbolic(&state.head_name, &message)?;
+    }
+    fs::remove_dir_all(STATE_DIR)
+}
+
+/// Apply the remaining commits one by one, stopping at the first conflict
+fn replay(mut state: State) -> io::Result<()> {
+    while let Some(step) = state.todo.first().cloned() {
+        let sha = &step.sha;
+        let head = current_head()?;
+        let original = commit::read(sha)?;
+        let result = compute_pick(&head, sha, &original)?;
+
+        let current = worktree::snapshot_of_commit(Some(&head))?;
+        worktree::update(&current, &result.files, false)?;
+
+        if !result.conflicts.is_empty() {
+            result.stage_conflicts()?;
+            let label = pick_label(sha, &original);
+            for conflict in &result.conflicts {
+                println!(
+                    "{}",
+                    merge_cmd::describe_conflict(&conflict.path, conflict.kind, &label)
+                );
+            }
+            return Err(io::Error::other(format!(
+                "could not apply {}... {}\nResolve all conflicts manually, then run 'rebase --continue'.\nYou can instead skip this commit with 'rebase --skip', or run 'rebase --abort' to return to the state before the rebase.",
+                &sha[..7],
+                original.summary()
+            )));
+        }
+
+        commit_step(&head, &step, &original, &result.files, step.action.name())?;
+        state.todo.remove(0);
+        state.save()?;
+    }
+
+    finish(&state)?;
+    fs::remove_dir_all(STATE_DIR)?;
+    println!("Successfully rebased and updated {}.", state.head_name);
+    Ok(())
+}
+
+/// Point the rebased branch at the final commit and re-attach HEAD
+fn finish(state: &State) -> io::Result<()> {
+    if state.head_name == DETACHED {
+        return Ok(());
+    }
+    let head = current_head()?;
+    refs::update_ref(
+        &state.head_name,
+        &head,
+        &format!("rebase (finish): {} onto {}", state.head_name, state.onto),
+    )?;
+    refs::update_head_symbolic(
+        &state.head_name,
+        &format!("rebase (finish): returning to {}", state.head_name),
+    )
+}
+
+/// Cherry-pick: a three-way merge of HEAD and the commit, based on the
+/// commit's parent
+fn compute_pick(head: &str, sha: &str, original: &Commit) -> io::Result<TreeMerge> {
+    let base_label = format!("parent of {}", pick_label(sha, original));
+    let their_label = pick_label(sha, original);
+    let labels = Labels {
+        base: &base_label,
+        ours: "HEAD",
+        theirs: &their_label,
+    };
+    let parent = original.parents.first().map(String::as_str);
+    merge::merge_commits(parent, head, sha, &labels, merge_cmd::conflict_style()?)
+}
+
+/// Record a picked commit on top of HEAD, keeping its author and message,
+/// and log it as `rebase (<action>)`. A pick that changes nothing is
+/// dropped; a fixup or squash replaces HEAD with HEAD amended by it.
+fn commit_step(
+    head: &str,
+    step: &Step,
+    original: &Commit,
+    files: &Snapshot,
+    action: &str,
+) -> io::Result<()> {
+    let sha = &step.sha;
+    let tree_sha = tree::write_snapshot(files)?;
+    let head_commit = commit::read(head)?;
+    if step.action != Action::Pick {
+        let message = match step.action {
+            Action::Squash => squashed_message(&head_commit.message, &original.message),
+            _ => head_commit.message.clone(),
+        };
+        let new_sha = commit::create_with_author(
+            &tree_sha,
+            &head_commit.parents,
+            &head_commit.author,
+            &message,
+        )?;
+        return refs::update_head_detached(
+            &new_sha,
+            &format!("rebase ({}): {}", action, head_commit.summary()),
+        );
+    }
+    if tree_sha == head_commit.tree {
+        println!(
+            "dropping {} {} -- patch contents already upstream",
+            sha,
+            original.summary()
+        );
+        return Ok(());
+    }
+    let new_sha = commit::create_with_author(
+        &tree_sha,
+        &[head.to_string()],
+        &original.author,
+        &original.message,
+    )?;
+    refs::update_head_detached(
+        &new_sha,
+        &format!("rebase ({}): {}", action, original.summary()),
+    )
+}
+
+/// `message` with what a squash commit's `squashed` message adds to it: all
+/// of it but a "squash! ..." subject line
+fn squashed_message(message: &str, squashed: &str) -> String {
+    let added = match squashed.split_once('\n') {
+        Some((subject, rest)) if subject.starts_with("squash! ") => rest,
+        None if squashed.starts_with("squash! ") => "",
+        _ => squashed,
+    };
+    let added = added.trim();
+    if added.is_empty() {
+        return message.to_string();
+    }
+    format!("{}\n\n{}\n", message.trim_end(), added)
+}
+
+/// `commits` as steps, each fixup!/squash! commit moved after the commit it
+/// names and the other fixups and squashes of that commit before it. One
+/// naming no earlier commit stays where it is, as a pick.
+fn squash_order(commits: Vec<String>) -> io::Result<Vec<Step>> {
+    let mut groups: Vec<(Step, String, Vec<Step>)> = Vec::new();
+    for sha in commits {
+        let subject = commit::read(&sha)?.summary().to_string();
+        if let Some((action, target)) = squash_target(&subject) {
+            let found = groups
+                .iter()
+                .position(|(pick, _, _)| target.len() >= 4 && pick.sha.starts_with(target))
+                .or_else(|| groups.iter().position(|(_, subject, _)| subject == target))
+                .or_else(|| {
+                    groups
+                        .iter()
+                        .position(|(_, subject, _)| subject.starts_with(target))
+                });
+            if let Some(found) = found {
+                groups[found].2.push(Step { action, sha });
+                continue;
+            }
+        }
+
+        groups.push((Step::pick(sha), subject, Vec::new()));
+    }
+    Ok(groups
+        .into_iter()
+        .flat_map(|(pick, _, melded)| std::iter::once(pick).chain(melded))
+        .collect())
+}
+
+/// What a "fixup! "/"squash! " subject asks for, and the subject (or id)
+/// it names once any further such prefixes are taken off
+fn squash_target(subject: &str) -> Option<(Action, &str)> {
+    let action = if subject.starts_with("fixup! ") {
+        Action::Fixup
+    } else if subject.starts_with("squash! ") {
+        Action::Squash
+    } else {
+        return None;
+    };
+    let mut target = subject;
+    while let Some(rest) = target
+        .strip_prefix("fixup! ")
+        .or_else(|| target.strip_prefix("squash! "))
+    {
+        target = rest;
+    }
+    Some((action, target.trim()))
+}
+
+/// Non-merge commits reachable from `head` but not from `upstream`, parents
+/// before children
+fn commits_to_replay(head: &str, upstream: &str) -> io::Result<Vec<String>> {
+    let excluded = merge::ancestors(upstream)?;
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    // Post-order DFS: a commit is emitted once all its parents have been
+    let mut stack = vec![(head.to_string(), false)];
+
+    while let Some((sha, expanded)) = stack.pop() {
+        if expanded {
+            if commit::read(&sha)?.parents.len() <= 1 {
+                order.push(sha);
+            }
+            continue;
+        }
+        if excluded.contains(&sha) || !visited.insert(sha.clone()) {
+            continue;
+        }
+        stack.push((sha.clone(), true));
+        let parents = commit::read(&sha)?.parents;
+        for parent in parents.into_iter().rev() {
+            stack.push((parent, false));
+        }
+    }
+    Ok(order)
+}
+
+fn pick_label(sha: &str, original: &Commit) -> String {
+    format!("{} ({})", &sha[..7], original.summary())
+}
+
+fn current_head() -> io::Result<String> {
+    refs::head_commit()?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HEAD does not point to a commit"))
+}
+
+fn resolve_commit(name: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("invalid upstream '{}'", name),
+        )
+    })?;
+    let (sha, kind) = tag::peel(&sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a commit", name, kind),
+        ));
+    }
+    Ok(sha)
+}
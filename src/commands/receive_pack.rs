@@ -0,0 +1,190 @@
+// receive-pack: accept a push into this repository
+//
+// receive-pack [--stateless-rpc] [--advertise-refs] <directory>
+//
+// Speaks the server side of the version 0 receive-pack protocol on standard
+// input and output. The refs are advertised, then the client sends one
+// "<old> <new> <ref>" command per ref to update (a <new> of all zeros
+// deletes it) and a pack with the objects the updates need. Each ref is
+// only updated if it still holds <old>, and the outcome for every ref is
+// reported back when the client asks for report-status. Refs hidden
+// through receive.hideRefs or transfer.hideRefs are neither advertised nor
+// updated, and the branch checked out in this working tree is left alone
+// unless receive.denyCurrentBranch is "ignore" or "warn". A bare repository
+// has nothing checked out, so any of its branches can be updated, though
+// the one HEAD names still can't be deleted.
+//
+// --advertise-refs only advertises the refs, and --stateless-rpc handles
+// the commands and pack without advertising first, as the two halves of a
+// smart HTTP exchange do.
+
+use crate::git::advertise::{self, HiddenRefs};
+use crate::git::config::Config;
+use crate::git::http::USER_AGENT;
+use crate::git::pktline::{self, FLUSH};
+use crate::git::reflog::ZERO_SHA;
+use crate::git::{object, pack, refs, repository};
+use std::io::{self, BufWriter, Read, Write};
+
+const USAGE: &str = "Usage: receive-pack [--stateless-rpc] [--advertise-refs] <directory>";
+
+/// One requested ref update
+struct Command {
+    old: String,
+    new: String,
+    refname: String,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut stateless = false;
+    let mut advertise_only = false;
+    let mut dir = None;
+    for arg in args {
+        match arg.as_str() {
+            "--stateless-rpc" => stateless = true,
+            "--advertise-refs" => advertise_only = true,
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg if dir.is_none() => dir = Some(arg),
+            _ => return Err(usage()),
+        }
+    }
+    advertise::enter_repository(dir.ok_or_else(usage)?)?;
+
+    let config = Config::load()?;
+    let hidden = HiddenRefs::load(&config, "receive");
+    let mut out = BufWriter::new(io::stdout().lock());
+    if advertise_only || !stateless {
+        let visible = advertise::visible_refs(false, &hidden)?;
+        // no-thin: the pack has to carry every delta base it uses
+        let capabilities = format!("report-status delete-refs no-thin agent={}", USER_AGENT);
+        advertise::write(&mut out, &visible, &capabilities, false)?;
+        out.flush()?;
+        if advertise_only {
+            return Ok(());
+        }
+    }
+
+    let mut input = io::stdin().lock();
+    let Some((commands, capabilities)) = read_commands(&mut input)? else {
+        // Nothing to update
+        return Ok(());
+    };
+
+    let limits = pack::Limits::from_config(&config)?;
+    let unpacked = if commands.iter().all(|command| command.new == ZERO_SHA) {
+        Ok(())
+    } else {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        pack::unpack(data, &limits).map(|_| ())
+    };
+
+    let mut report = Vec::new();
+    match &unpacked {
+        Ok(()) => report.push("unpack ok\n".to_string()),
+        Err(e) => report.push(format!("unpack {}\n", e)),
+    }
+    for command in &commands {
+        let outcome = match &unpacked {
+            Ok(()) => update(command, &hidden, &config),
+            Err(_) => Err("unpacker error".to_string()),
+        };
+        report.push(match outcome {
+            Ok(()) => format!("ok {}\n", command.refname),
+            Err(reason) => format!("ng {} {}\n", command.refname, reason),
+        });
+    }
+
+    if capabilities.iter().any(|c| c == "report-status") {
+        for line in report {
+            out.write_all(&pktline::encode(line.as_bytes()))?;
+        }
+        out.write_all(FLUSH)?;
+    }
+    out.flush()
+}
+
+/// The commands up to the first flush, with the capabilities the client
+/// asked for on the first one; `None` if there are none
+fn read_commands(input: &mut impl Read) -> io::Result<Option<(Vec<Command>, Vec<String>)>> {
+    let mut commands = Vec::new();
+    let mut capabilities = Vec::new();
+    loop {
+        let packet = match pktline::read(input) {
+            Ok(packet) => packet,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && commands.is_empty() => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        let Some(line) = packet else {
+            break;
+        };
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches('\n');
+        let (line, caps) = line.split_once('\0').unwrap_or((line, ""));
+        if commands.is_empty() {
+            capabilities = caps.split(' ').map(str::to_string).collect();
+        }
+        let mut fields = line.splitn(3, ' ');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(old), Some(new), Some(refname)) if refs::is_sha(old) && refs::is_sha(new) => {
+                commands.push(Command {
+                    old: old.to_string(),
+                    new: new.to_string(),
+                    refname: refname.to_string(),
+                })
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("protocol error: expected old/new/ref, got '{}'", line),
+                ))
+            }
+        }
+    }
+    if commands.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((commands, capabilities)))
+}
+
+/// Carry out one command, or say why not
+fn update(command: &Command, hidden: &HiddenRefs, config: &Config) -> Result<(), String> {
+    let name = command.refname.as_str();
+    if hidden.is_hidden(name) {
+        return Err("deny updating a hidden ref".to_string());
+    }
+    if !name.starts_with("refs/") || !refs::is_valid_name(name) {
+        return Err("funny refname".to_string());
+    }
+    let current =
+        refs::read_head().map_err(|e| e.to_string())? == refs::Head::Branch(name.to_string());
+    let deleting = command.new == ZERO_SHA;
+    if current && deleting {
+        return Err("deletion of the current branch prohibited".to_string());
+    }
+    if current
+        && !repository::is_bare()
+        && !matches!(
+            config.get("receive.denycurrentbranch"),
+            Some("ignore" | "warn" | "false" | "no" | "off" | "0")
+        )
+    {
+        return Err("branch is currently checked out".to_string());
+    }
+    if !deleting && object::read(&command.new).is_err() {
+        return Err("missing necessary objects".to_string());
+    }
+
+    let result = if deleting {
+        refs::delete_ref_checked(name, &command.old)
+    } else {
+        refs::update_ref_checked(name, &command.new, Some(&command.old), "push")
+    };
+    result.map_err(|_| "failed to update ref".to_string())
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -0,0 +1,81 @@
+// reflog: show the history of a ref
+//
+// reflog [show] [-n <n> | --max-count=<n>] [<ref>]
+// reflog exists <ref>
+//
+// show (the default) lists the updates recorded for <ref> (HEAD by
+// default), newest first, as "<short sha> <ref>@{<n>}: <message>", where
+// <ref>@{<n>} names the value the ref had <n> updates ago. exists exits
+// with status 1 unless <ref>, a full ref name, has a reflog.
+
+use crate::git::{object, reflog, refs, revision};
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "Usage: reflog [show] [-n <n>] [<ref>] | reflog exists <ref>";
+
+const DEFAULT_ABBREV: usize = 7;
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("show") => show(&args[1..]),
+        Some("exists") => match &args[1..] {
+            [refname] if reflog::exists(refname) => Ok(()),
+            [_] => process::exit(1),
+            _ => Err(usage()),
+        },
+        _ => show(args),
+    }
+}
+
+fn show(args: &[String]) -> io::Result<()> {
+    let mut max_count = usize::MAX;
+    let mut name = None;
+    let parse_count = |value: &str| value.parse::<usize>().map_err(|_| usage());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" => max_count = parse_count(args.next().ok_or_else(usage)?)?,
+            arg if arg.starts_with("--max-count=") => {
+                max_count = parse_count(&arg["--max-count=".len()..])?
+            }
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg if name.is_none() => name = Some(arg),
+            _ => return Err(usage()),
+        }
+    }
+    let name = match name {
+        Some(name) => name,
+        None => {
+            refs::require_head_commit()?;
+            "HEAD"
+        }
+    };
+    let refname = revision::full_ref_name(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "ambiguous argument '{}': unknown revision or path not in the working tree.",
+                name
+            ),
+        )
+    })?;
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let entries = reflog::read(&refname)?;
+    for (n, entry) in entries.iter().rev().enumerate().take(max_count) {
+        writeln!(
+            out,
+            "{} {}@{{{}}}: {}",
+            object::abbreviate(&entry.new, DEFAULT_ABBREV)?,
+            name,
+            n,
+            entry.message
+        )?;
+    }
+    out.flush()
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
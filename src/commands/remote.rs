@@ -0,0 +1,602 @@
+// remote: manage the remotes a repository tracks
+//
+// remote [-v]
+// remote add [-t <branch>]... [-m <branch>] <name> <url>
+// remote remove <name>
+// remote rename <old> <new>
+// remote set-url [--push] [--add | --delete] <name> <url> [<old-url>]
+// remote show [-n] <name>...
+// remote set-head <name> (-a | --auto | -d | --delete | <branch>)
+//
+// Remotes live in .git/config as remote.<name>.url, with fetch refspecs
+// mapping the remote's branches to refs/remotes/<name>/. Without a
+// subcommand the remotes are listed, with -v along with their fetch and
+// push URLs.
+//
+// add records a new remote whose refspec fetches every branch, or only
+// each -t branch, and -m points refs/remotes/<name>/HEAD at <branch>.
+// remove drops the remote, its remote-tracking branches and the upstream
+// configuration of branches that followed it; rename moves all of those to
+// the new name. set-url replaces the first URL (the first matching the
+// extended regex <old-url>), --add adds one and --delete removes every URL
+// matching <url>; --push works on the push URLs instead. show describes a
+// remote, asking it for its branches unless -n is given.
+//
+// set-head points refs/remotes/<name>/HEAD, the remote's default branch as
+// seen locally, at refs/remotes/<name>/<branch>. With --auto the remote is
+// asked which branch its HEAD is on; --delete removes the symbolic ref.
+// The remote-tracking branch must already exist.
+//
+// As in git, a missing remote exits with status 2 and one that already
+// exists with status 3.
+
+use crate::commands::clone;
+use crate::git::config::{self, Config};
+use crate::git::merge;
+use crate::git::progress::Progress;
+use crate::git::refs;
+use crate::git::regex::{Regex, Syntax};
+use crate::git::repository;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const USAGE: &str =
+    "Usage: remote [-v] | remote add [-t <branch>]... [-m <branch>] <name> <url> | \
+                     remote remove <name> | remote rename <old> <new> | \
+                     remote set-url [--push] [--add | --delete] <name> <url> [<old-url>] | \
+                     remote show [-n] <name>... | \
+                     remote set-head <name> (-a | --auto | -d | --delete | <branch>)";
+
+const CONFIG_FILE: &str = "config";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        None => list(false),
+        Some("-v" | "--verbose") if args.len() == 1 => list(true),
+        Some("add") => run_add(&args[1..]),
+        Some("remove" | "rm") => match &args[1..] {
+            [name] => remove(name),
+            _ => Err(usage()),
+        },
+        Some("rename") => match &args[1..] {
+            [old, new] => rename(old, new),
+            _ => Err(usage()),
+        },
+        Some("set-url") => set_url(&args[1..]),
+        Some("show") => show(&args[1..]),
+        Some("set-head") => match &args[1..] {
+            [name, target] => set_head(name, target),
+            _ => Err(usage()),
+        },
+        _ => Err(usage()),
+    }
+}
+
+/// The names of the configured remotes, in config order
+fn remote_names(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for entry in config.entries() {
+        if entry.section == "remote" {
+            if let Some(name) = &entry.subsection {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+    }
+    names
+}
+
+fn exists(config: &Config, name: &str) -> bool {
+    remote_names(config).iter().any(|remote| remote == name)
+}
+
+fn list(verbose: bool) -> io::Result<()> {
+    let config = Config::load()?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    for name in remote_names(&config) {
+        if !verbose {
+            writeln!(out, "{}", name)?;
+            continue;
+        }
+        let urls = config.get_all(&format!("remote.{}.url", name));
+        if let Some(url) = urls.first() {
+            writeln!(out, "{}\t{} (fetch)", name, url)?;
+        }
+        for url in push_urls(&config, &name) {
+            writeln!(out, "{}\t{} (push)", name, url)?;
+        }
+    }
+    out.flush()
+}
+
+/// The push URLs of remote `name`, which are its URLs unless pushurl is set
+fn push_urls<'a>(config: &'a Config, name: &str) -> Vec<&'a str> {
+    let push = config.get_all(&format!("remote.{}.pushurl", name));
+    if push.is_empty() {
+        config.get_all(&format!("remote.{}.url", name))
+    } else {
+        push
+    }
+}
+
+fn run_add(args: &[String]) -> io::Result<()> {
+    let mut branches = Vec::new();
+    let mut master = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-t" | "--track" => branches.push(args.next().ok_or_else(usage)?.as_str()),
+            "-m" | "--master" => master = Some(args.next().ok_or_else(usage)?.as_str()),
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => positional.push(arg),
+        }
+    }
+    let [name, url] = positional[..] else {
+        return Err(usage());
+    };
+    if exists(&Config::load()?, name) {
+        fail(3, &format!("remote {} already exists.", name));
+    }
+    add(name, url, &branches)?;
+    if let Some(master) = master {
+        refs::write_symref(
+            &format!("refs/remotes/{}/HEAD", name),
+            &format!("refs/remotes/{}/{}", name, master),
+        )?;
+    }
+    Ok(())
+}
+
+/// Record remote `name` at `url`, fetching every branch into
+/// refs/remotes/<name>/, or only `branches` if any are given
+pub fn add(name: &str, url: &str, branches: &[&str]) -> io::Result<()> {
+    check_name(name)?;
+    let path = &repository::path(CONFIG_FILE);
+    config::set_value(path, &format!("remote.{}.url", name), url)?;
+    let key = format!("remote.{}.fetch", name);
+    if branches.is_empty() {
+        config::add_value(
+            path,
+            &key,
+            &format!("+refs/heads/*:refs/remotes/{}/*", name),
+        )?;
+    }
+    for branch in branches {
+        config::add_value(
+            path,
+            &key,
+            &format!("+refs/heads/{}:refs/remotes/{}/{}", branch, name, branch),
+        )?;
+    }
+    Ok(())
+}
+
+/// A remote name has to make valid ref names under refs/remotes/
+fn check_name(name: &str) -> io::Result<()> {
+    if name.is_empty() || !refs::is_valid_name(&format!("refs/remotes/{}/test", name)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a valid remote name", name),
+        ));
+    }
+    Ok(())
+}
+
+fn remove(name: &str) -> io::Result<()> {
+    let config = Config::load()?;
+    if !exists(&config, name) {
+        fail(2, &format!("No such remote: '{}'", name));
+    }
+    let path = &repository::path(CONFIG_FILE);
+    for branch in branches_following(&config, name) {
+        config::unset_value(path, &format!("branch.{}.remote", branch), true)?;
+        config::unset_value(path, &format!("branch.{}.merge", branch), true)?;
+    }
+    if config.get("remote.pushDefault") == Some(name) {
+        config::unset_value(path, "remote.pushDefault", true)?;
+    }
+    config::remove_section(path, "remote", Some(name))?;
+
+    // Symbolic refs go first so none is left pointing at a deleted branch
+    let mut tracking = refs::list_refs(&tracking_prefix(name))?;
+    tracking.sort_by_key(|(refname, _)| refs::read_symref(refname).ok().flatten().is_none());
+    for (refname, _) in tracking {
+        refs::delete_ref(&refname)?;
+    }
+    Ok(())
+}
+
+fn rename(old: &str, new: &str) -> io::Result<()> {
+    let config = Config::load()?;
+    if !exists(&config, old) {
+        fail(2, &format!("No such remote: '{}'", old));
+    }
+    if exists(&config, new) {
+        fail(3, &format!("remote {} already exists.", new));
+    }
+    check_name(new)?;
+    let path = &repository::path(CONFIG_FILE);
+    config::rename_section(path, "remote", Some(old), Some(new))?;
+
+    // Refspecs that stored into the old remote's namespace now use the new one's
+    let fetch_key = format!("remote.{}.fetch", new);
+    let (old_prefix, new_prefix) = (tracking_prefix(old), tracking_prefix(new));
+    let refspecs: Vec<String> = config
+        .get_all(&format!("remote.{}.fetch", old))
+        .iter()
+        .map(|refspec| refspec.replace(&format!(":{}", old_prefix), &format!(":{}", new_prefix)))
+        .collect();
+    config::unset_value(path, &fetch_key, true)?;
+    for refspec in &refspecs {
+        config::add_value(path, &fetch_key, refspec)?;
+    }
+    for branch in branches_following(&config, old) {
+        config::set_value(path, &format!("branch.{}.remote", branch), new)?;
+    }
+    if config.get("remote.pushDefault") == Some(old) {
+        config::set_value(path, "remote.pushDefault", new)?;
+    }
+
+    let tracking = refs::list_refs(&old_prefix)?;
+    let mut symrefs = Vec::new();
+    for (refname, sha) in &tracking {
+        let renamed = format!("{}{}", new_prefix, &refname[old_prefix.len()..]);
+        match refs::read_symref(refname)? {
+            Some(target) => symrefs.push((renamed, target)),
+            None => refs::update_ref(
+                &renamed,
+                sha,
+                &format!("remote: renamed {} to {}", refname, renamed),
+            )?,
+        }
+    }
+    for (renamed, target) in symrefs {
+        let target = match target.strip_prefix(&old_prefix) {
+            Some(rest) => format!("{}{}", new_prefix, rest),
+            None => target,
+        };
+        refs::write_symref(&renamed, &target)?;
+    }
+    for (refname, _) in tracking.iter().rev() {
+        refs::delete_ref(refname)?;
+    }
+    Ok(())
+}
+
+/// The local branches whose upstream is on remote `name`
+fn branches_following(config: &Config, name: &str) -> Vec<String> {
+    config
+        .entries()
+        .iter()
+        .filter(|entry| {
+            entry.section == "branch"
+                && entry.name == "remote"
+                && entry.value.as_deref() == Some(name)
+        })
+        .filter_map(|entry| entry.subsection.clone())
+        .collect()
+}
+
+/// Where the remote-tracking branches of remote `name` live
+fn tracking_prefix(name: &str) -> String {
+    format!("refs/remotes/{}/", name)
+}
+
+fn set_url(args: &[String]) -> io::Result<()> {
+    let mut push = false;
+    let mut add = false;
+    let mut delete = false;
+    let mut positional = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--push" => push = true,
+            "--add" => add = true,
+            "--delete" => delete = true,
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => positional.push(arg),
+        }
+    }
+    let (name, url, old_url) = match positional[..] {
+        [name, url] => (name, url, None),
+        [name, url, old_url] if !add && !delete => (name, url, Some(old_url)),
+        _ => return Err(usage()),
+    };
+    if add && delete {
+        return Err(usage());
+    }
+
+    let config = Config::load()?;
+    if !exists(&config, name) {
+        fail(2, &format!("No such remote '{}'", name));
+    }
+    let path = &repository::path(CONFIG_FILE);
+    let key = format!("remote.{}.{}", name, if push { "pushurl" } else { "url" });
+    if add {
+        return config::add_value(path, &key, url);
+    }
+
+    let urls = config.get_all(&key);
+    let pattern = if delete { Some(url) } else { old_url };
+    let matching: Vec<bool> = match pattern {
+        Some(pattern) => {
+            let regex = Regex::new(pattern, Syntax::Extended, false)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            urls.iter()
+                .map(|url| regex.is_match(url.as_bytes()))
+                .collect()
+        }
+        None => (0..urls.len()).map(|i| i == 0).collect(),
+    };
+    if pattern.is_some() && !matching.contains(&true) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No such URL found: {}", pattern.unwrap_or_default()),
+        ));
+    }
+    if delete && !push && matching.iter().all(|&matched| matched) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Will not delete all non-push URLs",
+        ));
+    }
+
+    let mut replaced = false;
+    let mut values = Vec::new();
+    for (value, matched) in urls.iter().zip(&matching) {
+        if !matched {
+            values.push(value.to_string());
+        } else if !delete && !replaced {
+            values.push(url.to_string());
+            replaced = true;
+        }
+    }
+    if urls.is_empty() && !delete {
+        values.push(url.to_string());
+    }
+    config::unset_value(path, &key, true)?;
+    for value in &values {
+        config::add_value(path, &key, value)?;
+    }
+    Ok(())
+}
+
+fn show(args: &[String]) -> io::Result<()> {
+    let mut query = true;
+    let mut names = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-n" => query = false,
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => names.push(arg),
+        }
+    }
+    if names.is_empty() {
+        return list(false);
+    }
+    let config = Config::load()?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    for name in names {
+        let Some(url) = config.get(&format!("remote.{}.url", name)) else {
+            out.flush()?;
+            fail(2, &format!("No such remote '{}'", name));
+        };
+        writeln!(out, "* remote {}", name)?;
+        writeln!(out, "  Fetch URL: {}", url)?;
+        for push_url in push_urls(&config, name) {
+            writeln!(out, "  Push  URL: {}", push_url)?;
+        }
+        if query {
+            show_queried(&mut out, &config, name, url)?;
+        } else {
+            show_local(&mut out, &config, name)?;
+        }
+    }
+    out.flush()
+}
+
+/// The parts of `show` that need the remote's branches
+fn show_queried(out: &mut impl Write, config: &Config, name: &str, url: &str) -> io::Result<()> {
+    let (_, advertisement) = clone::connect(url, None, &mut Progress::silent())?;
+    let (advertised, head) = advertisement.into_discovery();
+    let head = head
+        .as_ref()
+        .and_then(|(branch, _)| branch.strip_prefix("refs/heads/"))
+        .unwrap_or("(unknown)");
+    writeln!(out, "  HEAD branch: {}", head)?;
+
+    let prefix = tracking_prefix(name);
+    let remote_branches: Vec<(&str, &str)> = advertised
+        .iter()
+        .filter_map(|(refname, sha)| Some((refname.strip_prefix("refs/heads/")?, sha.as_str())))
+        .collect();
+    let mut rows = Vec::new();
+    for (branch, _) in &remote_branches {
+        let status = if refs::read_ref(&format!("{}{}", prefix, branch))?.is_some() {
+            "tracked".to_string()
+        } else {
+            format!("new (next fetch will store in remotes/{})", name)
+        };
+        rows.push((branch.to_string(), status));
+    }
+    for (refname, _) in refs::list_refs(&prefix)? {
+        let branch = &refname[prefix.len()..];
+        if branch != "HEAD" && !remote_branches.iter().any(|(name, _)| *name == branch) {
+            rows.push((
+                branch.to_string(),
+                "stale (use 'git remote prune' to remove)".to_string(),
+            ));
+        }
+    }
+    rows.sort();
+    let title = if rows.len() == 1 {
+        "Remote branch:"
+    } else {
+        "Remote branches:"
+    };
+    write_table(out, title, &rows)?;
+    show_pull(out, config, name)?;
+
+    let mut pushes = Vec::new();
+    for (branch, sha) in &remote_branches {
+        let Some(local) = refs::read_ref(&refs::branch_ref(branch))? else {
+            continue;
+        };
+        let status = if local == *sha {
+            "up to date"
+        } else if merge::is_ancestor(sha, &local).unwrap_or(false) {
+            "fast-forwardable"
+        } else {
+            "local out of date"
+        };
+        pushes.push((
+            branch.to_string(),
+            format!("pushes to {} ({})", branch, status),
+        ));
+    }
+    pushes.sort();
+    let title = if pushes.len() == 1 {
+        "Local ref configured for 'git push':"
+    } else {
+        "Local refs configured for 'git push':"
+    };
+    write_table(out, title, &pushes)
+}
+
+/// The parts of `show -n`, from what is known locally
+fn show_local(out: &mut impl Write, config: &Config, name: &str) -> io::Result<()> {
+    writeln!(out, "  HEAD branch: (not queried)")?;
+    let prefix = tracking_prefix(name);
+    let rows: Vec<(String, String)> = refs::list_refs(&prefix)?
+        .into_iter()
+        .map(|(refname, _)| refname[prefix.len()..].to_string())
+        .filter(|branch| branch != "HEAD")
+        .map(|branch| (branch, String::new()))
+        .collect();
+    let title = if rows.len() == 1 {
+        "Remote branch: (status not queried)"
+    } else {
+        "Remote branches: (status not queried)"
+    };
+    write_table(out, title, &rows)?;
+    show_pull(out, config, name)?;
+    writeln!(
+        out,
+        "  Local ref configured for 'git push' (status not queried):"
+    )?;
+    writeln!(out, "    (matching) pushes to (matching)")
+}
+
+/// The local branches set up to pull from remote `name`
+fn show_pull(out: &mut impl Write, config: &Config, name: &str) -> io::Result<()> {
+    let mut rows = Vec::new();
+    for branch in branches_following(config, name) {
+        let Some(merge) = config.get(&format!("branch.{}.merge", branch)) else {
+            continue;
+        };
+        let merge = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+        let rebase = config
+            .get_bool(&format!("branch.{}.rebase", branch))
+            .ok()
+            .flatten()
+            .unwrap_or(false);
+        let verb = if rebase {
+            "rebases onto"
+        } else {
+            "merges with"
+        };
+        rows.push((branch, format!("{} remote {}", verb, merge)));
+    }
+    rows.sort();
+    let title = if rows.len() == 1 {
+        "Local branch configured for 'git pull':"
+    } else {
+        "Local branches configured for 'git pull':"
+    };
+    write_table(out, title, &rows)
+}
+
+/// `title` and one "<name> <detail>" line per row, the names padded to
+/// line the details up; nothing at all without rows
+fn write_table(out: &mut impl Write, title: &str, rows: &[(String, String)]) -> io::Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "  {}", title)?;
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, detail) in rows {
+        if detail.is_empty() {
+            writeln!(out, "    {}", name)?;
+        } else {
+            writeln!(out, "    {:width$} {}", name, detail, width = width)?;
+        }
+    }
+    Ok(())
+}
+
+/// Report `message` as git does and exit with `code`
+fn fail(code: i32, message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(code)
+}
+
+fn set_head(name: &str, target: &str) -> io::Result<()> {
+    let config = Config::load()?;
+    let url = config
+        .get(&format!("remote.{}.url", name))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No such remote '{}'", name),
+            )
+        })?
+        .to_string();
+    let head = format!("refs/remotes/{}/HEAD", name);
+
+    let branch = match target {
+        "-d" | "--delete" => {
+            if refs::read_symref(&head)?.is_some() {
+                refs::delete_ref(&head)?;
+            }
+            return Ok(());
+        }
+        "-a" | "--auto" => {
+            let (_, advertisement) = clone::connect(&url, None, &mut Progress::silent())?;
+            let remote_head = advertisement
+                .symrefs
+                .iter()
+                .find(|(symref, _)| symref == "HEAD")
+                .map(|(_, target)| target.as_str())
+                .unwrap_or_default();
+            match remote_head.strip_prefix("refs/heads/") {
+                Some(branch) => branch.to_string(),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "Cannot determine remote HEAD",
+                    ))
+                }
+            }
+        }
+        branch if branch.starts_with('-') => return Err(usage()),
+        branch => branch.to_string(),
+    };
+
+    let tracking = format!("refs/remotes/{}/{}", name, branch);
+    if refs::read_ref(&tracking)?.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Not a valid ref: {}", tracking),
+        ));
+    }
+    refs::write_symref(&head, &tracking)?;
+    if target == "-a" || target == "--auto" {
+        writeln!(io::stdout(), "{}/HEAD set to {}", name, branch)?;
+    }
+    Ok(())
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
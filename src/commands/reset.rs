@@ -0,0 +1,176 @@
+// reset: move the current branch to another commit
+//
+// reset [--soft | --mixed | --hard | --keep] [-q] [<commit>]
+//
+// --soft   only move the branch (or the detached HEAD)
+// --mixed  also make the index match <commit>; the default
+// --hard   also make the working tree match, discarding local changes
+// --keep   like --hard, but refuse if a file that differs between HEAD and
+//          <commit> has local changes; other local changes are kept
+//
+// <commit> defaults to HEAD, and the previous HEAD is saved in ORIG_HEAD.
+// On a branch with no commits yet, HEAD is the empty tree, so a reset just
+// unstages everything (and --hard removes the staged files).
+// Any merge or revert in progress is forgotten.
+
+use crate::git::commit;
+use crate::git::index::{Entry, Index};
+use crate::git::refs;
+use crate::git::{revision, tag, worktree};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MERGE_HEAD: &str = ".git/MERGE_HEAD";
+/// State left behind by a conflicted merge or revert
+const IN_PROGRESS: &[&str] = &[MERGE_HEAD, ".git/MERGE_MSG", ".git/REVERT_HEAD"];
+const SEQUENCER_DIR: &str = ".git/sequencer";
+
+const USAGE: &str = "Usage: reset [--soft | --mixed | --hard | --keep] [-q] [<commit>]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Soft,
+    Mixed,
+    Hard,
+    Keep,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut mode = Mode::Mixed;
+    let mut quiet = false;
+    let mut target = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "--soft" => mode = Mode::Soft,
+            "--mixed" => mode = Mode::Mixed,
+            "--hard" => mode = Mode::Hard,
+            "--keep" => mode = Mode::Keep,
+            "-q" | "--quiet" => quiet = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg if target.is_none() => target = Some(arg.to_string()),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+
+    reset(target.as_deref().unwrap_or("HEAD"), mode, quiet)
+}
+
+fn reset(target: &str, mode: Mode, quiet: bool) -> io::Result<()> {
+    if mode == Mode::Soft && Path::new(MERGE_HEAD).exists() {
+        return Err(io::Error::other(
+            "Cannot do a soft reset in the middle of a merge.",
+        ));
+    }
+
+    let head = refs::head_commit()?;
+    // On an unborn branch HEAD stands for the empty tree
+    let sha = match (target, &head) {
+        ("HEAD", None) => None,
+        _ => Some(resolve_commit(target)?),
+    };
+    let files = worktree::snapshot_of_commit(sha.as_deref())?;
+
+    match mode {
+        Mode::Soft => {}
+        Mode::Mixed => {
+            let unstaged = reset_index(&files)?;
+            if !quiet && !unstaged.is_empty() {
+                println!("Unstaged changes after reset:");
+                for line in unstaged {
+                    println!("{}", line);
+                }
+            }
+        }
+        Mode::Hard => {
+            // Whatever is staged is dropped: files only in the index go too
+            let staged = Index::load()?.snapshot();
+            worktree::update(&staged, &files, true)?;
+            // Local edits to files the index and <commit> agree on
+            for (path, file) in &files {
+                if !worktree::matches_worktree(path, file)? {
+                    worktree::checkout_entry(path, file)?;
+                }
+            }
+            reset_index(&files)?;
+        }
+        Mode::Keep => {
+            // Refuses before touching anything if local changes are in the way
+            let current = worktree::snapshot_of_commit(head.as_deref())?;
+            worktree::update(&current, &files, false)?;
+            reset_index(&files)?;
+        }
+    }
+
+    let Some(sha) = sha else {
+        return clear_in_progress();
+    };
+    if let Some(head) = &head {
+        refs::write_ref("ORIG_HEAD", head)?;
+    }
+    refs::update_head(&sha, &format!("reset: moving to {}", target))?;
+    clear_in_progress()?;
+
+    if mode == Mode::Hard && !quiet {
+        let commit = commit::read(&sha)?;
+        println!("HEAD is now at {} {}", &sha[..7], commit.summary());
+    }
+    Ok(())
+}
+
+/// Stage exactly `files`. Returns the "M\tpath" / "D\tpath" lines for files
+/// whose working tree copy now differs from the index.
+fn reset_index(files: &worktree::Snapshot) -> io::Result<Vec<String>> {
+    let mut index = Index::default();
+    let mut unstaged = Vec::new();
+    for (path, file) in files {
+        if worktree::matches_worktree(path, file)? {
+            index.add(Entry::checked_out(path, file)?);
+            continue;
+        }
+        let status = if fs::symlink_metadata(path).is_ok() {
+            'M'
+        } else {
+            'D'
+        };
+        unstaged.push(format!("{}\t{}", status, path));
+        index.add(Entry::new(path, file));
+    }
+    index.write()?;
+    Ok(unstaged)
+}
+
+fn clear_in_progress() -> io::Result<()> {
+    for path in IN_PROGRESS {
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    match fs::remove_dir_all(SEQUENCER_DIR) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn resolve_commit(name: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("ambiguous argument '{}': unknown revision", name),
+        )
+    })?;
+    let (sha, kind) = tag::peel(&sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a commit", name, kind),
+        ));
+    }
+    Ok(sha)
+}
@@ -0,0 +1,135 @@
+// rev-list: list commits
+//
+// rev-list [--max-count=<n> | -n <n> | -<n>] [--reverse] [--count]
+//          <rev>... [^<rev>...] [--not <rev>...]
+//
+// Prints the commits reachable from the given revisions, newest first by
+// committer date. A revision prefixed with "^", or given after --not,
+// takes away everything reachable from it (a second --not turns the
+// prefix off again). A..B stands for ^A B, A...B for the commits on
+// either side but not in both; an empty side is HEAD. --max-count stops
+// after <n> commits, --reverse prints them oldest first (after the limit
+// is applied) and --count prints only how many there are.
+
+use crate::git::revwalk::RevWalk;
+use crate::git::{merge, revision};
+use std::io::{self, BufWriter, Write};
+
+const USAGE: &str =
+    "Usage: rev-list [--max-count=<n>] [--reverse] [--count] <rev>... [^<rev>...] [--not <rev>...]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut max_count: Option<usize> = None;
+    let mut reverse = false;
+    let mut count = false;
+    let mut negated = false;
+    let mut walk = RevWalk::new();
+    let mut any = false;
+
+    let parse_count = |value: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--not" => negated = !negated,
+            "--reverse" => reverse = true,
+            "--count" => count = true,
+            "-n" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+                max_count = Some(parse_count(value)?);
+            }
+            arg if arg.starts_with("--max-count=") => {
+                max_count = Some(parse_count(&arg["--max-count=".len()..])?)
+            }
+            arg if arg.len() > 1
+                && arg.starts_with('-')
+                && arg[1..].bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                max_count = Some(parse_count(&arg[1..])?)
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => {
+                any = true;
+                add_revision(&mut walk, arg, negated)?;
+            }
+        }
+    }
+    if !any {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+    }
+
+    let mut commits = Vec::new();
+    for sha in walk.take(max_count.unwrap_or(usize::MAX)) {
+        commits.push(sha?);
+    }
+    let mut out = BufWriter::new(io::stdout().lock());
+    if count {
+        writeln!(out, "{}", commits.len())?;
+        return out.flush();
+    }
+    if reverse {
+        commits.reverse();
+    }
+    for sha in commits {
+        writeln!(out, "{}", sha)?;
+    }
+    out.flush()
+}
+
+/// Add `arg` to the walk: a revision, a range, or (with a "^" or under
+/// --not) a revision to leave out
+pub fn add_revision(walk: &mut RevWalk, arg: &str, negated: bool) -> io::Result<()> {
+    if let Some((from, to)) = arg.split_once("...") {
+        let from = commit(or_head(from))?;
+        let to = commit(or_head(to))?;
+        for base in merge::merge_bases(&from, &to)? {
+            walk.hide(&base)?;
+        }
+        walk.push(&from)?;
+        return walk.push(&to);
+    }
+    if let Some((from, to)) = arg.split_once("..") {
+        walk.hide(&commit(or_head(from))?)?;
+        return walk.push(&commit(or_head(to))?);
+    }
+    match arg.strip_prefix('^') {
+        Some(rev) => walk.hide(&commit(rev)?),
+        None if negated => walk.hide(&commit(arg)?),
+        None => walk.push(&commit(arg)?),
+    }
+}
+
+fn or_head(rev: &str) -> &str {
+    if rev.is_empty() {
+        "HEAD"
+    } else {
+        rev
+    }
+}
+
+/// The commit `rev` names, through any tags
+fn commit(rev: &str) -> io::Result<String> {
+    let not_found = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "ambiguous argument '{}': unknown revision or path not in the working tree.",
+                rev
+            ),
+        )
+    };
+    let sha = revision::resolve(rev)?.ok_or_else(not_found)?;
+    revision::peel(&sha, "commit")?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a commit", rev),
+        )
+    })
+}
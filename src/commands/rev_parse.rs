@@ -0,0 +1,125 @@
+// rev-parse: turn revision expressions into object ids
+//
+// rev-parse [--verify [-q]] [--short[=<n>] | --abbrev-ref | --symbolic-full-name] <rev>...
+// rev-parse --git-dir | --show-toplevel | --is-inside-work-tree |
+//           --is-bare-repository
+//
+// Prints the id each <rev> names, one per line; see git/revision.rs for the
+// syntax. --verify takes exactly one revision and fails unless it names an
+// object, quietly with -q. --short (which implies --verify) abbreviates the
+// id to <n> digits, 7 by default, or more where that would be ambiguous. --symbolic-full-name
+// prints the full name of each ref instead (refs/heads/main), and
+// --abbrev-ref its shortest unambiguous one (main); other expressions
+// print nothing in those modes.
+
+use crate::git::{object, refs, repository, revision};
+use std::env;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "Usage: rev-parse [--verify [-q]] [--short[=<n>] | --abbrev-ref | --symbolic-full-name] <rev>...";
+
+const DEFAULT_ABBREV: usize = 7;
+
+#[derive(PartialEq, Eq)]
+enum Output {
+    Id,
+    Short(usize),
+    FullName,
+    AbbrevRef,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut verify = false;
+    let mut quiet = false;
+    let mut output = Output::Id;
+    let mut revs = Vec::new();
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    let mut options_done = false;
+    for arg in args {
+        match arg.as_str() {
+            arg if options_done => revs.push(arg),
+            "--" => options_done = true,
+            "--verify" => verify = true,
+            "-q" | "--quiet" => quiet = true,
+            "--short" => {
+                verify = true;
+                output = Output::Short(DEFAULT_ABBREV);
+            }
+            "--symbolic-full-name" => output = Output::FullName,
+            "--abbrev-ref" => output = Output::AbbrevRef,
+            "--git-dir" => writeln!(out, "{}", repository::git_dir().display())?,
+            "--show-toplevel" => writeln!(out, "{}", env::current_dir()?.display())?,
+            "--is-inside-work-tree" => writeln!(out, "{}", !repository::is_bare())?,
+            "--is-bare-repository" => writeln!(out, "{}", repository::is_bare())?,
+            arg if arg.starts_with("--short=") => {
+                let length: usize = arg["--short=".len()..]
+                    .parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+                verify = true;
+                output = Output::Short(length.clamp(4, 40));
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => revs.push(arg),
+        }
+    }
+
+    if verify {
+        let sha = match revs.as_slice() {
+            [rev] => revision::resolve(rev).ok().flatten(),
+            _ => None,
+        };
+        let Some(sha) = sha.filter(|sha| object::locate(sha).ok().flatten().is_some()) else {
+            out.flush()?;
+            if quiet {
+                process::exit(1);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Needed a single revision",
+            ));
+        };
+        print(&mut out, revs[0], &sha, &output)?;
+        return out.flush();
+    }
+
+    for rev in revs {
+        let sha = revision::resolve(rev)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "ambiguous argument '{}': unknown revision or path not in the working tree.",
+                    rev
+                ),
+            )
+        })?;
+        print(&mut out, rev, &sha, &output)?;
+    }
+    out.flush()
+}
+
+fn print(out: &mut impl Write, rev: &str, sha: &str, output: &Output) -> io::Result<()> {
+    match output {
+        Output::Id => writeln!(out, "{}", sha),
+        Output::Short(length) => writeln!(out, "{}", object::abbreviate(sha, *length)?),
+        Output::FullName | Output::AbbrevRef => {
+            let Some(mut refname) = revision::full_ref_name(rev)? else {
+                return Ok(());
+            };
+            // HEAD stands for the branch it is on
+            if refname == "HEAD" {
+                if let refs::Head::Branch(branch) = refs::read_head()? {
+                    refname = branch;
+                }
+            }
+            if *output == Output::FullName {
+                writeln!(out, "{}", refname)
+            } else {
+                writeln!(out, "{}", refs::shorten(&refname)?)
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+// revert: record a new commit undoing the changes of an existing one
+//
+// revert [--no-commit] [-m <parent-number>] <commit>
+// revert --continue   commit once conflicts are resolved
+// revert --abort      return to the pre-revert state
+//
+// Reverting C is a three-way merge of HEAD and C's parent with C itself as
+// the base. A conflicted revert leaves REVERT_HEAD, MERGE_MSG and ORIG_HEAD
+// behind, like a conflicted merge.
+
+use crate::commands::merge as merge_cmd;
+use crate::git::commit::{self, Commit};
+use crate::git::config::{self, Config};
+use crate::git::merge::{self, Labels, TreeMerge};
+use crate::git::{hooks, refs, revision, tag, tree, worktree};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const REVERT_HEAD: &str = ".git/REVERT_HEAD";
+const MERGE_HEAD: &str = ".git/MERGE_HEAD";
+const MERGE_MSG: &str = ".git/MERGE_MSG";
+const ORIG_HEAD: &str = ".git/ORIG_HEAD";
+const SEQUENCER_DIR: &str = ".git/sequencer";
+const SEQUENCER_OPTS: &str = ".git/sequencer/opts";
+
+const USAGE: &str =
+    "Usage: revert [--no-commit] [-m <parent-number>] <commit> | --continue | --abort";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut no_commit = false;
+    let mut mainline = None;
+    let mut target = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--continue" => return continue_revert(),
+            "--abort" => return abort_revert(),
+            "-n" | "--no-commit" => no_commit = true,
+            "-m" | "--mainline" => {
+                i += 1;
+                let number = args
+                    .get(i)
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidInput, "-m expects a parent number")
+                    })?;
+                mainline = Some(number);
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg if target.is_none() => target = Some(arg.to_string()),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        }
+        i += 1;
+    }
+
+    let target = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+    revert(&target, mainline, no_commit)
+}
+
+fn revert(target: &str, mainline: Option<usize>, no_commit: bool) -> io::Result<()> {
+    if Path::new(REVERT_HEAD).exists() || Path::new(MERGE_HEAD).exists() {
+        return Err(io::Error::other(
+            "a revert or merge is already in progress\n(use 'revert --continue' or 'revert --abort')",
+        ));
+    }
+
+    let sha = resolve_commit(target)?;
+    let reverted = commit::read(&sha)?;
+    let parent = mainline_parent(&sha, &reverted, mainline)?;
+    let head = refs::head_commit()?.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "cannot revert on an unborn branch")
+    })?;
+
+    let result = compute_revert(&head, &sha, &reverted, parent.as_deref())?;
+
+    // A rejected message must stop the revert before the worktree changes
+    let mut message = revert_message(&sha, &reverted);
+    if result.conflicts.is_empty() && !no_commit {
+        message = hooks::check_commit_message(&message)?;
+    }
+
+    // Refuses (before writing anything) if local changes would be clobbered
+    let current = worktree::snapshot_of_commit(Some(&head))?;
+    worktree::update(&current, &result.files, false)?;
+
+    if result.conflicts.is_empty() && no_commit {
+        return Ok(());
+    }
+    if result.conflicts.is_empty() {
+        return create_commit(&head, &result.files, &message);
+    }
+
+    result.stage_conflicts()?;
+    refs::write_ref("ORIG_HEAD", &head)?;
+    refs::write_ref("REVERT_HEAD", &sha)?;
+    if let Some(mainline) = mainline {
+        fs::create_dir_all(SEQUENCER_DIR)?;
+        config::set_value(
+            Path::new(SEQUENCER_OPTS),
+            "options.mainline",
+            &mainline.to_string(),
+        )?;
+    }
+    let mut merge_msg = format!("{}\n\n# Conflicts:\n", message.trim_end());
+    for conflict in &result.conflicts {
+        merge_msg.push_str(&format!("#\t{}\n", conflict.path));
+        println!(
+            "{}",
+            merge_cmd::describe_conflict(
+                &conflict.path,
+                conflict.kind,
+                &parent_label(&sha, &reverted)
+            )
+        );
+    }
+    fs::write(MERGE_MSG, merge_msg)?;
+
+    Err(io::Error::other(format!(
+        "could not revert {}... {}\nResolve the conflicts, then run 'revert --continue'.",
+        &sha[..7],
+        reverted.summary()
+    )))
+}
+
+fn continue_revert() -> io::Result<()> {
+    let (head, sha) = revert_state()?;
+    let reverted = commit::read(&sha)?;
+    let parent = mainline_parent(&sha, &reverted, saved_mainline()?)?;
+    let result = compute_revert(&head, &sha, &reverted, parent.as_deref())?;
+
+    let files = merge_cmd::resolved_files(result)?;
+    let message = merge_cmd::strip_comments(&fs::read_to_string(MERGE_MSG)?);
+    let message = hooks::check_commit_message(message.trim_end())?;
+    create_commit(&head, &files, &message)?;
+    clear_revert_state()
+}
+
+fn abort_revert() -> io::Result<()> {
+    let (head, sha) = revert_state()?;
+    let reverted = commit::read(&sha)?;
+    let parent = mainline_parent(&sha, &reverted, saved_mainline()?)?;
+    let result = compute_revert(&head, &sha, &reverted, parent.as_deref())?;
+
+    // Files written by the revert are overwritten unconditionally
+    let original = worktree::snapshot_of_commit(Some(&head))?;
+    worktree::update(&result.files, &original, true)?;
+    result.unstage_conflicts(&original)?;
+    clear_revert_state()
+}
+
+fn compute_revert(
+    head: &str,
+    sha: &str,
+    reverted: &Commit,
+    parent: Option<&str>,
+) -> io::Result<TreeMerge> {
+    let base_label = format!("{} ({})", &sha[..7], reverted.summary());
+    let parent_label = parent_label(sha, reverted);
+    let labels = Labels {
+        base: &base_label,
+        ours: "HEAD",
+        theirs: &parent_label,
+    };
+    let style = merge_cmd::conflict_style()?;
+
+    // Reverting a root commit merges towards the empty tree
+    match parent {
+        Some(parent) => merge::merge_commits(Some(sha), head, parent, &labels, style),
+        None => {
+            let base = worktree::snapshot_of_commit(Some(sha))?;
+            let ours = worktree::snapshot_of_commit(Some(head))?;
+            let empty = worktree::snapshot_of_commit(None)?;
+            merge::merge_trees(&base, &ours, &empty, &labels, style)
+        }
+    }
+}
+
+/// Parent whose side of a merge commit is kept; ordinary commits have one
+fn mainline_parent(
+    sha: &str,
+    reverted: &Commit,
+    mainline: Option<usize>,
+) -> io::Result<Option<String>> {
+    match (reverted.parents.len(), mainline) {
+        (0, None) | (1, None) => Ok(reverted.parents.first().cloned()),
+        (n, Some(m)) if n > 1 && m <= n => Ok(Some(reverted.parents[m - 1].clone())),
+        (n, None) if n > 1 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("commit {} is a merge but no -m option was given.", sha),
+        )),
+        (n, Some(m)) if n > 1 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("commit {} does not have parent {}", sha, m),
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("mainline was specified but commit {} is not a merge.", sha),
+        )),
+    }
+}
+
+fn create_commit(head: &str, files: &worktree::Snapshot, message: &str) -> io::Result<()> {
+    let tree_sha = tree::write_snapshot(files)?;
+    let sha = commit::create(&tree_sha, &[head.to_string()], message)?;
+    refs::update_head(
+        &sha,
+        &format!("revert: {}", message.lines().next().unwrap_or("")),
+    )?;
+    println!("[{}] {}", &sha[..7], message.lines().next().unwrap_or(""));
+    Ok(())
+}
+
+fn revert_message(sha: &str, reverted: &Commit) -> String {
+    format!(
+        "Revert \"{}\"\n\nThis reverts commit {}.\n",
+        reverted.summary(),
+        sha
+    )
+}
+
+fn parent_label(sha: &str, reverted: &Commit) -> String {
+    format!("parent of {} ({})", &sha[..7], reverted.summary())
+}
+
+fn resolve_commit(name: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("bad revision '{}'", name))
+    })?;
+    let (sha, kind) = tag::peel(&sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a commit", name, kind),
+        ));
+    }
+    Ok(sha)
+}
+
+fn revert_state() -> io::Result<(String, String)> {
+    let sha = fs::read_to_string(REVERT_HEAD)
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "no revert in progress"))?;
+    let head = fs::read_to_string(ORIG_HEAD)?;
+    Ok((head.trim().to_string(), sha.trim().to_string()))
+}
+
+/// The `-m` given to a revert that stopped on conflicts
+fn saved_mainline() -> io::Result<Option<usize>> {
+    let mut opts = Config::default();
+    opts.read_file(Path::new(SEQUENCER_OPTS))?;
+    Ok(opts
+        .get("options.mainline")
+        .and_then(|value| value.parse().ok()))
+}
+
+fn clear_revert_state() -> io::Result<()> {
+    match fs::remove_dir_all(SEQUENCER_DIR) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    for path in [REVERT_HEAD, MERGE_MSG] {
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
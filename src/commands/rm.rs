@@ -0,0 +1,195 @@
+// rm: remove files from the index and the working tree
+//
+// rm [-f] [--cached] [-r] [-n] [-q] [--] <pathspec>...
+//
+// A pathspec is a tracked path, a directory holding tracked files (needs
+// -r), or a glob such as '*.txt'. Unless -f is given, a file is only
+// removed if the index matches both HEAD and the working tree, so nothing
+// that hasn't been committed is lost. With --cached the working tree is
+// left alone and only a file staged differently from both HEAD and the
+// working tree is refused.
+
+use crate::git::index::{Entry, Index};
+use crate::git::{refs, worktree};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+
+const USAGE: &str = "Usage: rm [-f] [--cached] [-r] [-n] [-q] [--] <pathspec>...";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut force = false;
+    let mut cached = false;
+    let mut recursive = false;
+    let mut dry_run = false;
+    let mut quiet = false;
+    let mut pathspecs = Vec::new();
+
+    let mut options_done = false;
+    for arg in args {
+        match arg.as_str() {
+            arg if options_done => pathspecs.push(normalize(arg)),
+            "--" => options_done = true,
+            "-f" | "--force" => force = true,
+            "--cached" => cached = true,
+            "-r" => recursive = true,
+            "-n" | "--dry-run" => dry_run = true,
+            "-q" | "--quiet" => quiet = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => pathspecs.push(normalize(arg)),
+        }
+    }
+    if pathspecs.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+    }
+
+    let mut index = Index::load()?;
+    let paths = matching_paths(&index, &pathspecs, recursive)?;
+
+    if !force {
+        let head = worktree::snapshot_of_commit(refs::head_commit()?.as_deref())?;
+        check_removable(&index, &paths, &head, cached)?;
+    }
+
+    if !quiet {
+        for path in &paths {
+            println!("rm '{}'", path);
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    for path in &paths {
+        index.remove(path);
+    }
+    index.write()?;
+    if !cached {
+        for path in &paths {
+            worktree::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Tracked paths named by `pathspecs`, each pathspec having to match at
+/// least one
+fn matching_paths(
+    index: &Index,
+    pathspecs: &[String],
+    recursive: bool,
+) -> io::Result<BTreeSet<String>> {
+    let mut paths = BTreeSet::new();
+    for spec in pathspecs {
+        let mut matched = false;
+        for entry in index.entries() {
+            let inside = spec.is_empty() || entry.path.starts_with(&format!("{}/", spec));
+            if inside && !recursive {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("not removing '{}' recursively without -r", display(spec)),
+                ));
+            }
+            if entry.path == *spec || inside || refs::glob_match(spec, &entry.path) {
+                matched = true;
+                paths.insert(entry.path.clone());
+            }
+        }
+        if !matched {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("pathspec '{}' did not match any files", display(spec)),
+            ));
+        }
+    }
+    Ok(paths)
+}
+
+/// Refuse, listing every offending file, if removing `paths` would lose
+/// changes not recorded in HEAD
+fn check_removable(
+    index: &Index,
+    paths: &BTreeSet<String>,
+    head: &worktree::Snapshot,
+    cached: bool,
+) -> io::Result<()> {
+    let mut both = Vec::new();
+    let mut staged = Vec::new();
+    let mut local = Vec::new();
+
+    for path in paths {
+        let entry = match index.get(path) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let in_head = head.get(path) == Some(&entry.file());
+        let in_worktree = is_unmodified(entry)?;
+
+        if !in_head && !in_worktree {
+            both.push(path.as_str());
+        } else if !cached && !in_head {
+            staged.push(path.as_str());
+        } else if !cached && !in_worktree {
+            local.push(path.as_str());
+        }
+    }
+
+    let keep_hint = "(use --cached to keep the file, or -f to force removal)";
+    let mut problems = Vec::new();
+    for (files, what, hint) in [
+        (
+            &both,
+            "staged content different from both the\nfile and the HEAD",
+            "(use -f to force removal)",
+        ),
+        (&staged, "changes staged in the index", keep_hint),
+        (&local, "local modifications", keep_hint),
+    ] {
+        if !files.is_empty() {
+            let (subject, verb) = match files.len() {
+                1 => ("file", "has"),
+                _ => ("files", "have"),
+            };
+            problems.push(format!(
+                "the following {} {} {}:\n    {}\n{}",
+                subject,
+                verb,
+                what,
+                files.join("\n    "),
+                hint
+            ));
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(io::Error::other(problems.join("\n")))
+}
+
+/// A file that is missing from the working tree has nothing to lose
+fn is_unmodified(entry: &Entry) -> io::Result<bool> {
+    if fs::symlink_metadata(&entry.path).is_err() {
+        return Ok(true);
+    }
+    worktree::matches_worktree(&entry.path, &entry.file())
+}
+
+/// Pathspecs are relative to the top of the repository
+fn normalize(spec: &str) -> String {
+    let spec = spec.trim_start_matches("./").trim_end_matches('/');
+    if spec == "." {
+        String::new()
+    } else {
+        spec.to_string()
+    }
+}
+
+fn display(spec: &str) -> &str {
+    if spec.is_empty() {
+        "."
+    } else {
+        spec
+    }
+}
@@ -0,0 +1,821 @@
+// send-pack: update refs in a remote repository over the receive-pack
+// protocol
+//
+// send-pack [--all] [--force] [--dry-run] [--atomic] [--quiet] <repository> [<ref>...]
+//
+// Each <ref> is a refspec: "<src>:<dst>", "<name>" for the same name on both
+// sides, ":<dst>" to delete, and a leading "+" to allow a non-fast-forward
+// update. --all pushes every local branch; with neither, the branches that
+// exist on both sides are pushed. The remote is sent one pack with the
+// objects it is missing and reports the result for every ref. With
+// --atomic, which the remote must advertise, it updates every ref or none:
+// one update refused here fails them all before anything is sent, and one
+// the remote refuses leaves the others alone there.
+//
+// A push may carry push options, strings the remote's hooks get to see,
+// when the remote advertises push-options. A signed push sends the update
+// commands inside a push certificate, signed with gpg, naming the pusher,
+// the remote and the nonce the remote advertised with push-cert=<nonce>.
+//
+// The remote reports back with report-status, or report-status-v2 when it
+// offers it: an "unpack" line, then "ok <ref>" or "ng <ref> <reason>" for
+// each ref, v2 adding "option" lines after an ok for a ref a hook updated
+// differently than asked. With side-band-64k the report comes on band 1
+// and what the remote's hooks print on band 2, shown as "remote: ..." lines.
+// With --quiet a remote that offers it is asked to keep its own progress
+// to itself (quiet).
+
+use crate::git::http::{self, HttpClient};
+use crate::git::ident::{self, Role};
+use crate::git::pktline::{self, Packet};
+use crate::git::url::{self, RemoteUrl, Scheme};
+use crate::git::{daemon, merge, object, pack, refs, revision, signature, ssh, tag};
+use std::io::{self, Cursor, Read, Write};
+
+const USAGE: &str =
+    "Usage: send-pack [--all] [--force] [--dry-run] [--atomic] [--quiet] <repository> [<ref>...]";
+const ZERO_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// Width git pads the "old..new" column to
+const SUMMARY_WIDTH: usize = 17;
+
+/// Whether a push is signed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Signed {
+    #[default]
+    No,
+    /// When the remote supports push certificates
+    IfAsked,
+    /// Failing when the remote doesn't support them
+    Yes,
+}
+
+impl Signed {
+    /// A --signed or push.gpgSign value
+    pub fn parse(value: &str) -> Option<Signed> {
+        match value.to_ascii_lowercase().as_str() {
+            "if-asked" => Some(Signed::IfAsked),
+            "true" | "yes" | "on" | "1" => Some(Signed::Yes),
+            "false" | "no" | "off" | "0" => Some(Signed::No),
+            _ => None,
+        }
+    }
+}
+
+/// How a push goes about its updates
+#[derive(Debug, Clone, Default)]
+pub struct SendOptions {
+    /// Push every local branch as well as the refspecs
+    pub all: bool,
+    /// Allow every update that isn't a fast-forward
+    pub force: bool,
+    /// Work out and report the updates without sending them
+    pub dry_run: bool,
+    /// Have the remote make every update or none
+    pub atomic: bool,
+    /// Also push the annotated tags the remote lacks that point into the
+    /// history being pushed
+    pub follow_tags: bool,
+    /// Strings passed on to the remote's hooks
+    pub push_options: Vec<String>,
+    pub signed: Signed,
+    /// Ask the remote not to report progress of its own
+    pub quiet: bool,
+}
+
+/// What came of one ref update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Made, or with --dry-run would have been
+    Ok,
+    UpToDate,
+    /// Refused before asking the remote, for the reason given
+    Rejected(&'static str),
+    /// Refused by the remote, for the reason it gave
+    RemoteRejected(String),
+    /// Left out of the remote's report
+    RemoteFailure,
+}
+
+/// One ref update to request
+#[derive(Debug, Clone)]
+pub struct Update {
+    /// Local name as written on the command line, for messages
+    pub src: String,
+    /// The local ref `src` names, if it names one rather than a commit
+    pub src_ref: Option<String>,
+    pub dst: String,
+    pub old: String,
+    /// `None` deletes the remote ref
+    pub new: Option<String>,
+    pub force: bool,
+    pub status: Status,
+}
+
+/// The updates of a push, in the order asked for
+pub struct SendOutcome {
+    /// The remote as shown in messages
+    pub url: String,
+    pub updates: Vec<Update>,
+}
+
+impl SendOutcome {
+    pub fn rejected(&self) -> bool {
+        self.updates
+            .iter()
+            .any(|update| !matches!(update.status, Status::Ok | Status::UpToDate))
+    }
+}
+
+/// What receive-pack advertises before the request
+struct Advertisement {
+    /// `(name, sha)` of every ref on the remote
+    refs: Vec<(String, String)>,
+    capabilities: Vec<String>,
+}
+
+/// A receive-pack conversation, as in clone's upload-pack `Session`
+enum Session {
+    Http {
+        client: HttpClient,
+        remote: RemoteUrl,
+    },
+    Ssh(ssh::Connection),
+    Daemon(daemon::Connection),
+}
+
+impl Session {
+    fn send(self, body: impl Read + Send + 'static) -> io::Result<Vec<u8>> {
+        match self {
+            Session::Http { client, remote } => {
+                let response = client
+                    .post_rpc_stream(
+                        &remote.http_endpoint("git-receive-pack"),
+                        "git-receive-pack",
+                        body,
+                    )?
+                    .send()
+                    .map_err(io::Error::other)?;
+                if !response.status().is_success() {
+                    return Err(io::Error::other(format!(
+                        "Failed to push: {}",
+                        response.status()
+                    )));
+                }
+                Ok(response.bytes().map_err(io::Error::other)?.to_vec())
+            }
+            Session::Ssh(connection) => connection.request(body),
+            Session::Daemon(connection) => connection.request(body),
+        }
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = SendOptions::default();
+    let mut positional = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--all" => options.all = true,
+            "-f" | "--force" => options.force = true,
+            "-n" | "--dry-run" => options.dry_run = true,
+            "--atomic" => options.atomic = true,
+            "-q" | "--quiet" => options.quiet = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => positional.push(arg.to_string()),
+        }
+    }
+    let (repository, refspecs) = positional
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+
+    let outcome = send(repository, refspecs, &options)?;
+    report(&outcome, false, &mut io::stdout().lock())?;
+    finish(outcome.rejected(), repository)
+}
+
+/// Update `refspecs` in the remote repository `repository`, sending the
+/// objects it needs for them. Without refspecs (or --all) the branches that
+/// exist on both sides are pushed.
+pub fn send(
+    repository: &str,
+    refspecs: &[String],
+    options: &SendOptions,
+) -> io::Result<SendOutcome> {
+    let (session, advertised) = open_session(repository)?;
+    let remote_refs = &advertised.refs;
+
+    let mut specs: Vec<String> = refspecs.to_vec();
+    if options.all {
+        specs.extend(
+            refs::list_refs("refs/heads/")?
+                .into_iter()
+                .map(|(name, _)| name),
+        );
+    } else if specs.is_empty() {
+        // Matching: branches that exist on both sides
+        for (name, _) in refs::list_refs("refs/heads/")? {
+            if remote_refs.iter().any(|(remote, _)| *remote == name) {
+                specs.push(name);
+            }
+        }
+    }
+
+    let mut updates = Vec::new();
+    for spec in &specs {
+        updates.push(parse_refspec(spec, remote_refs, options.force)?);
+    }
+    if options.follow_tags {
+        for tag in followed_tags(&updates, remote_refs)? {
+            updates.push(parse_refspec(&tag, remote_refs, false)?);
+        }
+    }
+    let supports = |capability: &str| advertised.capabilities.iter().any(|c| c == capability);
+    if updates.iter().any(|u| u.new.is_none()) && !supports("delete-refs") {
+        return Err(io::Error::other(
+            "the receiving end does not support deleting refs",
+        ));
+    }
+    if options.atomic && !supports("atomic") {
+        return Err(io::Error::other(
+            "the receiving end does not support --atomic push",
+        ));
+    }
+    if !options.push_options.is_empty() && !supports("push-options") {
+        return Err(io::Error::other(
+            "the receiving end does not support push options",
+        ));
+    }
+    let nonce = advertised
+        .capabilities
+        .iter()
+        .find_map(|c| c.strip_prefix("push-cert="));
+    if options.signed == Signed::Yes && nonce.is_none() {
+        return Err(io::Error::other(
+            "the receiving end does not support --signed push",
+        ));
+    }
+    let certificate = match options.signed {
+        Signed::No => None,
+        Signed::IfAsked | Signed::Yes => nonce.map(|nonce| Certificate {
+            pushee: url::anonymize(repository),
+            nonce,
+        }),
+    };
+
+    // Refuse what the remote would lose, and leave out what is already
+    // current
+    for update in &mut updates {
+        let old = &update.old;
+        match &update.new {
+            Some(new) if new == old => update.status = Status::UpToDate,
+            Some(_) if old != ZERO_SHA && !update.force && update.dst.starts_with("refs/tags/") => {
+                update.status = Status::Rejected("already exists")
+            }
+            Some(new) if old != ZERO_SHA && !update.force => {
+                if let Err(reason) = fast_forward(old, new)? {
+                    update.status = Status::Rejected(reason);
+                }
+            }
+            _ => {}
+        }
+    }
+    let refused = |update: &Update| matches!(update.status, Status::Rejected(_));
+    if options.atomic && updates.iter().any(refused) {
+        for update in updates.iter_mut().filter(|u| u.status == Status::Ok) {
+            update.status = Status::Rejected("atomic push failed");
+        }
+    }
+    let url = url::parse(repository)?.to_string();
+    let commands: Vec<&Update> = updates
+        .iter()
+        .filter(|update| update.status == Status::Ok)
+        .collect();
+    if commands.is_empty() || options.dry_run {
+        return Ok(SendOutcome { url, updates });
+    }
+
+    let response = session.send(request(
+        &commands,
+        remote_refs,
+        &advertised.capabilities,
+        options,
+        certificate.as_ref(),
+    )?)?;
+    let response = match supports("side-band-64k") {
+        true => demultiplex(&response, &mut io::stderr())?,
+        false => response,
+    };
+    let statuses = parse_report(&response)?;
+    for update in updates.iter_mut().filter(|u| u.status == Status::Ok) {
+        let Some(status) = statuses.iter().find(|status| status.name == update.dst) else {
+            update.status = Status::RemoteFailure;
+            continue;
+        };
+        if let Some(reason) = &status.reason {
+            update.status = Status::RemoteRejected(reason.clone());
+            continue;
+        }
+        if let Some(refname) = &status.refname {
+            update.dst = refname.clone();
+        }
+        if let Some(old) = &status.old {
+            update.old = old.clone();
+        }
+        if let Some(new) = &status.new {
+            update.new = Some(new.clone()).filter(|new| new != ZERO_SHA);
+        }
+        update.force |= status.forced;
+    }
+    Ok(SendOutcome { url, updates })
+}
+
+/// Print "To <url>" and a line for each update to `out`, as git does; up
+/// to date ones only when `verbose`
+pub fn report(outcome: &SendOutcome, verbose: bool, out: &mut impl Write) -> io::Result<()> {
+    let changed = outcome
+        .updates
+        .iter()
+        .any(|update| update.status != Status::UpToDate);
+    if verbose || changed {
+        writeln!(out, "To {}", outcome.url)?;
+    }
+    for update in &outcome.updates {
+        match &update.status {
+            Status::Ok => report_success(update, out)?,
+            Status::UpToDate if verbose => report_line(update, '=', "[up to date]", None, out)?,
+            Status::UpToDate => {}
+            Status::Rejected(reason) => report_line(update, '!', "[rejected]", Some(reason), out)?,
+            Status::RemoteRejected(reason) => {
+                report_line(update, '!', "[remote rejected]", Some(reason), out)?
+            }
+            Status::RemoteFailure => report_line(
+                update,
+                '!',
+                "[remote failure]",
+                Some("remote failed to report status"),
+                out,
+            )?,
+        }
+    }
+    if !changed {
+        writeln!(out, "Everything up-to-date")?;
+    }
+    Ok(())
+}
+
+/// Connect to `git-receive-pack` and read the refs and capabilities it
+/// advertises
+fn open_session(repo_url: &str) -> io::Result<(Session, Advertisement)> {
+    let remote = url::parse(repo_url)?;
+    let (session, data) = match remote.scheme {
+        Scheme::Http | Scheme::Https => {
+            let mut client = http::client(repo_url)?;
+            let refs_url = remote.http_endpoint("info/refs?service=git-receive-pack");
+            let response = client.get_authenticated(&remote, &refs_url, &[])?;
+            if !response.status().is_success() {
+                return Err(io::Error::other(format!(
+                    "Failed to fetch refs: {}",
+                    response.status()
+                )));
+            }
+            let body = response.bytes().map_err(io::Error::other)?.to_vec();
+            let session = Session::Http { client, remote };
+            (session, body)
+        }
+        Scheme::Ssh => {
+            let mut connection = ssh::connect(&remote, "git-receive-pack", None)?;
+            let advertisement = connection.read_advertisement()?;
+            (Session::Ssh(connection), advertisement)
+        }
+        Scheme::Git => {
+            let mut connection = daemon::connect(&remote, "git-receive-pack", None)?;
+            let advertisement = connection.read_advertisement()?;
+            (Session::Daemon(connection), advertisement)
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "Unsupported transport '{}' for {}",
+                    remote.scheme.as_str(),
+                    remote
+                ),
+            ))
+        }
+    };
+
+    let mut advertised = Advertisement {
+        refs: Vec::new(),
+        capabilities: Vec::new(),
+    };
+    for packet in pktline::split(&data)? {
+        let line = match packet {
+            Packet::Data(line) => String::from_utf8_lossy(line).into_owned(),
+            Packet::Flush | Packet::Delim => continue,
+        };
+        // Smart HTTP starts with "# service=git-receive-pack"
+        if line.starts_with('#') {
+            continue;
+        }
+        let (line, caps) = match line.split_once('\0') {
+            Some((line, caps)) => (line.to_string(), Some(caps.to_string())),
+            None => (line, None),
+        };
+        if let Some(caps) = caps {
+            advertised.capabilities = caps.split_whitespace().map(str::to_string).collect();
+        }
+        if let Some((sha, name)) = line.trim_end().split_once(' ') {
+            // An empty repository advertises only "capabilities^{}"
+            if name != "capabilities^{}" {
+                advertised.refs.push((name.to_string(), sha.to_string()));
+            }
+        }
+    }
+    Ok((session, advertised))
+}
+
+fn parse_refspec(spec: &str, remote_refs: &[(String, String)], force: bool) -> io::Result<Update> {
+    let (forced, spec) = match spec.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, spec),
+    };
+    let (src, dst) = match spec.split_once(':') {
+        Some((src, dst)) => (src, if dst.is_empty() { src } else { dst }),
+        None => (spec, spec),
+    };
+
+    let (src_ref, new) = if src.is_empty() {
+        (None, None)
+    } else {
+        let (full, sha) = local_ref(src)?;
+        (full, Some(sha))
+    };
+    let dst = match remote_name(dst, src_ref.as_deref(), remote_refs) {
+        Err(_) if new.is_none() => dst.to_string(),
+        dst => dst?,
+    };
+    let old = remote_refs
+        .iter()
+        .find(|(name, _)| *name == dst)
+        .map(|(_, sha)| sha.clone())
+        .unwrap_or_else(|| ZERO_SHA.to_string());
+
+    if new.is_none() && old == ZERO_SHA {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unable to delete '{}': remote ref does not exist", dst),
+        ));
+    }
+    Ok(Update {
+        src: src.to_string(),
+        src_ref,
+        dst,
+        old,
+        new,
+        force: force || forced,
+        status: Status::Ok,
+    })
+}
+
+/// The annotated tags a --follow-tags push adds to `updates`: those the
+/// remote doesn't have whose commit one of the updates brings along
+fn followed_tags(updates: &[Update], remote_refs: &[(String, String)]) -> io::Result<Vec<String>> {
+    let mut tags = Vec::new();
+    for (name, sha) in refs::list_refs("refs/tags/")? {
+        if remote_refs.iter().any(|(dst, _)| *dst == name)
+            || updates.iter().any(|update| update.dst == name)
+        {
+            continue;
+        }
+        if object::read(&sha)?.0 != "tag" {
+            continue;
+        }
+        let (target, _) = tag::peel(&sha)?;
+        for new in updates.iter().filter_map(|update| update.new.as_deref()) {
+            if new == target || merge::is_ancestor(&target, new)? {
+                tags.push(name);
+                break;
+            }
+        }
+    }
+    Ok(tags)
+}
+
+/// The local ref (if `name` is one) and the object `name` stands for
+fn local_ref(name: &str) -> io::Result<(Option<String>, String)> {
+    for candidate in [
+        name.to_string(),
+        refs::branch_ref(name),
+        refs::tag_ref(name),
+    ] {
+        if candidate.starts_with("refs/") {
+            if let Some(sha) = refs::read_ref(&candidate)? {
+                return Ok((Some(candidate), sha));
+            }
+        }
+    }
+    match revision::resolve(name)? {
+        Some(sha) => Ok((None, sha)),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("src refspec {} does not match any", name),
+        )),
+    }
+}
+
+/// Full remote ref name for the destination side of a refspec
+fn remote_name(
+    dst: &str,
+    src_ref: Option<&str>,
+    remote_refs: &[(String, String)],
+) -> io::Result<String> {
+    if dst.starts_with("refs/") {
+        return Ok(dst.to_string());
+    }
+    for candidate in [refs::branch_ref(dst), refs::tag_ref(dst)] {
+        if remote_refs.iter().any(|(name, _)| *name == candidate) {
+            return Ok(candidate);
+        }
+    }
+    match src_ref {
+        Some(src) if src.starts_with("refs/heads/") => Ok(refs::branch_ref(dst)),
+        Some(src) if src.starts_with("refs/tags/") => Ok(refs::tag_ref(dst)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The destination you provided is not a full refname (i.e., starting with \"refs/\"): {}",
+                dst
+            ),
+        )),
+    }
+}
+
+/// Whether `new` may replace `old` without --force
+fn fast_forward(old: &str, new: &str) -> io::Result<Result<(), &'static str>> {
+    if object::read(old).is_err() {
+        return Ok(Err("fetch first"));
+    }
+    if merge::is_ancestor(old, new)? {
+        Ok(Ok(()))
+    } else {
+        Ok(Err("non-fast-forward"))
+    }
+}
+
+/// What a signed push certifies besides the updates
+struct Certificate<'a> {
+    /// The remote, without credentials
+    pushee: String,
+    /// The remote's push-cert nonce
+    nonce: &'a str,
+}
+
+/// The update commands, or the certificate holding them, then any push
+/// options, then (unless everything is a deletion) a pack of the objects
+/// the remote doesn't have, generated as the request is sent
+fn request(
+    commands: &[&Update],
+    remote_refs: &[(String, String)],
+    advertised: &[String],
+    options: &SendOptions,
+    certificate: Option<&Certificate>,
+) -> io::Result<Box<dyn Read + Send>> {
+    let push_options = &options.push_options;
+    let supports = |capability: &str| advertised.iter().any(|c| c == capability);
+    let mut capabilities = match supports("report-status-v2") {
+        true => "report-status-v2 delete-refs".to_string(),
+        false => "report-status delete-refs".to_string(),
+    };
+    if supports("side-band-64k") {
+        capabilities.push_str(" side-band-64k");
+    }
+    if options.quiet && supports("quiet") {
+        capabilities.push_str(" quiet");
+    }
+    if options.atomic {
+        capabilities.push_str(" atomic");
+    }
+    if !push_options.is_empty() {
+        capabilities.push_str(" push-options");
+    }
+    capabilities.push_str(&format!(" agent={}", http::USER_AGENT));
+    let update_lines = commands.iter().map(|update| {
+        let new = update.new.as_deref().unwrap_or(ZERO_SHA);
+        format!("{} {} {}\n", update.old, new, update.dst)
+    });
+
+    let mut body = Vec::new();
+    match certificate {
+        Some(certificate) => {
+            let mut text = format!(
+                "certificate version 0.1\npusher {}\npushee {}\nnonce {}\n",
+                ident::signature(Role::Committer)?,
+                certificate.pushee,
+                certificate.nonce
+            );
+            for option in push_options {
+                text.push_str(&format!("push-option {}\n", option));
+            }
+            text.push('\n');
+            text.extend(update_lines);
+            let signed = signature::sign(text.as_bytes())?;
+            text.push_str(&String::from_utf8_lossy(&signed));
+            let start = format!("push-cert\0{}\n", capabilities);
+            body.extend_from_slice(&pktline::encode(start.as_bytes()));
+            for line in text.split_inclusive('\n') {
+                body.extend_from_slice(&pktline::encode(line.as_bytes()));
+            }
+            body.extend_from_slice(&pktline::encode(b"push-cert-end\n"));
+        }
+        None => {
+            for (i, line) in update_lines.enumerate() {
+                let line = match i {
+                    0 => format!("{}\0{}\n", line.trim_end(), capabilities),
+                    _ => line,
+                };
+                body.extend_from_slice(&pktline::encode(line.as_bytes()));
+            }
+        }
+    }
+    body.extend_from_slice(pktline::FLUSH);
+    if !push_options.is_empty() {
+        for option in push_options {
+            body.extend_from_slice(&pktline::encode(option.as_bytes()));
+        }
+        body.extend_from_slice(pktline::FLUSH);
+    }
+
+    let tips: Vec<String> = commands.iter().filter_map(|u| u.new.clone()).collect();
+    if !tips.is_empty() {
+        let known: Vec<String> = remote_refs.iter().map(|(_, sha)| sha.clone()).collect();
+        let objects = pack::objects_to_send(&tips, &known)?;
+        return Ok(Box::new(Cursor::new(body).chain(pack::stream(objects))));
+    }
+    Ok(Box::new(Cursor::new(body)))
+}
+
+/// One ref's entry in a status report
+#[derive(Debug, Default)]
+struct RefStatus {
+    name: String,
+    /// Why the remote refused the update; `None` for ok
+    reason: Option<String>,
+    /// What report-status-v2 says a hook made of the update instead, where
+    /// it differs from the command
+    refname: Option<String>,
+    old: Option<String>,
+    new: Option<String>,
+    forced: bool,
+}
+
+/// The per-ref results of a report-status or report-status-v2 report. A
+/// failed unpack is shown as an error; the refs then all say why they
+/// failed.
+fn parse_report(response: &[u8]) -> io::Result<Vec<RefStatus>> {
+    let mut statuses: Vec<RefStatus> = Vec::new();
+    for packet in pktline::split(response)? {
+        let line = match packet {
+            Packet::Data(line) => String::from_utf8_lossy(line).trim_end().to_string(),
+            Packet::Flush | Packet::Delim => break,
+        };
+        if let Some(status) = line.strip_prefix("unpack ") {
+            if status != "ok" {
+                eprintln!("error: remote unpack failed: {}", status);
+            }
+        } else if let Some(name) = line.strip_prefix("ok ") {
+            statuses.push(RefStatus {
+                name: name.to_string(),
+                ..RefStatus::default()
+            });
+        } else if let Some(rest) = line.strip_prefix("ng ") {
+            let (name, reason) = rest.split_once(' ').unwrap_or((rest, "failed"));
+            statuses.push(RefStatus {
+                name: name.to_string(),
+                reason: Some(reason.to_string()),
+                ..RefStatus::default()
+            });
+        } else if let Some(option) = line.strip_prefix("option ") {
+            // Options describe the ok line before them
+            let Some(status) = statuses.last_mut().filter(|s| s.reason.is_none()) else {
+                continue;
+            };
+            let (key, value) = option.split_once(' ').unwrap_or((option, ""));
+            match key {
+                "refname" => status.refname = Some(value.to_string()),
+                "old-oid" => status.old = Some(value.to_string()),
+                "new-oid" => status.new = Some(value.to_string()),
+                "forced-update" => status.forced = true,
+                _ => {}
+            }
+        }
+    }
+    Ok(statuses)
+}
+
+/// Take apart a side-band response: band 1 is returned, band 2 is shown
+/// on `progress` as "remote: ..." lines, and band 3 is a fatal error
+fn demultiplex(response: &[u8], progress: &mut impl Write) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for packet in pktline::split(response)? {
+        let Packet::Data(payload) = packet else {
+            break;
+        };
+        let Some((&band, content)) = payload.split_first() else {
+            continue;
+        };
+        match band {
+            1 => data.extend_from_slice(content),
+            2 => {
+                for line in content.split_inclusive(|&b| b == b'\n' || b == b'\r') {
+                    progress.write_all(b"remote: ")?;
+                    progress.write_all(line)?;
+                }
+            }
+            3 => {
+                return Err(io::Error::other(format!(
+                    "remote error: {}",
+                    String::from_utf8_lossy(content).trim_end()
+                )))
+            }
+            _ => {}
+        }
+    }
+    Ok(data)
+}
+
+fn report_success(update: &Update, out: &mut impl Write) -> io::Result<()> {
+    match &update.new {
+        None => report_line(update, '-', "[deleted]", None, out),
+        Some(_) if update.old == ZERO_SHA => {
+            let kind = if update.dst.starts_with("refs/tags/") {
+                "[new tag]"
+            } else {
+                "[new branch]"
+            };
+            report_line(update, '*', kind, None, out)
+        }
+        Some(new) if update.force && !is_ancestor_or_missing(&update.old, new) => {
+            let summary = format!("{}...{}", &update.old[..7], &new[..7]);
+            report_line(update, '+', &summary, Some("forced update"), out)
+        }
+        Some(new) => {
+            let summary = format!("{}..{}", &update.old[..7], &new[..7]);
+            report_line(update, ' ', &summary, None, out)
+        }
+    }
+}
+
+fn is_ancestor_or_missing(old: &str, new: &str) -> bool {
+    object::read(old).is_err() || merge::is_ancestor(old, new).unwrap_or(false)
+}
+
+fn report_line(
+    update: &Update,
+    flag: char,
+    summary: &str,
+    reason: Option<&str>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let dst = short_name(&update.dst);
+    let refs = match update.new {
+        None => dst.to_string(),
+        Some(_) => format!("{} -> {}", short_name(&update.src), dst),
+    };
+    match reason {
+        Some(reason) => writeln!(
+            out,
+            " {} {:<width$} {} ({})",
+            flag,
+            summary,
+            refs,
+            reason,
+            width = SUMMARY_WIDTH
+        ),
+        None => writeln!(
+            out,
+            " {} {:<width$} {}",
+            flag,
+            summary,
+            refs,
+            width = SUMMARY_WIDTH
+        ),
+    }
+}
+
+fn short_name(name: &str) -> &str {
+    name.strip_prefix("refs/heads/")
+        .or_else(|| name.strip_prefix("refs/tags/"))
+        .unwrap_or(name)
+}
+
+pub fn finish(rejected: bool, repository: &str) -> io::Result<()> {
+    if rejected {
+        return Err(io::Error::other(format!(
+            "failed to push some refs to '{}'",
+            repository
+        )));
+    }
+    Ok(())
+}
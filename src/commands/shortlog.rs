@@ -0,0 +1,130 @@
+// shortlog: summarize history by author
+//
+// shortlog [-s | --summary] [-n | --numbered] [-e | --email] [-c | --committer]
+//          [<rev>... | <rev>..<rev>]
+//
+// Groups the commits reachable from the given revisions (HEAD by default)
+// by author and prints each author with their commit count followed by
+// the commits' subjects, oldest first:
+//
+// Alice (2):
+//       first change
+//       second change
+//
+// Authors are listed by name, or by count with -n. -s prints only the
+// counts, -e adds each author's email and -c groups by committer instead.
+// Revisions are the ones rev-list accepts.
+
+use crate::commands::rev_list;
+use crate::git::revwalk::RevWalk;
+use crate::git::{commit, encoding, ident, refs};
+use std::collections::BTreeMap;
+use std::io::{self, BufWriter, Write};
+
+const USAGE: &str =
+    "Usage: shortlog [-s] [-n] [-e] [-c] [<rev>... | <rev>..<rev>] [--not <rev>...]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut summary = false;
+    let mut numbered = false;
+    let mut email = false;
+    let mut committer = false;
+    let mut negated = false;
+    let mut walk = RevWalk::new();
+    let mut any = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "-s" | "--summary" => summary = true,
+            "-n" | "--numbered" => numbered = true,
+            "-e" | "--email" => email = true,
+            "-c" | "--committer" => committer = true,
+            "--not" => negated = !negated,
+            arg if arg.starts_with('-') && arg.len() > 2 && !arg.starts_with("--") => {
+                // Bundled short flags such as -sn
+                for flag in arg[1..].chars() {
+                    match flag {
+                        's' => summary = true,
+                        'n' => numbered = true,
+                        'e' => email = true,
+                        'c' => committer = true,
+                        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+                    }
+                }
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => {
+                any = true;
+                rev_list::add_revision(&mut walk, arg, negated)?;
+            }
+        }
+    }
+    if !any {
+        refs::require_head_commit()?;
+        rev_list::add_revision(&mut walk, "HEAD", false)?;
+    }
+
+    // Subjects per author, newest first as walked
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for sha in walk {
+        let commit = commit::read(&sha?)?;
+        let identity = if committer {
+            &commit.committer
+        } else {
+            &commit.author
+        };
+        groups
+            .entry(author_key(identity, email))
+            .or_default()
+            .push(subject(&commit.message));
+    }
+
+    let mut groups: Vec<(String, Vec<String>)> = groups.into_iter().collect();
+    if numbered {
+        // Stable, so equal counts stay in name order
+        groups.sort_by_key(|(_, subjects)| std::cmp::Reverse(subjects.len()));
+    }
+
+    let mut text = String::new();
+    for (author, subjects) in &groups {
+        if summary {
+            text.push_str(&format!("{:6}\t{}\n", subjects.len(), author));
+            continue;
+        }
+        text.push_str(&format!("{} ({}):\n", author, subjects.len()));
+        for subject in subjects.iter().rev() {
+            text.push_str(&format!("      {}\n", subject));
+        }
+        text.push('\n');
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    out.write_all(&encoding::for_output(&text)?)?;
+    out.flush()
+}
+
+/// What commits are grouped under: the name in `identity`, with its email
+/// when `email` is set. This is where a mailmap would apply.
+fn author_key(identity: &str, email: bool) -> String {
+    let (who, _) = ident::split(identity);
+    if email {
+        return who.to_string();
+    }
+    match who.find('<') {
+        Some(start) => who[..start].trim().to_string(),
+        None => who.to_string(),
+    }
+}
+
+/// The message's first paragraph on one line
+fn subject(message: &str) -> String {
+    message
+        .trim_start_matches('\n')
+        .lines()
+        .take_while(|line| !line.trim().is_empty())
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,228 @@
+// show: pretty-print objects
+//
+// show [-s | --no-patch] [-U<n>] [--date=<mode>] [-a | --text] [<object>...]
+//
+// Each object (HEAD by default) is shown according to its type. A commit
+// gets git's log header (commit, Merge, Author and Date lines), its message
+// indented by four spaces and the patch against its first parent (against
+// an empty tree for a root commit), left out with -s. A tag gets its tag,
+// Tagger and Date lines and message, followed by the object it points to.
+// A tree lists its entry names as "ls-tree --name-only" does, with a "/"
+// after subtrees, below a "tree <object>" line. A blob, such as the file
+// <rev>:<path> names, is written out as it is, streamed rather than read
+// into memory when it is stored loose.
+//
+// Written to a terminal, a binary blob (one with a NUL byte in its first
+// 8000 bytes) is left out with a note on stderr unless -a says to show it
+// as text, and one bigger than core.bigFileThreshold (512 MiB by default,
+// with k, m or g suffixes as config takes them) is preceded by a warning.
+//
+// Dates are shown in the --date mode (see git/date.rs), by default that of
+// log.date.
+
+use crate::commands::diff as diff_cmd;
+use crate::git::config::Config;
+use crate::git::date::{self, Mode};
+use crate::git::object::{self, Error};
+use crate::git::{commit, diff, encoding, ident, refs, revision, tag, tree};
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+
+const USAGE: &str =
+    "Usage: show [-s | --no-patch] [-U<n>] [--date=<mode>] [-a | --text] [<object>...]";
+
+/// Default for core.bigFileThreshold
+const DEFAULT_BIG_FILE_THRESHOLD: u64 = 512 << 20;
+
+/// How a commit is shown, here and by log
+pub struct Options {
+    pub patch: bool,
+    pub context: usize,
+    pub date: Mode,
+    /// The paths the patch is limited to, every path for none
+    pub paths: Vec<String>,
+    /// Write binary blobs to a terminal too
+    pub text: bool,
+    /// Blobs bigger than this get a warning before going to a terminal
+    pub big_file_threshold: u64,
+}
+
+impl Options {
+    /// The defaults, with the date mode of log.date
+    pub fn from_config() -> io::Result<Options> {
+        let config = Config::load()?;
+        let date = match config.get("log.date") {
+            Some(mode) => Mode::parse(mode)?,
+            None => Mode::default(),
+        };
+        let big_file_threshold = match config.get_int("core.bigFileThreshold")? {
+            Some(threshold) => u64::try_from(threshold).unwrap_or_default(),
+            None => DEFAULT_BIG_FILE_THRESHOLD,
+        };
+        Ok(Options {
+            patch: true,
+            context: 3,
+            date,
+            paths: Vec::new(),
+            text: false,
+            big_file_threshold,
+        })
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = Options::from_config()?;
+    let mut names = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-s" | "--no-patch" => options.patch = false,
+            "-p" | "--patch" => options.patch = true,
+            "-a" | "--text" => options.text = true,
+            arg if arg.starts_with("-U") || arg.starts_with("--unified=") => {
+                let value = arg.strip_prefix("--unified=").unwrap_or_else(|| &arg[2..]);
+                options.context = value
+                    .parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+            }
+            arg if arg.starts_with("--date=") => options.date = Mode::parse(&arg[7..])?,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => names.push(arg),
+        }
+    }
+    if names.is_empty() {
+        refs::require_head_commit()?;
+        names.push("HEAD");
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    for name in names {
+        let sha = revision::resolve(name)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "ambiguous argument '{}': unknown revision or path not in the working tree.",
+                    name
+                ),
+            )
+        })?;
+        show(&mut out, name, &sha, &options)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Show object `sha`, which the command line called `name`
+fn show(out: &mut impl Write, name: &str, sha: &str, options: &Options) -> io::Result<()> {
+    let (kind, size, mut reader) = object::open(sha)?;
+    if kind == "blob" {
+        return show_blob(out, name, size, reader, options);
+    }
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    match kind.as_str() {
+        "commit" => show_commit(out, sha, options),
+        "tag" => {
+            let tag = tag::parse(&content)?;
+            let mut text = format!("tag {}\n", tag.name);
+            if let Some(tagger) = &tag.tagger {
+                text.push_str(&identity_lines("Tagger", tagger, &options.date));
+            }
+            text.push('\n');
+            text.push_str(&tag.message);
+            if !tag.message.is_empty() && !tag.message.ends_with('\n') {
+                text.push('\n');
+            }
+            out.write_all(&encoding::for_output(&text)?)?;
+            out.write_all(b"\n")?;
+            show(out, &tag.object, &tag.object, options)
+        }
+        "tree" => {
+            writeln!(out, "tree {}\n", name)?;
+            for entry in tree::parse(&content)? {
+                let slash = if entry.is_tree() { "/" } else { "" };
+                out.write_all(entry.name.as_bytes())?;
+                writeln!(out, "{}", slash)?;
+            }
+            Ok(())
+        }
+        kind => Err(Error::InvalidFormat(format!("cannot show a {} object", kind)).into()),
+    }
+}
+
+/// Stream out the blob `name` of `size` bytes, minding a terminal
+fn show_blob(
+    out: &mut impl Write,
+    name: &str,
+    size: u64,
+    mut blob: impl Read,
+    options: &Options,
+) -> io::Result<()> {
+    // Enough of the start is read ahead to tell whether it is binary
+    let mut start = Vec::new();
+    (&mut blob).take(8000).read_to_end(&mut start)?;
+    if io::stdout().is_terminal() {
+        if diff::is_binary(&start) && !options.text {
+            eprintln!(
+                "{}: binary file of {} bytes not shown on a terminal; use --text to show it anyway",
+                name, size
+            );
+            return Ok(());
+        }
+        if size > options.big_file_threshold {
+            eprintln!(
+                "warning: {} is {} bytes, over core.bigFileThreshold ({} bytes)",
+                name, size, options.big_file_threshold
+            );
+        }
+    }
+    out.write_all(&start)?;
+    io::copy(&mut blob, out)?;
+    Ok(())
+}
+
+/// Show the commit `sha`: its header, message and (unless left out) patch
+pub fn show_commit(out: &mut impl Write, sha: &str, options: &Options) -> io::Result<()> {
+    let commit = commit::read(sha)?;
+    let mut text = format!("commit {}\n", sha);
+    if commit.parents.len() > 1 {
+        let parents: Vec<&str> = commit.parents.iter().map(|p| &p[..7]).collect();
+        text.push_str(&format!("Merge: {}\n", parents.join(" ")));
+    }
+    text.push_str(&identity_lines("Author", &commit.author, &options.date));
+    text.push('\n');
+    // Blank lines around the message are dropped
+    let message = commit.message.trim_matches('\n');
+    for line in message.lines() {
+        text.push_str(&format!("    {}\n", line));
+    }
+    out.write_all(&encoding::for_output(&text)?)?;
+
+    if options.patch {
+        let parent_tree = match commit.parents.first() {
+            Some(parent) => Some(commit::read(parent)?.tree),
+            None => None,
+        };
+        let patch = diff_cmd::tree_patch(
+            parent_tree.as_deref(),
+            &commit.tree,
+            options.context,
+            &options.paths,
+        )?;
+        if !patch.is_empty() {
+            out.write_all(b"\n")?;
+            out.write_all(&patch)?;
+        }
+    }
+    Ok(())
+}
+
+/// The `<label>: Name <email>` and `Date:` lines of an identity
+fn identity_lines(label: &str, identity: &str, mode: &Mode) -> String {
+    let (who, timestamp) = ident::split(identity);
+    let mut lines = format!("{}: {}\n", label, who);
+    if let Some(date) = date::format(timestamp, mode) {
+        lines.push_str(&format!("Date:   {}\n", date));
+    }
+    lines
+}
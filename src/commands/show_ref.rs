@@ -0,0 +1,141 @@
+// show-ref: list refs and the objects they point at
+//
+// show-ref [--head] [--heads] [--tags] [-d] [-s] [-q] [<pattern>...]
+// show-ref --verify [-d] [-s] [-q] <ref>...
+//
+// Prints "<sha> <ref>" for every ref, by name, limited to branches with
+// --heads and tags with --tags (or both), and to refs matching a <pattern>
+// if any are given: a pattern matches a ref it ends, on a "/" boundary, so
+// "main" matches refs/heads/main and refs/remotes/origin/main. --head lists
+// HEAD first. -d follows every annotated tag with a "<sha> <ref>^{}" line
+// for the object it tags, -s prints only the ids and -q prints nothing.
+// The exit status is 1 when nothing matches.
+//
+// --verify looks up each <ref> as an exact full name (or HEAD) and fails
+// for one that doesn't exist, quietly with -q.
+
+use crate::git::{refs, tag};
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "Usage: show-ref [--head] [--heads] [--tags] [-d] [-s] [-q] [<pattern>...] | \
+                     show-ref --verify [-d] [-s] [-q] <ref>...";
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Format {
+    dereference: bool,
+    hash_only: bool,
+    quiet: bool,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut format = Format::default();
+    let mut verify = false;
+    let mut head = false;
+    let mut heads = false;
+    let mut tags = false;
+    let mut patterns = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--verify" => verify = true,
+            "--head" => head = true,
+            "--heads" => heads = true,
+            "--tags" => tags = true,
+            "-d" | "--dereference" => format.dereference = true,
+            "-s" | "--hash" => format.hash_only = true,
+            "-q" | "--quiet" => format.quiet = true,
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+            }
+            arg => patterns.push(arg),
+        }
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    if verify {
+        if patterns.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--verify requires a reference",
+            ));
+        }
+        for name in patterns {
+            let found = if name == "HEAD" || name.starts_with("refs/") {
+                refs::read_ref(name)?
+            } else {
+                None
+            };
+            match found {
+                Some(sha) => show(&mut out, name, &sha, format)?,
+                None if format.quiet => process::exit(1),
+                None => {
+                    out.flush()?;
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("'{}' - not a valid ref", name),
+                    ));
+                }
+            }
+        }
+        return out.flush();
+    }
+
+    let mut candidates = Vec::new();
+    // --head shows HEAD whatever the patterns say
+    if head {
+        if let Some(sha) = refs::head_commit()? {
+            candidates.push(("HEAD".to_string(), sha));
+        }
+    }
+    // --heads and --tags read only the refs they list
+    let listed = match (heads, tags) {
+        (false, false) => refs::list_refs("refs/")?,
+        (true, false) => refs::list_refs("refs/heads/")?,
+        (false, true) => refs::list_refs("refs/tags/")?,
+        (true, true) => [
+            refs::list_refs("refs/heads/")?,
+            refs::list_refs("refs/tags/")?,
+        ]
+        .concat(),
+    };
+    for (name, sha) in listed {
+        let matches = patterns.is_empty()
+            || patterns.iter().any(|pattern| {
+                name == *pattern
+                    || name
+                        .strip_suffix(pattern)
+                        .is_some_and(|rest| rest.ends_with('/'))
+            });
+        if matches {
+            candidates.push((name, sha));
+        }
+    }
+    if candidates.is_empty() {
+        process::exit(1);
+    }
+    for (name, sha) in &candidates {
+        show(&mut out, name, sha, format)?;
+    }
+    out.flush()
+}
+
+fn show(out: &mut impl Write, name: &str, sha: &str, format: Format) -> io::Result<()> {
+    if format.quiet {
+        return Ok(());
+    }
+    let line = |out: &mut dyn Write, sha: &str, name: &str| {
+        if format.hash_only {
+            writeln!(out, "{}", sha)
+        } else {
+            writeln!(out, "{} {}", sha, name)
+        }
+    };
+    line(out, sha, name)?;
+    if format.dereference {
+        let (target, _) = tag::peel(sha)?;
+        if target != sha {
+            line(out, &target, &format!("{}^{{}}", name))?;
+        }
+    }
+    Ok(())
+}
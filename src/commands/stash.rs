@@ -0,0 +1,569 @@
+// stash: put local changes away and bring them back later
+//
+// stash [push] [-q] [-m <message>] [-p] [--] [<pathspec>...]
+// stash list
+// stash apply [<stash>]
+// stash pop [<stash>]
+// stash drop [<stash>]
+//
+// push records the changes to tracked files in a commit on refs/stash and
+// puts the files back as HEAD has them. The commit is git's: its tree is
+// the working tree, its first parent HEAD and its second a commit of the
+// index. With pathspecs only the files they name are stashed and reset;
+// with -p the differences between HEAD and the working tree are shown a
+// hunk at a time, and only the hunks picked are stashed and taken out of
+// the working tree, the index being left alone.
+//
+// The reflog of refs/stash is the stack of stashes, stash@{0} the newest.
+// A <stash> is stash@{<n>} or just <n>. apply merges the changes of a
+// stash into the working tree, leaving them unstaged but for new files;
+// pop does the same and drops the stash unless there were conflicts.
+
+use crate::commands::merge as merge_cmd;
+use crate::git::commit;
+use crate::git::diff;
+use crate::git::index::{Entry, Index};
+use crate::git::merge::{self, Labels};
+use crate::git::patch::{self, Hunk};
+use crate::git::refs::{self, Head};
+use crate::git::tree::{self, FileEntry, MODE_EXECUTABLE, MODE_FILE, MODE_GITLINK, MODE_SYMLINK};
+use crate::git::worktree::{self, Snapshot};
+use crate::git::{convert, object, reflog};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+const STASH: &str = "refs/stash";
+
+const USAGE: &str = "Usage: stash [push [-q] [-m <message>] [-p] [--] [<pathspec>...]] | list | apply [<stash>] | pop [<stash>] | drop [<stash>]";
+
+const HUNK_HELP: &str = "\
+y - stash this hunk
+n - do not stash this hunk
+q - quit; do not stash this hunk or any of the remaining ones
+a - stash this hunk and all later hunks in the file
+d - do not stash this hunk or any of the later hunks in the file
+? - print help";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        None => push(&[]),
+        Some("push") => push(&args[1..]),
+        Some("list") => list(),
+        Some("apply") => apply(stash_arg(&args[1..])?, false),
+        Some("pop") => apply(stash_arg(&args[1..])?, true),
+        Some("drop") => drop_stash(stash_arg(&args[1..])?),
+        Some(arg) if arg.starts_with('-') => push(args),
+        Some(_) => Err(usage()),
+    }
+}
+
+fn push(args: &[String]) -> io::Result<()> {
+    let mut message = None;
+    let mut patch_mode = false;
+    let mut quiet = false;
+    let mut pathspecs = Vec::new();
+    let mut options_done = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            arg if options_done => pathspecs.push(normalize(arg)),
+            "--" => options_done = true,
+            "-m" | "--message" => message = Some(args.next().ok_or_else(usage)?.clone()),
+            "-p" | "--patch" => patch_mode = true,
+            "-q" | "--quiet" => quiet = true,
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => pathspecs.push(normalize(arg)),
+        }
+    }
+
+    let Some(head) = refs::head_commit()? else {
+        return Err(io::Error::other("You do not have the initial commit yet"));
+    };
+    let head_commit = commit::read(&head)?;
+    let head_files = worktree::snapshot_of_commit(Some(&head))?;
+    let index = Index::load()?;
+    if index.entries().iter().any(|entry| entry.stage != 0) {
+        return Err(io::Error::other(
+            "could not save the current state: unmerged files",
+        ));
+    }
+    let staged = index.snapshot();
+
+    for spec in &pathspecs {
+        let known = staged.keys().chain(head_files.keys());
+        if !known.into_iter().any(|path| matches(spec, path)) {
+            eprintln!(
+                "error: pathspec '{}' did not match any file(s) known to git",
+                spec
+            );
+            eprintln!("Did you forget to 'git add'?");
+            process::exit(1);
+        }
+    }
+    let selected = |path: &str| pathspecs.is_empty() || pathspecs.iter().any(|s| matches(s, path));
+
+    // What the working tree holds for each tracked path
+    let mut on_disk = Snapshot::new();
+    for entry in index.entries() {
+        if let Some(file) = worktree_file(&index, entry)? {
+            on_disk.insert(entry.path.clone(), file);
+        }
+    }
+
+    let paths: BTreeSet<&String> = head_files
+        .keys()
+        .chain(staged.keys())
+        .filter(|path| selected(path))
+        .collect();
+    let changed = paths.iter().any(|path| {
+        head_files.get(*path) != staged.get(*path) || staged.get(*path) != on_disk.get(*path)
+    });
+    if !changed {
+        if !quiet {
+            println!("No local changes to save");
+        }
+        return Ok(());
+    }
+
+    // The stashed working tree, and what each selected path goes back to
+    let mut stashed = staged.clone();
+    let mut restored = on_disk.clone();
+    if patch_mode {
+        stashed = head_files.clone();
+        let Some(picked) = pick_hunks(&paths, &head_files, &on_disk)? else {
+            return Err(io::Error::other("No changes selected"));
+        };
+        for (path, (stash, keep)) in picked {
+            set(&mut stashed, &path, stash);
+            set(&mut restored, &path, keep);
+        }
+    } else {
+        for path in &paths {
+            set(&mut stashed, path, on_disk.get(*path).cloned());
+            set(&mut restored, path, head_files.get(*path).cloned());
+        }
+    }
+
+    let branch = match refs::read_head()? {
+        Head::Branch(name) => name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&name)
+            .to_string(),
+        Head::Detached(_) => "(no branch)".to_string(),
+    };
+    let on = format!("{}: {} {}", branch, &head[..7], head_commit.summary());
+    let index_tree = tree::write_snapshot(&staged)?;
+    let index_commit = commit::create(
+        &index_tree,
+        std::slice::from_ref(&head),
+        &format!("index on {}", on),
+    )?;
+    let message = match message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}", on),
+    };
+    let stash_tree = tree::write_snapshot(&stashed)?;
+    let stash = commit::create(&stash_tree, &[head.clone(), index_commit], &message)?;
+    refs::update_ref(STASH, &stash, &message)?;
+
+    worktree::update(&on_disk, &restored, true)?;
+    // The update staged what it wrote; the index goes back to HEAD for the
+    // stashed paths, and stays as it was with -p
+    let mut index_after = Index::load()?;
+    for path in &paths {
+        let keep = match patch_mode {
+            true => index.get(path).cloned(),
+            false => match head_files.get(*path) {
+                Some(file) if restored.get(*path) == Some(file) => {
+                    Some(Entry::checked_out(path, file)?)
+                }
+                Some(file) => Some(Entry::new(path, file)),
+                None => None,
+            },
+        };
+        index_after.remove(path);
+        if let Some(entry) = keep {
+            index_after.add(entry);
+        }
+    }
+    index_after.write()?;
+
+    if !quiet {
+        println!("Saved working directory and index state {}", message);
+    }
+    Ok(())
+}
+
+/// Ask about each hunk of the changes from HEAD to the working tree in
+/// `paths`. Returns, for each file something was picked from, what the
+/// stash records for it and what is left in the working tree; `None` when
+/// nothing was picked.
+#[allow(clippy::type_complexity)]
+fn pick_hunks(
+    paths: &BTreeSet<&String>,
+    head: &Snapshot,
+    on_disk: &Snapshot,
+) -> io::Result<Option<Vec<(String, (Option<FileEntry>, Option<FileEntry>))>>> {
+    let mut input = io::stdin().lock();
+    let mut out = io::stdout().lock();
+    let mut picked = Vec::new();
+    'files: for path in paths {
+        let (old, new) = (head.get(*path), on_disk.get(*path));
+        if old == new {
+            continue;
+        }
+        writeln!(out, "diff --git a/{} b/{}", path, path)?;
+        let old_content = content(old)?;
+        let new_content = content(new)?;
+        let text = old.zip(new).filter(|(old, new)| {
+            old.mode == new.mode
+                && old.mode != MODE_GITLINK
+                && !diff::is_binary(&old_content)
+                && !diff::is_binary(&new_content)
+        });
+
+        // Anything other than lines changing in place is taken or left whole
+        if text.is_none() {
+            let what = match (old, new) {
+                (_, None) => "deletion",
+                (None, _) => "addition",
+                (Some(old), Some(new)) if old.sha == new.sha => "mode change",
+                _ => "change",
+            };
+            match ask(&mut input, &mut out, &format!("(1/1) Stash {}", what))? {
+                Answer::Yes | Answer::All => {
+                    picked.push((path.to_string(), (new.cloned(), old.cloned())))
+                }
+                Answer::No | Answer::Done => {}
+                Answer::Quit => break 'files,
+            }
+            continue;
+        }
+
+        let unified = diff::unified(&old_content, &new_content, 3);
+        let mut patch_text = format!("--- a/{}\n+++ b/{}\n", path, path).into_bytes();
+        patch_text.extend_from_slice(&unified);
+        let hunks = patch::parse(&patch_text, 1)?
+            .pop()
+            .map(|file| file.hunks)
+            .unwrap_or_default();
+        let shown = hunk_texts(&unified);
+        let total = hunks.len();
+        let mut take = vec![false; total];
+        let mut quit = false;
+        let mut rest = None;
+        for i in 0..total {
+            let answer = match rest {
+                Some(answer) => answer,
+                None => {
+                    out.write_all(shown[i].as_bytes())?;
+                    ask(
+                        &mut input,
+                        &mut out,
+                        &format!("({}/{}) Stash this hunk", i + 1, total),
+                    )?
+                }
+            };
+            match answer {
+                Answer::Yes => take[i] = true,
+                Answer::No => {}
+                Answer::All => {
+                    take[i] = true;
+                    rest = Some(Answer::Yes);
+                }
+                Answer::Done => rest = Some(Answer::No),
+                Answer::Quit => {
+                    quit = true;
+                    break;
+                }
+            }
+        }
+        if take.iter().any(|&taken| taken) {
+            let (stashed, kept): (Vec<(usize, Hunk)>, Vec<(usize, Hunk)>) =
+                hunks.into_iter().enumerate().partition(|(i, _)| take[*i]);
+            let mode = new.map_or(MODE_FILE, |new| new.mode.as_str());
+            let stash = apply_hunks(&old_content, stashed, mode)?;
+            let keep = apply_hunks(&old_content, kept, mode)?;
+            picked.push((path.to_string(), (Some(stash), Some(keep))));
+        }
+        if quit {
+            break;
+        }
+    }
+    Ok(Some(picked).filter(|picked| !picked.is_empty()))
+}
+
+/// The file `hunks` of a diff against `base` make of it
+fn apply_hunks(base: &[u8], hunks: Vec<(usize, Hunk)>, mode: &str) -> io::Result<FileEntry> {
+    let hunks: Vec<Hunk> = hunks.into_iter().map(|(_, hunk)| hunk).collect();
+    let content = patch::apply(base, &hunks, None, false)
+        .map_err(|line| io::Error::other(format!("could not apply the hunk at line {}", line)))?;
+    Ok(FileEntry {
+        mode: mode.to_string(),
+        sha: object::write("blob", &content)?,
+    })
+}
+
+/// `unified` split into its hunks, each with its "@@" line
+fn hunk_texts(unified: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(unified);
+    let mut hunks: Vec<String> = Vec::new();
+    for line in text.split_inclusive('\n') {
+        match hunks.last_mut() {
+            Some(hunk) if !line.starts_with("@@") => hunk.push_str(line),
+            _ => hunks.push(line.to_string()),
+        }
+    }
+    for hunk in &mut hunks {
+        if !hunk.ends_with('\n') {
+            hunk.push('\n');
+        }
+    }
+    hunks
+}
+
+#[derive(Clone, Copy)]
+enum Answer {
+    Yes,
+    No,
+    Quit,
+    /// This and the rest of the file
+    All,
+    /// None of the rest of the file
+    Done,
+}
+
+fn ask(input: &mut impl BufRead, out: &mut impl Write, prompt: &str) -> io::Result<Answer> {
+    loop {
+        write!(out, "{} [y,n,q,a,d,?]? ", prompt)?;
+        out.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(out)?;
+            return Ok(Answer::Quit);
+        }
+        match line.trim().chars().next() {
+            Some('y') => return Ok(Answer::Yes),
+            Some('n') => return Ok(Answer::No),
+            Some('q') => return Ok(Answer::Quit),
+            Some('a') => return Ok(Answer::All),
+            Some('d') => return Ok(Answer::Done),
+            _ => writeln!(out, "{}", HUNK_HELP)?,
+        }
+    }
+}
+
+fn list() -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    for (n, entry) in reflog::read(STASH)?.iter().rev().enumerate() {
+        writeln!(out, "stash@{{{}}}: {}", n, entry.message)?;
+    }
+    Ok(())
+}
+
+/// Merge the changes of stash `n` into the working tree; with `pop`, drop
+/// the stash once they are in
+fn apply(n: usize, pop: bool) -> io::Result<()> {
+    let stash = stash_commit(n)?;
+    let stashed = commit::read(&stash)?;
+    let base = stashed.parents.first().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("stash@{{{}}} is not a stash-like commit", n),
+        )
+    })?;
+    let head = refs::require_head_commit()?;
+    let ours = worktree::snapshot_of_commit(Some(&head))?;
+    let labels = Labels {
+        base: "Stash base",
+        ours: "Updated upstream",
+        theirs: "Stashed changes",
+    };
+    let result = merge::merge_trees(
+        &worktree::snapshot_of_commit(Some(base))?,
+        &ours,
+        &tree::flatten(&stashed.tree)?,
+        &labels,
+        merge_cmd::conflict_style()?,
+    )?;
+    worktree::update(&ours, &result.files, false)?;
+
+    // Changes come back unstaged, but for files HEAD doesn't have
+    let mut index = Index::load()?;
+    for (path, file) in &ours {
+        if result.files.get(path) != Some(file) {
+            index.remove(path);
+            index.add(Entry::new(path, file));
+        }
+    }
+    index.write()?;
+
+    if !result.conflicts.is_empty() {
+        result.stage_conflicts()?;
+        for conflict in &result.conflicts {
+            println!(
+                "{}",
+                merge_cmd::describe_conflict(&conflict.path, conflict.kind, labels.theirs)
+            );
+        }
+        let kept = match pop {
+            true => "\nThe stash entry is kept in case you need it again.",
+            false => "",
+        };
+        return Err(io::Error::other(format!("Conflicts in stash.{}", kept)));
+    }
+    if pop {
+        drop_stash(n)?;
+    }
+    Ok(())
+}
+
+/// Take stash `n` off the stack, the entry after it taking over its old
+/// value so the log still reads as a history
+fn drop_stash(n: usize) -> io::Result<()> {
+    let mut entries = reflog::read(STASH)?;
+    let position = entries
+        .len()
+        .checked_sub(n + 1)
+        .ok_or_else(|| no_such_stash(n))?;
+    let removed = entries.remove(position);
+    if let Some(next) = entries.get_mut(position) {
+        next.old = removed.old.clone();
+    }
+    match entries.last() {
+        Some(top) => {
+            refs::write_ref(STASH, &top.new)?;
+            reflog::write(STASH, &entries)?;
+        }
+        None => {
+            refs::delete_ref(STASH)?;
+            reflog::delete(STASH)?;
+        }
+    }
+    println!("Dropped refs/stash@{{{}}} ({})", n, removed.new);
+    Ok(())
+}
+
+fn stash_commit(n: usize) -> io::Result<String> {
+    let entries = reflog::read(STASH)?;
+    entries
+        .len()
+        .checked_sub(n + 1)
+        .map(|position| entries[position].new.clone())
+        .ok_or_else(|| no_such_stash(n))
+}
+
+/// The stash an apply, pop or drop names: stash@{<n>} or <n>, the newest
+/// by default
+fn stash_arg(args: &[String]) -> io::Result<usize> {
+    let arg = match args {
+        [] => return Ok(0),
+        [arg] => arg.as_str(),
+        _ => return Err(usage()),
+    };
+    let number = arg
+        .strip_prefix("stash@{")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(arg);
+    number.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a stash reference", arg),
+        )
+    })
+}
+
+fn no_such_stash(n: usize) -> io::Error {
+    if n == 0 {
+        return io::Error::new(io::ErrorKind::NotFound, "No stash entries found.");
+    }
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("stash@{{{}}} is not a valid reference", n),
+    )
+}
+
+/// What the working tree holds at the path of the staged `entry`, stored as
+/// a blob; `None` if the file is gone
+fn worktree_file(index: &Index, entry: &Entry) -> io::Result<Option<FileEntry>> {
+    let staged = entry.file();
+    let meta = match fs::symlink_metadata(&entry.path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if staged.mode == MODE_GITLINK || index.is_stat_clean(entry, &meta) {
+        return Ok(Some(staged));
+    }
+    let (mode, content) = if meta.file_type().is_symlink() {
+        let target = fs::read_link(&entry.path)?;
+        (
+            MODE_SYMLINK,
+            target.to_string_lossy().into_owned().into_bytes(),
+        )
+    } else if meta.is_file() && staged.mode == MODE_SYMLINK && !worktree::symlinks_enabled()? {
+        (MODE_SYMLINK, fs::read(&entry.path)?)
+    } else if meta.is_file() {
+        let content = convert::to_git(&entry.path, fs::read(&entry.path)?)?;
+        (file_mode(&meta), content)
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(FileEntry {
+        mode: mode.to_string(),
+        sha: object::write("blob", &content)?,
+    }))
+}
+
+fn file_mode(meta: &fs::Metadata) -> &'static str {
+    #[cfg(unix)]
+    {
+        if meta.permissions().mode() & 0o111 != 0 {
+            return MODE_EXECUTABLE;
+        }
+    }
+    let _ = meta;
+    MODE_FILE
+}
+
+fn content(file: Option<&FileEntry>) -> io::Result<Vec<u8>> {
+    match file {
+        Some(file) if file.mode != MODE_GITLINK => Ok(object::read_expecting(&file.sha, "blob")?),
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn set(files: &mut Snapshot, path: &str, file: Option<FileEntry>) {
+    match file {
+        Some(file) => files.insert(path.to_string(), file),
+        None => files.remove(path),
+    };
+}
+
+/// Whether `spec` names `path`: the path itself, a directory above it, or
+/// a glob matching it
+fn matches(spec: &str, path: &str) -> bool {
+    spec.is_empty()
+        || path == spec
+        || path.starts_with(&format!("{}/", spec))
+        || refs::glob_match(spec, path)
+}
+
+fn normalize(spec: &str) -> String {
+    let spec = spec.strip_prefix("./").unwrap_or(spec);
+    let spec = spec.trim_end_matches('/');
+    if spec == "." {
+        String::new()
+    } else {
+        spec.to_string()
+    }
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -0,0 +1,479 @@
+// status: show the state of the index and the working tree
+//
+// status [-s | --short | --porcelain] [-b | --branch]
+//
+// Lists the changes staged against HEAD, the unmerged paths, the changes
+// in the working tree not yet staged and the untracked files (those not
+// excluded by .gitignore), along with how the current branch compares to
+// its upstream (branch.<name>.remote and branch.<name>.merge).
+//
+// The short format (also what --porcelain gives) is git's: one "XY <path>"
+// line per path, X the state in the index and Y in the working tree (M
+// modified, A added, D deleted, T type changed, U unmerged), "??" for
+// untracked files, with a directory that holds nothing tracked shown as
+// "<dir>/". With --branch it starts with the line shell prompts read:
+//
+// ## main...origin/main [ahead 1, behind 2]
+//
+// Without --short the same is spelt out the way "git status" does.
+
+use crate::commands::submodule as submodule_cmd;
+use crate::git::config::Config;
+use crate::git::index::{Entry, Index};
+use crate::git::merge;
+use crate::git::refs::{self, Head};
+use crate::git::tree::{self, FileEntry, MODE_GITLINK};
+use crate::git::{ignore, worktree};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+const USAGE: &str = "Usage: status [-s | --short | --porcelain] [-b | --branch]";
+
+/// Where the current branch stands against its upstream
+struct Tracking {
+    /// e.g. `origin/main`
+    upstream: String,
+    /// `None` when the upstream ref is gone
+    counts: Option<(usize, usize)>,
+}
+
+/// The state of one tracked path: index against HEAD, worktree against the
+/// index, as the letters of the short format (' ' for unchanged)
+struct PathStatus {
+    staged: char,
+    unstaged: char,
+}
+
+impl PathStatus {
+    fn is_unmerged(&self) -> bool {
+        matches!(
+            (self.staged, self.unstaged),
+            ('U', _) | (_, 'U') | ('D', 'D') | ('A', 'A')
+        )
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut short = false;
+    let mut branch = false;
+    for arg in args {
+        match arg.as_str() {
+            "-s" | "--short" | "--porcelain" => short = true,
+            "-b" | "--branch" => branch = true,
+            "-sb" | "-bs" => {
+                short = true;
+                branch = true;
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+
+    let head = refs::read_head()?;
+    let head_commit = refs::head_commit()?;
+    let tracking = match &head {
+        Head::Branch(name) if head_commit.is_some() => tracking(name)?,
+        _ => None,
+    };
+    let index = Index::load()?;
+    let paths = path_statuses(&index, head_commit.as_deref())?;
+    let untracked = untracked(&index)?;
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    if short {
+        if branch {
+            writeln!(
+                out,
+                "## {}",
+                branch_line(&head, head_commit.is_some(), tracking.as_ref())
+            )?;
+        }
+        for (path, status) in &paths {
+            writeln!(out, "{}{} {}", status.staged, status.unstaged, path)?;
+        }
+        for path in &untracked {
+            writeln!(out, "?? {}", path)?;
+        }
+    } else {
+        long_format(
+            &mut out,
+            &head,
+            head_commit.is_some(),
+            tracking.as_ref(),
+            &paths,
+            &untracked,
+        )?;
+    }
+    out.flush()
+}
+
+/// The upstream of branch `refname` and the commits each side has that the
+/// other lacks; `None` if the branch has no upstream configured
+fn tracking(refname: &str) -> io::Result<Option<Tracking>> {
+    let name = refname.strip_prefix("refs/heads/").unwrap_or(refname);
+    let config = Config::load()?;
+    let (Some(remote), Some(merge_ref)) = (
+        config.get(&format!("branch.{}.remote", name)),
+        config.get(&format!("branch.{}.merge", name)),
+    ) else {
+        return Ok(None);
+    };
+    let upstream_branch = merge_ref.strip_prefix("refs/heads/").unwrap_or(merge_ref);
+    // "." tracks a local branch
+    let (upstream_ref, upstream) = if remote == "." {
+        (merge_ref.to_string(), upstream_branch.to_string())
+    } else {
+        (
+            format!("refs/remotes/{}/{}", remote, upstream_branch),
+            format!("{}/{}", remote, upstream_branch),
+        )
+    };
+
+    let counts = match (refs::read_ref(refname)?, refs::read_ref(&upstream_ref)?) {
+        (Some(local), Some(theirs)) => {
+            let ours = merge::ancestors(&local)?;
+            let theirs = merge::ancestors(&theirs)?;
+            Some((
+                ours.difference(&theirs).count(),
+                theirs.difference(&ours).count(),
+            ))
+        }
+        _ => None,
+    };
+    Ok(Some(Tracking { upstream, counts }))
+}
+
+/// What follows "## " in the short format
+fn branch_line(head: &Head, born: bool, tracking: Option<&Tracking>) -> String {
+    let name = match head {
+        Head::Detached(_) => return "HEAD (no branch)".to_string(),
+        Head::Branch(name) => name.strip_prefix("refs/heads/").unwrap_or(name),
+    };
+    if !born {
+        return format!("No commits yet on {}", name);
+    }
+    let Some(tracking) = tracking else {
+        return name.to_string();
+    };
+    let mut line = format!("{}...{}", name, tracking.upstream);
+    match tracking.counts {
+        None => line.push_str(" [gone]"),
+        Some((0, 0)) => {}
+        Some((ahead, 0)) => line.push_str(&format!(" [ahead {}]", ahead)),
+        Some((0, behind)) => line.push_str(&format!(" [behind {}]", behind)),
+        Some((ahead, behind)) => line.push_str(&format!(" [ahead {}, behind {}]", ahead, behind)),
+    }
+    line
+}
+
+/// Every tracked path that differs somewhere, in path order
+fn path_statuses(
+    index: &Index,
+    head_commit: Option<&str>,
+) -> io::Result<BTreeMap<String, PathStatus>> {
+    let head = worktree::snapshot_of_commit(head_commit)?;
+
+    // The stages each unmerged path has
+    let mut unmerged: BTreeMap<&str, [bool; 3]> = BTreeMap::new();
+    for entry in index.entries().iter().filter(|e| e.stage != 0) {
+        unmerged.entry(&entry.path).or_default()[entry.stage as usize - 1] = true;
+    }
+
+    let mut statuses = BTreeMap::new();
+    for (path, stages) in &unmerged {
+        let code = match stages {
+            [true, false, false] => "DD",
+            [false, true, false] => "AU",
+            [true, true, false] => "UD",
+            [false, false, true] => "UA",
+            [true, false, true] => "DU",
+            [false, true, true] => "AA",
+            _ => "UU",
+        };
+        let mut code = code.chars();
+        statuses.insert(
+            path.to_string(),
+            PathStatus {
+                staged: code.next().unwrap_or(' '),
+                unstaged: code.next().unwrap_or(' '),
+            },
+        );
+    }
+
+    for entry in index.entries().iter().filter(|e| e.stage == 0) {
+        let file = entry.file();
+        let staged = match head.get(&entry.path) {
+            None => 'A',
+            Some(old) => change(old, &file),
+        };
+        let unstaged = worktree_change(index, entry)?;
+        if staged != ' ' || unstaged != ' ' {
+            statuses.insert(entry.path.clone(), PathStatus { staged, unstaged });
+        }
+    }
+    for path in head.keys() {
+        if index.get(path).is_none() && !unmerged.contains_key(path.as_str()) {
+            statuses.insert(
+                path.clone(),
+                PathStatus {
+                    staged: 'D',
+                    unstaged: ' ',
+                },
+            );
+        }
+    }
+    Ok(statuses)
+}
+
+/// The letter for a path going from `old` to `new`
+fn change(old: &FileEntry, new: &FileEntry) -> char {
+    let is_file = |mode: &str| mode == tree::MODE_FILE || mode == tree::MODE_EXECUTABLE;
+    if old.mode == new.mode && old.sha == new.sha {
+        ' '
+    } else if old.mode == new.mode || (is_file(&old.mode) && is_file(&new.mode)) {
+        'M'
+    } else {
+        'T'
+    }
+}
+
+/// How the file on disk differs from its staged `entry`
+fn worktree_change(index: &Index, entry: &Entry) -> io::Result<char> {
+    let file = entry.file();
+    let meta = match fs::symlink_metadata(&entry.path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok('D'),
+        Err(e) => return Err(e),
+    };
+    if file.mode == MODE_GITLINK {
+        // A submodule that isn't checked out is taken as unchanged
+        return Ok(match submodule_cmd::checked_out_commit(&entry.path)? {
+            Some(sha) if sha != file.sha => 'M',
+            _ => ' ',
+        });
+    }
+    let on_disk = if meta.file_type().is_symlink() {
+        tree::MODE_SYMLINK
+    } else if meta.is_file() && file.mode == tree::MODE_SYMLINK && !worktree::symlinks_enabled()? {
+        // A symlink checked out as a plain file
+        tree::MODE_SYMLINK
+    } else if meta.is_file() {
+        file_mode(&meta)
+    } else {
+        return Ok('D');
+    };
+    if index.is_stat_clean(entry, &meta) {
+        return Ok(' ');
+    }
+    if on_disk != file.mode && (on_disk == tree::MODE_SYMLINK || file.mode == tree::MODE_SYMLINK) {
+        return Ok('T');
+    }
+    if on_disk != file.mode || !worktree::matches_worktree(&entry.path, &file)? {
+        return Ok('M');
+    }
+    Ok(' ')
+}
+
+fn file_mode(meta: &fs::Metadata) -> &'static str {
+    #[cfg(unix)]
+    {
+        if meta.permissions().mode() & 0o111 != 0 {
+            return tree::MODE_EXECUTABLE;
+        }
+    }
+    tree::MODE_FILE
+}
+
+/// Untracked files, a directory with nothing tracked below it standing in
+/// for everything it holds
+fn untracked(index: &Index) -> io::Result<Vec<String>> {
+    let tracked: HashSet<String> = index.entries().iter().map(|e| e.path.clone()).collect();
+    let mut tracked_dirs = HashSet::new();
+    for path in &tracked {
+        let mut dir = path.as_str();
+        while let Some(pos) = dir.rfind('/') {
+            dir = &dir[..pos];
+            tracked_dirs.insert(dir.to_string());
+        }
+    }
+
+    let mut listed: Vec<String> = Vec::new();
+    for path in ignore::untracked_files(&tracked)? {
+        let collapsed = path
+            .match_indices('/')
+            .map(|(pos, _)| &path[..pos])
+            .find(|dir| !tracked_dirs.contains(*dir))
+            .map(|dir| format!("{}/", dir))
+            .unwrap_or(path.clone());
+        if listed.last() != Some(&collapsed) {
+            listed.push(collapsed);
+        }
+    }
+    Ok(listed)
+}
+
+fn long_format(
+    out: &mut impl Write,
+    head: &Head,
+    born: bool,
+    tracking: Option<&Tracking>,
+    paths: &BTreeMap<String, PathStatus>,
+    untracked: &[String],
+) -> io::Result<()> {
+    match head {
+        Head::Branch(name) => writeln!(
+            out,
+            "On branch {}",
+            name.strip_prefix("refs/heads/").unwrap_or(name)
+        )?,
+        Head::Detached(sha) => writeln!(out, "HEAD detached at {}", &sha[..7])?,
+    }
+    if let Some(tracking) = tracking {
+        let upstream = &tracking.upstream;
+        let plural = |n: usize| if n == 1 { "commit" } else { "commits" };
+        match tracking.counts {
+            None => writeln!(
+                out,
+                "Your branch is based on '{}', but the upstream is gone.\n  (use \"git branch --unset-upstream\" to fixup)",
+                upstream
+            )?,
+            Some((0, 0)) => writeln!(out, "Your branch is up to date with '{}'.", upstream)?,
+            Some((ahead, 0)) => writeln!(
+                out,
+                "Your branch is ahead of '{}' by {} {}.\n  (use \"git push\" to publish your local commits)",
+                upstream,
+                ahead,
+                plural(ahead)
+            )?,
+            Some((0, behind)) => writeln!(
+                out,
+                "Your branch is behind '{}' by {} {}, and can be fast-forwarded.\n  (use \"git pull\" to update your local branch)",
+                upstream,
+                behind,
+                plural(behind)
+            )?,
+            Some((ahead, behind)) => writeln!(
+                out,
+                "Your branch and '{}' have diverged,\nand have {} and {} different commits each, respectively.\n  (use \"git pull\" if you want to integrate the remote branch with yours)",
+                upstream, ahead, behind
+            )?,
+        }
+        writeln!(out)?;
+    }
+    let merging = Path::new(".git/MERGE_HEAD").exists();
+    if merging {
+        if paths.values().any(PathStatus::is_unmerged) {
+            writeln!(
+                out,
+                "You have unmerged paths.\n  (fix conflicts and run \"git commit\")\n  (use \"git merge --abort\" to abort the merge)\n"
+            )?;
+        } else {
+            writeln!(
+                out,
+                "All conflicts fixed but you are still merging.\n  (use \"git commit\" to conclude merge)\n"
+            )?;
+        }
+    }
+    if !born {
+        writeln!(out, "\nNo commits yet\n")?;
+    }
+
+    let label = |code: char| match code {
+        'A' => "new file:",
+        'D' => "deleted:",
+        'T' => "typechange:",
+        _ => "modified:",
+    };
+    let unmerged_label = |status: &PathStatus| match (status.staged, status.unstaged) {
+        ('D', 'D') => "both deleted:",
+        ('A', 'U') => "added by us:",
+        ('U', 'D') => "deleted by them:",
+        ('U', 'A') => "added by them:",
+        ('D', 'U') => "deleted by us:",
+        ('A', 'A') => "both added:",
+        _ => "both modified:",
+    };
+    let staged: Vec<_> = paths
+        .iter()
+        .filter(|(_, s)| !s.is_unmerged() && s.staged != ' ')
+        .collect();
+    let unmerged: Vec<_> = paths.iter().filter(|(_, s)| s.is_unmerged()).collect();
+    let unstaged: Vec<_> = paths
+        .iter()
+        .filter(|(_, s)| !s.is_unmerged() && s.unstaged != ' ')
+        .collect();
+
+    if !staged.is_empty() {
+        writeln!(out, "Changes to be committed:")?;
+        // A merge can't be partly unstaged
+        if !merging {
+            let hint = if born {
+                "git restore --staged <file>..."
+            } else {
+                "git rm --cached <file>..."
+            };
+            writeln!(out, "  (use \"{}\" to unstage)", hint)?;
+        }
+        for (path, status) in &staged {
+            writeln!(out, "\t{:<12}{}", label(status.staged), path)?;
+        }
+        writeln!(out)?;
+    }
+    if !unmerged.is_empty() {
+        writeln!(
+            out,
+            "Unmerged paths:\n  (use \"git add <file>...\" to mark resolution)"
+        )?;
+        for (path, status) in &unmerged {
+            writeln!(out, "\t{:<17}{}", unmerged_label(status), path)?;
+        }
+        writeln!(out)?;
+    }
+    if !unstaged.is_empty() {
+        writeln!(
+            out,
+            "Changes not staged for commit:\n  (use \"git add{} <file>...\" to update what will be committed)\n  (use \"git restore <file>...\" to discard changes in working directory)",
+            if unstaged.iter().any(|(_, s)| s.unstaged == 'D') { "/rm" } else { "" }
+        )?;
+        for (path, status) in &unstaged {
+            writeln!(out, "\t{:<12}{}", label(status.unstaged), path)?;
+        }
+        writeln!(out)?;
+    }
+    if !untracked.is_empty() {
+        writeln!(
+            out,
+            "Untracked files:\n  (use \"git add <file>...\" to include in what will be committed)"
+        )?;
+        for path in untracked {
+            writeln!(out, "\t{}", path)?;
+        }
+        writeln!(out)?;
+    }
+
+    if !staged.is_empty() {
+        return Ok(());
+    }
+    if !unstaged.is_empty() || !unmerged.is_empty() {
+        writeln!(
+            out,
+            "no changes added to commit (use \"git add\" and/or \"git commit -a\")"
+        )
+    } else if !untracked.is_empty() {
+        writeln!(
+            out,
+            "nothing added to commit but untracked files present (use \"git add\" to track)"
+        )
+    } else if !born {
+        writeln!(
+            out,
+            "nothing to commit (create/copy files and use \"git add\" to track)"
+        )
+    } else {
+        writeln!(out, "nothing to commit, working tree clean")
+    }
+}
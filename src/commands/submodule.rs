@@ -0,0 +1,109 @@
+// submodule: inspect and maintain submodules
+//
+// submodule [status]   recorded commit of each submodule, prefixed with
+//                      '-' (not checked out), '+' (checked out at a different
+//                      commit) or ' ' (matches)
+// submodule sync       copy URLs from .gitmodules into .git/config, and into
+//                      each checked-out submodule's remote.origin.url
+
+use crate::git::config::{self, Config};
+use crate::git::{commit, refs, submodule, tree};
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+
+const USAGE: &str = "Usage: submodule [status | sync]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        None | Some("status") => status(),
+        Some("sync") => sync(),
+        Some(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
+
+fn status() -> io::Result<()> {
+    let modules = submodule::read_gitmodules()?;
+    let mut stdout = io::stdout();
+
+    for (path, recorded) in gitlinks()? {
+        if !modules.iter().any(|m| m.path == path) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no submodule mapping found in .gitmodules for path '{}'",
+                    path
+                ),
+            ));
+        }
+
+        let (prefix, sha) = match checked_out_commit(&path)? {
+            None => ('-', recorded),
+            Some(current) if current == recorded => (' ', current),
+            Some(current) => ('+', current),
+        };
+        writeln!(stdout, "{}{} {}", prefix, sha, path)?;
+    }
+    stdout.flush()
+}
+
+fn sync() -> io::Result<()> {
+    let git_config = Path::new(".git/config");
+    let mut local = Config::default();
+    local.read_file(git_config)?;
+
+    // Relative URLs are relative to the superproject's remote, or to the
+    // superproject itself when it has none
+    let base = match local.get("remote.origin.url") {
+        Some(url) => url.to_string(),
+        None => env::current_dir()?.display().to_string(),
+    };
+
+    for module in submodule::read_gitmodules()? {
+        let key = format!("submodule.{}.url", module.name);
+        // Only submodules that have been registered are synced
+        if local.get(&key).is_none() {
+            continue;
+        }
+        let url = match &module.url {
+            Some(url) => submodule::absolute_url(url, &base),
+            None => continue,
+        };
+
+        println!("Synchronizing submodule url for '{}'", module.path);
+        config::set_value(git_config, &key, &url)?;
+
+        let module_config = Path::new(&module.path).join(".git/config");
+        if module_config.exists() {
+            config::set_value(&module_config, "remote.origin.url", &url)?;
+        }
+    }
+    Ok(())
+}
+
+/// Submodule paths and pinned commits in HEAD's tree
+fn gitlinks() -> io::Result<BTreeMap<String, String>> {
+    let head = match refs::head_commit()? {
+        Some(head) => head,
+        None => return Ok(BTreeMap::new()),
+    };
+    let commit = commit::read(&head)?;
+    Ok(tree::flatten(&commit.tree)?
+        .into_iter()
+        .filter(|(_, entry)| entry.mode == tree::MODE_GITLINK)
+        .map(|(path, entry)| (path, entry.sha))
+        .collect())
+}
+
+/// HEAD commit of the submodule checked out at `path`, if there is one
+pub fn checked_out_commit(path: &str) -> io::Result<Option<String>> {
+    if !Path::new(path).join(".git").exists() {
+        return Ok(None);
+    }
+    let superproject = env::current_dir()?;
+    env::set_current_dir(path)?;
+    let head = refs::head_commit();
+    env::set_current_dir(superproject)?;
+    head
+}
@@ -0,0 +1,75 @@
+// switch: change to another local branch
+//
+// switch [-f] <branch>
+// switch [-f] --detach [<rev>]
+// switch [-f] --orphan <new-branch>
+//
+// --detach detaches HEAD at <rev> (HEAD by default) instead of switching to
+// a branch. --orphan puts HEAD on <new-branch> without creating it, so the
+// next commit starts a history of its own; the tracked files are removed
+// and the index emptied, as if by "rm -rf .".
+
+use crate::commands::checkout;
+use crate::git::index::Index;
+use crate::git::{refs, revision, worktree};
+use std::io;
+
+const USAGE: &str = "Usage: switch [-f] (<branch> | --detach [<rev>] | --orphan <new-branch>)";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut force = false;
+    let mut detach = false;
+    let mut orphan = false;
+    let mut branch = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "-f" | "--force" | "--discard-changes" => force = true,
+            "-d" | "--detach" => detach = true,
+            "--orphan" => orphan = true,
+            _ if branch.is_none() => branch = Some(arg.as_str()),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+
+    match (detach, orphan, branch) {
+        (true, false, rev) => {
+            let rev = rev.unwrap_or("HEAD");
+            let sha = revision::resolve(rev)?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("invalid reference: {}", rev),
+                )
+            })?;
+            checkout::detach(rev, &sha, force)
+        }
+        (false, true, Some(branch)) => switch_orphan(branch, force),
+        (false, false, Some(branch)) => checkout::switch_branch(branch, force),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
+
+/// Put HEAD on the unborn branch `branch` with nothing tracked
+fn switch_orphan(branch: &str, force: bool) -> io::Result<()> {
+    let refname = refs::branch_ref(branch);
+    if !refs::is_valid_branch_name(branch) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a valid branch name", branch),
+        ));
+    }
+    if refs::read_ref(&refname)?.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("a branch named '{}' already exists", branch),
+        ));
+    }
+
+    let current = worktree::snapshot_of_commit(refs::head_commit()?.as_deref())?;
+    worktree::update(&current, &worktree::Snapshot::new(), force)?;
+    // Files staged but never committed are untracked from here on
+    Index::from_snapshot(&worktree::Snapshot::new()).write()?;
+    refs::write_head_symbolic(&refname)?;
+    println!("Switched to a new branch '{}'", branch);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+// symbolic-ref: read, change or delete a symbolic ref
+//
+// symbolic-ref [-q] [--short] [--no-recurse] <name>
+// symbolic-ref [-m <reason>] <name> <ref>
+// symbolic-ref -d [-q] <name>
+//
+// With only <name> (usually HEAD), prints the ref it points at, following
+// a chain of symbolic refs to its end unless --no-recurse is given; --short
+// prints the name without its refs/heads/ (or similar) prefix. A <name>
+// that isn't a symbolic ref is an error, or with -q just exit status 1.
+// With <ref>, makes <name> point at <ref>, which has to be a well-formed
+// ref name (under refs/ for HEAD), recording the change in <name>'s reflog
+// with <reason> as its message. -d deletes the symbolic ref <name>.
+
+use crate::git::refs;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "Usage: symbolic-ref [-q] [--short] [--no-recurse] <name> | \
+                     symbolic-ref [-m <reason>] <name> <ref> | symbolic-ref -d [-q] <name>";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut quiet = false;
+    let mut short = false;
+    let mut recurse = true;
+    let mut delete = false;
+    let mut message = String::new();
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            "--short" => short = true,
+            "--no-recurse" => recurse = false,
+            "--recurse" => recurse = true,
+            "-d" | "--delete" => delete = true,
+            "-m" => message = args.next().ok_or_else(usage)?.clone(),
+            arg if arg.starts_with("-m") => message = arg[2..].to_string(),
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => positional.push(arg),
+        }
+    }
+
+    match (delete, positional.as_slice()) {
+        (true, [name]) => delete_symref(name, quiet),
+        (false, [name]) => show(name, quiet, short, recurse),
+        (false, [name, target]) => {
+            if *name == "HEAD" && !target.starts_with("refs/") {
+                return Err(invalid(
+                    "Refusing to point HEAD outside of refs/".to_string(),
+                ));
+            }
+            if !refs::is_valid_name(target) {
+                return Err(invalid(format!(
+                    "Refusing to set '{}' to invalid ref '{}'",
+                    name, target
+                )));
+            }
+            refs::update_symref(name, target, &message)
+        }
+        _ => Err(usage()),
+    }
+}
+
+fn show(name: &str, quiet: bool, short: bool, recurse: bool) -> io::Result<()> {
+    let Some(mut target) = refs::read_symref(name)? else {
+        return not_symbolic(name, quiet);
+    };
+    if recurse {
+        // Bound the chain as refs::read_ref does
+        for _ in 0..5 {
+            match refs::read_symref(&target)? {
+                Some(next) => target = next,
+                None => break,
+            }
+        }
+    }
+    if short {
+        target = refs::shorten(&target)?;
+    }
+    let mut out = BufWriter::new(io::stdout().lock());
+    writeln!(out, "{}", target)?;
+    out.flush()
+}
+
+fn delete_symref(name: &str, quiet: bool) -> io::Result<()> {
+    if name == "HEAD" {
+        return Err(invalid("deleting 'HEAD' is not allowed".to_string()));
+    }
+    if refs::read_symref(name)?.is_none() {
+        if quiet {
+            process::exit(1);
+        }
+        return Err(invalid(format!(
+            "Cannot delete {}, not a symbolic ref",
+            name
+        )));
+    }
+    refs::delete_ref(name)
+}
+
+fn not_symbolic(name: &str, quiet: bool) -> io::Result<()> {
+    if quiet {
+        process::exit(1);
+    }
+    Err(invalid(format!("ref {} is not a symbolic ref", name)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
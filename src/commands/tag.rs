@@ -0,0 +1,231 @@
+// tag: create, list and delete tags
+//
+// tag [-l] [-n] [--contains [<commit>]] [<pattern>...]  list tags
+// tag [-f] <name> [<commit>]           lightweight tag (a ref only)
+// tag [-f] -a -m <msg> <name> [<commit>]  annotated tag object
+// tag -d <name>...                     delete tags
+//
+// --contains lists only the tags whose commits reach <commit> (HEAD when
+// it comes last with none), leaving out those that don't point to a
+// commit at all.
+
+use crate::git::ident::{self, Role};
+use crate::git::reach::Reachability;
+use crate::git::refs;
+use crate::git::{commit, object, revision, tag};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+const USAGE: &str =
+    "Usage: tag [-l] [--contains <commit>] [<pattern>...] | [-f] [-a] [-m <msg>] <name> [<commit>] \
+    | -d <name>...";
+
+enum Mode {
+    List,
+    Create,
+    Delete,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut mode = None;
+    let mut annotate = false;
+    let mut force = false;
+    let mut show_annotation = false;
+    let mut message: Option<String> = None;
+    let mut contains: Option<String> = None;
+    let mut positional = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-l" | "--list" => mode = Some(Mode::List),
+            "-d" | "--delete" => mode = Some(Mode::Delete),
+            "-a" | "--annotate" => annotate = true,
+            "-f" | "--force" => force = true,
+            "-n" => show_annotation = true,
+            "--contains" => {
+                // Like git, the commit may only be left out at the end
+                i += 1;
+                let name = args.get(i).map_or("HEAD", String::as_str);
+                contains = Some(resolve_commit(name)?);
+                mode = Some(Mode::List);
+            }
+            arg if arg.starts_with("--contains=") => {
+                contains = Some(resolve_commit(&arg["--contains=".len()..])?);
+                mode = Some(Mode::List);
+            }
+            "-m" | "--message" => {
+                i += 1;
+                let msg = args.get(i).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "Missing message after -m")
+                })?;
+                // Multiple -m flags become separate paragraphs, as in git
+                message = Some(match message {
+                    Some(existing) => format!("{}\n\n{}", existing, msg),
+                    None => msg.clone(),
+                });
+                annotate = true;
+            }
+            arg if arg.starts_with('-') => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+            }
+            arg => positional.push(arg),
+        }
+        i += 1;
+    }
+
+    let mode = mode.unwrap_or(if positional.is_empty() {
+        Mode::List
+    } else {
+        Mode::Create
+    });
+
+    match mode {
+        Mode::List => list_tags(&positional, show_annotation, contains.as_deref()),
+        Mode::Delete => {
+            if positional.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+            }
+            positional.iter().try_for_each(|name| delete_tag(name))
+        }
+        Mode::Create => {
+            let (name, target) = match positional.as_slice() {
+                [name] => (*name, "HEAD"),
+                [name, target] => (*name, *target),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+            };
+            let message = match (annotate, message) {
+                (false, _) => None,
+                (true, Some(message)) => Some(message),
+                (true, None) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "no tag message given (use -m <msg>)",
+                    ))
+                }
+            };
+            create_tag(name, target, message.as_deref(), force)
+        }
+    }
+}
+
+fn list_tags(patterns: &[&str], show_annotation: bool, contains: Option<&str>) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let mut reach = match contains {
+        Some(_) => Some(Reachability::new()?),
+        None => None,
+    };
+    // Each pattern only reads the tags it could match
+    let mut tags = BTreeMap::new();
+    for pattern in patterns
+        .iter()
+        .copied()
+        .chain(patterns.is_empty().then_some("*"))
+    {
+        tags.extend(refs::refs_glob(&format!("refs/tags/{}", pattern))?);
+    }
+    for (refname, sha) in tags {
+        let name = &refname["refs/tags/".len()..];
+        if let (Some(target), Some(reach)) = (contains, reach.as_mut()) {
+            let (commit, kind) = tag::peel(&sha)?;
+            if kind != "commit" || !reach.is_ancestor(target, &commit)? {
+                continue;
+            }
+        }
+        if show_annotation {
+            writeln!(stdout, "{:<15} {}", name, annotation_line(&sha)?)?;
+        } else {
+            writeln!(stdout, "{}", name)?;
+        }
+    }
+    stdout.flush()
+}
+
+/// First line of a tag message, or of the commit subject for lightweight tags
+fn annotation_line(sha: &str) -> io::Result<String> {
+    let (kind, content) = object::read(sha)?;
+    let message = match kind.as_str() {
+        "tag" => tag::parse(&content)?.message,
+        "commit" => commit::parse(&content)?.message,
+        _ => String::new(),
+    };
+    Ok(message.lines().next().unwrap_or("").to_string())
+}
+
+fn create_tag(name: &str, target: &str, message: Option<&str>, force: bool) -> io::Result<()> {
+    if name.starts_with('-') || !refs::is_valid_name(&refs::tag_ref(name)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a valid tag name.", name),
+        ));
+    }
+
+    let refname = refs::tag_ref(name);
+    let existing = refs::read_ref(&refname)?;
+    if existing.is_some() && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("tag '{}' already exists", name),
+        ));
+    }
+
+    let target_sha = revision::resolve(target)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Failed to resolve '{}' as a valid ref.", target),
+        )
+    })?;
+
+    let tag_sha = match message {
+        Some(message) => {
+            let (kind, _) = object::read(&target_sha)?;
+            let content = tag::format(
+                &target_sha,
+                &kind,
+                name,
+                &ident::signature(Role::Committer)?,
+                message,
+            );
+            object::write("tag", &content)?
+        }
+        None => target_sha,
+    };
+
+    refs::write_ref(&refname, &tag_sha)?;
+    if let Some(old) = existing {
+        if old != tag_sha {
+            println!("Updated tag '{}' (was {})", name, &old[..7]);
+        }
+    }
+    Ok(())
+}
+
+fn delete_tag(name: &str) -> io::Result<()> {
+    let refname = refs::tag_ref(name);
+    let sha = refs::read_ref(&refname)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("tag '{}' not found.", name),
+        )
+    })?;
+    refs::delete_ref(&refname)?;
+    println!("Deleted tag '{}' (was {})", name, &sha[..7]);
+    Ok(())
+}
+
+fn resolve_commit(name: &str) -> io::Result<String> {
+    let sha = revision::resolve(name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("malformed object name {}", name),
+        )
+    })?;
+    let (sha, kind) = tag::peel(&sha)?;
+    if kind != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a {}, not a commit", name, kind),
+        ));
+    }
+    Ok(sha)
+}
@@ -0,0 +1,52 @@
+// update-index: bring the index's stat data up to date
+//
+// update-index (--refresh | --really-refresh) [-q] [--ignore-missing]
+//
+// --refresh looks at every staged file whose stat data doesn't show it to
+// be unchanged and hashes it: if the content still matches, the new stat
+// data is recorded so later commands can skip the file, and otherwise it
+// is reported as "<path>: needs update". Unresolved conflicts are
+// reported as "<path>: needs merge". --really-refresh hashes every file,
+// trusting no stat data. The exit status is 1 when something was
+// reported; -q reports nothing and exits 0, and --ignore-missing says
+// nothing about files that were deleted.
+
+use crate::git::index::{Index, Refresh};
+use std::io;
+use std::process;
+
+const USAGE: &str = "Usage: update-index (--refresh | --really-refresh) [-q] [--ignore-missing]";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut really = None;
+    let mut quiet = false;
+    let mut ignore_missing = false;
+    for arg in args {
+        match arg.as_str() {
+            "--refresh" => really = Some(false),
+            "--really-refresh" => really = Some(true),
+            "-q" => quiet = true,
+            "--ignore-missing" => ignore_missing = true,
+            _ => return Err(usage()),
+        }
+    }
+    let really = really.ok_or_else(usage)?;
+
+    let mut index = Index::load()?;
+    let problems = index.refresh(really, ignore_missing)?;
+    index.write()?;
+    if quiet || problems.is_empty() {
+        return Ok(());
+    }
+    for problem in &problems {
+        match problem {
+            Refresh::NeedsUpdate(path) => println!("{}: needs update", path),
+            Refresh::NeedsMerge(path) => println!("{}: needs merge", path),
+        }
+    }
+    process::exit(1);
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -0,0 +1,101 @@
+// update-ref: set or delete a ref safely
+//
+// update-ref [-m <reason>] [--no-deref] <ref> <new> [<old>]
+// update-ref [-m <reason>] [--no-deref] -d <ref> [<old>]
+//
+// Points <ref> (a full ref name such as refs/heads/main, or HEAD) at the
+// object <new> names, or deletes it with -d. With <old>, the update only
+// happens if the ref still holds <old> once it is locked; an <old> of 40
+// zeros (or "") requires that the ref doesn't exist yet. A symbolic ref is
+// followed to the ref it points at unless --no-deref is given. The update
+// is recorded in the ref's reflog with <reason> as its message.
+
+use crate::git::reflog::ZERO_SHA;
+use crate::git::{refs, revision};
+use std::io;
+
+const USAGE: &str =
+    "Usage: update-ref [-m <reason>] [--no-deref] (-d <ref> [<old>] | <ref> <new> [<old>])";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut message = String::new();
+    let mut deref = true;
+    let mut delete = false;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" => message = args.next().ok_or_else(usage)?.clone(),
+            "--no-deref" => deref = false,
+            "-d" => delete = true,
+            arg if arg.starts_with("-m") => message = arg[2..].to_string(),
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg => positional.push(arg),
+        }
+    }
+
+    let (name, new, old) = match (delete, positional.as_slice()) {
+        (true, [name]) => (*name, None, None),
+        (true, [name, old]) => (*name, None, Some(*old)),
+        (false, [name, new]) => (*name, Some(*new), None),
+        (false, [name, new, old]) => (*name, Some(*new), Some(*old)),
+        _ => return Err(usage()),
+    };
+    if !refs::is_valid_name(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("refusing to update ref with bad name '{}'", name),
+        ));
+    }
+    let refname = if deref {
+        follow_symrefs(name)?
+    } else {
+        name.to_string()
+    };
+    let old = match old {
+        Some("") => Some(ZERO_SHA.to_string()),
+        Some(old) => Some(object_id(old)?),
+        None => None,
+    };
+
+    match new {
+        Some(new) => {
+            let new = object_id(new)?;
+            refs::update_ref_checked(&refname, &new, old.as_deref(), &message)
+        }
+        None => match old {
+            Some(old) => refs::delete_ref_checked(&refname, &old),
+            None => refs::delete_ref(&refname),
+        },
+    }
+}
+
+/// The ref at the end of the chain of symbolic refs starting at `name`
+fn follow_symrefs(name: &str) -> io::Result<String> {
+    let mut name = name.to_string();
+    // Bound the chain as refs::read_ref does
+    for _ in 0..5 {
+        match refs::read_symref(&name)? {
+            Some(target) => name = target,
+            None => break,
+        }
+    }
+    Ok(name)
+}
+
+/// The id `rev` names; all zeros stays as it is
+fn object_id(rev: &str) -> io::Result<String> {
+    if rev == ZERO_SHA {
+        return Ok(rev.to_string());
+    }
+    revision::resolve(rev)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: not a valid SHA1", rev),
+        )
+    })
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -0,0 +1,214 @@
+// upload-pack: serve a fetch or clone of this repository
+//
+// upload-pack [--stateless-rpc] [--advertise-refs] <directory>
+//
+// Speaks the server side of the version 0 upload-pack protocol on standard
+// input and output, which is what a client at the other end of ssh (or a
+// local path) runs. The refs are advertised, then the client sends the
+// objects it wants and the commits it has, and gets back a pack of
+// everything it wants that isn't reachable from what it has; a client that
+// asks for multi_ack_detailed hears about every commit it has that we have
+// too, and a NAK at the end of each round of them. A client may only ask
+// for the tips of advertised refs, so refs hidden through
+// uploadpack.hideRefs or transfer.hideRefs can't be fetched.
+//
+// --advertise-refs only advertises the refs, and --stateless-rpc answers a
+// single request and exits, as the two halves of a smart HTTP exchange do.
+
+use crate::git::advertise::{self, HiddenRefs};
+use crate::git::config::Config;
+use crate::git::http::USER_AGENT;
+use crate::git::pktline::{self, SidebandWriter, BAND_DATA, FLUSH};
+use crate::git::{object, pack, refs, tag};
+use std::collections::HashSet;
+use std::io::{self, BufWriter, Write};
+
+const USAGE: &str = "Usage: upload-pack [--stateless-rpc] [--advertise-refs] <directory>";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut stateless = false;
+    let mut advertise_only = false;
+    let mut dir = None;
+    for arg in args {
+        match arg.as_str() {
+            "--stateless-rpc" => stateless = true,
+            "--advertise-refs" => advertise_only = true,
+            arg if arg.starts_with('-') => return Err(usage()),
+            arg if dir.is_none() => dir = Some(arg),
+            _ => return Err(usage()),
+        }
+    }
+    advertise::enter_repository(dir.ok_or_else(usage)?)?;
+
+    let hidden = HiddenRefs::load(&Config::load()?, "uploadpack");
+    let visible = advertise::visible_refs(true, &hidden)?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    if advertise_only || !stateless {
+        let mut capabilities =
+            String::from("multi_ack_detailed side-band side-band-64k no-progress");
+        if let refs::Head::Branch(branch) = refs::read_head()? {
+            if visible.iter().any(|(name, _)| *name == branch) {
+                capabilities.push_str(&format!(" symref=HEAD:{}", branch));
+            }
+        }
+        capabilities.push_str(&format!(" agent={}", USER_AGENT));
+        advertise::write(&mut out, &visible, &capabilities, true)?;
+        out.flush()?;
+        if advertise_only {
+            return Ok(());
+        }
+    }
+
+    let mut input = io::stdin().lock();
+    let Some((wants, capabilities)) = read_wants(&mut input)? else {
+        // The client only wanted the advertisement
+        return Ok(());
+    };
+    // Tags are advertised peeled too, and either id may be asked for
+    let mut advertised = HashSet::new();
+    for (name, sha) in &visible {
+        advertised.insert(sha.clone());
+        if name.starts_with("refs/tags/") {
+            advertised.insert(tag::peel(sha)?.0);
+        }
+    }
+    for want in &wants {
+        if !advertised.contains(want) {
+            let message = format!("ERR upload-pack: not our ref {}", want);
+            out.write_all(&pktline::encode(message.as_bytes()))?;
+            out.flush()?;
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("git upload-pack: not our ref {}", want),
+            ));
+        }
+    }
+
+    let asked = |capability: &str| capabilities.iter().any(|c| c == capability);
+    let multi_ack = asked("multi_ack_detailed");
+    let Some(common) = negotiate(&mut input, &mut out, stateless, multi_ack)? else {
+        return Ok(());
+    };
+    let objects = pack::objects_to_send(&wants, &common)?;
+    let max_len = if asked("side-band-64k") {
+        Some(65520)
+    } else if asked("side-band") {
+        Some(1000)
+    } else {
+        None
+    };
+    match max_len {
+        Some(max_len) => {
+            let mut band = SidebandWriter::new(&mut out, BAND_DATA, max_len);
+            pack::write_to(&objects, &mut band)?;
+            band.flush()?;
+            out.write_all(FLUSH)?;
+        }
+        None => pack::write_to(&objects, &mut out)?,
+    }
+    out.flush()
+}
+
+/// The "want" lines up to the first flush, with the capabilities the client
+/// asked for on the first one; `None` if the client wants nothing
+fn read_wants(input: &mut impl io::Read) -> io::Result<Option<(Vec<String>, Vec<String>)>> {
+    let mut wants = Vec::new();
+    let mut capabilities = Vec::new();
+    loop {
+        let packet = match pktline::read(input) {
+            Ok(packet) => packet,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && wants.is_empty() => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        let Some(line) = packet else {
+            break;
+        };
+        let line = String::from_utf8_lossy(&line);
+        let mut words = line.trim_end().split(' ');
+        match (words.next(), words.next()) {
+            (Some("want"), Some(sha)) if refs::is_sha(sha) => {
+                if wants.is_empty() {
+                    capabilities = words.map(str::to_string).collect();
+                }
+                wants.push(sha.to_string());
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "git upload-pack: protocol error, expected to get object ID, not '{}'",
+                        line.trim_end()
+                    ),
+                ))
+            }
+        }
+    }
+    if wants.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((wants, capabilities)))
+}
+
+/// Read the client's "have" lines until it says "done". With `multi_ack`
+/// (multi_ack_detailed) each one we also have is acknowledged as common,
+/// every round ends in a NAK and "done" in an ACK of the last common one;
+/// without it only the first one we have is acknowledged, as git does.
+/// Returns the common objects, or `None` when a stateless request ends
+/// without "done".
+fn negotiate(
+    input: &mut impl io::Read,
+    out: &mut impl Write,
+    stateless: bool,
+    multi_ack: bool,
+) -> io::Result<Option<Vec<String>>> {
+    let mut common: Vec<String> = Vec::new();
+    loop {
+        let Some(line) = pktline::read(input)? else {
+            if common.is_empty() || multi_ack {
+                out.write_all(&pktline::encode(b"NAK\n"))?;
+            }
+            out.flush()?;
+            if stateless {
+                return Ok(None);
+            }
+            continue;
+        };
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end();
+        if line == "done" {
+            match common.last() {
+                None => out.write_all(&pktline::encode(b"NAK\n"))?,
+                Some(sha) if multi_ack => {
+                    out.write_all(&pktline::encode(format!("ACK {}\n", sha).as_bytes()))?
+                }
+                Some(_) => {}
+            }
+            return Ok(Some(common));
+        }
+        match line.strip_prefix("have ") {
+            Some(sha) if refs::is_sha(sha) => {
+                if object::locate(sha)?.is_some() {
+                    common.push(sha.to_string());
+                    let ack = match multi_ack {
+                        true => format!("ACK {} common\n", sha),
+                        false if common.len() == 1 => format!("ACK {}\n", sha),
+                        false => continue,
+                    };
+                    out.write_all(&pktline::encode(ack.as_bytes()))?;
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("git upload-pack: expected SHA1 list, got '{}'", line),
+                ))
+            }
+        }
+    }
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -0,0 +1,273 @@
+// worktree: manage linked working trees
+//
+// worktree list
+// worktree lock [--reason <string>] <worktree>
+// worktree unlock <worktree>
+// worktree move <worktree> <new-path>
+// worktree prune [-n] [-v]
+//
+// Each linked worktree has an administrative directory
+// `.git/worktrees/<id>/` holding its HEAD, a `gitdir` file with the absolute
+// path of the worktree's `.git` file and, while locked, a `locked` file with
+// the reason. The worktree's `.git` file points back with
+// `gitdir: <admin dir>`.
+
+use crate::git::refs;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const WORKTREES_DIR: &str = ".git/worktrees";
+
+const USAGE: &str = "Usage: worktree (list | lock [--reason <string>] <worktree> | unlock <worktree> | move <worktree> <new-path> | prune [-n] [-v])";
+
+/// A linked worktree as recorded in its administrative directory
+struct Linked {
+    id: String,
+    admin_dir: PathBuf,
+    /// Worktree directory, from the `gitdir` file; `None` if that is unreadable
+    path: Option<PathBuf>,
+    lock_reason: Option<String>,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let rest = args.get(1..).unwrap_or(&[]);
+    match args.first().map(String::as_str) {
+        Some("list") => list(),
+        Some("lock") => lock(rest),
+        Some("unlock") => match rest {
+            [target] => unlock(target),
+            _ => Err(usage()),
+        },
+        Some("move") => match rest {
+            [target, destination] => move_worktree(target, destination),
+            _ => Err(usage()),
+        },
+        Some("prune") => prune(rest),
+        _ => Err(usage()),
+    }
+}
+
+fn list() -> io::Result<()> {
+    let mut stdout = io::stdout();
+
+    let main_dir = std::env::current_dir()?;
+    let main_head = fs::read_to_string(".git/HEAD")?;
+    writeln!(stdout, "{}", describe(&main_dir, &main_head)?)?;
+
+    for worktree in linked_worktrees()? {
+        let head = fs::read_to_string(worktree.admin_dir.join("HEAD")).unwrap_or_default();
+        let mut line = match &worktree.path {
+            Some(path) => describe(path, &head)?,
+            None => format!("(unknown) [{}]", worktree.id),
+        };
+        if worktree.lock_reason.is_some() {
+            line.push_str(" locked");
+        }
+        if stale_reason(&worktree).is_some() {
+            line.push_str(" prunable");
+        }
+        writeln!(stdout, "{}", line)?;
+    }
+    stdout.flush()
+}
+
+/// `<path>  <short sha> [<branch>]` (or `(detached HEAD)`), as in `worktree list`
+fn describe(path: &Path, head: &str) -> io::Result<String> {
+    let head = head.trim();
+    let (sha, branch) = match head.strip_prefix("ref:") {
+        Some(target) => {
+            let target = target.trim();
+            let branch = target.strip_prefix("refs/heads/").unwrap_or(target);
+            (refs::read_ref(target)?, format!("[{}]", branch))
+        }
+        None => (Some(head.to_string()), "(detached HEAD)".to_string()),
+    };
+    let short = match &sha {
+        Some(sha) if sha.len() >= 7 => sha[..7].to_string(),
+        _ => "0000000".to_string(),
+    };
+    Ok(format!("{}  {} {}", path.display(), short, branch))
+}
+
+fn lock(args: &[String]) -> io::Result<()> {
+    let (reason, target) = match args {
+        [flag, reason, target] if flag == "--reason" => (reason.as_str(), target),
+        [target] => ("", target),
+        _ => return Err(usage()),
+    };
+
+    let worktree = find(target)?;
+    if let Some(existing) = &worktree.lock_reason {
+        return Err(io::Error::other(if existing.is_empty() {
+            format!("'{}' is already locked", target)
+        } else {
+            format!("'{}' is already locked, reason: {}", target, existing)
+        }));
+    }
+    fs::write(worktree.admin_dir.join("locked"), reason)
+}
+
+fn unlock(target: &str) -> io::Result<()> {
+    let worktree = find(target)?;
+    if worktree.lock_reason.is_none() {
+        return Err(io::Error::other(format!("'{}' is not locked", target)));
+    }
+    fs::remove_file(worktree.admin_dir.join("locked"))
+}
+
+fn move_worktree(target: &str, destination: &str) -> io::Result<()> {
+    let worktree = find(target)?;
+    if let Some(reason) = &worktree.lock_reason {
+        return Err(io::Error::other(if reason.is_empty() {
+            "cannot move a locked working tree;\nuse 'unlock' first".to_string()
+        } else {
+            format!(
+                "cannot move a locked working tree, lock reason: {}\nuse 'unlock' first",
+                reason
+            )
+        }));
+    }
+    let source = worktree.path.clone().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("'{}' has no readable gitdir file", target),
+        )
+    })?;
+    if !source.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "validation failed, cannot move working tree: '{}' does not exist",
+                source.display()
+            ),
+        ));
+    }
+
+    // Moving into an existing directory keeps the worktree's name, like mv
+    let mut destination = PathBuf::from(destination);
+    if destination.is_dir() {
+        if let Some(name) = source.file_name() {
+            destination.push(name);
+        }
+    }
+    if destination.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("target '{}' already exists", destination.display()),
+        ));
+    }
+
+    fs::rename(&source, &destination)?;
+    let destination = destination.canonicalize()?;
+    fs::write(
+        worktree.admin_dir.join("gitdir"),
+        format!("{}\n", destination.join(".git").display()),
+    )
+}
+
+fn prune(args: &[String]) -> io::Result<()> {
+    let mut dry_run = false;
+    let mut verbose = false;
+    for arg in args {
+        match arg.as_str() {
+            "-n" | "--dry-run" => dry_run = true,
+            "-v" | "--verbose" => verbose = true,
+            _ => return Err(usage()),
+        }
+    }
+
+    for worktree in linked_worktrees()? {
+        // A locked worktree may live on removable media; never prune it
+        if worktree.lock_reason.is_some() {
+            continue;
+        }
+        let reason = match stale_reason(&worktree) {
+            Some(reason) => reason,
+            None => continue,
+        };
+        if verbose || dry_run {
+            println!("Removing worktrees/{}: {}", worktree.id, reason);
+        }
+        if !dry_run {
+            fs::remove_dir_all(&worktree.admin_dir)?;
+        }
+    }
+
+    // Drop the parent directory once the last worktree is gone
+    if !dry_run {
+        let _ = fs::remove_dir(WORKTREES_DIR);
+    }
+    Ok(())
+}
+
+/// Why a worktree's administrative data is stale, or `None` if it is live
+fn stale_reason(worktree: &Linked) -> Option<&'static str> {
+    match &worktree.path {
+        None => Some("gitdir file does not exist"),
+        Some(path) if !path.join(".git").exists() => {
+            Some("gitdir file points to non-existent location")
+        }
+        Some(_) => None,
+    }
+}
+
+/// Every linked worktree, sorted by id
+fn linked_worktrees() -> io::Result<Vec<Linked>> {
+    let entries = match fs::read_dir(WORKTREES_DIR) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut worktrees = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let admin_dir = entry.path();
+        let path = fs::read_to_string(admin_dir.join("gitdir"))
+            .ok()
+            .map(|gitdir| {
+                let gitdir = PathBuf::from(gitdir.trim());
+                // gitdir names the worktree's `.git` file
+                gitdir.parent().map(Path::to_path_buf).unwrap_or(gitdir)
+            });
+        let lock_reason = fs::read_to_string(admin_dir.join("locked")).ok();
+        worktrees.push(Linked {
+            id: entry.file_name().to_string_lossy().into_owned(),
+            admin_dir,
+            path,
+            lock_reason,
+        });
+    }
+    worktrees.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(worktrees)
+}
+
+/// Look a linked worktree up by its path or by its id
+fn find(target: &str) -> io::Result<Linked> {
+    let wanted = Path::new(target).canonicalize().ok();
+    linked_worktrees()?
+        .into_iter()
+        .find(|worktree| {
+            let by_path = match (&wanted, &worktree.path) {
+                (Some(wanted), Some(path)) => {
+                    path.canonicalize().map(|p| &p == wanted).unwrap_or(false)
+                }
+                _ => false,
+            };
+            by_path || worktree.id == target
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("'{}' is not a working tree", target),
+            )
+        })
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
@@ -48,12 +48,8 @@ fn write_tree(directory: &Path) -> io::Result<String> {
             write_tree(&path)?
         } else {
             // println!("Creating file hash for {:?}", path);
-            object::create_file_hash(&path.to_string_lossy(), true).map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Error creating file hash: {:?}", e),
-                )
-            })?
+            object::create_file_hash(&path.to_string_lossy(), true)
+                .map_err(|e| io::Error::other(format!("Error creating file hash: {:?}", e)))?
         };
 
         // println!("Hash created for {:?}", path);
@@ -68,17 +64,13 @@ fn write_tree(directory: &Path) -> io::Result<String> {
     for entry in &entries {
         content.extend_from_slice(format!("{} {}\0", entry.mode, entry.name).as_bytes());
 
-        let hash_bytes = hex::decode(&entry.hash).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Error decoding hash: {:?}", e),
-            )
-        })?;
+        let hash_bytes = hex::decode(&entry.hash)
+            .map_err(|e| io::Error::other(format!("Error decoding hash: {:?}", e)))?;
         if hash_bytes.len() != 20 {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Hash must be 20 bytes, got {}", hash_bytes.len()),
-            ));
+            return Err(io::Error::other(format!(
+                "Hash must be 20 bytes, got {}",
+                hash_bytes.len()
+            )));
         }
         content.extend_from_slice(&hash_bytes);
     }
@@ -95,9 +87,8 @@ fn write_tree(directory: &Path) -> io::Result<String> {
     hasher.update(&store);
     let tree_hash = hasher.digest().to_string();
 
-    object::write_blob(&store, &tree_hash).map_err(|e| {
-        io::Error::new(io::ErrorKind::Other, format!("Error writing tree: {:?}", e))
-    })?;
+    object::write_blob(&store, &tree_hash)
+        .map_err(|e| io::Error::other(format!("Error writing tree: {:?}", e)))?;
 
     Ok(tree_hash)
 }
@@ -111,9 +102,6 @@ fn get_mode_for_file(file_type: &FileType) -> io::Result<&'static str> {
         // Not handling executable files
         Ok("100644")
     } else {
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Unsupported file type",
-        ))
+        Err(io::Error::other("Unsupported file type"))
     }
 }
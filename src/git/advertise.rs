@@ -0,0 +1,141 @@
+// Ref advertisement for the upload-pack and receive-pack services
+// A service opens the conversation by listing its refs, one pkt-line of
+// "<sha> <name>" each, with the capabilities it supports after a NUL on the
+// first line, and ends the list with a flush packet. A repository with no
+// refs sends a single "capabilities^{}" line so there is somewhere to put
+// the capabilities.
+//
+// transfer.hideRefs, together with uploadpack.hideRefs or receive.hideRefs
+// for the one service, names refs to keep out of the advertisement: each
+// value hides the refs at or below it (`refs/changes` hides every
+// `refs/changes/...`), a leading "!" makes a value unhide instead, and the
+// last value that matches a ref decides. The services also refuse requests
+// that touch hidden refs, so hiding one keeps clients away from it.
+
+use crate::git::config::Config;
+use crate::git::pktline::{self, FLUSH};
+use crate::git::reflog::ZERO_SHA;
+use crate::git::{refs, repository, tag};
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The `hideRefs` patterns that apply to one service
+#[derive(Debug, Clone, Default)]
+pub struct HiddenRefs {
+    patterns: Vec<String>,
+}
+
+impl HiddenRefs {
+    /// `transfer.hideRefs` and `<section>.hideRefs`, in config order
+    pub fn load(config: &Config, section: &str) -> HiddenRefs {
+        let patterns = config
+            .entries()
+            .iter()
+            .filter(|entry| {
+                entry.name == "hiderefs"
+                    && entry.subsection.is_none()
+                    && (entry.section == "transfer" || entry.section == section)
+            })
+            .filter_map(|entry| entry.value.clone())
+            .collect();
+        HiddenRefs { patterns }
+    }
+
+    pub fn is_hidden(&self, refname: &str) -> bool {
+        for pattern in self.patterns.iter().rev() {
+            let (unhide, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern.as_str()),
+            };
+            // "^" marks a full name; without namespaces every name is full
+            let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+            let pattern = pattern.trim_end_matches('/');
+            if refname == pattern
+                || refname
+                    .strip_prefix(pattern)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            {
+                return !unhide;
+            }
+        }
+        false
+    }
+}
+
+/// Serve the repository at `dir`: a working tree, the `.git` directory
+/// inside one, or a bare repository
+pub fn enter_repository(dir: &str) -> io::Result<()> {
+    let path = if dir.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(dir)
+    };
+    let worktree = match path.file_name() {
+        Some(name) if name == ".git" => path.parent().unwrap_or(Path::new(".")),
+        _ => path,
+    };
+    let worktree = if worktree.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        worktree
+    };
+    if worktree.join(".git").is_dir() {
+        env::set_current_dir(worktree)
+    } else if repository::is_git_dir(path) {
+        env::set_current_dir(path)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("'{}' does not appear to be a git repository", dir),
+        ))
+    }
+}
+
+/// The refs to advertise as `(name, sha)`: HEAD first when `with_head` is
+/// set, then every ref under refs/ by name, leaving out hidden ones
+pub fn visible_refs(with_head: bool, hidden: &HiddenRefs) -> io::Result<Vec<(String, String)>> {
+    let mut visible = Vec::new();
+    if with_head && !hidden.is_hidden("HEAD") {
+        if let Some(sha) = refs::head_commit()? {
+            visible.push(("HEAD".to_string(), sha));
+        }
+    }
+    for (name, sha) in refs::list_refs("refs/")? {
+        if !hidden.is_hidden(&name) {
+            visible.push((name, sha));
+        }
+    }
+    Ok(visible)
+}
+
+/// Write the advertisement of `refs` with `capabilities`. With `peel`, an
+/// annotated tag is followed by a "<sha> <name>^{}" line naming the object
+/// it tags.
+pub fn write(
+    out: &mut impl Write,
+    refs: &[(String, String)],
+    capabilities: &str,
+    peel: bool,
+) -> io::Result<()> {
+    if refs.is_empty() {
+        let line = format!("{} capabilities^{{}}\0{}\n", ZERO_SHA, capabilities);
+        out.write_all(&pktline::encode(line.as_bytes()))?;
+    }
+    for (i, (name, sha)) in refs.iter().enumerate() {
+        let line = if i == 0 {
+            format!("{} {}\0{}\n", sha, name, capabilities)
+        } else {
+            format!("{} {}\n", sha, name)
+        };
+        out.write_all(&pktline::encode(line.as_bytes()))?;
+        if peel && name.starts_with("refs/tags/") {
+            let (target, _) = tag::peel(sha)?;
+            if target != *sha {
+                let line = format!("{} {}^{{}}\n", target, name);
+                out.write_all(&pktline::encode(line.as_bytes()))?;
+            }
+        }
+    }
+    out.write_all(FLUSH)
+}
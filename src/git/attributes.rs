@@ -0,0 +1,140 @@
+// Path attributes
+// Lines of .gitattributes files are a pattern followed by attributes:
+// "name" sets one, "-name" unsets it, "name=value" gives it a value and
+// "!name" makes it unspecified again. "binary" is shorthand for
+// "-diff -merge -text". Patterns match as in .gitignore, except that there
+// is no "!" to negate a pattern and "dir/" patterns match nothing.
+//
+// For a path, the global file (core.attributesFile, by default
+// ~/.config/git/attributes) is read first, then the .gitattributes of each
+// directory from the top down to the path's own, then
+// .git/info/attributes; for each attribute the last line that matches wins.
+
+use crate::git::config::Config;
+use crate::git::ignore::wildmatch;
+use crate::git::repository;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What a path's attribute says
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Set,
+    Unset,
+    Value(String),
+}
+
+/// The attributes in effect for one path; unspecified ones are absent
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    states: BTreeMap<String, State>,
+}
+
+impl Attributes {
+    pub fn get(&self, name: &str) -> Option<&State> {
+        self.states.get(name)
+    }
+
+    fn apply(&mut self, assignment: &str) {
+        if assignment == "binary" {
+            self.states.insert("binary".to_string(), State::Set);
+            for name in ["diff", "merge", "text"] {
+                self.states.insert(name.to_string(), State::Unset);
+            }
+        } else if let Some(name) = assignment.strip_prefix('-') {
+            self.states.insert(name.to_string(), State::Unset);
+        } else if let Some(name) = assignment.strip_prefix('!') {
+            self.states.remove(name);
+        } else if let Some((name, value)) = assignment.split_once('=') {
+            self.states
+                .insert(name.to_string(), State::Value(value.to_string()));
+        } else {
+            self.states.insert(assignment.to_string(), State::Set);
+        }
+    }
+}
+
+/// The attributes of `path`, relative to the top of the working tree
+pub fn for_path(path: &str) -> io::Result<Attributes> {
+    let mut attributes = Attributes::default();
+    if let Some(file) = attributes_file()? {
+        apply_file(&mut attributes, &file, "", path)?;
+    }
+    let mut base = String::new();
+    apply_file(&mut attributes, Path::new(".gitattributes"), "", path)?;
+    let dirs: Vec<&str> = path.split('/').collect();
+    for dir in &dirs[..dirs.len() - 1] {
+        if !base.is_empty() {
+            base.push('/');
+        }
+        base.push_str(dir);
+        apply_file(
+            &mut attributes,
+            &Path::new(&base).join(".gitattributes"),
+            &base,
+            path,
+        )?;
+    }
+    let info = repository::path("info/attributes");
+    apply_file(&mut attributes, &info, "", path)?;
+    Ok(attributes)
+}
+
+/// Apply the lines of the attributes file at `file`, which sits in the
+/// directory `base`, that match `path`
+fn apply_file(attributes: &mut Attributes, file: &Path, base: &str, path: &str) -> io::Result<()> {
+    let text = match fs::read(file) {
+        Ok(data) => String::from_utf8_lossy(&data).into_owned(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let relative = if base.is_empty() {
+        path
+    } else {
+        match path
+            .strip_prefix(base)
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            Some(relative) => relative,
+            None => return Ok(()),
+        }
+    };
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(pattern) = fields.next() else {
+            continue;
+        };
+        if pattern.starts_with('#') || pattern.starts_with('!') || pattern.ends_with('/') {
+            continue;
+        }
+        let subject = if pattern.contains('/') {
+            relative
+        } else {
+            relative.rsplit('/').next().unwrap_or(relative)
+        };
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if wildmatch(pattern.as_bytes(), subject.as_bytes()) {
+            for assignment in fields {
+                attributes.apply(assignment);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// core.attributesFile, or $XDG_CONFIG_HOME/git/attributes
+/// (~/.config/git/attributes)
+fn attributes_file() -> io::Result<Option<PathBuf>> {
+    let config = Config::load()?;
+    if let Some(path) = config.get_path("core.attributesFile") {
+        return Ok(Some(path));
+    }
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| home.join(".config")));
+    Ok(config_home.map(|dir| dir.join("git/attributes")))
+}
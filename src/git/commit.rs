@@ -0,0 +1,209 @@
+// Commit object parsing
+// Built with the `strict` feature, parsing also rejects commits git's fsck
+// would: headers out of order, ids that aren't ids, and malformed or
+// missing identities.
+
+use crate::git::config::Config;
+use crate::git::encoding;
+use crate::git::ident::{self, Role};
+use crate::git::object::{self, Error};
+use crate::git::shallow;
+
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub tree: String,
+    pub parents: Vec<String>,
+    /// `Name <email> timestamp tz`
+    pub author: String,
+    /// `Name <email> timestamp tz`
+    pub committer: String,
+    /// The charset the commit declares for its text, `None` for UTF-8
+    pub encoding: Option<String>,
+    /// Decoded to UTF-8
+    pub message: String,
+}
+
+impl Commit {
+    /// When the commit was made, in seconds since the epoch
+    pub fn commit_time(&self) -> i64 {
+        let (_, timestamp) = ident::split(&self.committer);
+        timestamp
+            .split(' ')
+            .next()
+            .and_then(|epoch| epoch.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// First line of the commit message
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+}
+
+/// Parse the content of a commit object (header already stripped)
+pub fn parse(content: &[u8]) -> Result<Commit, Error> {
+    let encoding = declared_encoding(content);
+    let text = encoding::decode(content, encoding.as_deref());
+    let (headers, message) = match text.find("\n\n") {
+        Some(pos) => (&text[..pos], &text[pos + 2..]),
+        None => (text.as_ref(), ""),
+    };
+
+    let mut tree = None;
+    let mut parents = Vec::new();
+    let mut author = String::new();
+    let mut committer = String::new();
+
+    for line in headers.lines() {
+        if let Some(value) = line.strip_prefix("tree ") {
+            tree = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("parent ") {
+            parents.push(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("author ") {
+            author = value.to_string();
+        } else if let Some(value) = line.strip_prefix("committer ") {
+            committer = value.to_string();
+        }
+    }
+
+    let tree = tree.ok_or_else(|| Error::InvalidFormat("No tree found in commit".to_string()))?;
+    #[cfg(feature = "strict")]
+    check_headers(headers)?;
+
+    Ok(Commit {
+        tree,
+        parents,
+        author,
+        committer,
+        encoding,
+        message: message.to_string(),
+    })
+}
+
+/// Refuse headers that don't start "tree", any number of "parent", then
+/// "author" and "committer", each well-formed
+#[cfg(feature = "strict")]
+fn check_headers(headers: &str) -> Result<(), Error> {
+    let invalid = |problem: &str| Err(Error::InvalidFormat(format!("Invalid commit: {}", problem)));
+    let mut lines = headers.lines().peekable();
+    match lines.next().and_then(|line| line.strip_prefix("tree ")) {
+        Some(id) if is_object_id(id) => {}
+        _ => return invalid("no tree id on the first line"),
+    }
+    while let Some(id) = lines.peek().and_then(|line| line.strip_prefix("parent ")) {
+        if !is_object_id(id) {
+            return invalid("bad parent id");
+        }
+        lines.next();
+    }
+    for role in ["author", "committer"] {
+        let identity = lines
+            .next()
+            .and_then(|line| line.strip_prefix(role))
+            .and_then(|rest| rest.strip_prefix(' '));
+        match identity {
+            Some(identity) if is_identity(identity) => {}
+            _ => return invalid(&format!("missing or malformed {}", role)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "strict")]
+fn is_object_id(id: &str) -> bool {
+    id.len() == 40 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// `Name <email> <epoch> <+hhmm>`
+#[cfg(feature = "strict")]
+fn is_identity(identity: &str) -> bool {
+    let (person, timestamp) = ident::split(identity);
+    let Some((epoch, zone)) = timestamp.split_once(' ') else {
+        return false;
+    };
+    let digits = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+    person.contains('<')
+        && person.ends_with('>')
+        && digits(epoch)
+        && zone.len() == 5
+        && zone.starts_with(['+', '-'])
+        && digits(&zone[1..])
+}
+
+/// The value of the "encoding" header, read before the text is decoded
+fn declared_encoding(content: &[u8]) -> Option<String> {
+    content
+        .split(|&b| b == b'\n')
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| line.strip_prefix(b"encoding "))
+        .map(|label| String::from_utf8_lossy(label).trim().to_string())
+        .filter(|label| !encoding::is_utf8(label))
+}
+
+/// Read and parse a commit object. A commit at the edge of a shallow
+/// repository reads as having no parents, as they aren't here.
+pub fn read(commit_sha: &str) -> Result<Commit, Error> {
+    let content = object::read_expecting(commit_sha, "commit")?;
+    let mut commit = parse(&content)?;
+    if !commit.parents.is_empty() && shallow::contains(commit_sha)? {
+        commit.parents.clear();
+    }
+    Ok(commit)
+}
+
+/// Serialize a commit object's content, its text in the charset
+/// `encoding` (with a header saying so) or UTF-8
+pub fn format(
+    tree: &str,
+    parents: &[String],
+    author: &str,
+    committer: &str,
+    encoding: Option<&str>,
+    message: &str,
+) -> Vec<u8> {
+    let mut text = format!("tree {}\n", tree);
+    for parent in parents {
+        text.push_str(&format!("parent {}\n", parent));
+    }
+    text.push_str(&format!("author {}\n", author));
+    text.push_str(&format!("committer {}\n", committer));
+    if let Some(encoding) = encoding {
+        text.push_str(&format!("encoding {}\n", encoding));
+    }
+    text.push('\n');
+    text.push_str(message);
+    if !message.ends_with('\n') {
+        text.push('\n');
+    }
+    match encoding {
+        Some(label) => encoding::encode(&text, label),
+        None => text.into_bytes(),
+    }
+}
+
+/// Create a commit object authored and committed by the current identity
+pub fn create(tree: &str, parents: &[String], message: &str) -> Result<String, Error> {
+    let signature = ident::signature(Role::Author)?;
+    create_with_author(tree, parents, &signature, message)
+}
+
+/// Create a commit object keeping an existing author, committed by the
+/// current identity (used when replaying commits)
+pub fn create_with_author(
+    tree: &str,
+    parents: &[String],
+    author: &str,
+    message: &str,
+) -> Result<String, Error> {
+    let committer = ident::signature(Role::Committer)?;
+    let encoding = encoding::commit_encoding(&Config::load()?);
+    let content = format(
+        tree,
+        parents,
+        author,
+        &committer,
+        encoding.as_deref(),
+        message,
+    );
+    object::write("commit", &content)
+}
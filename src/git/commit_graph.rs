@@ -0,0 +1,307 @@
+// Commit-graph
+// `git commit-graph write` keeps what walks need of every commit it covers
+// in .git/objects/info/commit-graph, or in a chain of layers listed, oldest
+// first, in .git/objects/info/commit-graphs/commit-graph-chain, each
+// covering the commits the ones below it don't. A file is:
+//
+// "CGPH", version 1, hash version 1 (SHA-1), chunk count, base layer count
+// chunk table: id and offset for each chunk, then a terminating offset
+// OIDF: for each first byte, how many commits sort up to it (256 x u32)
+// OIDL: the commit ids, sorted
+// CDAT: for each commit, its tree id, the positions of its first two
+//       parents (0x70000000 for none; the second with its high bit set
+//       points into EDGE for a merge of more), then 30 bits of generation
+//       number and 34 of commit date
+// EDGE: the positions of the third and later parents of octopus merges,
+//       the last of each list with its high bit set
+//
+// Positions count across the layers, those of the base layers first. The
+// graph is left alone when core.commitGraph is false, and in a shallow
+// repository, whose commits don't have the parents the graph lists.
+
+use crate::git::config::Config;
+use crate::git::repository;
+use crate::git::shallow;
+use std::fs;
+use std::io;
+
+const GRAPH: &str = "objects/info/commit-graph";
+const CHAIN_DIR: &str = "objects/info/commit-graphs";
+
+const CHUNK_OID_FANOUT: u32 = u32::from_be_bytes(*b"OIDF");
+const CHUNK_OID_LOOKUP: u32 = u32::from_be_bytes(*b"OIDL");
+const CHUNK_COMMIT_DATA: u32 = u32::from_be_bytes(*b"CDAT");
+const CHUNK_EXTRA_EDGES: u32 = u32::from_be_bytes(*b"EDGE");
+
+const HASH_LEN: usize = 20;
+/// Bytes of CDAT per commit
+const DATA_LEN: usize = HASH_LEN + 16;
+const PARENT_NONE: u32 = 0x7000_0000;
+const PARENT_EXTRA: u32 = 0x8000_0000;
+
+/// The generation of a commit the graph doesn't cover: above every other
+pub const GENERATION_INFINITY: u32 = u32::MAX;
+
+/// The commit-graph of the repository, its layers oldest first
+pub struct CommitGraph {
+    layers: Vec<Layer>,
+}
+
+/// One commit-graph file and where its chunks start
+struct Layer {
+    data: Vec<u8>,
+    count: u32,
+    /// Commits in the layers below
+    base: u32,
+    fanout: usize,
+    lookup: usize,
+    commits: usize,
+    edges: Option<usize>,
+}
+
+impl CommitGraph {
+    /// The repository's commit-graph; none if it has none, or it doesn't
+    /// apply
+    pub fn open() -> io::Result<Option<CommitGraph>> {
+        if Config::load()?.get_bool("core.commitGraph")? == Some(false)
+            || !shallow::read()?.is_empty()
+        {
+            return Ok(None);
+        }
+        let mut paths = Vec::new();
+        if repository::path(GRAPH).is_file() {
+            paths.push(repository::path(GRAPH));
+        } else {
+            let chain = repository::path(CHAIN_DIR).join("commit-graph-chain");
+            match fs::read_to_string(&chain) {
+                Ok(text) => paths.extend(
+                    text.lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .map(|hash| {
+                            repository::path(CHAIN_DIR).join(format!("graph-{}.graph", hash))
+                        }),
+                ),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+        let mut layers: Vec<Layer> = Vec::new();
+        for path in paths {
+            let base = layers.last().map_or(0, |layer| layer.base + layer.count);
+            let layer = Layer::parse(fs::read(&path)?, base)
+                .ok_or_else(|| corrupt(&format!("{} is not a valid graph", path.display())))?;
+            layers.push(layer);
+        }
+        Ok(Some(CommitGraph { layers }))
+    }
+
+    /// The generation number of `sha`, `GENERATION_INFINITY` if the graph
+    /// doesn't cover it
+    pub fn generation(&self, sha: &str) -> u32 {
+        match self.find(sha) {
+            Some((layer, index)) => {
+                let data = layer.commit(index);
+                read_u32(data, HASH_LEN + 8) >> 2
+            }
+            None => GENERATION_INFINITY,
+        }
+    }
+
+    /// The parents of `sha`; `None` if the graph doesn't cover it
+    pub fn parents(&self, sha: &str) -> io::Result<Option<Vec<String>>> {
+        let Some((layer, index)) = self.find(sha) else {
+            return Ok(None);
+        };
+        let data = layer.commit(index);
+        let mut parents = Vec::new();
+        let first = read_u32(data, HASH_LEN);
+        if first != PARENT_NONE {
+            parents.push(self.id_at(first)?);
+        }
+        let second = read_u32(data, HASH_LEN + 4);
+        if second & PARENT_EXTRA == 0 {
+            if second != PARENT_NONE {
+                parents.push(self.id_at(second)?);
+            }
+            return Ok(Some(parents));
+        }
+        // An octopus merge lists the rest of its parents among the edges
+        let edges = layer
+            .edges
+            .ok_or_else(|| corrupt("commit-graph has no EDGE chunk"))?;
+        let mut at = edges + (second & !PARENT_EXTRA) as usize * 4;
+        loop {
+            let edge = layer
+                .data
+                .get(at..at + 4)
+                .map(|bytes| read_u32(bytes, 0))
+                .ok_or_else(|| corrupt("commit-graph edge out of range"))?;
+            parents.push(self.id_at(edge & !PARENT_EXTRA)?);
+            if edge & PARENT_EXTRA != 0 {
+                return Ok(Some(parents));
+            }
+            at += 4;
+        }
+    }
+
+    /// The layer holding `sha` and its index there
+    fn find(&self, sha: &str) -> Option<(&Layer, u32)> {
+        let id: [u8; HASH_LEN] = hex::decode(sha).ok()?.try_into().ok()?;
+        self.layers
+            .iter()
+            .find_map(|layer| layer.find(&id).map(|index| (layer, index)))
+    }
+
+    /// The id of the commit at global `position`
+    fn id_at(&self, position: u32) -> io::Result<String> {
+        self.layers
+            .iter()
+            .find(|layer| position >= layer.base && position < layer.base + layer.count)
+            .map(|layer| hex::encode(layer.id(position - layer.base)))
+            .ok_or_else(|| corrupt("commit-graph parent out of range"))
+    }
+}
+
+impl Layer {
+    /// The graph file `data`; `None` if it is malformed or cut short
+    fn parse(data: Vec<u8>, base: u32) -> Option<Layer> {
+        let header = data.get(..8)?;
+        if &header[..4] != b"CGPH" || header[4] != 1 || header[5] != 1 {
+            return None;
+        }
+        let chunks = header[6] as usize;
+        let (mut fanout, mut lookup, mut commits, mut edges) = (None, None, None, None);
+        for i in 0..chunks {
+            let at = 8 + i * 12;
+            let entry = data.get(at..at + 12)?;
+            let offset = usize::try_from(u64::from_be_bytes(entry[4..].try_into().ok()?)).ok()?;
+            match read_u32(entry, 0) {
+                CHUNK_OID_FANOUT => fanout = Some(offset),
+                CHUNK_OID_LOOKUP => lookup = Some(offset),
+                CHUNK_COMMIT_DATA => commits = Some(offset),
+                CHUNK_EXTRA_EDGES => edges = Some(offset),
+                _ => {}
+            }
+        }
+        let fanout = fanout?;
+        let count = read_u32(data.get(fanout..fanout.checked_add(256 * 4)?)?, 255 * 4);
+        let (lookup, commits) = (lookup?, commits?);
+        data.get(lookup..lookup.checked_add((count as usize).checked_mul(HASH_LEN)?)?)?;
+        data.get(commits..commits.checked_add((count as usize).checked_mul(DATA_LEN)?)?)?;
+        Some(Layer {
+            data,
+            count,
+            base,
+            fanout,
+            lookup,
+            commits,
+            edges,
+        })
+    }
+
+    fn find(&self, id: &[u8; HASH_LEN]) -> Option<u32> {
+        let bucket = |byte: usize| read_u32(&self.data, self.fanout + byte * 4);
+        let end = bucket(id[0] as usize);
+        let start = match id[0] {
+            0 => 0,
+            byte => bucket(byte as usize - 1),
+        };
+        let (mut low, mut high) = (start, end.min(self.count));
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.id(mid).cmp(id) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    fn id(&self, index: u32) -> &[u8] {
+        let at = self.lookup + index as usize * HASH_LEN;
+        &self.data[at..at + HASH_LEN]
+    }
+
+    fn commit(&self, index: u32) -> &[u8] {
+        let at = self.commits + index as usize * DATA_LEN;
+        &self.data[at..at + DATA_LEN]
+    }
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A one-layer graph of a root commit 11.. and its child 22..
+    fn graph() -> Vec<u8> {
+        let ids = [[0x11u8; HASH_LEN], [0x22u8; HASH_LEN]];
+        let mut fanout = Vec::new();
+        for byte in 0..256 {
+            let count = ids.iter().filter(|id| id[0] as usize <= byte).count() as u32;
+            fanout.extend_from_slice(&count.to_be_bytes());
+        }
+        let lookup = ids.concat();
+        let mut commits = Vec::new();
+        for (parent, generation) in [(PARENT_NONE, 1u64), (0, 2)] {
+            commits.extend_from_slice(&[0xee; HASH_LEN]);
+            commits.extend_from_slice(&parent.to_be_bytes());
+            commits.extend_from_slice(&PARENT_NONE.to_be_bytes());
+            commits.extend_from_slice(&(generation << 34 | 1_700_000_000).to_be_bytes());
+        }
+
+        let mut data = b"CGPH\x01\x01\x03\x00".to_vec();
+        let mut offset = 8 + 4 * 12;
+        for (id, chunk) in [(b"OIDF", &fanout), (b"OIDL", &lookup), (b"CDAT", &commits)] {
+            data.extend_from_slice(id);
+            data.extend_from_slice(&(offset as u64).to_be_bytes());
+            offset += chunk.len();
+        }
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(offset as u64).to_be_bytes());
+        for chunk in [fanout, lookup, commits] {
+            data.extend_from_slice(&chunk);
+        }
+        data
+    }
+
+    #[test]
+    fn reads_generations_and_parents() {
+        let layer = Layer::parse(graph(), 0).unwrap();
+        let graph = CommitGraph {
+            layers: vec![layer],
+        };
+        let (root, child) = ("11".repeat(HASH_LEN), "22".repeat(HASH_LEN));
+        assert_eq!(graph.generation(&root), 1);
+        assert_eq!(graph.generation(&child), 2);
+        assert_eq!(
+            graph.generation(&"33".repeat(HASH_LEN)),
+            GENERATION_INFINITY
+        );
+        assert_eq!(graph.parents(&child).unwrap(), Some(vec![root.clone()]));
+        assert_eq!(graph.parents(&root).unwrap(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_a_truncated_or_foreign_graph() {
+        let data = graph();
+        for len in 0..data.len() {
+            assert!(Layer::parse(data[..len].to_vec(), 0).is_none(), "{}", len);
+        }
+        let mut version = data.clone();
+        version[4] = 2;
+        assert!(Layer::parse(version, 0).is_none());
+        let mut offset = data;
+        offset[8 + 4..8 + 12].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(Layer::parse(offset, 0).is_none());
+    }
+}
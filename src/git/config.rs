@@ -0,0 +1,958 @@
+// Git config file reader and writer
+// Parses the INI-like format used by `.git/config` and `~/.gitconfig`:
+// - `[section]`, `[section "subsection"]` and legacy `[section.subsection]` headers
+// - `key = value` pairs, bare boolean keys, quoted values and escapes
+// - `#` / `;` comments and backslash line continuations
+// - `[include] path = <file>` pulling another file's entries in at that
+//   point, relative to the including file, with `~/` meaning $HOME
+//
+// Values are strings; the typed getters read them as git does: booleans
+// as true/yes/on/1 or false/no/off/0, integers with an optional k, m or g
+// suffix, paths with `~/` expanded, and colors as the terminal escape
+// sequence for words like "bold red" or "#ff0000 ul".
+
+use crate::git::dry_run;
+use crate::git::interrupt;
+use crate::git::repository;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A single `key = value` entry; section and name are stored lower-cased,
+/// the subsection keeps its case
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub section: String,
+    pub subsection: Option<String>,
+    pub name: String,
+    pub value: Option<String>,
+    /// The file the entry was read from
+    pub origin: PathBuf,
+}
+
+impl Entry {
+    /// Fully qualified `section[.subsection].name` key
+    pub fn key(&self) -> String {
+        match &self.subsection {
+            Some(sub) => format!("{}.{}.{}", self.section, sub, self.name),
+            None => format!("{}.{}", self.section, self.name),
+        }
+    }
+}
+
+/// Merged view over all config files, later files overriding earlier ones
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<Entry>,
+}
+
+impl Config {
+    /// Load the global files (`$XDG_CONFIG_HOME/git/config`, then
+    /// `~/.gitconfig`, or just `$GIT_CONFIG_GLOBAL` if set) and then the
+    /// repository's `.git/config`
+    pub fn load() -> io::Result<Config> {
+        let mut config = Config::default();
+        for global in global_config_paths() {
+            config.read_file(&global)?;
+        }
+        config.read_file(&repository::path("config"))?;
+        Ok(config)
+    }
+
+    /// Parse a config file and append its entries, followed by those of the
+    /// files it includes; a missing file is not an error
+    pub fn read_file(&mut self, path: &Path) -> io::Result<()> {
+        self.read_file_at_depth(path, 0)
+    }
+
+    fn read_file_at_depth(&mut self, path: &Path, depth: usize) -> io::Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let entries = parse(&text).map_err(|msg| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad config file {}: {}", path.display(), msg),
+            )
+        })?;
+        for mut entry in entries {
+            entry.origin = path.to_path_buf();
+            let include = match (&entry, &entry.value) {
+                (e, Some(value))
+                    if e.section == "include" && e.subsection.is_none() && e.name == "path" =>
+                {
+                    Some(value.clone())
+                }
+                _ => None,
+            };
+            self.entries.push(entry);
+            let Some(include) = include else {
+                continue;
+            };
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "exceeded maximum include depth ({}) while including {} from {}",
+                        MAX_INCLUDE_DEPTH,
+                        include,
+                        path.display()
+                    ),
+                ));
+            }
+            let mut included = expand_path(&include);
+            if included.is_relative() {
+                included = path.parent().unwrap_or(Path::new(".")).join(included);
+            }
+            self.read_file_at_depth(&included, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Last value for `key`, git's "last one wins" rule for single-valued keys.
+    /// A bare key with no `=` reads as `true`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.key() == key)
+            .map(|e| e.value.as_deref().unwrap_or("true"))
+    }
+
+    /// Every value of the multi-valued `key`, in config order
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .filter(|e| e.key() == key)
+            .map(|e| e.value.as_deref().unwrap_or("true"))
+            .collect()
+    }
+
+    /// Boolean value of `key`: true/yes/on/1 or false/no/off/0/empty
+    pub fn get_bool(&self, key: &str) -> io::Result<Option<bool>> {
+        self.get(key)
+            .map(|value| parse_bool(key, value))
+            .transpose()
+    }
+
+    /// Integer value of `key`, which may carry a k, m or g suffix for
+    /// multiples of 1024
+    pub fn get_int(&self, key: &str) -> io::Result<Option<i64>> {
+        self.get(key).map(|value| parse_int(key, value)).transpose()
+    }
+
+    /// Path value of `key`, with a leading `~/` expanded to $HOME
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        self.get(key).map(expand_path)
+    }
+
+    /// Color value of `key` as the escape sequence that turns it on
+    pub fn get_color(&self, key: &str) -> io::Result<Option<String>> {
+        self.get(key)
+            .map(|value| parse_color(key, value))
+            .transpose()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+}
+
+/// Includes nested deeper than this are taken to be a loop
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// The user's config files, lowest precedence first
+fn global_config_paths() -> Vec<PathBuf> {
+    if let Some(path) = env::var_os("GIT_CONFIG_GLOBAL") {
+        return vec![PathBuf::from(path)];
+    }
+    let home = home_dir();
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+    let mut paths: Vec<PathBuf> = config_home
+        .map(|dir| dir.join("git/config"))
+        .into_iter()
+        .collect();
+    paths.extend(home.map(|home| home.join(".gitconfig")));
+    paths
+}
+
+/// The user's config file that `config --global` reads and changes: the
+/// one in $HOME, unless only the XDG one exists
+pub fn global_path() -> Option<PathBuf> {
+    let paths = global_config_paths();
+    paths
+        .iter()
+        .rev()
+        .find(|path| path.exists())
+        .or(paths.last())
+        .cloned()
+}
+
+/// `value` as a path, with a leading `~/` (or a lone `~`) standing for $HOME
+pub fn expand_path(value: &str) -> PathBuf {
+    match (value.strip_prefix('~'), home_dir()) {
+        (Some(""), Some(home)) => home,
+        (Some(rest), Some(home)) if rest.starts_with('/') => home.join(&rest[1..]),
+        _ => PathBuf::from(value),
+    }
+}
+
+/// `value` of `key` as a boolean; any other integer counts too, as true
+/// unless it is zero
+pub fn parse_bool(key: &str, value: &str) -> io::Result<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" | "" => Ok(false),
+        _ => parse_int(key, value).map(|n| n != 0).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad boolean config value '{}' for '{}'", value, key),
+            )
+        }),
+    }
+}
+
+/// `value` of `key` as an integer, scaled by 1024 for each step of a k, m
+/// or g suffix
+pub fn parse_int(key: &str, value: &str) -> io::Result<i64> {
+    let bad = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "bad numeric config value '{}' for '{}': {}",
+                value, key, reason
+            ),
+        )
+    };
+    let trimmed = value.trim();
+    let (digits, factor) = match trimmed.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => {
+            let factor = match unit.to_ascii_lowercase() {
+                'k' => 1 << 10,
+                'm' => 1 << 20,
+                'g' => 1 << 30,
+                _ => return Err(bad("invalid unit")),
+            };
+            (&trimmed[..i], factor)
+        }
+        _ => (trimmed, 1),
+    };
+    let number: i64 = digits.parse().map_err(|_| bad("invalid unit"))?;
+    number
+        .checked_mul(factor)
+        .ok_or_else(|| bad("out of range"))
+}
+
+/// The eight basic colors, in the order of their ANSI codes
+const COLORS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Attributes and the codes turning them on and off
+const ATTRIBUTES: [(&str, u8, u8); 7] = [
+    ("bold", 1, 22),
+    ("dim", 2, 22),
+    ("italic", 3, 23),
+    ("ul", 4, 24),
+    ("blink", 5, 25),
+    ("reverse", 7, 27),
+    ("strike", 9, 29),
+];
+
+/// `value` of `key`, a color as git writes one, as the escape sequence that
+/// turns it on. The words are up to two colors, foreground then background
+/// (a name, "bright" and a name, a number from 0 to 255 or "#rrggbb";
+/// "normal" leaves one as it is and "default" resets it), attributes
+/// ("bold", "dim", "italic", "ul", "blink", "reverse", "strike", each
+/// turned off by a "no" or "no-" in front) and "reset" to start from plain
+/// text. An empty value is no sequence at all.
+pub fn parse_color(key: &str, value: &str) -> io::Result<String> {
+    let bad = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid color value: {} for '{}'", value, key),
+        )
+    };
+    let mut reset = false;
+    let mut attributes = Vec::new();
+    let mut colors: Vec<Option<(u8, String)>> = Vec::new();
+    for word in value.split_whitespace().map(str::to_lowercase) {
+        if word == "reset" {
+            reset = true;
+            continue;
+        }
+        if let Some(color) = parse_color_word(&word) {
+            if colors.len() == 2 {
+                return Err(bad());
+            }
+            colors.push(color);
+            continue;
+        }
+        let (off, name) = match word.strip_prefix("no") {
+            Some(name) => (true, name.strip_prefix('-').unwrap_or(name)),
+            None => (false, word.as_str()),
+        };
+        let &(_, on_code, off_code) = ATTRIBUTES
+            .iter()
+            .find(|(attribute, _, _)| *attribute == name)
+            .ok_or_else(bad)?;
+        let code = if off { off_code } else { on_code };
+        if !attributes.contains(&code) {
+            attributes.push(code);
+        }
+    }
+
+    let mut codes: Vec<String> = attributes.iter().map(u8::to_string).collect();
+    // The background takes the foreground's codes shifted by ten
+    for (shift, color) in [0, 10].into_iter().zip(colors) {
+        if let Some((base, rest)) = color {
+            codes.push(format!("{}{}", base + shift, rest));
+        }
+    }
+    if !reset && codes.is_empty() {
+        return Ok(String::new());
+    }
+    if reset {
+        codes.insert(0, String::new());
+    }
+    Ok(format!("\x1b[{}m", codes.join(";")))
+}
+
+/// One color word as the foreground code and what follows it; `None` inside
+/// for "normal", which changes nothing, and `None` outside for a word that
+/// isn't a color
+fn parse_color_word(word: &str) -> Option<Option<(u8, String)>> {
+    if word == "normal" {
+        return Some(None);
+    }
+    if word == "default" {
+        return Some(Some((39, String::new())));
+    }
+    let basic = |name: &str| COLORS.iter().position(|color| *color == name);
+    if let Some(n) = basic(word) {
+        return Some(Some((30 + n as u8, String::new())));
+    }
+    if let Some(n) = word.strip_prefix("bright").and_then(basic) {
+        return Some(Some((90 + n as u8, String::new())));
+    }
+    if let Some(hex) = word.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+        return Some(Some((
+            38,
+            format!(";2;{};{};{}", channel(0), channel(2), channel(4)),
+        )));
+    }
+    let n: u8 = word.parse().ok()?;
+    Some(Some((38, format!(";5;{}", n))))
+}
+
+/// Lower-case the section and variable name; the subsection keeps its case
+pub fn normalize_key(key: &str) -> String {
+    match (key.find('.'), key.rfind('.')) {
+        (Some(first), Some(last)) if first != last => format!(
+            "{}{}{}",
+            key[..first].to_lowercase(),
+            &key[first..last],
+            key[last..].to_lowercase()
+        ),
+        _ => key.to_lowercase(),
+    }
+}
+
+// ============================================================================
+// PARSER
+// ============================================================================
+
+fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut section: Option<(String, Option<String>)> = None;
+    let mut lines = text.lines().enumerate();
+
+    while let Some((lineno, raw_line)) = lines.next() {
+        let line = raw_line.trim_start();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let (header, rest) = parse_section_header(line)
+                .ok_or_else(|| format!("invalid section header on line {}", lineno + 1))?;
+            section = Some(header);
+            // A key may follow the header on the same line
+            let rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with('#') || rest.starts_with(';') {
+                continue;
+            }
+            let (sec, sub) = section.clone().unwrap();
+            let entry = parse_variable(rest, &mut lines, &sec, &sub)
+                .map_err(|msg| format!("{} on line {}", msg, lineno + 1))?;
+            entries.push(entry);
+            continue;
+        }
+
+        let (sec, sub) = section
+            .clone()
+            .ok_or_else(|| format!("key outside of a section on line {}", lineno + 1))?;
+        let entry = parse_variable(line, &mut lines, &sec, &sub)
+            .map_err(|msg| format!("{} on line {}", msg, lineno + 1))?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Parse `[section "sub"]` or `[section.sub]`, returning the remainder of the line
+fn parse_section_header(line: &str) -> Option<((String, Option<String>), &str)> {
+    let inner = &line[1..];
+
+    if let Some(quote) = inner.find('"') {
+        let name = inner[..quote].trim();
+        let mut sub = String::new();
+        let mut chars = inner[quote + 1..].char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        sub.push(escaped);
+                    }
+                }
+                '"' => {
+                    end = Some(quote + 1 + i + 1);
+                    break;
+                }
+                _ => sub.push(c),
+            }
+        }
+        let after_quote = &inner[end?..];
+        let close = after_quote.find(']')?;
+        if !after_quote[..close].trim().is_empty() || !valid_name(name) {
+            return None;
+        }
+        return Some(((name.to_lowercase(), Some(sub)), &after_quote[close + 1..]));
+    }
+
+    let close = inner.find(']')?;
+    let header = inner[..close].trim();
+    let rest = &inner[close + 1..];
+    match header.split_once('.') {
+        // Legacy `[section.subsection]` syntax: subsection is lower-cased
+        Some((name, sub)) if valid_name(name) => {
+            Some(((name.to_lowercase(), Some(sub.to_lowercase())), rest))
+        }
+        None if valid_name(header) => Some(((header.to_lowercase(), None), rest)),
+        _ => None,
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+fn parse_variable<'a, I>(
+    line: &str,
+    lines: &mut I,
+    section: &str,
+    subsection: &Option<String>,
+) -> Result<Entry, String>
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    let (name, value) = match line.find('=') {
+        Some(eq) => (line[..eq].trim(), Some(&line[eq + 1..])),
+        None => (line.split(['#', ';']).next().unwrap_or("").trim(), None),
+    };
+
+    if name.is_empty()
+        || !name.starts_with(|c: char| c.is_ascii_alphabetic())
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(format!("invalid key '{}'", name));
+    }
+
+    let value = match value {
+        Some(v) => Some(parse_value(v, lines)?),
+        None => None,
+    };
+
+    Ok(Entry {
+        section: section.to_string(),
+        subsection: subsection.clone(),
+        name: name.to_lowercase(),
+        value,
+        origin: PathBuf::new(),
+    })
+}
+
+/// Unquote and unescape a value, following continuation lines
+fn parse_value<'a, I>(first: &str, lines: &mut I) -> Result<String, String>
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut current = first.to_string();
+
+    loop {
+        let mut chars = current.trim_start().chars().peekable();
+        // Whitespace is only kept if followed by more content (trailing is trimmed)
+        let mut pending_space = String::new();
+        let mut continued = false;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    value.push_str(&pending_space);
+                    pending_space.clear();
+                    in_quotes = !in_quotes;
+                }
+                '\\' => {
+                    value.push_str(&pending_space);
+                    pending_space.clear();
+                    match chars.next() {
+                        None => {
+                            continued = true;
+                            break;
+                        }
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('b') => {
+                            value.pop();
+                        }
+                        Some('\\') => value.push('\\'),
+                        Some('"') => value.push('"'),
+                        Some(other) => return Err(format!("invalid escape '\\{}'", other)),
+                    }
+                }
+                '#' | ';' if !in_quotes => break,
+                c if c.is_whitespace() && !in_quotes => pending_space.push(c),
+                c => {
+                    value.push_str(&pending_space);
+                    pending_space.clear();
+                    value.push(c);
+                }
+            }
+        }
+
+        if !continued {
+            break;
+        }
+        match lines.next() {
+            Some((_, next)) => current = next.to_string(),
+            None => break,
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated quoted value".to_string());
+    }
+    Ok(value)
+}
+
+// ============================================================================
+// WRITER
+// ============================================================================
+
+/// Set `key` to `value` in the config file at `path`, editing the file in
+/// place: the last existing assignment is replaced, otherwise the variable is
+/// added to the end of its section (which is created if needed)
+pub fn set_value(path: &Path, key: &str, value: &str) -> io::Result<()> {
+    let (section, subsection, name) = split_key(key).ok_or_else(|| missing_section(key))?;
+    let mut lines = read_lines(path)?;
+
+    // The last assignment of the key and the last line of its section
+    let (assignments, section_end) = locate(&lines, &section, subsection.as_deref(), &name);
+    let assignment = assignments.last().copied();
+
+    let new_line = format!("\t{} = {}", name, quote_value(value));
+    match (assignment, section_end) {
+        (Some(i), _) if lines[i].trim_start().starts_with('[') => {
+            // `[section] key = value` on one line: keep the header, move the key
+            let header_end = lines[i].find(']').map(|p| p + 1).unwrap_or(lines[i].len());
+            lines[i].truncate(header_end);
+            lines.insert(i + 1, new_line);
+        }
+        (Some(i), _) => {
+            // Drop continuation lines of the old value along with it
+            let mut end = i;
+            while lines[end].ends_with('\\') && end + 1 < lines.len() {
+                end += 1;
+            }
+            lines.splice(i..=end, [new_line]);
+        }
+        (None, Some(end)) => lines.insert(end + 1, new_line),
+        (None, None) => {
+            lines.push(section_header(&section, subsection.as_deref()));
+            lines.push(new_line);
+        }
+    }
+
+    write_lines(path, &lines)
+}
+
+/// Add another value for the multi-valued `key` in the config file at
+/// `path`, after the existing ones
+pub fn add_value(path: &Path, key: &str, value: &str) -> io::Result<()> {
+    let (section, subsection, name) = split_key(key).ok_or_else(|| missing_section(key))?;
+    let mut lines = read_lines(path)?;
+    let (_, section_end) = locate(&lines, &section, subsection.as_deref(), &name);
+    let new_line = format!("\t{} = {}", name, quote_value(value));
+    match section_end {
+        Some(end) => lines.insert(end + 1, new_line),
+        None => {
+            lines.push(section_header(&section, subsection.as_deref()));
+            lines.push(new_line);
+        }
+    }
+    write_lines(path, &lines)
+}
+
+/// Remove assignments of `key` from the config file at `path`: every one
+/// with `all`, otherwise the only one. Returns how many there were; with
+/// several and not `all` the file is left alone.
+pub fn unset_value(path: &Path, key: &str, all: bool) -> io::Result<usize> {
+    let (section, subsection, name) = split_key(key).ok_or_else(|| missing_section(key))?;
+    let mut lines = read_lines(path)?;
+    let (assignments, _) = locate(&lines, &section, subsection.as_deref(), &name);
+    if assignments.is_empty() || (assignments.len() > 1 && !all) {
+        return Ok(assignments.len());
+    }
+    for &i in assignments.iter().rev() {
+        if lines[i].trim_start().starts_with('[') {
+            let header_end = lines[i].find(']').map(|p| p + 1).unwrap_or(lines[i].len());
+            lines[i].truncate(header_end);
+            continue;
+        }
+        let mut end = i;
+        while lines[end].ends_with('\\') && end + 1 < lines.len() {
+            end += 1;
+        }
+        lines.drain(i..=end);
+    }
+    write_lines(path, &lines)?;
+    Ok(assignments.len())
+}
+
+/// Rename every instance of section `section.subsection` in the config
+/// file at `path` to `section.new_subsection`. Returns whether there was one.
+pub fn rename_section(
+    path: &Path,
+    section: &str,
+    subsection: Option<&str>,
+    new_subsection: Option<&str>,
+) -> io::Result<bool> {
+    let mut lines = read_lines(path)?;
+    let mut found = false;
+    for line in lines.iter_mut() {
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with('[') {
+            continue;
+        }
+        if let Some(((sec, sub), rest)) = parse_section_header(trimmed) {
+            if sec == section && sub.as_deref() == subsection {
+                *line = format!("{}{}", section_header(section, new_subsection), rest);
+                found = true;
+            }
+        }
+    }
+    if found {
+        write_lines(path, &lines)?;
+    }
+    Ok(found)
+}
+
+/// Remove every instance of section `section.subsection`, with all its
+/// variables, from the config file at `path`. Returns whether there was one.
+pub fn remove_section(path: &Path, section: &str, subsection: Option<&str>) -> io::Result<bool> {
+    let lines = read_lines(path)?;
+    let mut kept = Vec::with_capacity(lines.len());
+    let mut in_section = false;
+    let mut found = false;
+    for line in lines {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            if let Some(((sec, sub), _)) = parse_section_header(trimmed) {
+                in_section = sec == section && sub.as_deref() == subsection;
+                found |= in_section;
+            }
+        }
+        if !in_section {
+            kept.push(line);
+        }
+    }
+    if found {
+        write_lines(path, &kept)?;
+    }
+    Ok(found)
+}
+
+fn missing_section(key: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("key does not contain a section: {}", key),
+    )
+}
+
+fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.lines().map(str::to_string).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// The lines assigning `name` in the section, and the last line of the
+/// last instance of the section
+fn locate(
+    lines: &[String],
+    section: &str,
+    subsection: Option<&str>,
+    name: &str,
+) -> (Vec<usize>, Option<usize>) {
+    let mut current: Option<(String, Option<String>)> = None;
+    let mut assignments = Vec::new();
+    let mut section_end = None;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let mut rest = trimmed;
+        if trimmed.starts_with('[') {
+            match parse_section_header(trimmed) {
+                Some((header, after)) => {
+                    current = Some(header);
+                    rest = after.trim_start();
+                }
+                None => continue,
+            }
+        }
+        let in_section = current
+            .as_ref()
+            .is_some_and(|(sec, sub)| sec == section && sub.as_deref() == subsection);
+        if !in_section {
+            continue;
+        }
+        section_end = Some(i);
+        let var = rest.split(['=', '#', ';']).next().unwrap_or("").trim();
+        if !var.is_empty() && var.to_lowercase() == name {
+            assignments.push(i);
+        }
+    }
+    (assignments, section_end)
+}
+
+fn section_header(section: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(sub) => format!(
+            "[{} \"{}\"]",
+            section,
+            sub.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        None => format!("[{}]", section),
+    }
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> io::Result<()> {
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+    let mut output = lines.join("\n");
+    output.push('\n');
+
+    // Write through a lock file so readers never see a half-written config
+    let lock = path.with_extension("lock");
+    let _cleanup = interrupt::remove_on_interrupt(&lock);
+    fs::write(&lock, output)?;
+    fs::rename(&lock, path)
+}
+
+/// Split `section[.subsection].name`, lower-casing section and name
+fn split_key(key: &str) -> Option<(String, Option<String>, String)> {
+    let first = key.find('.')?;
+    let last = key.rfind('.')?;
+    let section = key[..first].to_lowercase();
+    let name = key[last + 1..].to_lowercase();
+    if !valid_name(&section) || name.is_empty() {
+        return None;
+    }
+    let subsection = (first != last).then(|| key[first + 1..last].to_string());
+    Some((section, subsection, name))
+}
+
+/// Quote a value when the parser would otherwise alter it
+fn quote_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    if needs_quotes {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "# a comment
+[core]
+\tbare = false
+\tautocrlf
+[remote \"Origin\"] url = https://example.com/r.git ; trailing
+\tfetch = +refs/heads/*:refs/remotes/origin/*
+[Branch.Main]
+\tmerge = refs/heads/main
+[alias]
+\tlg = \"log --oneline \\
+  --graph\" # note
+\tmsg = a\\tb\\\"c\\\\d
+\tspaced =   x  y  ; z
+[core]
+\tbare = yes
+";
+
+    fn config(text: &str) -> Config {
+        Config {
+            entries: parse(text).unwrap(),
+        }
+    }
+
+    #[test]
+    fn parses_sections_values_and_escapes() {
+        let config = config(TEXT);
+        assert_eq!(config.get("core.bare"), Some("yes"));
+        assert_eq!(config.get_bool("core.bare").unwrap(), Some(true));
+        assert_eq!(config.get("CORE.AutoCRLF"), Some("true"));
+        assert_eq!(
+            config.get("remote.Origin.url"),
+            Some("https://example.com/r.git")
+        );
+        assert_eq!(config.get("remote.origin.url"), None);
+        assert_eq!(config.get("branch.main.merge"), Some("refs/heads/main"));
+        assert_eq!(config.get("alias.lg"), Some("log --oneline --graph"));
+        assert_eq!(config.get("alias.msg"), Some("a\tb\"c\\d"));
+        assert_eq!(config.get("alias.spaced"), Some("x  y"));
+        assert_eq!(config.get_all("core.bare"), ["false", "yes"]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        for text in [
+            "[core\n",
+            "bare = true\n",
+            "[core]\n1bare = true\n",
+            "[core]\nbare = \"open\n",
+            "[core]\nbare = \\q\n",
+            "[co re]\n",
+        ] {
+            assert!(parse(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn parses_typed_values() {
+        for (value, expected) in [
+            ("yes", true),
+            ("On", true),
+            ("2", true),
+            ("0", false),
+            ("", false),
+        ] {
+            assert_eq!(parse_bool("k", value).unwrap(), expected, "{:?}", value);
+        }
+        assert!(parse_bool("k", "maybe").is_err());
+        assert_eq!(parse_int("k", "1k").unwrap(), 1024);
+        assert_eq!(parse_int("k", " 2M ").unwrap(), 2 << 20);
+        assert_eq!(parse_int("k", "-3").unwrap(), -3);
+        assert!(parse_int("k", "1x").is_err());
+        assert!(parse_int("k", "9999999999g").is_err());
+
+        assert_eq!(parse_color("k", "bold red").unwrap(), "\x1b[1;31m");
+        assert_eq!(parse_color("k", "brightblue black").unwrap(), "\x1b[94;40m");
+        assert_eq!(
+            parse_color("k", "#ff0000 ul").unwrap(),
+            "\x1b[4;38;2;255;0;0m"
+        );
+        assert_eq!(parse_color("k", "reset 208").unwrap(), "\x1b[;38;5;208m");
+        assert_eq!(parse_color("k", "nobold").unwrap(), "\x1b[22m");
+        assert_eq!(parse_color("k", "").unwrap(), "");
+        assert!(parse_color("k", "red green blue").is_err());
+        assert!(parse_color("k", "sparkly").is_err());
+
+        assert_eq!(normalize_key("Remote.Origin.URL"), "remote.Origin.url");
+        assert_eq!(normalize_key("Core.Bare"), "core.bare");
+    }
+
+    #[test]
+    fn reads_includes_and_edits_in_place() {
+        let root = std::env::temp_dir().join(format!("byog-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        let path = root.join("config");
+        fs::write(
+            &path,
+            "[user]\n\tname = Before\n[include]\n\tpath = sub/extra\n",
+        )
+        .unwrap();
+        fs::write(root.join("sub/extra"), "[user]\n\tname = Included\n").unwrap();
+
+        let mut config = Config::default();
+        config.read_file(&path).unwrap();
+        assert_eq!(config.get("user.name"), Some("Included"));
+        assert_eq!(
+            config.entries().last().unwrap().origin,
+            root.join("sub/extra")
+        );
+
+        set_value(&path, "user.email", "me@example.com").unwrap();
+        set_value(&path, "user.name", "After").unwrap();
+        add_value(
+            &path,
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/origin/*",
+        )
+        .unwrap();
+        add_value(&path, "remote.origin.fetch", "+refs/tags/*:refs/tags/*").unwrap();
+        let mut config = Config::default();
+        config.read_file(&path).unwrap();
+        assert_eq!(config.get("user.email"), Some("me@example.com"));
+        assert_eq!(config.get_all("remote.origin.fetch").len(), 2);
+
+        // Several values are only removed all at once
+        assert_eq!(unset_value(&path, "remote.origin.fetch", false).unwrap(), 2);
+        assert_eq!(unset_value(&path, "remote.origin.fetch", true).unwrap(), 2);
+        assert_eq!(unset_value(&path, "user.email", false).unwrap(), 1);
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("\tname = After\n"), "{}", text);
+        assert!(
+            !text.contains("fetch") && !text.contains("email"),
+            "{}",
+            text
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
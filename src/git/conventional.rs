@@ -0,0 +1,71 @@
+// Conventional Commits message validation
+// https://www.conventionalcommits.org/
+//
+// <type>[(<scope>)][!]: <description>
+//
+// [body]
+//
+// [footers]
+
+/// Types accepted when `commit.conventionalTypes` isn't set
+pub const DEFAULT_TYPES: &[&str] = &[
+    "build", "chore", "ci", "docs", "feat", "fix", "perf", "refactor", "revert", "style", "test",
+];
+
+/// Check a commit message against the spec. Messages git generates itself
+/// (merges, reverts, fixup!/squash! commits) are accepted as they are.
+pub fn validate(message: &str, types: &[&str]) -> Result<(), String> {
+    let mut lines = message.lines();
+    let header = lines.next().unwrap_or("").trim_end();
+    if header.is_empty() {
+        return Err("commit message is empty".to_string());
+    }
+
+    let generated = ["Merge ", "Revert \"", "fixup! ", "squash! ", "amend! "];
+    if generated.iter().any(|prefix| header.starts_with(prefix)) {
+        return Ok(());
+    }
+
+    let kind_len = header
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(header.len());
+    let kind = &header[..kind_len];
+    let mut rest = &header[kind_len..];
+
+    if kind.is_empty() {
+        return Err(format!("'{}' does not start with a type", header));
+    }
+    if !types.contains(&kind) {
+        return Err(format!(
+            "unknown type '{}' (expected one of: {})",
+            kind,
+            types.join(", ")
+        ));
+    }
+
+    if let Some(after) = rest.strip_prefix('(') {
+        let close = after
+            .find(')')
+            .ok_or_else(|| format!("unclosed scope in '{}'", header))?;
+        let scope = &after[..close];
+        if scope.trim().is_empty() || scope.contains('(') {
+            return Err(format!("invalid scope '{}'", scope));
+        }
+        rest = &after[close + 1..];
+    }
+    rest = rest.strip_prefix('!').unwrap_or(rest);
+
+    let description = rest
+        .strip_prefix(": ")
+        .ok_or_else(|| format!("expected ': ' after the type in '{}'", header))?;
+    if description.trim().is_empty() {
+        return Err("description after the type is empty".to_string());
+    }
+
+    if let Some(second) = lines.next() {
+        if !second.trim().is_empty() {
+            return Err("the subject line must be followed by a blank line".to_string());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+// Content conversion from the working tree into the repository
+// A file is stored in its "clean" form: first the output of the clean
+// command of its `filter` attribute's driver (filter.<driver>.clean, with
+// "%f" standing for the path), then with CRLF line endings turned into LF
+// if it is text. A file is text when its `text` attribute is set, or when
+// the decision is left to the content, which is binary if it holds a NUL or
+// a lone CR: with `text=auto`, with `eol` set, or with no `text` attribute
+// and core.autocrlf true or input. `-text` (or `binary`) keeps the bytes.
+//
+// A failing clean filter is an error if filter.<driver>.required is set,
+// and otherwise leaves the content unfiltered.
+
+use crate::git::attributes::{self, State};
+use crate::git::config::Config;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// How much of the content is looked at to tell binary from text
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// `content` read from the working tree file `path`, as it is stored
+pub fn to_git(path: &str, content: Vec<u8>) -> io::Result<Vec<u8>> {
+    let config = Config::load()?;
+    let attrs = attributes::for_path(path)?;
+
+    let content = match attrs.get("filter") {
+        Some(State::Value(driver)) => clean(&config, driver, path, content)?,
+        _ => content,
+    };
+
+    let text = match attrs.get("text") {
+        Some(State::Set) => true,
+        Some(State::Unset) => false,
+        Some(State::Value(value)) if value == "auto" => !is_binary(&content),
+        _ if attrs.get("eol").is_some() => !is_binary(&content),
+        _ => {
+            let autocrlf = config.get("core.autocrlf").unwrap_or("false");
+            (autocrlf == "input" || config.get_bool("core.autocrlf")?.unwrap_or(false))
+                && !is_binary(&content)
+        }
+    };
+    Ok(if text { crlf_to_lf(content) } else { content })
+}
+
+/// Whether `content` looks binary: a NUL, or a CR not before an LF
+fn is_binary(content: &[u8]) -> bool {
+    let start = &content[..content.len().min(BINARY_CHECK_LEN)];
+    start
+        .iter()
+        .enumerate()
+        .any(|(i, &byte)| byte == 0 || (byte == b'\r' && content.get(i + 1) != Some(&b'\n')))
+}
+
+fn crlf_to_lf(content: Vec<u8>) -> Vec<u8> {
+    if !content.windows(2).any(|pair| pair == b"\r\n") {
+        return content;
+    }
+    let mut converted = Vec::with_capacity(content.len());
+    for (i, &byte) in content.iter().enumerate() {
+        if byte != b'\r' || content.get(i + 1) != Some(&b'\n') {
+            converted.push(byte);
+        }
+    }
+    converted
+}
+
+/// Run `content` through the clean command of filter `driver`
+fn clean(config: &Config, driver: &str, path: &str, content: Vec<u8>) -> io::Result<Vec<u8>> {
+    let required = config
+        .get_bool(&format!("filter.{}.required", driver))?
+        .unwrap_or(false);
+    let Some(command) = config.get(&format!("filter.{}.clean", driver)) else {
+        if required {
+            return Err(io::Error::other(format!(
+                "{}: clean filter '{}' failed",
+                path, driver
+            )));
+        }
+        return Ok(content);
+    };
+    let quoted = format!("'{}'", path.replace('\'', "'\\''"));
+    let command = command.replace("%f", &quoted);
+    match run_filter(&command, &content) {
+        Ok(output) => Ok(output),
+        Err(e) if required => Err(io::Error::other(format!(
+            "{}: clean filter '{}' failed: {}",
+            path, driver, e
+        ))),
+        Err(_) => {
+            eprintln!("error: external filter '{}' failed", command);
+            Ok(content)
+        }
+    }
+}
+
+/// The output of the shell command `command` fed `input`
+fn run_filter(command: &str, input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    // Feed the input from another thread so a filter that writes before it
+    // has read everything can't deadlock against us
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    // A filter may exit without reading all of its input
+    let _ = writer.join();
+    if !output.status.success() {
+        return Err(io::Error::other(format!("exited with {}", output.status)));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,193 @@
+// Credential helpers
+// When an HTTP remote answers 401, the user name and password (or token)
+// to retry with come from the helpers configured in `credential.helper` and
+// `credential.<url>.helper`, asked in config order; an empty value clears
+// the helpers before it. A helper is a program spoken to in git's
+// credential protocol: it is run with "get", "store" or "erase" and given
+// lines of "key=value" on stdin,
+//
+//   protocol=https
+//   host=example.com
+//   username=me
+//
+// and for "get" answers with lines of the same form, adding what it knows.
+// A helper value "foo" runs `git credential-foo`, an absolute path runs
+// that program, and one starting with "!" is a shell command. Helpers that
+// can answer with a token instead are told so with "capability[]=authtype"
+// and answer "authtype=Bearer" and "credential=<token>". Credentials that
+// worked are sent to every helper's "store", ones the remote refused to
+// its "erase". The path is passed too when `credential.useHttpPath` is
+// set, and `credential.username` gives the user name when the URL has
+// none.
+
+use crate::git::config::Config;
+use crate::git::http;
+use crate::git::url::RemoteUrl;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// What is known about the credentials for one remote, as the key=value
+/// fields the helpers read and write
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Credential {
+    pub protocol: String,
+    pub host: String,
+    pub path: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The scheme of an `Authorization` header to send instead of a user
+    /// name and password, e.g. "Bearer"
+    pub authtype: Option<String>,
+    /// What goes after `authtype` in that header
+    pub credential: Option<String>,
+}
+
+impl Credential {
+    /// The `Authorization` header value these credentials make, once there
+    /// is enough of them for one
+    pub fn authorization(&self) -> Option<String> {
+        use base64::Engine;
+        if let (Some(authtype), Some(credential)) = (&self.authtype, &self.credential) {
+            return Some(format!("{} {}", authtype, credential));
+        }
+        let (Some(username), Some(password)) = (&self.username, &self.password) else {
+            return None;
+        };
+        let pair = format!("{}:{}", username, password);
+        Some(format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(pair)
+        ))
+    }
+
+    fn encode(&self) -> String {
+        let mut text = "capability[]=authtype\n".to_string();
+        text.push_str(&format!("protocol={}\nhost={}\n", self.protocol, self.host));
+        let fields = [
+            ("path", &self.path),
+            ("username", &self.username),
+            ("password", &self.password),
+            ("authtype", &self.authtype),
+            ("credential", &self.credential),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                text.push_str(&format!("{}={}\n", key, value));
+            }
+        }
+        text
+    }
+
+    /// Take in a helper's answer; returns false if it said to stop asking
+    fn update(&mut self, answer: &str) -> bool {
+        for line in answer.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = Some(value.to_string());
+            match key {
+                "username" => self.username = value,
+                "password" => self.password = value,
+                "authtype" => self.authtype = value,
+                "credential" => self.credential = value,
+                "quit" if value.as_deref() == Some("1") || value.as_deref() == Some("true") => {
+                    return false
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+}
+
+/// Ask the helpers for the credentials to `remote` at `repo_url`, starting
+/// from those in the URL; `None` if no helper had enough to go on
+pub fn fill(repo_url: &str, remote: &RemoteUrl) -> io::Result<Option<Credential>> {
+    let config = Config::load()?;
+    let mut credential = Credential {
+        protocol: remote.scheme.as_str().to_string(),
+        host: remote.authority(),
+        path: None,
+        username: remote
+            .user
+            .clone()
+            .or_else(|| config.get("credential.username").map(str::to_string)),
+        password: remote.password.clone(),
+        authtype: None,
+        credential: None,
+    };
+    if config.get_bool("credential.usehttppath")? == Some(true) {
+        credential.path = Some(remote.path.trim_start_matches('/').to_string());
+    }
+    for helper in helpers(&config, repo_url) {
+        if credential.authorization().is_some() {
+            break;
+        }
+        let answer = run(&helper, "get", &credential)?;
+        if !credential.update(&answer) {
+            return Ok(None);
+        }
+    }
+    Ok(Some(credential).filter(|credential| credential.authorization().is_some()))
+}
+
+/// Tell the helpers `credential` worked, so they can keep it
+pub fn approve(repo_url: &str, credential: &Credential) -> io::Result<()> {
+    for helper in helpers(&Config::load()?, repo_url) {
+        run(&helper, "store", credential)?;
+    }
+    Ok(())
+}
+
+/// Tell the helpers `credential` was refused, so they forget it
+pub fn reject(repo_url: &str, credential: &Credential) -> io::Result<()> {
+    for helper in helpers(&Config::load()?, repo_url) {
+        run(&helper, "erase", credential)?;
+    }
+    Ok(())
+}
+
+/// The helpers configured for `repo_url`, in the order to ask them
+fn helpers(config: &Config, repo_url: &str) -> Vec<String> {
+    let mut helpers = Vec::new();
+    for entry in config.entries() {
+        if entry.section != "credential" || entry.name != "helper" {
+            continue;
+        }
+        if let Some(pattern) = &entry.subsection {
+            if !http::url_matches(pattern, repo_url) {
+                continue;
+            }
+        }
+        match entry.value.as_deref() {
+            None | Some("") => helpers.clear(),
+            Some(value) => helpers.push(value.to_string()),
+        }
+    }
+    helpers
+}
+
+/// Run `helper` for `action` with `credential` on its stdin, returning what
+/// it printed
+fn run(helper: &str, action: &str, credential: &Credential) -> io::Result<String> {
+    let command = if let Some(shell) = helper.strip_prefix('!') {
+        format!("{} {}", shell, action)
+    } else if helper.starts_with('/') {
+        format!("{} {}", helper, action)
+    } else {
+        format!("git credential-{} {}", helper, action)
+    };
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run {}: {}", command, e)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A helper with nothing to say may not read its input
+        let _ = stdin.write_all(credential.encode().as_bytes());
+    }
+    let output = child.wait_with_output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
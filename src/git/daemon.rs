@@ -0,0 +1,120 @@
+// git:// transport
+// Talks to a git daemon over TCP, on port 9418 unless the URL names
+// another. The client opens with a single packet naming the service and
+// the repository,
+//
+//   git-upload-pack /path/to/repo.git\0host=example.com\0
+//
+// the host being the one in the URL (with its port, if it has one) so a
+// daemon serving several hosts can tell which is meant; a protocol version
+// other than v0 is asked for after one more NUL, "\0version=2\0". From
+// there the conversation is the one ssh carries: the ref advertisement, the
+// request, and the response until the daemon closes the connection. A
+// daemon that won't serve the repository says why in an "ERR" packet.
+
+use crate::git::pktline;
+use crate::git::url::RemoteUrl;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+
+/// Where a git daemon listens unless told otherwise
+pub const DEFAULT_PORT: u16 = 9418;
+
+/// A connection to a service run by a git daemon
+pub struct Connection {
+    stream: TcpStream,
+}
+
+/// Ask the daemon at `remote`'s host to run `service` (e.g.
+/// `git-upload-pack`) for the repository at its path, speaking `protocol`
+/// (e.g. "version=2") if given
+pub fn connect(
+    remote: &RemoteUrl,
+    service: &str,
+    protocol: Option<&str>,
+) -> io::Result<Connection> {
+    let host = remote
+        .host
+        .as_deref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "git:// URL without a host"))?;
+    let port = remote.port.unwrap_or(DEFAULT_PORT);
+    let mut stream = TcpStream::connect((host, port)).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("unable to connect to {}:{}: {}", host, port, e),
+        )
+    })?;
+    let mut line = format!("{} {}\0host={}\0", service, remote.path, remote.authority());
+    if let Some(protocol) = protocol {
+        line.push_str(&format!("\0{}\0", protocol));
+    }
+    stream.write_all(&pktline::encode(line.as_bytes()))?;
+    Ok(Connection { stream })
+}
+
+impl Connection {
+    /// Read the ref advertisement, up to and including its flush packet,
+    /// as ssh's `Connection::read_advertisement` returns it
+    pub fn read_advertisement(&mut self) -> io::Result<Vec<u8>> {
+        let mut advertisement = Vec::new();
+        loop {
+            let payload = match pktline::read(&mut self.stream) {
+                Ok(Some(payload)) => payload,
+                Ok(None) => {
+                    advertisement.extend_from_slice(pktline::FLUSH);
+                    return Ok(advertisement);
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the remote end hung up unexpectedly",
+                    ))
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(message) = payload.strip_prefix(b"ERR ") {
+                return Err(io::Error::other(format!(
+                    "remote error: {}",
+                    String::from_utf8_lossy(message).trim_end()
+                )));
+            }
+            advertisement.extend_from_slice(&pktline::encode(&payload));
+        }
+    }
+
+    /// Send one protocol v2 command and read its response up to the flush
+    /// packet ending it, leaving the service waiting for the next
+    pub fn command(&mut self, request: &[u8]) -> io::Result<Vec<u8>> {
+        self.stream.write_all(request)?;
+        self.read_advertisement()
+    }
+
+    /// Send part of a request, leaving the connection open for more
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.write_all(data)
+    }
+
+    /// Send a complete request, then read everything the service sends back
+    /// until the daemon hangs up
+    pub fn request(self, body: impl Read) -> io::Result<Vec<u8>> {
+        let mut response = Vec::new();
+        self.respond(body)?.read_to_end(&mut response)?;
+        Ok(response)
+    }
+
+    /// Send a complete request and return the connection to read the
+    /// response from as it comes
+    pub fn respond(mut self, mut body: impl Read) -> io::Result<TcpStream> {
+        io::copy(&mut body, &mut self.stream)?;
+        // The service reads to the end of the request, as over ssh
+        self.stream.shutdown(Shutdown::Write)?;
+        Ok(self.stream)
+    }
+}
+
+/// What the service has sent so far, read while the conversation goes on
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
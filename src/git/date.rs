@@ -0,0 +1,421 @@
+// Date parsing for GIT_AUTHOR_DATE, GIT_COMMITTER_DATE and mail headers
+// Every form comes back as git's internal `<epoch> <+hhmm>`. Accepted are:
+// - git's own `<epoch> <+hhmm>`, and `@<epoch>` with an optional zone; a
+//   bare number of nine digits or more is an epoch too, as git takes it
+// - RFC 2822, `Thu, 07 Apr 2005 22:13:13 +0200`
+// - ISO 8601, `2005-04-07T22:13:13+02:00` or `2005-04-07 22:13:13 +0200`,
+//   with optional seconds, fraction, zone ("Z" for UTC) or time of day
+// - relative dates: "now", "yesterday" and "<n> <unit>[s] ago" for seconds,
+//   minutes, hours, days, weeks, months and years ("3.days.ago" too)
+// A date without a zone is in local time.
+//
+// Dates are shown in the modes of `--date=<mode>`:
+//
+//   default       Thu Apr 7 15:13:13 2005 -0700
+//   iso           2005-04-07 15:13:13 -0700
+//   iso-strict    2005-04-07T15:13:13-07:00
+//   rfc           Thu, 7 Apr 2005 15:13:13 -0700
+//   short         2005-04-07
+//   raw           1112911993 -0700
+//   unix          1112911993
+//   relative      2 weeks ago
+//   format:<fmt>  strftime's <fmt>
+//
+// each in the zone the date was recorded in, or with "-local" after the
+// mode (or "local" alone for the default) in the local zone.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone,
+};
+use std::io;
+
+/// Digits a number needs before it is taken for an epoch without an `@`;
+/// git's cut-off, which leaves room for dates like 20050407
+const MIN_BARE_EPOCH_DIGITS: usize = 9;
+
+/// `text` as `<epoch> <+hhmm>`, or `None` if it isn't a date we know
+pub fn parse(text: &str) -> Option<String> {
+    let text = text.trim();
+    parse_raw(text)
+        .or_else(|| {
+            DateTime::parse_from_rfc2822(text)
+                .ok()
+                .map(|date| (date.timestamp(), date.offset().local_minus_utc() / 60))
+        })
+        .or_else(|| parse_iso8601(text))
+        .or_else(|| parse_relative(text))
+        .map(|(epoch, offset)| format!("{} {}", epoch, format_offset(offset)))
+}
+
+/// `<epoch> <+hhmm>`, `@<epoch>` or `@<epoch> <+hhmm>`, or an epoch alone
+/// if it is too long to be anything else
+fn parse_raw(text: &str) -> Option<(i64, i32)> {
+    let (at, text) = match text.strip_prefix('@') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (epoch, zone) = match text.split_once(' ') {
+        Some((epoch, zone)) => (epoch, Some(zone.trim())),
+        None if at || text.len() >= MIN_BARE_EPOCH_DIGITS => (text, None),
+        None => return None,
+    };
+    if epoch.is_empty() || !epoch.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let epoch: i64 = epoch.parse().ok()?;
+    let offset = match zone {
+        Some(zone) => parse_offset(zone)?,
+        None => local_offset(DateTime::from_timestamp(epoch, 0)?.naive_utc(), true)?,
+    };
+    Some((epoch, offset))
+}
+
+/// `YYYY-MM-DD`, then optionally `T` or a space and `HH:MM[:SS[.fff]]`,
+/// then optionally a zone
+fn parse_iso8601(text: &str) -> Option<(i64, i32)> {
+    let date_len = 10.min(text.len());
+    let date = NaiveDate::parse_from_str(text.get(..date_len)?, "%Y-%m-%d").ok()?;
+    let rest = &text[date_len..];
+    if rest.is_empty() {
+        let naive = date.and_hms_opt(0, 0, 0)?;
+        return Some((naive_epoch(naive, None)?, local_offset(naive, false)?));
+    }
+    let rest = rest.strip_prefix(['T', ' '])?;
+
+    // The zone starts at a sign or a "Z" after the time of day
+    let zone_at = rest
+        .find(|c: char| matches!(c, '+' | '-' | 'Z') || c.is_whitespace())
+        .unwrap_or(rest.len());
+    let (time, zone) = rest.split_at(zone_at);
+    let zone = zone.trim();
+    let time = time.split('.').next()?;
+    let naive = ["%H:%M:%S", "%H:%M"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(time, format).ok())
+        .map(|time| date.and_time(time))?;
+    let offset = match zone {
+        "" => None,
+        "Z" | "UTC" | "GMT" => Some(0),
+        zone => Some(parse_offset(zone)?),
+    };
+    let epoch = naive_epoch(naive, offset)?;
+    Some((epoch, offset.or_else(|| local_offset(naive, false))?))
+}
+
+/// "now", "yesterday" or "<n> <unit>[s] ago", relative to the current time
+fn parse_relative(text: &str) -> Option<(i64, i32)> {
+    let now = Local::now();
+    let text = text.to_ascii_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| c == '.' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let date = match words.as_slice() {
+        ["now"] => now,
+        ["yesterday"] => now - Duration::days(1),
+        [count, unit, "ago"] => {
+            let count: i64 = count.parse().ok()?;
+            let unit = unit.strip_suffix('s').unwrap_or(unit);
+            match unit {
+                "second" | "sec" => now - Duration::seconds(count),
+                "minute" | "min" => now - Duration::minutes(count),
+                "hour" => now - Duration::hours(count),
+                "day" => now - Duration::days(count),
+                "week" => now - Duration::weeks(count),
+                "month" => now.checked_sub_months(Months::new(u32::try_from(count).ok()?))?,
+                "year" => now
+                    .checked_sub_months(Months::new(u32::try_from(count).ok()?.checked_mul(12)?))?,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some((date.timestamp(), date.offset().local_minus_utc() / 60))
+}
+
+/// `+hhmm`, `+hh:mm` or `+hh` as minutes east of UTC
+fn parse_offset(zone: &str) -> Option<i32> {
+    let (sign, digits) = match zone.as_bytes().first()? {
+        b'+' => (1, &zone[1..]),
+        b'-' => (-1, &zone[1..]),
+        _ => return None,
+    };
+    let digits = digits.replace(':', "");
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// `+hhmm` for an offset in minutes east of UTC
+fn format_offset(minutes: i32) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    format!("{}{:02}{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+/// The epoch of wall-clock time `naive` in zone `offset`, local if `None`
+fn naive_epoch(naive: NaiveDateTime, offset: Option<i32>) -> Option<i64> {
+    match offset {
+        Some(minutes) => FixedOffset::east_opt(minutes * 60)?
+            .from_local_datetime(&naive)
+            .single()
+            .map(|date| date.timestamp()),
+        None => Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|date| date.timestamp()),
+    }
+}
+
+/// The local zone's offset in minutes at `naive`, which is UTC when `utc`
+/// is set and wall-clock time otherwise
+fn local_offset(naive: NaiveDateTime, utc: bool) -> Option<i32> {
+    let offset = if utc {
+        Local.offset_from_utc_datetime(&naive).fix()
+    } else {
+        Local.from_local_datetime(&naive).earliest()?.offset().fix()
+    };
+    Some(offset.local_minus_utc() / 60)
+}
+
+/// How a date is shown
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Style {
+    #[default]
+    Default,
+    Iso,
+    IsoStrict,
+    Rfc,
+    Short,
+    Raw,
+    Unix,
+    Relative,
+    /// A strftime format
+    Format(String),
+}
+
+/// A `--date=<mode>`: the style, and whether to show the date in the local
+/// zone rather than the one it was recorded in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mode {
+    pub style: Style,
+    pub local: bool,
+}
+
+impl Mode {
+    pub fn parse(mode: &str) -> io::Result<Mode> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown date format {}", mode),
+            )
+        };
+        if let Some(format) = mode.strip_prefix("format:") {
+            return Ok(Mode {
+                style: Style::Format(check_format(format).ok_or_else(invalid)?),
+                local: false,
+            });
+        }
+        if let Some(format) = mode.strip_prefix("format-local:") {
+            return Ok(Mode {
+                style: Style::Format(check_format(format).ok_or_else(invalid)?),
+                local: true,
+            });
+        }
+        if mode == "local" {
+            return Ok(Mode {
+                style: Style::Default,
+                local: true,
+            });
+        }
+        let (name, local) = match mode.strip_suffix("-local") {
+            Some(name) => (name, true),
+            None => (mode, false),
+        };
+        let style = match name {
+            "default" => Style::Default,
+            "iso" | "iso8601" => Style::Iso,
+            "iso-strict" | "iso8601-strict" => Style::IsoStrict,
+            "rfc" | "rfc2822" => Style::Rfc,
+            "short" => Style::Short,
+            "raw" => Style::Raw,
+            "unix" => Style::Unix,
+            "relative" => Style::Relative,
+            _ => return Err(invalid()),
+        };
+        Ok(Mode { style, local })
+    }
+}
+
+/// `format` if chrono can use it as a strftime format
+fn check_format(format: &str) -> Option<String> {
+    match StrftimeItems::new(format).any(|item| item == Item::Error) {
+        true => None,
+        false => Some(format.to_string()),
+    }
+}
+
+/// A `<epoch> <+hhmm>` timestamp shown in `mode`, or `None` if it is
+/// malformed
+pub fn format(timestamp: &str, mode: &Mode) -> Option<String> {
+    let (epoch, zone) = timestamp.split_once(' ')?;
+    let epoch: i64 = epoch.parse().ok()?;
+    let zone: i32 = zone.trim().parse().ok()?;
+    let utc = DateTime::from_timestamp(epoch, 0)?;
+    let offset = if mode.local {
+        Local.offset_from_utc_datetime(&utc.naive_utc()).fix()
+    } else {
+        FixedOffset::east_opt(zone.signum() * (zone.abs() / 100 * 3600 + zone.abs() % 100 * 60))?
+    };
+    let date = utc.with_timezone(&offset);
+    let text = match &mode.style {
+        // The local zone goes without saying
+        Style::Default if mode.local => date.format("%a %b %-d %H:%M:%S %Y").to_string(),
+        Style::Default => date.format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+        Style::Iso => date.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        Style::IsoStrict => date.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        Style::Rfc => date.format("%a, %-d %b %Y %H:%M:%S %z").to_string(),
+        Style::Short => date.format("%Y-%m-%d").to_string(),
+        Style::Raw => date.format("%s %z").to_string(),
+        Style::Unix => epoch.to_string(),
+        Style::Relative => relative(Local::now().timestamp() - epoch),
+        Style::Format(format) => date.format(format).to_string(),
+    };
+    Some(text)
+}
+
+/// "<n> <units> ago" for a date `seconds` in the past, rounded as git
+/// rounds it
+fn relative(seconds: i64) -> String {
+    let ago = |count: i64, unit: &str| {
+        let plural = if count == 1 { "" } else { "s" };
+        format!("{} {}{} ago", count, unit, plural)
+    };
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+    if seconds < 90 {
+        return ago(seconds, "second");
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return ago(minutes, "minute");
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return ago(hours, "hour");
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return ago(days, "day");
+    }
+    if days < 70 {
+        return ago((days + 3) / 7, "week");
+    }
+    if days < 365 {
+        return ago((days + 15) / 30, "month");
+    }
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months == 0 {
+            return ago(years, "year");
+        }
+        let plural = if years == 1 { "" } else { "s" };
+        return format!("{} year{}, {}", years, plural, ago(months, "month"));
+    }
+    ago((days + 183) / 365, "year")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The epoch `text` parses to, whatever the local zone
+    fn epoch(text: &str) -> Option<i64> {
+        parse(text)?.split_once(' ')?.0.parse().ok()
+    }
+
+    #[test]
+    fn parses_raw_epochs() {
+        assert_eq!(
+            parse("1700000000 +0000").as_deref(),
+            Some("1700000000 +0000")
+        );
+        assert_eq!(
+            parse("1700000000 -0130").as_deref(),
+            Some("1700000000 -0130")
+        );
+        assert_eq!(
+            parse(" @1700000000 +02:00 ").as_deref(),
+            Some("1700000000 +0200")
+        );
+        assert_eq!(epoch("@1700000000"), Some(1700000000));
+        assert_eq!(epoch("@0"), Some(0));
+        assert_eq!(epoch("1700000000"), Some(1700000000));
+        // Too short to be taken for an epoch without the @
+        assert_eq!(parse("17000000"), None);
+        assert_eq!(parse("1700000000 +2"), None);
+        assert_eq!(parse("@17x0000000"), None);
+    }
+
+    #[test]
+    fn parses_rfc2822_and_iso8601() {
+        assert_eq!(
+            parse("Thu, 07 Apr 2005 22:13:13 +0200").as_deref(),
+            Some("1112904793 +0200")
+        );
+        assert_eq!(
+            parse("2005-04-07T22:13:13+02:00").as_deref(),
+            Some("1112904793 +0200")
+        );
+        assert_eq!(
+            parse("2005-04-07 22:13:13.25 +0200").as_deref(),
+            Some("1112904793 +0200")
+        );
+        assert_eq!(
+            parse("2005-04-07T20:13Z").as_deref(),
+            Some("1112904780 +0000")
+        );
+        assert_eq!(parse("2005-13-07"), None);
+        assert_eq!(parse("2005-04-07 25:00 +0000"), None);
+        assert_eq!(parse("not a date"), None);
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        let now = Local::now().timestamp();
+        let ago = |text: &str| now - epoch(text).unwrap();
+        assert!(ago("now").abs() < 5);
+        assert!((ago("3.days.ago") - 3 * 86400).abs() < 5);
+        assert!((ago("1 hour ago") - 3600).abs() < 5);
+        assert_eq!(parse("3 fortnights ago"), None);
+    }
+
+    #[test]
+    fn formats_in_each_mode() {
+        let shown = |mode: &str| format("1112911993 -0700", &Mode::parse(mode).unwrap()).unwrap();
+        assert_eq!(shown("default"), "Thu Apr 7 15:13:13 2005 -0700");
+        assert_eq!(shown("iso"), "2005-04-07 15:13:13 -0700");
+        assert_eq!(shown("iso-strict"), "2005-04-07T15:13:13-07:00");
+        assert_eq!(shown("rfc"), "Thu, 7 Apr 2005 15:13:13 -0700");
+        assert_eq!(shown("short"), "2005-04-07");
+        assert_eq!(shown("raw"), "1112911993 -0700");
+        assert_eq!(shown("unix"), "1112911993");
+        assert_eq!(shown("format:%Y/%m"), "2005/04");
+        assert!(Mode::parse("sometime").is_err());
+        assert_eq!(relative(60), "60 seconds ago");
+        assert_eq!(relative(3 * 86400), "3 days ago");
+        assert_eq!(relative(400 * 86400), "1 year, 1 month ago");
+    }
+}
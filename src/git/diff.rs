@@ -0,0 +1,371 @@
+// Line diffing
+// Myers' O(ND) algorithm ("An O(ND) Difference Algorithm and Its
+// Variations", 1986): find the shortest edit script between two sequences
+// by exploring diagonals, then backtrack through the saved frontiers. The
+// edit script is rendered as unified diff hunks for `diff` and friends.
+
+/// Split content into lines, each keeping its trailing `\n` (the last line
+/// may lack one)
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == b'\n' {
+            lines.push(&data[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < data.len() {
+        lines.push(&data[start..]);
+    }
+    lines
+}
+
+/// Git's binary heuristic: a NUL byte within the first 8000 bytes
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+/// Index pairs `(old, new)` of elements kept unchanged by a shortest edit
+/// script from `old` to `new`, in increasing order
+pub fn matching_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    // Trim the common prefix and suffix first: cheap, and it keeps the
+    // quadratic worst case confined to the part that actually changed
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    pairs.extend(
+        myers(old_mid, new_mid)
+            .into_iter()
+            .map(|(a, b)| (a + prefix, b + prefix)),
+    );
+    pairs.extend((0..suffix).map(|i| (old.len() - suffix + i, new.len() - suffix + i)));
+    pairs
+}
+
+fn myers<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = n + m;
+    if max == 0 {
+        return Vec::new();
+    }
+
+    // v[k + offset] = furthest x reached on diagonal k
+    let offset = max as usize;
+    let mut v = vec![0isize; 2 * offset + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let idx = (k + offset as isize) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // Backtrack from (n, m) through each saved frontier, collecting snakes
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = |k: isize| (k + offset as isize) as usize;
+        let prev_k = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[idx(prev_k)];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            x = prev_x;
+            y = prev_y;
+        }
+    }
+
+    // The d = 0 frontier leaves the initial snake from (0, 0)
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        pairs.push((x as usize, y as usize));
+    }
+
+    pairs.reverse();
+    pairs
+}
+
+/// One step of an edit script, by line index (a kept line by its old index)
+enum Edit {
+    Keep(usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Deletions come before insertions within each changed region, as in
+/// git's output
+fn edit_script<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut old_changed = vec![true; old.len()];
+    let mut new_changed = vec![true; new.len()];
+    for (a, b) in matching_lines(old, new) {
+        old_changed[a] = false;
+        new_changed[b] = false;
+    }
+    compact(old, &mut old_changed, &new_changed);
+    compact(new, &mut new_changed, &old_changed);
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && old_changed[i] {
+            edits.push(Edit::Delete(i));
+            i += 1;
+        } else if j < new.len() && new_changed[j] {
+            edits.push(Edit::Insert(j));
+            j += 1;
+        } else {
+            edits.push(Edit::Keep(i));
+            i += 1;
+            j += 1;
+        }
+    }
+    edits
+}
+
+/// A run `start..end` of changed lines, possibly empty
+#[derive(Clone, Copy)]
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+/// Slide each group of changed lines of `lines` to a canonical place, as
+/// git's xdl_change_compact does: as far down as it will go, unless it can
+/// be lined up with a change on the other side, whose groups are followed
+/// in step.
+fn compact<T: PartialEq>(lines: &[T], changed: &mut [bool], other: &[bool]) {
+    let is_changed = |flags: &[bool], i: usize| flags.get(i).copied().unwrap_or(false);
+    let first = |flags: &[bool]| {
+        let end = (0..).find(|&i| !is_changed(flags, i)).unwrap_or(0);
+        Group { start: 0, end }
+    };
+    let next = |flags: &[bool], g: &mut Group| -> bool {
+        if g.end >= flags.len() {
+            return false;
+        }
+        g.start = g.end + 1;
+        g.end = g.start;
+        while is_changed(flags, g.end) {
+            g.end += 1;
+        }
+        true
+    };
+    let previous = |flags: &[bool], g: &mut Group| -> bool {
+        if g.start == 0 {
+            return false;
+        }
+        g.end = g.start - 1;
+        g.start = g.end;
+        while g.start > 0 && is_changed(flags, g.start - 1) {
+            g.start -= 1;
+        }
+        true
+    };
+    let slide_down = |flags: &mut [bool], g: &mut Group| -> bool {
+        if g.end < lines.len() && lines[g.start] == lines[g.end] {
+            flags[g.start] = false;
+            flags[g.end] = true;
+            g.start += 1;
+            g.end += 1;
+            while is_changed(flags, g.end) {
+                g.end += 1;
+            }
+            return true;
+        }
+        false
+    };
+    let slide_up = |flags: &mut [bool], g: &mut Group| -> bool {
+        if g.start > 0 && lines[g.start - 1] == lines[g.end - 1] {
+            g.start -= 1;
+            g.end -= 1;
+            flags[g.start] = true;
+            flags[g.end] = false;
+            while g.start > 0 && is_changed(flags, g.start - 1) {
+                g.start -= 1;
+            }
+            return true;
+        }
+        false
+    };
+
+    let mut g = first(changed);
+    let mut go = first(other);
+    loop {
+        if g.end != g.start {
+            // Sliding may merge the group with its neighbours, so repeat
+            // until its size settles
+            let mut earliest_end;
+            let mut end_matching_other;
+            loop {
+                let size = g.end - g.start;
+                end_matching_other = None;
+                while slide_up(changed, &mut g) {
+                    previous(other, &mut go);
+                }
+                earliest_end = g.end;
+                if go.end > go.start {
+                    end_matching_other = Some(g.end);
+                }
+                while slide_down(changed, &mut g) {
+                    next(other, &mut go);
+                    if go.end > go.start {
+                        end_matching_other = Some(g.end);
+                    }
+                }
+                if size == g.end - g.start {
+                    break;
+                }
+            }
+
+            if g.end != earliest_end && end_matching_other.is_some() {
+                while go.end == go.start {
+                    slide_up(changed, &mut g);
+                    previous(other, &mut go);
+                }
+            }
+        }
+        if !next(changed, &mut g) {
+            break;
+        }
+        next(other, &mut go);
+    }
+}
+
+/// The hunks of a unified diff from `old` to `new`, each introduced by an
+/// `@@ -start,count +start,count @@` line, with `context` unchanged lines
+/// kept around every change. Empty when the contents are equal.
+pub fn unified(old: &[u8], new: &[u8], context: usize) -> Vec<u8> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let edits = edit_script(&old_lines, &new_lines);
+
+    // Lines of each side consumed before edit k
+    let mut old_pos = vec![0; edits.len() + 1];
+    let mut new_pos = vec![0; edits.len() + 1];
+    for (k, edit) in edits.iter().enumerate() {
+        let (a, b) = match edit {
+            Edit::Keep(_) => (1, 1),
+            Edit::Delete(_) => (1, 0),
+            Edit::Insert(_) => (0, 1),
+        };
+        old_pos[k + 1] = old_pos[k] + a;
+        new_pos[k + 1] = new_pos[k] + b;
+    }
+
+    // Changes closer together than twice the context share a hunk
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&k| !matches!(edits[k], Edit::Keep(_)))
+        .collect();
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for &k in &changes {
+        match groups.last_mut() {
+            Some((_, last)) if k - *last <= 2 * context + 1 => *last = k,
+            _ => groups.push((k, k)),
+        }
+    }
+
+    let mut out = Vec::new();
+    for (first, last) in groups {
+        let start = first.saturating_sub(context);
+        let end = (last + context + 1).min(edits.len());
+        let (old_start, old_len) = (old_pos[start], old_pos[end] - old_pos[start]);
+        let (new_start, new_len) = (new_pos[start], new_pos[end] - new_pos[start]);
+
+        out.extend_from_slice(
+            format!(
+                "@@ -{} +{} @@",
+                hunk_range(old_start, old_len),
+                hunk_range(new_start, new_len)
+            )
+            .as_bytes(),
+        );
+        if let Some(func) = old_lines[..old_start]
+            .iter()
+            .rev()
+            .find_map(|l| function_line(l))
+        {
+            out.push(b' ');
+            out.extend_from_slice(func);
+        }
+        out.push(b'\n');
+
+        for edit in &edits[start..end] {
+            let (sign, line) = match *edit {
+                Edit::Keep(a) => (b' ', old_lines[a]),
+                Edit::Delete(a) => (b'-', old_lines[a]),
+                Edit::Insert(b) => (b'+', new_lines[b]),
+            };
+            out.push(sign);
+            out.extend_from_slice(line);
+            if !line.ends_with(b"\n") {
+                out.extend_from_slice(b"\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// `start,count` of a hunk header: a single line omits the count, and an
+/// empty range names the line before it
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Git's default funcname rule: a line starting with a letter, `_` or `$`,
+/// cut to 80 bytes, is shown after the hunk header it precedes
+fn function_line(line: &[u8]) -> Option<&[u8]> {
+    match line.first() {
+        Some(&c) if c.is_ascii_alphabetic() || c == b'_' || c == b'$' => {
+            Some(line[..line.len().min(80)].trim_ascii_end())
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,24 @@
+// Dry runs
+// A command given --dry-run works out what it would do and says so, but
+// leaves the repository as it was. Rather than each command remembering to
+// skip each of its writes, the functions that change the repository's state
+// check here first and, once `enable` has been called, return without
+// touching the disk: ref updates and deletions (packed-refs included), the
+// reflogs, the index, config files and FETCH_HEAD. Objects are still
+// written. They are only ever added, change nothing until a ref points at
+// them, and a dry-run fetch needs the ones it downloads to work out which
+// of its ref updates would be fast-forwards.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turn every gated write of this process into a no-op from now on
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether writes to the repository's state are to be skipped
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,277 @@
+// Dumb HTTP transport
+// A server that doesn't run git's smart HTTP service can still serve a
+// repository as plain files, once `update-server-info` has written the
+// lists a client needs: info/refs has a "<sha>\t<name>" line for each ref
+// (and its peeled value, for a tag), and objects/info/packs a "P <name>"
+// line for each pack. HEAD is read as the file it is.
+//
+// Fetching walks from the wanted objects through parents, trees and tag
+// targets, stopping at what is already here. Each missing object is asked
+// for as a loose object first; failing that, the index of each listed pack
+// is fetched (once) until one has the object, and that whole pack is
+// downloaded and kept, index and all, which brings in everything else it
+// holds too. A pack download that breaks off is picked up where it stopped
+// with a Range request, as many times as http.maxRetries allows; a fetch
+// that fails anyway leaves what it got, so running it again goes on from
+// there.
+
+use crate::git::http::{self, HttpClient};
+use crate::git::progress::Progress;
+use crate::git::tree::MODE_GITLINK;
+use crate::git::url::RemoteUrl;
+use crate::git::{commit, object, repository, tag, tree};
+use flate2::read::ZlibDecoder;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::thread;
+
+/// The refs listed by an info/refs file, as `(name, sha)`
+pub fn parse_info_refs(body: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(sha, name)| (name.to_string(), sha.to_string()))
+        .collect()
+}
+
+/// What the remote's HEAD file holds: "ref: <name>" for a symbolic ref, else
+/// a sha; `None` when there is no HEAD to read
+pub fn read_head(client: &HttpClient, remote: &RemoteUrl) -> io::Result<Option<String>> {
+    Ok(get(client, &remote.http_endpoint("HEAD"))?
+        .map(|body| String::from_utf8_lossy(&body).trim().to_string()))
+}
+
+/// Bring in `wants` and everything they reference that isn't here yet
+pub fn fetch(
+    client: &HttpClient,
+    remote: &RemoteUrl,
+    wants: &[String],
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let mut walker = Walker {
+        client,
+        remote,
+        packs: None,
+        indexes: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut stack = wants.to_vec();
+    while let Some(sha) = stack.pop() {
+        if !seen.insert(sha.clone()) {
+            continue;
+        }
+        progress.check()?;
+        if object::locate(&sha)?.is_none() {
+            walker.fetch_object(&sha, progress)?;
+        }
+        let (kind, content) = object::read(&sha)?;
+        match kind.as_str() {
+            "commit" => {
+                let commit = commit::parse(&content)?;
+                stack.extend(commit.parents);
+                stack.push(commit.tree);
+            }
+            "tree" => {
+                for entry in tree::parse(&content)? {
+                    // Submodule commits live in another repository
+                    if entry.mode != MODE_GITLINK {
+                        stack.push(entry.sha);
+                    }
+                }
+            }
+            "tag" => stack.push(tag::parse(&content)?.object),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+struct Walker<'a> {
+    client: &'a HttpClient,
+    remote: &'a RemoteUrl,
+    /// The packs objects/info/packs lists and that haven't been looked in
+    /// yet, once it has been read
+    packs: Option<Vec<String>>,
+    /// The indexes fetched so far, by pack name, of packs not downloaded
+    indexes: Vec<(String, Vec<u8>)>,
+}
+
+impl Walker<'_> {
+    fn fetch_object(&mut self, sha: &str, progress: &mut Progress) -> io::Result<()> {
+        let loose = format!("objects/{}/{}", &sha[..2], &sha[2..]);
+        if let Some(data) = get(self.client, &self.remote.http_endpoint(&loose))? {
+            return store_loose(sha, &data);
+        }
+        let Some(pack) = self.pack_with(sha)? else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Unable to find {} under {}\nCannot obtain needed object {}",
+                    sha, self.remote, sha
+                ),
+            ));
+        };
+        progress.report(&format!("Getting pack {}", pack));
+        let position = self.indexes.iter().position(|(name, _)| *name == pack);
+        let (_, index) = self.indexes.remove(position.unwrap_or_default());
+        let path = format!("objects/pack/{}.pack", pack);
+        let url = self.remote.http_endpoint(&path);
+        let data = get_resumable(self.client, &url, progress)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Unable to get pack {}", pack),
+            )
+        })?;
+
+        let dir = &repository::path("objects/pack");
+        fs::create_dir_all(dir)?;
+        let pack_path = dir.join(format!("{}.pack", pack));
+        // The index goes last: a pack is only used once its index exists
+        for (path, content) in [
+            (pack_path.clone(), data.as_slice()),
+            (pack_path.with_extension("idx"), index.as_slice()),
+        ] {
+            let temp = path.with_extension("tmp");
+            fs::write(&temp, content)?;
+            fs::rename(&temp, &path)?;
+        }
+        Ok(())
+    }
+
+    /// The name of a pack on the remote whose index lists `sha`
+    fn pack_with(&mut self, sha: &str) -> io::Result<Option<String>> {
+        let wanted = hex::decode(sha).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid object id {}", sha),
+            )
+        })?;
+        if let Some((name, _)) = self.indexes.iter().find(|(_, idx)| lists(idx, &wanted)) {
+            return Ok(Some(name.clone()));
+        }
+        if self.packs.is_none() {
+            let list = get(
+                self.client,
+                &self.remote.http_endpoint("objects/info/packs"),
+            )?;
+            let list = String::from_utf8_lossy(list.as_deref().unwrap_or_default()).into_owned();
+            self.packs = Some(
+                list.lines()
+                    .filter_map(|line| line.strip_prefix("P "))
+                    .filter_map(|name| name.trim().strip_suffix(".pack"))
+                    .map(str::to_string)
+                    .collect(),
+            );
+        }
+        while let Some(name) = self.packs.as_mut().and_then(Vec::pop) {
+            let path = format!("objects/pack/{}.idx", name);
+            let Some(index) = get(self.client, &self.remote.http_endpoint(&path))? else {
+                continue;
+            };
+            let found = lists(&index, &wanted);
+            self.indexes.push((name.clone(), index));
+            if found {
+                return Ok(Some(name));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Whether the pack index `idx` (version 1 or 2) lists the object `sha`
+fn lists(idx: &[u8], sha: &[u8]) -> bool {
+    let (fanout, names, stride) = match idx.strip_prefix(b"\xfftOc") {
+        Some(_) => (8, 8 + 256 * 4, 20),
+        None => (0, 256 * 4 + 4, 24),
+    };
+    let Some(count) = idx
+        .get(fanout + 255 * 4..fanout + 256 * 4)
+        .map(|n| u32::from_be_bytes([n[0], n[1], n[2], n[3]]) as usize)
+    else {
+        return false;
+    };
+    (0..count).any(|i| idx.get(names + i * stride..names + i * stride + 20) == Some(sha))
+}
+
+/// Keep the loose object `data` as `sha`, once it is known to be that object
+fn store_loose(sha: &str, data: &[u8]) -> io::Result<()> {
+    let mut raw = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut raw)?;
+    let corrupt = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("object {} is corrupt", sha),
+        )
+    };
+    let nul = raw.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+    let header = String::from_utf8_lossy(&raw[..nul]).into_owned();
+    let kind = header.split(' ').next().unwrap_or_default();
+    if object::write(kind, &raw[nul + 1..])? != sha {
+        return Err(corrupt());
+    }
+    Ok(())
+}
+
+/// The body at `url` as `get` returns it, a download that breaks off being
+/// asked for again from where it stopped. A server that answers with the
+/// whole file instead of the range has it started over.
+fn get_resumable(
+    client: &HttpClient,
+    url: &str,
+    progress: &mut Progress,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    let mut attempt = 0;
+    loop {
+        let mut request = client.get(url);
+        if !data.is_empty() {
+            progress.report(&format!("Resuming {} at byte {}", url, data.len()));
+            request = request.header(RANGE, format!("bytes={}-", data.len()));
+        }
+        let mut response = client
+            .send(request)
+            .map_err(|e| io::Error::other(format!("Error fetching {}: {}", url, e)))?;
+        match response.status() {
+            StatusCode::NOT_FOUND if data.is_empty() => return Ok(None),
+            StatusCode::PARTIAL_CONTENT if !data.is_empty() => {}
+            status if status.is_success() => data.clear(),
+            status => {
+                return Err(io::Error::other(format!(
+                    "Failed to fetch {}: {}",
+                    url, status
+                )))
+            }
+        }
+        // What was read before a failure is kept in `data`
+        match response.read_to_end(&mut data) {
+            Ok(_) => return Ok(Some(data)),
+            Err(e) if attempt < client.max_retries() && http::is_transient(&e) => {
+                thread::sleep(http::backoff(attempt));
+                progress.check()?;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The body at `url`; `None` if the server has nothing there
+fn get(client: &HttpClient, url: &str) -> io::Result<Option<Vec<u8>>> {
+    let response = client
+        .send(client.get(url))
+        .map_err(|e| io::Error::other(format!("Error fetching {}: {}", url, e)))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(io::Error::other(format!(
+            "Failed to fetch {}: {}",
+            url,
+            response.status()
+        )));
+    }
+    Ok(Some(response.bytes().map_err(io::Error::other)?.to_vec()))
+}
@@ -0,0 +1,69 @@
+// Character encodings of commit messages
+// Commit text is UTF-8 unless the commit has an "encoding" header naming
+// another charset, as git writes when i18n.commitEncoding is set to one:
+//
+// tree ...
+// author ...
+// committer ...
+// encoding ISO-8859-1
+//
+// <message in ISO-8859-1>
+//
+// Messages are decoded to UTF-8 when read, and turned into
+// i18n.logOutputEncoding (by default the commit encoding, else UTF-8) when
+// shown.
+
+use crate::git::config::Config;
+use encoding_rs::Encoding;
+use std::io;
+
+/// Whether `label` names UTF-8, which commits don't declare
+pub fn is_utf8(label: &str) -> bool {
+    Encoding::for_label(label.trim().as_bytes()) == Some(encoding_rs::UTF_8)
+}
+
+/// `bytes` in the charset `label` as UTF-8. Unknown charsets and invalid
+/// sequences are read as (lossy) UTF-8.
+pub fn decode(bytes: &[u8], label: Option<&str>) -> String {
+    match label.and_then(|label| Encoding::for_label(label.trim().as_bytes())) {
+        Some(encoding) => encoding.decode_without_bom_handling(bytes).0.into_owned(),
+        None => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// `text` in the charset `label`; characters it lacks become numeric
+/// character references. A charset that can only be read (UTF-16) or isn't
+/// known gives UTF-8.
+pub fn encode(text: &str, label: &str) -> Vec<u8> {
+    match Encoding::for_label(label.trim().as_bytes()) {
+        Some(encoding) => encoding.encode(text).0.into_owned(),
+        None => text.as_bytes().to_vec(),
+    }
+}
+
+/// The charset new commit messages are recorded in, if not UTF-8. One
+/// that can't be written leaves them in UTF-8.
+pub fn commit_encoding(config: &Config) -> Option<String> {
+    let label = config.get("i18n.commitEncoding")?;
+    match Encoding::for_label(label.trim().as_bytes()) {
+        Some(encoding) if encoding.output_encoding() == encoding => {
+            Some(label.to_string()).filter(|label| !is_utf8(label))
+        }
+        _ => None,
+    }
+}
+
+/// The charset messages are shown in
+pub fn log_output_encoding(config: &Config) -> String {
+    config
+        .get("i18n.logOutputEncoding")
+        .or_else(|| config.get("i18n.commitEncoding"))
+        .unwrap_or("UTF-8")
+        .to_string()
+}
+
+/// `text` as it should be written to the terminal
+pub fn for_output(text: &str) -> io::Result<Vec<u8>> {
+    let label = log_output_encoding(&Config::load()?);
+    Ok(encode(text, &label))
+}
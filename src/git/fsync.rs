@@ -0,0 +1,251 @@
+// Durability of written files: core.fsync and core.fsyncMethod
+// core.fsync lists the components whose files are flushed to disk before
+// the write counts as done: loose-object, pack, pack-metadata, commit-graph,
+// index and reference, or the aggregates objects (loose-object,pack),
+// derived-metadata (pack-metadata,commit-graph), committed (objects,
+// reference), added (committed,index) and all. The list adds to git's
+// default of pack,pack-metadata,commit-graph; "-name" takes a component
+// away and "none" starts over from nothing.
+//
+// core.fsyncMethod picks how: "fsync" (the default) flushes each file as it
+// is written, "writeout-only" only hands the data to the OS, and "batch"
+// writes loose objects out without waiting and flushes them all at once,
+// before the next ref or index update that could point at them, or when
+// the command ends.
+
+use crate::git::config::Config;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    LooseObject,
+    Pack,
+    PackMetadata,
+    CommitGraph,
+    Index,
+    Reference,
+}
+
+impl Component {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+const OBJECTS: u32 = 1 << Component::LooseObject as u32 | 1 << Component::Pack as u32;
+const DERIVED_METADATA: u32 =
+    1 << Component::PackMetadata as u32 | 1 << Component::CommitGraph as u32;
+const COMMITTED: u32 = OBJECTS | 1 << Component::Reference as u32;
+const ADDED: u32 = COMMITTED | 1 << Component::Index as u32;
+const ALL: u32 = ADDED | DERIVED_METADATA;
+const DEFAULT: u32 = (OBJECTS | DERIVED_METADATA) & !(1 << Component::LooseObject as u32);
+
+const COMPONENT_NAMES: &[(&str, u32)] = &[
+    ("loose-object", 1 << Component::LooseObject as u32),
+    ("pack", 1 << Component::Pack as u32),
+    ("pack-metadata", 1 << Component::PackMetadata as u32),
+    ("commit-graph", 1 << Component::CommitGraph as u32),
+    ("index", 1 << Component::Index as u32),
+    ("reference", 1 << Component::Reference as u32),
+    ("objects", OBJECTS),
+    ("derived-metadata", DERIVED_METADATA),
+    ("committed", COMMITTED),
+    ("added", ADDED),
+    ("all", ALL),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    Fsync,
+    WriteoutOnly,
+    Batch,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    components: u32,
+    method: Method,
+}
+
+/// Settings as read for a repository, and loose objects awaiting the
+/// batch flush
+struct State {
+    repository: Option<PathBuf>,
+    settings: Settings,
+    pending: Vec<PathBuf>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    repository: None,
+    settings: Settings {
+        components: DEFAULT,
+        method: Method::Fsync,
+    },
+    pending: Vec::new(),
+});
+
+/// Write `data` to `path` like `fs::write`, flushing it to disk if
+/// core.fsync asks for `component`
+pub fn write(path: &Path, data: &[u8], component: Component) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(data)?;
+    sync(&file, path, component)
+}
+
+/// Flush the just-written `file` at `path` as core.fsync asks for
+/// `component`
+pub fn sync(file: &File, path: &Path, component: Component) -> io::Result<()> {
+    let mut state = lock_state();
+    let settings = state.settings()?;
+    if component != Component::LooseObject {
+        // Whatever this points at must be on disk first
+        state.flush_pending()?;
+    }
+    if settings.components & component.bit() == 0 {
+        return Ok(());
+    }
+    match settings.method {
+        Method::Fsync => file.sync_all(),
+        Method::WriteoutOnly => writeout(file),
+        Method::Batch if component == Component::LooseObject => {
+            writeout(file)?;
+            state.pending.push(path.to_path_buf());
+            Ok(())
+        }
+        Method::Batch => file.sync_all(),
+    }
+}
+
+/// Flush the loose objects a batch has written so far
+pub fn flush_batch() -> io::Result<()> {
+    lock_state().flush_pending()
+}
+
+fn lock_state() -> std::sync::MutexGuard<'static, State> {
+    STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl State {
+    /// The settings of the repository in the current directory, read again
+    /// when that changes (as it does during a clone)
+    fn settings(&mut self) -> io::Result<Settings> {
+        let repository = std::env::current_dir()?;
+        if self.repository.as_ref() != Some(&repository) {
+            self.flush_pending()?;
+            self.settings = read_settings(&Config::load()?)?;
+            self.repository = Some(repository);
+        }
+        Ok(self.settings)
+    }
+
+    fn flush_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        flush_all(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+fn read_settings(config: &Config) -> io::Result<Settings> {
+    let mut components = DEFAULT;
+    // The older switch for loose objects alone
+    if config.get_bool("core.fsyncObjectFiles")? == Some(true) {
+        components |= Component::LooseObject.bit();
+    }
+    if let Some(value) = config.get("core.fsync") {
+        components = parse_components(value, components);
+    }
+
+    let method = match config.get("core.fsyncMethod") {
+        None | Some("fsync") => Method::Fsync,
+        Some("writeout-only") => Method::WriteoutOnly,
+        Some("batch") => Method::Batch,
+        Some(other) => {
+            eprintln!(
+                "warning: ignoring unknown core.fsyncMethod value '{}'",
+                other
+            );
+            Method::Fsync
+        }
+    };
+    Ok(Settings { components, method })
+}
+
+/// Apply each entry of a core.fsync list in turn to `default`
+fn parse_components(value: &str, default: u32) -> u32 {
+    let mut components = default;
+    for name in value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if name == "none" {
+            components = 0;
+            continue;
+        }
+        let (negated, name) = match name.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, name),
+        };
+        match COMPONENT_NAMES.iter().find(|(known, _)| *known == name) {
+            Some((_, bits)) if negated => components &= !bits,
+            Some((_, bits)) => components |= bits,
+            None => eprintln!("warning: ignoring unknown core.fsync component '{}'", name),
+        }
+    }
+    components
+}
+
+/// Start writing the file's data out without waiting for the disk
+fn writeout(file: &File) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        let flags = libc::SYNC_FILE_RANGE_WRITE;
+        if unsafe { libc::sync_file_range(file.as_raw_fd(), 0, 0, flags) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        file.sync_data()
+    }
+}
+
+/// Flush the files at `paths` to disk: one `syncfs` of their filesystem
+/// where there is one, else each in turn
+fn flush_all(paths: &[PathBuf]) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        // Any of them will do; all may be gone if a failed clone cleaned up
+        let file = match paths.iter().find_map(|path| File::open(path).ok()) {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        if unsafe { libc::syncfs(file.as_raw_fd()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        for path in paths {
+            match File::open(path) {
+                Ok(file) => file.sync_all()?,
+                // Removed since, e.g. a failed clone cleaning up
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+// Entry points for the fuzz targets under fuzz/, built with the `strict`
+// feature
+// Each hands arbitrary bytes to one of the parsers of data that comes from
+// elsewhere. Whatever the input, the parser has to return, with an error if
+// the data is malformed; a panic, an overflow or a runaway allocation found
+// here is a bug.
+
+use crate::git::pack::{self, Limits, Pack};
+use crate::git::{commit, tree};
+use sha1_smol::Sha1;
+use std::io;
+
+/// `data` as a pack received from a remote. Its trailing checksum is
+/// replaced by the right one, or hardly any input would get past it.
+pub fn pack(data: &[u8]) -> io::Result<()> {
+    let mut data = data.to_vec();
+    if data.len() >= 20 {
+        let body = data.len() - 20;
+        let checksum = Sha1::from(&data[..body]).digest().bytes();
+        data[body..].copy_from_slice(&checksum);
+    }
+    let pack = Pack::from_bytes(data, &Limits::default())?;
+    for (_, offset) in pack.entries() {
+        pack.entry(*offset)?;
+    }
+    Ok(())
+}
+
+/// `data` as a base object, a NUL, and a delta against it
+pub fn delta(data: &[u8]) -> io::Result<()> {
+    let split = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    let (base, delta) = data.split_at(split);
+    pack::apply_delta(base, delta.get(1..).unwrap_or_default()).map(drop)
+}
+
+/// `data` as the content of a tree object
+pub fn tree(data: &[u8]) -> io::Result<()> {
+    tree::parse(data)?;
+    Ok(())
+}
+
+/// `data` as the content of a commit object
+pub fn commit(data: &[u8]) -> io::Result<()> {
+    commit::parse(data)?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+// Repository hooks
+// Executables in `.git/hooks/` run at fixed points of a command; a hook that
+// exits non-zero stops the command. Hooks that are missing or not executable
+// are skipped, so the `.sample` files written by init are inert.
+
+use crate::git::config::Config;
+use crate::git::conventional;
+use crate::git::repository;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+pub const HOOKS_DIR: &str = "hooks";
+pub const COMMIT_EDITMSG: &str = ".git/COMMIT_EDITMSG";
+
+/// Example `commit-msg` hook enforcing Conventional Commits subjects
+pub const COMMIT_MSG_SAMPLE: &str = r#"#!/bin/sh
+#
+# An example hook script to check that the commit message follows the
+# Conventional Commits format: "type(scope)!: description".
+# It is called with one argument, the file holding the proposed message,
+# and stops the commit by exiting non-zero.
+#
+# To enable this hook, rename this file to "commit-msg". The same check is
+# also built in: set commit.conventional = true instead.
+
+subject=$(sed -n '/^[^#]/{p;q;}' "$1")
+
+case "$subject" in
+"Merge "*|"Revert \""*|"fixup! "*|"squash! "*|"amend! "*)
+	exit 0
+	;;
+esac
+
+types='build|chore|ci|docs|feat|fix|perf|refactor|revert|style|test'
+if ! printf '%s\n' "$subject" | grep -Eq "^($types)(\([^()]+\))?!?: [^ ]"; then
+	echo >&2 "commit-msg: subject does not follow Conventional Commits:"
+	echo >&2 "    $subject"
+	exit 1
+fi
+"#;
+
+/// Run hook `name` with `args`. Returns whether the hook was installed;
+/// a hook that fails is an error.
+pub fn run(name: &str, args: &[&str]) -> io::Result<bool> {
+    let path = repository::path(HOOKS_DIR).join(name);
+    if !is_executable(&path) {
+        return Ok(false);
+    }
+
+    let status = Command::new(&path)
+        .args(args)
+        .status()
+        .map_err(|e| io::Error::other(format!("cannot run {} hook: {}", name, e)))?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{} hook exited with {}",
+            name, status
+        )));
+    }
+    Ok(true)
+}
+
+/// Vet the message of a commit about to be created by a porcelain command:
+/// the `commit-msg` hook sees it in COMMIT_EDITMSG and may rewrite it, then
+/// the built-in Conventional Commits check applies if `commit.conventional`
+/// is on. Returns the message to commit.
+pub fn check_commit_message(message: &str) -> io::Result<String> {
+    fs::write(COMMIT_EDITMSG, message)?;
+    let message = if run("commit-msg", &[COMMIT_EDITMSG])? {
+        fs::read_to_string(COMMIT_EDITMSG)?
+    } else {
+        message.to_string()
+    };
+
+    let config = Config::load()?;
+    if config.get_bool("commit.conventional")?.unwrap_or(false) {
+        let configured = config.get("commit.conventionalTypes");
+        let types: Vec<&str> = match configured {
+            Some(list) => list.split([',', ' ']).filter(|t| !t.is_empty()).collect(),
+            None => conventional::DEFAULT_TYPES.to_vec(),
+        };
+        conventional::validate(&message, &types).map_err(|reason| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("commit message rejected: {}", reason),
+            )
+        })?;
+    }
+    Ok(message)
+}
+
+/// The names of the hooks that would run, sorted
+pub fn enabled() -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(repository::path(HOOKS_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Samples are executable but never run under their own name
+        if !name.ends_with(".sample") && is_executable(&entry.path()) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Write the sample hooks into a new repository
+pub fn install_samples() -> io::Result<()> {
+    fs::create_dir_all(repository::path(HOOKS_DIR))?;
+    let path = repository::path(HOOKS_DIR).join("commit-msg.sample");
+    fs::write(&path, COMMIT_MSG_SAMPLE)?;
+    #[cfg(unix)]
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -0,0 +1,182 @@
+// Identity lines for commits and tags: `Name <email> <epoch> <+zzzz>`
+// The name and email come from GIT_AUTHOR_NAME / GIT_AUTHOR_EMAIL (or the
+// GIT_COMMITTER_* pair for the committer), then author.name / author.email
+// (committer.*) and user.name / user.email from config, and the email
+// finally from $EMAIL. A missing name or email is an error naming the
+// config to set, except in reflog entries, which fall back to the login
+// name at the host name as git does.
+//
+// The date is the current time unless GIT_AUTHOR_DATE (GIT_COMMITTER_DATE)
+// gives one in any form `date::parse` accepts, so scripts can make
+// reproducible commits and rewrite history with the original dates.
+//
+// In deterministic mode, turned on by GIT_DETERMINISTIC or
+// core.deterministic, nothing about the machine or the clock goes into an
+// identity line: without the environment variables above, the author is
+// "A U Thor <author@example.com>", the committer (and tagger, and reflog
+// identity) "C O Mitter <committer@example.com>", and every date
+// 1112911993 -0700, as in git's own test suite. The same commands then
+// make the same objects byte for byte.
+
+use crate::git::config::Config;
+use crate::git::date;
+use chrono::Local;
+use std::env;
+use std::fs;
+use std::io;
+
+/// Which identity of a commit is wanted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Who wrote the change
+    Author,
+    /// Who made the commit; also the tagger of tags and the identity in
+    /// reflog entries
+    Committer,
+}
+
+impl Role {
+    fn env_prefix(self) -> &'static str {
+        match self {
+            Role::Author => "GIT_AUTHOR",
+            Role::Committer => "GIT_COMMITTER",
+        }
+    }
+
+    fn config_section(self) -> &'static str {
+        match self {
+            Role::Author => "author",
+            Role::Committer => "committer",
+        }
+    }
+
+    /// The fixed name or email of deterministic mode
+    fn deterministic_identity(self, field: &str) -> &'static str {
+        match (self, field) {
+            (Role::Author, "name") => "A U Thor",
+            (Role::Author, _) => "author@example.com",
+            (Role::Committer, "name") => "C O Mitter",
+            (Role::Committer, _) => "committer@example.com",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Role::Author => "Author",
+            Role::Committer => "Committer",
+        }
+    }
+}
+
+/// The date of every identity line in deterministic mode
+pub const DETERMINISTIC_DATE: &str = "1112911993 -0700";
+
+/// Whether deterministic mode is on: GIT_DETERMINISTIC, or else
+/// core.deterministic
+pub fn is_deterministic() -> io::Result<bool> {
+    if let Ok(value) = env::var("GIT_DETERMINISTIC") {
+        return Ok(matches!(
+            value.to_lowercase().as_str(),
+            "true" | "yes" | "on" | "1"
+        ));
+    }
+    Ok(Config::load()?
+        .get_bool("core.deterministic")?
+        .unwrap_or(false))
+}
+
+/// `Name <email>` for `role`
+pub fn identity(role: Role) -> io::Result<String> {
+    let config = Config::load()?;
+    let deterministic = is_deterministic()?;
+    let lookup = |field: &str| {
+        env::var(format!("{}_{}", role.env_prefix(), field.to_uppercase()))
+            .ok()
+            .or_else(|| {
+                if deterministic {
+                    return Some(role.deterministic_identity(field).to_string());
+                }
+                config
+                    .get(&format!("{}.{}", role.config_section(), field))
+                    .or_else(|| config.get(&format!("user.{}", field)))
+                    .map(str::to_string)
+            })
+    };
+    let email = lookup("email").or_else(|| env::var("EMAIL").ok());
+    let (Some(name), Some(email)) = (lookup("name"), email) else {
+        return Err(io::Error::other(format!(
+            "{} identity unknown\n\n*** Please tell me who you are.\n\nRun\n\n  git config --global user.email \"you@example.com\"\n  git config --global user.name \"Your Name\"\n\nto set your account's default identity.\nOmit --global to set the identity only in this repository.",
+            role.title()
+        )));
+    };
+    // Angle brackets and newlines would break the identity line
+    let clean = |value: &str| {
+        value
+            .chars()
+            .filter(|c| !matches!(c, '<' | '>' | '\n'))
+            .collect::<String>()
+            .trim()
+            .to_string()
+    };
+    let (name, email) = (clean(&name), clean(&email));
+    if name.is_empty() {
+        return Err(io::Error::other(format!(
+            "empty ident name (for <{}>) not allowed",
+            email
+        )));
+    }
+    Ok(format!("{} <{}>", name, email))
+}
+
+/// The committer's signature for a reflog entry, which is written even
+/// when no identity is configured
+pub fn reflog_signature() -> String {
+    let identity = identity(Role::Committer).unwrap_or_else(|_| {
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let host = fs::read_to_string("/etc/hostname")
+            .map(|host| host.trim().to_string())
+            .ok()
+            .filter(|host| !host.is_empty())
+            .unwrap_or_else(|| "(none)".to_string());
+        format!("{} <{}@{}>", user, user, host)
+    });
+    let timestamp = timestamp(Role::Committer).unwrap_or_else(|_| format_current_timestamp());
+    format!("{} {}", identity, timestamp)
+}
+
+/// `role`'s identity and date, as used in author/committer/tagger lines
+pub fn signature(role: Role) -> io::Result<String> {
+    Ok(format!("{} {}", identity(role)?, timestamp(role)?))
+}
+
+/// `<epoch> <+hhmm>` from GIT_AUTHOR_DATE or GIT_COMMITTER_DATE for `role`,
+/// or for the current local time when it isn't set (the fixed date in
+/// deterministic mode)
+pub fn timestamp(role: Role) -> io::Result<String> {
+    match env::var(format!("{}_DATE", role.env_prefix())) {
+        Ok(value) if !value.is_empty() => date::parse(&value).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid date format: {}", value),
+            )
+        }),
+        _ if is_deterministic()? => Ok(DETERMINISTIC_DATE.to_string()),
+        _ => Ok(format_current_timestamp()),
+    }
+}
+
+/// `<epoch seconds> <+hhmm>` for the current local time
+pub fn format_current_timestamp() -> String {
+    let now_local = Local::now();
+    format!("{} {}", now_local.timestamp(), now_local.format("%z"))
+}
+
+/// Split an identity line into `Name <email>` and its `<epoch> <+hhmm>`
+pub fn split(identity: &str) -> (&str, &str) {
+    match identity.rfind('>') {
+        Some(end) => (&identity[..=end], identity[end + 1..].trim()),
+        None => (identity.trim(), ""),
+    }
+}
@@ -0,0 +1,292 @@
+// Ignored files
+// Untracked paths matching a pattern from the .gitignore files, from
+// .git/info/exclude or from the file core.excludesFile names (by default
+// ~/.config/git/ignore) are left out when the working tree is listed. A
+// .gitignore applies to its directory and everything below; deeper files
+// override shallower ones, and within a file the last matching line wins.
+//
+// Patterns are one per line; blank lines and lines starting with "#" are
+// skipped. "!" re-includes what an earlier pattern excluded (but not inside
+// an excluded directory), a trailing "/" only matches directories, and a
+// pattern with a "/" before its end is anchored to the .gitignore's
+// directory; otherwise it matches a name at any depth. "*", "?" and "[...]"
+// don't match "/", while "**" matches across directories.
+
+use crate::git::config::Config;
+use crate::git::repository;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+struct Rule {
+    /// Directory of the file the rule came from, "" for the top
+    base: String,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+/// The ignore rules in effect at some point of a working tree walk
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// The rules of the repository's top directory and the global files
+    pub fn load() -> io::Result<Ignore> {
+        let mut ignore = Ignore::default();
+        if let Some(path) = excludes_file()? {
+            ignore.read_file(&path, "")?;
+        }
+        ignore.read_file(&repository::path("info/exclude"), "")?;
+        ignore.enter("")?;
+        Ok(ignore)
+    }
+
+    /// Add the rules of `dir`'s .gitignore, for paths below it. Returns a
+    /// mark for `leave`.
+    pub fn enter(&mut self, dir: &str) -> io::Result<usize> {
+        let mark = self.rules.len();
+        let path = if dir.is_empty() {
+            PathBuf::from(".gitignore")
+        } else {
+            Path::new(dir).join(".gitignore")
+        };
+        self.read_file(&path, dir)?;
+        Ok(mark)
+    }
+
+    /// Drop the rules added since `enter` returned `mark`
+    pub fn leave(&mut self, mark: usize) {
+        self.rules.truncate(mark);
+    }
+
+    /// Add the patterns in `text`, relative to the directory `base`
+    pub fn add_patterns(&mut self, text: &str, base: &str) {
+        for line in text.lines() {
+            if let Some(rule) = parse_rule(line, base) {
+                self.rules.push(rule);
+            }
+        }
+    }
+
+    fn read_file(&mut self, path: &Path, base: &str) -> io::Result<()> {
+        match fs::read(path) {
+            Ok(data) => {
+                self.add_patterns(&String::from_utf8_lossy(&data), base);
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether `path` (relative to the top of the working tree) is ignored.
+    /// Its parent directories are taken not to be.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        for rule in self.rules.iter().rev() {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let relative = if rule.base.is_empty() {
+                path
+            } else {
+                match path
+                    .strip_prefix(rule.base.as_str())
+                    .and_then(|rest| rest.strip_prefix('/'))
+                {
+                    Some(relative) => relative,
+                    None => continue,
+                }
+            };
+            let subject = if rule.anchored {
+                relative
+            } else {
+                relative.rsplit('/').next().unwrap_or(relative)
+            };
+            if wildmatch(rule.pattern.as_bytes(), subject.as_bytes()) {
+                return !rule.negated;
+            }
+        }
+        false
+    }
+}
+
+fn parse_rule(line: &str, base: &str) -> Option<Rule> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    // Trailing spaces are dropped unless escaped
+    let mut pattern = line.to_string();
+    while pattern.ends_with(' ') && !pattern.ends_with("\\ ") {
+        pattern.pop();
+    }
+    let (negated, mut pattern) = match pattern.strip_prefix('!') {
+        Some(rest) => (true, rest.to_string()),
+        None => (false, pattern),
+    };
+    let dir_only = pattern.ends_with('/');
+    if dir_only {
+        pattern.pop();
+    }
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(&pattern).to_string();
+    if pattern.is_empty() {
+        return None;
+    }
+    Some(Rule {
+        base: base.to_string(),
+        pattern,
+        negated,
+        dir_only,
+        anchored,
+    })
+}
+
+/// core.excludesFile, or $XDG_CONFIG_HOME/git/ignore (~/.config/git/ignore)
+fn excludes_file() -> io::Result<Option<PathBuf>> {
+    let config = Config::load()?;
+    if let Some(path) = config.get_path("core.excludesFile") {
+        return Ok(Some(path));
+    }
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| home.join(".config")));
+    Ok(config_home.map(|dir| dir.join("git/ignore")))
+}
+
+/// Match a gitignore glob against a `/`-separated path
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            match rest.first() {
+                // "**" at the end matches everything left
+                None => true,
+                // "**/" matches any number of leading directories
+                Some(b'/') => {
+                    let rest = &rest[1..];
+                    wildmatch(rest, text)
+                        || text
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, &b)| b == b'/')
+                            .any(|(i, _)| wildmatch(rest, &text[i + 1..]))
+                }
+                // Otherwise it is just two stars
+                Some(_) => wildmatch(&pattern[1..], text),
+            }
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for skip in 0..=text.len() {
+                if wildmatch(rest, &text[skip..]) {
+                    return true;
+                }
+                if text.get(skip) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => {
+            text.first().is_some_and(|&b| b != b'/') && wildmatch(&pattern[1..], &text[1..])
+        }
+        Some(b'[') => match text.first() {
+            Some(&b) if b != b'/' => match match_class(&pattern[1..], b) {
+                Some((matched, used)) => matched && wildmatch(&pattern[1 + used..], &text[1..]),
+                // No closing bracket: a literal "["
+                None => b == b'[' && wildmatch(&pattern[1..], &text[1..]),
+            },
+            _ => false,
+        },
+        Some(b'\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && wildmatch(&pattern[2..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && wildmatch(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Match `byte` against the class after a `[`; returns whether it matched
+/// and how much of the pattern the class took, its `]` included
+fn match_class(class: &[u8], byte: u8) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let negated = matches!(class.first(), Some(b'!') | Some(b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let c = *class.get(i)?;
+        if c == b']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if class.get(i + 1) == Some(&b'-') && class.get(i + 2).is_some_and(|&end| end != b']') {
+            matched |= c <= byte && byte <= class[i + 2];
+            i += 3;
+        } else {
+            matched |= c == byte;
+            i += 1;
+        }
+    }
+}
+
+/// Untracked, unignored files of the working tree, sorted. Ignored
+/// directories are not looked into.
+pub fn untracked_files(tracked: &HashSet<String>) -> io::Result<Vec<String>> {
+    let mut ignore = Ignore::load()?;
+    let mut files = Vec::new();
+    walk("", tracked, &mut ignore, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn walk(
+    dir: &str,
+    tracked: &HashSet<String>,
+    ignore: &mut Ignore,
+    files: &mut Vec<String>,
+) -> io::Result<()> {
+    let listing = if dir.is_empty() { "." } else { dir };
+    for entry in fs::read_dir(listing)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ".git" {
+            continue;
+        }
+        let path = if dir.is_empty() {
+            name
+        } else {
+            format!("{}/{}", dir, name)
+        };
+        let is_dir = entry.file_type()?.is_dir();
+        if tracked.contains(&path) {
+            continue;
+        }
+        if ignore.is_ignored(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            // Another repository inside this one is not looked into
+            if Path::new(&path).join(".git").exists() {
+                continue;
+            }
+            let mark = ignore.enter(&path)?;
+            walk(&path, tracked, ignore, files)?;
+            ignore.leave(mark);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,509 @@
+// The index (staging area)
+// `.git/index` records the tree the next commit will be made from, along with
+// the stat data each file had when it was last written, so unchanged files
+// can be recognised without hashing them. The format is git's dircache:
+//
+// "DIRC" <version> <entry count>
+// entries, sorted by path then stage, each padded to a multiple of 8 bytes:
+//   ctime mtime (seconds, nanoseconds) dev ino mode uid gid size  (u32 each)
+//   <20-byte sha> <u16 flags: stage and name length> <path> NUL...
+// extensions ("TREE", "REUC", ...)
+// <SHA-1 of everything above>
+//
+// Versions 2 and 3 are read; version 2 is written, without extensions (git
+// rebuilds its caches as needed).
+//
+// Stat data only proves a file unchanged if the file was last modified
+// before the index was written: a change made within the same timestamp
+// tick leaves the stat data looking the same. Such "racily clean" entries
+// are always compared by content, and when the index is written any whose
+// file has in fact changed get their recorded size zeroed, so that they
+// keep looking modified once the index is no longer new.
+
+use crate::git::dry_run;
+use crate::git::fsync::{self, Component};
+use crate::git::interrupt;
+use crate::git::refs;
+use crate::git::tree::{FileEntry, MODE_EXECUTABLE, MODE_GITLINK, MODE_SYMLINK};
+use crate::git::worktree::{self, Snapshot};
+use sha1_smol::Sha1;
+use std::fs::{self, Metadata, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+pub const INDEX: &str = ".git/index";
+const INDEX_LOCK: &str = ".git/index.lock";
+
+const SIGNATURE: &[u8] = b"DIRC";
+const FLAG_EXTENDED: u16 = 0x4000;
+const STAGE_SHIFT: u16 = 12;
+const NAME_MASK: u16 = 0x0fff;
+
+/// The id of the empty blob, the one file content a zero size can describe
+const EMPTY_BLOB: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    pub ctime: (u32, u32),
+    pub mtime: (u32, u32),
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub sha: String,
+    /// 0 for a merged entry, 1-3 for the base/ours/theirs sides of a conflict
+    pub stage: u8,
+    pub path: String,
+}
+
+impl Entry {
+    /// An entry without stat data: git treats it as possibly modified and
+    /// compares the file's content the next time it looks
+    pub fn new(path: &str, file: &FileEntry) -> Entry {
+        Entry {
+            mode: u32::from_str_radix(&file.mode, 8).unwrap_or(0o100644),
+            sha: file.sha.clone(),
+            path: path.to_string(),
+            ..Entry::default()
+        }
+    }
+
+    /// An entry for a file that was just written from `file`, stamped with
+    /// its current stat data
+    pub fn checked_out(path: &str, file: &FileEntry) -> io::Result<Entry> {
+        let mut entry = Entry::new(path, file);
+        if file.mode == MODE_GITLINK {
+            return Ok(entry);
+        }
+        #[cfg(unix)]
+        {
+            let meta = fs::symlink_metadata(path)?;
+            entry.ctime = (meta.ctime() as u32, meta.ctime_nsec() as u32);
+            entry.mtime = (meta.mtime() as u32, meta.mtime_nsec() as u32);
+            entry.dev = meta.dev() as u32;
+            entry.ino = meta.ino() as u32;
+            entry.uid = meta.uid();
+            entry.gid = meta.gid();
+            entry.size = meta.size() as u32;
+        }
+        Ok(entry)
+    }
+
+    /// Whether stat data was recorded at all, as it is for a file the
+    /// entry was made from rather than one staged from a tree
+    pub fn has_stat(&self) -> bool {
+        self.mtime != (0, 0) || self.ctime != (0, 0)
+    }
+
+    /// Whether the recorded stat data still describes the file `meta` was
+    /// read from. Nothing is proved for an entry without stat data.
+    #[cfg(unix)]
+    pub fn stat_matches(&self, meta: &Metadata) -> bool {
+        self.has_stat()
+            && mode_matches(&self.file(), meta)
+            && (self.size != 0 || self.sha == EMPTY_BLOB)
+            && self.ctime == (meta.ctime() as u32, meta.ctime_nsec() as u32)
+            && self.mtime == (meta.mtime() as u32, meta.mtime_nsec() as u32)
+            && self.ino == meta.ino() as u32
+            && self.uid == meta.uid()
+            && self.gid == meta.gid()
+            && self.size == meta.size() as u32
+    }
+
+    #[cfg(not(unix))]
+    pub fn stat_matches(&self, _meta: &Metadata) -> bool {
+        false
+    }
+
+    pub fn file(&self) -> FileEntry {
+        FileEntry {
+            mode: format!("{:o}", self.mode),
+            sha: self.sha.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    entries: Vec<Entry>,
+    /// When `.git/index` was last written, if it was loaded from there
+    timestamp: Option<(u32, u32)>,
+}
+
+/// What `Index::refresh` found for one entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refresh {
+    /// The file differs from what is staged
+    NeedsUpdate(String),
+    /// The path has unresolved conflicts
+    NeedsMerge(String),
+}
+
+impl Index {
+    /// Read `.git/index`. A repository whose index was never written (a
+    /// fresh clone) is taken to have HEAD's tree staged.
+    pub fn load() -> io::Result<Index> {
+        match fs::read(INDEX) {
+            Ok(data) => {
+                let mut index = parse(&data)?;
+                index.timestamp = modification_time(&fs::metadata(INDEX)?);
+                Ok(index)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let head = refs::head_commit()?;
+                let files = worktree::snapshot_of_commit(head.as_deref())?;
+                Ok(Index::from_snapshot(&files))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn from_snapshot(files: &Snapshot) -> Index {
+        Index {
+            entries: files
+                .iter()
+                .map(|(path, file)| Entry::new(path, file))
+                .collect(),
+            timestamp: None,
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The merged entry at `path`, or the first stage of a conflict there
+    pub fn get(&self, path: &str) -> Option<&Entry> {
+        let pos = self
+            .entries
+            .partition_point(|e| e.path.as_bytes() < path.as_bytes());
+        self.entries.get(pos).filter(|e| e.path == path)
+    }
+
+    /// Stage `entry`, replacing whatever was staged at its path (including
+    /// the sides of a conflict)
+    pub fn add(&mut self, entry: Entry) {
+        self.remove(&entry.path);
+        let pos = self.entries.partition_point(|e| {
+            (e.path.as_bytes(), e.stage) < (entry.path.as_bytes(), entry.stage)
+        });
+        self.entries.insert(pos, entry);
+    }
+
+    /// Stage a conflict at `path`: the base, ours and theirs versions in
+    /// `stages` at stages 1, 2 and 3, leaving out the sides without one,
+    /// in place of whatever was staged there
+    pub fn add_conflict(&mut self, path: &str, stages: &[Option<FileEntry>; 3]) {
+        self.remove(path);
+        let pos = self
+            .entries
+            .partition_point(|e| e.path.as_bytes() < path.as_bytes());
+        let sides = stages.iter().zip(1..).filter_map(|(file, stage)| {
+            file.as_ref().map(|file| Entry {
+                stage,
+                ..Entry::new(path, file)
+            })
+        });
+        self.entries.splice(pos..pos, sides);
+    }
+
+    /// Unstage every entry at `path`; returns whether there were any
+    pub fn remove(&mut self, path: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        self.entries.len() != before
+    }
+
+    /// Whether `entry` was modified no earlier than the index was written,
+    /// so that its stat data can't be trusted
+    pub fn is_racy(&self, entry: &Entry) -> bool {
+        self.timestamp.map_or(true, |stamp| entry.mtime >= stamp)
+    }
+
+    /// Whether the file `meta` describes is known to be unchanged from
+    /// `entry` without reading it
+    pub fn is_stat_clean(&self, entry: &Entry, meta: &Metadata) -> bool {
+        !self.is_racy(entry) && entry.stat_matches(meta)
+    }
+
+    /// Bring the stat data of unchanged files up to date, hashing every
+    /// file whose stat data doesn't prove it clean (or all of them with
+    /// `really`), and report the entries that do differ. A missing file
+    /// counts as changed unless `ignore_missing` is set.
+    pub fn refresh(&mut self, really: bool, ignore_missing: bool) -> io::Result<Vec<Refresh>> {
+        let mut problems = Vec::new();
+        let mut refreshed = Vec::with_capacity(self.entries.len());
+        for (i, entry) in self.entries.iter().enumerate() {
+            if entry.stage != 0 {
+                let first_stage = i == 0 || self.entries[i - 1].path != entry.path;
+                if first_stage {
+                    problems.push(Refresh::NeedsMerge(entry.path.clone()));
+                }
+                refreshed.push(entry.clone());
+                continue;
+            }
+            let file = entry.file();
+            if file.mode == MODE_GITLINK {
+                refreshed.push(entry.clone());
+                continue;
+            }
+            let meta = match fs::symlink_metadata(&entry.path) {
+                Ok(meta) => meta,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    if !ignore_missing {
+                        problems.push(Refresh::NeedsUpdate(entry.path.clone()));
+                    }
+                    refreshed.push(entry.clone());
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if !really && self.is_stat_clean(entry, &meta) {
+                refreshed.push(entry.clone());
+                continue;
+            }
+            if mode_matches(&file, &meta) && worktree::matches_worktree(&entry.path, &file)? {
+                refreshed.push(Entry::checked_out(&entry.path, &file)?);
+            } else {
+                problems.push(Refresh::NeedsUpdate(entry.path.clone()));
+                refreshed.push(entry.clone());
+            }
+        }
+        self.entries = refreshed;
+        Ok(problems)
+    }
+
+    /// The staged tree. Conflicted paths contribute their "ours" side.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut files = Snapshot::new();
+        for entry in &self.entries {
+            if entry.stage == 0 || entry.stage == 2 {
+                files.insert(entry.path.clone(), entry.file());
+            }
+        }
+        files
+    }
+
+    /// Write the index through `.git/index.lock`, failing if another
+    /// process holds the lock
+    pub fn write(&self) -> io::Result<()> {
+        if dry_run::is_enabled() {
+            return Ok(());
+        }
+        let mut lock = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(INDEX_LOCK)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => io::Error::other(format!(
+                    "Unable to create '{}': File exists.\nAnother git process seems to be running in this repository.",
+                    INDEX_LOCK
+                )),
+                _ => e,
+            })?;
+        let _cleanup = interrupt::remove_on_interrupt(Path::new(INDEX_LOCK));
+        // The new index is no older than its lock file; an entry modified
+        // since then can't be trusted to be clean
+        let data = lock
+            .metadata()
+            .map(|meta| modification_time(&meta))
+            .and_then(|stamp| self.smudge_racy_entries(stamp))
+            .map(|entries| serialize(entries.as_deref().unwrap_or(&self.entries)));
+        let written = data
+            .and_then(|data| lock.write_all(&data))
+            .and_then(|()| fsync::sync(&lock, Path::new(INDEX_LOCK), Component::Index));
+        if let Err(e) = written {
+            let _ = fs::remove_file(INDEX_LOCK);
+            return Err(e);
+        }
+        fs::rename(INDEX_LOCK, INDEX)
+    }
+
+    /// A copy of the entries with the racily clean ones that have changed
+    /// since being staged marked as modified, or `None` if there are none
+    fn smudge_racy_entries(&self, stamp: Option<(u32, u32)>) -> io::Result<Option<Vec<Entry>>> {
+        let Some(stamp) = stamp else {
+            return Ok(None);
+        };
+        let mut smudged = None;
+        for (i, entry) in self.entries.iter().enumerate() {
+            if !entry.has_stat()
+                || entry.size == 0
+                || entry.mtime < stamp
+                || entry.file().mode == MODE_GITLINK
+            {
+                continue;
+            }
+            if worktree::matches_worktree(&entry.path, &entry.file())? {
+                continue;
+            }
+            let entries = smudged.get_or_insert_with(|| self.entries.clone());
+            entries[i].size = 0;
+        }
+        Ok(smudged)
+    }
+}
+
+fn serialize(entries: &[Entry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(SIGNATURE);
+    out.extend_from_slice(&2u32.to_be_bytes());
+    out.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+    for entry in entries {
+        let start = out.len();
+        for value in [
+            entry.ctime.0,
+            entry.ctime.1,
+            entry.mtime.0,
+            entry.mtime.1,
+            entry.dev,
+            entry.ino,
+            entry.mode,
+            entry.uid,
+            entry.gid,
+            entry.size,
+        ] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        out.extend_from_slice(&hex::decode(&entry.sha).unwrap_or_else(|_| vec![0; 20]));
+        let name_len = entry.path.len().min(NAME_MASK as usize) as u16;
+        let flags = ((entry.stage as u16) << STAGE_SHIFT) | name_len;
+        out.extend_from_slice(&flags.to_be_bytes());
+        out.extend_from_slice(entry.path.as_bytes());
+        // At least one NUL, up to the next multiple of 8
+        let len = out.len() - start;
+        out.resize(start + (len + 8) / 8 * 8, 0);
+    }
+
+    let mut hasher = Sha1::new();
+    hasher.update(&out);
+    out.extend_from_slice(&hasher.digest().bytes());
+    out
+}
+
+/// A file's modification time as the index records it
+#[cfg(unix)]
+fn modification_time(meta: &Metadata) -> Option<(u32, u32)> {
+    Some((meta.mtime() as u32, meta.mtime_nsec() as u32))
+}
+
+#[cfg(not(unix))]
+fn modification_time(_meta: &Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+/// Whether the file `meta` describes is of the type, and executable or not,
+/// as `file` says
+fn mode_matches(file: &FileEntry, meta: &Metadata) -> bool {
+    if meta.file_type().is_symlink() {
+        return file.mode == MODE_SYMLINK;
+    }
+    if file.mode == MODE_SYMLINK {
+        // A symlink checked out as a plain file
+        return meta.is_file() && !worktree::symlinks_enabled().unwrap_or(true);
+    }
+    #[cfg(unix)]
+    {
+        let executable = meta.mode() & 0o111 != 0;
+        meta.is_file() && (file.mode == MODE_EXECUTABLE) == executable
+    }
+    #[cfg(not(unix))]
+    {
+        meta.is_file()
+    }
+}
+
+fn parse(data: &[u8]) -> io::Result<Index> {
+    let corrupt = |what: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("index file corrupt: {}", what),
+        )
+    };
+
+    if data.len() < 32 || &data[..4] != SIGNATURE {
+        return Err(corrupt("bad signature"));
+    }
+    let (body, checksum) = data.split_at(data.len() - 20);
+    let mut hasher = Sha1::new();
+    hasher.update(body);
+    if hasher.digest().bytes()[..] != *checksum {
+        return Err(corrupt("bad checksum"));
+    }
+
+    let read_u32 = |pos: usize| -> io::Result<u32> {
+        body.get(pos..pos + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| corrupt("truncated entry"))
+    };
+    let version = read_u32(4)?;
+    if version != 2 && version != 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("index file version {} is not supported", version),
+        ));
+    }
+    let count = read_u32(8)? as usize;
+
+    let mut entries = Vec::with_capacity(count.min(body.len() / 62));
+    let mut pos = 12;
+    for _ in 0..count {
+        let start = pos;
+        let mut fields = [0u32; 10];
+        for (i, field) in fields.iter_mut().enumerate() {
+            *field = read_u32(pos + i * 4)?;
+        }
+        pos += 40;
+        let sha = body
+            .get(pos..pos + 20)
+            .ok_or_else(|| corrupt("truncated entry"))?;
+        pos += 20;
+        let flags = body
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| corrupt("truncated entry"))?;
+        pos += 2;
+        if flags & FLAG_EXTENDED != 0 {
+            // Version 3 extended flags (intent-to-add, skip-worktree)
+            pos += 2;
+        }
+
+        let name_end = body[pos.min(body.len())..]
+            .iter()
+            .position(|&b| b == 0)
+            .map(|n| pos + n)
+            .ok_or_else(|| corrupt("unterminated path"))?;
+        let path = String::from_utf8_lossy(&body[pos..name_end]).into_owned();
+        let len = name_end - start;
+        pos = start + (len + 8) / 8 * 8;
+
+        entries.push(Entry {
+            ctime: (fields[0], fields[1]),
+            mtime: (fields[2], fields[3]),
+            dev: fields[4],
+            ino: fields[5],
+            mode: fields[6],
+            uid: fields[7],
+            gid: fields[8],
+            size: fields[9],
+            sha: hex::encode(sha),
+            stage: ((flags >> STAGE_SHIFT) & 0x3) as u8,
+            path,
+        });
+    }
+    if pos > body.len() {
+        return Err(corrupt("truncated entry"));
+    }
+
+    // Extensions follow; none of them are needed here
+    Ok(Index {
+        entries,
+        timestamp: None,
+    })
+}
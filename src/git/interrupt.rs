@@ -0,0 +1,193 @@
+// Ctrl-C handling
+// The binary installs handlers for SIGINT and SIGTERM. The first signal
+// cancels the process-wide token that `Progress` polls by default, so an
+// operation marked `cancellable` (clone, fetch, worktree updates) stops at
+// its next step and undoes its partial work itself. Anything else, or a
+// second signal while the operation is stuck, gets the blunt treatment: the
+// lock files and half-written paths registered below are removed and the
+// process exits with status 130, as if the signal had killed it.
+//
+// The signal handler only writes a byte to a pipe; a watcher thread does the
+// rest, outside the restrictions of signal context.
+
+use crate::git::progress::CancellationToken;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Exit status of a process killed by SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+static CANCELLABLE: AtomicUsize = AtomicUsize::new(0);
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+static CLEANUP: Mutex<Vec<Cleanup>> = Mutex::new(Vec::new());
+
+/// A path to remove if the process is interrupted
+struct Cleanup {
+    id: usize,
+    path: PathBuf,
+    keep_dir: bool,
+}
+
+/// The token cancelled by the first interrupt
+pub fn token() -> CancellationToken {
+    TOKEN.get_or_init(CancellationToken::new).clone()
+}
+
+/// Whether an interrupt has been received
+pub fn received() -> bool {
+    token().is_cancelled()
+}
+
+/// Held while an operation that polls `token()` runs; the first interrupt
+/// then leaves the cleanup to the operation instead of exiting
+pub struct Cancellable(());
+
+impl Drop for Cancellable {
+    fn drop(&mut self) {
+        CANCELLABLE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn cancellable() -> Cancellable {
+    CANCELLABLE.fetch_add(1, Ordering::SeqCst);
+    Cancellable(())
+}
+
+/// Keeps a path registered for removal until dropped
+pub struct CleanupGuard(usize);
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        lock_cleanup().retain(|entry| entry.id != self.0);
+    }
+}
+
+/// Remove the file or directory tree at `path` if interrupted before the
+/// guard is dropped
+pub fn remove_on_interrupt(path: &Path) -> CleanupGuard {
+    register(path, false)
+}
+
+/// Remove everything inside the directory `dir`, but not `dir` itself, if
+/// interrupted before the guard is dropped
+pub fn empty_on_interrupt(dir: &Path) -> CleanupGuard {
+    register(dir, true)
+}
+
+fn register(path: &Path, keep_dir: bool) -> CleanupGuard {
+    // Commands may change directory after registering
+    let path = std::env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    lock_cleanup().push(Cleanup { id, path, keep_dir });
+    CleanupGuard(id)
+}
+
+fn lock_cleanup() -> std::sync::MutexGuard<'static, Vec<Cleanup>> {
+    CLEANUP
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Remove `path` and everything below it; with `keep_dir`, only what is
+/// inside the directory. A missing path is not an error.
+pub fn discard(path: &Path, keep_dir: bool) -> io::Result<()> {
+    if !keep_dir {
+        return match fs::symlink_metadata(path) {
+            Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+            Ok(_) => fs::remove_file(path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        };
+    }
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        discard(&entry?.path(), false)?;
+    }
+    Ok(())
+}
+
+fn clean_up() {
+    // Newest first: a lock inside a directory goes before the directory
+    for entry in lock_cleanup().iter().rev() {
+        let _ = discard(&entry.path, entry.keep_dir);
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::thread;
+
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_signal(_: libc::c_int) {
+        let byte = 1u8;
+        // write(2) is async-signal-safe; a full pipe just drops the byte
+        unsafe {
+            libc::write(
+                PIPE.load(Ordering::SeqCst),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+
+    pub fn install() {
+        let mut fds = [0 as libc::c_int; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+        // Keep the pipe out of ssh, hooks and other children
+        for fd in fds {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+        PIPE.store(fds[1], Ordering::SeqCst);
+        let reader = unsafe { File::from_raw_fd(fds[0]) };
+        thread::spawn(move || watch(reader));
+
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(libc::SIGINT, handler);
+            libc::signal(libc::SIGTERM, handler);
+        }
+    }
+
+    fn watch(mut reader: File) {
+        let mut received = 0;
+        let mut byte = [0u8; 1];
+        while let Ok(1) = reader.read(&mut byte) {
+            received += 1;
+            super::interrupted(received);
+        }
+    }
+}
+
+fn interrupted(count: usize) {
+    token().cancel();
+    if count == 1 && CANCELLABLE.load(Ordering::SeqCst) > 0 {
+        return;
+    }
+    clean_up();
+    process::exit(EXIT_INTERRUPTED);
+}
+
+/// Route SIGINT and SIGTERM through the handling above. A no-op where
+/// signals don't exist.
+pub fn install() {
+    #[cfg(unix)]
+    signals::install();
+}
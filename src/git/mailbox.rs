@@ -0,0 +1,338 @@
+// Patch mail handling
+// An mbox is a sequence of RFC 2822 messages, each starting with a
+// "From <sender> <date>" separator line, as written by `format-patch`:
+//
+// From 1ad3c5e... Mon Sep 17 00:00:00 2001
+// From: A U Thor <author@example.com>
+// Date: Thu, 7 Apr 2005 15:13:13 -0700
+// Subject: [PATCH 1/2] Fix the frotz
+//
+// Commit message body
+// ---
+//  file.c | 2 +-
+// diff --git a/file.c b/file.c
+// ...
+//
+// `split` cuts the mbox into messages and `parse` extracts the author, date,
+// subject, commit message and patch from one of them.
+
+use base64::Engine;
+
+/// What `am` needs from one patch mail
+#[derive(Debug, Default, Clone)]
+pub struct MailInfo {
+    pub author: String,
+    pub email: String,
+    pub subject: String,
+    pub date: String,
+    /// Commit message body, without the subject line
+    pub message: String,
+    /// Everything from the `---` separator or the first diff on
+    pub patch: Vec<u8>,
+}
+
+/// Split an mbox into its messages. Each message keeps its "From " line.
+/// Input that doesn't start with one is taken to be a single message.
+pub fn split(mbox: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages: Vec<Vec<u8>> = Vec::new();
+    let mut current: Vec<u8> = Vec::new();
+
+    for line in mbox.split_inclusive(|&b| b == b'\n') {
+        if is_separator(line) && !current.is_empty() {
+            messages.push(std::mem::take(&mut current));
+        }
+        current.extend_from_slice(line);
+    }
+    if current.iter().any(|b| !b.is_ascii_whitespace()) {
+        messages.push(current);
+    }
+    messages
+}
+
+/// A "From <sender> <date>" line starting a new message. Writers escape body
+/// lines beginning with "From ", but not always, so the envelope is also
+/// recognised by the year ending it.
+fn is_separator(line: &[u8]) -> bool {
+    let line = String::from_utf8_lossy(line);
+    let mut words = line.split_whitespace();
+    if words.next() != Some("From") {
+        return false;
+    }
+    let words: Vec<&str> = words.collect();
+    words.len() >= 2
+        && words
+            .last()
+            .is_some_and(|year| year.len() == 4 && year.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Extract the commit information from one message. With `keep_subject`
+/// the subject is used as is; otherwise "Re:" and bracketed prefixes such
+/// as "[PATCH 2/5]" are removed.
+pub fn parse(mail: &[u8], keep_subject: bool) -> MailInfo {
+    let mut lines = mail.split_inclusive(|&b| b == b'\n');
+
+    // Headers run up to the first empty line; the envelope line is skipped
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut first = true;
+    for line in lines.by_ref() {
+        if first && line.starts_with(b"From ") {
+            first = false;
+            continue;
+        }
+        first = false;
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    // The patch is kept byte for byte; only the transfer encoding and CRLF
+    // line ends are undone
+    let raw_body: Vec<u8> = lines.flat_map(|line| line.iter().copied()).collect();
+    let body = match header("content-transfer-encoding").map(str::to_ascii_lowercase) {
+        Some(ref encoding) if encoding == "quoted-printable" => {
+            decode_quoted_printable(&strip_cr(&raw_body), false)
+        }
+        Some(ref encoding) if encoding == "base64" => {
+            let compact: Vec<u8> = raw_body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(compact)
+                .unwrap_or(raw_body)
+        }
+        _ => raw_body,
+    };
+    let body = strip_cr(&body);
+
+    let mut info = MailInfo::default();
+    let (author, email) = split_address(&decode_header(header("from").unwrap_or("")));
+    info.author = author;
+    info.email = email;
+    info.date = header("date").unwrap_or("").to_string();
+    let mut subject = decode_header(header("subject").unwrap_or(""));
+
+    // The body may restate From:/Date:/Subject: when the sender isn't the
+    // author; those lines override the mail headers
+    let mut body = &body[..];
+    loop {
+        let end = body
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(body.len(), |i| i + 1);
+        let line = String::from_utf8_lossy(&body[..end]);
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.to_ascii_lowercase(), value.trim()),
+            None => break,
+        };
+        match name.as_str() {
+            "from" => {
+                let (author, email) = split_address(&decode_header(value));
+                info.author = author;
+                info.email = email;
+            }
+            "date" => info.date = value.to_string(),
+            "subject" => subject = decode_header(value),
+            _ => break,
+        }
+        body = &body[end..];
+    }
+
+    info.subject = if keep_subject {
+        subject.trim().to_string()
+    } else {
+        clean_subject(&subject)
+    };
+
+    let patch_start = body
+        .split_inclusive(|&b| b == b'\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .find(|(_, line)| starts_patch(line))
+        .map_or(body.len(), |(start, _)| start);
+
+    let message = String::from_utf8_lossy(&body[..patch_start]);
+    let message = message.trim_matches('\n');
+    info.message = if message.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", message)
+    };
+    info.patch = body[patch_start..].to_vec();
+    info
+}
+
+fn strip_cr(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    for (i, &b) in text.iter().enumerate() {
+        if !(b == b'\r' && text.get(i + 1) == Some(&b'\n')) {
+            out.push(b);
+        }
+    }
+    out
+}
+
+fn starts_patch(line: &[u8]) -> bool {
+    line.trim_ascii_end() == b"---" || line.starts_with(b"diff -") || line.starts_with(b"Index: ")
+}
+
+/// Drop reply markers and bracketed tags from the front of a subject:
+/// "Re: [PATCH v2 1/3] foo" becomes "foo"
+fn clean_subject(subject: &str) -> String {
+    let mut rest = subject.trim();
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        if lower.starts_with("re:") {
+            rest = rest[3..].trim_start();
+        } else if rest.starts_with('[') {
+            match rest.find(']') {
+                Some(end) => rest = rest[end + 1..].trim_start(),
+                None => break,
+            }
+        } else if let Some(after) = rest.strip_prefix(':') {
+            rest = after.trim_start();
+        } else {
+            break;
+        }
+    }
+    rest.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Name and address from "Name <addr>", "addr (Name)" or a bare address
+fn split_address(from: &str) -> (String, String) {
+    let from = from.trim();
+    let unquote = |name: &str| name.trim().trim_matches('"').replace("\\\"", "\"");
+
+    if let (Some(open), Some(close)) = (from.find('<'), from.rfind('>')) {
+        if open < close {
+            let email = from[open + 1..close].trim().to_string();
+            let mut name = unquote(&from[..open]);
+            if name.is_empty() {
+                name = email.clone();
+            }
+            return (name, email);
+        }
+    }
+    if let (Some(open), Some(close)) = (from.find('('), from.rfind(')')) {
+        if open < close {
+            let email = from[..open].trim().to_string();
+            return (unquote(&from[open + 1..close]), email);
+        }
+    }
+    (from.to_string(), from.to_string())
+}
+
+/// Decode RFC 2047 encoded words ("=?UTF-8?q?Ren=C3=A9?="). Whitespace
+/// between two encoded words is dropped.
+fn decode_header(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let decoded = encoded_word(&rest[start..]);
+        let gap = &rest[..start];
+        match decoded {
+            Some((text, len)) => {
+                if !(after_word && gap.trim().is_empty()) {
+                    out.push_str(gap);
+                }
+                out.push_str(&text);
+                rest = &rest[start + len..];
+                after_word = true;
+            }
+            None => {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decode the encoded word at the start of `s`, returning its text and
+/// length
+fn encoded_word(s: &str) -> Option<(String, usize)> {
+    let inner = s.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    let len = s.len() - inner.len() + end + 2;
+
+    let bytes = match encoding {
+        "Q" | "q" => decode_quoted_printable(text.as_bytes(), true),
+        "B" | "b" => base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .ok()?,
+        _ => return None,
+    };
+    Some((decode_charset(charset, &bytes), len))
+}
+
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    // Drop an RFC 2231 language suffix: "utf-8*en"
+    let charset = charset.split('*').next().unwrap_or("").to_ascii_lowercase();
+    match charset.as_str() {
+        "iso-8859-1" | "latin1" | "iso-8859-15" => bytes.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Quoted-printable: "=XX" is a byte and "=" at the end of a line joins it
+/// with the next. Inside encoded words "_" stands for a space.
+fn decode_quoted_printable(bytes: &[u8], header: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'=' if bytes.get(i + 1) == Some(&b'\n') => i += 2,
+            b'=' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 3;
+                    }
+                    None => {
+                        out.push(b'=');
+                        i += 1;
+                    }
+                }
+            }
+            b'_' if header => {
+                out.push(b' ');
+                i += 1;
+            }
+            byte => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,509 @@
+// Three-way merge
+// - Merge base discovery over the commit graph
+// - Line-level diff3 content merge with conflict markers
+// - Tree-level merge of three snapshots (base, ours, theirs)
+
+use crate::git::commit;
+use crate::git::diff;
+use crate::git::index::{Entry, Index};
+use crate::git::object;
+use crate::git::reach::Reachability;
+use crate::git::tree::FileEntry;
+use crate::git::worktree::{self, Snapshot};
+use std::collections::{HashSet, VecDeque};
+use std::io;
+
+// ============================================================================
+// MERGE BASE
+// ============================================================================
+
+/// All best common ancestors of `a` and `b`: common ancestors that are not
+/// themselves ancestors of another common ancestor. Criss-cross histories
+/// have more than one.
+pub fn merge_bases(a: &str, b: &str) -> io::Result<Vec<String>> {
+    let from_a = ancestors(a)?;
+
+    // Walk back from `b`, stopping at the first common commit on each path
+    let mut candidates = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([b.to_string()]);
+    while let Some(sha) = queue.pop_front() {
+        if !seen.insert(sha.clone()) {
+            continue;
+        }
+        if from_a.contains(&sha) {
+            candidates.push(sha);
+            continue;
+        }
+        queue.extend(commit::read(&sha)?.parents);
+    }
+
+    // A candidate reachable from another candidate is not a *best* base
+    let mut reach = Reachability::new()?;
+    let mut bases = Vec::new();
+    for candidate in &candidates {
+        let mut redundant = false;
+        for other in &candidates {
+            if other != candidate && reach.is_ancestor(candidate, other)? {
+                redundant = true;
+                break;
+            }
+        }
+        if !redundant {
+            bases.push(candidate.clone());
+        }
+    }
+    Ok(bases)
+}
+
+/// Whether `ancestor` is reachable from `descendant` (a commit is its own ancestor)
+pub fn is_ancestor(ancestor: &str, descendant: &str) -> io::Result<bool> {
+    Reachability::new()?.is_ancestor(ancestor, descendant)
+}
+
+/// Every commit reachable from `start`, including itself
+pub fn ancestors(start: &str) -> io::Result<HashSet<String>> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![start.to_string()];
+    while let Some(sha) = stack.pop() {
+        if seen.insert(sha.clone()) {
+            stack.extend(commit::read(&sha)?.parents);
+        }
+    }
+    Ok(seen)
+}
+
+// ============================================================================
+// CONTENT MERGE
+// ============================================================================
+
+/// `merge.conflictStyle`: whether conflict hunks include the base version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStyle {
+    Merge,
+    Diff3,
+}
+
+impl ConflictStyle {
+    pub fn from_config(value: Option<&str>) -> ConflictStyle {
+        match value {
+            Some("diff3") | Some("zdiff3") => ConflictStyle::Diff3,
+            _ => ConflictStyle::Merge,
+        }
+    }
+}
+
+/// Names printed after the conflict markers
+pub struct Labels<'a> {
+    pub base: &'a str,
+    pub ours: &'a str,
+    pub theirs: &'a str,
+}
+
+/// Result of merging one file's content
+pub struct TextMerge {
+    pub content: Vec<u8>,
+    pub conflicts: usize,
+}
+
+/// diff3 merge of three versions of a file. Regions changed on only one side
+/// take that side; regions changed identically take either; anything else is
+/// written out between conflict markers.
+pub fn merge_text(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: &Labels,
+    style: ConflictStyle,
+) -> TextMerge {
+    let base_lines = diff::split_lines(base);
+    let our_lines = diff::split_lines(ours);
+    let their_lines = diff::split_lines(theirs);
+
+    // For each base line, the matching line index on each side (if kept)
+    let mut ours_match = vec![None; base_lines.len()];
+    for (b, o) in diff::matching_lines(&base_lines, &our_lines) {
+        ours_match[b] = Some(o);
+    }
+    let mut theirs_match = vec![None; base_lines.len()];
+    for (b, t) in diff::matching_lines(&base_lines, &their_lines) {
+        theirs_match[b] = Some(t);
+    }
+
+    let mut content = Vec::new();
+    let mut conflicts = 0;
+    let (mut b, mut o, mut t) = (0, 0, 0);
+
+    loop {
+        // Stable run: the base line survives unchanged in both sides
+        let mut run = 0;
+        while b + run < base_lines.len()
+            && ours_match[b + run] == Some(o + run)
+            && theirs_match[b + run] == Some(t + run)
+        {
+            run += 1;
+        }
+        if run > 0 {
+            for line in &base_lines[b..b + run] {
+                content.extend_from_slice(line);
+            }
+            b += run;
+            o += run;
+            t += run;
+            continue;
+        }
+
+        // Unstable chunk up to the next base line kept by both sides
+        let (b_end, o_end, t_end) = (b..base_lines.len())
+            .find_map(|i| match (ours_match[i], theirs_match[i]) {
+                (Some(oi), Some(ti)) => Some((i, oi, ti)),
+                _ => None,
+            })
+            .unwrap_or((base_lines.len(), our_lines.len(), their_lines.len()));
+
+        if b_end == b && o_end == o && t_end == t {
+            break;
+        }
+
+        let base_chunk = &base_lines[b..b_end];
+        let our_chunk = &our_lines[o..o_end];
+        let their_chunk = &their_lines[t..t_end];
+
+        if our_chunk == base_chunk || our_chunk == their_chunk {
+            push_lines(&mut content, their_chunk);
+        } else if their_chunk == base_chunk {
+            push_lines(&mut content, our_chunk);
+        } else {
+            conflicts += 1;
+            push_marker(&mut content, "<<<<<<<", labels.ours);
+            push_terminated(&mut content, our_chunk);
+            if style == ConflictStyle::Diff3 {
+                push_marker(&mut content, "|||||||", labels.base);
+                push_terminated(&mut content, base_chunk);
+            }
+            content.extend_from_slice(b"=======\n");
+            push_terminated(&mut content, their_chunk);
+            push_marker(&mut content, ">>>>>>>", labels.theirs);
+        }
+
+        b = b_end;
+        o = o_end;
+        t = t_end;
+    }
+
+    TextMerge { content, conflicts }
+}
+
+fn push_lines(content: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        content.extend_from_slice(line);
+    }
+}
+
+/// Lines inside a conflict hunk must end in a newline so the next marker
+/// starts on its own line
+fn push_terminated(content: &mut Vec<u8>, lines: &[&[u8]]) {
+    push_lines(content, lines);
+    if !content.is_empty() && !content.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+}
+
+fn push_marker(content: &mut Vec<u8>, marker: &str, label: &str) {
+    let line = if label.is_empty() {
+        format!("{}\n", marker)
+    } else {
+        format!("{} {}\n", marker, label)
+    };
+    content.extend_from_slice(line.as_bytes());
+}
+
+/// Whether content still contains an unresolved conflict marker line
+pub fn has_conflict_markers(content: &[u8]) -> bool {
+    diff::split_lines(content).iter().any(|line| {
+        line.starts_with(b"<<<<<<< ")
+            || line.starts_with(b">>>>>>> ")
+            || *line == b"=======\n"
+            || *line == b"======="
+    })
+}
+
+// ============================================================================
+// TREE MERGE
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both sides changed the same lines
+    Content,
+    /// Both sides added the path with different content
+    AddAdd,
+    /// One side modified the path, the other deleted it
+    ModifyDelete { deleted_by_ours: bool },
+    /// Both sides changed a file that can't be merged line by line
+    Binary,
+    /// One side has a file where the other has a directory; the file was
+    /// moved aside to `<path>~<label>`, which is the conflict's path
+    FileDirectory { file_in_ours: bool },
+}
+
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub path: String,
+    pub kind: ConflictKind,
+    /// The base, ours and theirs versions, staged at 1, 2 and 3 until the
+    /// conflict is resolved; `None` for a side without the file
+    pub stages: [Option<FileEntry>; 3],
+}
+
+pub struct TreeMerge {
+    /// What the working tree should contain, conflicted files included
+    pub files: Snapshot,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl TreeMerge {
+    /// Stage the sides of each conflict in place of the merged file, so
+    /// the paths show as unmerged until resolved. Call once `files` are in
+    /// the working tree and the index.
+    pub fn stage_conflicts(&self) -> io::Result<()> {
+        if self.conflicts.is_empty() {
+            return Ok(());
+        }
+        let mut index = Index::load()?;
+        for conflict in &self.conflicts {
+            index.add_conflict(&conflict.path, &conflict.stages);
+        }
+        index.write()
+    }
+
+    /// Undo `stage_conflicts` once the working tree and index have gone
+    /// back to `restored`: each conflicted path is staged as it is there
+    pub fn unstage_conflicts(&self, restored: &Snapshot) -> io::Result<()> {
+        if self.conflicts.is_empty() {
+            return Ok(());
+        }
+        let mut index = Index::load()?;
+        for conflict in &self.conflicts {
+            match restored.get(&conflict.path) {
+                Some(file) => index.add(Entry::checked_out(&conflict.path, file)?),
+                None => {
+                    index.remove(&conflict.path);
+                }
+            }
+        }
+        index.write()
+    }
+}
+
+/// Merge three snapshots path by path
+pub fn merge_trees(
+    base: &Snapshot,
+    ours: &Snapshot,
+    theirs: &Snapshot,
+    labels: &Labels,
+    style: ConflictStyle,
+) -> io::Result<TreeMerge> {
+    let mut paths: Vec<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut files = Snapshot::new();
+    let mut conflicts = Vec::new();
+
+    for path in paths {
+        let b = base.get(path);
+        let o = ours.get(path);
+        let t = theirs.get(path);
+
+        // Trivial cases: one side unchanged, or both changed identically
+        if o == t {
+            if let Some(entry) = o {
+                files.insert(path.clone(), entry.clone());
+            }
+            continue;
+        }
+        if o == b {
+            if let Some(entry) = t {
+                files.insert(path.clone(), entry.clone());
+            }
+            continue;
+        }
+        if t == b {
+            if let Some(entry) = o {
+                files.insert(path.clone(), entry.clone());
+            }
+            continue;
+        }
+
+        match (o, t) {
+            (Some(o), Some(t)) => {
+                let kind = if b.is_none() {
+                    ConflictKind::AddAdd
+                } else {
+                    ConflictKind::Content
+                };
+                let (entry, clean) = merge_file(path, b, o, t, labels, style, &mut conflicts)?;
+                files.insert(path.clone(), entry);
+                if !clean && !conflicts.iter().any(|c: &Conflict| &c.path == path) {
+                    conflicts.push(Conflict {
+                        path: path.clone(),
+                        kind,
+                        stages: [b.cloned(), Some(o.clone()), Some(t.clone())],
+                    });
+                }
+            }
+            // Modified on one side, deleted on the other: keep the modified
+            // version in the working tree so the user can decide
+            (Some(entry), None) | (None, Some(entry)) => {
+                files.insert(path.clone(), entry.clone());
+                conflicts.push(Conflict {
+                    path: path.clone(),
+                    kind: ConflictKind::ModifyDelete {
+                        deleted_by_ours: o.is_none(),
+                    },
+                    stages: [b.cloned(), o.cloned(), t.cloned()],
+                });
+            }
+            (None, None) => {}
+        }
+    }
+
+    move_files_out_of_directories(&mut files, &mut conflicts, ours, labels);
+    Ok(TreeMerge { files, conflicts })
+}
+
+/// Where the merged tree has a file at a path that is also a directory of
+/// other merged files, move the file (which must have come from one side
+/// alone) to `<path>~<label of that side>` and record the conflict there
+fn move_files_out_of_directories(
+    files: &mut Snapshot,
+    conflicts: &mut Vec<Conflict>,
+    ours: &Snapshot,
+    labels: &Labels,
+) {
+    let in_the_way: Vec<String> = files
+        .keys()
+        .filter(|path| {
+            let dir = format!("{}/", path);
+            files
+                .range(dir.clone()..)
+                .next()
+                .is_some_and(|(next, _)| next.starts_with(&dir))
+        })
+        .cloned()
+        .collect();
+    for path in in_the_way {
+        let Some(entry) = files.remove(&path) else {
+            continue;
+        };
+        let file_in_ours = ours.get(&path) == Some(&entry);
+        let label = if file_in_ours {
+            labels.ours
+        } else {
+            labels.theirs
+        };
+        let base = format!("{}~{}", path, label.replace('/', "_"));
+        let mut moved = base.clone();
+        let mut suffix = 0;
+        while files.contains_key(&moved) {
+            suffix += 1;
+            moved = format!("{}_{}", base, suffix);
+        }
+        match conflicts.iter_mut().find(|conflict| conflict.path == path) {
+            Some(conflict) => conflict.path = moved.clone(),
+            None => conflicts.push(Conflict {
+                path: moved.clone(),
+                kind: ConflictKind::FileDirectory { file_in_ours },
+                // Staged as the side it came from, at its new path
+                stages: match file_in_ours {
+                    true => [None, Some(entry.clone()), None],
+                    false => [None, None, Some(entry.clone())],
+                },
+            }),
+        }
+        files.insert(moved, entry);
+    }
+}
+
+/// Merge the trees of two commits against the tree of `base` (the empty tree
+/// when there is none)
+pub fn merge_commits(
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    labels: &Labels,
+    style: ConflictStyle,
+) -> io::Result<TreeMerge> {
+    let base = worktree::snapshot_of_commit(base)?;
+    let ours = worktree::snapshot_of_commit(Some(ours))?;
+    let theirs = worktree::snapshot_of_commit(Some(theirs))?;
+    merge_trees(&base, &ours, &theirs, labels, style)
+}
+
+/// Content-merge one path changed on both sides. Returns the entry to use and
+/// whether it merged cleanly.
+fn merge_file(
+    path: &str,
+    base: Option<&FileEntry>,
+    ours: &FileEntry,
+    theirs: &FileEntry,
+    labels: &Labels,
+    style: ConflictStyle,
+    conflicts: &mut Vec<Conflict>,
+) -> io::Result<(FileEntry, bool)> {
+    // Mode: take whichever side changed it; disagreement keeps ours
+    let base_mode = base.map(|e| e.mode.as_str());
+    let mode = if Some(ours.mode.as_str()) == base_mode {
+        theirs.mode.clone()
+    } else {
+        ours.mode.clone()
+    };
+
+    if ours.sha == theirs.sha {
+        let clean = ours.mode == theirs.mode || base_mode == Some(ours.mode.as_str());
+        return Ok((
+            FileEntry {
+                mode,
+                sha: ours.sha.clone(),
+            },
+            clean,
+        ));
+    }
+
+    let mergeable = |e: &FileEntry| e.mode.starts_with("100");
+    let base_content = match base {
+        Some(entry) if mergeable(entry) => object::read_expecting(&entry.sha, "blob")?,
+        _ => Vec::new(),
+    };
+    if !mergeable(ours) || !mergeable(theirs) {
+        conflicts.push(Conflict {
+            path: path.to_string(),
+            kind: ConflictKind::Binary,
+            stages: [base.cloned(), Some(ours.clone()), Some(theirs.clone())],
+        });
+        return Ok((ours.clone(), false));
+    }
+
+    let our_content = object::read_expecting(&ours.sha, "blob")?;
+    let their_content = object::read_expecting(&theirs.sha, "blob")?;
+    if diff::is_binary(&base_content)
+        || diff::is_binary(&our_content)
+        || diff::is_binary(&their_content)
+    {
+        conflicts.push(Conflict {
+            path: path.to_string(),
+            kind: ConflictKind::Binary,
+            stages: [base.cloned(), Some(ours.clone()), Some(theirs.clone())],
+        });
+        return Ok((ours.clone(), false));
+    }
+
+    let merged = merge_text(&base_content, &our_content, &their_content, labels, style);
+    let sha = object::write("blob", &merged.content)?;
+    Ok((FileEntry { mode, sha }, merged.conflicts == 0))
+}
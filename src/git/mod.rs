@@ -1 +1,50 @@
+pub mod advertise;
+pub mod attributes;
+pub mod commit;
+pub mod commit_graph;
+pub mod config;
+pub mod conventional;
+pub mod convert;
+pub mod credential;
+pub mod daemon;
+pub mod date;
+pub mod diff;
+pub mod dry_run;
+pub mod dumb_http;
+pub mod encoding;
+pub mod fsync;
+#[cfg(feature = "strict")]
+pub mod fuzz;
+pub mod hooks;
+pub mod http;
+pub mod ident;
+pub mod ignore;
+pub mod index;
+pub mod interrupt;
+pub mod mailbox;
+pub mod merge;
+pub mod negotiate;
+pub mod netrc;
 pub mod object;
+pub mod pack;
+pub mod patch;
+pub mod pickaxe;
+pub mod pktline;
+pub mod progress;
+pub mod promisor;
+pub mod reach;
+pub mod reflog;
+pub mod refs;
+pub mod refspec;
+pub mod regex;
+pub mod repository;
+pub mod revision;
+pub mod revwalk;
+pub mod shallow;
+pub mod signature;
+pub mod ssh;
+pub mod submodule;
+pub mod tag;
+pub mod tree;
+pub mod url;
+pub mod worktree;
@@ -0,0 +1,151 @@
+// Fetch negotiation
+// Before the server packs what a fetch wants, the client tells it which
+// commits it already has, so the pack can leave out everything they reach.
+// The haves are the local commits, newest first by committer date from the
+// ref tips, sent in rounds: 16 to start with, then twice as many each round
+// (to 32 and then 32 more each round over a connection that stays open;
+// stateless requests carry everything from before again, so they keep
+// doubling up to 16384 and grow by a tenth after that). The server
+// acknowledges each have it has too, and says "ready" once those cover the
+// wants. A commit acknowledged as common is not walked past, its ancestors
+// being common as well. Negotiation ends when the server is ready, when
+// there are no commits left to offer, or when 256 haves have gone by
+// without a new acknowledgement after the first one, however many the
+// history still holds.
+
+use crate::git::commit;
+use crate::git::object;
+use crate::git::revision;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::io;
+
+/// Haves in the first round
+pub const INITIAL_FLUSH: usize = 16;
+/// Where a connection that stays open stops doubling the rounds
+const PIPESAFE_FLUSH: usize = 32;
+/// Where stateless requests stop doubling the rounds
+const LARGE_FLUSH: usize = 16384;
+/// Haves sent in vain since the last new acknowledgement before giving up
+const MAX_IN_VAIN: usize = 256;
+
+/// How many haves to send in the round after one of `count`
+pub fn next_flush(stateless: bool, count: usize) -> usize {
+    match stateless {
+        true if count < LARGE_FLUSH => count * 2,
+        true => count * 11 / 10,
+        false if count < PIPESAFE_FLUSH => count * 2,
+        false => count + PIPESAFE_FLUSH,
+    }
+}
+
+/// The local side of a negotiation: which commits are left to offer and
+/// which the server has acknowledged
+#[derive(Debug, Default)]
+pub struct Negotiator {
+    queue: BinaryHeap<Queued>,
+    seen: HashSet<String>,
+    common: HashSet<String>,
+    /// Acknowledged haves, in the order they were acknowledged
+    acked: Vec<String>,
+    /// Haves sent since the last new acknowledgement, once there was one
+    in_vain: Option<usize>,
+    /// Tie-breaker for commits with the same date
+    order: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Queued {
+    time: i64,
+    order: Reverse<usize>,
+    sha: String,
+    parents: Vec<String>,
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.order).cmp(&(other.time, other.order))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Negotiator {
+    /// Start from the local ref tips `tips`; tags count as the commits they
+    /// point at, and tips that aren't commits at all are left out
+    pub fn new(tips: &[String]) -> io::Result<Negotiator> {
+        let mut negotiator = Negotiator::default();
+        for tip in tips {
+            if let Some(sha) = revision::peel(tip, "commit")? {
+                negotiator.enqueue(&sha)?;
+            }
+        }
+        Ok(negotiator)
+    }
+
+    fn enqueue(&mut self, sha: &str) -> io::Result<()> {
+        // History can stop short of a root here, as in a shallow clone
+        if !self.seen.insert(sha.to_string()) || object::locate(sha)?.is_none() {
+            return Ok(());
+        }
+        let commit = commit::read(sha)?;
+        self.order += 1;
+        self.queue.push(Queued {
+            time: commit.commit_time(),
+            order: Reverse(self.order),
+            sha: sha.to_string(),
+            parents: commit.parents,
+        });
+        Ok(())
+    }
+
+    /// The next (up to) `count` haves to offer; empty once there is nothing
+    /// left worth offering
+    pub fn next_batch(&mut self, count: usize) -> io::Result<Vec<String>> {
+        let mut batch = Vec::new();
+        while batch.len() < count && !self.gave_up() {
+            let Some(queued) = self.queue.pop() else {
+                break;
+            };
+            if self.common.contains(&queued.sha) {
+                // Whatever it reaches is common too
+                self.common.extend(queued.parents);
+                continue;
+            }
+            for parent in &queued.parents {
+                self.enqueue(parent)?;
+            }
+            if let Some(in_vain) = self.in_vain.as_mut() {
+                *in_vain += 1;
+            }
+            batch.push(queued.sha);
+        }
+        Ok(batch)
+    }
+
+    /// Take in the server's acknowledgement of `sha`
+    pub fn ack(&mut self, sha: &str) -> io::Result<()> {
+        // Only what was offered can be acknowledged
+        if !self.seen.contains(sha) || !self.common.insert(sha.to_string()) {
+            return Ok(());
+        }
+        self.acked.push(sha.to_string());
+        self.common.extend(commit::read(sha)?.parents);
+        self.in_vain = Some(0);
+        Ok(())
+    }
+
+    /// The haves the server has acknowledged, to repeat in a stateless
+    /// request
+    pub fn acked(&self) -> &[String] {
+        &self.acked
+    }
+
+    fn gave_up(&self) -> bool {
+        self.in_vain.is_some_and(|in_vain| in_vain >= MAX_IN_VAIN)
+    }
+}
@@ -0,0 +1,109 @@
+// .netrc lookup
+// Like curl under git, a remote that wants a user name and password and
+// wasn't given a password in the URL is first tried with the entry for its
+// host in `~/.netrc` (or `~/_netrc`, where there is no .netrc). The file is
+// a run of whitespace-separated tokens:
+//
+//   machine example.com login me password secret
+//   default login anonymous password me@example.com
+//
+// `machine` starts the entry for a host, `default` the one for every host
+// not named before it; `login` and `password` fill it in and the other
+// keywords curl knows (`account`, and `macdef` with the macro after it up to
+// a blank line) are passed over. Hosts are matched without a port, case
+// insensitively. When the URL names a user, only an entry for that login
+// will do.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// One machine's (or the default) login and password
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Entry {
+    /// `None` for the default entry
+    machine: Option<String>,
+    login: Option<String>,
+    password: Option<String>,
+}
+
+/// The login and password .netrc has for `host`, for `user` if the URL
+/// names one
+pub fn lookup(host: &str, user: Option<&str>) -> io::Result<Option<(String, String)>> {
+    let Some(text) = read()? else {
+        return Ok(None);
+    };
+    let found = parse(&text).into_iter().find(|entry| {
+        let host_matches = entry
+            .machine
+            .as_deref()
+            .map_or(true, |machine| machine.eq_ignore_ascii_case(host));
+        let user_matches = user.map_or(true, |user| entry.login.as_deref() == Some(user));
+        host_matches && user_matches && entry.password.is_some()
+    });
+    Ok(found.map(|entry| {
+        let login = entry.login.or(user.map(str::to_string)).unwrap_or_default();
+        (login, entry.password.unwrap_or_default())
+    }))
+}
+
+fn read() -> io::Result<Option<String>> {
+    let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) else {
+        return Ok(None);
+    };
+    for name in [".netrc", "_netrc"] {
+        match fs::read_to_string(PathBuf::from(&home).join(name)) {
+            Ok(text) => return Ok(Some(text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// The entries of a .netrc file, in file order. Nothing after a default
+/// entry counts, as curl doesn't look past it.
+fn parse(text: &str) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    // The keyword whose value is the next token
+    let mut keyword: Option<&str> = None;
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        for word in line.split_whitespace() {
+            if let Some(key) = keyword.take() {
+                let value = Some(word.to_string());
+                if key == "machine" {
+                    entries.push(Entry {
+                        machine: value,
+                        ..Entry::default()
+                    });
+                    continue;
+                }
+                match (key, entries.last_mut()) {
+                    ("login", Some(entry)) => entry.login = value,
+                    ("password", Some(entry)) => entry.password = value,
+                    _ => {}
+                }
+                continue;
+            }
+            let after_default = entries.last().is_some_and(|e| e.machine.is_none());
+            match word {
+                "machine" | "default" if after_default => return entries,
+                "default" => entries.push(Entry::default()),
+                "machine" | "login" | "password" | "account" => keyword = Some(word),
+                "macdef" => {
+                    // The macro runs up to the next blank line
+                    for line in lines.by_ref() {
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                    }
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+    entries
+}
@@ -1,10 +1,19 @@
+use crate::git::fsync::{self, Component};
+use crate::git::pack::{self, Pack};
+use crate::git::promisor;
+use crate::git::repository;
 use flate2::read::{ZlibDecoder, ZlibEncoder};
 use flate2::Compression;
 use sha1_smol::Sha1;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::io::Read;
-use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// How many repositories deep one borrowing objects from another can go
+const MAX_ALTERNATE_DEPTH: usize = 5;
 
 #[derive(Debug)]
 pub enum Error {
@@ -13,12 +22,124 @@ pub enum Error {
     Decompression(String),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::InvalidFormat(msg) => write!(f, "invalid object format: {}", msg),
+            Error::Decompression(msg) => write!(f, "decompression failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
         Error::Io(err)
     }
 }
 
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            other => io::Error::new(io::ErrorKind::InvalidData, other.to_string()),
+        }
+    }
+}
+
+/// Read an object of any type, returning its type and content (header stripped)
+pub fn read(object_id: &str) -> Result<(String, Vec<u8>), Error> {
+    let decompressed = read_object(object_id, 40)?;
+
+    let null_pos = decompressed
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| {
+            Error::InvalidFormat(format!("No null byte found in object {}", object_id))
+        })?;
+    let header = String::from_utf8_lossy(&decompressed[..null_pos]);
+    let (kind, size) = header
+        .split_once(' ')
+        .ok_or_else(|| Error::InvalidFormat(format!("Invalid header in object {}", object_id)))?;
+    let size: usize = size.parse().map_err(|_| {
+        Error::InvalidFormat(format!("Invalid size in header for object {}", object_id))
+    })?;
+
+    let content = decompressed[null_pos + 1..].to_vec();
+    if content.len() != size {
+        return Err(Error::InvalidFormat(format!(
+            "Object {} is {} bytes but its header says {}",
+            object_id,
+            content.len(),
+            size
+        )));
+    }
+
+    Ok((kind.to_string(), content))
+}
+
+/// Open an object to read its content as a stream, along with its type and
+/// size. A loose object is inflated as it is read; a packed one, which may
+/// have to be rebuilt from deltas, is read whole first.
+pub fn open(object_id: &str) -> Result<(String, u64, Box<dyn Read>), Error> {
+    let Some(Location::Loose(path)) = locate(object_id)? else {
+        let (kind, content) = read(object_id)?;
+        let size = content.len() as u64;
+        return Ok((kind, size, Box::new(io::Cursor::new(content))));
+    };
+    let mut decoder = ZlibDecoder::new(io::BufReader::new(fs::File::open(path)?));
+    let mut header = Vec::new();
+    let mut byte = [0];
+    while header.len() <= 32 {
+        decoder
+            .read_exact(&mut byte)
+            .map_err(|err| Error::Decompression(err.to_string()))?;
+        if byte[0] == 0 {
+            break;
+        }
+        header.push(byte[0]);
+    }
+    let invalid = || Error::InvalidFormat(format!("Invalid header in object {}", object_id));
+    let header = String::from_utf8_lossy(&header);
+    let (kind, size) = header.split_once(' ').ok_or_else(invalid)?;
+    let size: u64 = size.parse().map_err(|_| invalid())?;
+    Ok((kind.to_string(), size, Box::new(decoder.take(size))))
+}
+
+/// Read an object and check that it has the expected type
+pub fn read_expecting(object_id: &str, expected: &str) -> Result<Vec<u8>, Error> {
+    let (kind, content) = read(object_id)?;
+    if kind != expected {
+        return Err(Error::InvalidFormat(format!(
+            "Object {} is a {}, expected {}",
+            object_id, kind, expected
+        )));
+    }
+    Ok(content)
+}
+
+/// Compute the object id of `content` stored as an object of type `kind`
+pub fn hash(kind: &str, content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{} {}\0", kind, content.len()).as_bytes());
+    hasher.update(content);
+    hasher.digest().to_string()
+}
+
+/// Store `content` as an object of type `kind`, returning its id
+pub fn write(kind: &str, content: &[u8]) -> Result<String, Error> {
+    let header = format!("{} {}\0", kind, content.len());
+    let mut store = Vec::with_capacity(header.len() + content.len());
+    store.extend_from_slice(header.as_bytes());
+    store.extend_from_slice(content);
+
+    let hash = hash(kind, content);
+    write_blob(&store, &hash)?;
+    Ok(hash)
+}
+
 pub fn read_blob(object_id: &str) -> Result<(String, usize, Vec<u8>), Error> {
     let decompressed = read_object(object_id, 40)?;
 
@@ -31,11 +152,9 @@ pub fn read_blob(object_id: &str) -> Result<(String, usize, Vec<u8>), Error> {
     // validate header
     let header = String::from_utf8_lossy(&decompressed[..null_pos]);
     let header_parts: Vec<&str> = header.split_whitespace().collect();
-    if header_parts.len() != 2
-        || (header_parts[0] != "blob" && header_parts[0] != "tree" && header_parts[0] != "commit")
-    {
+    if header_parts.len() != 2 || !matches!(header_parts[0], "blob" | "tree" | "commit" | "tag") {
         return Err(Error::InvalidFormat(
-            "Expected blob, tree, or commit header".to_string(),
+            "Expected blob, tree, commit or tag header".to_string(),
         ));
     }
 
@@ -80,22 +199,20 @@ pub fn read_tree_object(object_id: &str) -> Result<(String, usize, Vec<u8>), Err
 pub fn write_blob(blob_data: &[u8], hash: &str) -> Result<(), Error> {
     let dir_name = &hash[..2];
     let object_hash = &hash[2..];
-    let path = Path::new(".git/objects").join(dir_name).join(object_hash);
+    let path = repository::path("objects").join(dir_name).join(object_hash);
 
     let dir = path
         .parent()
-        .ok_or_else(|| Error::Io(io::Error::new(io::ErrorKind::Other, "Invalid path")))?;
+        .ok_or_else(|| Error::Io(io::Error::other("Invalid path")))?;
     fs::create_dir_all(dir).map_err(Error::Io)?;
 
     // Compress the blob data
-    let mut encoder = ZlibEncoder::new(&blob_data[..], Compression::default());
+    let mut encoder = ZlibEncoder::new(blob_data, Compression::default());
     let mut compressed = Vec::new();
-    encoder
-        .read_to_end(&mut compressed)
-        .map_err(|err| Error::Io(err))?;
+    encoder.read_to_end(&mut compressed).map_err(Error::Io)?;
 
     // Write to file
-    fs::write(&path, &compressed).map_err(Error::Io)?;
+    fsync::write(&path, &compressed, Component::LooseObject).map_err(Error::Io)?;
 
     Ok(())
 }
@@ -108,12 +225,37 @@ fn read_object(object_id: &str, expected_hash_size: usize) -> Result<Vec<u8>, Er
             object_id.len()
         )));
     }
+    if !object_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::InvalidFormat(format!(
+            "Invalid object id: {}",
+            object_id
+        )));
+    }
 
     let dir_name = &object_id[..2];
     let object_hash = &object_id[2..];
-    let path = Path::new(".git/objects").join(dir_name).join(object_hash);
+    let path = repository::path("objects").join(dir_name).join(object_hash);
 
-    let content = fs::read(&path).map_err(Error::Io)?;
+    let content = match fs::read(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            // Not loose; packed objects come back with the same header
+            if let Some((kind, content)) = pack::read_object(object_id)? {
+                let mut object = format!("{} {}\0", kind, content.len()).into_bytes();
+                object.extend(content);
+                return Ok(object);
+            }
+            match borrowed_loose(object_id)? {
+                Some(path) => fs::read(path)?,
+                // A partial clone may have left it out, to be fetched now
+                None if promisor::fetch_missing(object_id)? => {
+                    return read_object(object_id, expected_hash_size)
+                }
+                None => return Err(Error::Io(e)),
+            }
+        }
+        Err(e) => return Err(Error::Io(e)),
+    };
 
     let mut decoder = ZlibDecoder::new(&content[..]);
     let mut decompressed = Vec::new();
@@ -144,3 +286,251 @@ pub fn create_file_hash(file_path: &str, should_write: bool) -> Result<String, E
 
     Ok(hash)
 }
+
+/// Where an object is kept
+pub enum Location {
+    Loose(PathBuf),
+    Packed { pack: Arc<Pack>, offset: u64 },
+}
+
+/// An object in the database and where it is kept
+pub struct StoredObject {
+    pub id: String,
+    pub location: Location,
+}
+
+/// Where `sha` is kept, preferring a loose copy as reading does
+pub fn locate(sha: &str) -> io::Result<Option<Location>> {
+    if sha.len() != 40 || !sha.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    let path = repository::path("objects").join(&sha[..2]).join(&sha[2..]);
+    if path.is_file() {
+        return Ok(Some(Location::Loose(path)));
+    }
+    for pack in pack::packs()? {
+        if let Some(offset) = pack.find(sha) {
+            return Ok(Some(Location::Packed { pack, offset }));
+        }
+    }
+    Ok(borrowed_loose(sha)?.map(Location::Loose))
+}
+
+/// Where `sha` is kept as `locate` finds it, fetching it first if a partial
+/// clone left it out, as reading it would
+pub fn find(sha: &str) -> io::Result<Option<Location>> {
+    match locate(sha)? {
+        None if promisor::fetch_missing(sha)? => locate(sha),
+        location => Ok(location),
+    }
+}
+
+/// The object directories this repository borrows objects from: those
+/// listed in `.git/objects/info/alternates`, one per line, then those they
+/// borrow from in turn. A relative entry is relative to the objects
+/// directory listing it.
+pub fn alternates() -> io::Result<Vec<PathBuf>> {
+    let local = std::env::current_dir()?.join(repository::path("objects"));
+    let mut dirs = vec![(fs::canonicalize(&local).unwrap_or(local), 0)];
+    let mut next = 0;
+    while let Some((dir, depth)) = dirs.get(next).cloned() {
+        next += 1;
+        let list = match fs::read_to_string(dir.join("info/alternates")) {
+            Ok(list) => list,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if depth == MAX_ALTERNATE_DEPTH {
+            continue;
+        }
+        for line in list.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Ok(path) = fs::canonicalize(dir.join(line)) else {
+                continue;
+            };
+            if !dirs.iter().any(|(known, _)| *known == path) {
+                dirs.push((path, depth + 1));
+            }
+        }
+    }
+    Ok(dirs.into_iter().skip(1).map(|(dir, _)| dir).collect())
+}
+
+/// Where `sha` is kept loose in a repository this one borrows from
+fn borrowed_loose(sha: &str) -> io::Result<Option<PathBuf>> {
+    for dir in alternates()? {
+        let path = dir.join(&sha[..2]).join(&sha[2..]);
+        if path.is_file() {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// The ids of every object starting with the hex `prefix` (at least two
+/// digits), as abbreviated names are looked up
+pub fn find_by_prefix(prefix: &str) -> io::Result<Vec<String>> {
+    let prefix = prefix.to_ascii_lowercase();
+    let mut ids = Vec::new();
+    if prefix.len() < 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Ok(ids);
+    }
+    let mut dirs = vec![repository::path("objects")];
+    dirs.extend(alternates()?);
+    for dir in dirs {
+        match fs::read_dir(dir.join(&prefix[..2])) {
+            Ok(entries) => {
+                for entry in entries.filter_map(|entry| entry.ok()) {
+                    let id = format!("{}{}", &prefix[..2], entry.file_name().to_string_lossy());
+                    if id.len() == 40 && id.starts_with(&prefix) {
+                        ids.push(id);
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    for pack in pack::packs()? {
+        // Pack indexes are sorted by id
+        let entries = pack.entries();
+        let start = entries.partition_point(|(id, _)| id.as_str() < prefix.as_str());
+        ids.extend(
+            entries[start..]
+                .iter()
+                .take_while(|(id, _)| id.starts_with(&prefix))
+                .map(|(id, _)| id.clone()),
+        );
+    }
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
+
+/// The shortest prefix of `sha`, at least `length` long, naming only it
+pub fn abbreviate(sha: &str, length: usize) -> io::Result<&str> {
+    for length in length..sha.len() {
+        if find_by_prefix(&sha[..length])?.len() <= 1 {
+            return Ok(&sha[..length]);
+        }
+    }
+    Ok(sha)
+}
+
+/// Every object in the database: loose objects by directory, then each
+/// pack's in index order. An object kept in several places is listed for
+/// each of them.
+pub fn all_objects() -> io::Result<impl Iterator<Item = StoredObject>> {
+    let mut objects = Vec::new();
+    for_each_loose(|id, path| {
+        objects.push(StoredObject {
+            id,
+            location: Location::Loose(path),
+        })
+    })?;
+    for pack in pack::packs()? {
+        for (id, offset) in pack.entries() {
+            objects.push(StoredObject {
+                id: id.clone(),
+                location: Location::Packed {
+                    pack: Arc::clone(&pack),
+                    offset: *offset,
+                },
+            });
+        }
+    }
+    Ok(objects.into_iter())
+}
+
+/// How the loose objects spread over the 256 fan-out directories
+/// `.git/objects/00` to `ff`, named by an id's first byte
+#[derive(Debug, Clone)]
+pub struct LooseStatistics {
+    pub count: usize,
+    /// Bytes the object files take up, compressed
+    pub disk_size: u64,
+    /// Objects in each directory
+    pub shards: [usize; 256],
+}
+
+impl LooseStatistics {
+    /// The directory holding the most objects, and how many it holds
+    pub fn fullest_shard(&self) -> (u8, usize) {
+        let (shard, count) = self
+            .shards
+            .iter()
+            .enumerate()
+            .max_by_key(|(shard, count)| (**count, std::cmp::Reverse(*shard)))
+            .unwrap_or((0, &0));
+        (shard as u8, *count)
+    }
+
+    /// Mean objects per directory, empty ones included
+    pub fn mean_per_shard(&self) -> f64 {
+        self.count as f64 / self.shards.len() as f64
+    }
+}
+
+pub fn loose_statistics() -> io::Result<LooseStatistics> {
+    let mut stats = LooseStatistics {
+        count: 0,
+        disk_size: 0,
+        shards: [0; 256],
+    };
+    let mut sizes = Ok(());
+    for_each_loose(|id, path| {
+        stats.count += 1;
+        if let Ok(shard) = u8::from_str_radix(&id[..2], 16) {
+            stats.shards[shard as usize] += 1;
+        }
+        match fs::metadata(&path) {
+            Ok(meta) => stats.disk_size += meta.len(),
+            Err(e) => sizes = Err(e),
+        }
+    })?;
+    sizes?;
+    Ok(stats)
+}
+
+/// Call `visit` with the id and path of every loose object, skipping
+/// anything in the object directories that isn't named like one
+fn for_each_loose(mut visit: impl FnMut(String, PathBuf)) -> io::Result<()> {
+    let is_hex = |name: &str, len: usize| {
+        name.len() == len && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    let mut shards: Vec<(String, PathBuf)> = match fs::read_dir(repository::path("objects")) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.path(),
+                )
+            })
+            .filter(|(name, _)| is_hex(name, 2))
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    shards.sort();
+
+    for (prefix, dir) in shards {
+        let mut names: Vec<(String, PathBuf)> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.path(),
+                )
+            })
+            .filter(|(name, _)| is_hex(name, 38))
+            .collect();
+        names.sort();
+        for (name, path) in names {
+            visit(format!("{}{}", prefix, name), path);
+        }
+    }
+    Ok(())
+}
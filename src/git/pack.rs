@@ -0,0 +1,1401 @@
+// Pack files
+// A pack is "PACK" <version 2> <object count>, then every object as a
+// variable-length type/size header followed by its zlib-compressed content,
+// then the SHA-1 of all of the above. Packs written here store objects
+// whole, without deltas, which any receiver accepts.
+//
+// Packs kept under `.git/objects/pack` are found through their `.idx`
+// (object ids in order, each with its offset in the pack). Their objects
+// may be deltas against an earlier object in the pack (OFS_DELTA) or
+// against one named by id (REF_DELTA), replayed on top of the base. Objects
+// are read from the pack file where the index puts them, so a pack is never
+// held in memory whole. A pack fetched from elsewhere is kept that way too,
+// once its index has been worked out.
+
+use crate::git::commit;
+use crate::git::config::Config;
+use crate::git::object;
+use crate::git::repository;
+use crate::git::tree::{self, MODE_GITLINK};
+use flate2::bufread::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha1_smol::Sha1;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// Longest delta chain followed before the pack is taken to be corrupt
+const MAX_DELTA_DEPTH: usize = 10_000;
+
+/// Most packs `packs` keeps open between calls; those used least recently
+/// are closed first
+const MAX_OPEN_PACKS: usize = 32;
+
+/// The most bytes an object's type/size header and delta base take up in a
+/// pack: a 64-bit size, then an offset or a 20-byte id
+const MAX_ENTRY_HEADER: usize = 10 + 20;
+
+/// Most memory reserved up front for an object whose size comes from the
+/// data being parsed; anything bigger grows as it is read
+const MAX_PREALLOCATION: usize = 16 << 20;
+
+/// Default for transfer.maxObjectSize
+const DEFAULT_MAX_OBJECT_SIZE: u64 = 1 << 30;
+
+/// Default for transfer.maxUnpackedSize
+const DEFAULT_MAX_UNPACKED_SIZE: u64 = 16 << 30;
+
+/// How big the objects of a pack received from another repository may be,
+/// so that data compressing to almost nothing can't exhaust memory or disk:
+/// transfer.maxObjectSize for any one object (1 GiB by default) and
+/// transfer.maxUnpackedSize for all of them together (16 GiB), 0 lifting a
+/// limit. An object's declared size is checked before it is inflated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_object_size: u64,
+    pub max_unpacked_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_object_size: DEFAULT_MAX_OBJECT_SIZE,
+            max_unpacked_size: DEFAULT_MAX_UNPACKED_SIZE,
+        }
+    }
+}
+
+impl Limits {
+    pub fn from_config(config: &Config) -> io::Result<Limits> {
+        let limit = |key: &str, default: u64| -> io::Result<u64> {
+            Ok(match config.get_int(key)? {
+                None => default,
+                Some(0) => u64::MAX,
+                Some(limit) => u64::try_from(limit).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("bad numeric config value '{}' for '{}'", limit, key),
+                    )
+                })?,
+            })
+        };
+        Ok(Limits {
+            max_object_size: limit("transfer.maxObjectSize", DEFAULT_MAX_OBJECT_SIZE)?,
+            max_unpacked_size: limit("transfer.maxUnpackedSize", DEFAULT_MAX_UNPACKED_SIZE)?,
+        })
+    }
+
+    /// No limits, for packs of our own
+    pub fn none() -> Limits {
+        Limits {
+            max_object_size: u64::MAX,
+            max_unpacked_size: u64::MAX,
+        }
+    }
+
+    /// Refuse an object that is, or says it is, `size` bytes
+    pub fn check_object(&self, size: u64) -> io::Result<()> {
+        if size > self.max_object_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "object of {} bytes exceeds transfer.maxObjectSize ({} bytes)",
+                    size, self.max_object_size
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What the objects of one received pack have come to so far, against its
+/// `Limits`
+#[derive(Debug)]
+pub struct Budget {
+    limits: Limits,
+    unpacked: u64,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Budget {
+        Budget {
+            limits,
+            unpacked: 0,
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Count an object of `size` bytes, refusing it if it is too big on its
+    /// own or takes the pack past its total
+    pub fn spend(&mut self, size: u64) -> io::Result<()> {
+        self.limits.check_object(size)?;
+        self.unpacked = self.unpacked.saturating_add(size);
+        if self.unpacked > self.limits.max_unpacked_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "objects unpacked exceed transfer.maxUnpackedSize ({} bytes)",
+                    self.limits.max_unpacked_size
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The objects stored in kept packs, which new packs leave out
+pub fn kept_objects() -> io::Result<HashSet<String>> {
+    let mut kept = HashSet::new();
+    for pack in packs()? {
+        if pack.is_kept() {
+            kept.extend(pack.entries().iter().map(|(id, _)| id.clone()));
+        }
+    }
+    Ok(kept)
+}
+
+/// Objects reachable from `tips` that aren't reachable from `known` (what
+/// the receiver already has), tips first. Known objects missing locally are
+/// ignored.
+pub fn objects_to_send(tips: &[String], known: &[String]) -> io::Result<Vec<String>> {
+    let mut have = HashSet::new();
+    for sha in known {
+        if object::read(sha).is_ok() {
+            walk(sha, &mut have, &mut Vec::new())?;
+        }
+    }
+
+    let mut objects = Vec::new();
+    for sha in tips {
+        walk(sha, &mut have, &mut objects)?;
+    }
+    Ok(objects)
+}
+
+/// Add `sha` and everything it references to `objects`, skipping objects in
+/// `seen` (which every visited object is added to)
+fn walk(sha: &str, seen: &mut HashSet<String>, objects: &mut Vec<String>) -> io::Result<()> {
+    let mut stack = vec![sha.to_string()];
+    while let Some(sha) = stack.pop() {
+        if !seen.insert(sha.clone()) {
+            continue;
+        }
+        let (kind, content) = object::read(&sha)?;
+        match kind.as_str() {
+            "commit" => {
+                let commit = commit::parse(&content)?;
+                stack.extend(commit.parents);
+                stack.push(commit.tree);
+            }
+            "tree" => {
+                for entry in tree::parse(&content)? {
+                    // Submodule commits live in another repository
+                    if entry.mode != MODE_GITLINK {
+                        stack.push(entry.sha);
+                    }
+                }
+            }
+            "tag" => {
+                let text = String::from_utf8_lossy(&content);
+                if let Some(target) = text.lines().next().and_then(|l| l.strip_prefix("object ")) {
+                    stack.push(target.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+        objects.push(sha);
+    }
+    Ok(())
+}
+
+/// Build a pack holding `objects`
+pub fn write(objects: &[String]) -> io::Result<Vec<u8>> {
+    let mut pack = Vec::new();
+    write_to(objects, &mut pack)?;
+    Ok(pack)
+}
+
+/// A pack holding `objects`, generated on another thread as it is read, so
+/// it never has to be held in memory whole
+pub fn stream(objects: Vec<String>) -> PackStream {
+    let (sender, receiver) = mpsc::sync_channel(STREAM_CHUNKS);
+    thread::spawn(move || {
+        let mut writer = ChunkWriter {
+            sender: sender.clone(),
+            buffer: Vec::with_capacity(STREAM_CHUNK_SIZE),
+        };
+        let result = write_to(&objects, &mut writer).and_then(|()| writer.flush());
+        if let Err(e) = result {
+            let _ = sender.send(Err(e));
+        }
+    });
+    PackStream {
+        receiver,
+        chunk: Vec::new(),
+        pos: 0,
+    }
+}
+
+/// Chunks in flight between the generating thread and the reader
+const STREAM_CHUNKS: usize = 4;
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The reading end of `stream`; a failure to generate the pack comes out
+/// as a read error
+pub struct PackStream {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for PackStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // The generator is done
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+struct ChunkWriter {
+    sender: SyncSender<io::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= STREAM_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(STREAM_CHUNK_SIZE));
+        self.sender
+            .send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "pack reader went away"))
+    }
+}
+
+/// Write a pack holding `objects` to `out`
+pub fn write_to(objects: &[String], out: &mut impl Write) -> io::Result<()> {
+    let mut pack = HashingWriter {
+        inner: out,
+        hasher: Sha1::new(),
+    };
+    pack.write_all(b"PACK")?;
+    pack.write_all(&2u32.to_be_bytes())?;
+    pack.write_all(&(objects.len() as u32).to_be_bytes())?;
+
+    for sha in objects {
+        let (kind, content) = object::read(sha)?;
+        let type_code: u8 = match kind.as_str() {
+            "commit" => 1,
+            "tree" => 2,
+            "blob" => 3,
+            "tag" => 4,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("object {} has unknown type {}", sha, other),
+                ))
+            }
+        };
+
+        // Type in bits 4-6 of the first byte, size in its low nibble and
+        // then 7 bits per byte, with the high bit marking continuation
+        let mut size = content.len();
+        let mut byte = (type_code << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+        let mut header = Vec::new();
+        while size > 0 {
+            header.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        header.push(byte);
+        pack.write_all(&header)?;
+
+        let mut encoder = ZlibEncoder::new(&mut pack, Compression::default());
+        encoder.write_all(&content)?;
+        encoder.finish()?;
+    }
+
+    let digest = pack.hasher.digest().bytes();
+    pack.inner.write_all(&digest)
+}
+
+/// Passes writes through, hashing everything written
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha1,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A pack under `.git/objects/pack`, its objects read from the file as
+/// they are asked for, at the offsets its index gives
+pub struct Pack {
+    pub path: PathBuf,
+    data: Mutex<Box<dyn PackData>>,
+    /// Length of the pack, its trailing checksum included
+    size: u64,
+    /// Object ids and their offsets, sorted by id
+    entries: Vec<(String, u64)>,
+    /// Every offset, sorted, for finding where an object ends
+    offsets: Vec<u64>,
+}
+
+/// How an object is stored in a pack
+#[derive(Debug, Clone)]
+pub struct PackedEntry {
+    /// Bytes the object takes up in the pack, its header included
+    pub disk_size: u64,
+    /// The object a delta is against; `None` for a whole object
+    pub delta_base: Option<String>,
+}
+
+/// The type/size header of a packed object
+struct EntryHeader {
+    type_code: u8,
+    size: usize,
+    base: Option<Base>,
+    /// Where the compressed data starts
+    data_start: u64,
+}
+
+enum Base {
+    Offset(u64),
+    Id(String),
+}
+
+/// Where a pack's bytes are read from: its file, or memory for one received
+/// whole
+trait PackData: BufRead + Seek + Send {}
+
+impl<T: BufRead + Seek + Send> PackData for T {}
+
+impl Pack {
+    /// Read `<name>.pack` and its `<name>.idx`
+    pub fn open(path: &Path) -> io::Result<Pack> {
+        let mut file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut signature = [0u8; 4];
+        if size < 32 || file.read_exact(&mut signature).is_err() || &signature != b"PACK" {
+            return Err(corrupt(path, "not a pack file"));
+        }
+        let index = fs::read(path.with_extension("idx"))?;
+        let entries = parse_index(&index).ok_or_else(|| corrupt(path, "bad pack index"))?;
+        Ok(Pack::new(
+            path.to_path_buf(),
+            Box::new(io::BufReader::new(file)),
+            size,
+            entries,
+        ))
+    }
+
+    fn new(path: PathBuf, data: Box<dyn PackData>, size: u64, entries: Vec<(String, u64)>) -> Pack {
+        let mut offsets: Vec<u64> = entries.iter().map(|(_, offset)| *offset).collect();
+        offsets.sort_unstable();
+        Pack {
+            path,
+            data: Mutex::new(data),
+            size,
+            entries,
+            offsets,
+        }
+    }
+
+    /// A pack received without an index, such as one a fetch downloads:
+    /// checked whole, then with every object read to work out its id. A
+    /// delta against an object outside the pack needs the repository to have
+    /// it. Malformed data of any kind is an error, as is an object beyond
+    /// `limits`.
+    pub fn from_bytes(data: Vec<u8>, limits: &Limits) -> io::Result<Pack> {
+        let mut pack = received(data, limits)?;
+        let pending = pack.offsets.clone();
+        resolve_objects(
+            &mut pack,
+            pending,
+            &mut Budget::new(*limits),
+            |kind, content| Ok(object::hash(kind, content)),
+        )?;
+        Ok(pack)
+    }
+
+    /// Every object in the pack as `(id, offset)`, sorted by id
+    pub fn entries(&self) -> &[(String, u64)] {
+        &self.entries
+    }
+
+    /// Whether a `.keep` file next to the pack asks for it to be left as it is
+    pub fn is_kept(&self) -> bool {
+        self.path.with_extension("keep").exists()
+    }
+
+    pub fn find(&self, sha: &str) -> Option<u64> {
+        self.entries
+            .binary_search_by(|(id, _)| id.as_str().cmp(sha))
+            .ok()
+            .map(|pos| self.entries[pos].1)
+    }
+
+    /// Type and content of the object at `offset`, with its deltas applied
+    pub fn read_at(&self, offset: u64) -> io::Result<(String, Vec<u8>)> {
+        self.read_limited(offset, &Limits::none())
+    }
+
+    /// `read_at`, refusing to inflate or build anything bigger than `limits`
+    /// allow
+    fn read_limited(&self, offset: u64, limits: &Limits) -> io::Result<(String, Vec<u8>)> {
+        let mut deltas = Vec::new();
+        let mut offset = offset;
+        let (kind, mut content) = loop {
+            if deltas.len() > MAX_DELTA_DEPTH {
+                return Err(corrupt(&self.path, "delta chain too long"));
+            }
+            let header = self.header(offset)?;
+            limits.check_object(header.size as u64)?;
+            let content = self.inflate(&header)?;
+            match header.base {
+                None => break (type_name(header.type_code, &self.path)?, content),
+                Some(Base::Offset(base)) => offset = base,
+                Some(Base::Id(id)) => match self.find(&id) {
+                    Some(base) => offset = base,
+                    None => {
+                        // A thin pack's base lives elsewhere in the repository
+                        deltas.push(content);
+                        break object::read(&id)?;
+                    }
+                },
+            }
+            deltas.push(content);
+        };
+        for delta in deltas.iter().rev() {
+            limits.check_object(delta_result_size(delta)? as u64)?;
+            content = apply_delta(&content, delta)?;
+        }
+        Ok((kind, content))
+    }
+
+    /// Storage details of the object at `offset`
+    pub fn entry(&self, offset: u64) -> io::Result<PackedEntry> {
+        let end = match self.offsets.partition_point(|&o| o <= offset) {
+            next if next < self.offsets.len() => self.offsets[next],
+            // The last object ends where the trailing checksum starts
+            _ => self.size - 20,
+        };
+        let delta_base = match self.header(offset)?.base {
+            None => None,
+            Some(Base::Id(id)) => Some(id),
+            Some(Base::Offset(base)) => self
+                .entries
+                .iter()
+                .find(|(_, o)| *o == base)
+                .map(|(id, _)| id.clone()),
+        };
+        Ok(PackedEntry {
+            disk_size: end.saturating_sub(offset),
+            delta_base,
+        })
+    }
+
+    /// Run `f` on the pack's bytes from `offset` on
+    fn read_from<T>(
+        &self,
+        offset: u64,
+        f: impl FnOnce(&mut dyn PackData) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut data = self
+            .data
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        data.seek(SeekFrom::Start(offset))?;
+        f(&mut **data)
+    }
+
+    fn header(&self, offset: u64) -> io::Result<EntryHeader> {
+        let mut buffer = [0u8; MAX_ENTRY_HEADER];
+        let end = offset
+            .saturating_add(MAX_ENTRY_HEADER as u64)
+            .min(self.size);
+        let len = end.saturating_sub(offset) as usize;
+        self.read_from(offset, |data| data.read_exact(&mut buffer[..len]))?;
+        let data = &buffer[..len];
+        let truncated = || corrupt(&self.path, "truncated object");
+        let mut pos = 0;
+        let mut byte = *data.get(pos).ok_or_else(truncated)?;
+        pos += 1;
+        let type_code = (byte >> 4) & 0x07;
+        let mut size = (byte & 0x0f) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = *data.get(pos).ok_or_else(truncated)?;
+            pos += 1;
+            if shift > 57 {
+                return Err(corrupt(&self.path, "object size too large"));
+            }
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        let base = match type_code {
+            OBJ_OFS_DELTA => {
+                let (distance, next) = read_ofs_delta_offset(data, pos)?;
+                pos = next;
+                let base = offset
+                    .checked_sub(distance as u64)
+                    .ok_or_else(|| corrupt(&self.path, "delta base before start of pack"))?;
+                Some(Base::Offset(base))
+            }
+            OBJ_REF_DELTA => {
+                let id = data.get(pos..pos + 20).ok_or_else(truncated)?;
+                pos += 20;
+                Some(Base::Id(hex::encode(id)))
+            }
+            _ => None,
+        };
+        Ok(EntryHeader {
+            type_code,
+            size,
+            base,
+            data_start: offset + pos as u64,
+        })
+    }
+
+    fn inflate(&self, header: &EntryHeader) -> io::Result<Vec<u8>> {
+        self.inflate_sized(header).map(|(content, _)| content)
+    }
+
+    /// The object's content and how many bytes of the pack it was
+    /// compressed into
+    fn inflate_sized(&self, header: &EntryHeader) -> io::Result<(Vec<u8>, u64)> {
+        let (content, used) = self
+            .read_from(header.data_start, |data| inflate_exact(data, header.size))
+            .map_err(|e| corrupt(&self.path, &e.to_string()))?;
+        Ok((content, used as u64))
+    }
+}
+
+/// Inflate the zlib stream at the start of `compressed`, which must come to
+/// exactly `size` bytes, returning them and how much of `compressed` the
+/// stream took up. Inflating stops a byte past `size`, so an entry claiming
+/// to be small can't make us inflate much more than it says.
+pub fn inflate_exact(compressed: impl BufRead, size: usize) -> io::Result<(Vec<u8>, usize)> {
+    let mut decoder = ZlibDecoder::new(compressed);
+    let mut content = Vec::with_capacity(size.min(MAX_PREALLOCATION));
+    (&mut decoder)
+        .take(size as u64 + 1)
+        .read_to_end(&mut content)?;
+    if content.len() != size {
+        let found = match content.len() > size {
+            true => "more".to_string(),
+            false => content.len().to_string(),
+        };
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("object size mismatch: expected {}, got {}", size, found),
+        ));
+    }
+    Ok((content, decoder.total_in() as usize))
+}
+
+/// Store every object of `data`, a pack received without an index, as a
+/// loose object, returning the ids. Deltas may be against objects already
+/// in the repository, as in a thin pack.
+pub fn unpack(data: Vec<u8>, limits: &Limits) -> io::Result<Vec<String>> {
+    let mut pack = received(data, limits)?;
+    unpack_pack(&mut pack, limits)
+}
+
+/// Write every object of `pack`, whose ids are yet to be worked out, as a
+/// loose object
+fn unpack_pack(pack: &mut Pack, limits: &Limits) -> io::Result<Vec<String>> {
+    let mut ids = Vec::with_capacity(pack.offsets.len());
+    let pending = pack.offsets.clone();
+    resolve_objects(pack, pending, &mut Budget::new(*limits), |kind, content| {
+        let id = object::write(kind, content)?;
+        ids.push(id.clone());
+        Ok(id)
+    })?;
+    Ok(ids)
+}
+
+/// Keep the pack read from `input`, received without an index, under
+/// `.git/objects/pack` as `pack-<checksum>.pack` with a version 2 `.idx`
+/// for it, returning the ids of its objects. The pack goes to disk as it
+/// arrives rather than being collected first: each object's offset, CRC and
+/// (for a whole object) id are worked out on the way through, leaving only
+/// the deltas to resolve once the pack is all there. A thin pack, with
+/// deltas against objects outside it, can't stand on its own and is
+/// unpacked into loose objects instead.
+pub fn store(input: impl Read, limits: &Limits) -> io::Result<Vec<String>> {
+    let dir = &repository::path("objects/pack");
+    fs::create_dir_all(dir)?;
+    let temp = dir.join(format!("tmp_pack_{}", std::process::id()));
+    let result = store_incoming(input, &temp, limits);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn store_incoming(input: impl Read, temp: &Path, limits: &Limits) -> io::Result<Vec<String>> {
+    let path = PathBuf::from("incoming pack");
+    let mut incoming = Incoming {
+        input: io::BufReader::with_capacity(64 * 1024, input),
+        output: io::BufWriter::new(fs::File::create(temp)?),
+        sha: Sha1::new(),
+        crc: flate2::Crc::new(),
+        offset: 0,
+        ended: false,
+        error: None,
+    };
+    let mut header = [0u8; 12];
+    read_incoming(&mut incoming, &mut header, &path)?;
+    if &header[..4] != b"PACK" {
+        return Err(corrupt(&path, "not a pack file"));
+    }
+    let count = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+
+    let mut budget = Budget::new(*limits);
+    let mut entries = Vec::new();
+    let mut offsets = Vec::new();
+    let mut crcs = Vec::new();
+    let mut deltas = Vec::new();
+    for _ in 0..count {
+        let offset = incoming.offset;
+        incoming.crc.reset();
+        let (type_code, size) = read_entry_header(&mut incoming, &path)?;
+        limits.check_object(size as u64)?;
+        // The base of a delta is passed over now and read from the pack on
+        // disk once it is whole
+        let kind = match type_code {
+            OBJ_OFS_DELTA => {
+                let mut byte = [0u8; 1];
+                read_incoming(&mut incoming, &mut byte, &path)?;
+                while byte[0] & 0x80 != 0 {
+                    read_incoming(&mut incoming, &mut byte, &path)?;
+                }
+                None
+            }
+            OBJ_REF_DELTA => {
+                read_incoming(&mut incoming, &mut [0u8; 20], &path)?;
+                None
+            }
+            _ => Some(type_name(type_code, &path)?),
+        };
+        let mut sha = Sha1::new();
+        if let Some(kind) = &kind {
+            sha.update(format!("{} {}\0", kind, size).as_bytes());
+        }
+        let mut decoder = ZlibDecoder::new(&mut incoming);
+        let mut buffer = [0u8; 64 * 1024];
+        let mut inflated = 0u64;
+        let read = loop {
+            let n = match decoder.read(&mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(n) => n,
+                Err(e) => break Err(e),
+            };
+            inflated += n as u64;
+            if inflated > size as u64 {
+                break Ok(());
+            }
+            if kind.is_some() {
+                sha.update(&buffer[..n]);
+            }
+        };
+        if incoming.ended && (read.is_err() || inflated != size as u64) {
+            return Err(truncated(&path));
+        }
+        read.map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => {
+                corrupt(&path, &e.to_string())
+            }
+            _ => e,
+        })?;
+        if inflated != size as u64 {
+            return Err(corrupt(&path, "object size mismatch"));
+        }
+        offsets.push(offset);
+        crcs.push((offset, incoming.crc.sum()));
+        match kind {
+            Some(_) => {
+                budget.spend(size as u64)?;
+                entries.push((sha.digest().to_string(), offset));
+            }
+            None => deltas.push(offset),
+        }
+    }
+    let checksum = incoming.sha.digest().bytes();
+    let mut trailer = [0u8; 20];
+    read_incoming(&mut incoming, &mut trailer, &path)?;
+    if trailer != checksum {
+        return Err(corrupt(&path, "pack checksum mismatch"));
+    }
+    if incoming.read(&mut [0u8; 1])? != 0 {
+        return Err(corrupt(&path, "junk after the last object"));
+    }
+    incoming.output.flush()?;
+    if let Some(e) = incoming.error.take() {
+        return Err(e);
+    }
+    let size = incoming.offset;
+    drop(incoming);
+
+    // An empty pack has nothing worth keeping
+    if count == 0 {
+        fs::remove_file(temp)?;
+        return Ok(Vec::new());
+    }
+    entries.sort_unstable();
+    // Only deltas need the pack read back, each from where it was found to
+    // start
+    if !deltas.is_empty() {
+        let file = io::BufReader::new(fs::File::open(temp)?);
+        let mut pack = Pack::new(path, Box::new(file), size, entries);
+        resolve_objects(&mut pack, deltas.clone(), &mut budget, |kind, content| {
+            Ok(object::hash(kind, content))
+        })?;
+        for offset in deltas {
+            if let Some(Base::Id(id)) = pack.header(offset)?.base {
+                if pack.find(&id).is_none() {
+                    pack.entries.clear();
+                    let ids = unpack_pack(&mut pack, limits)?;
+                    drop(pack);
+                    fs::remove_file(temp)?;
+                    return Ok(ids);
+                }
+            }
+        }
+        entries = pack.entries;
+    }
+
+    let name = format!("pack-{}", hex::encode(checksum));
+    let pack_path = temp.with_file_name(format!("{}.pack", name));
+    let index = write_index(&entries, &crcs, &checksum);
+    // The index goes last: a pack is only used once its index exists
+    fs::rename(temp, &pack_path)?;
+    let index_path = pack_path.with_extension("idx");
+    let index_temp = index_path.with_extension("tmp");
+    fs::write(&index_temp, index)?;
+    fs::rename(&index_temp, &index_path)?;
+    Ok(entries.into_iter().map(|(id, _)| id).collect())
+}
+
+/// A pack being received: everything read through it is written to the
+/// file it is being kept in and counted into the checksum and the current
+/// object's CRC, as it is consumed
+struct Incoming<R> {
+    input: io::BufReader<R>,
+    output: io::BufWriter<fs::File>,
+    sha: Sha1,
+    crc: flate2::Crc,
+    offset: u64,
+    /// Set once the input has run out
+    ended: bool,
+    /// A failure to write, which `consume` has no way to return
+    error: Option<io::Error>,
+}
+
+impl<R: Read> Read for Incoming<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for Incoming<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buf = self.input.fill_buf()?;
+        self.ended = buf.is_empty();
+        Ok(buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let data = &self.input.buffer()[..amt];
+        self.sha.update(data);
+        self.crc.update(data);
+        if self.error.is_none() {
+            self.error = self.output.write_all(data).err();
+        }
+        self.offset += amt as u64;
+        self.input.consume(amt);
+    }
+}
+
+/// Fill `buf` from a pack being received
+fn read_incoming(input: &mut impl Read, buf: &mut [u8], path: &Path) -> io::Result<()> {
+    input.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => truncated(path),
+        _ => e,
+    })
+}
+
+/// A pack being received ending early: an `UnexpectedEof`, as the
+/// connection it came over most likely broke
+fn truncated(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("{}: truncated pack", path.display()),
+    )
+}
+
+/// The type code and size of an object's type/size header, read from a
+/// stream
+fn read_entry_header(input: &mut impl Read, path: &Path) -> io::Result<(u8, usize)> {
+    let mut byte = [0u8; 1];
+    read_incoming(input, &mut byte, path)?;
+    let type_code = (byte[0] >> 4) & 0x07;
+    let mut size = (byte[0] & 0x0f) as usize;
+    let mut shift = 4;
+    while byte[0] & 0x80 != 0 {
+        read_incoming(input, &mut byte, path)?;
+        if shift > 57 {
+            return Err(corrupt(path, "object size too large"));
+        }
+        size |= ((byte[0] & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+    Ok((type_code, size))
+}
+
+/// A version 2 pack index for `entries` (sorted by id), with the CRC-32 of
+/// each object's bytes in the pack by offset
+fn write_index(entries: &[(String, u64)], crcs: &[(u64, u32)], pack_checksum: &[u8]) -> Vec<u8> {
+    let mut index = Vec::new();
+    index.extend_from_slice(b"\xfftOc");
+    index.extend_from_slice(&2u32.to_be_bytes());
+    let mut fanout = [0u32; 256];
+    for (id, _) in entries {
+        let first = u8::from_str_radix(&id[..2], 16).unwrap_or(0);
+        for count in &mut fanout[first as usize..] {
+            *count += 1;
+        }
+    }
+    for count in fanout {
+        index.extend_from_slice(&count.to_be_bytes());
+    }
+    for (id, _) in entries {
+        index.extend_from_slice(&hex::decode(id).unwrap_or_default());
+    }
+    for (_, offset) in entries {
+        let crc = crcs
+            .binary_search_by_key(offset, |(at, _)| *at)
+            .map(|i| crcs[i].1)
+            .unwrap_or(0);
+        index.extend_from_slice(&crc.to_be_bytes());
+    }
+    // Offsets past 2^31 go in a table of 8-byte offsets after the others
+    let mut large = Vec::new();
+    for (_, offset) in entries {
+        if *offset < 0x8000_0000 {
+            index.extend_from_slice(&(*offset as u32).to_be_bytes());
+        } else {
+            index.extend_from_slice(&(0x8000_0000 | large.len() as u32).to_be_bytes());
+            large.push(*offset);
+        }
+    }
+    for offset in large {
+        index.extend_from_slice(&offset.to_be_bytes());
+    }
+    index.extend_from_slice(pack_checksum);
+    let checksum = Sha1::from(&index).digest().bytes();
+    index.extend_from_slice(&checksum);
+    index
+}
+
+/// `data` checked to be a whole pack, with where each object starts found
+/// by inflating each in turn; the ids are still to be resolved
+fn received(data: Vec<u8>, limits: &Limits) -> io::Result<Pack> {
+    let path = PathBuf::from("incoming pack");
+    if data.len() < 32 || &data[..4] != b"PACK" {
+        return Err(corrupt(&path, "not a pack file"));
+    }
+    let (body, checksum) = data.split_at(data.len() - 20);
+    if Sha1::from(body).digest().bytes() != checksum {
+        return Err(corrupt(&path, "pack checksum mismatch"));
+    }
+    let count = u32::from_be_bytes([data[8], data[9], data[10], data[11]]) as usize;
+    let size = data.len() as u64;
+    let mut pack = Pack::new(path, Box::new(io::Cursor::new(data)), size, Vec::new());
+
+    let mut pos = 12;
+    for _ in 0..count {
+        pack.offsets.push(pos);
+        let header = pack.header(pos)?;
+        limits.check_object(header.size as u64)?;
+        let (_, used) = pack.inflate_sized(&header)?;
+        pos = header.data_start + used;
+    }
+    if pos != size - 20 {
+        return Err(corrupt(&pack.path, "junk after the last object"));
+    }
+    Ok(pack)
+}
+
+/// Read the objects at `pending` offsets of a `received` pack, handing each
+/// to `each`, which returns its id, and record the ids in the pack's
+/// entries
+fn resolve_objects(
+    pack: &mut Pack,
+    mut pending: Vec<u64>,
+    budget: &mut Budget,
+    mut each: impl FnMut(&str, &[u8]) -> io::Result<String>,
+) -> io::Result<()> {
+    let limits = *budget.limits();
+    // A delta against an object named by id can only be resolved once that
+    // object's id is known, so keep going round until nothing is left
+    while !pending.is_empty() {
+        let mut waiting = Vec::new();
+        for offset in pending.iter().copied() {
+            match pack.read_limited(offset, &limits) {
+                Ok((kind, content)) => {
+                    budget.spend(content.len() as u64)?;
+                    let id = each(&kind, &content)?;
+                    let at = pack
+                        .entries
+                        .partition_point(|(entry, _)| entry.as_str() < id.as_str());
+                    pack.entries.insert(at, (id, offset));
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => waiting.push(offset),
+                Err(e) => return Err(e),
+            }
+        }
+        if waiting.len() == pending.len() {
+            return Err(corrupt(&pack.path, "delta base missing"));
+        }
+        pending = waiting;
+    }
+    Ok(())
+}
+
+fn type_name(type_code: u8, path: &Path) -> io::Result<String> {
+    let name = match type_code {
+        1 => "commit",
+        2 => "tree",
+        3 => "blob",
+        4 => "tag",
+        _ => return Err(corrupt(path, "unknown object type")),
+    };
+    Ok(name.to_string())
+}
+
+fn corrupt(path: &Path, problem: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), problem),
+    )
+}
+
+/// Object ids and offsets from a version 1 or 2 `.idx`
+fn parse_index(index: &[u8]) -> Option<Vec<(String, u64)>> {
+    let be32 = |pos: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            index.get(pos..pos + 4)?.try_into().ok()?,
+        ))
+    };
+
+    let mut entries = Vec::new();
+    if index.starts_with(b"\xfftOc") {
+        if be32(4)? != 2 {
+            return None;
+        }
+        let count = be32(8 + 255 * 4)? as usize;
+        let ids: usize = 8 + 256 * 4;
+        let offsets = ids.checked_add(count.checked_mul(24)?)?;
+        let large_offsets = offsets.checked_add(count.checked_mul(4)?)?;
+        // Every object takes at least 28 bytes of the index
+        if index.len() < large_offsets {
+            return None;
+        }
+        for i in 0..count {
+            let id = hex::encode(index.get(ids + i * 20..ids + i * 20 + 20)?);
+            let offset = be32(offsets + i * 4)?;
+            // The high bit points into the table of 64-bit offsets
+            let offset = if offset & 0x8000_0000 != 0 {
+                let at = large_offsets.checked_add((offset & 0x7fff_ffff) as usize * 8)?;
+                u64::from_be_bytes(index.get(at..at + 8)?.try_into().ok()?)
+            } else {
+                offset as u64
+            };
+            entries.push((id, offset));
+        }
+    } else {
+        let count = be32(255 * 4)? as usize;
+        if index.len() < 256 * 4 + count.checked_mul(24)? {
+            return None;
+        }
+        for i in 0..count {
+            let at = 256 * 4 + i * 24;
+            let offset = be32(at)? as u64;
+            entries.push((hex::encode(index.get(at + 4..at + 24)?), offset));
+        }
+    }
+    Some(entries)
+}
+
+static PACKS: Mutex<Vec<Arc<Pack>>> = Mutex::new(Vec::new());
+
+/// The packs of the repository in the current directory, then those of the
+/// repositories it borrows objects from. Each is opened once and kept open
+/// until it disappears from disk, or until it is among the least recently
+/// used when more than `MAX_OPEN_PACKS` are.
+pub fn packs() -> io::Result<Vec<Arc<Pack>>> {
+    let mut dirs = vec![std::env::current_dir()?.join(repository::path("objects"))];
+    dirs.extend(object::alternates()?);
+    let mut paths = Vec::new();
+    for dir in dirs {
+        let mut found = Vec::new();
+        match fs::read_dir(dir.join("pack")) {
+            Ok(entries) => {
+                for entry in entries {
+                    let path = entry?.path();
+                    if path.extension().is_some_and(|ext| ext == "pack")
+                        && path.with_extension("idx").exists()
+                    {
+                        found.push(path);
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        found.sort();
+        paths.extend(found);
+    }
+
+    let mut cache = PACKS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut packs = Vec::with_capacity(paths.len());
+    for path in paths {
+        // The cache runs from least to most recently used
+        let pack = match cache.iter().position(|pack| pack.path == path) {
+            Some(at) => cache.remove(at),
+            None => Arc::new(Pack::open(&path)?),
+        };
+        cache.push(Arc::clone(&pack));
+        packs.push(pack);
+    }
+    cache.retain(|pack| packs.iter().any(|kept| Arc::ptr_eq(kept, pack)));
+    let excess = cache.len().saturating_sub(MAX_OPEN_PACKS);
+    cache.drain(..excess);
+    Ok(packs)
+}
+
+/// Type and content of `sha` if one of the repository's packs has it
+pub fn read_object(sha: &str) -> io::Result<Option<(String, Vec<u8>)>> {
+    for pack in packs()? {
+        if let Some(offset) = pack.find(sha) {
+            return pack.read_at(offset).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Read OFS_DELTA offset using Git's variable-length encoding
+pub fn read_ofs_delta_offset(data: &[u8], mut offset: usize) -> io::Result<(usize, usize)> {
+    let invalid = |problem: &str| io::Error::new(io::ErrorKind::InvalidData, problem.to_string());
+    let mut c = *data
+        .get(offset)
+        .ok_or_else(|| invalid("No data for OFS_DELTA offset"))?;
+    offset += 1;
+    let mut ofs = (c & 0x7F) as usize;
+
+    while c & 0x80 != 0 {
+        c = *data
+            .get(offset)
+            .ok_or_else(|| invalid("Incomplete OFS_DELTA offset"))?;
+        offset += 1;
+        ofs = ofs
+            .checked_add(1)
+            .and_then(|ofs| ofs.checked_mul(0x80))
+            .map(|ofs| ofs | (c & 0x7F) as usize)
+            .ok_or_else(|| invalid("OFS_DELTA offset too large"))?;
+    }
+
+    Ok((ofs, offset))
+}
+
+/// Apply a delta to a base object to reconstruct the target object. Every
+/// read of the delta is checked, so a malformed one is an error.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |problem: String| io::Error::new(io::ErrorKind::InvalidData, problem);
+    let mut offset = 0;
+
+    let base_size = read_delta_size(delta, &mut offset, "base")?;
+    if base_size != base.len() {
+        return Err(invalid(format!(
+            "Delta base size mismatch: expected {}, got {}",
+            base_size,
+            base.len()
+        )));
+    }
+    let result_size = read_delta_size(delta, &mut offset, "result")?;
+    let mut result = Vec::with_capacity(result_size.min(MAX_PREALLOCATION));
+
+    // Apply delta instructions
+    while let Some(&cmd) = delta.get(offset) {
+        offset += 1;
+
+        if cmd & 0x80 != 0 {
+            // Copy command: up to 4 offset bytes and 3 size bytes follow,
+            // one for each bit set
+            let mut next = |bit: u8, shift: usize| -> io::Result<usize> {
+                if cmd & bit == 0 {
+                    return Ok(0);
+                }
+                let byte = *delta
+                    .get(offset)
+                    .ok_or_else(|| invalid("Truncated delta copy command".to_string()))?;
+                offset += 1;
+                Ok((byte as usize) << shift)
+            };
+            let copy_offset = next(0x01, 0)? | next(0x02, 8)? | next(0x04, 16)? | next(0x08, 24)?;
+            let copy_size = match next(0x10, 0)? | next(0x20, 8)? | next(0x40, 16)? {
+                0 => 0x10000, // Default size when not specified
+                size => size,
+            };
+
+            // Validate and copy from base
+            let copied = copy_offset
+                .checked_add(copy_size)
+                .and_then(|end| base.get(copy_offset..end))
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "Delta copy out of bounds: offset={}, size={}, base_len={}",
+                        copy_offset,
+                        copy_size,
+                        base.len()
+                    ))
+                })?;
+            result.extend_from_slice(copied);
+        } else if cmd != 0 {
+            // Insert command: insert new data from delta
+            let inserted = delta
+                .get(offset..offset + cmd as usize)
+                .ok_or_else(|| invalid("Delta insert out of bounds".to_string()))?;
+            result.extend_from_slice(inserted);
+            offset += inserted.len();
+        } else {
+            // cmd == 0 is invalid
+            return Err(invalid("Invalid delta command: 0".to_string()));
+        }
+
+        // Stop as soon as the result outgrows its declared size rather than
+        // let a hostile delta build something huge
+        if result.len() > result_size {
+            break;
+        }
+    }
+
+    // Verify result size
+    if result.len() != result_size {
+        return Err(invalid(format!(
+            "Delta result size mismatch: expected {}, got {}",
+            result_size,
+            result.len()
+        )));
+    }
+
+    Ok(result)
+}
+
+/// Read one of the variable-length sizes a delta starts with
+/// The size of what `delta` builds, as it declares it
+pub fn delta_result_size(delta: &[u8]) -> io::Result<usize> {
+    let mut offset = 0;
+    read_delta_size(delta, &mut offset, "base")?;
+    read_delta_size(delta, &mut offset, "result")
+}
+
+fn read_delta_size(delta: &[u8], offset: &mut usize, what: &str) -> io::Result<usize> {
+    let invalid = |problem: String| io::Error::new(io::ErrorKind::InvalidData, problem);
+    let mut size = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *delta
+            .get(*offset)
+            .ok_or_else(|| invalid(format!("Incomplete {} size in delta", what)))?;
+        *offset += 1;
+        if shift >= 64 {
+            return Err(invalid(format!("{} size encoding too large", what)));
+        }
+        size |= ((byte & 0x7F) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    usize::try_from(size).map_err(|_| invalid(format!("{} size in delta too large", what)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &[u8] = b"hello world\n";
+    const TARGET: &[u8] = b"hello there\n";
+
+    /// A delta turning BASE into TARGET: copy "hello ", insert "there\n"
+    fn delta() -> Vec<u8> {
+        let mut delta = vec![BASE.len() as u8, TARGET.len() as u8, 0x90, 6, 6];
+        delta.extend_from_slice(b"there\n");
+        delta
+    }
+
+    /// An object's type/size header and content, compressed
+    fn entry(type_code: u8, content: &[u8], base: &[u8]) -> Vec<u8> {
+        let mut size = content.len();
+        let mut byte = (type_code << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+        let mut entry = Vec::new();
+        while size > 0 {
+            entry.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        entry.push(byte);
+        entry.extend_from_slice(base);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).unwrap();
+        entry.extend(encoder.finish().unwrap());
+        entry
+    }
+
+    /// A pack of a blob and an OFS_DELTA against it, with the delta's
+    /// offset
+    fn pack() -> (Vec<u8>, u64) {
+        let mut data = b"PACK\0\0\0\x02\0\0\0\x02".to_vec();
+        let blob = entry(3, BASE, &[]);
+        let distance = blob.len() as u8;
+        data.extend(blob);
+        let offset = data.len() as u64;
+        data.extend(entry(OBJ_OFS_DELTA, &delta(), &[distance]));
+        let checksum = Sha1::from(&data).digest().bytes();
+        data.extend_from_slice(&checksum);
+        (data, offset)
+    }
+
+    #[test]
+    fn applies_deltas() {
+        assert_eq!(apply_delta(BASE, &delta()).unwrap(), TARGET);
+        assert_eq!(delta_result_size(&delta()).unwrap(), TARGET.len());
+        assert!(apply_delta(b"short", &delta()).is_err());
+        // Copying past the end of the base, an insert past the end of the
+        // delta, and the reserved command 0
+        for ops in [&[0x91, 8, 6][..], &[9, b'x'], &[0]] {
+            let mut bad = vec![BASE.len() as u8, TARGET.len() as u8];
+            bad.extend_from_slice(ops);
+            assert!(apply_delta(BASE, &bad).is_err(), "{:?}", ops);
+        }
+    }
+
+    #[test]
+    fn resolves_a_received_pack() {
+        let (data, offset) = pack();
+        let pack = Pack::from_bytes(data, &Limits::default()).unwrap();
+        let (base_id, target_id) = (object::hash("blob", BASE), object::hash("blob", TARGET));
+        assert_eq!(pack.find(&target_id), Some(offset));
+        assert_eq!(
+            pack.read_at(offset).unwrap(),
+            ("blob".to_string(), TARGET.to_vec())
+        );
+        let entry = pack.entry(offset).unwrap();
+        assert_eq!(entry.delta_base, Some(base_id.clone()));
+        assert_eq!(pack.entry(12).unwrap().delta_base, None);
+        assert_eq!(pack.entry(12).unwrap().disk_size, offset - 12);
+    }
+
+    #[test]
+    fn rejects_a_bad_received_pack() {
+        let (data, _) = pack();
+        let mut checksum = data.clone();
+        *checksum.last_mut().unwrap() ^= 1;
+        let mut short = data[..data.len() - 21].to_vec();
+        short.extend_from_slice(&Sha1::from(&short).digest().bytes());
+        for bad in [checksum, short, b"PACK".to_vec()] {
+            assert!(Pack::from_bytes(bad, &Limits::default()).is_err());
+        }
+        let limits = Limits {
+            max_object_size: 4,
+            max_unpacked_size: u64::MAX,
+        };
+        assert!(Pack::from_bytes(data, &limits).is_err());
+    }
+
+    #[test]
+    fn index_round_trips_and_pack_reads_from_disk() {
+        let (data, offset) = pack();
+        let received = Pack::from_bytes(data.clone(), &Limits::default()).unwrap();
+        let entries = received.entries().to_vec();
+        let index = write_index(&entries, &[], &data[data.len() - 20..]);
+        assert_eq!(parse_index(&index).unwrap(), entries);
+        assert!(parse_index(&index[..index.len() / 2]).is_none());
+        let mut version = index.clone();
+        version[7] = 3;
+        assert!(parse_index(&version).is_none());
+
+        // Offsets past 2^31 go through the table of large offsets
+        let far = vec![("11".repeat(20), 12), ("22".repeat(20), 0x1_2345_6789)];
+        assert_eq!(parse_index(&write_index(&far, &[], &[0; 20])).unwrap(), far);
+
+        let root = std::env::temp_dir().join(format!("byog-pack-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("pack-test.pack");
+        fs::write(&path, &data).unwrap();
+        fs::write(path.with_extension("idx"), &index).unwrap();
+        let pack = Pack::open(&path).unwrap();
+        assert_eq!(pack.entries(), &entries[..]);
+        assert_eq!(pack.read_at(offset).unwrap().1, TARGET);
+        assert_eq!(pack.read_at(12).unwrap().1, BASE);
+        assert_eq!(
+            pack.entry(offset).unwrap().disk_size,
+            data.len() as u64 - 20 - offset
+        );
+        assert!(pack.read_at(data.len() as u64).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+// Unified diff patches
+// A patch is parsed into one `FilePatch` per file it touches, from git's
+// extended headers ("diff --git", modes, renames) or from a plain `diff -u`.
+// Applying a file's hunks looks for each at the line its header names, then
+// ever further away from it; when allowed, a hunk that still can't be
+// placed loses its outermost context lines one at a time (the "fuzz") until
+// it fits or too little context is left to trust.
+
+use crate::git::diff::split_lines;
+use std::io;
+
+/// The changes a patch makes to one file
+#[derive(Debug, Default)]
+pub struct FilePatch {
+    /// `None` for a file the patch creates
+    pub old_path: Option<String>,
+    /// `None` for a file the patch deletes
+    pub new_path: Option<String>,
+    pub old_mode: Option<String>,
+    pub new_mode: Option<String>,
+    pub hunks: Vec<Hunk>,
+    /// Set for "Binary files differ" and binary patches, which carry no hunks
+    pub binary: bool,
+}
+
+#[derive(Debug)]
+pub struct Hunk {
+    /// First line of the old side, counting from 1; for a hunk that only
+    /// adds lines, the line they follow
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<Line>,
+}
+
+/// A line of a hunk, keeping its `\n` unless the file ends without one
+#[derive(Debug)]
+pub enum Line {
+    Context(Vec<u8>),
+    Delete(Vec<u8>),
+    Insert(Vec<u8>),
+}
+
+impl Line {
+    fn text(&self) -> &[u8] {
+        match self {
+            Line::Context(text) | Line::Delete(text) | Line::Insert(text) => text,
+        }
+    }
+}
+
+/// Parse every file patch in `data`, dropping `strip` leading components
+/// from the paths of its headers (`-p1` drops the `a/` and `b/`). Text
+/// outside the patches, such as a commit message, is skipped.
+pub fn parse(data: &[u8], strip: usize) -> io::Result<Vec<FilePatch>> {
+    let lines = split_lines(data);
+    let mut patches = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let is_plain = lines[i].starts_with(b"--- ")
+            && lines.get(i + 1).is_some_and(|l| l.starts_with(b"+++ "))
+            && lines.get(i + 2).is_some_and(|l| l.starts_with(b"@@ "));
+        if lines[i].starts_with(b"diff --git ") || is_plain {
+            patches.push(parse_file(&lines, &mut i, strip)?);
+        } else {
+            i += 1;
+        }
+    }
+    Ok(patches)
+}
+
+fn parse_file(lines: &[&[u8]], i: &mut usize, strip: usize) -> io::Result<FilePatch> {
+    let mut patch = FilePatch::default();
+
+    if let Some(names) = text(lines[*i]).strip_prefix("diff --git ") {
+        let name = git_header_name(names, strip);
+        patch.old_path = name.clone();
+        patch.new_path = name;
+        *i += 1;
+
+        while let Some(&line) = lines.get(*i) {
+            let line = text(line);
+            if let Some(mode) = line.strip_prefix("old mode ") {
+                patch.old_mode = Some(mode.to_string());
+            } else if let Some(mode) = line.strip_prefix("new mode ") {
+                patch.new_mode = Some(mode.to_string());
+            } else if let Some(mode) = line.strip_prefix("new file mode ") {
+                patch.old_path = None;
+                patch.new_mode = Some(mode.to_string());
+            } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+                patch.new_path = None;
+                patch.old_mode = Some(mode.to_string());
+            } else if let Some(path) = line.strip_prefix("rename from ") {
+                patch.old_path = Some(unquote(path));
+            } else if let Some(path) = line.strip_prefix("rename to ") {
+                patch.new_path = Some(unquote(path));
+            } else if let Some(index) = line.strip_prefix("index ") {
+                // "index <old>..<new> <mode>" when the mode is unchanged
+                if let Some((_, mode)) = index.split_once(' ') {
+                    patch.old_mode.get_or_insert_with(|| mode.to_string());
+                    patch.new_mode.get_or_insert_with(|| mode.to_string());
+                }
+            } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+                patch.binary = true;
+            } else if !line.starts_with("similarity index ")
+                && !line.starts_with("dissimilarity index ")
+            {
+                break;
+            }
+            *i += 1;
+        }
+    }
+
+    if lines[*i..].len() >= 2
+        && lines[*i].starts_with(b"--- ")
+        && lines[*i + 1].starts_with(b"+++ ")
+    {
+        patch.old_path = file_header_name(&text(lines[*i])[4..], strip);
+        patch.new_path = file_header_name(&text(lines[*i + 1])[4..], strip);
+        *i += 2;
+    }
+    if patch.old_path.is_none() && patch.new_path.is_none() {
+        return Err(corrupt(*i));
+    }
+
+    while lines.get(*i).is_some_and(|l| l.starts_with(b"@@ ")) {
+        patch.hunks.push(parse_hunk(lines, i)?);
+    }
+    Ok(patch)
+}
+
+fn parse_hunk(lines: &[&[u8]], i: &mut usize) -> io::Result<Hunk> {
+    let header_line = *i;
+    let (old, new) = text(lines[*i])
+        .strip_prefix("@@ -")
+        .and_then(|rest| rest.split_once(" @@"))
+        .and_then(|(ranges, _)| ranges.split_once(" +"))
+        .ok_or_else(|| corrupt(header_line))?;
+    let (old_start, old_len) = parse_range(old).ok_or_else(|| corrupt(header_line))?;
+    let (new_start, new_len) = parse_range(new).ok_or_else(|| corrupt(header_line))?;
+    *i += 1;
+
+    let mut hunk = Hunk {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+        lines: Vec::new(),
+    };
+    let (mut old_seen, mut new_seen) = (0, 0);
+    while old_seen < old_len || new_seen < new_len || lines.get(*i).is_some_and(|l| l[0] == b'\\') {
+        let line = lines.get(*i).ok_or_else(|| corrupt(*i))?;
+        match line[0] {
+            b' ' | b'\n' => {
+                // Editors tend to strip the space off an empty context line
+                let content = if line[0] == b'\n' { line } else { &line[1..] };
+                hunk.lines.push(Line::Context(content.to_vec()));
+                old_seen += 1;
+                new_seen += 1;
+            }
+            b'-' => {
+                hunk.lines.push(Line::Delete(line[1..].to_vec()));
+                old_seen += 1;
+            }
+            b'+' => {
+                hunk.lines.push(Line::Insert(line[1..].to_vec()));
+                new_seen += 1;
+            }
+            // "\ No newline at end of file" applies to the line before
+            b'\\' => match hunk.lines.last_mut() {
+                Some(Line::Context(text) | Line::Delete(text) | Line::Insert(text)) => {
+                    if text.ends_with(b"\n") {
+                        text.pop();
+                    }
+                }
+                None => return Err(corrupt(*i)),
+            },
+            _ => return Err(corrupt(*i)),
+        }
+        if old_seen > old_len || new_seen > new_len {
+            return Err(corrupt(*i));
+        }
+        *i += 1;
+    }
+    Ok(hunk)
+}
+
+/// `start[,count]`, the count defaulting to 1
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// The name in a `---` or `+++` line, without a trailing timestamp;
+/// `/dev/null` names no file
+fn file_header_name(value: &str, strip: usize) -> Option<String> {
+    let name = if value.starts_with('"') {
+        unquote(value)
+    } else {
+        value.split('\t').next().unwrap_or(value).to_string()
+    };
+    if name == "/dev/null" {
+        return None;
+    }
+    strip_components(&name, strip)
+}
+
+/// The name in "diff --git a/<name> b/<name>". Both halves name the same
+/// file unless it was renamed, which the rename headers then settle.
+fn git_header_name(names: &str, strip: usize) -> Option<String> {
+    if names.starts_with('"') {
+        let (first, _) = split_quoted(names)?;
+        return strip_components(&first, strip);
+    }
+    names
+        .match_indices(' ')
+        .map(|(at, _)| (&names[..at], &names[at + 1..]))
+        .filter_map(|(a, b)| Some((strip_components(a, strip)?, strip_components(b, strip)?)))
+        .find(|(a, b)| a == b)
+        .map(|(a, _)| a)
+}
+
+fn strip_components(name: &str, strip: usize) -> Option<String> {
+    let mut name = name;
+    for _ in 0..strip {
+        name = name.split_once('/')?.1;
+    }
+    Some(name.to_string())
+}
+
+/// Undo git's C-style quoting of a path with unusual characters, leaving
+/// an unquoted path as it is
+fn unquote(value: &str) -> String {
+    match split_quoted(value) {
+        Some((name, _)) => name,
+        None => value.to_string(),
+    }
+}
+
+/// A leading quoted string, unescaped, and the text after it
+fn split_quoted(value: &str) -> Option<(String, &str)> {
+    let bytes = value.strip_prefix('"')?.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let name = String::from_utf8_lossy(&out).into_owned();
+                return Some((name, &value[i + 2..]));
+            }
+            b'\\' => {
+                let escaped = *bytes.get(i + 1)?;
+                match escaped {
+                    b'0'..=b'7' => {
+                        let octal = std::str::from_utf8(bytes.get(i + 1..i + 4)?).ok()?;
+                        out.push(u8::from_str_radix(octal, 8).ok()?);
+                        i += 2;
+                    }
+                    b'n' => out.push(b'\n'),
+                    b't' => out.push(b'\t'),
+                    b'a' => out.push(0x07),
+                    b'b' => out.push(0x08),
+                    b'f' => out.push(0x0c),
+                    b'r' => out.push(b'\r'),
+                    b'v' => out.push(0x0b),
+                    other => out.push(other),
+                }
+                i += 2;
+            }
+            byte => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+fn text(line: &[u8]) -> &str {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    std::str::from_utf8(line).unwrap_or("")
+}
+
+fn corrupt(line: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupt patch at line {}", line + 1),
+    )
+}
+
+/// Apply `hunks` to `content`. `min_context` is how many context lines on
+/// each side of a hunk must still match once fuzz is allowed; `None` keeps
+/// all of them. `unidiff_zero` accepts hunks without context anywhere,
+/// as a `diff -U0` needs. A hunk that can't be placed fails with the line
+/// its header names.
+pub fn apply(
+    content: &[u8],
+    hunks: &[Hunk],
+    min_context: Option<usize>,
+    unidiff_zero: bool,
+) -> Result<Vec<u8>, usize> {
+    // Each hunk patches the result of the ones before it, so its position
+    // in the new version is where to start looking
+    let mut image = split_lines(content);
+    for hunk in hunks {
+        let (pos, body) = locate(&image, hunk, min_context, unidiff_zero).ok_or(hunk.old_start)?;
+        let old_len = body
+            .iter()
+            .filter(|line| !matches!(line, Line::Insert(_)))
+            .count();
+        let new_lines = body
+            .iter()
+            .filter(|line| !matches!(line, Line::Delete(_)))
+            .map(Line::text);
+        image.splice(pos..pos + old_len, new_lines);
+    }
+    Ok(image.concat())
+}
+
+/// Where in `image` the hunk goes, and the hunk lines left after fuzz
+fn locate<'a>(
+    image: &[&[u8]],
+    hunk: &'a Hunk,
+    min_context: Option<usize>,
+    unidiff_zero: bool,
+) -> Option<(usize, &'a [Line])> {
+    let is_context = |line: &&Line| matches!(line, Line::Context(_));
+    let leading = hunk.lines.iter().take_while(is_context).count();
+    let trailing = hunk.lines.iter().rev().take_while(is_context).count();
+    let min_context = min_context.unwrap_or(usize::MAX);
+
+    // A hunk from the first line must match at the top of the file, one
+    // without context after it at the bottom; without any context there is
+    // no telling, short of adding to an empty file
+    let mut match_beginning = hunk.old_start == 0 || (hunk.old_start == 1 && !unidiff_zero);
+    let mut match_end = !unidiff_zero && trailing == 0;
+    let mut pos = hunk.new_start.saturating_sub(1);
+    let (mut lead, mut trail) = (leading, trailing);
+
+    loop {
+        let body = &hunk.lines[leading - lead..hunk.lines.len() - (trailing - trail)];
+        let old: Vec<&[u8]> = body
+            .iter()
+            .filter(|line| !matches!(line, Line::Insert(_)))
+            .map(Line::text)
+            .collect();
+        if let Some(found) = find(image, &old, pos, match_beginning, match_end) {
+            return Some((found, body));
+        }
+
+        if lead <= min_context && trail <= min_context {
+            return None;
+        }
+        if match_beginning || match_end {
+            match_beginning = false;
+            match_end = false;
+            continue;
+        }
+        // Drop context from the longer side first, both when they're even
+        if lead >= trail {
+            lead -= 1;
+            pos = pos.saturating_sub(1);
+        }
+        if trail > lead {
+            trail -= 1;
+        }
+    }
+}
+
+/// The position nearest `pos` where `image` continues with exactly `old`,
+/// trying the next line before the previous one
+fn find(
+    image: &[&[u8]],
+    old: &[&[u8]],
+    pos: usize,
+    match_beginning: bool,
+    match_end: bool,
+) -> Option<usize> {
+    let last = image.len().checked_sub(old.len())?;
+    let fits = |at: usize| {
+        at <= last
+            && (!match_beginning || at == 0)
+            && (!match_end || at == last)
+            && image[at..at + old.len()] == *old
+    };
+
+    let pos = pos.min(image.len());
+    for distance in 0..=image.len() {
+        if fits(pos + distance) {
+            return Some(pos + distance);
+        }
+        match pos.checked_sub(distance) {
+            Some(at) if distance > 0 && fits(at) => return Some(at),
+            _ => {}
+        }
+    }
+    None
+}
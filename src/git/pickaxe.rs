@@ -0,0 +1,135 @@
+// Pickaxe
+// Finds the commits whose changes bring in or take out a string, as log's
+// -S and -G do. With -S a file matches when its old and new versions hold
+// the string a different number of times, so a change that only moves it
+// around doesn't count; with -G it matches when a line the diff adds or
+// removes matches the extended regex. A commit is compared with its first
+// parent, a root commit with an empty tree; merges are passed over, as log
+// shows no changes for them. Binary files are searched by -S but not -G.
+//
+// The changed files are searched one at a time, the search ending at the
+// first that matches unless every matching path is wanted, and a file's
+// added and removed lines only until one of them matches.
+
+use crate::git::commit::{self, Commit};
+use crate::git::diff;
+use crate::git::object;
+use crate::git::regex::{Regex, Syntax};
+use crate::git::tree::{self, FileEntry, MODE_GITLINK};
+use std::io;
+
+#[derive(Debug, Clone)]
+pub enum Pickaxe {
+    /// -S: the number of occurrences changes
+    String { needle: Vec<u8>, ignore_case: bool },
+    /// -G: an added or removed line matches
+    Regex(Regex),
+}
+
+impl Pickaxe {
+    pub fn string(needle: &str, ignore_case: bool) -> Pickaxe {
+        let mut needle = needle.as_bytes().to_vec();
+        if ignore_case {
+            needle.make_ascii_lowercase();
+        }
+        Pickaxe::String {
+            needle,
+            ignore_case,
+        }
+    }
+
+    pub fn regex(pattern: &str, ignore_case: bool) -> io::Result<Pickaxe> {
+        let regex = Regex::new(pattern, Syntax::Extended, ignore_case).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("invalid regex: {}", e))
+        })?;
+        Ok(Pickaxe::Regex(regex))
+    }
+
+    /// The paths whose changes in `commit` this finds: only the first of
+    /// them unless `all`, none for a merge
+    pub fn matching_paths(&self, commit: &Commit, all: bool) -> io::Result<Vec<String>> {
+        let parent_tree = match commit.parents.as_slice() {
+            [] => None,
+            [parent] => Some(commit::read(parent)?.tree),
+            _ => return Ok(Vec::new()),
+        };
+        let mut paths = Vec::new();
+        for change in tree::diff(parent_tree.as_deref(), Some(&commit.tree), true)? {
+            if self.matches(change.old.as_ref(), change.new.as_ref())? {
+                paths.push(change.path);
+                if !all {
+                    break;
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    fn matches(&self, old: Option<&FileEntry>, new: Option<&FileEntry>) -> io::Result<bool> {
+        let old = content(old)?;
+        let new = content(new)?;
+        match self {
+            Pickaxe::String {
+                needle,
+                ignore_case,
+            } => {
+                let count = |data: &[u8]| match ignore_case {
+                    true => occurrences(&data.to_ascii_lowercase(), needle),
+                    false => occurrences(data, needle),
+                };
+                Ok(count(&old) != count(&new))
+            }
+            Pickaxe::Regex(regex) => {
+                if diff::is_binary(&old) || diff::is_binary(&new) {
+                    return Ok(false);
+                }
+                let old_lines = diff::split_lines(&old);
+                let new_lines = diff::split_lines(&new);
+                let kept = diff::matching_lines(&old_lines, &new_lines);
+                let is_match =
+                    |line: &[u8]| regex.is_match(line.strip_suffix(b"\n").unwrap_or(line));
+                let removed = changed(&old_lines, kept.iter().map(|&(old, _)| old));
+                let added = changed(&new_lines, kept.iter().map(|&(_, new)| new));
+                let found = removed.chain(added).any(is_match);
+                Ok(found)
+            }
+        }
+    }
+}
+
+/// The content of one side of a change, empty where the file doesn't exist
+/// or is a submodule
+fn content(entry: Option<&FileEntry>) -> io::Result<Vec<u8>> {
+    match entry {
+        Some(entry) if entry.mode != MODE_GITLINK => Ok(object::read(&entry.sha)?.1),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// The lines of `lines` not among those `kept` (in increasing order)
+fn changed<'a>(
+    lines: &'a [&'a [u8]],
+    kept: impl Iterator<Item = usize> + 'a,
+) -> impl Iterator<Item = &'a [u8]> + 'a {
+    let mut kept = kept.peekable();
+    lines.iter().enumerate().filter_map(move |(i, line)| {
+        if kept.next_if_eq(&i).is_some() {
+            return None;
+        }
+        Some(*line)
+    })
+}
+
+/// How many times `needle` occurs in `haystack`, without overlaps
+fn occurrences(haystack: &[u8], needle: &[u8]) -> usize {
+    if needle.is_empty() {
+        return 0;
+    }
+    let mut count = 0;
+    let mut rest = haystack;
+    while let Some(pos) = rest.windows(needle.len()).position(|w| w == needle) {
+        count += 1;
+        rest = &rest[pos + needle.len()..];
+    }
+    count
+}
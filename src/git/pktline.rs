@@ -0,0 +1,200 @@
+// pkt-line framing
+// Every message of the pack protocols is a packet: four hex digits giving
+// the length (which counts the four digits themselves) followed by the
+// payload. "0000", a flush packet, ends a section of the conversation;
+// protocol v2 also has "0001", a delimiter between the parts of a command
+// or a response.
+
+use std::io::{self, Read, Write};
+
+pub const FLUSH: &[u8] = b"0000";
+pub const DELIM: &[u8] = b"0001";
+
+/// Largest packet the protocol allows, header included
+const MAX_LEN: usize = 65520;
+
+/// Side-band channel carrying the pack data
+pub const BAND_DATA: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Flush,
+    Delim,
+    Data(&'a [u8]),
+}
+
+pub fn encode(payload: &[u8]) -> Vec<u8> {
+    let mut packet = format!("{:04x}", payload.len() + 4).into_bytes();
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Split a buffer made entirely of packets
+pub fn split(data: &[u8]) -> io::Result<Vec<Packet<'_>>> {
+    let mut packets = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data
+            .get(pos..pos + 4)
+            .ok_or_else(|| bad_length(&data[pos..]))?;
+        let len = std::str::from_utf8(header)
+            .ok()
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| bad_length(header))?;
+        if len <= 1 {
+            packets.push(if len == 0 {
+                Packet::Flush
+            } else {
+                Packet::Delim
+            });
+            pos += 4;
+            continue;
+        }
+        if !(4..=MAX_LEN).contains(&len) || pos + len > data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("protocol error: bad line length {}", len),
+            ));
+        }
+        packets.push(Packet::Data(&data[pos + 4..pos + len]));
+        pos += len;
+    }
+    Ok(packets)
+}
+
+/// Read one packet from a stream; `None` is a flush packet. The stream
+/// ending before a packet starts is an `UnexpectedEof` error.
+pub fn read(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+    let len = std::str::from_utf8(&header)
+        .ok()
+        .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| bad_length(&header))?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if !(4..=MAX_LEN).contains(&len) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("protocol error: bad line length {}", len),
+        ));
+    }
+    let mut payload = vec![0u8; len - 4];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Writes everything sent through it as side-band packets on one band,
+/// split to fit `max_len` (1000 for side-band, 65520 for side-band-64k)
+pub struct SidebandWriter<W: Write> {
+    inner: W,
+    band: u8,
+    max_payload: usize,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> SidebandWriter<W> {
+    pub fn new(inner: W, band: u8, max_len: usize) -> SidebandWriter<W> {
+        SidebandWriter {
+            inner,
+            band,
+            // Room for the header and the band byte
+            max_payload: max_len.min(MAX_LEN) - 5,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut payload = Vec::with_capacity(data.len() + 1);
+        payload.push(self.band);
+        payload.extend_from_slice(data);
+        self.inner.write_all(&encode(&payload))
+    }
+}
+
+impl<W: Write> Write for SidebandWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= self.max_payload {
+            let rest = self.buffer.split_off(self.max_payload);
+            let full = std::mem::replace(&mut self.buffer, rest);
+            self.send(&full)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let data = std::mem::take(&mut self.buffer);
+            self.send(&data)?;
+        }
+        self.inner.flush()
+    }
+}
+
+fn bad_length(header: &[u8]) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "protocol error: bad line length character: {}",
+            String::from_utf8_lossy(&header[..header.len().min(4)])
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_and_splits_packets() {
+        assert_eq!(encode(b"hi\n"), b"0007hi\n");
+        assert_eq!(encode(b""), b"0004");
+        let packets = split(b"0007hi\n00000001000ahello\n0004").unwrap();
+        assert_eq!(
+            packets,
+            [
+                Packet::Data(b"hi\n"),
+                Packet::Flush,
+                Packet::Delim,
+                Packet::Data(b"hello\n"),
+                Packet::Data(b""),
+            ]
+        );
+        assert_eq!(split(b"").unwrap(), []);
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        for data in [&b"00"[..], b"zzzz", b"0003", b"0009abc", b"fff1"] {
+            let err = split(data).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", data);
+        }
+        let err = read(&mut &b"0002"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reads_packets_from_a_stream() {
+        let mut input = &b"0008abcd00000006x"[..];
+        assert_eq!(read(&mut input).unwrap(), Some(b"abcd".to_vec()));
+        assert_eq!(read(&mut input).unwrap(), None);
+        // A packet cut short, then nothing at all
+        let err = read(&mut input).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            read(&mut input).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn sideband_writer_splits_to_fit() {
+        let mut out = Vec::new();
+        let mut writer = SidebandWriter::new(&mut out, BAND_DATA, 10);
+        writer.write_all(b"abcdefgh").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(out, b"000a\x01abcde0008\x01fgh");
+    }
+}
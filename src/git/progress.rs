@@ -0,0 +1,97 @@
+// Progress reporting and cancellation for long-running operations
+// An operation is handed a `Progress`: it passes a message to the callback as
+// each step advances, and polls the cancellation token between steps,
+// stopping with `ErrorKind::Interrupted` once the token has been cancelled.
+// Tokens are cheap to clone and may be cancelled from any thread, e.g. a
+// GUI's abort button while the operation runs on a worker thread. Unless
+// given one, a `Progress` polls the process's interrupt token, so Ctrl-C
+// cancels it.
+
+use crate::git::interrupt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Receives each progress message as an operation runs
+pub type ProgressCallback = Box<dyn FnMut(&str)>;
+
+/// Shared flag asking a running operation to stop
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// A progress callback, if any, and the token the operation polls
+pub struct Progress {
+    callback: Option<ProgressCallback>,
+    token: CancellationToken,
+}
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress {
+            callback: None,
+            token: interrupt::token(),
+        }
+    }
+}
+
+impl Progress {
+    pub fn new(callback: impl FnMut(&str) + 'static) -> Progress {
+        Progress {
+            callback: Some(Box::new(callback)),
+            ..Progress::default()
+        }
+    }
+
+    /// Reports nothing; cancelled only by an interrupt
+    pub fn silent() -> Progress {
+        Progress::default()
+    }
+
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Progress {
+        self.token = token;
+        self
+    }
+
+    pub fn report(&mut self, message: &str) {
+        if let Some(callback) = self.callback.as_mut() {
+            callback(message);
+        }
+    }
+
+    /// Whether messages go nowhere, so there is no point in asking a
+    /// remote for them
+    pub fn is_silent(&self) -> bool {
+        self.callback.is_none()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Fail with `ErrorKind::Interrupted` if the operation was cancelled
+    pub fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "operation cancelled",
+            ));
+        }
+        Ok(())
+    }
+}
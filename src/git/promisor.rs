@@ -0,0 +1,164 @@
+// Partial clones
+// A clone made with --filter (blob:none, say) leaves out the objects the
+// filter excludes, trusting the remote to hand them over when they are
+// needed. That remote is the promisor: remote.<name>.promisor marks it,
+// remote.<name>.partialclonefilter keeps the filter its later fetches use,
+// and extensions.partialClone (which needs core.repositoryformatversion 1)
+// names it the way git looks for it. Each pack fetched from it gets an
+// empty .promisor file beside it, which tells git's fsck and gc that what
+// its objects refer to but it leaves out is missing on purpose.
+//
+// A read that finds an object nowhere here fetches it from the promisor
+// (see object::read), so every command gets left-out objects. An update of
+// the work tree, about to read many of them, fetches the blobs it writes
+// all in one request first.
+
+use crate::commands::clone;
+use crate::git::config::{self, Config};
+use crate::git::progress::Progress;
+use crate::git::repository;
+use crate::git::tree::{FileEntry, MODE_GITLINK};
+use crate::git::{interrupt, object, pack};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The remote a partial clone was made from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Promisor {
+    pub remote: String,
+    /// What its fetches leave out, when they filter at all
+    pub filter: Option<String>,
+}
+
+impl Promisor {
+    /// The repository's promisor remote: the one extensions.partialClone
+    /// names, or else the first with remote.<name>.promisor set, as git
+    /// marks them too; none unless it is a partial clone
+    pub fn load() -> io::Result<Option<Promisor>> {
+        let config = Config::load()?;
+        let remote = match config.get("extensions.partialClone") {
+            Some(remote) => Some(remote),
+            None => config
+                .entries()
+                .iter()
+                .filter(|entry| entry.section == "remote" && entry.name == "promisor")
+                .filter_map(|entry| entry.subsection.as_deref())
+                .find(|remote| {
+                    let key = format!("remote.{}.promisor", remote);
+                    matches!(config.get_bool(&key), Ok(Some(true)))
+                }),
+        };
+        let Some(remote) = remote else {
+            return Ok(None);
+        };
+        let filter = config.get(&format!("remote.{}.partialclonefilter", remote));
+        Ok(Some(Promisor {
+            remote: remote.to_string(),
+            filter: filter.map(str::to_string),
+        }))
+    }
+}
+
+/// Make `remote` the promisor remote, its fetches filtered by `filter`
+pub fn record(remote: &str, filter: &str) -> io::Result<()> {
+    let path = &repository::path("config");
+    config::set_value(path, "core.repositoryformatversion", "1")?;
+    config::set_value(path, &format!("remote.{}.promisor", remote), "true")?;
+    config::set_value(
+        path,
+        &format!("remote.{}.partialclonefilter", remote),
+        filter,
+    )?;
+    config::set_value(path, "extensions.partialClone", remote)
+}
+
+/// Mark the pack just fetched from the promisor, which holds `objects`
+pub fn mark(objects: &[String]) -> io::Result<()> {
+    // Nothing fetched, or a pack unpacked into loose objects, leaves no
+    // pack to find
+    let Some(first) = objects.first() else {
+        return Ok(());
+    };
+    let local = std::env::current_dir()?.join(repository::path("objects"));
+    for pack in pack::packs()? {
+        if pack.find(first).is_some() && pack.path.starts_with(&local) {
+            fs::write(pack.path.with_extension("promisor"), "")?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetch the objects among `shas` that a partial clone left out from its
+/// promisor remote, all in one request. Outside a partial clone this does
+/// nothing, and what is missing stays missing.
+pub fn fetch(shas: &[String], progress: &mut Progress) -> io::Result<()> {
+    let Some(promisor) = Promisor::load()? else {
+        return Ok(());
+    };
+    let mut seen = HashSet::new();
+    let mut wants = Vec::new();
+    for sha in shas {
+        if seen.insert(sha) && object::locate(sha)?.is_none() {
+            wants.push(sha.clone());
+        }
+    }
+    if wants.is_empty() {
+        return Ok(());
+    }
+    let _cancellable = interrupt::cancellable();
+    let config = Config::load()?;
+    let repo_url = config
+        .get(&format!("remote.{}.url", promisor.remote))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("promisor remote '{}' has no url", promisor.remote),
+            )
+        })?;
+    progress.report(&format!(
+        "Fetching {} missing objects from {}",
+        wants.len(),
+        promisor.remote
+    ));
+    // The objects are wanted by id; none of the refs matter
+    let (session, _) =
+        clone::connect_with_prefixes(repo_url, None, &["HEAD".to_string()], progress)?;
+    let filter = promisor.filter.as_deref();
+    let (pack, _) = session.fetch(&wants, &[], None, filter, progress)?;
+    let objects = pack::store(pack, &pack::Limits::from_config(&config)?)?;
+    mark(&objects)
+}
+
+/// Fetch `sha`, found nowhere here, if this is a partial clone; whether it
+/// is here now. Reads fall back on this, so whatever reads a left-out
+/// object gets it. Reads made while a fetch is under way don't start
+/// another one.
+pub fn fetch_missing(sha: &str) -> io::Result<bool> {
+    static FETCHING: AtomicBool = AtomicBool::new(false);
+    if FETCHING.swap(true, Ordering::SeqCst) {
+        return Ok(false);
+    }
+    // Progress goes to stderr, leaving stdout to the command
+    let mut progress = Progress::new(|line| eprintln!("{}", line));
+    let result = fetch(&[sha.to_string()], &mut progress);
+    FETCHING.store(false, Ordering::SeqCst);
+    result?;
+    Ok(object::locate(sha)?.is_some())
+}
+
+/// Fetch the blobs of `files` about to be checked out, leaving out
+/// submodules, whose commits live elsewhere; one request for them all
+/// saves one per file when each is read
+pub fn prefetch<'a>(
+    files: impl IntoIterator<Item = &'a FileEntry>,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let blobs: Vec<String> = files
+        .into_iter()
+        .filter(|file| file.mode != MODE_GITLINK)
+        .map(|file| file.sha.clone())
+        .collect();
+    fetch(&blobs, progress)
+}
@@ -0,0 +1,107 @@
+// Reachability
+// Answers whether one commit is reachable from another, for merge-base
+// --is-ancestor, the --contains of tag and branch, and the fast-forward
+// checks of merge, fetch and push. A commit's generation number is one more
+// than the highest of its parents' (a root's is 1), so a commit can only
+// reach commits of lower generation. The commit-graph (see
+// git/commit_graph.rs) records the generations, and a walk looking for a
+// commit goes no deeper than its generation instead of on to the roots,
+// reading the parents from the graph rather than from the commit objects.
+//
+// Commits the graph doesn't cover, those made since it was written, have an
+// unknown generation and are walked through. They are never reachable from
+// the ones it covers, as a graph covers every ancestor of its commits.
+// Without a graph no commit has a known generation and every walk goes all
+// the way.
+//
+// Answers are remembered, so asking whether each of many tips (every tag,
+// say) reaches the same commit reads each commit once.
+
+use crate::git::commit;
+use crate::git::commit_graph::{CommitGraph, GENERATION_INFINITY};
+use std::collections::HashMap;
+use std::io;
+
+pub struct Reachability {
+    graph: Option<CommitGraph>,
+    /// The commit last looked for, and for each commit passed on the way
+    /// whether it reaches it
+    target: Option<String>,
+    reaches: HashMap<String, bool>,
+}
+
+/// A step of the walk: entering a commit, or leaving it once its parents
+/// are decided
+enum Visit {
+    Enter(String),
+    Leave(String, Vec<String>),
+}
+
+impl Reachability {
+    pub fn new() -> io::Result<Reachability> {
+        Ok(Reachability {
+            graph: CommitGraph::open()?,
+            target: None,
+            reaches: HashMap::new(),
+        })
+    }
+
+    /// Whether `ancestor` is reachable from `descendant` (a commit is its
+    /// own ancestor)
+    pub fn is_ancestor(&mut self, ancestor: &str, descendant: &str) -> io::Result<bool> {
+        if self.target.as_deref() != Some(ancestor) {
+            self.target = Some(ancestor.to_string());
+            self.reaches.clear();
+            self.reaches.insert(ancestor.to_string(), true);
+        }
+        let cutoff = self.generation(ancestor);
+        let mut stack = vec![Visit::Enter(descendant.to_string())];
+        while let Some(visit) = stack.pop() {
+            match visit {
+                Visit::Enter(sha) => match self.reaches.get(&sha) {
+                    // The commits still to be left are the path that led
+                    // here, and so reach it too
+                    Some(true) => {
+                        for visit in stack {
+                            if let Visit::Leave(sha, _) = visit {
+                                self.reaches.insert(sha, true);
+                            }
+                        }
+                        return Ok(true);
+                    }
+                    Some(false) => {}
+                    None if self.generation(&sha) < cutoff => {
+                        self.reaches.insert(sha, false);
+                    }
+                    None => {
+                        // Left once the parents above it are done
+                        let parents = self.parents(&sha)?;
+                        stack.push(Visit::Leave(sha, parents.clone()));
+                        stack.extend(parents.into_iter().map(Visit::Enter));
+                    }
+                },
+                Visit::Leave(sha, parents) => {
+                    let reaches = parents.iter().any(|p| self.reaches.get(p) == Some(&true));
+                    self.reaches.insert(sha, reaches);
+                }
+            }
+        }
+        Ok(self.reaches.get(descendant) == Some(&true))
+    }
+
+    fn generation(&self, sha: &str) -> u32 {
+        match &self.graph {
+            Some(graph) => graph.generation(sha),
+            None => GENERATION_INFINITY,
+        }
+    }
+
+    fn parents(&self, sha: &str) -> io::Result<Vec<String>> {
+        if let Some(graph) = &self.graph {
+            if let Some(parents) = graph.parents(sha)? {
+                return Ok(parents);
+            }
+        }
+        Ok(commit::read(sha)?.parents)
+    }
+}
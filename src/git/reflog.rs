@@ -0,0 +1,139 @@
+// Reflogs
+// Every update of a ref is recorded in `.git/logs/<refname>`, one line per
+// update, oldest first:
+//
+// <old sha> <new sha> <Name> <<email>> <epoch> <+hhmm>\t<message>
+//
+// A ref that didn't exist before has the all-zero id as its old value.
+// core.logAllRefUpdates decides which refs get a log: by default HEAD,
+// branches, remote-tracking branches and notes do (none do in a bare
+// repository); "false" limits logging to refs whose log already exists,
+// "always" logs every ref. refs/stash is always logged: its log is the
+// stack of stashes.
+
+use crate::git::config::Config;
+use crate::git::dry_run;
+use crate::git::fsync::{self, Component};
+use crate::git::ident;
+use crate::git::repository;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub const ZERO_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// One recorded update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub old: String,
+    pub new: String,
+    /// `Name <email> <epoch> <+hhmm>` of whoever made the update
+    pub identity: String,
+    pub message: String,
+}
+
+fn log_path(refname: &str) -> PathBuf {
+    repository::path("logs").join(refname)
+}
+
+/// Whether `refname` has a reflog
+pub fn exists(refname: &str) -> bool {
+    log_path(refname).is_file()
+}
+
+/// Whether an update of `refname` should be logged
+fn should_log(refname: &str) -> io::Result<bool> {
+    if exists(refname) || refname == "refs/stash" {
+        return Ok(true);
+    }
+    let config = Config::load()?;
+    match config.get("core.logAllRefUpdates") {
+        Some(value) if value.eq_ignore_ascii_case("always") => Ok(true),
+        _ if config.get_bool("core.logAllRefUpdates")? == Some(false) => Ok(false),
+        None if config.get_bool("core.bare")? == Some(true) => Ok(false),
+        _ => Ok(refname == "HEAD"
+            || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                .iter()
+                .any(|prefix| refname.starts_with(prefix))),
+    }
+}
+
+/// Record that `refname` moved from `old` (`None` if it didn't exist) to
+/// `new`, when the ref is one that gets logged
+pub fn append(refname: &str, old: Option<&str>, new: &str, message: &str) -> io::Result<()> {
+    if dry_run::is_enabled() || !should_log(refname)? {
+        return Ok(());
+    }
+    let path = log_path(refname);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // The message is a single line
+    let message = message.lines().next().unwrap_or("").trim_end();
+    let line = format!(
+        "{} {} {}\t{}\n",
+        old.unwrap_or(ZERO_SHA),
+        new,
+        ident::reflog_signature(),
+        message
+    );
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    file.write_all(line.as_bytes())?;
+    fsync::sync(&file, &path, Component::Reference)
+}
+
+/// The entries of `refname`'s log, oldest first; empty if it has none
+pub fn read(refname: &str) -> io::Result<Vec<Entry>> {
+    let text = match fs::read_to_string(log_path(refname)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut fields = head.splitn(3, ' ');
+        let (Some(old), Some(new), Some(identity)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("corrupt reflog entry for {}: {}", refname, line),
+            ));
+        };
+        entries.push(Entry {
+            old: old.to_string(),
+            new: new.to_string(),
+            identity: identity.to_string(),
+            message: message.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Replace `refname`'s log with `entries`, oldest first
+pub fn write(refname: &str, entries: &[Entry]) -> io::Result<()> {
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+    let content: String = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{} {} {}\t{}\n",
+                entry.old, entry.new, entry.identity, entry.message
+            )
+        })
+        .collect();
+    fsync::write(&log_path(refname), content.as_bytes(), Component::Reference)
+}
+
+/// Remove `refname`'s log, as when the ref itself is deleted
+pub fn delete(refname: &str) -> io::Result<()> {
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+    match fs::remove_file(log_path(refname)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,789 @@
+// Reference reading and writing
+// Refs live as loose files under `.git/`, each holding either a 40-character
+// SHA or a `ref: <target>` pointer to another ref (a symbolic ref), or as
+// lines of `.git/packed-refs`:
+//
+// # pack-refs with: peeled fully-peeled sorted
+// <sha> refs/tags/v1.0
+// ^<sha of the commit the tag points at>
+//
+// A loose ref takes precedence over a packed one of the same name, so refs
+// are written loose; deleting a ref removes it from both places.
+//
+// Listing refs reads only the loose ones in the directories that can hold
+// a match, and packed-refs is parsed once and kept, sorted, for as long as
+// the file stays the same (the same modification time and size), so that
+// looking refs up in a repository with tens of thousands of them doesn't
+// mean reading them all over again each time.
+//
+// Every write goes through `<ref>.lock`: the lock file is created
+// exclusively, so a second writer fails instead of racing, and is renamed
+// over the ref once its new content is on disk, so readers see either the
+// old value or the new one. A compare-and-swap checks the old value while
+// holding the lock.
+
+use crate::git::config::Config;
+use crate::git::dry_run;
+use crate::git::fsync::{self, Component};
+use crate::git::interrupt::{self, CleanupGuard};
+use crate::git::reflog::{self, ZERO_SHA};
+use crate::git::repository;
+use crate::git::tag;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Where HEAD points
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// Attached to a branch, e.g. `refs/heads/main` (which may not exist yet)
+    Branch(String),
+    /// Detached at a commit
+    Detached(String),
+}
+
+/// Read `.git/HEAD` without resolving it
+pub fn read_head() -> io::Result<Head> {
+    let content = fs::read_to_string(repository::path("HEAD"))?;
+    let content = content.trim();
+    match content.strip_prefix("ref:") {
+        Some(target) => Ok(Head::Branch(target.trim().to_string())),
+        None if is_sha(content) => Ok(Head::Detached(content.to_string())),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid HEAD: {}", content),
+        )),
+    }
+}
+
+/// Commit HEAD currently resolves to, or `None` on an unborn branch
+pub fn head_commit() -> io::Result<Option<String>> {
+    match read_head()? {
+        Head::Branch(name) => read_ref(&name),
+        Head::Detached(sha) => Ok(Some(sha)),
+    }
+}
+
+/// Fail with git's message when HEAD is on a branch with no commits yet, for
+/// commands that need HEAD to point at one
+pub fn require_head_commit() -> io::Result<String> {
+    match read_head()? {
+        Head::Detached(sha) => Ok(sha),
+        Head::Branch(name) => read_ref(&name)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "your current branch '{}' does not have any commits yet",
+                    name.strip_prefix("refs/heads/").unwrap_or(&name)
+                ),
+            )
+        }),
+    }
+}
+
+/// Resolve a full ref name (e.g. `refs/heads/main` or `HEAD`) to a SHA,
+/// following symbolic refs. Returns `None` if the ref doesn't exist.
+pub fn read_ref(name: &str) -> io::Result<Option<String>> {
+    let mut name = name.to_string();
+    // Bound the chain so a symref loop can't hang us
+    for _ in 0..5 {
+        let path = repository::path(&name);
+        if !path.is_file() {
+            return packed_ref(&name);
+        }
+        let content = fs::read_to_string(&path)?;
+        let content = content.trim();
+        match content.strip_prefix("ref:") {
+            Some(target) => name = target.trim().to_string(),
+            None if is_sha(content) => return Ok(Some(content.to_string())),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid ref {}: {}", name, content),
+                ))
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Symbolic ref chain too deep at {}", name),
+    ))
+}
+
+/// The lock on one ref while it is being updated. Dropping it without
+/// committing leaves the ref as it was. In a dry run no lock file is made
+/// and committing or deleting changes nothing.
+pub struct RefLock {
+    name: String,
+    path: PathBuf,
+    lock_path: PathBuf,
+    /// `None` in a dry run
+    file: Option<File>,
+    /// Set once the lock file has become the ref
+    committed: bool,
+    _cleanup: CleanupGuard,
+}
+
+impl RefLock {
+    /// Take the lock on `name` (a full ref name), creating parent
+    /// directories. Fails if another process holds it, or if `name` isn't
+    /// a valid ref name, so no update or deletion reaches outside refs.
+    pub fn acquire(name: &str) -> io::Result<RefLock> {
+        check_name(name)?;
+        let path = repository::path(name);
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let cleanup = interrupt::remove_on_interrupt(&lock_path);
+        if dry_run::is_enabled() {
+            return Ok(RefLock {
+                name: name.to_string(),
+                path,
+                lock_path,
+                file: None,
+                committed: true,
+                _cleanup: cleanup,
+            });
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "Unable to create '{}': File exists.\n\nAnother git process seems to be running in this repository.\nIf no other process is running, remove the file manually to continue.",
+                        lock_path.display()
+                    ),
+                ))
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(RefLock {
+            name: name.to_string(),
+            path,
+            lock_path,
+            file: Some(file),
+            committed: false,
+            _cleanup: cleanup,
+        })
+    }
+
+    /// Fail unless the ref currently holds `expected`; the all-zero id
+    /// expects it not to exist
+    pub fn verify(&self, expected: &str) -> io::Result<()> {
+        let current = read_ref(&self.name)?;
+        match (current.as_deref(), expected) {
+            (None, ZERO_SHA) => Ok(()),
+            (Some(current), expected) if current == expected => Ok(()),
+            (Some(_), ZERO_SHA) => Err(self.conflict("reference already exists")),
+            (None, _) => {
+                Err(self.conflict(&format!("unable to resolve reference '{}'", self.name)))
+            }
+            (Some(current), expected) => {
+                Err(self.conflict(&format!("is at {} but expected {}", current, expected)))
+            }
+        }
+    }
+
+    fn conflict(&self, reason: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("cannot lock ref '{}': {}", self.name, reason),
+        )
+    }
+
+    /// Replace the ref with `content` and release the lock
+    pub fn commit(mut self, content: &str) -> io::Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        file.write_all(content.as_bytes())?;
+        fsync::sync(file, &self.lock_path, Component::Reference)?;
+        fs::rename(&self.lock_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    /// Remove the ref and release the lock
+    pub fn delete(self) -> io::Result<()> {
+        match self.file {
+            Some(_) => fs::remove_file(&self.path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RefLock {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// Point `name` (a full ref name) at `sha`, creating parent directories
+pub fn write_ref(name: &str, sha: &str) -> io::Result<()> {
+    RefLock::acquire(name)?.commit(&format!("{}\n", sha))
+}
+
+/// Delete a ref, loose or packed, and its reflog, pruning directories it leaves empty
+pub fn delete_ref(name: &str) -> io::Result<()> {
+    remove(RefLock::acquire(name)?)
+}
+
+/// Delete `name` as `delete_ref` does if it currently holds `expected`
+pub fn delete_ref_checked(name: &str, expected: &str) -> io::Result<()> {
+    let lock = RefLock::acquire(name)?;
+    lock.verify(expected)?;
+    remove(lock)
+}
+
+fn remove(lock: RefLock) -> io::Result<()> {
+    let name = lock.name.clone();
+    let path = lock.path.clone();
+    let was_packed = remove_packed(&name)?;
+    match lock.delete() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && was_packed => {}
+        result => result?,
+    }
+    reflog::delete(&name)?;
+    prune_empty_dirs(&path);
+    Ok(())
+}
+
+/// Remove the directories under .git/refs that `path` leaves empty
+fn prune_empty_dirs(path: &Path) {
+    if dry_run::is_enabled() {
+        return;
+    }
+    let refs_root = &repository::path("refs");
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        if dir == refs_root || !dir.starts_with(refs_root) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+}
+
+/// All refs under `prefix` (e.g. `refs/tags/`) as sorted `(name, sha)`
+/// pairs, loose and packed
+pub fn list_refs(prefix: &str) -> io::Result<Vec<(String, String)>> {
+    refs_glob(&format!("{}/*", prefix.trim_end_matches('/')))
+}
+
+/// The refs under refs/ whose names match the glob `pattern` (as
+/// `glob_match` takes it, `*` reaching across slashes), as sorted
+/// `(name, sha)` pairs, loose and packed. Only the part of the pattern
+/// before its first wildcard decides where to look: the loose refs outside
+/// the directories it leads to aren't read, and the packed refs are looked
+/// up from where it would sort.
+pub fn refs_glob(pattern: &str) -> io::Result<Vec<(String, String)>> {
+    let literal = &pattern[..pattern.find(['*', '?', '[']).unwrap_or(pattern.len())];
+    let mut refs = BTreeMap::new();
+    let packed = packed_refs()?;
+    let start = packed.partition_point(|entry| entry.name.as_str() < literal);
+    for entry in packed[start..]
+        .iter()
+        .take_while(|entry| entry.name.starts_with(literal))
+    {
+        if glob_match(pattern, &entry.name) {
+            refs.insert(entry.name.clone(), entry.sha.clone());
+        }
+    }
+    let mut loose = Vec::new();
+    let refs_dir = repository::path("refs");
+    if refs_dir.is_dir() {
+        let wanted = |name: &str| name.starts_with(literal) && glob_match(pattern, name);
+        collect_matching(&refs_dir, "refs", literal, &wanted, &mut loose)?;
+    }
+    refs.extend(loose);
+    Ok(refs.into_iter().collect())
+}
+
+fn collect_refs(dir: &Path, name: &str, refs: &mut Vec<(String, String)>) -> io::Result<()> {
+    collect_matching(dir, name, "", &|_| true, refs)
+}
+
+/// The loose refs `wanted` picks under `dir`, which holds the refs named
+/// `name/...`, passing over directories whose names can't start with
+/// `literal`
+fn collect_matching(
+    dir: &Path,
+    name: &str,
+    literal: &str,
+    wanted: &dyn Fn(&str) -> bool,
+    refs: &mut Vec<(String, String)>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let child = format!("{}/{}", name, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            let below = format!("{}/", child);
+            if below.starts_with(literal) || literal.starts_with(&below) {
+                collect_matching(&entry.path(), &child, literal, wanted, refs)?;
+            }
+        } else if wanted(&child) {
+            if let Some(sha) = read_ref(&child)? {
+                refs.push((child, sha));
+            }
+        }
+    }
+    Ok(())
+}
+
+const PACKED_REFS: &str = "packed-refs";
+
+/// One ref in `.git/packed-refs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PackedRef {
+    name: String,
+    sha: String,
+    /// What an annotated tag points at, from the `^` line after it
+    peeled: Option<String>,
+}
+
+/// packed-refs as it was last parsed: where it is, and the modification
+/// time and size it had then
+struct PackedCache {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    refs: Arc<Vec<PackedRef>>,
+}
+
+static PACKED_CACHE: Mutex<Option<PackedCache>> = Mutex::new(None);
+
+/// The refs of `.git/packed-refs`, sorted by name, parsed again only when
+/// the file has changed since the last time
+fn packed_refs() -> io::Result<Arc<Vec<PackedRef>>> {
+    let path = std::env::current_dir()?.join(repository::path(PACKED_REFS));
+    let stamp = match fs::metadata(&path) {
+        Ok(metadata) => Some((metadata.modified()?, metadata.len())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let mut cache = PACKED_CACHE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(cached) = cache.as_ref() {
+        if cached.path == path && cached.stamp == stamp {
+            return Ok(Arc::clone(&cached.refs));
+        }
+    }
+    let mut refs = parse_packed_refs()?;
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    let refs = Arc::new(refs);
+    *cache = Some(PackedCache {
+        path,
+        stamp,
+        refs: Arc::clone(&refs),
+    });
+    Ok(refs)
+}
+
+fn read_packed_refs() -> io::Result<Vec<PackedRef>> {
+    Ok(packed_refs()?.to_vec())
+}
+
+fn parse_packed_refs() -> io::Result<Vec<PackedRef>> {
+    let text = match fs::read_to_string(repository::path(PACKED_REFS)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let corrupt = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected line in {}: {}", PACKED_REFS, line),
+        )
+    };
+    let mut packed: Vec<PackedRef> = Vec::new();
+    for line in text.lines() {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(peeled) = line.strip_prefix('^') {
+            let last = packed.last_mut().ok_or_else(|| corrupt(line))?;
+            if !is_sha(peeled) {
+                return Err(corrupt(line));
+            }
+            last.peeled = Some(peeled.to_string());
+            continue;
+        }
+        match line.split_once(' ') {
+            Some((sha, name)) if is_sha(sha) => packed.push(PackedRef {
+                name: name.to_string(),
+                sha: sha.to_string(),
+                peeled: None,
+            }),
+            _ => return Err(corrupt(line)),
+        }
+    }
+    Ok(packed)
+}
+
+/// Replace `.git/packed-refs` with `packed`, sorted by name
+fn write_packed_refs(mut packed: Vec<PackedRef>) -> io::Result<()> {
+    packed.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut content = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+    for entry in &packed {
+        content.push_str(&format!("{} {}\n", entry.sha, entry.name));
+        if let Some(peeled) = &entry.peeled {
+            content.push_str(&format!("^{}\n", peeled));
+        }
+    }
+    RefLock::acquire("packed-refs")?.commit(&content)?;
+    // A rewrite within the same tick of the clock could look unchanged
+    *PACKED_CACHE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    Ok(())
+}
+
+fn packed_ref(name: &str) -> io::Result<Option<String>> {
+    let packed = packed_refs()?;
+    Ok(packed
+        .binary_search_by(|entry| entry.name.as_str().cmp(name))
+        .ok()
+        .map(|found| packed[found].sha.clone()))
+}
+
+/// Take `name` out of `.git/packed-refs`; whether it was there
+fn remove_packed(name: &str) -> io::Result<bool> {
+    let mut packed = read_packed_refs()?;
+    let before = packed.len();
+    packed.retain(|entry| entry.name != name);
+    if packed.len() == before {
+        return Ok(false);
+    }
+    write_packed_refs(packed)?;
+    Ok(true)
+}
+
+/// Move loose refs into `.git/packed-refs`: the tags, or with `all` every
+/// ref under refs/ that isn't symbolic. Refs already packed stay packed.
+/// With `prune` the loose files are removed once packed; their reflogs are
+/// kept.
+pub fn pack_refs(all: bool, prune: bool) -> io::Result<()> {
+    let mut packed: BTreeMap<String, PackedRef> = read_packed_refs()?
+        .into_iter()
+        .map(|entry| (entry.name.clone(), entry))
+        .collect();
+    let mut loose = Vec::new();
+    let refs_dir = repository::path("refs");
+    if refs_dir.is_dir() {
+        collect_refs(&refs_dir, "refs", &mut loose)?;
+    }
+    let mut to_prune = Vec::new();
+    for (name, sha) in loose {
+        if (!all && !name.starts_with("refs/tags/")) || read_symref(&name)?.is_some() {
+            continue;
+        }
+        let (target, _) = tag::peel(&sha)?;
+        let peeled = (target != sha).then_some(target);
+        packed.insert(
+            name.clone(),
+            PackedRef {
+                name: name.clone(),
+                sha: sha.clone(),
+                peeled,
+            },
+        );
+        to_prune.push((name, sha));
+    }
+    write_packed_refs(packed.into_values().collect())?;
+
+    if prune {
+        for (name, sha) in to_prune {
+            // A ref that moved since it was read keeps its new loose value
+            let lock = RefLock::acquire(&name)?;
+            if lock.verify(&sha).is_ok() {
+                let path = lock.path.clone();
+                lock.delete()?;
+                prune_empty_dirs(&path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Attach HEAD to the branch `refname`
+pub fn write_head_symbolic(refname: &str) -> io::Result<()> {
+    write_symref("HEAD", refname)
+}
+
+/// Make `name` a symbolic ref pointing at `target`
+pub fn write_symref(name: &str, target: &str) -> io::Result<()> {
+    RefLock::acquire(name)?.commit(&format!("ref: {}\n", target))
+}
+
+/// The ref `name` points at if it is a symbolic ref, `None` if it holds a
+/// SHA or doesn't exist
+pub fn read_symref(name: &str) -> io::Result<Option<String>> {
+    match fs::read_to_string(repository::path(name)) {
+        Ok(content) => Ok(content
+            .trim()
+            .strip_prefix("ref:")
+            .map(|target| target.trim().to_string())),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::IsADirectory
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Detach HEAD at `sha`
+pub fn write_head_detached(sha: &str) -> io::Result<()> {
+    write_ref("HEAD", sha)
+}
+
+/// Point `name` at `sha` as `write_ref` does, recording the update in its
+/// reflog, and in HEAD's when HEAD is on `name`
+pub fn update_ref(name: &str, sha: &str, message: &str) -> io::Result<()> {
+    update_ref_checked(name, sha, None, message)
+}
+
+/// `update_ref`, but only if `name` holds `expected` (the all-zero id for
+/// a ref that must not exist yet) at the moment it is locked
+pub fn update_ref_checked(
+    name: &str,
+    sha: &str,
+    expected: Option<&str>,
+    message: &str,
+) -> io::Result<()> {
+    let lock = RefLock::acquire(name)?;
+    if let Some(expected) = expected {
+        lock.verify(expected)?;
+    }
+    let old = read_ref(name)?;
+    lock.commit(&format!("{}\n", sha))?;
+    reflog::append(name, old.as_deref(), sha, message)?;
+    if read_head()? == Head::Branch(name.to_string()) {
+        reflog::append("HEAD", old.as_deref(), sha, message)?;
+    }
+    Ok(())
+}
+
+/// Move the current branch, or a detached HEAD, to `sha`, logging `message`
+pub fn update_head(sha: &str, message: &str) -> io::Result<()> {
+    match read_head()? {
+        Head::Branch(name) => update_ref(&name, sha, message),
+        Head::Detached(_) => update_head_detached(sha, message),
+    }
+}
+
+/// Detach HEAD at `sha`, logging `message` in HEAD's reflog
+pub fn update_head_detached(sha: &str, message: &str) -> io::Result<()> {
+    let old = read_ref("HEAD")?;
+    write_head_detached(sha)?;
+    reflog::append("HEAD", old.as_deref(), sha, message)
+}
+
+/// Attach HEAD to the branch `refname`, logging `message` in HEAD's reflog
+/// unless the branch is yet to be born
+pub fn update_head_symbolic(refname: &str, message: &str) -> io::Result<()> {
+    update_symref("HEAD", refname, message)
+}
+
+/// Make `name` a symbolic ref pointing at `target`, logging `message` in
+/// `name`'s reflog unless `target` doesn't exist yet
+pub fn update_symref(name: &str, target: &str, message: &str) -> io::Result<()> {
+    let old = read_ref(name)?;
+    write_symref(name, target)?;
+    match read_ref(target)? {
+        Some(new) => reflog::append(name, old.as_deref(), &new, message),
+        None => Ok(()),
+    }
+}
+
+/// Resolve a user-supplied name to a SHA, trying it as a full SHA, then
+/// `HEAD`, then the usual ref namespaces in git's DWIM order
+pub fn resolve(name: &str) -> io::Result<Option<String>> {
+    if is_sha(name) {
+        return Ok(Some(name.to_string()));
+    }
+    match dwim(name)? {
+        Some(refname) => read_ref(&refname),
+        None => Ok(None),
+    }
+}
+
+/// The full name of the ref a short `name` stands for, in git's DWIM
+/// order: `HEAD` (or another pseudo-ref under `.git/` itself), then tags,
+/// branches and remote-tracking branches. A candidate file that doesn't
+/// hold a ref counts as no match, so a branch isn't hidden by it.
+pub fn dwim(name: &str) -> io::Result<Option<String>> {
+    for candidate in dwim_candidates(name) {
+        match read_ref(&candidate) {
+            Ok(Some(_)) => return Ok(Some(candidate)),
+            Ok(None) => {}
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+fn dwim_candidates(name: &str) -> Vec<String> {
+    let mut candidates = Vec::new();
+    if is_pseudo_ref(name) {
+        candidates.push(name.to_string());
+    }
+    candidates.extend([
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+        format!("refs/remotes/{}/HEAD", name),
+    ]);
+    candidates
+}
+
+/// Whether `name` may be a ref directly under `.git/`, like HEAD,
+/// FETCH_HEAD or ORIG_HEAD: upper-case letters and underscores only, so
+/// that `config` or `index` are never read as refs
+fn is_pseudo_ref(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+}
+
+/// `refname` without its namespace, unless the short name would find
+/// another ref first
+pub fn shorten(refname: &str) -> io::Result<String> {
+    for namespace in ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"] {
+        if let Some(short) = refname.strip_prefix(namespace) {
+            if dwim(short)?.as_deref() == Some(refname) {
+                return Ok(short.to_string());
+            }
+        }
+    }
+    Ok(refname.to_string())
+}
+
+/// Whether `name` is well-formed as a ref name: `/`-separated components,
+/// none empty, starting with "." or ending with ".lock", and no "..",
+/// "@{", control characters, spaces or any of `~^:?*[\`. The name may
+/// not end in "." or be just "@".
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "@"
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+        && name.split('/').all(|component| {
+            !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+        })
+}
+
+/// `is_valid_name`, but also allowing a single "*" as a refspec pattern
+/// may have, as in `refs/heads/*`
+pub fn is_valid_pattern(name: &str) -> bool {
+    name.matches('*').count() <= 1 && is_valid_name(&name.replacen('*', "x", 1))
+}
+
+/// Whether `branch` may name a local branch: `refs/heads/<branch>` has to
+/// be a valid ref name, and the name can't look like an option or be HEAD
+pub fn is_valid_branch_name(branch: &str) -> bool {
+    !branch.starts_with('-') && branch != "HEAD" && is_valid_name(&branch_ref(branch))
+}
+
+/// Refuse to lock a ref under a name `is_valid_name` rejects
+fn check_name(name: &str) -> io::Result<()> {
+    if is_valid_name(name) {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("refusing to update ref with bad name '{}'", name),
+    ))
+}
+
+/// Full ref name of a local branch
+pub fn branch_ref(branch: &str) -> String {
+    format!("refs/heads/{}", branch)
+}
+
+/// The branch a new repository starts out on: `init.defaultBranch`, or
+/// main
+pub fn default_branch() -> io::Result<String> {
+    match Config::load()?.get("init.defaultbranch") {
+        None => Ok("main".to_string()),
+        Some(branch) if is_valid_branch_name(branch) => Ok(branch.to_string()),
+        Some(branch) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid initial branch name: '{}'", branch),
+        )),
+    }
+}
+
+/// Full ref name of a tag
+pub fn tag_ref(tag: &str) -> String {
+    format!("refs/tags/{}", tag)
+}
+
+/// Shell-style glob match supporting `*`, `?` and `[...]` classes, as used for
+/// ref patterns like `refs/tags/v1.*`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_at(&pattern, &text)
+}
+
+fn glob_match_at(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|skip| glob_match_at(&pattern[1..], &text[skip..])),
+        Some('?') => !text.is_empty() && glob_match_at(&pattern[1..], &text[1..]),
+        Some('[') => {
+            let close = match pattern.iter().skip(1).position(|&c| c == ']') {
+                Some(close) => close + 1,
+                None => {
+                    return text.first() == Some(&'[') && glob_match_at(&pattern[1..], &text[1..])
+                }
+            };
+            let Some(&c) = text.first() else {
+                return false;
+            };
+            let class = &pattern[1..close];
+            let (negate, class) = match class.first() {
+                Some('!') | Some('^') => (true, &class[1..]),
+                _ => (false, class),
+            };
+            let mut matched = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    matched |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+            matched != negate && glob_match_at(&pattern[close + 1..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && glob_match_at(&pattern[1..], &text[1..]),
+    }
+}
+
+pub fn is_sha(value: &str) -> bool {
+    value.len() == 40 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
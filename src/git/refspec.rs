@@ -0,0 +1,124 @@
+// Refspecs
+// `[+]<src>[:<dst>]` maps refs on one side of a fetch or push to refs on
+// the other: "+refs/heads/*:refs/remotes/origin/*" stores every branch of
+// the remote as a remote-tracking branch, "+" allowing updates that aren't
+// fast-forwards. A "*" in <src> matches any part of a name, which takes its
+// place in <dst>; both sides need one or neither. A <src> without "refs/"
+// is found the way a short ref name is: as itself, under refs/, refs/tags/,
+// refs/heads/ or refs/remotes/, in that order. Without <dst> the ref is
+// fetched but not stored anywhere but FETCH_HEAD.
+
+use crate::git::refs;
+use std::io;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    pub force: bool,
+    pub src: String,
+    pub dst: Option<String>,
+}
+
+impl Refspec {
+    pub fn parse(spec: &str) -> io::Result<Refspec> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid refspec '{}'", spec),
+            )
+        };
+        let (force, rest) = match spec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let (src, dst) = match rest.split_once(':') {
+            Some((src, dst)) => (src, Some(dst).filter(|dst| !dst.is_empty())),
+            None => (rest, None),
+        };
+        if src.is_empty() {
+            return Err(invalid());
+        }
+        let globs = |side: &str| side.matches('*').count();
+        match dst {
+            Some(dst) if globs(src) != globs(dst) => return Err(invalid()),
+            None if globs(src) > 0 => return Err(invalid()),
+            _ if globs(src) > 1 => return Err(invalid()),
+            _ => {}
+        }
+        let valid = |side: &str| {
+            side == "HEAD"
+                || refs::is_valid_pattern(side)
+                || refs::is_valid_pattern(&format!("refs/heads/{}", side))
+        };
+        if !valid(src) || dst.is_some_and(|dst| !valid(dst)) {
+            return Err(invalid());
+        }
+        Ok(Refspec {
+            force,
+            src: src.to_string(),
+            dst: dst.map(str::to_string),
+        })
+    }
+
+    pub fn is_pattern(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    /// What a protocol v2 ls-refs needs to list for <src> to select from:
+    /// the part of a pattern before its "*", every name a short name may
+    /// stand for
+    pub fn ref_prefixes(&self) -> Vec<String> {
+        match self.src.split_once('*') {
+            Some((prefix, _)) => vec![prefix.to_string()],
+            None => short_name_candidates(&self.src),
+        }
+    }
+
+    /// The refs among `names` that <src> selects, each with where <dst>
+    /// puts it (`None` without a <dst>)
+    pub fn select<'a>(&self, names: &[&'a str]) -> Vec<(&'a str, Option<String>)> {
+        if let Some((prefix, suffix)) = self.src.split_once('*') {
+            return names
+                .iter()
+                .filter_map(|name| {
+                    let middle = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                    let dst = self.dst.as_ref().map(|dst| dst.replacen('*', middle, 1));
+                    Some((*name, dst))
+                })
+                .collect();
+        }
+        let found = short_name_candidates(&self.src)
+            .into_iter()
+            .find_map(|candidate| names.iter().find(|name| **name == candidate));
+        match found {
+            Some(name) => vec![(*name, self.dst.as_ref().map(|dst| full_dst(dst, name)))],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The full names a short ref name may stand for, in the order tried
+fn short_name_candidates(name: &str) -> Vec<String> {
+    if name == "HEAD" || name.starts_with("refs/") {
+        return vec![name.to_string()];
+    }
+    vec![
+        name.to_string(),
+        format!("refs/{}", name),
+        refs::tag_ref(name),
+        refs::branch_ref(name),
+        format!("refs/remotes/{}", name),
+        format!("refs/remotes/{}/HEAD", name),
+    ]
+}
+
+/// A <dst> named in short, which lands next to its source: a tag among
+/// tags, anything else among branches
+fn full_dst(dst: &str, src: &str) -> String {
+    if dst == "HEAD" || dst.starts_with("refs/") {
+        dst.to_string()
+    } else if src.starts_with("refs/tags/") {
+        refs::tag_ref(dst)
+    } else {
+        refs::branch_ref(dst)
+    }
+}
@@ -0,0 +1,542 @@
+// Regular expressions for grep
+// POSIX basic (the default) and extended syntax over bytes, with the GNU
+// extensions git's grep gets from the C library:
+//
+//   .  [abc]  [^a-z]  [[:alpha:]]   any byte, bracket expressions
+//   ^  $  \<  \>  \b  \B            anchors and word boundaries
+//   \w  \W  \s  \S                  word and space classes
+//   *  +  ?  {m,n}  |  ( )          extended syntax; in basic syntax
+//                                   +, ?, {, |, ( and ) need a backslash
+//
+// Back-references are not supported. Patterns compile to a small program
+// run as a Pike VM, which tries every alternative in step, so matching
+// takes time linear in the line whatever the pattern.
+
+const CLASS_NAMES: &[&str] = &[
+    "alnum", "alpha", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space",
+    "upper", "xdigit",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Basic,
+    Extended,
+    /// The pattern is a plain string
+    Fixed,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+type ByteSet = Box<[bool; 256]>;
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Byte(ByteSet),
+    Split(usize, usize),
+    Jump(usize),
+    Assert(Assertion),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+    WordStart,
+    WordEnd,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Set(ByteSet),
+    Assert(Assertion),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+/// Repetition counts beyond this are refused, as the program grows with them
+const MAX_REPEAT: u32 = 1000;
+
+impl Regex {
+    pub fn new(pattern: &str, syntax: Syntax, ignore_case: bool) -> Result<Regex, String> {
+        Regex::with_words(pattern, syntax, ignore_case, false)
+    }
+
+    /// With `words`, a match must start and end at word boundaries
+    pub fn with_words(
+        pattern: &str,
+        syntax: Syntax,
+        ignore_case: bool,
+        words: bool,
+    ) -> Result<Regex, String> {
+        let mut node = match syntax {
+            Syntax::Fixed => Node::Concat(
+                pattern
+                    .bytes()
+                    .map(|b| Node::Set(literal(b, ignore_case)))
+                    .collect(),
+            ),
+            _ => {
+                let mut parser = Parser {
+                    pattern: pattern.as_bytes(),
+                    pos: 0,
+                    extended: syntax == Syntax::Extended,
+                    ignore_case,
+                    depth: 0,
+                };
+                let node = parser.alternation()?;
+                if parser.pos < parser.pattern.len() {
+                    return Err("Unmatched ) or \\)".to_string());
+                }
+                node
+            }
+        };
+        if words {
+            node = Node::Concat(vec![
+                Node::Assert(Assertion::WordBoundary),
+                node,
+                Node::Assert(Assertion::WordBoundary),
+            ]);
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    /// Whether the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        let len = self.program.len();
+        let mut current = Threads::new(len);
+        let mut next = Threads::new(len);
+        for pos in 0..=text.len() {
+            // A new attempt starts at every position
+            if self.add(&mut current, 0, text, pos) {
+                return true;
+            }
+            let Some(&byte) = text.get(pos) else {
+                break;
+            };
+            next.clear();
+            for i in 0..current.list.len() {
+                let pc = current.list[i];
+                if let Inst::Byte(set) = &self.program[pc] {
+                    if set[byte as usize] && self.add(&mut next, pc + 1, text, pos + 1) {
+                        return true;
+                    }
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Follow the empty transitions from `pc` at `pos`, queueing the byte
+    /// tests they reach; returns whether one reaches the match
+    fn add(&self, threads: &mut Threads, pc: usize, text: &[u8], pos: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if threads.seen[pc] {
+                continue;
+            }
+            threads.seen[pc] = true;
+            match &self.program[pc] {
+                Inst::Byte(_) => threads.list.push(pc),
+                Inst::Split(a, b) => {
+                    stack.push(*b);
+                    stack.push(*a);
+                }
+                Inst::Jump(target) => stack.push(*target),
+                Inst::Assert(assertion) => {
+                    if holds(*assertion, text, pos) {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::Match => return true,
+            }
+        }
+        false
+    }
+}
+
+struct Threads {
+    list: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            list: Vec::new(),
+            seen: vec![false; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.iter_mut().for_each(|seen| *seen = false);
+    }
+}
+
+fn is_word(byte: Option<&u8>) -> bool {
+    byte.is_some_and(|&b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+fn holds(assertion: Assertion, text: &[u8], pos: usize) -> bool {
+    let before = is_word(pos.checked_sub(1).and_then(|i| text.get(i)));
+    let after = is_word(text.get(pos));
+    match assertion {
+        Assertion::Start => pos == 0,
+        Assertion::End => pos == text.len(),
+        Assertion::WordBoundary => before != after,
+        Assertion::NotWordBoundary => before == after,
+        Assertion::WordStart => !before && after,
+        Assertion::WordEnd => before && !after,
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Set(set) => program.push(Inst::Byte(set.clone())),
+        Node::Assert(assertion) => program.push(Inst::Assert(*assertion)),
+        Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, program)),
+        Node::Alternate(nodes) => {
+            let mut jumps = Vec::new();
+            for (i, node) in nodes.iter().enumerate() {
+                if i + 1 < nodes.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    let next = program.len();
+                    program[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(node, program);
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    program.push(Inst::Jump(split));
+                    let end = program.len();
+                    program[split] = Inst::Split(split + 1, end);
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn literal(byte: u8, ignore_case: bool) -> ByteSet {
+    let mut set = Box::new([false; 256]);
+    set[byte as usize] = true;
+    if ignore_case {
+        set[byte.to_ascii_lowercase() as usize] = true;
+        set[byte.to_ascii_uppercase() as usize] = true;
+    }
+    set
+}
+
+fn set_of(test: impl Fn(u8) -> bool) -> ByteSet {
+    let mut set = Box::new([false; 256]);
+    for b in 0..=255u8 {
+        set[b as usize] = test(b);
+    }
+    set
+}
+
+fn named_class(name: &str) -> Option<fn(u8) -> bool> {
+    Some(match name {
+        "alnum" => |b: u8| b.is_ascii_alphanumeric(),
+        "alpha" => |b: u8| b.is_ascii_alphabetic(),
+        "blank" => |b: u8| b == b' ' || b == b'\t',
+        "cntrl" => |b: u8| b.is_ascii_control(),
+        "digit" => |b: u8| b.is_ascii_digit(),
+        "graph" => |b: u8| b.is_ascii_graphic(),
+        "lower" => |b: u8| b.is_ascii_lowercase(),
+        "print" => |b: u8| b.is_ascii_graphic() || b == b' ',
+        "punct" => |b: u8| b.is_ascii_punctuation(),
+        "space" => |b: u8| b.is_ascii_whitespace() || b == 0x0b,
+        "upper" => |b: u8| b.is_ascii_uppercase(),
+        "xdigit" => |b: u8| b.is_ascii_hexdigit(),
+        _ => return None,
+    })
+}
+
+struct Parser<'a> {
+    pattern: &'a [u8],
+    pos: usize,
+    extended: bool,
+    ignore_case: bool,
+    /// Groups open around the current position
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.pattern.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.pattern.get(self.pos + offset).copied()
+    }
+
+    /// Whether an operator spelled `op` in extended syntax (`\op` in basic)
+    /// comes next
+    fn at_operator(&self, op: u8) -> bool {
+        if self.extended {
+            self.peek() == Some(op)
+        } else {
+            self.peek() == Some(b'\\') && self.peek_at(1) == Some(op)
+        }
+    }
+
+    fn skip_operator(&mut self) {
+        self.pos += if self.extended { 1 } else { 2 };
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.concatenation()?];
+        while self.at_operator(b'|') {
+            self.skip_operator();
+            branches.push(self.concatenation()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().unwrap_or(Node::Empty),
+            _ => Node::Alternate(branches),
+        })
+    }
+
+    fn at_branch_end(&self) -> bool {
+        self.peek().is_none()
+            || self.at_operator(b'|')
+            || (self.depth > 0 && self.at_operator(b')'))
+    }
+
+    fn concatenation(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while !self.at_branch_end() {
+            let atom_start = nodes.is_empty();
+            let atom = match self.atom(atom_start)? {
+                Some(atom) => atom,
+                None => continue,
+            };
+            nodes.push(self.repetitions(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn repetitions(&mut self, mut node: Node) -> Result<Node, String> {
+        if matches!(node, Node::Assert(_)) {
+            return Ok(node);
+        }
+        loop {
+            let (min, max) = if self.peek() == Some(b'*') {
+                self.pos += 1;
+                (0, None)
+            } else if self.at_operator(b'+') {
+                self.skip_operator();
+                (1, None)
+            } else if self.at_operator(b'?') {
+                self.skip_operator();
+                (0, Some(1))
+            } else if self.at_operator(b'{') {
+                self.skip_operator();
+                self.interval()?
+            } else {
+                return Ok(node);
+            };
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+            };
+        }
+    }
+
+    /// `m}`, `m,}` or `m,n}` after the opening brace
+    fn interval(&mut self) -> Result<(u32, Option<u32>), String> {
+        let number = |parser: &mut Parser| -> Option<u32> {
+            let start = parser.pos;
+            while parser.peek().is_some_and(|b| b.is_ascii_digit()) {
+                parser.pos += 1;
+            }
+            std::str::from_utf8(&parser.pattern[start..parser.pos])
+                .ok()?
+                .parse()
+                .ok()
+        };
+        let invalid = || "Invalid content of \\{\\}".to_string();
+        let min = number(self).ok_or_else(invalid)?;
+        let max = if self.peek() == Some(b',') {
+            self.pos += 1;
+            number(self)
+        } else {
+            Some(min)
+        };
+        if !self.at_operator(b'}') {
+            return Err("Unmatched \\{".to_string());
+        }
+        self.skip_operator();
+        if max.is_some_and(|max| max < min) || min > MAX_REPEAT || max.unwrap_or(0) > MAX_REPEAT {
+            return Err(invalid());
+        }
+        Ok((min, max))
+    }
+
+    /// One atom, or `None` for something that matches nothing by itself
+    fn atom(&mut self, at_start: bool) -> Result<Option<Node>, String> {
+        let byte = match self.peek() {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+
+        if self.at_operator(b'(') {
+            self.skip_operator();
+            self.depth += 1;
+            let inner = self.alternation()?;
+            if !self.at_operator(b')') {
+                return Err("Unmatched ( or \\(".to_string());
+            }
+            self.skip_operator();
+            self.depth -= 1;
+            return Ok(Some(inner));
+        }
+        if self.extended && byte == b')' {
+            return Err("Unmatched ) or \\)".to_string());
+        }
+
+        self.pos += 1;
+        let node = match byte {
+            b'.' => Node::Set(set_of(|b| b != b'\n')),
+            b'[' => Node::Set(self.bracket()?),
+            // Only anchors where they can be; a literal elsewhere in basic syntax
+            b'^' if self.extended || at_start => Node::Assert(Assertion::Start),
+            b'$' if self.extended || self.at_branch_end() => Node::Assert(Assertion::End),
+            b'*' if at_start => Node::Set(literal(b'*', false)),
+            b'*' | b'+' | b'?' | b'{' if self.extended => {
+                return Err("Invalid preceding regular expression".to_string())
+            }
+            b'\\' => {
+                let escaped = self.peek().ok_or("Trailing backslash")?;
+                self.pos += 1;
+                match escaped {
+                    b'w' => Node::Set(set_of(|b| is_word(Some(&b)))),
+                    b'W' => Node::Set(set_of(|b| !is_word(Some(&b)))),
+                    b's' => Node::Set(set_of(|b| b.is_ascii_whitespace())),
+                    b'S' => Node::Set(set_of(|b| !b.is_ascii_whitespace())),
+                    b'b' => Node::Assert(Assertion::WordBoundary),
+                    b'B' => Node::Assert(Assertion::NotWordBoundary),
+                    b'<' => Node::Assert(Assertion::WordStart),
+                    b'>' => Node::Assert(Assertion::WordEnd),
+                    b'1'..=b'9' => return Err("back-references are not supported".to_string()),
+                    other => Node::Set(literal(other, self.ignore_case)),
+                }
+            }
+            other => Node::Set(literal(other, self.ignore_case)),
+        };
+        Ok(Some(node))
+    }
+
+    /// A bracket expression, after its `[`
+    fn bracket(&mut self) -> Result<ByteSet, String> {
+        let unmatched = || "Unmatched [, [^, [:, [., or [=".to_string();
+        let mut set = Box::new([false; 256]);
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut first = true;
+        loop {
+            let byte = self.peek().ok_or_else(unmatched)?;
+            self.pos += 1;
+            if byte == b']' && !first {
+                break;
+            }
+            first = false;
+
+            if byte == b'[' && self.peek() == Some(b':') {
+                let rest = &self.pattern[self.pos + 1..];
+                let end = rest
+                    .windows(2)
+                    .position(|w| w == b":]")
+                    .ok_or_else(unmatched)?;
+                let name = String::from_utf8_lossy(&rest[..end]);
+                if !CLASS_NAMES.contains(&name.as_ref()) {
+                    return Err("Invalid character class name".to_string());
+                }
+                let test = named_class(&name).ok_or_else(unmatched)?;
+                for b in 0..=255u8 {
+                    set[b as usize] |= test(b);
+                }
+                self.pos += end + 3;
+                continue;
+            }
+
+            let low = byte;
+            let high = if self.peek() == Some(b'-') && self.peek_at(1).is_some_and(|b| b != b']') {
+                let high = self.peek_at(1).ok_or_else(unmatched)?;
+                self.pos += 2;
+                if high < low {
+                    return Err("Invalid range end".to_string());
+                }
+                high
+            } else {
+                low
+            };
+            for b in low..=high {
+                set[b as usize] = true;
+            }
+        }
+
+        if self.ignore_case {
+            for b in 0..=255u8 {
+                if set[b as usize] {
+                    set[b.to_ascii_lowercase() as usize] = true;
+                    set[b.to_ascii_uppercase() as usize] = true;
+                }
+            }
+        }
+        if negated {
+            for (b, member) in set.iter_mut().enumerate() {
+                *member = !*member && b != b'\n' as usize;
+            }
+        }
+        Ok(set)
+    }
+}
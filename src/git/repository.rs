@@ -0,0 +1,67 @@
+// Where the repository lives
+// Commands run at the top of a work tree, whose repository is the .git
+// directory there, or inside a bare repository, which has no work tree and
+// is a git directory of its own. A directory is taken for a bare
+// repository when it holds HEAD, objects/ and refs/, as git checks, or
+// when its config sets core.bare.
+
+use crate::git::config::Config;
+use std::path::{Path, PathBuf};
+
+/// The git directory of the repository commands run in: `.git`, or `.`
+/// when the current directory is a bare repository
+pub fn git_dir() -> &'static Path {
+    if !Path::new(".git").exists() && is_git_dir(Path::new(".")) {
+        Path::new(".")
+    } else {
+        Path::new(".git")
+    }
+}
+
+/// `name` inside the git directory
+pub fn path(name: impl AsRef<Path>) -> PathBuf {
+    git_dir().join(name)
+}
+
+/// Whether commands run in a bare repository, with no work tree
+pub fn is_bare() -> bool {
+    git_dir() == Path::new(".")
+}
+
+/// Whether `dir` is itself a git directory, as a bare repository is
+pub fn is_git_dir(dir: &Path) -> bool {
+    if dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir() {
+        return true;
+    }
+    let mut config = Config::default();
+    config.read_file(&dir.join("config")).is_ok()
+        && matches!(config.get_bool("core.bare"), Ok(Some(true)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn recognizes_a_bare_layout_or_core_bare() {
+        let root = std::env::temp_dir().join(format!("byog-repository-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let layout = root.join("layout.git");
+        fs::create_dir_all(layout.join("objects")).unwrap();
+        fs::create_dir_all(layout.join("refs")).unwrap();
+        assert!(!is_git_dir(&layout));
+        fs::write(layout.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert!(is_git_dir(&layout));
+
+        let configured = root.join("configured");
+        fs::create_dir_all(&configured).unwrap();
+        fs::write(configured.join("config"), "[core]\n\tbare = false\n").unwrap();
+        assert!(!is_git_dir(&configured));
+        fs::write(configured.join("config"), "[core]\n\tbare = true\n").unwrap();
+        assert!(is_git_dir(&configured));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
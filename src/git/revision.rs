@@ -0,0 +1,276 @@
+// Revision expressions
+// What commands accept wherever they take a commit or other object:
+//
+// <sha>, <abbreviated sha>    an object id, or at least 4 of its leading digits
+// <ref>, HEAD, @              a ref, looked up in git's DWIM order
+// <ref>@{<n>}, @{<n>}         the value <ref> (the current branch) had <n>
+//                             updates ago, from its reflog
+// <rev>~<n>                   the <n>th first-parent ancestor
+// <rev>^<n>                   the <n>th parent (^0 is the commit itself)
+// <rev>^{<type>}, <rev>^{}    <rev> peeled to a commit, tree, blob or tag, or
+//                             through every tag
+// <rev>:<path>                the file or directory at <path> in <rev>'s tree
+// :<path>, :<n>:<path>        the blob staged at <path> (in stage <n>)
+//
+// Suffixes combine from left to right, as in HEAD~2^2:src/main.rs.
+
+use crate::git::index::Index;
+use crate::git::{commit, object, reflog, refs, tag, tree};
+use std::io;
+
+/// Abbreviated ids must be at least this long
+const MIN_ABBREV: usize = 4;
+
+/// The object `expr` names, or `None` if it names nothing. Malformed or
+/// ambiguous expressions are errors.
+pub fn resolve(expr: &str) -> io::Result<Option<String>> {
+    if let Some(rest) = expr.strip_prefix(':') {
+        return staged(rest);
+    }
+    match split_path(expr) {
+        Some((rev, path)) => {
+            let Some(sha) = resolve_rev(rev)? else {
+                return Ok(None);
+            };
+            let Some(tree_sha) = peel(&sha, "tree")? else {
+                return Ok(None);
+            };
+            find_path(&tree_sha, path)?.map(Some).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("path '{}' does not exist in '{}'", path, rev),
+                )
+            })
+        }
+        None => resolve_rev(expr),
+    }
+}
+
+/// The full name of the ref `expr` stands for (`HEAD` for HEAD), if it is
+/// a plain ref name rather than an expression
+pub fn full_ref_name(expr: &str) -> io::Result<Option<String>> {
+    if expr == "@" {
+        return Ok(Some("HEAD".to_string()));
+    }
+    if refs::is_sha(expr) || expr.contains(['^', '~', ':']) || expr.contains("@{") {
+        return Ok(None);
+    }
+    refs::dwim(expr)
+}
+
+/// Split `<rev>:<path>` at its first colon outside braces
+fn split_path(expr: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in expr.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => return Some((&expr[..i], &expr[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `<path>` or `<n>:<path>` after the leading colon
+fn staged(rest: &str) -> io::Result<Option<String>> {
+    let (stage, path) = match rest.split_once(':') {
+        Some((stage @ ("0" | "1" | "2" | "3"), path)) => (stage.parse::<u8>().unwrap_or(0), path),
+        _ => (0, rest),
+    };
+    let index = Index::load()?;
+    let found = index
+        .entries()
+        .iter()
+        .find(|e| e.path == path && e.stage == stage);
+    match found {
+        Some(entry) => Ok(Some(entry.sha.clone())),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "path '{}' does not exist (neither on disk nor in the index)",
+                path
+            ),
+        )),
+    }
+}
+
+fn resolve_rev(expr: &str) -> io::Result<Option<String>> {
+    // The base name runs up to the first suffix outside braces
+    let mut depth = 0;
+    let mut end = expr.len();
+    for (i, c) in expr.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '^' | '~' if depth == 0 => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    let Some(mut sha) = resolve_base(&expr[..end])? else {
+        return Ok(None);
+    };
+
+    let mut rest = &expr[end..];
+    while let Some(op) = rest.chars().next() {
+        rest = &rest[op.len_utf8()..];
+        if op == '^' && rest.starts_with('{') {
+            let close = rest.find('}').ok_or_else(|| invalid(expr))?;
+            let kind = &rest[1..close];
+            rest = &rest[close + 1..];
+            let peeled = match kind {
+                "" => Some(tag::peel(&sha)?.0),
+                "object" => Some(sha.clone()),
+                "commit" | "tree" | "blob" | "tag" => peel(&sha, kind)?,
+                _ => return Err(invalid(expr)),
+            };
+            match peeled {
+                Some(peeled) => sha = peeled,
+                None => return Ok(None),
+            }
+            continue;
+        }
+        if op != '^' && op != '~' {
+            return Err(invalid(expr));
+        }
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let n: usize = match digits {
+            0 => 1,
+            _ => rest[..digits].parse().map_err(|_| invalid(expr))?,
+        };
+        rest = &rest[digits..];
+
+        let Some(commit_sha) = peel(&sha, "commit")? else {
+            return Ok(None);
+        };
+        sha = commit_sha;
+        if op == '^' {
+            if n > 0 {
+                match commit::read(&sha)?.parents.get(n - 1) {
+                    Some(parent) => sha = parent.clone(),
+                    None => return Ok(None),
+                }
+            }
+        } else {
+            for _ in 0..n {
+                match commit::read(&sha)?.parents.first() {
+                    Some(parent) => sha = parent.clone(),
+                    None => return Ok(None),
+                }
+            }
+        }
+    }
+    Ok(Some(sha))
+}
+
+fn invalid(expr: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid revision: {}", expr),
+    )
+}
+
+/// A name without suffixes: a ref, possibly with `@{<n>}`, or an id
+fn resolve_base(name: &str) -> io::Result<Option<String>> {
+    if let Some(open) = name.find("@{") {
+        let selector = name[open + 2..]
+            .strip_suffix('}')
+            .ok_or_else(|| invalid(name))?;
+        let n: usize = selector.parse().map_err(|_| invalid(name))?;
+        let refname = match &name[..open] {
+            // The current branch, or HEAD when detached
+            "" => match refs::read_head()? {
+                refs::Head::Branch(branch) => branch,
+                refs::Head::Detached(_) => "HEAD".to_string(),
+            },
+            base => match full_ref_name(base)? {
+                Some(refname) => refname,
+                None => return Ok(None),
+            },
+        };
+        return reflog_entry(&refname, n);
+    }
+    if name.is_empty() || name == "@" {
+        return refs::read_ref("HEAD");
+    }
+    if let Some(sha) = refs::resolve(name)? {
+        return Ok(Some(sha));
+    }
+
+    let is_hex = name.bytes().all(|b| b.is_ascii_hexdigit());
+    if !is_hex || name.len() < MIN_ABBREV || name.len() >= 40 {
+        return Ok(None);
+    }
+    let mut candidates = object::find_by_prefix(name)?;
+    match candidates.len() {
+        0 => Ok(None),
+        1 => Ok(candidates.pop()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("short object ID {} is ambiguous", name),
+        )),
+    }
+}
+
+/// The value `refname` had `n` updates ago
+fn reflog_entry(refname: &str, n: usize) -> io::Result<Option<String>> {
+    if !reflog::exists(refname) {
+        return Ok(None);
+    }
+    let entries = reflog::read(refname)?;
+    if n >= entries.len() {
+        let name = refname.strip_prefix("refs/heads/").unwrap_or(refname);
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("log for '{}' only has {} entries", name, entries.len()),
+        ));
+    }
+    Ok(Some(entries[entries.len() - 1 - n].new.clone()))
+}
+
+/// `sha` peeled to an object of type `kind`: tags are followed, and a
+/// commit stands for its tree. `None` if it doesn't lead to one.
+pub fn peel(sha: &str, kind: &str) -> io::Result<Option<String>> {
+    let mut current = sha.to_string();
+    for _ in 0..16 {
+        let (found, content) = object::read(&current)?;
+        if found == kind {
+            return Ok(Some(current));
+        }
+        current = match found.as_str() {
+            "tag" => tag::parse(&content)?.object,
+            "commit" if kind == "tree" => commit::parse(&content)?.tree,
+            _ => return Ok(None),
+        };
+    }
+    Ok(None)
+}
+
+/// The object at `path` below tree `tree_sha`; the tree itself for ""
+fn find_path(tree_sha: &str, path: &str) -> io::Result<Option<String>> {
+    let mut sha = tree_sha.to_string();
+    let mut is_tree = true;
+    for name in path
+        .split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+    {
+        // Only trees have entries
+        if !is_tree {
+            return Ok(None);
+        }
+        match tree::read(&sha)?
+            .into_iter()
+            .find(|entry| entry.name == name)
+        {
+            Some(entry) => {
+                is_tree = entry.is_tree();
+                sha = entry.sha;
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(sha))
+}
@@ -0,0 +1,100 @@
+// Commit traversal
+// Lists the commits reachable from a set of starting commits but not from
+// any hidden one, newest first by committer date, the order rev-list and
+// log use. Commits made at the same time come out in the order they were
+// reached. What is hidden is worked out before the walk starts, so a
+// hidden commit's whole history stays out even where the visible side
+// reaches it by another path.
+
+use crate::git::commit;
+use crate::git::merge;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::io;
+
+#[derive(Debug, Default)]
+pub struct RevWalk {
+    queue: BinaryHeap<Queued>,
+    seen: HashSet<String>,
+    hidden: HashSet<String>,
+    /// Tie-breaker for commits with the same date
+    order: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Queued {
+    time: i64,
+    order: Reverse<usize>,
+    sha: String,
+    parents: Vec<String>,
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.order).cmp(&(other.time, other.order))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl RevWalk {
+    pub fn new() -> RevWalk {
+        RevWalk::default()
+    }
+
+    /// Start the walk (also) from commit `sha`
+    pub fn push(&mut self, sha: &str) -> io::Result<()> {
+        self.enqueue(sha)
+    }
+
+    /// Leave out commit `sha` and everything reachable from it
+    pub fn hide(&mut self, sha: &str) -> io::Result<()> {
+        if !self.hidden.contains(sha) {
+            self.hidden.extend(merge::ancestors(sha)?);
+        }
+        Ok(())
+    }
+
+    fn enqueue(&mut self, sha: &str) -> io::Result<()> {
+        if !self.seen.insert(sha.to_string()) {
+            return Ok(());
+        }
+        let commit = commit::read(sha)?;
+        self.order += 1;
+        self.queue.push(Queued {
+            time: commit.commit_time(),
+            order: Reverse(self.order),
+            sha: sha.to_string(),
+            parents: commit.parents,
+        });
+        Ok(())
+    }
+
+    /// The next commit, `None` once the walk is done
+    pub fn next_commit(&mut self) -> io::Result<Option<String>> {
+        while let Some(queued) = self.queue.pop() {
+            if self.hidden.contains(&queued.sha) {
+                continue;
+            }
+            for parent in &queued.parents {
+                if !self.hidden.contains(parent) {
+                    self.enqueue(parent)?;
+                }
+            }
+            return Ok(Some(queued.sha));
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_commit().transpose()
+    }
+}
@@ -0,0 +1,96 @@
+// Shallow history
+// A shallow clone has only the recent part of its history. The commits at
+// its edge, whose parents were never fetched, are listed one id per line in
+// .git/shallow, and read as having no parents (see commit::read), so walks
+// over the history stop there rather than at a missing object.
+//
+// A fetch into a shallow repository tells the remote its edge with a
+// "shallow <id>" line for each of them, so the remote doesn't take the
+// parents as being here. Asking for "deepen <n>" cuts the fetched history
+// n commits below the tips wanted, or with deepen-relative n commits below
+// the current edge. The remote answers with "shallow <id>" for each commit
+// at the new edge and "unshallow <id>" for each one of ours whose parents
+// it sends after all; once the pack is in, .git/shallow is updated with
+// them, and removed altogether when no edge is left.
+
+use crate::git::repository;
+use std::fs;
+use std::io;
+
+const SHALLOW: &str = "shallow";
+
+/// The depth --unshallow asks for: everything
+pub const INFINITE_DEPTH: u32 = 0x7fff_ffff;
+
+/// How a fetch deepens history, or cuts it short
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deepen {
+    /// This many commits from each tip fetched (--depth)
+    Depth(u32),
+    /// This many more commits below the current edge (--deepen)
+    Relative(u32),
+}
+
+/// The commits at the edge of a shallow repository; none if it isn't one
+pub fn read() -> io::Result<Vec<String>> {
+    match fs::read_to_string(repository::path(SHALLOW)) {
+        Ok(text) => Ok(text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether commit `sha` is at the edge of a shallow repository
+pub fn contains(sha: &str) -> io::Result<bool> {
+    Ok(read()?.iter().any(|edge| edge == sha))
+}
+
+/// The changes to the edge a remote announced while fetching
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Update {
+    pub shallow: Vec<String>,
+    pub unshallow: Vec<String>,
+}
+
+impl Update {
+    /// Take in a line of the remote's response; false if it is about
+    /// something else
+    pub fn parse_line(&mut self, line: &str) -> bool {
+        if let Some(sha) = line.strip_prefix("shallow ") {
+            self.shallow.push(sha.to_string());
+        } else if let Some(sha) = line.strip_prefix("unshallow ") {
+            self.unshallow.push(sha.to_string());
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Write the new edge to .git/shallow. Unlike ref updates this is done
+    /// under --dry-run too, as it describes the objects fetched.
+    pub fn apply(&self) -> io::Result<()> {
+        if self.shallow.is_empty() && self.unshallow.is_empty() {
+            return Ok(());
+        }
+        let mut edge = read()?;
+        edge.extend(self.shallow.iter().cloned());
+        edge.retain(|sha| !self.unshallow.contains(sha));
+        edge.sort();
+        edge.dedup();
+        if edge.is_empty() {
+            return match fs::remove_file(repository::path(SHALLOW)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let text: String = edge.iter().map(|sha| format!("{}\n", sha)).collect();
+        let temp = repository::path(format!("{}.lock", SHALLOW));
+        fs::write(&temp, text)?;
+        fs::rename(&temp, repository::path(SHALLOW))
+    }
+}
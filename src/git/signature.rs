@@ -0,0 +1,255 @@
+// Commit signatures, and signing for push certificates
+// A signed commit carries an armored signature in its "gpgsig" header, each
+// line after the first continued with a leading space; what was signed is
+// the commit with that header taken out. Checking is left to gpg.program
+// (gpg unless configured), run the way git runs it:
+//
+// gpg --status-fd=1 --keyid-format=long --verify <signature file> -
+//
+// with the payload on standard input. Its "[GNUPG:]" status lines say
+// whether the signature is good, who made it and how far their key is
+// trusted; gpg.minTrustLevel sets the trust a good signature needs.
+//
+// Signing runs `gpg --status-fd=2 -bsau <key>` with the payload on standard
+// input, the key being user.signingKey or else the committer's identity,
+// and takes the armored signature gpg writes out.
+
+use crate::git::config::Config;
+use crate::git::ident::{self, Role};
+use crate::git::object;
+use std::fs;
+use std::io::{self, Write};
+use std::process::{self, Command, Stdio};
+
+const SIGNATURE_HEADER: &str = "gpgsig";
+
+/// How far the owner of a key is trusted, as gpg's web of trust says
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Trust {
+    Undefined,
+    Never,
+    Marginal,
+    Fully,
+    Ultimate,
+}
+
+impl Trust {
+    /// A gpg.minTrustLevel value
+    pub fn parse(name: &str) -> Option<Trust> {
+        match name.to_ascii_lowercase().as_str() {
+            "undefined" => Some(Trust::Undefined),
+            "never" => Some(Trust::Never),
+            "marginal" => Some(Trust::Marginal),
+            "fully" => Some(Trust::Fully),
+            "ultimate" => Some(Trust::Ultimate),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Good,
+    Bad,
+    /// Good, but made after the signature's own expiry
+    ExpiredSignature,
+    /// Good, but by a key that has since expired
+    ExpiredKey,
+    /// Good, but by a key that has since been revoked
+    RevokedKey,
+    /// Not checkable, typically because the key isn't in the keyring
+    Unverifiable,
+}
+
+/// What gpg made of a signature
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub status: Status,
+    /// The user id of the signing key, empty if unknown
+    pub signer: String,
+    /// Long id of the signing key
+    pub key: String,
+    pub trust: Trust,
+}
+
+impl Verification {
+    /// A good signature by a key trusted at least `min_trust`
+    pub fn is_trusted(&self, min_trust: Trust) -> bool {
+        self.status == Status::Good && self.trust >= min_trust
+    }
+}
+
+/// Split a signed commit's content into the signed payload and the
+/// signature; `None` for an unsigned commit
+pub fn split_commit(content: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut payload = Vec::with_capacity(content.len());
+    let mut signature = Vec::new();
+    let mut in_signature = false;
+    let mut in_headers = true;
+
+    for line in content.split_inclusive(|&b| b == b'\n') {
+        if in_headers {
+            if line == b"\n" {
+                in_headers = false;
+            } else if let Some(rest) = line.strip_prefix(SIGNATURE_HEADER.as_bytes()) {
+                if let Some(value) = rest.strip_prefix(b" ") {
+                    in_signature = true;
+                    signature.extend_from_slice(value);
+                    continue;
+                }
+            } else if in_signature {
+                if let Some(value) = line.strip_prefix(b" ") {
+                    signature.extend_from_slice(value);
+                    continue;
+                }
+                in_signature = false;
+            }
+        }
+        payload.extend_from_slice(line);
+    }
+    if signature.is_empty() {
+        return None;
+    }
+    Some((payload, signature))
+}
+
+/// Check the signature of commit `sha`; `None` if it isn't signed
+pub fn verify_commit(sha: &str) -> io::Result<Option<Verification>> {
+    let content = object::read_expecting(sha, "commit")?;
+    match split_commit(&content) {
+        Some((payload, signature)) => verify(&payload, &signature).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The trust gpg.minTrustLevel asks for, or `default` when unset
+pub fn min_trust_level(config: &Config, default: Trust) -> io::Result<Trust> {
+    match config.get("gpg.minTrustLevel") {
+        Some(value) => Trust::parse(value).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid value for 'gpg.mintrustlevel': '{}'", value),
+            )
+        }),
+        None => Ok(default),
+    }
+}
+
+/// Have gpg check `signature` over `payload`
+pub fn verify(payload: &[u8], signature: &[u8]) -> io::Result<Verification> {
+    let config = Config::load()?;
+    let program = config.get("gpg.program").unwrap_or("gpg").to_string();
+
+    let signature_file =
+        std::env::temp_dir().join(format!(".git_vtag_tmp{}-{}", process::id(), unique()));
+    fs::write(&signature_file, signature)?;
+    let output = Command::new(&program)
+        .args(["--status-fd=1", "--keyid-format=long", "--verify"])
+        .arg(&signature_file)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                // gpg may stop reading early on a signature it can't use
+                let _ = stdin.write_all(payload);
+            }
+            child.wait_with_output()
+        });
+    let _ = fs::remove_file(&signature_file);
+    let output = output.map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("could not run {} to verify the signature: {}", program, e),
+        )
+    })?;
+
+    Ok(parse_status(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// An armored detached signature of `payload` by user.signingKey, or the
+/// committer's key without one
+pub fn sign(payload: &[u8]) -> io::Result<Vec<u8>> {
+    let config = Config::load()?;
+    let program = config.get("gpg.program").unwrap_or("gpg").to_string();
+    let key = match config.get("user.signingKey") {
+        Some(key) => key.to_string(),
+        None => ident::identity(Role::Committer)?,
+    };
+    let output = Command::new(&program)
+        .args(["--status-fd=2", "-bsau", &key])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(payload)?;
+            }
+            child.wait_with_output()
+        })
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("could not run {} to sign: {}", program, e),
+            )
+        })?;
+    let status = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !status.contains("[GNUPG:] SIG_CREATED ") {
+        return Err(io::Error::other(format!(
+            "gpg failed to sign the data:\n{}",
+            status.trim_end()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Distinguishes the signature files of one process
+fn unique() -> usize {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+fn parse_status(status: &str) -> Verification {
+    let mut verification = Verification {
+        status: Status::Unverifiable,
+        signer: String::new(),
+        key: String::new(),
+        trust: Trust::Undefined,
+    };
+    for line in status.lines() {
+        let Some(line) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let result = match keyword {
+            "GOODSIG" => Status::Good,
+            "BADSIG" => Status::Bad,
+            "EXPSIG" => Status::ExpiredSignature,
+            "EXPKEYSIG" => Status::ExpiredKey,
+            "REVKEYSIG" => Status::RevokedKey,
+            "ERRSIG" => Status::Unverifiable,
+            _ => {
+                if let Some(level) = keyword.strip_prefix("TRUST_") {
+                    verification.trust = Trust::parse(level).unwrap_or(Trust::Undefined);
+                }
+                continue;
+            }
+        };
+        // Several signatures, or a bad one next to a good one, don't count
+        if verification.status != Status::Unverifiable || !verification.key.is_empty() {
+            verification.status = Status::Bad;
+            continue;
+        }
+        verification.status = result;
+        let (key, signer) = rest.split_once(' ').unwrap_or((rest, ""));
+        verification.key = key.to_string();
+        if result != Status::Unverifiable {
+            verification.signer = signer.to_string();
+        }
+    }
+    verification
+}
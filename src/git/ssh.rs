@@ -0,0 +1,253 @@
+// SSH transport
+// Runs `git-upload-pack '<path>'` on the remote through the ssh client and
+// speaks the pack protocol over its stdin/stdout. Unlike smart HTTP the
+// connection is stateful: the ref advertisement and the pack request share
+// one session. A protocol version other than v0 is asked for by setting
+// GIT_PROTOCOL for the remote side, which ssh passes on with SendEnv.
+
+use crate::git::config::Config;
+use crate::git::url::RemoteUrl;
+use std::env;
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// A running remote service process
+pub struct Connection {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: ChildStdout,
+}
+
+/// What a service sends back to a request, read from its stdout; at the
+/// end, ssh failing before anything came is an error
+pub struct Response {
+    connection: Connection,
+    empty: bool,
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.connection.stdout.read(buf)?;
+        if n > 0 {
+            self.empty = false;
+        } else if buf.is_empty() {
+            return Ok(0);
+        } else {
+            let status = self.connection.child.wait()?;
+            if !status.success() && self.empty {
+                return Err(io::Error::other(format!(
+                    "ssh exited with {}: Could not read from remote repository.",
+                    status
+                )));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// What the service has sent so far, read while the conversation goes on
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+/// Start `service` (e.g. `git-upload-pack`) for the repository at `remote`,
+/// with `protocol` (e.g. "version=2") as its GIT_PROTOCOL. The ssh program
+/// comes from `GIT_SSH_COMMAND`, `GIT_SSH`, `core.sshCommand` or plain
+/// `ssh`, in that order.
+pub fn connect(
+    remote: &RemoteUrl,
+    service: &str,
+    protocol: Option<&str>,
+) -> io::Result<Connection> {
+    let host = remote
+        .host
+        .as_deref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "ssh URL without a host"))?;
+    let destination = match &remote.user {
+        Some(user) => format!("{}@{}", user, host),
+        None => host.to_string(),
+    };
+    let remote_command = format!("{} {}", service, shell_quote(&remote.path));
+
+    let mut args = Vec::new();
+    if protocol.is_some() {
+        args.push("-o".to_string());
+        args.push("SendEnv=GIT_PROTOCOL".to_string());
+    }
+    if let Some(port) = remote.port {
+        args.push("-p".to_string());
+        args.push(port.to_string());
+    }
+    args.push(destination);
+    args.push(remote_command);
+
+    let mut command = ssh_command(&args)?;
+    if let Some(protocol) = protocol {
+        command.env("GIT_PROTOCOL", protocol);
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::other(format!("Failed to run ssh: {}", e)))?;
+
+    let stdin = child.stdin.take();
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| io::Error::other("ssh stdout not captured"))?;
+    Ok(Connection {
+        child,
+        stdin,
+        stdout,
+    })
+}
+
+impl Connection {
+    /// Read the ref advertisement, up to and including its flush packet. The
+    /// pkt-lines are returned as received, the same shape as the body of an
+    /// HTTP `info/refs` response without the service announcement.
+    pub fn read_advertisement(&mut self) -> io::Result<Vec<u8>> {
+        let mut advertisement = Vec::new();
+        loop {
+            let mut len_hex = [0u8; 4];
+            if let Err(e) = self.stdout.read_exact(&mut len_hex) {
+                return Err(self.hung_up(e));
+            }
+            advertisement.extend_from_slice(&len_hex);
+
+            let len = std::str::from_utf8(&len_hex)
+                .ok()
+                .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "protocol error: bad line length character: {}",
+                            String::from_utf8_lossy(&len_hex)
+                        ),
+                    )
+                })?;
+            if len == 0 {
+                return Ok(advertisement);
+            }
+            if len < 4 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("protocol error: bad line length {}", len),
+                ));
+            }
+
+            let mut payload = vec![0u8; len - 4];
+            if let Err(e) = self.stdout.read_exact(&mut payload) {
+                return Err(self.hung_up(e));
+            }
+            if let Some(message) = payload.strip_prefix(b"ERR ") {
+                return Err(io::Error::other(format!(
+                    "remote error: {}",
+                    String::from_utf8_lossy(message).trim_end()
+                )));
+            }
+            advertisement.extend_from_slice(&payload);
+        }
+    }
+
+    /// Send one protocol v2 command and read its response up to the flush
+    /// packet ending it, leaving the service waiting for the next
+    pub fn command(&mut self, request: &[u8]) -> io::Result<Vec<u8>> {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| io::Error::other("ssh stdin already closed"))?;
+        stdin.write_all(request)?;
+        stdin.flush()?;
+        self.read_advertisement()
+    }
+
+    /// Send part of a request, leaving the service's stdin open for more
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| io::Error::other("ssh stdin already closed"))?;
+        stdin.write_all(data)?;
+        stdin.flush()
+    }
+
+    /// Send a complete request, then read everything the service sends back
+    /// until it exits
+    pub fn request(self, body: impl Read) -> io::Result<Vec<u8>> {
+        let mut response = Vec::new();
+        self.respond(body)?.read_to_end(&mut response)?;
+        Ok(response)
+    }
+
+    /// Send a complete request and return the service's output to be read
+    /// as it comes, rather than all of it once the service is done
+    pub fn respond(mut self, mut body: impl Read) -> io::Result<Response> {
+        if let Some(mut stdin) = self.stdin.take() {
+            io::copy(&mut body, &mut stdin)?;
+            // Dropping stdin sends EOF so the service knows the request is over
+        }
+        Ok(Response {
+            connection: self,
+            empty: true,
+        })
+    }
+
+    /// Error for an advertisement cut short, with ssh's exit status if it died
+    fn hung_up(&mut self, cause: io::Error) -> io::Error {
+        drop(self.stdin.take());
+        match self.child.wait() {
+            Ok(status) if !status.success() => io::Error::other(format!(
+                "ssh exited with {}: Could not read from remote repository.",
+                status
+            )),
+            _ => io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("the remote end hung up unexpectedly ({})", cause),
+            ),
+        }
+    }
+}
+
+/// Build the ssh invocation. `GIT_SSH_COMMAND` and `core.sshCommand` are
+/// shell snippets; `GIT_SSH` names a program run directly.
+fn ssh_command(args: &[String]) -> io::Result<Command> {
+    let shell_snippet = match env::var("GIT_SSH_COMMAND") {
+        Ok(snippet) if !snippet.is_empty() => Some(snippet),
+        _ => None,
+    };
+    let program = match env::var("GIT_SSH") {
+        Ok(program) if !program.is_empty() => Some(program),
+        _ => None,
+    };
+
+    let shell_snippet = match (shell_snippet, &program) {
+        (Some(snippet), _) => Some(snippet),
+        (None, Some(_)) => None,
+        (None, None) => Config::load()?.get("core.sshCommand").map(str::to_string),
+    };
+
+    if let Some(snippet) = shell_snippet {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("{} \"$@\"", snippet))
+            .arg(snippet);
+        command.args(args);
+        return Ok(command);
+    }
+
+    let mut command = Command::new(program.unwrap_or_else(|| "ssh".to_string()));
+    command.args(args);
+    Ok(command)
+}
+
+/// Quote for the remote shell: wrap in single quotes, escaping embedded
+/// ones as `'\''`
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
@@ -0,0 +1,123 @@
+// Submodules
+// `.gitmodules` at the top of the working tree maps each submodule name to
+// its path and URL:
+//
+// [submodule "lib"]
+//     path = vendor/lib
+//     url = ../lib.git
+//
+// The commit a submodule is pinned at is the gitlink entry at that path in
+// the superproject's tree.
+
+use crate::git::config::Config;
+use std::io;
+use std::path::Path;
+
+pub const GITMODULES: &str = ".gitmodules";
+
+#[derive(Debug, Clone)]
+pub struct Submodule {
+    pub name: String,
+    pub path: String,
+    pub url: Option<String>,
+}
+
+/// Submodules declared in `.gitmodules`, in file order. Entries without a
+/// path are skipped, as git does.
+pub fn read_gitmodules() -> io::Result<Vec<Submodule>> {
+    let mut file = Config::default();
+    file.read_file(Path::new(GITMODULES))?;
+
+    let mut modules: Vec<Submodule> = Vec::new();
+    for entry in file.entries() {
+        let name = match (&entry.section[..], &entry.subsection) {
+            ("submodule", Some(name)) => name,
+            _ => continue,
+        };
+        let index = match modules.iter().position(|m| &m.name == name) {
+            Some(index) => index,
+            None => {
+                modules.push(Submodule {
+                    name: name.clone(),
+                    path: String::new(),
+                    url: None,
+                });
+                modules.len() - 1
+            }
+        };
+        match (entry.name.as_str(), &entry.value) {
+            ("path", Some(path)) => modules[index].path = path.trim_end_matches('/').to_string(),
+            ("url", Some(url)) => modules[index].url = Some(url.clone()),
+            _ => {}
+        }
+    }
+
+    modules.retain(|m| !m.path.is_empty());
+    Ok(modules)
+}
+
+/// URL to clone a submodule from: `submodule.<name>.url` in config overrides
+/// `.gitmodules`, and relative URLs (`./x`, `../x`) are taken relative to the
+/// superproject's remote
+pub fn resolve_url(
+    module: &Submodule,
+    config: &Config,
+    superproject_url: &str,
+) -> io::Result<String> {
+    let url = config
+        .get(&format!("submodule.{}.url", module.name))
+        .map(str::to_string)
+        .or_else(|| module.url.clone())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No url found for submodule path '{}' in .gitmodules",
+                    module.path
+                ),
+            )
+        })?;
+
+    Ok(absolute_url(&url, superproject_url))
+}
+
+/// `url` as an absolute URL: relative `./x` and `../x` forms are taken
+/// relative to `base`, anything else is returned unchanged
+pub fn absolute_url(url: &str, base: &str) -> String {
+    if url.starts_with("./") || url.starts_with("../") {
+        join_relative_url(base, url)
+    } else {
+        url.to_string()
+    }
+}
+
+/// Resolve `./` and `../` components of `relative` against `base`, where each
+/// `../` strips one path component (or the path of an scp-like `host:path`)
+fn join_relative_url(base: &str, relative: &str) -> String {
+    let mut base = base.trim_end_matches('/').to_string();
+    let mut rest = relative;
+    // Becomes ':' once `../` climbs out of an scp-like URL's path
+    let mut separator = '/';
+
+    loop {
+        if let Some(stripped) = rest.strip_prefix("./") {
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix("../") {
+            rest = stripped;
+            match base.rfind('/') {
+                Some(pos) if !base[..pos].ends_with('/') => base.truncate(pos),
+                _ => match base.rfind(':') {
+                    Some(pos) if !base.contains("://") => {
+                        base.truncate(pos);
+                        separator = ':';
+                    }
+                    _ => {}
+                },
+            }
+        } else {
+            break;
+        }
+    }
+
+    format!("{}{}{}", base, separator, rest)
+}
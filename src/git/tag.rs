@@ -0,0 +1,72 @@
+// Annotated tag objects
+//
+// object 4ce3da1f...
+// type commit
+// tag v1.0
+// tagger Name <email> 1756208876 +0530
+//
+// Release 1.0
+
+use crate::git::object::{self, Error};
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub object: String,
+    pub name: String,
+    /// `Name <email> timestamp tz`; old tags may lack one
+    pub tagger: Option<String>,
+    pub message: String,
+}
+
+/// Parse the content of a tag object (header already stripped)
+pub fn parse(content: &[u8]) -> Result<Tag, Error> {
+    let text = String::from_utf8_lossy(content);
+    let (headers, message) = match text.find("\n\n") {
+        Some(pos) => (&text[..pos], &text[pos + 2..]),
+        None => (text.as_ref(), ""),
+    };
+
+    let header = |name: &str| {
+        headers
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+    };
+    let target = header("object")
+        .ok_or_else(|| Error::InvalidFormat("No object found in tag".to_string()))?;
+
+    Ok(Tag {
+        object: target.trim().to_string(),
+        name: header("tag").unwrap_or_default().to_string(),
+        tagger: header("tagger").map(str::to_string),
+        message: message.to_string(),
+    })
+}
+
+/// Serialize a tag object's content
+pub fn format(target: &str, kind: &str, name: &str, tagger: &str, message: &str) -> Vec<u8> {
+    let mut content = format!(
+        "object {}\ntype {}\ntag {}\ntagger {}\n\n{}",
+        target, kind, name, tagger, message
+    );
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.into_bytes()
+}
+
+/// Follow tag objects until reaching a non-tag object, returning its id and type
+pub fn peel(object_id: &str) -> Result<(String, String), Error> {
+    let mut current = object_id.to_string();
+    // Tags of tags are legal but a chain this long means a cycle
+    for _ in 0..16 {
+        let (kind, content) = object::read(&current)?;
+        if kind != "tag" {
+            return Ok((current, kind));
+        }
+        current = parse(&content)?.object;
+    }
+    Err(Error::InvalidFormat(format!(
+        "Tag chain too deep starting at {}",
+        object_id
+    )))
+}
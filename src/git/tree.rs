@@ -0,0 +1,414 @@
+// Tree object parsing and traversal
+// Parsing rejects an entry whose name is empty, ".", "..", ".git" in any
+// case or holds a "/", as checking it out would write outside its
+// directory or into the repository. Built with the `strict` feature it also
+// rejects the rest of what git's fsck would: unknown modes, and entries out
+// of order or repeated.
+
+use crate::git::object::{self, Error};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+pub const MODE_TREE: &str = "40000";
+pub const MODE_FILE: &str = "100644";
+pub const MODE_EXECUTABLE: &str = "100755";
+pub const MODE_SYMLINK: &str = "120000";
+pub const MODE_GITLINK: &str = "160000";
+
+/// One `<mode> <name>\0<sha>` record of a tree object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: String,
+    pub name: String,
+    pub sha: String,
+}
+
+impl TreeEntry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == MODE_TREE
+    }
+}
+
+/// A blob (or gitlink) reachable from a tree, keyed by its full path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    pub mode: String,
+    pub sha: String,
+}
+
+/// Parse the content of a tree object into its entries
+pub fn parse(content: &[u8]) -> Result<Vec<TreeEntry>, Error> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+
+    while pos < content.len() {
+        // Find space separator (between mode and name)
+        let space_pos = content[pos..]
+            .iter()
+            .position(|&b| b == b' ')
+            .map(|p| pos + p)
+            .ok_or_else(|| Error::InvalidFormat("Invalid tree entry format".to_string()))?;
+
+        let mode = String::from_utf8_lossy(&content[pos..space_pos]).to_string();
+        pos = space_pos + 1;
+
+        // Find null byte separator (between name and SHA1)
+        let null_pos = content[pos..]
+            .iter()
+            .position(|&b| b == 0)
+            .map(|p| pos + p)
+            .ok_or_else(|| Error::InvalidFormat("Invalid tree entry format".to_string()))?;
+        let name = String::from_utf8_lossy(&content[pos..null_pos]).to_string();
+        pos = null_pos + 1;
+
+        // Extract 20-byte SHA1
+        if pos + 20 > content.len() {
+            return Err(Error::InvalidFormat(
+                "Incomplete SHA1 in tree entry".to_string(),
+            ));
+        }
+        let sha = hex::encode(&content[pos..pos + 20]);
+        pos += 20;
+
+        entries.push(TreeEntry { mode, name, sha })
+    }
+    check_names(&entries)?;
+    #[cfg(feature = "strict")]
+    check_entries(&entries)?;
+    Ok(entries)
+}
+
+/// Refuse a name that is empty, ".", "..", ".git" or holds a "/"
+fn check_names(entries: &[TreeEntry]) -> Result<(), Error> {
+    for entry in entries {
+        if matches!(entry.name.as_str(), "" | "." | "..")
+            || entry.name.eq_ignore_ascii_case(".git")
+            || entry.name.contains('/')
+        {
+            return Err(Error::InvalidFormat(format!(
+                "Invalid tree entry name '{}'",
+                entry.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Refuse a mode other than the five git writes, and entries not in
+/// canonical order
+#[cfg(feature = "strict")]
+fn check_entries(entries: &[TreeEntry]) -> Result<(), Error> {
+    const MODES: [&str; 5] = [
+        MODE_TREE,
+        MODE_FILE,
+        MODE_EXECUTABLE,
+        MODE_SYMLINK,
+        MODE_GITLINK,
+    ];
+    for entry in entries {
+        if !MODES.contains(&entry.mode.as_str()) {
+            return Err(Error::InvalidFormat(format!(
+                "Invalid mode {} for tree entry {}",
+                entry.mode, entry.name
+            )));
+        }
+    }
+    if let Some(pair) = entries
+        .windows(2)
+        .find(|pair| sort_key(&pair[0]) >= sort_key(&pair[1]))
+    {
+        return Err(Error::InvalidFormat(format!(
+            "Tree entry {} out of order or repeated",
+            pair[1].name
+        )));
+    }
+    Ok(())
+}
+
+/// Read and parse a tree object
+pub fn read(tree_sha: &str) -> Result<Vec<TreeEntry>, Error> {
+    let content = object::read_expecting(tree_sha, "tree")?;
+    parse(&content)
+}
+
+/// Recursively collect every non-tree entry under `tree_sha`, keyed by
+/// `/`-separated path relative to the tree root
+pub fn flatten(tree_sha: &str) -> Result<BTreeMap<String, FileEntry>, Error> {
+    let mut files = BTreeMap::new();
+    flatten_into(tree_sha, "", &mut files)?;
+    Ok(files)
+}
+
+fn flatten_into(
+    tree_sha: &str,
+    prefix: &str,
+    files: &mut BTreeMap<String, FileEntry>,
+) -> Result<(), Error> {
+    for entry in read(tree_sha)? {
+        let path = if prefix.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", prefix, entry.name)
+        };
+        if entry.is_tree() {
+            flatten_into(&entry.sha, &path, files)?;
+        } else {
+            files.insert(
+                path,
+                FileEntry {
+                    mode: entry.mode,
+                    sha: entry.sha,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Visit every non-tree entry under `tree_sha` with its full path, in tree
+/// order. Trees are read as the walk reaches them, so only the chain of
+/// trees above the current entry is held at a time.
+pub fn walk<F>(tree_sha: &str, visit: &mut F) -> Result<(), Error>
+where
+    F: FnMut(&str, &TreeEntry) -> Result<(), Error>,
+{
+    walk_from(tree_sha, "", visit)
+}
+
+fn walk_from<F>(tree_sha: &str, prefix: &str, visit: &mut F) -> Result<(), Error>
+where
+    F: FnMut(&str, &TreeEntry) -> Result<(), Error>,
+{
+    for entry in read(tree_sha)? {
+        let path = if prefix.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", prefix, entry.name)
+        };
+        if entry.is_tree() {
+            walk_from(&entry.sha, &path, visit)?;
+        } else {
+            visit(&path, &entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// One path that differs between two trees; `None` where the path is
+/// absent. Without recursion a subtree is reported as an entry of mode
+/// `MODE_TREE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub path: String,
+    pub old: Option<FileEntry>,
+    pub new: Option<FileEntry>,
+}
+
+/// Compare two trees (`None` for an empty tree), walking both in tree
+/// order. With `recursive`, changed subtrees are descended into and only
+/// the files beneath them are reported.
+pub fn diff(old: Option<&str>, new: Option<&str>, recursive: bool) -> Result<Vec<Change>, Error> {
+    let mut changes = Vec::new();
+    diff_into(old, new, "", recursive, &mut changes)?;
+    Ok(changes)
+}
+
+fn diff_into(
+    old: Option<&str>,
+    new: Option<&str>,
+    prefix: &str,
+    recursive: bool,
+    changes: &mut Vec<Change>,
+) -> Result<(), Error> {
+    let old_entries = match old {
+        Some(sha) => read(sha)?,
+        None => Vec::new(),
+    };
+    let new_entries = match new {
+        Some(sha) => read(sha)?,
+        None => Vec::new(),
+    };
+    // Trees sort as if their names ended in '/'
+    let key = |entry: &TreeEntry| {
+        let mut key = entry.name.as_bytes().to_vec();
+        if entry.is_tree() {
+            key.push(b'/');
+        }
+        key
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < old_entries.len() || j < new_entries.len() {
+        let order = match (old_entries.get(i), new_entries.get(j)) {
+            (Some(a), Some(b)) => key(a).cmp(&key(b)),
+            (Some(_), None) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+        let (a, b) = match order {
+            Ordering::Less => {
+                i += 1;
+                (Some(&old_entries[i - 1]), None)
+            }
+            Ordering::Greater => {
+                j += 1;
+                (None, Some(&new_entries[j - 1]))
+            }
+            Ordering::Equal => {
+                i += 1;
+                j += 1;
+                (Some(&old_entries[i - 1]), Some(&new_entries[j - 1]))
+            }
+        };
+        if let (Some(a), Some(b)) = (a, b) {
+            if a.mode == b.mode && a.sha == b.sha {
+                continue;
+            }
+        }
+
+        let name = a.or(b).map(|e| e.name.as_str()).unwrap_or_default();
+        let path = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let is_tree = a.or(b).is_some_and(TreeEntry::is_tree);
+        if recursive && is_tree {
+            let (a, b) = (a.map(|e| e.sha.as_str()), b.map(|e| e.sha.as_str()));
+            diff_into(a, b, &path, recursive, changes)?;
+            continue;
+        }
+        let file = |entry: Option<&TreeEntry>| {
+            entry.map(|e| FileEntry {
+                mode: e.mode.clone(),
+                sha: e.sha.clone(),
+            })
+        };
+        changes.push(Change {
+            path,
+            old: file(a),
+            new: file(b),
+        });
+    }
+    Ok(())
+}
+
+/// Write the tree objects for a flat path -> entry map, returning the root
+/// tree's id. Intermediate directories are created from the paths.
+pub fn write_snapshot(files: &BTreeMap<String, FileEntry>) -> Result<String, Error> {
+    let entries: Vec<(&str, &FileEntry)> = files.iter().map(|(p, e)| (p.as_str(), e)).collect();
+    write_level(&entries)
+}
+
+fn write_level(files: &[(&str, &FileEntry)]) -> Result<String, Error> {
+    let mut entries: Vec<TreeEntry> = Vec::new();
+    let mut subdirs: BTreeMap<&str, Vec<(&str, &FileEntry)>> = BTreeMap::new();
+
+    for &(path, entry) in files {
+        match path.split_once('/') {
+            Some((dir, rest)) => subdirs.entry(dir).or_default().push((rest, entry)),
+            None => entries.push(TreeEntry {
+                mode: entry.mode.clone(),
+                name: path.to_string(),
+                sha: entry.sha.clone(),
+            }),
+        }
+    }
+    for (dir, children) in subdirs {
+        entries.push(TreeEntry {
+            mode: MODE_TREE.to_string(),
+            name: dir.to_string(),
+            sha: write_level(&children)?,
+        });
+    }
+
+    object::write("tree", &serialize(&mut entries)?)
+}
+
+/// Encode entries in git's canonical order: by name, with directories
+/// compared as if their name ended in `/`
+fn serialize(entries: &mut [TreeEntry]) -> Result<Vec<u8>, Error> {
+    entries.sort_by_key(sort_key);
+
+    let mut content = Vec::new();
+    for entry in entries.iter() {
+        content.extend_from_slice(format!("{} {}\0", entry.mode, entry.name).as_bytes());
+        let sha = hex::decode(&entry.sha)
+            .map_err(|e| Error::InvalidFormat(format!("Invalid SHA {}: {}", entry.sha, e)))?;
+        content.extend_from_slice(&sha);
+    }
+    Ok(content)
+}
+
+fn sort_key(entry: &TreeEntry) -> Vec<u8> {
+    let mut key = entry.name.as_bytes().to_vec();
+    if entry.is_tree() {
+        key.push(b'/');
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+    fn entry(mode: &str, name: &str) -> TreeEntry {
+        TreeEntry {
+            mode: mode.to_string(),
+            name: name.to_string(),
+            sha: SHA.to_string(),
+        }
+    }
+
+    /// Tree content with `entries` in the order given
+    fn raw(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut content = Vec::new();
+        for (mode, name) in entries {
+            content.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
+            content.extend_from_slice(&hex::decode(SHA).unwrap());
+        }
+        content
+    }
+
+    #[test]
+    fn parse_round_trips_serialize() {
+        let mut entries = vec![
+            entry(MODE_FILE, "b.txt"),
+            entry(MODE_TREE, "a"),
+            entry(MODE_EXECUTABLE, "a.sh"),
+            entry(MODE_SYMLINK, "link"),
+            entry(MODE_GITLINK, "sub"),
+        ];
+        let content = serialize(&mut entries).unwrap();
+        assert_eq!(parse(&content).unwrap(), entries);
+        // "a.sh" sorts before the tree "a", compared as "a/"
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a.sh", "a", "b.txt", "link", "sub"]);
+    }
+
+    #[test]
+    fn parse_rejects_truncated_entries() {
+        let content = raw(&[(MODE_FILE, "a")]);
+        for len in [3, 9, content.len() - 1] {
+            assert!(parse(&content[..len]).is_err(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_names_that_leave_the_directory() {
+        for name in ["", ".", "..", ".git", ".GIT", "a/b", "../x"] {
+            assert!(parse(&raw(&[(MODE_TREE, name)])).is_err(), "{:?}", name);
+            assert!(parse(&raw(&[(MODE_FILE, name)])).is_err(), "{:?}", name);
+        }
+        assert!(parse(&raw(&[(MODE_FILE, "..a"), (MODE_FILE, ".gitignore")])).is_ok());
+    }
+
+    #[cfg(feature = "strict")]
+    #[test]
+    fn strict_parse_rejects_bad_modes_and_order() {
+        assert!(parse(&raw(&[("100664", "a")])).is_err());
+        assert!(parse(&raw(&[(MODE_FILE, "b"), (MODE_FILE, "a")])).is_err());
+        assert!(parse(&raw(&[(MODE_FILE, "a"), (MODE_FILE, "a")])).is_err());
+    }
+}
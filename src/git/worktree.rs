@@ -0,0 +1,577 @@
+// Working tree updates
+// Moves the files on disk from one tree snapshot to another, the core of
+// branch switching: unchanged paths are left alone, and local modifications
+// are never silently overwritten. The index follows the files written.
+// Updates can be cancelled through a `Progress`, undoing what was done.
+//
+// With core.symlinks set to false (init and clone set it when the
+// filesystem can't hold symlinks), a symlink is checked out as a plain file
+// holding the link's target, and such a file is taken to still be the
+// symlink the index says it is.
+//
+// Paths are checked before anything is written: one that would leave the
+// work tree or reach into the repository (an empty, "." or ".." component,
+// or ".git" in any case), a name longer than the filesystem allows, or on
+// Windows one it can't represent at all ("aux.c", "a:b", a trailing dot),
+// fails the update with the path in the error rather than part way
+// through. Leading directories that are symlinks on disk are replaced by
+// real directories rather than written through.
+// Windows paths past MAX_PATH are written through the `\\?\` prefix, which
+// lifts the limit to 32767 characters.
+
+use crate::git::commit;
+use crate::git::config::{self, Config};
+use crate::git::index::{Entry, Index};
+use crate::git::interrupt;
+use crate::git::object;
+use crate::git::progress::Progress;
+use crate::git::promisor;
+use crate::git::repository;
+use crate::git::tree::{self, FileEntry, MODE_EXECUTABLE, MODE_GITLINK, MODE_SYMLINK};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+/// The longest file name most filesystems hold: bytes on Unix, UTF-16
+/// units on Windows
+const NAME_MAX: usize = 255;
+
+/// The longest path the OS accepts, in the same units as `NAME_MAX`
+#[cfg(windows)]
+const PATH_MAX: usize = 32767;
+#[cfg(not(windows))]
+const PATH_MAX: usize = 4096;
+
+/// Paths at least this long need the `\\?\` prefix on Windows
+#[cfg(windows)]
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Every file of a tree, keyed by path relative to the repository root
+pub type Snapshot = BTreeMap<String, FileEntry>;
+
+/// Snapshot of a commit's tree, or an empty snapshot for an unborn branch
+pub fn snapshot_of_commit(commit_sha: Option<&str>) -> io::Result<Snapshot> {
+    match commit_sha {
+        Some(sha) => {
+            let commit = commit::read(sha)?;
+            Ok(tree::flatten(&commit.tree)?)
+        }
+        None => Ok(Snapshot::new()),
+    }
+}
+
+/// Update the working tree from `current` to `target`.
+///
+/// Every affected path is checked before anything is written: a tracked file
+/// that differs from `current`, or an untracked file in the way of a new path,
+/// aborts the whole update unless `force` is set. A directory in the way of a
+/// new file is only in the way if it holds something besides the tracked
+/// files the update removes.
+pub fn update(current: &Snapshot, target: &Snapshot, force: bool) -> io::Result<()> {
+    update_with_progress(current, target, force, &mut Progress::silent())
+}
+
+/// How to put back one path touched by an update that didn't finish
+enum Undo<'a> {
+    Restore(&'a str, &'a FileEntry),
+    Remove(&'a str),
+}
+
+/// `update`, reporting "Updating files" progress and checking for
+/// cancellation between files. An update that is cancelled or fails part
+/// way puts back every file it had touched, and the index is left alone.
+pub fn update_with_progress(
+    current: &Snapshot,
+    target: &Snapshot,
+    force: bool,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let _cancellable = interrupt::cancellable();
+    let mut to_remove = Vec::new();
+    let mut to_write = Vec::new();
+
+    for (path, entry) in current {
+        match target.get(path) {
+            Some(new_entry) if new_entry == entry => {}
+            Some(new_entry) => to_write.push((path, new_entry, Some(entry))),
+            None => to_remove.push((path, entry)),
+        }
+    }
+    for (path, entry) in target {
+        if !current.contains_key(path) {
+            to_write.push((path, entry, None));
+        }
+    }
+    for (path, _) in &to_remove {
+        validate_path(path)?;
+    }
+    for (path, _, _) in &to_write {
+        validate_path(path)?;
+    }
+
+    if !force {
+        let removed: HashSet<&str> = to_remove.iter().map(|(path, _)| path.as_str()).collect();
+        let mut dirty = Vec::new();
+        let mut untracked = Vec::new();
+        let mut untracked_dirs = Vec::new();
+        for (path, entry) in &to_remove {
+            if !is_up_to_date(path, entry)? {
+                dirty.push(path.as_str());
+            }
+        }
+        for (path, new_entry, old_entry) in &to_write {
+            match old_entry {
+                Some(old) => {
+                    if !is_up_to_date(path, old)? {
+                        dirty.push(path.as_str());
+                    }
+                }
+                None if is_real_dir(path) && new_entry.mode != MODE_GITLINK => {
+                    if has_untracked_files(Path::new(path.as_str()), &removed)? {
+                        untracked_dirs.push(path.as_str());
+                    }
+                }
+                None => {
+                    let exists = fs::symlink_metadata(path.as_str()).is_ok();
+                    if (exists && !matches_worktree(path, new_entry)?)
+                        || blocked_by_untracked_file(path, current)
+                    {
+                        untracked.push(path.as_str());
+                    }
+                }
+            }
+        }
+        if !dirty.is_empty() {
+            return Err(io::Error::other(format!(
+                "Your local changes to the following files would be overwritten:\n\t{}\nPlease commit your changes or stash them before you switch branches.",
+                dirty.join("\n\t")
+            )));
+        }
+        if !untracked.is_empty() {
+            return Err(io::Error::other(format!(
+                "The following untracked working tree files would be overwritten:\n\t{}\nPlease move or remove them before you switch branches.",
+                untracked.join("\n\t")
+            )));
+        }
+        if !untracked_dirs.is_empty() {
+            return Err(io::Error::other(format!(
+                "Updating the following directories would lose untracked files in them:\n\t{}\nPlease move or remove them before you switch branches.",
+                untracked_dirs.join("\n\t")
+            )));
+        }
+    }
+
+    // A partial clone fetches the blobs to write in one go, rather than one
+    // by one as each is read
+    promisor::prefetch(to_write.iter().map(|(_, entry, _)| *entry), progress)?;
+
+    let mut undo = Vec::new();
+    if let Err(e) = apply(&to_remove, &to_write, &mut undo, progress) {
+        for step in undo.iter().rev() {
+            let _ = match step {
+                Undo::Restore(path, entry) => checkout_entry(path, entry),
+                Undo::Remove(path) => remove_file(path),
+            };
+        }
+        return Err(e);
+    }
+
+    // Stage what was just written, with fresh stat data
+    let mut index = Index::load()?;
+    for (path, _) in &to_remove {
+        index.remove(path);
+    }
+    for (path, entry, _) in &to_write {
+        index.add(Entry::checked_out(path, entry)?);
+    }
+    index.write()
+}
+
+/// Carry out an update's removals and writes, recording in `undo` how to
+/// reverse each one before it is attempted
+fn apply<'a>(
+    to_remove: &[(&'a String, &'a FileEntry)],
+    to_write: &[(&'a String, &'a FileEntry, Option<&'a FileEntry>)],
+    undo: &mut Vec<Undo<'a>>,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let total = to_remove.len() + to_write.len();
+    let mut last_percent = None;
+    let mut report = |progress: &mut Progress, done: usize| {
+        let percent = done * 100 / total;
+        if last_percent != Some(percent) {
+            last_percent = Some(percent);
+            let suffix = if done == total { ", done." } else { "" };
+            progress.report(&format!(
+                "Updating files: {:3}% ({}/{}){}",
+                percent, done, total, suffix
+            ));
+        }
+    };
+
+    for (done, (path, entry)) in to_remove.iter().enumerate() {
+        progress.check()?;
+        undo.push(Undo::Restore(path, entry));
+        remove_file(path)?;
+        report(progress, done + 1);
+    }
+    for (done, (path, entry, old_entry)) in to_write.iter().enumerate() {
+        progress.check()?;
+        undo.push(match old_entry {
+            Some(old) => Undo::Restore(path, old),
+            // An identical untracked file may already have been there
+            None if fs::symlink_metadata(path.as_str()).is_ok() => Undo::Restore(path, entry),
+            None => Undo::Remove(path),
+        });
+        checkout_entry(path, entry)?;
+        report(progress, to_remove.len() + done + 1);
+    }
+    Ok(())
+}
+
+/// A tracked file is safe to overwrite if it is unmodified or already gone
+fn is_up_to_date(path: &str, entry: &FileEntry) -> io::Result<bool> {
+    if fs::symlink_metadata(path).is_err() {
+        return Ok(true);
+    }
+    matches_worktree(path, entry)
+}
+
+/// Whether `path` is a directory on disk, not a symlink to one
+fn is_real_dir(path: &str) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir())
+}
+
+/// Whether anything below `dir` other than the files in `removed` exists
+fn has_untracked_files(dir: &Path, removed: &HashSet<&str>) -> io::Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.to_string_lossy();
+        let name = name.strip_prefix("./").unwrap_or(&name);
+        if removed.contains(name) {
+            continue;
+        }
+        if !fs::symlink_metadata(&path)?.is_dir() || has_untracked_files(&path, removed)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Whether a leading directory of `path` exists on disk as an untracked
+/// non-directory, which would make creating `path` fail
+fn blocked_by_untracked_file(path: &str, current: &Snapshot) -> bool {
+    Path::new(path)
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .any(|dir| {
+            let tracked = current.contains_key(dir.to_string_lossy().as_ref());
+            !tracked
+                && fs::symlink_metadata(dir)
+                    .map(|m| !m.is_dir())
+                    .unwrap_or(false)
+        })
+}
+
+/// Whether the file on disk at `path` has exactly the content and type of `entry`
+pub fn matches_worktree(path: &str, entry: &FileEntry) -> io::Result<bool> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    if entry.mode == MODE_GITLINK {
+        return Ok(metadata.is_dir());
+    }
+
+    let content = if metadata.file_type().is_symlink() {
+        if entry.mode != MODE_SYMLINK {
+            return Ok(false);
+        }
+        fs::read_link(path)?
+            .to_string_lossy()
+            .into_owned()
+            .into_bytes()
+    } else if metadata.is_file() {
+        if entry.mode == MODE_SYMLINK && symlinks_enabled()? {
+            return Ok(false);
+        }
+        fs::read(path)?
+    } else {
+        return Ok(false);
+    };
+
+    Ok(object::hash("blob", &content) == entry.sha)
+}
+
+/// Write a single tree entry to the working tree
+pub fn checkout_entry(path: &str, entry: &FileEntry) -> io::Result<()> {
+    validate_path(path)?;
+    create_leading_dirs(path)?;
+    let entry_path = &long_path(Path::new(path))?;
+
+    // Replace whatever is currently at the path
+    if let Ok(metadata) = fs::symlink_metadata(entry_path) {
+        if metadata.is_dir() && !metadata.file_type().is_symlink() {
+            if entry.mode == MODE_GITLINK {
+                return Ok(());
+            }
+            fs::remove_dir_all(entry_path)?;
+        } else {
+            fs::remove_file(entry_path)?;
+        }
+    }
+
+    if entry.mode == MODE_GITLINK {
+        // Submodule placeholder: an empty directory
+        return fs::create_dir_all(entry_path);
+    }
+
+    let content = object::read_expecting(&entry.sha, "blob")?;
+    write_content(entry_path, &entry.mode, &content)
+}
+
+/// Create the file at `path`, which must not exist, with `content` as a
+/// file of `mode`: a symlink to `content`, or an executable or plain file
+pub fn write_content(path: &Path, mode: &str, content: &[u8]) -> io::Result<()> {
+    if mode == MODE_SYMLINK && symlinks_enabled()? {
+        #[cfg(unix)]
+        {
+            let target = String::from_utf8_lossy(content).into_owned();
+            return std::os::unix::fs::symlink(target, path);
+        }
+    }
+
+    fs::write(path, content)?;
+
+    // Set executable permission if needed (Unix-like systems only)
+    #[cfg(unix)]
+    {
+        if mode == MODE_EXECUTABLE {
+            let mut perms = fs::metadata(path)?.permissions();
+            perms.set_mode(0o755);
+            fs::set_permissions(path, perms)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the directories above `path`, replacing any that is a symlink
+/// or a file, so nothing is written through a link to outside the work tree
+pub fn create_leading_dirs(path: &str) -> io::Result<()> {
+    let mut dir = PathBuf::new();
+    let names: Vec<&str> = path.split('/').collect();
+    for name in &names[..names.len() - 1] {
+        dir.push(name);
+        let long = long_path(&dir)?;
+        match fs::symlink_metadata(&long) {
+            Ok(metadata) if metadata.is_dir() => continue,
+            Ok(_) => fs::remove_file(&long)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        fs::create_dir(&long)?;
+    }
+    Ok(())
+}
+
+/// Fail with a clear error if `path` can't be created on this system, or
+/// would be created outside the work tree or inside the repository
+pub fn validate_path(path: &str) -> io::Result<()> {
+    let invalid = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unable to create file {}: {}", path, reason),
+        )
+    };
+    let names = || path.split('/');
+    let is_dot_git = |name: &str| {
+        // NTFS also knows .git by its short name
+        name.eq_ignore_ascii_case(".git") || (cfg!(windows) && name.eq_ignore_ascii_case("git~1"))
+    };
+    if names().any(|name| matches!(name, "" | "." | "..") || is_dot_git(name)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid path '{}'", path),
+        ));
+    }
+    if path_len(path) >= PATH_MAX {
+        return Err(invalid("Filename too long"));
+    }
+    for name in names() {
+        if path_len(name) > NAME_MAX {
+            return Err(invalid("Filename too long"));
+        }
+        if cfg!(windows) && !is_windows_name(name) {
+            return Err(invalid("Invalid argument"));
+        }
+    }
+    Ok(())
+}
+
+/// The length of `path` in the units the OS limits
+fn path_len(path: &str) -> usize {
+    if cfg!(windows) {
+        path.encode_utf16().count()
+    } else {
+        path.len()
+    }
+}
+
+/// Whether Windows can hold a file called `name`: no reserved characters,
+/// no trailing dot or space, and no device name like "con" or "com1.txt"
+fn is_windows_name(name: &str) -> bool {
+    if name
+        .chars()
+        .any(|c| c < ' ' || matches!(c, '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*'))
+        || name.ends_with(['.', ' '])
+    {
+        return false;
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    let stem = stem.to_ascii_lowercase();
+    let device = matches!(stem.as_str(), "con" | "prn" | "aux" | "nul")
+        || (stem.len() == 4
+            && (stem.starts_with("com") || stem.starts_with("lpt"))
+            && matches!(stem.as_bytes()[3], b'1'..=b'9'));
+    !device
+}
+
+/// `path` in a form the OS can open: on Windows, a path of MAX_PATH or more
+/// is made absolute and given the `\\?\` prefix
+pub fn long_path(path: &Path) -> io::Result<PathBuf> {
+    #[cfg(windows)]
+    {
+        let absolute = std::env::current_dir()?.join(path);
+        let text = absolute.to_string_lossy();
+        if text.encode_utf16().count() >= WINDOWS_MAX_PATH && !text.starts_with(r"\\?\") {
+            // Verbatim paths take no "/" separators and no "." components
+            let text = text.replace('/', r"\").replace(r"\.\", r"\");
+            return Ok(PathBuf::from(match text.strip_prefix(r"\\") {
+                Some(unc) => format!(r"\\?\UNC\{}", unc),
+                None => format!(r"\\?\{}", text),
+            }));
+        }
+    }
+    Ok(path.to_path_buf())
+}
+
+/// core.symlinks: whether symlinks are checked out as symlinks
+pub fn symlinks_enabled() -> io::Result<bool> {
+    Ok(Config::load()?.get_bool("core.symlinks")?.unwrap_or(true))
+}
+
+/// Whether symlinks can be created in the directory `dir`
+pub fn filesystem_supports_symlinks(dir: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        let probe = dir.join(format!("symlink-probe-{}", std::process::id()));
+        let created = std::os::unix::fs::symlink("target", &probe).is_ok();
+        let _ = fs::remove_file(&probe);
+        created
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+        false
+    }
+}
+
+/// Record core.symlinks=false in a new repository whose filesystem can't
+/// hold symlinks
+pub fn configure_symlinks() -> io::Result<()> {
+    if filesystem_supports_symlinks(repository::git_dir()) {
+        return Ok(());
+    }
+    config::set_value(&repository::path("config"), "core.symlinks", "false")
+}
+
+/// Remove a tracked file and any parent directories left empty
+pub fn remove_file(path: &str) -> io::Result<()> {
+    validate_path(path)?;
+    let entry_path = Path::new(path);
+    let long = long_path(entry_path)?;
+    match fs::symlink_metadata(&long) {
+        // A submodule directory is only removed once it is empty
+        Ok(metadata) if metadata.is_dir() => {
+            if fs::remove_dir(&long).is_err() {
+                return Ok(());
+            }
+        }
+        Ok(_) => fs::remove_file(&long)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let mut parent = entry_path.parent();
+    while let Some(dir) = parent {
+        if dir.as_os_str().is_empty() || fs::remove_dir(long_path(dir)?).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_path_accepts_ordinary_paths() {
+        for path in [
+            "a",
+            "dir/file.txt",
+            "a/.gitignore",
+            "x/.github/ci.yml",
+            "..a",
+            "a..",
+        ] {
+            assert!(validate_path(path).is_ok(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn validate_path_rejects_paths_leaving_the_work_tree() {
+        for path in [
+            "",
+            "..",
+            "../escape.txt",
+            "a/../../escape.txt",
+            "./a",
+            "a/./b",
+            "/etc/passwd",
+            "a//b",
+            "a/",
+        ] {
+            let error = validate_path(path).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{}", path);
+        }
+    }
+
+    #[test]
+    fn validate_path_rejects_the_repository() {
+        for path in [
+            ".git",
+            ".git/hooks/post-checkout",
+            "sub/.GIT/config",
+            ".Git",
+        ] {
+            assert!(validate_path(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn validate_path_rejects_overlong_names() {
+        assert!(validate_path(&"a".repeat(NAME_MAX)).is_ok());
+        assert!(validate_path(&"a".repeat(NAME_MAX + 1)).is_err());
+        assert!(validate_path(&["abc"; PATH_MAX / 4 + 1].join("/")).is_err());
+    }
+}
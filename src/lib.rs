@@ -0,0 +1,7 @@
+//! A small Git implementation. The commands are usable as a library as well
+//! as through the binary; `commands::clone::clone` takes a
+//! [`CloneOptions`](commands::clone::CloneOptions) for configuring a clone
+//! programmatically.
+
+pub mod commands;
+pub mod git;
@@ -1,6 +1,5 @@
-mod commands;
-mod git;
-
+use codecrafters_git::commands;
+use codecrafters_git::git::{fsync, interrupt};
 use std::env;
 use std::io;
 use std::process;
@@ -14,46 +13,85 @@ fn main() {
     }
 
     let command = &args[1];
+    interrupt::install();
 
     let result: io::Result<()> = match command.as_str() {
         "init" => commands::init::run(),
-        "cat-file" => {
-            if args.len() != 4 {
-                eprintln!("Usage: {} cat-file -<flag> <object_id>", args[0]);
-                process::exit(1);
-            }
-            commands::cat_file::run(&args[3], &args[2])
-        }
-        "hash-object" => {
-            if args.len() == 3 && args[2] != "-w" {
-                commands::hash_object::run(&args[2], false)
-            } else if args.len() == 4 && args[2] == "-w" {
-                commands::hash_object::run(&args[3], true)
-            } else {
-                eprintln!("Usage: {} hash-object [-w] <file>", args[0]);
-                process::exit(1);
-            }
-        }
+        "cat-file" => commands::cat_file::run(&args[2..]),
+        "hash-object" => commands::hash_object::run(&args[2..]),
         "ls-tree" => {
             if args.len() == 3 && args[2] != "--name-only" {
-                commands::ls_tree::run(&args[3], false)
+                commands::ls_tree::run(&args[2], false)
             } else if args.len() == 4 && args[2] == "--name-only" {
                 commands::ls_tree::run(&args[3], true)
             } else {
-                eprintln!("Usage: {} ls-tree [-name-only] <tree_id>", args[0]);
+                eprintln!("Usage: {} ls-tree [--name-only] <tree-ish>", args[0]);
                 process::exit(1);
             }
         }
         "write-tree" => commands::write_tree::run(),
+        "commit" => commands::commit::run(&args[2..]),
         "commit-tree" => commands::commit_tree::run(&args[2..]),
+        "config" => commands::config::run(&args[2..]),
+        "update-index" => commands::update_index::run(&args[2..]),
+        "update-ref" => commands::update_ref::run(&args[2..]),
         "clone" => commands::clone::run(&args[2..]),
+        "fetch" => commands::fetch::run(&args[2..]),
+        "fetch-pack" => commands::fetch_pack::run(&args[2..]),
+        "send-pack" => commands::send_pack::run(&args[2..]),
+        "receive-pack" => commands::receive_pack::run(&args[2..]),
+        "upload-pack" => commands::upload_pack::run(&args[2..]),
+        "check-ref-format" => commands::check_ref_format::run(&args[2..]),
+        "branch" => commands::branch::run(&args[2..]),
+        "checkout" => commands::checkout::run(&args[2..]),
+        "switch" => commands::switch::run(&args[2..]),
+        "symbolic-ref" => commands::symbolic_ref::run(&args[2..]),
+        "tag" => commands::tag::run(&args[2..]),
+        "diff" => commands::diff::run(&args[2..]),
+        "diff-tree" => commands::diff_tree::run(&args[2..]),
+        "reflog" => commands::reflog::run(&args[2..]),
+        "log" => commands::log::run(&args[2..]),
+        "rev-list" => commands::rev_list::run(&args[2..]),
+        "rev-parse" => commands::rev_parse::run(&args[2..]),
+        "ls-remote" => commands::ls_remote::run(&args[2..]),
+        "remote" => commands::remote::run(&args[2..]),
+        "shortlog" => commands::shortlog::run(&args[2..]),
+        "show" => commands::show::run(&args[2..]),
+        "show-ref" => commands::show_ref::run(&args[2..]),
+        "status" => commands::status::run(&args[2..]),
+        "stash" => commands::stash::run(&args[2..]),
+        "grep" => commands::grep::run(&args[2..]),
+        "apply" => commands::apply::run(&args[2..]),
+        "am" => commands::am::run(&args[2..]),
+        "bugreport" => commands::bugreport::run(&args[2..]),
+        "merge" => commands::merge::run(&args[2..]),
+        "merge-base" => commands::merge_base::run(&args[2..]),
+        "rebase" => commands::rebase::run(&args[2..]),
+        "reset" => commands::reset::run(&args[2..]),
+        "revert" => commands::revert::run(&args[2..]),
+        "rm" => commands::rm::run(&args[2..]),
+        "mv" => commands::mv::run(&args[2..]),
+        "pack-objects" => commands::pack_objects::run(&args[2..]),
+        "pack-refs" => commands::pack_refs::run(&args[2..]),
+        "pull" => commands::pull::run(&args[2..]),
+        "push" => commands::push::run(&args[2..]),
+        "submodule" => commands::submodule::run(&args[2..]),
+        "worktree" => commands::worktree::run(&args[2..]),
+        "mailsplit" => commands::mailsplit::run(&args[2..]),
+        "mailinfo" => commands::mailinfo::run(&args[2..]),
         _ => {
             eprintln!("Unknown command: {}", command);
             process::exit(1);
         }
     };
+    // Loose objects written in a batch are flushed before exiting
+    let result = result.and_then(|()| fsync::flush_batch());
 
     if let Err(e) = result {
+        // An operation cancelled by Ctrl-C has already cleaned up after itself
+        if e.kind() == io::ErrorKind::Interrupted && interrupt::received() {
+            process::exit(interrupt::EXIT_INTERRUPTED);
+        }
         eprintln!("Error: {}", e);
         process::exit(1);
     }
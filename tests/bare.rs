@@ -0,0 +1,149 @@
+// A bare repository is its own git directory: it can be cloned from by
+// path, a bare clone can be used by the tool's own commands, and
+// upload-pack and receive-pack serve one as a remote.
+
+mod common;
+
+use common::Scratch;
+
+#[test]
+fn clone_from_a_bare_repository_by_path() {
+    let scratch = Scratch::new("bare-clone-from");
+    let source = scratch.repo("source", &[("a.txt", "a\n")]);
+    scratch.git(
+        &scratch.root,
+        &[
+            "clone",
+            "-q",
+            "--bare",
+            source.to_str().unwrap(),
+            "bare.git",
+        ],
+    );
+
+    for (remote, dest) in [("bare.git", "copy"), ("bare.git/", "slash")] {
+        scratch.run_ok(&scratch.root, &["clone", remote, dest]);
+        let copy = scratch.path(dest);
+        assert_eq!(std::fs::read_to_string(copy.join("a.txt")).unwrap(), "a\n");
+        assert_eq!(
+            scratch.git(&copy, &["rev-parse", "HEAD"]),
+            scratch.git(&source, &["rev-parse", "HEAD"])
+        );
+    }
+}
+
+#[test]
+fn commands_run_inside_a_bare_clone() {
+    let scratch = Scratch::new("bare-commands");
+    let source = scratch.repo("source", &[("a.txt", "a\n")]);
+    scratch.run_ok(
+        &scratch.root,
+        &["clone", "--bare", source.to_str().unwrap(), "bare.git"],
+    );
+    let bare = scratch.path("bare.git");
+    assert_eq!(
+        scratch.git(&bare, &["rev-parse", "--is-bare-repository"]),
+        "true\n"
+    );
+
+    let head = scratch.git(&source, &["rev-parse", "HEAD"]);
+    assert_eq!(scratch.run_ok(&bare, &["rev-parse", "HEAD"]), head);
+    assert_eq!(scratch.run_ok(&bare, &["rev-parse", "--git-dir"]), ".\n");
+    assert_eq!(
+        scratch.run_ok(&bare, &["rev-parse", "--is-bare-repository"]),
+        "true\n"
+    );
+    assert_eq!(scratch.run_ok(&bare, &["branch"]), "* main\n");
+    let log = scratch.run_ok(&bare, &["log", "--oneline"]);
+    assert!(log.ends_with(" initial\n"), "{}", log);
+
+    scratch.run_ok(&bare, &["tag", "v1"]);
+    assert_eq!(scratch.git(&bare, &["rev-parse", "v1"]), head);
+    scratch.run_ok(&bare, &["config", "core.logAllRefUpdates", "false"]);
+    assert_eq!(
+        scratch.git(&bare, &["config", "core.logAllRefUpdates"]),
+        "false\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn push_and_fetch_through_a_bare_remote_over_ssh() {
+    let scratch = Scratch::new("bare-ssh");
+    let source = scratch.repo("source", &[("a.txt", "a\n")]);
+    let remote = scratch.path("remote.git");
+    scratch.git(
+        &scratch.root,
+        &[
+            "clone",
+            "-q",
+            "--bare",
+            source.to_str().unwrap(),
+            "remote.git",
+        ],
+    );
+    let ssh = scratch.fake_ssh();
+    let vars = [("GIT_SSH", ssh.as_path())];
+    let url = format!("ssh://localhost{}", remote.display());
+    for clone in ["pusher", "fetcher"] {
+        let output = scratch.run_env(&scratch.root, &["clone", &url, clone], &vars);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    // The branch HEAD names in the bare remote takes the push: nothing is
+    // checked out there
+    let pusher = scratch.path("pusher");
+    common::write_files(&pusher, &[("b.txt", "b\n")]);
+    scratch.git(&pusher, &["add", "b.txt"]);
+    scratch.git(&pusher, &["commit", "-q", "-m", "second"]);
+    let pushed = scratch.git(&pusher, &["rev-parse", "HEAD"]);
+    let output = scratch.run_env(&pusher, &["push", "origin", "main"], &vars);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(scratch.git(&remote, &["rev-parse", "main"]), pushed);
+    scratch.git(&remote, &["fsck", "--no-progress"]);
+
+    let fetcher = scratch.path("fetcher");
+    let output = scratch.run_env(&fetcher, &["fetch", "origin"], &vars);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(scratch.git(&fetcher, &["rev-parse", "origin/main"]), pushed);
+    assert_eq!(scratch.git(&fetcher, &["show", "origin/main:b.txt"]), "b\n");
+}
+
+#[test]
+fn upload_pack_serves_the_bare_repository_it_runs_in() {
+    // As git daemon runs it: in the repository, naming it "."
+    let scratch = Scratch::new("bare-daemon");
+    let source = scratch.repo("source", &[("a.txt", "a\n")]);
+    scratch.git(
+        &scratch.root,
+        &[
+            "clone",
+            "-q",
+            "--bare",
+            source.to_str().unwrap(),
+            "remote.git",
+        ],
+    );
+    let head = scratch.git(&source, &["rev-parse", "HEAD"]);
+    let remote = scratch.path("remote.git");
+    for service in ["upload-pack", "receive-pack"] {
+        let advertisement = scratch.run_ok(&remote, &[service, "--advertise-refs", "."]);
+        assert!(
+            advertisement.contains(&format!("{} refs/heads/main", head.trim())),
+            "{}",
+            advertisement
+        );
+    }
+}
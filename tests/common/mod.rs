@@ -0,0 +1,204 @@
+// Helpers for the end-to-end tests: scratch repositories built with the
+// system git, and the binary under test run inside them. HOME points into
+// the scratch directory so neither git nor the binary reads the user's
+// configuration.
+
+#![allow(dead_code)]
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A scratch directory, removed when dropped
+pub struct Scratch {
+    pub root: PathBuf,
+}
+
+impl Scratch {
+    pub fn new(name: &str) -> Scratch {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "byog-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("home")).unwrap();
+        Scratch { root }
+    }
+
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    /// A new repository at `relative`, made by git with one commit of `files`
+    pub fn repo(&self, relative: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = self.path(relative);
+        fs::create_dir_all(&dir).unwrap();
+        self.git(&dir, &["init", "-q", "-b", "main"]);
+        write_files(&dir, files);
+        self.git(&dir, &["add", "-A"]);
+        self.git(&dir, &["commit", "-q", "-m", "initial"]);
+        dir
+    }
+
+    fn env(&self, command: &mut Command) {
+        command
+            .env("HOME", self.path("home"))
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "A U Thor")
+            .env("GIT_AUTHOR_EMAIL", "author@example.com")
+            .env("GIT_COMMITTER_NAME", "C O Mitter")
+            .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE");
+    }
+
+    /// Run the system git in `dir`, which must succeed; its stdout
+    pub fn git(&self, dir: &Path, args: &[&str]) -> String {
+        let mut command = Command::new("git");
+        command.args(args).current_dir(dir);
+        self.env(&mut command);
+        let output = command.output().expect("git runs");
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Run the binary under test in `dir`
+    pub fn run(&self, dir: &Path, args: &[&str]) -> Output {
+        self.run_env(dir, args, &[])
+    }
+
+    /// Run the binary under test in `dir` with the variables `vars` set
+    pub fn run_env(&self, dir: &Path, args: &[&str], vars: &[(&str, &Path)]) -> Output {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_codecrafters-git"));
+        command.args(args).current_dir(dir);
+        self.env(&mut command);
+        command.envs(vars.iter().copied());
+        command.output().expect("binary runs")
+    }
+
+    /// A stand-in for ssh that runs the remote command on this machine, the
+    /// binary under test answering for git-upload-pack and
+    /// git-receive-pack; the program for GIT_SSH
+    #[cfg(unix)]
+    pub fn fake_ssh(&self) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin = self.path("bin");
+        fs::create_dir_all(&bin).unwrap();
+        let binary = env!("CARGO_BIN_EXE_codecrafters-git");
+        let scripts = [
+            (
+                "git-upload-pack",
+                format!("exec '{}' upload-pack \"$@\"", binary),
+            ),
+            (
+                "git-receive-pack",
+                format!("exec '{}' receive-pack \"$@\"", binary),
+            ),
+            (
+                "ssh",
+                format!(
+                    "while [ \"$1\" = -o ] || [ \"$1\" = -p ]; do shift 2; done\n\
+                     shift\n\
+                     PATH='{}':$PATH exec sh -c \"$*\"",
+                    bin.display()
+                ),
+            ),
+        ];
+        for (name, body) in scripts {
+            let path = bin.join(name);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        bin.join("ssh")
+    }
+
+    /// Run the binary under test in `dir`, which must succeed; its stdout
+    pub fn run_ok(&self, dir: &Path, args: &[&str]) -> String {
+        let output = self.run(dir, args);
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}{}",
+            args,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+pub fn write_files(dir: &Path, files: &[(&str, &str)]) {
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+/// Write a loose object into the repository at `repo` exactly as given,
+/// bypassing the checks git makes on what it writes; its id
+pub fn write_raw_object(repo: &Path, kind: &str, body: &[u8]) -> String {
+    let mut data = format!("{} {}\0", kind, body.len()).into_bytes();
+    data.extend_from_slice(body);
+    let sha = sha1_smol::Sha1::from(&data).digest().to_string();
+    let dir = repo.join(".git/objects").join(&sha[..2]);
+    fs::create_dir_all(&dir).unwrap();
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&data).unwrap();
+    fs::write(dir.join(&sha[2..]), encoder.finish().unwrap()).unwrap();
+    sha
+}
+
+/// Make branch `branch` of the repository at `repo` a commit on top of
+/// HEAD whose tree holds `escape.txt` under a directory entry called
+/// `name`, which git itself would never write
+pub fn craft_branch(repo: &Path, branch: &str, name: &str) -> String {
+    let blob = write_raw_object(repo, "blob", b"escaped\n");
+    let mut inner = b"100644 escape.txt\0".to_vec();
+    inner.extend_from_slice(&hex::decode(&blob).unwrap());
+    let inner = write_raw_object(repo, "tree", &inner);
+    let mut tree = format!("40000 {}\0", name).into_bytes();
+    tree.extend_from_slice(&hex::decode(&inner).unwrap());
+    let tree = write_raw_object(repo, "tree", &tree);
+    let head = fs::read_to_string(repo.join(".git/refs/heads/main")).unwrap();
+    let commit = format!(
+        "tree {}\nparent {}\nauthor A <a@example.com> 1 +0000\n\
+         committer A <a@example.com> 1 +0000\n\ncrafted\n",
+        tree,
+        head.trim()
+    );
+    let commit = write_raw_object(repo, "commit", commit.as_bytes());
+    fs::write(
+        repo.join(".git/refs/heads").join(branch),
+        format!("{}\n", commit),
+    )
+    .unwrap();
+    commit
+}
+
+/// A git patch creating `path` with mode `mode`, holding the one line
+/// `line`
+pub fn new_file_patch(path: &str, mode: &str, line: &str) -> String {
+    format!(
+        "diff --git a/{path} b/{path}\nnew file mode {mode}\n--- /dev/null\n+++ b/{path}\n\
+         @@ -0,0 +1 @@\n+{line}\n"
+    )
+}
@@ -0,0 +1,78 @@
+// Commits take their author date from GIT_AUTHOR_DATE or commit --date in
+// git's raw forms as well as the readable ones.
+
+mod common;
+
+use common::Scratch;
+use std::path::Path;
+
+/// The date part of the author line of `repo`'s HEAD, as git reads it
+fn author_date(scratch: &Scratch, repo: &Path) -> String {
+    let commit = scratch.git(repo, &["cat-file", "-p", "HEAD"]);
+    let author = commit
+        .lines()
+        .find(|line| line.starts_with("author "))
+        .unwrap();
+    let (_, date) = author.split_once("> ").unwrap();
+    date.to_string()
+}
+
+#[test]
+fn author_date_takes_raw_epochs() {
+    let scratch = Scratch::new("date-raw");
+    let repo = scratch.repo("repo", &[("a.txt", "a\n")]);
+    let commit = ["commit", "--allow-empty", "-m", "dated"];
+
+    for (date, expected) in [
+        ("1700000000 +0000", "1700000000 +0000"),
+        ("@1700000000 -0130", "1700000000 -0130"),
+        ("2005-04-07T22:13:13+02:00", "1112904793 +0200"),
+    ] {
+        let vars = [("GIT_AUTHOR_DATE", Path::new(date))];
+        let output = scratch.run_env(&repo, &commit, &vars);
+        assert!(output.status.success(), "{}", date);
+        assert_eq!(author_date(&scratch, &repo), expected, "{}", date);
+    }
+    for date in ["@1700000000", "1700000000"] {
+        let vars = [("GIT_AUTHOR_DATE", Path::new(date))];
+        assert!(scratch.run_env(&repo, &commit, &vars).status.success());
+        assert!(author_date(&scratch, &repo).starts_with("1700000000 "));
+    }
+    scratch.git(&repo, &["fsck", "--no-progress", "--strict"]);
+}
+
+#[test]
+fn commit_date_option_overrides_the_environment() {
+    let scratch = Scratch::new("date-option");
+    let repo = scratch.repo("repo", &[("a.txt", "a\n")]);
+    let vars = [("GIT_AUTHOR_DATE", Path::new("1600000000 +0000"))];
+
+    let output = scratch.run_env(
+        &repo,
+        &[
+            "commit",
+            "--allow-empty",
+            "--date",
+            "@1700000000 +0100",
+            "-m",
+            "x",
+        ],
+        &vars,
+    );
+    assert!(output.status.success());
+    assert_eq!(author_date(&scratch, &repo), "1700000000 +0100");
+
+    let head = scratch.git(&repo, &["rev-parse", "HEAD"]);
+    let output = scratch.run(
+        &repo,
+        &["commit", "--allow-empty", "--date=whenever", "-m", "x"],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("invalid date format: whenever"),
+        "{}",
+        stderr
+    );
+    assert_eq!(scratch.git(&repo, &["rev-parse", "HEAD"]), head);
+}
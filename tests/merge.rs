@@ -0,0 +1,146 @@
+// A conflicted merge leaves the sides of each conflict staged at 1, 2 and
+// 3, as git does, until it is continued or aborted; meanwhile commit and
+// stash refuse to go on and status lists the paths as unmerged.
+
+mod common;
+
+use common::Scratch;
+use std::path::{Path, PathBuf};
+
+/// A repository on main whose branch topic conflicts with it: both change
+/// f.txt's middle line, main deletes gone.txt which topic modifies, and
+/// both add new.txt
+fn conflicting(scratch: &Scratch) -> PathBuf {
+    let repo = scratch.repo(
+        "repo",
+        &[
+            ("f.txt", "a\nb\nc\n"),
+            ("gone.txt", "gone\n"),
+            ("same.txt", "same\n"),
+        ],
+    );
+    scratch.git(&repo, &["checkout", "-q", "-b", "topic"]);
+    common::write_files(
+        &repo,
+        &[
+            ("f.txt", "a\ntopic\nc\n"),
+            ("gone.txt", "changed\n"),
+            ("new.txt", "topic\n"),
+        ],
+    );
+    scratch.git(&repo, &["add", "-A"]);
+    scratch.git(&repo, &["commit", "-q", "-m", "topic"]);
+    scratch.git(&repo, &["checkout", "-q", "main"]);
+    common::write_files(&repo, &[("f.txt", "a\nmain\nc\n"), ("new.txt", "main\n")]);
+    scratch.git(&repo, &["rm", "-q", "gone.txt"]);
+    scratch.git(&repo, &["add", "-A"]);
+    scratch.git(&repo, &["commit", "-q", "-m", "main"]);
+    repo
+}
+
+/// "<stage> <path>" for each index entry, as git reads the index
+fn stages(scratch: &Scratch, repo: &Path) -> Vec<String> {
+    scratch
+        .git(repo, &["ls-files", "--stage"])
+        .lines()
+        .map(|line| {
+            let (_, rest) = line.split_once(' ').unwrap();
+            let (_, stage_path) = rest.split_once(' ').unwrap();
+            stage_path.replace('\t', " ")
+        })
+        .collect()
+}
+
+#[test]
+fn conflicted_merge_stages_base_ours_and_theirs() {
+    let scratch = Scratch::new("merge-stages");
+    let repo = conflicting(&scratch);
+
+    let output = scratch.run(&repo, &["merge", "topic"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        stages(&scratch, &repo),
+        [
+            "1 f.txt",
+            "2 f.txt",
+            "3 f.txt",
+            "1 gone.txt",
+            "3 gone.txt",
+            "2 new.txt",
+            "3 new.txt",
+            "0 same.txt",
+        ]
+    );
+    let porcelain = scratch.git(&repo, &["status", "--porcelain"]);
+    assert_eq!(porcelain, "UU f.txt\nDU gone.txt\nAA new.txt\n");
+}
+
+#[test]
+fn merge_abort_unstages_the_conflicts() {
+    let scratch = Scratch::new("merge-abort");
+    let repo = conflicting(&scratch);
+    scratch.run(&repo, &["merge", "topic"]);
+
+    scratch.run_ok(&repo, &["merge", "--abort"]);
+    assert_eq!(
+        stages(&scratch, &repo),
+        ["0 f.txt", "0 new.txt", "0 same.txt"]
+    );
+    assert_eq!(scratch.git(&repo, &["status", "--porcelain"]), "");
+}
+
+#[test]
+fn merge_continue_stages_the_resolutions() {
+    let scratch = Scratch::new("merge-continue");
+    let repo = conflicting(&scratch);
+    scratch.run(&repo, &["merge", "topic"]);
+
+    common::write_files(&repo, &[("f.txt", "a\nboth\nc\n"), ("new.txt", "both\n")]);
+    std::fs::remove_file(repo.join("gone.txt")).unwrap();
+    scratch.run_ok(&repo, &["merge", "--continue"]);
+    assert_eq!(
+        stages(&scratch, &repo),
+        ["0 f.txt", "0 new.txt", "0 same.txt"]
+    );
+    assert_eq!(scratch.git(&repo, &["status", "--porcelain"]), "");
+    assert_eq!(scratch.git(&repo, &["show", "HEAD:f.txt"]), "a\nboth\nc\n");
+}
+
+#[test]
+fn commit_refuses_while_conflicts_are_unmerged() {
+    let scratch = Scratch::new("merge-commit");
+    let repo = conflicting(&scratch);
+    scratch.run(&repo, &["merge", "topic"]);
+    let head = scratch.git(&repo, &["rev-parse", "HEAD"]);
+
+    let output = scratch.run(&repo, &["commit", "-m", "too soon"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("you have unmerged files"), "{}", stderr);
+
+    // Without a merge in progress the conflict still blocks the commit
+    std::fs::remove_file(repo.join(".git/MERGE_HEAD")).unwrap();
+    let output = scratch.run(&repo, &["commit", "-m", "too soon"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("you have unmerged files"), "{}", stderr);
+    assert_eq!(scratch.git(&repo, &["rev-parse", "HEAD"]), head);
+}
+
+#[test]
+fn status_and_stash_see_the_unmerged_paths() {
+    let scratch = Scratch::new("merge-status");
+    let repo = conflicting(&scratch);
+    scratch.run(&repo, &["merge", "topic"]);
+
+    let status = scratch.run_ok(&repo, &["status"]);
+    assert!(status.contains("Unmerged paths:"), "{}", status);
+    assert!(status.contains("both modified:   f.txt"), "{}", status);
+    assert!(status.contains("deleted by us:   gone.txt"), "{}", status);
+    assert!(status.contains("both added:      new.txt"), "{}", status);
+    assert!(!status.contains("All conflicts fixed"), "{}", status);
+
+    let output = scratch.run(&repo, &["stash"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unmerged files"), "{}", stderr);
+}
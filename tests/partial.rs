@@ -0,0 +1,92 @@
+// In a partial clone every command that reads an object the clone left out
+// fetches it from the promisor remote, not just the few that used to ask
+// for it by name.
+
+mod common;
+
+use common::Scratch;
+use std::path::{Path, PathBuf};
+
+/// A blob:none clone, made by git, of a repository where f.txt changed in
+/// each of three commits; only the last f.txt is present
+fn partial_clone(scratch: &Scratch) -> PathBuf {
+    let source = scratch.repo("source", &[("f.txt", "v1 needle1\n")]);
+    for version in ["2", "3"] {
+        let content = format!("v{} needle{}\n", version, version);
+        common::write_files(&source, &[("f.txt", &content)]);
+        scratch.git(&source, &["commit", "-q", "-am", version]);
+    }
+    scratch.git(&source, &["config", "uploadpack.allowFilter", "true"]);
+    let url = format!("file://{}", source.display());
+    scratch.git(
+        &scratch.root,
+        &["clone", "-q", "--filter=blob:none", &url, "partial"],
+    );
+    let partial = scratch.path("partial");
+    assert_eq!(missing(scratch, &partial), 2);
+    partial
+}
+
+/// How many objects reachable in `repo` are missing from it
+fn missing(scratch: &Scratch, repo: &Path) -> usize {
+    scratch
+        .git(repo, &["rev-list", "--objects", "--missing=print", "--all"])
+        .lines()
+        .filter(|line| line.starts_with('?'))
+        .count()
+}
+
+#[test]
+fn show_fetches_a_left_out_blob() {
+    let scratch = Scratch::new("partial-show");
+    let partial = partial_clone(&scratch);
+
+    assert_eq!(
+        scratch.run_ok(&partial, &["show", "HEAD~2:f.txt"]),
+        "v1 needle1\n"
+    );
+    assert_eq!(missing(&scratch, &partial), 1);
+}
+
+#[test]
+fn log_pickaxe_fetches_the_blobs_it_compares() {
+    let scratch = Scratch::new("partial-log");
+    let partial = partial_clone(&scratch);
+
+    let log = scratch.run_ok(&partial, &["log", "-S", "needle2", "--oneline"]);
+    let subjects: Vec<&str> = log.lines().map(|line| &line[line.len() - 1..]).collect();
+    assert_eq!(subjects, ["3", "2"], "{}", log);
+    assert_eq!(missing(&scratch, &partial), 0);
+}
+
+#[test]
+fn cat_file_fetches_a_left_out_blob() {
+    let scratch = Scratch::new("partial-cat-file");
+    let partial = partial_clone(&scratch);
+
+    assert_eq!(
+        scratch.run_ok(&partial, &["cat-file", "-p", "HEAD~1:f.txt"]),
+        "v2 needle2\n"
+    );
+    let blob = scratch.git(&partial, &["rev-parse", "HEAD~2:f.txt"]);
+    assert!(scratch
+        .run(&partial, &["cat-file", "-e", blob.trim()])
+        .status
+        .success());
+    assert_eq!(missing(&scratch, &partial), 0);
+    scratch.git(&partial, &["fsck", "--no-progress"]);
+}
+
+#[test]
+fn checkout_fetches_the_blobs_it_writes() {
+    let scratch = Scratch::new("partial-checkout");
+    let partial = partial_clone(&scratch);
+
+    scratch.run_ok(&partial, &["checkout", "HEAD~2"]);
+    assert_eq!(
+        std::fs::read_to_string(partial.join("f.txt")).unwrap(),
+        "v1 needle1\n"
+    );
+    assert_eq!(scratch.git(&partial, &["status", "--porcelain"]), "");
+    assert_eq!(missing(&scratch, &partial), 1);
+}
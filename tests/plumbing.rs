@@ -0,0 +1,90 @@
+// cat-file, ls-tree and commit-tree take revision expressions wherever
+// they take an object, and commit-tree checks what they name before
+// writing anything.
+
+mod common;
+
+use common::Scratch;
+
+#[test]
+fn cat_file_and_ls_tree_resolve_revisions() {
+    let scratch = Scratch::new("plumbing-resolve");
+    let repo = scratch.repo("repo", &[("a.txt", "a\n"), ("dir/b.txt", "b\n")]);
+
+    let commit = scratch.git(&repo, &["cat-file", "-p", "HEAD"]);
+    assert_eq!(scratch.run_ok(&repo, &["cat-file", "-p", "HEAD"]), commit);
+    assert_eq!(
+        scratch.run_ok(&repo, &["cat-file", "-t", "HEAD^{tree}"]),
+        "tree"
+    );
+    assert_eq!(
+        scratch.run_ok(&repo, &["cat-file", "-s", "HEAD:a.txt"]),
+        "2"
+    );
+    assert_eq!(
+        scratch.run_ok(&repo, &["cat-file", "-p", "main:dir/b.txt"]),
+        "b\n"
+    );
+    assert!(scratch
+        .run(&repo, &["cat-file", "-e", "HEAD~0"])
+        .status
+        .success());
+    assert!(!scratch
+        .run(&repo, &["cat-file", "-e", "nope"])
+        .status
+        .success());
+    let output = scratch.run(&repo, &["cat-file", "-p", "nope"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Not a valid object name nope"),
+        "{}",
+        stderr
+    );
+
+    for tree_ish in ["HEAD", "main^{tree}"] {
+        assert_eq!(
+            scratch.run_ok(&repo, &["ls-tree", "--name-only", tree_ish]),
+            "a.txt\ndir\n"
+        );
+    }
+    assert_eq!(
+        scratch.run_ok(&repo, &["ls-tree", "--name-only", "HEAD:dir"]),
+        "b.txt\n"
+    );
+    assert!(!scratch
+        .run(&repo, &["ls-tree", "--name-only", "HEAD:a.txt"])
+        .status
+        .success());
+}
+
+#[test]
+fn commit_tree_resolves_and_checks_its_arguments() {
+    let scratch = Scratch::new("plumbing-commit-tree");
+    let repo = scratch.repo("repo", &[("a.txt", "a\n")]);
+    let head = scratch.git(&repo, &["rev-parse", "HEAD"]);
+    let tree = scratch.git(&repo, &["rev-parse", "HEAD^{tree}"]);
+
+    let commit = scratch.run_ok(
+        &repo,
+        &["commit-tree", "HEAD^{tree}", "-p", "HEAD", "-m", "next"],
+    );
+    let parsed = scratch.git(&repo, &["cat-file", "-p", &commit]);
+    assert!(
+        parsed.starts_with(&format!("tree {}parent {}", tree, head)),
+        "{}",
+        parsed
+    );
+    scratch.git(&repo, &["fsck", "--no-progress", "--strict"]);
+
+    let objects = scratch.git(&repo, &["count-objects"]);
+    for args in [
+        ["commit-tree", "HEAD", "-p", "HEAD", "-m", "x"],
+        ["commit-tree", "HEAD^{tree}", "-p", "HEAD^{tree}", "-m", "x"],
+        ["commit-tree", "nope", "-p", "HEAD", "-m", "x"],
+    ] {
+        assert!(!scratch.run(&repo, &args).status.success(), "{:?}", args);
+    }
+    // Nothing was written for the refused commits
+    assert_eq!(scratch.git(&repo, &["count-objects"]), objects);
+}
@@ -0,0 +1,41 @@
+// pull --verify-signatures and --no-verify-signatures reach the merge, and
+// override merge.verifySignatures either way.
+
+mod common;
+
+use common::Scratch;
+
+#[test]
+fn verify_signatures_option_overrides_the_config() {
+    let scratch = Scratch::new("pull-verify-signatures");
+    let source = scratch.repo("source", &[("a.txt", "a\n")]);
+    scratch.git(
+        &scratch.root,
+        &["clone", "-q", source.to_str().unwrap(), "clone"],
+    );
+    common::write_files(&source, &[("a.txt", "unsigned\n")]);
+    scratch.git(&source, &["commit", "-q", "-am", "unsigned"]);
+    let clone = scratch.path("clone");
+    let head = scratch.git(&clone, &["rev-parse", "HEAD"]);
+
+    // The unsigned commit is refused, with nothing merged
+    let output = scratch.run(&clone, &["pull", "--verify-signatures"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("does not have a GPG signature"),
+        "{}",
+        stderr
+    );
+    assert_eq!(scratch.git(&clone, &["rev-parse", "HEAD"]), head);
+
+    scratch.git(&clone, &["config", "merge.verifySignatures", "true"]);
+    assert!(!scratch.run(&clone, &["pull"]).status.success());
+    assert_eq!(scratch.git(&clone, &["rev-parse", "HEAD"]), head);
+
+    scratch.run_ok(&clone, &["pull", "--no-verify-signatures"]);
+    assert_eq!(
+        scratch.git(&clone, &["rev-parse", "HEAD"]),
+        scratch.git(&source, &["rev-parse", "HEAD"])
+    );
+}
@@ -0,0 +1,65 @@
+// Short ref names are looked up in git's DWIM order, and only pseudo-refs
+// like HEAD are read as files directly under .git, so a branch named after
+// one of the repository's own files still resolves. A ref name that isn't
+// valid is refused whether the ref is being written or deleted, so no
+// command removes or overwrites files outside refs.
+
+mod common;
+
+use common::Scratch;
+
+#[test]
+fn branches_named_like_repository_files_resolve() {
+    let scratch = Scratch::new("refs-dwim");
+    let repo = scratch.repo("repo", &[("a.txt", "a\n")]);
+    let head = scratch.git(&repo, &["rev-parse", "HEAD"]);
+
+    for branch in ["config", "index", "description"] {
+        scratch.git(&repo, &["branch", branch]);
+        assert_eq!(scratch.run_ok(&repo, &["rev-parse", branch]), head);
+        let log = scratch.run_ok(&repo, &["log", branch]);
+        assert!(log.contains(head.trim()), "{}", log);
+    }
+    assert_eq!(scratch.run_ok(&repo, &["rev-parse", "HEAD"]), head);
+
+    // A pseudo-ref-shaped file that doesn't hold a ref is passed over
+    scratch.git(&repo, &["branch", "NOT_A_REF"]);
+    std::fs::write(repo.join(".git/NOT_A_REF"), "not a ref\n").unwrap();
+    assert_eq!(scratch.run_ok(&repo, &["rev-parse", "NOT_A_REF"]), head);
+
+    scratch.git(&repo, &["update-ref", "ORIG_HEAD", "HEAD"]);
+    assert_eq!(scratch.run_ok(&repo, &["rev-parse", "ORIG_HEAD"]), head);
+}
+
+#[test]
+fn bad_ref_names_are_refused_for_deletion_too() {
+    let scratch = Scratch::new("refs-bad-names");
+    let repo = scratch.repo("repo", &[("a.txt", "a\n")]);
+    let head = scratch.git(&repo, &["rev-parse", "HEAD"]);
+    let git_dir = repo.join(".git");
+    std::fs::write(git_dir.join("x"), &head).unwrap();
+    std::fs::write(git_dir.join("y"), "ref: refs/heads/main\n").unwrap();
+
+    let commands: [&[&str]; 4] = [
+        &["update-ref", "-d", "refs/heads/../../x"],
+        &["tag", "-d", "../../x"],
+        &["symbolic-ref", "-d", "refs/heads/../../y"],
+        &["symbolic-ref", "refs/../y", "refs/heads/main"],
+    ];
+    for args in commands {
+        let output = scratch.run(&repo, args);
+        assert!(!output.status.success(), "{:?} succeeded", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("refusing to update ref with bad name"),
+            "{:?}: {}",
+            args,
+            stderr
+        );
+    }
+    assert_eq!(std::fs::read_to_string(git_dir.join("x")).unwrap(), head);
+    assert_eq!(
+        std::fs::read_to_string(git_dir.join("y")).unwrap(),
+        "ref: refs/heads/main\n"
+    );
+}
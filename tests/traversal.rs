@@ -0,0 +1,201 @@
+// Trees naming ".." or ".git" must never get a file written outside the
+// work tree or into the repository, whichever command checks them out, and
+// neither must the paths of a patch that is applied.
+
+mod common;
+
+use common::Scratch;
+
+const NAMES: [&str; 3] = ["..", ".git", ".GIT"];
+
+#[test]
+fn switch_refuses_a_tree_that_escapes() {
+    for name in NAMES {
+        let scratch = Scratch::new("switch-escape");
+        let repo = scratch.repo("work/repo", &[("a.txt", "a\n")]);
+        common::craft_branch(&repo, "evil", name);
+
+        let output = scratch.run(&repo, &["switch", "evil"]);
+        assert!(!output.status.success(), "switch to {:?} succeeded", name);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(&format!("'{}'", name)), "{}", stderr);
+        assert!(!scratch.path("work/escape.txt").exists());
+        assert!(!repo.join(".git/escape.txt").exists());
+        assert!(!repo.join(".GIT/escape.txt").exists());
+        // Nothing was written before the tree was refused
+        assert_eq!(
+            std::fs::read_to_string(repo.join(".git/HEAD")).unwrap(),
+            "ref: refs/heads/main\n"
+        );
+    }
+}
+
+#[test]
+fn clone_refuses_a_tree_that_escapes() {
+    for name in NAMES {
+        let scratch = Scratch::new("clone-escape");
+        let source = scratch.repo("source", &[("a.txt", "a\n")]);
+        common::craft_branch(&source, "evil", name);
+        scratch.git(&source, &["symbolic-ref", "HEAD", "refs/heads/evil"]);
+        std::fs::create_dir_all(scratch.path("dest")).unwrap();
+
+        let output = scratch.run(
+            &scratch.path("dest"),
+            &["clone", source.to_str().unwrap(), "clone"],
+        );
+        assert!(!output.status.success(), "clone of {:?} succeeded", name);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(&format!("'{}'", name)), "{}", stderr);
+        assert!(!scratch.path("dest/escape.txt").exists());
+        assert!(!scratch.path("dest/clone/.git/escape.txt").exists());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn checkout_replaces_a_symlinked_directory_instead_of_writing_through_it() {
+    use std::os::unix::fs::symlink;
+
+    let scratch = Scratch::new("symlink-escape");
+    let repo = scratch.repo("repo", &[("a.txt", "a\n")]);
+    scratch.git(&repo, &["checkout", "-q", "-b", "topic"]);
+    common::write_files(&repo, &[("sub/file.txt", "inside\n")]);
+    scratch.git(&repo, &["add", "-A"]);
+    scratch.git(&repo, &["commit", "-q", "-m", "sub"]);
+    scratch.git(&repo, &["checkout", "-q", "main"]);
+    std::fs::create_dir_all(scratch.path("outside")).unwrap();
+    symlink(scratch.path("outside"), repo.join("sub")).unwrap();
+
+    // The untracked symlink is in the way of the directory; forced, the
+    // checkout replaces it rather than writing into what it points at
+    scratch.run_ok(&repo, &["checkout", "-f", "topic"]);
+    assert!(!scratch.path("outside/file.txt").exists());
+    assert!(repo.join("sub").symlink_metadata().unwrap().is_dir());
+    assert_eq!(
+        std::fs::read_to_string(repo.join("sub/file.txt")).unwrap(),
+        "inside\n"
+    );
+}
+
+/// Paths a patch must not touch
+const PATCH_PATHS: [&str; 5] = [
+    "../escaped.txt",
+    "sub/../../escaped.txt",
+    ".git/hooks/post-commit",
+    ".GIT/hooks/post-commit",
+    "sub//file.txt",
+];
+
+#[test]
+fn apply_refuses_a_patch_that_escapes() {
+    for path in PATCH_PATHS {
+        let scratch = Scratch::new("apply-escape");
+        let repo = scratch.repo("work/repo", &[("a.txt", "a\n")]);
+        // A harmless file first, so a patch applied part way would show
+        let patch = common::new_file_patch("good.txt", "100644", "good")
+            + &common::new_file_patch(path, "100755", "echo pwned");
+        std::fs::write(scratch.path("evil.patch"), patch).unwrap();
+        let patch = scratch.path("evil.patch");
+        let patch = patch.to_str().unwrap();
+
+        for args in [
+            &["apply", patch][..],
+            &["apply", "--index", patch],
+            &["apply", "--cached", patch],
+            &["apply", "--check", patch],
+        ] {
+            let output = scratch.run(&repo, args);
+            assert!(
+                !output.status.success(),
+                "{:?} of {:?} succeeded",
+                args,
+                path
+            );
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("invalid path"), "{}", stderr);
+        }
+        assert!(!repo.join("good.txt").exists());
+        assert!(!scratch.path("work/escaped.txt").exists());
+        assert!(!repo.join(".git/hooks/post-commit").exists());
+        assert_eq!(scratch.git(&repo, &["ls-files"]), "a.txt\n");
+    }
+}
+
+#[test]
+fn apply_refuses_a_rename_out_of_the_work_tree() {
+    let scratch = Scratch::new("apply-rename-escape");
+    let repo = scratch.repo("work/repo", &[("a.txt", "a\n")]);
+    let patch = "diff --git a/a.txt b/../moved.txt\nsimilarity index 100%\n\
+                 rename from a.txt\nrename to ../moved.txt\n";
+    std::fs::write(scratch.path("rename.patch"), patch).unwrap();
+
+    let output = scratch.run(
+        &repo,
+        &["apply", scratch.path("rename.patch").to_str().unwrap()],
+    );
+    assert!(!output.status.success());
+    assert!(repo.join("a.txt").exists());
+    assert!(!scratch.path("work/moved.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn apply_refuses_a_path_beyond_a_symlink() {
+    use std::os::unix::fs::symlink;
+
+    let scratch = Scratch::new("apply-symlink-escape");
+    let repo = scratch.repo("repo", &[("a.txt", "a\n")]);
+    std::fs::create_dir_all(scratch.path("outside")).unwrap();
+    common::write_files(&scratch.path("outside"), &[("old.txt", "old\n")]);
+    symlink(scratch.path("outside"), repo.join("sub")).unwrap();
+
+    let create = common::new_file_patch("sub/file.txt", "100644", "inside");
+    let delete = "diff --git a/sub/old.txt b/sub/old.txt\ndeleted file mode 100644\n\
+                  --- a/sub/old.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-old\n";
+    for (name, patch) in [("create.patch", create.as_str()), ("delete.patch", delete)] {
+        std::fs::write(scratch.path(name), patch).unwrap();
+        let output = scratch.run(&repo, &["apply", scratch.path(name).to_str().unwrap()]);
+        assert!(!output.status.success(), "{}", name);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("beyond a symbolic link"), "{}", stderr);
+    }
+    assert!(!scratch.path("outside/file.txt").exists());
+    assert!(scratch.path("outside/old.txt").exists());
+}
+
+#[test]
+fn am_refuses_a_mail_whose_patch_escapes() {
+    for path in ["../escaped.txt", ".git/hooks/post-commit"] {
+        let scratch = Scratch::new("am-escape");
+        let repo = scratch.repo("work/repo", &[("a.txt", "a\n")]);
+        let head = scratch.git(&repo, &["rev-parse", "HEAD"]);
+        let mail = format!(
+            "From 0000000000000000000000000000000000000000 Mon Sep 17 00:00:00 2001\n\
+             From: Mallory <mallory@example.com>\n\
+             Date: Thu, 7 Apr 2005 22:13:13 +0200\n\
+             Subject: [PATCH] innocent\n\n---\n{}{}-- \n2.39.5\n\n",
+            common::new_file_patch("good.txt", "100644", "good"),
+            common::new_file_patch(path, "100755", "echo pwned")
+        );
+        std::fs::write(scratch.path("evil.mbox"), mail).unwrap();
+
+        let output = scratch.run(&repo, &["am", scratch.path("evil.mbox").to_str().unwrap()]);
+        assert!(!output.status.success(), "am of {:?} succeeded", path);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("invalid path"), "{}", stderr);
+        assert_eq!(scratch.git(&repo, &["rev-parse", "HEAD"]), head);
+        assert!(!repo.join("good.txt").exists());
+        assert!(!scratch.path("work/escaped.txt").exists());
+        assert!(!repo.join(".git/hooks/post-commit").exists());
+        assert_eq!(scratch.git(&repo, &["ls-files"]), "a.txt\n");
+
+        // Nor does --continue stage what is found at the paths
+        common::write_files(&repo, &[("good.txt", "good\n"), (path, "echo pwned\n")]);
+        let output = scratch.run(&repo, &["am", "--continue"]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("invalid path"), "{}", stderr);
+        assert_eq!(scratch.git(&repo, &["rev-parse", "HEAD"]), head);
+        assert_eq!(scratch.git(&repo, &["ls-files"]), "a.txt\n");
+    }
+}